@@ -12,9 +12,12 @@ awslocal dynamodb create-table \
     --attribute-definitions \
         AttributeName=topic,AttributeType=S \
         AttributeName=hmac_key,AttributeType=S \
+        AttributeName=encrypted_push_id,AttributeType=S \
     --key-schema \
         AttributeName=topic,KeyType=HASH \
         AttributeName=hmac_key,KeyType=RANGE \
+    --global-secondary-indexes \
+        "IndexName=encrypted-push-id-index,Keys=[{AttributeName=encrypted_push_id,KeyType=HASH}],Projection={ProjectionType=KEYS_ONLY},ProvisionedThroughput={ReadCapacityUnits=1,WriteCapacityUnits=1}" \
     --billing-mode PAY_PER_REQUEST
 
 # Enable TTL on the push subscriptions table
@@ -55,18 +58,46 @@ awslocal dynamodb create-table \
     --attribute-definitions \
         AttributeName=id,AttributeType=S \
         AttributeName=group_invite_id,AttributeType=S \
+        AttributeName=status,AttributeType=S \
+        AttributeName=notification_sent_at,AttributeType=N \
     --key-schema \
         AttributeName=id,KeyType=HASH \
     --global-secondary-indexes \
         "IndexName=group-invite-index,Keys=[{AttributeName=group_invite_id,KeyType=HASH}],Projection={ProjectionType=ALL},ProvisionedThroughput={ReadCapacityUnits=1,WriteCapacityUnits=1}" \
+        "IndexName=status-notification-sent-index,Keys=[{AttributeName=status,KeyType=HASH},{AttributeName=notification_sent_at,KeyType=RANGE}],Projection={ProjectionType=ALL},ProvisionedThroughput={ReadCapacityUnits=1,WriteCapacityUnits=1}" \
     --billing-mode PAY_PER_REQUEST
 
+# Create DynamoDB table for processed envelopes, used to replay fan-outs
+awslocal dynamodb create-table \
+    --table-name world-chat-processed-envelopes \
+    --attribute-definitions \
+        AttributeName=bucket,AttributeType=S \
+        AttributeName=sort_key,AttributeType=S \
+    --key-schema \
+        AttributeName=bucket,KeyType=HASH \
+        AttributeName=sort_key,KeyType=RANGE \
+    --billing-mode PAY_PER_REQUEST
+
+# Enable TTL on the processed envelopes table
+awslocal dynamodb update-time-to-live \
+    --table-name world-chat-processed-envelopes \
+    --time-to-live-specification "Enabled=true,AttributeName=ttl"
+
 awslocal sqs create-queue --queue-name notification-queue.fifo --attributes '{
   "FifoQueue": "true",
   "ContentBasedDeduplication": "true",
   "DeduplicationScope": "messageGroup",
   "FifoThroughputLimit": "perMessageGroupId"
 }'
+awslocal sqs create-queue --queue-name notification-dlq.fifo --attributes '{"FifoQueue": "true"}'
+# Deferred fan-outs that keep failing end up in the fan-out DLQ after 10 receives
+FANOUT_DLQ_URL=$(awslocal sqs create-queue --queue-name fanout-retry-dlq.fifo --attributes '{"FifoQueue": "true"}' --query 'QueueUrl' --output text)
+FANOUT_DLQ_ARN=$(awslocal sqs get-queue-attributes --queue-url "$FANOUT_DLQ_URL" --attribute-names QueueArn --query 'Attributes.QueueArn' --output text)
+awslocal sqs create-queue --queue-name fanout-retry-queue.fifo --attributes "{
+  \"FifoQueue\": \"true\",
+  \"ContentBasedDeduplication\": \"true\",
+  \"RedrivePolicy\": \"{\\\"deadLetterTargetArn\\\":\\\"$FANOUT_DLQ_ARN\\\",\\\"maxReceiveCount\\\":\\\"10\\\"}\"
+}"
 awslocal sqs create-queue --queue-name subscription-request-queue.fifo --attributes '{"FifoQueue": "true", "ContentBasedDeduplication": "true"}'
 
 echo "AWS LocalStack resources initialized successfully!"
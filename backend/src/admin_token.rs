@@ -0,0 +1,50 @@
+//! Admin token issuance for on-call operators
+//!
+//! Started with `--issue-admin-token <operator>`, the binary loads its configuration, prints an
+//! admin-scoped token for the operator signed with the JWT KMS key and exits without binding the
+//! HTTP server. The token grants access to the `/v1/admin` routes for an hour.
+
+/// Command line flag that issues an admin token and exits
+pub const ISSUE_ADMIN_TOKEN_FLAG: &str = "--issue-admin-token";
+
+/// Operator the binary was asked to issue an admin token for with `--issue-admin-token`
+///
+/// # Panics
+///
+/// If the flag is not followed by an operator name
+#[must_use]
+pub fn requested_operator() -> Option<String> {
+    operator_from_args(std::env::args().skip(1))
+}
+
+fn operator_from_args(mut args: impl Iterator<Item = String>) -> Option<String> {
+    args.find(|arg| arg == ISSUE_ADMIN_TOKEN_FLAG)?;
+    match args.next() {
+        Some(operator) if !operator.is_empty() && !operator.starts_with('-') => Some(operator),
+        _ => panic!("{ISSUE_ADMIN_TOKEN_FLAG} requires the name of the operator"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> impl Iterator<Item = String> + '_ {
+        args.iter().map(ToString::to_string)
+    }
+
+    #[test]
+    fn test_operator_follows_flag() {
+        assert_eq!(
+            operator_from_args(args(&["--issue-admin-token", "alice"])),
+            Some("alice".to_string())
+        );
+        assert_eq!(operator_from_args(args(&["--validate-config"])), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "requires the name of the operator")]
+    fn test_flag_without_operator_panics() {
+        operator_from_args(args(&["--issue-admin-token"]));
+    }
+}
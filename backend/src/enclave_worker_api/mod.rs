@@ -3,15 +3,22 @@ use std::time::Duration;
 
 use crate::types::AppError;
 use axum::http::StatusCode;
-use reqwest::{header, Client};
-use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
+use reqwest::{header, Client, Response};
+use reqwest_middleware::{ClientBuilder, ClientWithMiddleware, RequestBuilder};
 use reqwest_tracing::TracingMiddleware;
 use serde_json;
 
-/// Default request timeout in seconds
-const DEFAULT_REQUEST_TIMEOUT_SECS: u64 = 30;
+/// Default request timeout in seconds, below the time budget of the routes calling the enclave
+/// worker (see `ENCLAVE_ROUTE_TIMEOUT`)
+pub(crate) const DEFAULT_REQUEST_TIMEOUT_SECS: u64 = 10;
 /// Maximum number of idle connections to maintain per host
 const MAX_IDLE_CONNECTIONS_PER_HOST: usize = 10;
+/// Maximum number of retries of a rate limited (429) request
+const MAX_RATE_LIMIT_RETRIES: u32 = 2;
+/// Maximum total time spent waiting on `Retry-After` before failing the request
+pub(crate) const RATE_LIMIT_RETRY_BUDGET: Duration = Duration::from_secs(5);
+/// Delay before retrying a rate limited request without a `Retry-After` header
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(1);
 
 /// Trait for the Enclave Worker API
 #[async_trait::async_trait]
@@ -88,13 +95,13 @@ impl EnclaveWorkerApi for EnclaveWorkerApiClient {
             )
         })?;
 
-        let response = self
-            .http_client
-            .post(url)
-            .header(header::CONTENT_TYPE, "application/json")
-            .body(json_body)
-            .send()
-            .await?;
+        let response = send_with_rate_limit_retry(|| {
+            self.http_client
+                .post(&url)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(json_body.clone())
+        })
+        .await?;
 
         if !response.status().is_success() {
             return Err(AppError::new(
@@ -112,7 +119,7 @@ impl EnclaveWorkerApi for EnclaveWorkerApiClient {
 
     async fn get_attestation_document(&self) -> Result<AttestationDocumentResponse, AppError> {
         let url = format!("{}/v1/attestation-document", self.enclave_worker_url);
-        let response = self.http_client.get(url).send().await?;
+        let response = send_with_rate_limit_retry(|| self.http_client.get(&url)).await?;
 
         if !response.status().is_success() {
             return Err(AppError::new(
@@ -129,6 +136,56 @@ impl EnclaveWorkerApi for EnclaveWorkerApiClient {
     }
 }
 
+/// Sends a request, retrying rate limited (429) responses after their `Retry-After` delay
+/// as long as the retry budget allows it.
+async fn send_with_rate_limit_retry<F>(build_request: F) -> Result<Response, AppError>
+where
+    F: Fn() -> RequestBuilder + Send + Sync,
+{
+    let mut retries = 0;
+    let mut waited = Duration::ZERO;
+
+    loop {
+        let response = build_request().send().await?;
+        if response.status() != StatusCode::TOO_MANY_REQUESTS {
+            return Ok(response);
+        }
+
+        let delay = parse_retry_after(response.headers()).unwrap_or(DEFAULT_RETRY_AFTER);
+        if retries >= MAX_RATE_LIMIT_RETRIES || waited + delay > RATE_LIMIT_RETRY_BUDGET {
+            tracing::warn!(
+                retries,
+                "Enclave worker rate limited, retry budget exhausted"
+            );
+            return Err(AppError::new(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "enclave_rate_limited",
+                "Enclave worker service is rate limited",
+                true,
+            ));
+        }
+
+        tracing::info!(retry_in = ?delay, "Enclave worker rate limited, retrying");
+        tokio::time::sleep(delay).await;
+        retries += 1;
+        waited += delay;
+    }
+}
+
+/// Parses a `Retry-After` header given in seconds
+///
+/// The HTTP-date form is not used by the enclave worker and is ignored.
+fn parse_retry_after(headers: &header::HeaderMap) -> Option<Duration> {
+    headers
+        .get(header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .map(Duration::from_secs)
+}
+
 #[cfg(any(test, feature = "test-utils"))]
 pub mod mock {
     use common_types::AttestationDocumentResponse;
@@ -175,3 +232,105 @@ pub mod mock {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use axum::{extract::State, response::IntoResponse, routing::get, Json, Router};
+
+    use super::*;
+
+    /// Starts an enclave worker mock answering 429 to the first `rate_limited` requests
+    async fn start_mock_server(
+        rate_limited: usize,
+        retry_after: &'static str,
+    ) -> (String, Arc<AtomicUsize>) {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let app = Router::new()
+            .route(
+                "/v1/attestation-document",
+                get(|State(requests): State<Arc<AtomicUsize>>| async move {
+                    if requests.fetch_add(1, Ordering::SeqCst) < rate_limited {
+                        (
+                            StatusCode::TOO_MANY_REQUESTS,
+                            [(header::RETRY_AFTER, retry_after)],
+                        )
+                            .into_response()
+                    } else {
+                        Json(AttestationDocumentResponse {
+                            attestation_doc_base64: "attestation".to_string(),
+                        })
+                        .into_response()
+                    }
+                }),
+            )
+            .with_state(requests.clone());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        (url, requests)
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        let mut headers = header::HeaderMap::new();
+        assert_eq!(parse_retry_after(&headers), None);
+
+        headers.insert(header::RETRY_AFTER, "2".parse().unwrap());
+        assert_eq!(parse_retry_after(&headers), Some(Duration::from_secs(2)));
+
+        headers.insert(
+            header::RETRY_AFTER,
+            "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap(),
+        );
+        assert_eq!(parse_retry_after(&headers), None);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_request_is_retried() {
+        let (url, requests) = start_mock_server(1, "0").await;
+        let client = EnclaveWorkerApiClient::new(url);
+
+        let response = client.get_attestation_document().await.unwrap();
+
+        assert_eq!(response.attestation_doc_base64, "attestation");
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_retries_are_bounded() {
+        let (url, requests) = start_mock_server(usize::MAX, "0").await;
+        let client = EnclaveWorkerApiClient::new(url);
+
+        let err = client.get_attestation_document().await.unwrap_err();
+
+        assert_eq!(
+            err.into_response().status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert_eq!(
+            requests.load(Ordering::SeqCst),
+            MAX_RATE_LIMIT_RETRIES as usize + 1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_retry_after_beyond_budget_is_not_awaited() {
+        let (url, requests) = start_mock_server(1, "60").await;
+        let client = EnclaveWorkerApiClient::new(url);
+
+        let err = client.get_attestation_document().await.unwrap_err();
+
+        assert_eq!(
+            err.into_response().status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+}
@@ -13,6 +13,15 @@ pub enum JwtError {
     #[error("Invalid signature")]
     InvalidSignature,
 
+    #[error("Token has been revoked")]
+    Revoked,
+
+    #[error("Token of {size} bytes exceeds the maximum of {max} bytes")]
+    TokenTooLarge { size: usize, max: usize },
+
+    #[error("Presented token of {size} bytes exceeds the maximum of {max} bytes")]
+    PresentedTokenTooLarge { size: usize, max: usize },
+
     #[error("Signing input build error: {0}")]
     SigningInput(String),
 
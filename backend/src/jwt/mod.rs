@@ -7,6 +7,18 @@
 //! - Verification uses `p256`'s `VerifyingKey` over SHA-256 of the compact input
 //! - `kid` is derived deterministically from the KMS key ARN and embedded in the header
 //!
+//! Constant-time comparisons:
+//! - Header fields (`alg`, `typ`, `kid`) are compared in constant time and all of them are
+//!   evaluated before branching, so response timing doesn't reveal how much of the configured
+//!   `kid` a forged token matched, nor which check failed
+//! - Signature checks need no byte comparison: `verify_digest` recomputes ECDSA over public
+//!   inputs only (digest, signature, public key), so there is no secret to leak
+//! - Claims (`iss`, `nbf`, `exp`, `iat`) are public and compared normally
+//!
+//! Revocation:
+//! - Optionally, `validate` rejects tokens of subjects revoked in a Redis-backed revocation list
+//!   (see `revocation`). `verify` performs the stateless checks only.
+//!
 //! Rationale:
 //! - Most rust jwt libraries didn't support external signing
 //! - From the libraries that did, they supported only synchronous signing, leading to sync/async gymnastics
@@ -15,22 +27,31 @@
 //! - eg. `jwt-compact` even though it didn't use OpenSSL it still had a sync `Algorithm` trait without a good support for Errors
 
 pub mod error;
+mod revocation;
 mod types;
 
 use error::JwtError;
-pub use types::{JwsPayload, KmsKeyDefinition};
+pub use revocation::TokenRevocationList;
+pub use types::{
+    JwsPayload, KmsKeyDefinition, ADMIN_SUBJECT_PREFIX, ADMIN_TOKEN_EXPIRATION, SCOPE_ADMIN,
+    SCOPE_USER,
+};
 
 use aws_sdk_kms::{
     primitives::Blob,
     types::{MessageType, SigningAlgorithmSpec},
     Client as KmsClient,
 };
-use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine as _,
+};
 use p256::ecdsa::{signature::DigestVerifier, Signature, VerifyingKey};
 use p256::pkcs8::DecodePublicKey;
 // use serde::de::DeserializeOwned; // no longer needed
 use sha2::{Digest, Sha256};
 use std::sync::Arc;
+use subtle::ConstantTimeEq;
 
 use crate::{
     jwt::types::{JwsHeader, JwsTokenParts},
@@ -40,6 +61,12 @@ use crate::{
 const ALG_ES256: &str = "ES256";
 const TYP_JWT: &str = "JWT";
 const MAX_SKEW_SECS: i64 = 60;
+/// Batches of at least this many tokens are verified in parallel by `validate_batch`
+pub const VALIDATE_BATCH_PARALLEL_THRESHOLD: usize = 16;
+/// Default maximum size of a compact token, well above the size of the tokens we issue
+pub const DEFAULT_MAX_TOKEN_BYTES: usize = 8 * 1024;
+/// Length of a base64url-encoded raw ES256 signature (64 bytes)
+const SIGNATURE_B64_LEN: usize = 86;
 
 // removed helper: decoding now lives on `JwsTokenParts`
 
@@ -50,44 +77,74 @@ pub struct JwtManager {
     kms_client: Arc<KmsClient>,
     key_arn: String,
     pub issuer: String,
+    revocation_list: Option<TokenRevocationList>,
+    max_token_bytes: usize,
 }
 
 impl JwtManager {
     /// Create a new JWT manager backed by AWS KMS.
     ///
+    /// If KMS can't return the public key and a last-known-good key is configured
+    /// (`JWT_PUBLIC_KEY_FALLBACK`), that key is used instead so the backend still starts and
+    /// validates tokens. Issuing tokens still requires KMS.
+    ///
     /// # Errors
-    /// Returns an error if the KMS public key cannot be retrieved or parsed.
+    /// Returns an error if the KMS public key cannot be retrieved without a fallback, or if the
+    /// used key cannot be parsed.
     pub async fn new(
         kms_client: Arc<KmsClient>,
         environment: &Environment,
     ) -> Result<Self, JwtError> {
         let key = KmsKeyDefinition::from_arn(environment.jwt_kms_key_arn());
-        let spki = kms_client
-            .get_public_key()
-            .key_id(&key.arn)
-            .send()
-            .await
-            .map_err(|e| JwtError::Kms(Box::new(e.into())))?
-            .public_key()
-            .ok_or_else(|| anyhow::anyhow!("missing public key in KMS response"))?
-            .as_ref()
-            .to_vec();
-
+        let live_spki = fetch_public_key(&kms_client, &key.arn).await;
         let verifying_key =
-            VerifyingKey::from_public_key_der(&spki).map_err(|e| JwtError::Other(e.into()))?;
+            resolve_verifying_key(live_spki, environment.jwt_public_key_fallback().as_deref())?;
+
         Ok(Self {
             verifying_key,
             kid: key.id,
             kms_client,
             key_arn: key.arn,
             issuer: environment.jwt_issuer_url(),
+            revocation_list: None,
+            max_token_bytes: environment.jwt_max_token_bytes(),
         })
     }
 
+    /// Reject tokens revoked in `revocation_list` during validation.
+    #[must_use]
+    pub fn with_revocation_list(mut self, revocation_list: TokenRevocationList) -> Self {
+        self.revocation_list = Some(revocation_list);
+        self
+    }
+
+    /// Reject tokens larger than `max_token_bytes`, at issuance and validation.
+    #[must_use]
+    pub const fn with_max_token_bytes(mut self, max_token_bytes: usize) -> Self {
+        self.max_token_bytes = max_token_bytes;
+        self
+    }
+
+    /// Revoke all tokens issued for `subject` so far.
+    ///
+    /// Revoked tokens fail validation with `JwtError::Revoked` until they expire.
+    ///
+    /// # Errors
+    /// Returns an error if no revocation list is configured or the revocation can't be stored.
+    pub async fn revoke(&self, subject: &str) -> Result<(), JwtError> {
+        let revocation_list = self
+            .revocation_list
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("token revocation list is not configured"))?;
+        revocation_list.revoke(subject).await
+    }
+
     /// Issue a compact JWS (JWT) string using ES256 via AWS KMS.
     ///
     /// # Errors
-    /// Returns an error if header/payload serialization fails or KMS signing fails.
+    /// Returns an error if header/payload serialization fails or KMS signing fails, or
+    /// `JwtError::TokenTooLarge` if the token would exceed the maximum size. The size is
+    /// checked before signing.
     pub async fn issue_token(&self, payload: &JwsPayload) -> Result<String, JwtError> {
         let header = JwsHeader {
             alg: ALG_ES256.to_string(),
@@ -95,6 +152,10 @@ impl JwtManager {
             kid: self.kid.clone(),
         };
         let signing_input = craft_signing_input(&header, payload)?;
+        check_token_size(
+            signing_input.len() + 1 + SIGNATURE_B64_LEN,
+            self.max_token_bytes,
+        )?;
 
         // Sign via KMS asynchronously and convert DER -> raw (r||s).
         let der_sig = self
@@ -121,25 +182,145 @@ impl JwtManager {
         Ok(token)
     }
 
+    /// Issue an admin-scoped token for the on-call `operator`, see `JwsPayload::admin`.
+    ///
+    /// # Errors
+    /// Returns the errors of `issue_token`.
+    pub async fn issue_admin_token(&self, operator: &str) -> Result<String, JwtError> {
+        self.issue_token(&JwsPayload::admin(operator, &self.issuer))
+            .await
+    }
+
     /// Validate a compact JWS (JWT) string and return parsed claims on success.
     ///
+    /// Performs the checks of `verify`, then rejects tokens revoked in the revocation list,
+    /// if configured. Revocation lookups that fail are logged and the token is accepted,
+    /// so a Redis outage doesn't lock out all users.
+    ///
     /// # Arguments
     /// * `token_str` - The compact JWS token string to validate
     /// * `issued_after` - Optional cutoff timestamp; reject tokens with `iat` before this time
     ///
     /// # Errors
-    /// Returns an error if parsing fails, header is unexpected, signature is invalid,
-    /// or time-based claims fail validation.
-    pub fn validate(
+    /// Returns the errors of `verify`, or `JwtError::Revoked` if the token was revoked.
+    pub async fn validate(
         &self,
         token_str: &str,
         issued_after: Option<i64>,
     ) -> Result<JwsPayload, JwtError> {
+        let claims = self.verify(token_str, issued_after)?;
+        self.check_revocation(claims).await
+    }
+
+    /// Validate many tokens at once, returning the result of each token in order.
+    ///
+    /// Equivalent to calling `validate` on each token without an `issued_after` cutoff.
+    /// Verification is CPU-bound, so batches of at least `VALIDATE_BATCH_PARALLEL_THRESHOLD`
+    /// tokens are verified in parallel on blocking threads; smaller batches are verified inline.
+    pub async fn validate_batch(&self, tokens: &[&str]) -> Vec<Result<JwsPayload, JwtError>> {
+        let verified = if tokens.len() < VALIDATE_BATCH_PARALLEL_THRESHOLD {
+            tokens
+                .iter()
+                .map(|token| self.verify(token, None))
+                .collect()
+        } else {
+            self.verify_parallel(tokens).await
+        };
+
+        let mut results = Vec::with_capacity(verified.len());
+        for claims in verified {
+            results.push(match claims {
+                Ok(claims) => self.check_revocation(claims).await,
+                Err(e) => Err(e),
+            });
+        }
+        results
+    }
+
+    /// Verify `tokens` in chunks spread over blocking threads, keeping the input order.
+    async fn verify_parallel(&self, tokens: &[&str]) -> Vec<Result<JwsPayload, JwtError>> {
+        let workers = std::thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get);
+        let chunk_size = tokens.len().div_ceil(workers);
+
+        let handles = tokens
+            .chunks(chunk_size)
+            .map(|chunk| {
+                let manager = self.clone();
+                let chunk = chunk.iter().map(ToString::to_string).collect::<Vec<_>>();
+                let len = chunk.len();
+                let handle = tokio::task::spawn_blocking(move || {
+                    chunk
+                        .iter()
+                        .map(|token| manager.verify(token, None))
+                        .collect::<Vec<_>>()
+                });
+                (handle, len)
+            })
+            .collect::<Vec<_>>();
+
+        let mut results = Vec::with_capacity(tokens.len());
+        for (handle, len) in handles {
+            match handle.await {
+                Ok(chunk_results) => results.extend(chunk_results),
+                Err(e) => {
+                    tracing::error!("Token verification task failed: {e}");
+                    results.extend((0..len).map(|_| {
+                        Err(JwtError::Other(anyhow::anyhow!(
+                            "token verification task failed"
+                        )))
+                    }));
+                }
+            }
+        }
+        results
+    }
+
+    /// Reject `claims` of a revoked subject, if a revocation list is configured.
+    ///
+    /// Fails open if the revocation list can't be read, so a Redis outage doesn't lock out all
+    /// users.
+    async fn check_revocation(&self, claims: JwsPayload) -> Result<JwsPayload, JwtError> {
+        if let Some(revocation_list) = &self.revocation_list {
+            match revocation_list.revoked_at(&claims.subject).await {
+                Ok(Some(revoked_at)) if claims.issued_at <= revoked_at => {
+                    return Err(JwtError::Revoked);
+                }
+                Ok(_) => {}
+                Err(e) => tracing::error!("Failed to check token revocation: {e}"),
+            }
+        }
+
+        Ok(claims)
+    }
+
+    /// Verify a compact JWS (JWT) string and return parsed claims on success.
+    ///
+    /// Stateless: checks the header, signature and claims, but not the revocation list.
+    ///
+    /// # Arguments
+    /// * `token_str` - The compact JWS token string to validate
+    /// * `issued_after` - Optional cutoff timestamp; reject tokens with `iat` before this time
+    ///
+    /// # Errors
+    /// Returns `JwtError::PresentedTokenTooLarge` if the token exceeds the maximum size, which is
+    /// checked before parsing, or an error if parsing fails, header is unexpected, signature is
+    /// invalid, or time-based claims fail validation.
+    pub fn verify(
+        &self,
+        token_str: &str,
+        issued_after: Option<i64>,
+    ) -> Result<JwsPayload, JwtError> {
+        if token_str.len() > self.max_token_bytes {
+            return Err(JwtError::PresentedTokenTooLarge {
+                size: token_str.len(),
+                max: self.max_token_bytes,
+            });
+        }
+
         let parts = JwsTokenParts::try_from(token_str)?;
 
         // Header checks: enforce alg, typ, and kid to prevent alg confusion
-        let header: &JwsHeader = &parts.header;
-        if header.alg != ALG_ES256 || header.typ != TYP_JWT || header.kid != self.kid {
+        if !header_matches(&parts.header, &self.kid) {
             return Err(JwtError::InvalidToken);
         }
 
@@ -164,6 +345,27 @@ impl JwtManager {
 
 // Extracted functions for testability
 
+/// Reject tokens to issue of `size` bytes above `max_token_bytes`, before signing them.
+pub(crate) const fn check_token_size(size: usize, max_token_bytes: usize) -> Result<(), JwtError> {
+    if size > max_token_bytes {
+        return Err(JwtError::TokenTooLarge {
+            size,
+            max: max_token_bytes,
+        });
+    }
+    Ok(())
+}
+
+/// Check the header's `alg`, `typ`, and `kid` against the expected values in constant time.
+///
+/// All three comparisons are combined without short-circuiting; only their lengths may leak.
+pub(crate) fn header_matches(header: &JwsHeader, expected_kid: &str) -> bool {
+    let matches = header.alg.as_bytes().ct_eq(ALG_ES256.as_bytes())
+        & header.typ.as_bytes().ct_eq(TYP_JWT.as_bytes())
+        & header.kid.as_bytes().ct_eq(expected_kid.as_bytes());
+    matches.into()
+}
+
 /// Verify ES256 signature over the compact input using a known key.
 pub(crate) fn verify_signature_with_key(
     parts: &JwsTokenParts<'_>,
@@ -207,6 +409,40 @@ pub(crate) fn validate_claims(
     Ok(())
 }
 
+/// Fetches the DER-encoded (SPKI) public key of `key_arn` from KMS.
+async fn fetch_public_key(kms_client: &KmsClient, key_arn: &str) -> Result<Vec<u8>, JwtError> {
+    Ok(kms_client
+        .get_public_key()
+        .key_id(key_arn)
+        .send()
+        .await
+        .map_err(|e| JwtError::Kms(Box::new(e.into())))?
+        .public_key()
+        .ok_or_else(|| anyhow::anyhow!("missing public key in KMS response"))?
+        .as_ref()
+        .to_vec())
+}
+
+/// Parses the live KMS public key, falling back to the base64 DER `fallback` key if the live
+/// fetch failed.
+fn resolve_verifying_key(
+    live_spki: Result<Vec<u8>, JwtError>,
+    fallback: Option<&str>,
+) -> Result<VerifyingKey, JwtError> {
+    let spki = match (live_spki, fallback) {
+        (Ok(spki), _) => spki,
+        (Err(e), Some(fallback)) => {
+            tracing::warn!("Failed to fetch the JWT public key from KMS, using the fallback: {e}");
+            STANDARD
+                .decode(fallback.trim())
+                .map_err(|e| JwtError::Other(e.into()))?
+        }
+        (Err(e), None) => return Err(e),
+    };
+
+    VerifyingKey::from_public_key_der(&spki).map_err(|e| JwtError::Other(e.into()))
+}
+
 /// Serialize + base64url-encode header and payload, and join with a dot.
 pub(crate) fn craft_signing_input(
     header: &JwsHeader,
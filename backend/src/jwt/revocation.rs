@@ -0,0 +1,68 @@
+//! Redis-backed token revocation list.
+//!
+//! Revoking a subject stores the revocation time under the subject, so every token issued
+//! for it up to that moment is rejected. Tokens issued afterwards are accepted again, so a
+//! user can re-authenticate once the compromise is resolved.
+//!
+//! Entries expire after the max token lifetime, as all revoked tokens have expired by then.
+
+use std::time::Duration;
+
+use redis::{aio::ConnectionManager, AsyncCommands};
+use tokio::time::timeout;
+
+use crate::jwt::{error::JwtError, types::TOKEN_EXPIRATION, MAX_SKEW_SECS};
+
+const REDIS_TIMEOUT: Duration = Duration::from_secs(3);
+const KEY_PREFIX: &str = "jwt:revoked";
+
+#[derive(Clone)]
+pub struct TokenRevocationList {
+    connection_manager: ConnectionManager,
+}
+
+impl TokenRevocationList {
+    #[must_use]
+    pub const fn new(connection_manager: ConnectionManager) -> Self {
+        Self { connection_manager }
+    }
+
+    /// Revoke all tokens issued for `subject` up to now.
+    ///
+    /// # Errors
+    /// Returns an error if the Redis write fails or times out.
+    pub async fn revoke(&self, subject: &str) -> Result<(), JwtError> {
+        let revoked_at = chrono::Utc::now().timestamp();
+        let ttl_secs = u64::try_from(TOKEN_EXPIRATION.num_seconds() + MAX_SKEW_SECS)
+            .map_err(|e| JwtError::Other(e.into()))?;
+
+        let mut conn = self.connection_manager.clone();
+        timeout(
+            REDIS_TIMEOUT,
+            conn.set_ex::<_, _, ()>(revocation_key(subject), revoked_at, ttl_secs),
+        )
+        .await
+        .map_err(|_| anyhow::anyhow!("Redis timeout"))?
+        .map_err(|e| anyhow::anyhow!("Redis error: {e}"))?;
+
+        Ok(())
+    }
+
+    /// Returns when `subject` was last revoked, if within the max token lifetime.
+    ///
+    /// # Errors
+    /// Returns an error if the Redis read fails or times out.
+    pub async fn revoked_at(&self, subject: &str) -> Result<Option<i64>, JwtError> {
+        let mut conn = self.connection_manager.clone();
+        let revoked_at = timeout(REDIS_TIMEOUT, conn.get(revocation_key(subject)))
+            .await
+            .map_err(|_| anyhow::anyhow!("Redis timeout"))?
+            .map_err(|e| anyhow::anyhow!("Redis error: {e}"))?;
+
+        Ok(revoked_at)
+    }
+}
+
+fn revocation_key(subject: &str) -> String {
+    format!("{KEY_PREFIX}:{subject}")
+}
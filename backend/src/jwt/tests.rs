@@ -36,6 +36,24 @@ mod test_helpers {
 
         format!("{signing_input}.{sig_b64}")
     }
+
+    /// Create a `JwtManager` that verifies with a known key (KMS is never called)
+    pub fn create_test_manager(verifying_key: VerifyingKey, kid: &str) -> JwtManager {
+        let kms_config = aws_sdk_kms::Config::builder()
+            .behavior_version(aws_sdk_kms::config::BehaviorVersion::latest())
+            .region(aws_sdk_kms::config::Region::new("us-east-1"))
+            .build();
+
+        JwtManager {
+            verifying_key,
+            kid: kid.to_string(),
+            kms_client: Arc::new(KmsClient::from_conf(kms_config)),
+            key_arn: "arn:aws:kms:us-east-1:000000000000:key/test".to_string(),
+            issuer: TEST_ISSUER.to_string(),
+            revocation_list: None,
+            max_token_bytes: DEFAULT_MAX_TOKEN_BYTES,
+        }
+    }
 }
 
 mod token_parsing {
@@ -235,6 +253,103 @@ mod header_validation {
         assert_eq!(parts.header.typ, "JWT");
         assert_eq!(parts.header.kid, "valid-kid");
     }
+
+    #[test]
+    fn test_header_matches_expected_values() {
+        let header = JwsHeader {
+            alg: ALG_ES256.to_string(),
+            typ: TYP_JWT.to_string(),
+            kid: "test-kid".to_string(),
+        };
+
+        assert!(header_matches(&header, "test-kid"));
+    }
+
+    #[test]
+    fn test_header_mismatch_in_any_field_rejected() {
+        let valid = JwsHeader {
+            alg: ALG_ES256.to_string(),
+            typ: TYP_JWT.to_string(),
+            kid: "test-kid".to_string(),
+        };
+
+        let cases = [
+            (
+                "alg",
+                JwsHeader {
+                    alg: "HS256".to_string(),
+                    ..valid.clone()
+                },
+            ),
+            (
+                "typ",
+                JwsHeader {
+                    typ: "JWE".to_string(),
+                    ..valid.clone()
+                },
+            ),
+            // Same length, differs only in the last byte
+            (
+                "kid",
+                JwsHeader {
+                    kid: "test-kie".to_string(),
+                    ..valid.clone()
+                },
+            ),
+            (
+                "kid_prefix",
+                JwsHeader {
+                    kid: "test".to_string(),
+                    ..valid.clone()
+                },
+            ),
+            (
+                "empty_kid",
+                JwsHeader {
+                    kid: String::new(),
+                    ..valid
+                },
+            ),
+        ];
+
+        for (test_name, header) in cases {
+            assert!(
+                !header_matches(&header, "test-kid"),
+                "Should reject header with wrong {test_name}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_validate_with_constant_time_header_checks() {
+        let (signing_key, verifying_key) = generate_test_keypair();
+        let manager = create_test_manager(verifying_key, "test-kid");
+        let payload = JwsPayload::from_encrypted_push_id(
+            "test-123".to_string(),
+            TEST_ISSUER,
+            EnclaveTrack::default(),
+        );
+
+        // Correctly signed token with the configured kid is accepted
+        let token = create_test_token(&signing_key, "test-kid", &payload);
+        let claims = manager.verify(&token, None).unwrap();
+        assert_eq!(claims.subject, "test-123");
+
+        // Correctly signed token with a different kid is rejected
+        let token = create_test_token(&signing_key, "test-kie", &payload);
+        assert!(matches!(
+            manager.verify(&token, None),
+            Err(JwtError::InvalidToken)
+        ));
+
+        // Token signed by another key is rejected at signature verification
+        let (other_signing_key, _) = generate_test_keypair();
+        let token = create_test_token(&other_signing_key, "test-kid", &payload);
+        assert!(matches!(
+            manager.verify(&token, None),
+            Err(JwtError::InvalidSignature)
+        ));
+    }
 }
 
 mod claims_validation {
@@ -252,6 +367,7 @@ mod claims_validation {
             not_before: now - 7200, // 2 hours ago
             issued_at: now - 7200,  // 2 hours ago
             enclave_track: EnclaveTrack::default(),
+            scope: None,
         };
 
         let result = validate_claims(&claims, now, 60, TEST_ISSUER); // 60 second skew
@@ -268,6 +384,7 @@ mod claims_validation {
             not_before: now - 3600, // Valid 1 hour ago
             issued_at: now - 3600,  // Issued 1 hour ago
             enclave_track: EnclaveTrack::default(),
+            scope: None,
         };
 
         let result = validate_claims(&claims, now, 60, TEST_ISSUER); // 60 second skew - should accept
@@ -285,6 +402,7 @@ mod claims_validation {
             not_before: future,
             issued_at: now - 3600, // Issued 1 hour ago
             enclave_track: EnclaveTrack::default(),
+            scope: None,
         };
 
         let result = validate_claims(&claims, now, 60, TEST_ISSUER);
@@ -301,6 +419,7 @@ mod claims_validation {
             not_before: now + 30,   // Valid in 30 seconds
             issued_at: now - 3600,  // Issued 1 hour ago
             enclave_track: EnclaveTrack::default(),
+            scope: None,
         };
 
         let result = validate_claims(&claims, now, 60, TEST_ISSUER); // 60 second skew - should accept
@@ -318,6 +437,7 @@ mod claims_validation {
             not_before: now - 3600, // Valid 1 hour ago
             issued_at: now - 3600,  // Issued 1 hour ago
             enclave_track: EnclaveTrack::default(),
+            scope: None,
         };
 
         // Without skew - should fail (now >= exp)
@@ -341,6 +461,7 @@ mod claims_validation {
             not_before: now - 30,
             issued_at: now - 30,
             enclave_track: EnclaveTrack::default(),
+            scope: None,
         };
 
         // Should be valid
@@ -383,6 +504,7 @@ mod claims_validation {
             expires_at: now + 3600,
             not_before: now - 60,
             enclave_track: EnclaveTrack::default(),
+            scope: None,
         };
 
         // With 60s skew, iat is still in the future -> reject
@@ -400,6 +522,7 @@ mod claims_validation {
             expires_at: now + 3600,
             not_before: now - 60,
             enclave_track: EnclaveTrack::default(),
+            scope: None,
         };
 
         // validate_claims enforces issuer now
@@ -588,6 +711,7 @@ mod signature_format {
 }
 
 mod key_management {
+    use super::test_helpers::*;
     use super::*;
 
     #[test]
@@ -639,6 +763,173 @@ mod key_management {
         // Plus our "key_" prefix
         assert!(key.id.len() > 30);
     }
+
+    #[test]
+    fn test_fallback_key_used_when_kms_fetch_fails() {
+        use p256::pkcs8::EncodePublicKey;
+
+        let (_, verifying_key) = generate_test_keypair();
+        let fallback = STANDARD.encode(verifying_key.to_public_key_der().unwrap().as_bytes());
+        let kms_failure = || Err(JwtError::Other(anyhow::anyhow!("KMS unreachable")));
+
+        let resolved = resolve_verifying_key(kms_failure(), Some(&fallback)).unwrap();
+        assert_eq!(resolved, verifying_key);
+
+        // Without a fallback the KMS failure is returned
+        assert!(resolve_verifying_key(kms_failure(), None).is_err());
+    }
+
+    #[test]
+    fn test_live_key_preferred_over_fallback() {
+        use p256::pkcs8::EncodePublicKey;
+
+        let (_, live_key) = generate_test_keypair();
+        let (_, fallback_key) = generate_test_keypair();
+        let live_spki = live_key.to_public_key_der().unwrap().as_bytes().to_vec();
+        let fallback = STANDARD.encode(fallback_key.to_public_key_der().unwrap().as_bytes());
+
+        let resolved = resolve_verifying_key(Ok(live_spki), Some(&fallback)).unwrap();
+        assert_eq!(resolved, live_key);
+    }
+}
+
+mod batch_validation {
+    use super::test_helpers::*;
+    use super::*;
+
+    /// Mix of valid, tampered and foreign-key tokens, `count` of each
+    fn mixed_tokens(signing_key: &SigningKey, count: usize) -> Vec<String> {
+        let (foreign_key, _) = generate_test_keypair();
+
+        (0..count)
+            .flat_map(|i| {
+                let payload = JwsPayload::from_encrypted_push_id(
+                    format!("push-{i}"),
+                    TEST_ISSUER,
+                    EnclaveTrack::default(),
+                );
+                let valid = create_test_token(signing_key, "test-kid", &payload);
+                let tampered = format!("{valid}x");
+                let foreign = create_test_token(&foreign_key, "test-kid", &payload);
+                [valid, tampered, foreign]
+            })
+            .collect()
+    }
+
+    async fn assert_batch_matches_individual(manager: &JwtManager, tokens: &[String]) {
+        let tokens = tokens.iter().map(String::as_str).collect::<Vec<_>>();
+
+        let batch = manager.validate_batch(&tokens).await;
+
+        assert_eq!(batch.len(), tokens.len());
+        for (token, batch_result) in tokens.iter().zip(batch) {
+            match (manager.validate(token, None).await, batch_result) {
+                (Ok(individual), Ok(batched)) => assert_eq!(individual.subject, batched.subject),
+                (Err(individual), Err(batched)) => {
+                    assert_eq!(individual.to_string(), batched.to_string());
+                }
+                (individual, batched) => {
+                    panic!("individual {individual:?} and batched {batched:?} results differ")
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_small_batch_matches_individual_validation() {
+        let (signing_key, verifying_key) = generate_test_keypair();
+        let manager = create_test_manager(verifying_key, "test-kid");
+
+        assert_batch_matches_individual(&manager, &mixed_tokens(&signing_key, 2)).await;
+    }
+
+    #[tokio::test]
+    async fn test_large_batch_matches_individual_validation() {
+        let (signing_key, verifying_key) = generate_test_keypair();
+        let manager = create_test_manager(verifying_key, "test-kid");
+        let tokens = mixed_tokens(&signing_key, VALIDATE_BATCH_PARALLEL_THRESHOLD);
+
+        assert!(tokens.len() >= VALIDATE_BATCH_PARALLEL_THRESHOLD);
+        assert_batch_matches_individual(&manager, &tokens).await;
+    }
+
+    #[tokio::test]
+    async fn test_empty_batch() {
+        let (_, verifying_key) = generate_test_keypair();
+        let manager = create_test_manager(verifying_key, "test-kid");
+
+        assert!(manager.validate_batch(&[]).await.is_empty());
+    }
+}
+
+mod token_size {
+    use super::test_helpers::*;
+    use super::*;
+
+    fn test_payload(subject: String) -> JwsPayload {
+        JwsPayload::from_encrypted_push_id(subject, TEST_ISSUER, EnclaveTrack::default())
+    }
+
+    #[tokio::test]
+    async fn test_oversized_payload_rejected_before_signing() {
+        let (_, verifying_key) = generate_test_keypair();
+        // The test manager's KMS client is unreachable, only an early rejection can return
+        let manager = create_test_manager(verifying_key, "test-kid");
+        let payload = test_payload("a".repeat(DEFAULT_MAX_TOKEN_BYTES));
+
+        let result = manager.issue_token(&payload).await;
+
+        assert!(matches!(
+            result,
+            Err(JwtError::TokenTooLarge { size, max: DEFAULT_MAX_TOKEN_BYTES })
+                if size > DEFAULT_MAX_TOKEN_BYTES
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_oversized_token_rejected_before_parsing() {
+        let (signing_key, verifying_key) = generate_test_keypair();
+        let token = create_test_token(&signing_key, "test-kid", &test_payload("test".into()));
+
+        // A valid token at the limit is accepted, one byte above it isn't
+        let manager =
+            create_test_manager(verifying_key, "test-kid").with_max_token_bytes(token.len());
+        assert!(manager.validate(&token, None).await.is_ok());
+
+        let manager = manager.with_max_token_bytes(token.len() - 1);
+        assert!(matches!(
+            manager.validate(&token, None).await,
+            Err(JwtError::PresentedTokenTooLarge { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_oversized_garbage_rejected_by_size() {
+        let (_, verifying_key) = generate_test_keypair();
+        let manager = create_test_manager(verifying_key, "test-kid");
+        let garbage = "a.".repeat(DEFAULT_MAX_TOKEN_BYTES);
+
+        assert!(matches!(
+            manager.validate(&garbage, None).await,
+            Err(JwtError::PresentedTokenTooLarge { .. })
+        ));
+    }
+
+    #[test]
+    fn test_signature_length_matches_issued_tokens() {
+        let (signing_key, _) = generate_test_keypair();
+        let payload = test_payload("test".into());
+        let header = JwsHeader {
+            alg: ALG_ES256.to_string(),
+            typ: TYP_JWT.to_string(),
+            kid: "test-kid".to_string(),
+        };
+        let signing_input = craft_signing_input(&header, &payload).unwrap();
+
+        let token = create_test_token(&signing_key, "test-kid", &payload);
+
+        assert_eq!(token.len(), signing_input.len() + 1 + SIGNATURE_B64_LEN);
+    }
 }
 
 mod integration_helpers {
@@ -660,6 +951,7 @@ mod integration_helpers {
             not_before: 1_234_567_890,
             issued_at: 1_234_567_890,
             enclave_track: EnclaveTrack::default(),
+            scope: None,
         };
 
         let result = craft_signing_input(&header, &payload).unwrap();
@@ -700,5 +992,171 @@ mod integration_helpers {
         // Check payload
         assert_eq!(parts.payload.subject, "test-123");
         assert_eq!(parts.payload.issuer, TEST_ISSUER);
+        assert_eq!(parts.payload.scope, Some(vec![SCOPE_USER.to_string()]));
+    }
+
+    #[test]
+    fn test_admin_token_roundtrip_without_kms() {
+        let (signing_key, verifying_key) = generate_test_keypair();
+        let payload = JwsPayload::admin("alice", TEST_ISSUER);
+        let token = create_test_token(&signing_key, "test-kid", &payload);
+
+        let parts = JwsTokenParts::try_from(token.as_str()).unwrap();
+        assert!(verify_signature_with_key(&parts, &verifying_key).is_ok());
+        let now = chrono::Utc::now().timestamp();
+        assert!(validate_claims(&parts.payload, now, 60, TEST_ISSUER).is_ok());
+
+        assert_eq!(parts.payload.subject, "admin:alice");
+        assert_eq!(parts.payload.scope, Some(vec![SCOPE_ADMIN.to_string()]));
+        assert_eq!(
+            parts.payload.expires_at - parts.payload.issued_at,
+            ADMIN_TOKEN_EXPIRATION.num_seconds()
+        );
+    }
+
+    #[test]
+    fn test_payload_without_scope_claim_parses() {
+        let payload: JwsPayload = serde_json::from_value(serde_json::json!({
+            "sub": "test-123",
+            "iss": TEST_ISSUER,
+            "iat": 1_234_567_890,
+            "exp": 1_234_567_890,
+            "nbf": 1_234_567_890,
+        }))
+        .unwrap();
+
+        assert!(payload.scope.is_none());
+    }
+}
+
+/// Malformed or adversarial tokens must always be rejected with an error, never panic or hang.
+mod robustness {
+    use super::test_helpers::*;
+    use super::*;
+    use proptest::prelude::*;
+
+    fn valid_token_and_manager() -> (String, JwtManager) {
+        let (signing_key, verifying_key) = generate_test_keypair();
+        let payload = JwsPayload::from_encrypted_push_id(
+            "test-123".to_string(),
+            TEST_ISSUER,
+            EnclaveTrack::default(),
+        );
+        let token = create_test_token(&signing_key, "test-kid", &payload);
+        (token, create_test_manager(verifying_key, "test-kid"))
+    }
+
+    #[test]
+    fn test_valid_token_accepted() {
+        let (token, manager) = valid_token_and_manager();
+        assert!(manager.verify(&token, None).is_ok());
+    }
+
+    #[test]
+    fn test_malformed_structure_rejected() {
+        let (token, manager) = valid_token_and_manager();
+        let parts: Vec<&str> = token.split('.').collect();
+
+        let malformed_tokens = vec![
+            ("empty", String::new()),
+            (
+                "missing_dot",
+                format!("{}.{}{}", parts[0], parts[1], parts[2]),
+            ),
+            ("no_dots", token.replace('.', "")),
+            ("too_many_dots", format!("{token}.extra")),
+            ("trailing_dot", format!("{token}.")),
+            (
+                "non_base64_header",
+                format!("!!!.{}.{}", parts[1], parts[2]),
+            ),
+            (
+                "non_base64_payload",
+                format!("{}.!!!.{}", parts[0], parts[2]),
+            ),
+            (
+                "non_base64_signature",
+                format!("{}.{}.!!!", parts[0], parts[1]),
+            ),
+            (
+                "swapped_parts",
+                format!("{}.{}.{}", parts[1], parts[0], parts[2]),
+            ),
+        ];
+
+        for (test_name, malformed_token) in malformed_tokens {
+            assert!(
+                matches!(
+                    manager.verify(&malformed_token, None),
+                    Err(JwtError::InvalidToken)
+                ),
+                "Should reject malformed token: {test_name}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_truncated_signature_rejected() {
+        let (token, manager) = valid_token_and_manager();
+        let sig_start = token.rfind('.').unwrap() + 1;
+
+        for len in sig_start..token.len() {
+            let truncated = &token[..len];
+            assert!(
+                matches!(
+                    manager.verify(truncated, None),
+                    Err(JwtError::InvalidToken | JwtError::InvalidSignature)
+                ),
+                "Should reject token truncated to {len} chars"
+            );
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn prop_arbitrary_string_rejected(token in ".{0,512}") {
+            let (_, manager) = valid_token_and_manager();
+            prop_assert!(manager.verify(&token, None).is_err());
+        }
+
+        #[test]
+        fn prop_arbitrary_segments_rejected(
+            segments in prop::collection::vec("[A-Za-z0-9_-]{0,128}", 0..6)
+        ) {
+            let (_, manager) = valid_token_and_manager();
+            prop_assert!(manager.verify(&segments.join("."), None).is_err());
+        }
+
+        #[test]
+        fn prop_random_signature_rejected(sig in prop::collection::vec(any::<u8>(), 0..128)) {
+            let (token, manager) = valid_token_and_manager();
+            let signing_input = &token[..token.rfind('.').unwrap()];
+            let forged = format!("{signing_input}.{}", URL_SAFE_NO_PAD.encode(sig));
+
+            prop_assert!(manager.verify(&forged, None).is_err());
+        }
+
+        #[test]
+        fn prop_single_char_mutation_rejected(
+            index in any::<prop::sample::Index>(),
+            replacement in prop::char::range('!', '~'),
+        ) {
+            let (token, manager) = valid_token_and_manager();
+            let position = index.index(token.len());
+            prop_assume!(token.as_bytes()[position] != u8::try_from(replacement).unwrap());
+
+            let mut mutated = token.clone();
+            mutated.replace_range(position..=position, &replacement.to_string());
+
+            prop_assert!(manager.verify(&mutated, None).is_err());
+        }
+
+        #[test]
+        fn prop_truncated_token_rejected(index in any::<prop::sample::Index>()) {
+            let (token, manager) = valid_token_and_manager();
+            let truncated = &token[..index.index(token.len())];
+
+            prop_assert!(manager.verify(truncated, None).is_err());
+        }
     }
 }
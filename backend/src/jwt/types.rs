@@ -8,6 +8,15 @@ use crate::jwt::error::JwtError;
 
 /// Default access token lifetime.
 pub const TOKEN_EXPIRATION: Duration = Duration::days(7);
+/// Lifetime of admin tokens, kept short as they are handed out to on-call operators.
+pub const ADMIN_TOKEN_EXPIRATION: Duration = Duration::hours(1);
+/// Prefix of the subject of admin tokens, followed by the operator's name.
+pub const ADMIN_SUBJECT_PREFIX: &str = "admin:";
+
+/// Scope of tokens issued to World ID authenticated users.
+pub const SCOPE_USER: &str = "user";
+/// Scope of tokens allowed to access admin routes.
+pub const SCOPE_ADMIN: &str = "admin";
 
 /// Compact JWS header used for ES256 tokens.
 ///
@@ -41,6 +50,9 @@ pub struct JwsPayload {
     /// Enclave track used when generating the encrypted push ID
     #[serde(default)]
     pub enclave_track: EnclaveTrack,
+    /// Scopes granted to the token, `None` for tokens issued before scopes were introduced
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<Vec<String>>,
 }
 
 impl JwsPayload {
@@ -59,6 +71,25 @@ impl JwsPayload {
             expires_at: exp,
             not_before: now,
             enclave_track,
+            scope: Some(vec![SCOPE_USER.to_string()]),
+        }
+    }
+
+    /// Claims of an admin-scoped token issued to the on-call `operator`.
+    ///
+    /// The subject names the operator, so their tokens can be revoked like any other subject.
+    #[must_use]
+    pub fn admin(operator: &str, issuer: &str) -> Self {
+        let now = Utc::now().timestamp();
+        let exp = (Utc::now() + ADMIN_TOKEN_EXPIRATION).timestamp();
+        Self {
+            subject: format!("{ADMIN_SUBJECT_PREFIX}{operator}"),
+            issuer: issuer.to_owned(),
+            issued_at: now,
+            expires_at: exp,
+            not_before: now,
+            enclave_track: EnclaveTrack::default(),
+            scope: Some(vec![SCOPE_ADMIN.to_string()]),
         }
     }
 }
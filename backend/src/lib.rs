@@ -1,5 +1,6 @@
 #![deny(clippy::all, clippy::pedantic, clippy::nursery, dead_code)]
 
+pub mod admin_token;
 pub mod enclave_worker_api;
 pub mod jwt;
 pub mod media_storage;
@@ -7,4 +8,5 @@ pub mod middleware;
 pub mod routes;
 pub mod server;
 pub mod types;
+pub mod validate_config;
 pub mod world_id;
@@ -1,54 +1,119 @@
 use std::sync::Arc;
 
-use aws_sdk_dynamodb::Client as DynamoDbClient;
-use aws_sdk_kms::Client as KmsClient;
-use aws_sdk_s3::Client as S3Client;
-use backend_storage::{auth_proof::AuthProofStorage, push_subscription::PushSubscriptionStorage};
+use backend_storage::{
+    auth_proof::AuthProofStorage,
+    push_subscription::{
+        PushSubscriptionStorage, SubscriptionInvalidationPublisher,
+        SUBSCRIPTION_INVALIDATION_CHANNEL,
+    },
+    queue::NotificationQueue,
+};
+use common_types::logging;
+use redis::aio::ConnectionManager;
 
 use backend::{
+    admin_token,
     enclave_worker_api::{EnclaveWorkerApi, EnclaveWorkerApiClient},
-    jwt::JwtManager,
-    media_storage::MediaStorage,
+    jwt::{JwtManager, TokenRevocationList},
+    media_storage::{MediaStorage, PresignedUrlCache, DEFAULT_IDEMPOTENCY_WINDOW},
+    routes::v1::admin::NotificationDeadLetterQueue,
     server,
-    types::Environment,
+    types::{AwsClients, Environment},
+    validate_config,
 };
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let environment = Environment::from_env();
 
-    // Initialize Datadog tracing
-    // This will set up OpenTelemetry with Datadog exporter
-    // The _guard must be kept alive for the duration of the program
-    let (_guard, tracer_shutdown) = datadog_tracing::init()?;
+    // Initialize logging, JSON logs also set up OpenTelemetry with Datadog exporter
+    // The telemetry must be kept alive for the duration of the program
+    let mut telemetry = logging::init(environment.log_format()?)?;
+
+    // Connect to Redis while loading the AWS config, which is shared by all AWS clients
+    let (redis_connection_manager, aws_clients) = tokio::join!(
+        connect_redis(environment.redis_url()),
+        AwsClients::load(&environment)
+    );
+    // Initalize Enclave Worker API client
+    let enclave_worker_api: Arc<dyn EnclaveWorkerApi> = Arc::new(EnclaveWorkerApiClient::new(
+        environment.enclave_worker_url(),
+    ));
+
+    // With `--validate-config`, check every dependency and exit without serving traffic
+    if validate_config::is_requested() {
+        let report = validate_config::validate(
+            &environment,
+            &aws_clients,
+            redis_connection_manager,
+            enclave_worker_api.as_ref(),
+        )
+        .await;
+        report.log();
+        telemetry.shutdown();
+
+        anyhow::ensure!(report.is_ok(), "Configuration validation failed");
+        return Ok(());
+    }
+
+    // With `--issue-admin-token <operator>`, print an admin token for on-call and exit
+    if let Some(operator) = admin_token::requested_operator() {
+        let jwt_manager = JwtManager::new(aws_clients.kms, &environment).await?;
+        println!("{}", jwt_manager.issue_admin_token(&operator).await?);
+        telemetry.shutdown();
+        return Ok(());
+    }
+    let redis_connection_manager = redis_connection_manager?;
 
     // Initialize JWT manager backed by AWS KMS
-    let kms_client = Arc::new(KmsClient::new(&environment.aws_config().await));
-    let jwt_manager = Arc::new(JwtManager::new(kms_client, &environment).await?);
+    let mut jwt_manager = JwtManager::new(aws_clients.kms, &environment).await?;
+    if let Some(connection_manager) = &redis_connection_manager {
+        jwt_manager =
+            jwt_manager.with_revocation_list(TokenRevocationList::new(connection_manager.clone()));
+    }
+    let jwt_manager = Arc::new(jwt_manager);
 
-    // Initialize S3 client and media storage
-    let s3_client = Arc::new(S3Client::from_conf(environment.s3_client_config().await));
-    let media_storage = Arc::new(MediaStorage::new(
-        s3_client,
+    // Initialize media storage
+    let mut media_storage = MediaStorage::new(
+        aws_clients.s3,
         environment.s3_bucket(),
         environment.presigned_url_expiry_secs(),
-    ));
+    );
+    if let Some(connection_manager) = &redis_connection_manager {
+        media_storage = media_storage.with_presigned_url_cache(PresignedUrlCache::new(
+            connection_manager.clone(),
+            DEFAULT_IDEMPOTENCY_WINDOW,
+        ));
+    }
+    let media_storage = Arc::new(media_storage);
 
-    // Initialize DynamoDB client, auth proof and push subscriptions storage
-    let dynamodb_client = Arc::new(DynamoDbClient::new(&environment.aws_config().await));
+    // Initialize auth proof and push subscriptions storage
+    let dynamodb_client = aws_clients.dynamodb;
     let auth_proof_storage = Arc::new(AuthProofStorage::new(
         dynamodb_client.clone(),
         environment.dynamodb_auth_table_name(),
     ));
-    let push_subscription_storage = Arc::new(PushSubscriptionStorage::new(
+    let mut push_subscription_storage = PushSubscriptionStorage::new(
         dynamodb_client,
         environment.dynamodb_push_subscription_table_name(),
-    ));
+        environment.dynamodb_push_subscription_push_id_index_name(),
+    );
 
-    // Initalize Enclave Worker API client
-    let enclave_worker_api: Arc<dyn EnclaveWorkerApi> = Arc::new(EnclaveWorkerApiClient::new(
-        environment.enclave_worker_url(),
-    ));
+    // Optionally publish subscription changes so caches can invalidate
+    if let Some(connection_manager) = redis_connection_manager {
+        push_subscription_storage = push_subscription_storage.with_invalidation_publisher(
+            SubscriptionInvalidationPublisher::new(
+                connection_manager,
+                SUBSCRIPTION_INVALIDATION_CHANNEL.to_string(),
+            ),
+        );
+    }
+    let push_subscription_storage = Arc::new(push_subscription_storage);
+
+    // Optional notification dead-letter queue, peeked by the admin endpoint
+    let notification_dead_letter_queue = environment.notification_dlq_config().map(|config| {
+        NotificationDeadLetterQueue(Arc::new(NotificationQueue::new(aws_clients.sqs, config)))
+    });
 
     let result = server::start(
         environment,
@@ -57,11 +122,23 @@ async fn main() -> anyhow::Result<()> {
         auth_proof_storage,
         push_subscription_storage,
         enclave_worker_api,
+        notification_dead_letter_queue,
     )
     .await;
 
-    // Ensure the tracer is properly shut down
-    tracer_shutdown.shutdown();
+    // Ensure the tracer and logs are properly flushed
+    telemetry.shutdown();
 
     result
 }
+
+/// Optional Redis connection, for token revocation, presigned URL idempotency
+/// and subscription invalidation
+async fn connect_redis(redis_url: Option<String>) -> anyhow::Result<Option<ConnectionManager>> {
+    match redis_url {
+        Some(redis_url) => Ok(Some(
+            ConnectionManager::new(redis::Client::open(redis_url)?).await?,
+        )),
+        None => Ok(None),
+    }
+}
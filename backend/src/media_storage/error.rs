@@ -35,6 +35,15 @@ pub enum BucketError {
     /// Invalid input provided
     #[error("Invalid input: {0}")]
     InvalidInput(String),
+
+    /// Uploaded content does not hash to the claimed SHA-256 digest
+    #[error("Digest mismatch: expected {expected}, got {actual}")]
+    DigestMismatch {
+        /// Claimed SHA-256 digest (hex)
+        expected: String,
+        /// SHA-256 digest of the received content (hex)
+        actual: String,
+    },
 }
 
 impl From<aws_sdk_s3::Error> for BucketError {
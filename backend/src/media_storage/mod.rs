@@ -1,5 +1,6 @@
 //! S3-based image storage operations
 mod error;
+mod presigned_url_cache;
 
 use std::sync::Arc;
 use std::time::Duration;
@@ -10,12 +11,21 @@ use aws_sdk_s3::{
 };
 use base64::{engine::general_purpose::STANDARD, Engine as _};
 use chrono::{DateTime, Utc};
+use futures::{Stream, StreamExt};
 use hex::FromHex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
 pub use error::{BucketError, BucketResult};
+pub use presigned_url_cache::{PresignedUrlCache, DEFAULT_IDEMPOTENCY_WINDOW};
+
+/// `Cache-Control` for media objects
+///
+/// Media is content-addressed by its SHA-256 digest, so an object never changes once written.
+pub const IMMUTABLE_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
 
 /// Presigned URL with expiration information
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PresignedUrl {
     /// The presigned URL for PUT operations
     pub url: String,
@@ -28,6 +38,7 @@ pub struct MediaStorage {
     s3_client: Arc<S3Client>,
     bucket_name: String,
     presigned_url_expiry_secs: u64,
+    presigned_url_cache: Option<PresignedUrlCache>,
 }
 
 impl MediaStorage {
@@ -48,9 +59,19 @@ impl MediaStorage {
             s3_client,
             bucket_name,
             presigned_url_expiry_secs,
+            presigned_url_cache: None,
         }
     }
 
+    /// Caches presigned upload URLs for requests with an idempotency key
+    ///
+    /// Without a cache, every request generates a new presigned URL.
+    #[must_use]
+    pub fn with_presigned_url_cache(mut self, presigned_url_cache: PresignedUrlCache) -> Self {
+        self.presigned_url_cache = Some(presigned_url_cache);
+        self
+    }
+
     #[must_use]
     pub fn map_sha256_to_s3_key(sha256: &str) -> String {
         let ad = &sha256[0..2];
@@ -80,6 +101,54 @@ impl MediaStorage {
         Ok(STANDARD.encode(digest_bytes))
     }
 
+    /// Verifies that a streamed body hashes to the claimed SHA-256 digest
+    ///
+    /// Chunks are fed to the hasher as they arrive, so the body is never buffered in full.
+    ///
+    /// # Arguments
+    ///
+    /// * `body` - Stream of body chunks
+    /// * `claimed_sha256` - The claimed SHA-256 digest as a 64-character hex string
+    ///
+    /// # Returns
+    ///
+    /// The number of bytes read from the stream
+    ///
+    /// # Errors
+    ///
+    /// Returns `BucketError::InvalidInput` if the claimed digest is malformed or the stream fails
+    /// Returns `BucketError::DigestMismatch` if the content hashes to a different digest
+    pub async fn verify_sha256_stream<S, B, E>(body: S, claimed_sha256: &str) -> BucketResult<u64>
+    where
+        S: Stream<Item = Result<B, E>> + Send,
+        B: AsRef<[u8]>,
+        E: std::fmt::Display,
+    {
+        let expected = <[u8; 32]>::from_hex(claimed_sha256)
+            .map_err(|e| BucketError::InvalidInput(format!("Invalid SHA-256 digest: {e}")))?;
+
+        let mut hasher = Sha256::new();
+        let mut content_length = 0u64;
+        let mut body = std::pin::pin!(body);
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk
+                .map_err(|e| BucketError::InvalidInput(format!("Failed to read body: {e}")))?;
+            let chunk = chunk.as_ref();
+            hasher.update(chunk);
+            content_length += chunk.len() as u64;
+        }
+
+        let actual: [u8; 32] = hasher.finalize().into();
+        if actual != expected {
+            return Err(BucketError::DigestMismatch {
+                expected: claimed_sha256.to_lowercase(),
+                actual: hex::encode(actual),
+            });
+        }
+
+        Ok(content_length)
+    }
+
     /// Checks if an object exists in the bucket
     ///
     /// # Arguments
@@ -96,8 +165,28 @@ impl MediaStorage {
     ///
     /// Returns `BucketError::S3Error` for S3 service errors
     /// Returns `BucketError::UpstreamError` for 5xx errors
-    #[allow(clippy::cognitive_complexity)]
     pub async fn check_object_exists(&self, s3_key: &str) -> BucketResult<bool> {
+        Ok(self.head_object_etag(s3_key).await?.is_some())
+    }
+
+    /// Gets the `ETag` of an object in the bucket, which clients can use for conditional GETs
+    ///
+    /// # Arguments
+    ///
+    /// * `s3_key` - The image ID to check (64-char hex string)
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(etag))` if object exists, `etag` is empty if S3 didn't return one
+    /// * `Ok(None)` if object does not exist
+    /// * `Err(BucketError)` if S3 operation fails
+    ///
+    /// # Errors
+    ///
+    /// Returns `BucketError::S3Error` for S3 service errors
+    /// Returns `BucketError::UpstreamError` for 5xx errors
+    #[allow(clippy::cognitive_complexity)]
+    pub async fn head_object_etag(&self, s3_key: &str) -> BucketResult<Option<String>> {
         let result = self
             .s3_client
             .head_object()
@@ -107,7 +196,7 @@ impl MediaStorage {
             .await;
 
         match result {
-            Ok(_) => Ok(true),
+            Ok(output) => Ok(Some(output.e_tag().unwrap_or_default().to_string())),
             // In production we've disabled s3:ListBucket permission for the bucket for security reasons
             // We still handle the case as fallback and log a warning message for this path
             Err(SdkError::ServiceError(service_err))
@@ -116,7 +205,7 @@ impl MediaStorage {
                 tracing::warn!(
                     "head_object returned NotFound, indicating S3:ListBucket permission is granted"
                 );
-                Ok(false)
+                Ok(None)
             }
             // In production we've disabled s3:ListBucket permission for the bucket for security reasons
             // In that case head_object will return 403 if the object doesn't exist
@@ -124,7 +213,7 @@ impl MediaStorage {
             Err(SdkError::ServiceError(service_err))
                 if service_err.raw().status().as_u16() == 403 =>
             {
-                Ok(false)
+                Ok(None)
             }
             Err(SdkError::ServiceError(service_err))
                 if service_err.raw().status().as_u16() >= 500 =>
@@ -186,4 +275,183 @@ impl MediaStorage {
             expires_at,
         })
     }
+
+    /// Generates a presigned URL for PUT operations, reusing the URL of a previous request
+    /// with the same idempotency key and parameters
+    ///
+    /// Falls back to `generate_presigned_put_url` if no presigned URL cache is configured.
+    ///
+    /// # Arguments
+    ///
+    /// * `idempotency_key` - Client-provided key identifying retries of the same request
+    /// * `content_digest_sha256` - The SHA-256 digest of the content
+    /// * `content_length` - The expected content length in bytes
+    /// * `content_type` - The content type of the upload
+    ///
+    /// # Errors
+    ///
+    /// Same as `generate_presigned_put_url`
+    pub async fn generate_idempotent_presigned_put_url(
+        &self,
+        idempotency_key: &str,
+        content_digest_sha256: &str,
+        content_length: i64,
+        content_type: &str,
+    ) -> BucketResult<PresignedUrl> {
+        let Some(cache) = &self.presigned_url_cache else {
+            return self
+                .generate_presigned_put_url(content_digest_sha256, content_length, content_type)
+                .await;
+        };
+
+        let cache_key = PresignedUrlCache::cache_key(
+            idempotency_key,
+            content_digest_sha256,
+            content_length,
+            content_type,
+        );
+        if let Some(presigned_url) = cache.get(&cache_key).await {
+            return Ok(presigned_url);
+        }
+
+        let presigned_url = self
+            .generate_presigned_put_url(content_digest_sha256, content_length, content_type)
+            .await?;
+        cache.set(&cache_key, &presigned_url).await;
+
+        Ok(presigned_url)
+    }
+
+    /// Generates a presigned URL for GET operations
+    ///
+    /// The URL overrides the response `Cache-Control` with [`IMMUTABLE_CACHE_CONTROL`]
+    /// so clients and CDNs can cache the object indefinitely.
+    ///
+    /// # Arguments
+    ///
+    /// * `content_digest_sha256` - The SHA-256 digest of the content
+    ///
+    /// # Returns
+    ///
+    /// A `PresignedUrl` struct containing the URL and expiration time
+    ///
+    /// # Errors
+    ///
+    /// Returns `BucketError::InvalidInput` if the digest is not a valid 64-character hex string
+    /// Returns `BucketError::S3Error` if presigned URL generation fails
+    /// Returns `BucketError::ConfigError` if presigning config creation fails
+    pub async fn generate_presigned_get_url(
+        &self,
+        content_digest_sha256: &str,
+    ) -> BucketResult<PresignedUrl> {
+        // Validates the digest before it is used to build the key
+        Self::map_sha256_to_b64(content_digest_sha256)?;
+        let s3_key = Self::map_sha256_to_s3_key(content_digest_sha256);
+
+        let presigned_config =
+            PresigningConfig::expires_in(Duration::from_secs(self.presigned_url_expiry_secs))
+                .map_err(|e| {
+                    BucketError::ConfigError(format!("Failed to create presigning config: {e}"))
+                })?;
+
+        let presigned_url = self
+            .s3_client
+            .get_object()
+            .bucket(&self.bucket_name)
+            .key(s3_key)
+            .response_cache_control(IMMUTABLE_CACHE_CONTROL)
+            .presigned(presigned_config)
+            .await
+            .map_err(|e| BucketError::S3Error(format!("Failed to generate presigned URL: {e}")))?;
+
+        let expires_at: DateTime<Utc> =
+            Utc::now() + Duration::from_secs(self.presigned_url_expiry_secs);
+
+        Ok(PresignedUrl {
+            url: presigned_url.uri().to_string(),
+            expires_at,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aws_sdk_s3::config::{BehaviorVersion, Credentials, Region};
+    use futures::stream;
+
+    use super::*;
+
+    fn test_media_storage() -> MediaStorage {
+        let config = aws_sdk_s3::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new("us-east-1"))
+            .credentials_provider(Credentials::new("test", "test", None, None, "test"))
+            .build();
+
+        MediaStorage::new(
+            Arc::new(S3Client::from_conf(config)),
+            "media-bucket".to_string(),
+            900,
+        )
+    }
+
+    fn chunked(content: &'static [u8]) -> impl Stream<Item = Result<&'static [u8], String>> {
+        stream::iter(content.chunks(3).map(Ok))
+    }
+
+    #[tokio::test]
+    async fn test_verify_sha256_stream_matching_content() {
+        let content = b"content addressed media";
+        let digest = hex::encode(Sha256::digest(content));
+
+        let len = MediaStorage::verify_sha256_stream(chunked(content), &digest)
+            .await
+            .unwrap();
+
+        assert_eq!(len, content.len() as u64);
+    }
+
+    #[tokio::test]
+    async fn test_verify_sha256_stream_mismatching_content() {
+        let digest = hex::encode(Sha256::digest(b"claimed content"));
+
+        let err = MediaStorage::verify_sha256_stream(chunked(b"uploaded content"), &digest)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            err,
+            BucketError::DigestMismatch { expected, .. } if expected == digest
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_verify_sha256_stream_stream_error() {
+        let digest = hex::encode(Sha256::digest(b""));
+        let body = stream::iter([Ok(&b"abc"[..]), Err("connection reset".to_string())]);
+
+        let err = MediaStorage::verify_sha256_stream(body, &digest)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err, BucketError::InvalidInput(_)));
+    }
+
+    #[tokio::test]
+    async fn test_presigned_get_url_overrides_cache_control() {
+        let digest = hex::encode(Sha256::digest(b"content addressed media"));
+
+        let presigned = test_media_storage()
+            .generate_presigned_get_url(&digest)
+            .await
+            .unwrap();
+
+        let url = url::Url::parse(&presigned.url).unwrap();
+        assert!(url
+            .path()
+            .ends_with(&MediaStorage::map_sha256_to_s3_key(&digest)));
+        assert!(url.query_pairs().any(
+            |(key, value)| key == "response-cache-control" && value == IMMUTABLE_CACHE_CONTROL
+        ));
+    }
 }
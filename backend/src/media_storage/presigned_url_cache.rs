@@ -0,0 +1,104 @@
+//! Redis-backed cache of presigned upload URLs, keyed by client idempotency key
+
+use std::time::Duration;
+
+use redis::{aio::ConnectionManager, AsyncCommands};
+use tokio::time::timeout;
+
+use super::PresignedUrl;
+
+const REDIS_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Default window in which retries with the same idempotency key get the same URL
+pub const DEFAULT_IDEMPOTENCY_WINDOW: Duration = Duration::from_secs(5 * 60);
+
+/// Caches presigned upload URLs so client retries get the same URL and expiry
+///
+/// All operations are best-effort: Redis failures are logged and treated as cache misses.
+#[derive(Clone)]
+pub struct PresignedUrlCache {
+    connection_manager: ConnectionManager,
+    window: Duration,
+}
+
+impl PresignedUrlCache {
+    /// Creates a new cache
+    ///
+    /// # Arguments
+    ///
+    /// * `connection_manager` - Redis connection manager
+    /// * `window` - How long a presigned URL is returned for the same idempotency key
+    #[must_use]
+    pub const fn new(connection_manager: ConnectionManager, window: Duration) -> Self {
+        Self {
+            connection_manager,
+            window,
+        }
+    }
+
+    /// Builds the cache key, which covers every parameter signed into the URL
+    #[must_use]
+    pub fn cache_key(
+        idempotency_key: &str,
+        digest: &str,
+        content_length: i64,
+        content_type: &str,
+    ) -> String {
+        format!("media:presigned-url:{digest}:{content_length}:{content_type}:{idempotency_key}")
+    }
+
+    /// Returns the cached presigned URL for `cache_key`, if any
+    pub async fn get(&self, cache_key: &str) -> Option<PresignedUrl> {
+        let mut conn = self.connection_manager.clone();
+        let cached = match timeout(REDIS_TIMEOUT, conn.get::<_, Option<String>>(cache_key)).await {
+            Ok(Ok(cached)) => cached?,
+            Ok(Err(e)) => {
+                tracing::warn!("Failed to get presigned URL cache key {cache_key}: {e}");
+                return None;
+            }
+            Err(_) => {
+                tracing::warn!("Timed out getting presigned URL cache key {cache_key}");
+                return None;
+            }
+        };
+
+        serde_json::from_str(&cached)
+            .inspect_err(|e| tracing::warn!("Invalid cached presigned URL for {cache_key}: {e}"))
+            .ok()
+    }
+
+    /// Caches `presigned_url` for the idempotency window, or until it expires if sooner
+    pub async fn set(&self, cache_key: &str, presigned_url: &PresignedUrl) {
+        let Ok(remaining) = (presigned_url.expires_at - chrono::Utc::now()).to_std() else {
+            return;
+        };
+        let ttl_secs = self.window.min(remaining).as_secs();
+        if ttl_secs == 0 {
+            return;
+        }
+
+        let value = match serde_json::to_string(presigned_url) {
+            Ok(value) => value,
+            Err(e) => {
+                tracing::warn!("Failed to serialize presigned URL for {cache_key}: {e}");
+                return;
+            }
+        };
+
+        let mut conn = self.connection_manager.clone();
+        match timeout(
+            REDIS_TIMEOUT,
+            conn.set_ex::<_, _, ()>(cache_key, value, ttl_secs),
+        )
+        .await
+        {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => {
+                tracing::warn!("Failed to set presigned URL cache key {cache_key}: {e}");
+            }
+            Err(_) => {
+                tracing::warn!("Timed out setting presigned URL cache key {cache_key}");
+            }
+        }
+    }
+}
@@ -1,8 +1,8 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Instant};
 
 use aide::OperationIo;
 use axum::{
-    extract::{FromRequestParts, Request},
+    extract::{FromRequestParts, Request, State},
     http::{header::AUTHORIZATION, request::Parts, StatusCode},
     middleware::Next,
     response::Response,
@@ -10,7 +10,8 @@ use axum::{
 };
 
 use crate::{
-    jwt::{JwsPayload, JwtManager},
+    jwt::{JwsPayload, JwtManager, SCOPE_USER},
+    middleware::server_timing::{ServerTiming, PHASE_JWT},
     types::{AppError, Environment},
 };
 
@@ -19,12 +20,28 @@ use crate::{
 pub struct AuthenticatedUser {
     /// The encrypted push ID from the JWT subject
     pub encrypted_push_id: String,
+    /// Scopes granted to the JWT, `None` for tokens without a `scope` claim
+    pub scope: Option<Vec<String>>,
+}
+
+impl AuthenticatedUser {
+    /// Returns whether the user's token grants `scope`
+    ///
+    /// Tokens issued before scopes were introduced carry no `scope` claim
+    /// and are only granted the user scope.
+    #[must_use]
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scope.as_ref().map_or(scope == SCOPE_USER, |scopes| {
+            scopes.iter().any(|granted| granted == scope)
+        })
+    }
 }
 
 impl From<JwsPayload> for AuthenticatedUser {
     fn from(payload: JwsPayload) -> Self {
         Self {
             encrypted_push_id: payload.subject,
+            scope: payload.scope,
         }
     }
 }
@@ -97,14 +114,16 @@ pub async fn auth_middleware(
         .headers()
         .get(AUTHORIZATION)
         .and_then(|header| header.to_str().ok())
-        .and_then(|header| header.strip_prefix("Bearer "));
+        .and_then(|header| header.strip_prefix("Bearer "))
+        .map(str::to_owned);
 
     // If auth is disabled, we skip token validation
     // and use the token as the encrypted push id
     if environment.disable_auth() {
         if let Some(token) = stripped_auth_header {
             let authenticated_user = AuthenticatedUser {
-                encrypted_push_id: token.to_string(),
+                encrypted_push_id: token,
+                scope: None,
             };
             request.extensions_mut().insert(authenticated_user);
         }
@@ -122,16 +141,19 @@ pub async fn auth_middleware(
     })?;
 
     // Validate JWT
-    let claims = jwt_manager
-        .validate(token, parse_cutoff_timestamp())
-        .map_err(|_| {
-            AppError::new(
-                StatusCode::UNAUTHORIZED,
-                "invalid_token",
-                "Invalid or expired token",
-                false,
-            )
-        })?;
+    let validation_start = Instant::now();
+    let claims = jwt_manager.validate(&token, parse_cutoff_timestamp()).await;
+    if let Some(timing) = request.extensions().get::<ServerTiming>() {
+        timing.record(PHASE_JWT, validation_start.elapsed());
+    }
+    let claims = claims.map_err(|_| {
+        AppError::new(
+            StatusCode::UNAUTHORIZED,
+            "invalid_token",
+            "Invalid or expired token",
+            false,
+        )
+    })?;
 
     // Add authenticated user to request extensions
     let user = AuthenticatedUser::from(claims);
@@ -139,3 +161,39 @@ pub async fn auth_middleware(
 
     Ok(next.run(request).await)
 }
+
+/// Scope authorization middleware
+///
+/// Must be layered inside `auth_middleware`. Rejects requests whose token doesn't grant
+/// the required scope, given as the middleware state:
+/// ```ignore
+/// router.layer(middleware::from_fn_with_state(SCOPE_ADMIN, require_scope))
+/// ```
+///
+/// Requests without an authenticated user only reach this middleware when auth is disabled,
+/// and are let through.
+///
+/// # Errors
+///
+/// - `AppError` - Missing scope with 403 status code
+pub async fn require_scope(
+    State(scope): State<&'static str>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let has_scope = request
+        .extensions()
+        .get::<AuthenticatedUser>()
+        .is_none_or(|user| user.has_scope(scope));
+
+    if !has_scope {
+        return Err(AppError::new(
+            StatusCode::FORBIDDEN,
+            "insufficient_scope",
+            "Token does not grant the required scope",
+            false,
+        ));
+    }
+
+    Ok(next.run(request).await)
+}
@@ -0,0 +1,126 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::Response,
+};
+use tokio::sync::Semaphore;
+
+use crate::types::AppError;
+
+/// Default maximum number of requests handled concurrently
+pub const DEFAULT_MAX_IN_FLIGHT_REQUESTS: usize = 512;
+
+/// Path of the health check, never shed so load doesn't fail the liveness probe
+const HEALTH_PATH: &str = "/health";
+
+/// Bound on the number of requests handled concurrently, shared by every route
+#[derive(Debug, Clone)]
+pub struct ConcurrencyLimit {
+    permits: Arc<Semaphore>,
+}
+
+impl ConcurrencyLimit {
+    /// Creates a limit of `max_in_flight` concurrent requests
+    #[must_use]
+    pub fn new(max_in_flight: usize) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(max_in_flight)),
+        }
+    }
+}
+
+/// Load shedding middleware
+///
+/// Every request holds a permit of the `ConcurrencyLimit` given as the middleware state while
+/// it's handled. Requests arriving while every permit is held are rejected right away instead
+/// of queueing, so a traffic spike can't pile up KMS calls and enclave round-trips:
+/// ```ignore
+/// router.layer(middleware::from_fn_with_state(limit, concurrency_limit_middleware))
+/// ```
+///
+/// # Errors
+///
+/// - `AppError` - Too many requests in flight with 503 status code
+pub async fn concurrency_limit_middleware(
+    State(limit): State<ConcurrencyLimit>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    if request.uri().path() == HEALTH_PATH {
+        return Ok(next.run(request).await);
+    }
+
+    let Ok(_permit) = limit.permits.try_acquire_owned() else {
+        return Err(AppError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "overloaded",
+            "Too many requests in flight, retry later",
+            true,
+        ));
+    };
+
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, middleware, routing::get, Router};
+    use tokio::sync::{oneshot, Mutex};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn request(path: &str) -> Request {
+        Request::builder().uri(path).body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_requests_beyond_limit_are_shed() {
+        let (entered_tx, entered_rx) = oneshot::channel::<()>();
+        let (release_tx, release_rx) = oneshot::channel::<()>();
+        let entered_tx = Arc::new(Mutex::new(Some(entered_tx)));
+        let release_rx = Arc::new(Mutex::new(Some(release_rx)));
+
+        let router = Router::new()
+            .route(
+                "/slow",
+                get(move || {
+                    let (entered_tx, release_rx) = (entered_tx.clone(), release_rx.clone());
+                    async move {
+                        // Only the first request blocks, until released
+                        let first = entered_tx.lock().await.take();
+                        if let Some(entered_tx) = first {
+                            let _ = entered_tx.send(());
+                            let release_rx = release_rx.lock().await.take().unwrap();
+                            let _ = release_rx.await;
+                        }
+                    }
+                }),
+            )
+            .route("/health", get(|| async {}))
+            .layer(middleware::from_fn_with_state(
+                ConcurrencyLimit::new(1),
+                concurrency_limit_middleware,
+            ));
+
+        let in_flight = tokio::spawn(router.clone().oneshot(request("/slow")));
+        entered_rx.await.unwrap();
+
+        // The only permit is held
+        let shed = router.clone().oneshot(request("/slow")).await.unwrap();
+        assert_eq!(shed.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let health = router.clone().oneshot(request("/health")).await.unwrap();
+        assert_eq!(health.status(), StatusCode::OK);
+
+        // The permit is released once the request completes
+        release_tx.send(()).unwrap();
+        assert_eq!(in_flight.await.unwrap().unwrap().status(), StatusCode::OK);
+
+        let accepted = router.oneshot(request("/slow")).await.unwrap();
+        assert_eq!(accepted.status(), StatusCode::OK);
+    }
+}
@@ -1,3 +1,10 @@
 pub mod auth;
+pub mod concurrency_limit;
+pub mod request_id;
+pub mod server_timing;
+pub mod timeout;
 
 pub use auth::AuthenticatedUser;
+pub use concurrency_limit::ConcurrencyLimit;
+pub use request_id::RequestId;
+pub use server_timing::ServerTiming;
@@ -0,0 +1,183 @@
+use std::fmt;
+
+use axum::{
+    extract::Request,
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use tracing::Instrument;
+
+/// `X-Request-Id` response header
+pub const REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+tokio::task_local! {
+    static CURRENT_REQUEST_ID: RequestId;
+}
+
+/// Unique id of a request, recorded on its span and returned to the client
+///
+/// Users can quote it to support, who can then find the request's logs. The id is generated
+/// rather than taken from the OpenTelemetry trace, as it is assigned before the trace starts so
+/// that shed requests get one too.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(String);
+
+impl RequestId {
+    /// Generates a new random request id
+    #[must_use]
+    pub fn new() -> Self {
+        Self(uuid::Uuid::new_v4().to_string())
+    }
+
+    /// Id of the request being handled, if it went through `request_id_middleware`
+    #[must_use]
+    pub fn current() -> Option<Self> {
+        CURRENT_REQUEST_ID.try_with(Clone::clone).ok()
+    }
+}
+
+impl Default for RequestId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Request id middleware
+///
+/// Generates a `RequestId` for each request, available through `RequestId::current` while the
+/// request is handled and returned in the `X-Request-Id` response header. Mount it outermost so
+/// every response carries one, and `request_span_middleware` inside the tracing layers.
+pub async fn request_id_middleware(mut request: Request, next: Next) -> Response {
+    let request_id = RequestId::new();
+    request.extensions_mut().insert(request_id.clone());
+
+    let mut response = CURRENT_REQUEST_ID
+        .scope(request_id.clone(), next.run(request))
+        .await;
+
+    if let Ok(value) = HeaderValue::from_str(&request_id.0) {
+        response.headers_mut().insert(REQUEST_ID, value);
+    }
+
+    response
+}
+
+/// Request span middleware
+///
+/// Handles the request in a span recording its `RequestId`, so its logs can be found by id.
+/// Mounted inside the tracing layers, the span belongs to the request's trace.
+pub async fn request_span_middleware(request: Request, next: Next) -> Response {
+    let span = match RequestId::current() {
+        Some(request_id) => tracing::info_span!("request", request_id = %request_id),
+        None => tracing::info_span!("request"),
+    };
+    next.run(request).instrument(span).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        sync::{Arc, Mutex},
+    };
+
+    use axum::{body::Body, http::StatusCode, middleware, routing::get, Router};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::middleware::concurrency_limit::{concurrency_limit_middleware, ConcurrencyLimit};
+    use crate::types::AppError;
+
+    /// Log output shared with the test
+    #[derive(Clone, Default)]
+    struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for CapturedLogs {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_error_response_references_logged_request_id() {
+        let logs = CapturedLogs::default();
+        let subscriber = tracing_subscriber::fmt()
+            .with_ansi(false)
+            .with_writer({
+                let logs = logs.clone();
+                move || logs.clone()
+            })
+            .finish();
+        let _default = tracing::subscriber::set_default(subscriber);
+
+        let router = Router::new()
+            .route(
+                "/failing",
+                get(|| async {
+                    Err::<(), _>(AppError::new(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        "internal_error",
+                        "Internal server error",
+                        true,
+                    ))
+                }),
+            )
+            .layer(middleware::from_fn(request_span_middleware))
+            .layer(middleware::from_fn(request_id_middleware));
+
+        let request = Request::builder()
+            .uri("/failing")
+            .body(Body::empty())
+            .unwrap();
+        let response = router.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        let header = response.headers()[REQUEST_ID].to_str().unwrap().to_string();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let reference = body["reference"].as_str().unwrap();
+        assert_eq!(reference, header);
+
+        // The error is logged in the span of the request
+        let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        assert!(logs
+            .lines()
+            .any(|line| line.contains("Server error") && line.contains(reference)));
+    }
+
+    #[tokio::test]
+    async fn test_shed_request_is_referenced() {
+        let router = Router::new()
+            .route("/any", get(|| async {}))
+            .layer(middleware::from_fn_with_state(
+                ConcurrencyLimit::new(0),
+                concurrency_limit_middleware,
+            ))
+            .layer(middleware::from_fn(request_id_middleware));
+
+        let request = Request::builder().uri("/any").body(Body::empty()).unwrap();
+        let response = router.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let header = response.headers()[REQUEST_ID].to_str().unwrap().to_string();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["reference"], header.as_str());
+    }
+}
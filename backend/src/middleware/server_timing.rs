@@ -0,0 +1,135 @@
+use std::{
+    convert::Infallible,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use aide::OperationIo;
+use axum::{
+    extract::{FromRequestParts, Request},
+    http::{request::Parts, HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+
+/// `Server-Timing` response header
+pub const SERVER_TIMING: HeaderName = HeaderName::from_static("server-timing");
+
+/// Phase for JWT validation
+pub const PHASE_JWT: &str = "jwt";
+/// Phase for `DynamoDB` and S3 calls
+pub const PHASE_STORAGE: &str = "storage";
+/// Phase for enclave worker calls
+pub const PHASE_ENCLAVE: &str = "enclave";
+
+/// Accumulates the time spent in each phase of a request
+///
+/// Inserted into the request extensions by `server_timing_middleware`, handlers and middleware
+/// record their phases on it and the totals are rendered into the `Server-Timing` header.
+#[derive(Debug, Clone, Default, OperationIo)]
+pub struct ServerTiming {
+    phases: Arc<Mutex<Vec<(&'static str, Duration)>>>,
+}
+
+impl ServerTiming {
+    /// Adds `duration` to the total of `phase`
+    ///
+    /// # Panics
+    ///
+    /// If the lock is poisoned
+    pub fn record(&self, phase: &'static str, duration: Duration) {
+        let mut phases = self.phases.lock().expect("server timing lock poisoned");
+        if let Some((_, total)) = phases.iter_mut().find(|(name, _)| *name == phase) {
+            *total += duration;
+        } else {
+            phases.push((phase, duration));
+        }
+    }
+
+    /// Awaits `future` and records its duration under `phase`
+    pub async fn time<F: Future>(&self, phase: &'static str, future: F) -> F::Output {
+        let start = Instant::now();
+        let output = future.await;
+        self.record(phase, start.elapsed());
+        output
+    }
+
+    /// Renders the recorded phases as a `Server-Timing` header value, e.g. `storage;dur=12.3`
+    ///
+    /// Returns `None` if no phase was recorded.
+    ///
+    /// # Panics
+    ///
+    /// If the lock is poisoned
+    #[must_use]
+    pub fn header_value(&self) -> Option<HeaderValue> {
+        let value = {
+            let phases = self.phases.lock().expect("server timing lock poisoned");
+            if phases.is_empty() {
+                return None;
+            }
+
+            phases
+                .iter()
+                .map(|(name, duration)| {
+                    format!("{name};dur={:.1}", duration.as_secs_f64() * 1000.0)
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        HeaderValue::from_str(&value).ok()
+    }
+}
+
+/// Axum extractor for the request's `ServerTiming`
+///
+/// Falls back to a detached accumulator if `server_timing_middleware` is not installed.
+impl<S> FromRequestParts<S> for ServerTiming
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts.extensions.get::<Self>().cloned().unwrap_or_default())
+    }
+}
+
+/// Server timing middleware
+///
+/// Adds a `ServerTiming` to the request extensions and renders the recorded phases
+/// into the `Server-Timing` response header.
+pub async fn server_timing_middleware(mut request: Request, next: Next) -> Response {
+    let timing = ServerTiming::default();
+    request.extensions_mut().insert(timing.clone());
+
+    let mut response = next.run(request).await;
+
+    if let Some(value) = timing.header_value() {
+        response.headers_mut().insert(SERVER_TIMING, value);
+    }
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_value_sums_phases() {
+        let timing = ServerTiming::default();
+        assert!(timing.header_value().is_none());
+
+        timing.record(PHASE_STORAGE, Duration::from_millis(2));
+        timing.record(PHASE_ENCLAVE, Duration::from_millis(5));
+        timing.record(PHASE_STORAGE, Duration::from_millis(3));
+
+        assert_eq!(
+            timing.header_value().unwrap(),
+            "storage;dur=5.0, enclave;dur=5.0"
+        );
+    }
+}
@@ -0,0 +1,137 @@
+use std::{
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use axum::{
+    extract::Request,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use futures::future::BoxFuture;
+use tower::{Layer, Service};
+
+use crate::enclave_worker_api::{DEFAULT_REQUEST_TIMEOUT_SECS, RATE_LIMIT_RETRY_BUDGET};
+use crate::types::AppError;
+
+/// Default time budget of a route
+pub const DEFAULT_ROUTE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Time budget of the routes calling the enclave worker
+///
+/// Covers an enclave worker call timing out after the rate limit retries waited their whole
+/// budget, plus the default budget for the rest of the route's work. A stuck call then fails
+/// with the enclave worker client's error rather than a route timeout.
+pub const ENCLAVE_ROUTE_TIMEOUT: Duration = Duration::from_secs(
+    DEFAULT_REQUEST_TIMEOUT_SECS
+        + RATE_LIMIT_RETRY_BUDGET.as_secs()
+        + DEFAULT_ROUTE_TIMEOUT.as_secs(),
+);
+
+/// Layer answering `504 Gateway Timeout` to requests whose handling exceeds `timeout`
+///
+/// The handler is dropped on timeout, freeing the connection:
+/// ```ignore
+/// router.layer(timeout_layer(DEFAULT_ROUTE_TIMEOUT))
+/// ```
+#[must_use]
+pub const fn timeout_layer(timeout: Duration) -> TimeoutLayer {
+    TimeoutLayer { timeout }
+}
+
+/// Error answered to requests exceeding their time budget
+#[must_use]
+pub const fn timeout_error() -> AppError {
+    AppError::new(
+        StatusCode::GATEWAY_TIMEOUT,
+        "timeout",
+        "The request took too long to complete",
+        true,
+    )
+}
+
+/// Layer applying a time budget to the requests, see `timeout_layer`
+#[derive(Debug, Clone, Copy)]
+pub struct TimeoutLayer {
+    timeout: Duration,
+}
+
+impl<S> Layer<S> for TimeoutLayer {
+    type Service = Timeout<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Timeout {
+            inner,
+            timeout: self.timeout,
+        }
+    }
+}
+
+/// Service answering `timeout_error` to requests exceeding `timeout`
+#[derive(Debug, Clone)]
+pub struct Timeout<S> {
+    inner: S,
+    timeout: Duration,
+}
+
+impl<S> Service<Request> for Timeout<S>
+where
+    S: Service<Request, Response = Response>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Response, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        let response = self.inner.call(request);
+        let timeout = self.timeout;
+        Box::pin(async move {
+            tokio::time::timeout(timeout, response)
+                .await
+                .unwrap_or_else(|_| Ok(timeout_error().into_response()))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, extract::Request, routing::get, Router};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_slow_handler_times_out_with_504() {
+        let router = Router::new()
+            .route("/slow", get(std::future::pending::<()>))
+            .route("/fast", get(|| async {}))
+            .layer(timeout_layer(Duration::from_millis(50)));
+
+        let request = |path: &str| Request::builder().uri(path).body(Body::empty()).unwrap();
+
+        let response = router.clone().oneshot(request("/slow")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"]["code"], "timeout");
+        assert_eq!(body["allowRetry"], true);
+
+        let response = router.oneshot(request("/fast")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn test_enclave_route_budget_exceeds_enclave_call_timeout() {
+        assert!(
+            ENCLAVE_ROUTE_TIMEOUT
+                > Duration::from_secs(DEFAULT_REQUEST_TIMEOUT_SECS) + RATE_LIMIT_RETRY_BUDGET
+        );
+    }
+}
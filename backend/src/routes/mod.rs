@@ -3,11 +3,22 @@ mod health;
 pub mod v1;
 
 use aide::axum::{routing::get, ApiRouter};
+use axum::middleware;
+
+use crate::middleware::{
+    request_id::request_span_middleware,
+    server_timing::server_timing_middleware,
+    timeout::{timeout_layer, DEFAULT_ROUTE_TIMEOUT},
+};
 
 /// Creates the router with all handler routes
 pub fn handler() -> ApiRouter {
     ApiRouter::new()
         .merge(docs::handler())
         .api_route("/health", get(health::handler))
+        .layer(timeout_layer(DEFAULT_ROUTE_TIMEOUT))
+        // v1 routes have their own time budgets
         .nest("/v1", v1::handler())
+        .layer(middleware::from_fn(server_timing_middleware))
+        .layer(middleware::from_fn(request_span_middleware))
 }
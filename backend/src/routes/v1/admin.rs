@@ -0,0 +1,210 @@
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::{extract::Query, http::StatusCode, Extension, Json};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    middleware::{server_timing::PHASE_STORAGE, ServerTiming},
+    types::AppError,
+};
+use backend_storage::{
+    ids::TopicId,
+    push_subscription::{PushSubscription, PushSubscriptionStorage},
+    queue::NotificationQueue,
+};
+
+/// Maximum number of dead letters returned by a single peek, the SQS receive limit
+const MAX_PEEK_LIMIT: i32 = 10;
+
+/// Dead-letter queue of the notification queue
+///
+/// Wrapped so it can't be mistaken for another queue extension.
+#[derive(Clone)]
+pub struct NotificationDeadLetterQueue(pub Arc<NotificationQueue>);
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct PurgeSubscriptionsQuery {
+    /// Topic whose subscriptions are deleted
+    pub topic: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct PurgeSubscriptionsResponse {
+    /// Number of subscriptions deleted
+    pub deleted: usize,
+}
+
+/// Delete all subscriptions of a topic
+///
+/// Operational tool to purge the stale subscriptions of a topic, e.g. after its group was
+/// deleted. Subscriptions are deleted regardless of their subscriber.
+///
+/// # Arguments
+///
+/// * `push_storage` - `DynamoDB` storage handler for push subscriptions
+/// * `query` - Query parameters containing the topic
+///
+/// # Returns
+///
+/// Returns `200 OK` with the number of subscriptions deleted.
+///
+/// # Errors
+///
+/// Returns an error if:
+/// - `400 BAD_REQUEST` - Missing or empty topic
+/// - `401 UNAUTHORIZED` - Invalid or missing authentication
+/// - `403 FORBIDDEN` - Token doesn't grant the admin scope
+/// - `503 SERVICE_UNAVAILABLE` - Database connectivity issues
+pub async fn purge_topic_subscriptions(
+    timing: ServerTiming,
+    Extension(push_storage): Extension<Arc<PushSubscriptionStorage>>,
+    Query(query): Query<PurgeSubscriptionsQuery>,
+) -> Result<Json<PurgeSubscriptionsResponse>, AppError> {
+    if query.topic.is_empty() {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "empty_topic",
+            "Topic cannot be empty",
+            false,
+        ));
+    }
+
+    let topic = TopicId::normalize(&query.topic);
+
+    // Subscriptions created before topics were normalized are stored under the raw topic
+    let mut keys = Vec::new();
+    for stored_topic in TopicId::stored_variants(&query.topic) {
+        let subscriptions = timing
+            .time(PHASE_STORAGE, push_storage.get_all_by_topic(&stored_topic))
+            .await?;
+        keys.extend(subscriptions.iter().map(PushSubscription::key));
+    }
+
+    timing
+        .time(PHASE_STORAGE, push_storage.batch_delete_many(&keys))
+        .await?;
+
+    tracing::info!(
+        topic = %topic,
+        deleted = keys.len(),
+        "Purged topic subscriptions"
+    );
+
+    Ok(Json(PurgeSubscriptionsResponse {
+        deleted: keys.len(),
+    }))
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct PeekDeadLettersQuery {
+    /// Number of messages to return, between 1 and 10 (defaults to 10)
+    pub limit: Option<i32>,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct DeadLetter {
+    /// SQS message ID
+    pub message_id: String,
+    /// Topic of the notification, `None` if the message isn't a valid notification
+    pub topic: Option<String>,
+    /// Raw message body, only set if the message isn't a valid notification
+    pub raw_body: Option<String>,
+    /// Approximate number of times the message was received, including this peek
+    pub receive_count: u32,
+    /// Seconds since the message was first sent (if available)
+    pub age_secs: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct PeekDeadLettersResponse {
+    /// Peeked messages, oldest first as returned by SQS
+    pub messages: Vec<DeadLetter>,
+}
+
+/// Peek notification dead letters
+///
+/// Returns the first messages of the notification dead-letter queue without consuming them,
+/// so on-call can triage failing notifications.
+///
+/// On-call authenticates with a token from `backend --issue-admin-token <operator>`.
+///
+/// # Arguments
+///
+/// * `dead_letter_queue` - Notification dead-letter queue, if configured
+/// * `query` - Query parameters containing the number of messages to return
+///
+/// # Returns
+///
+/// Returns `200 OK` with the peeked messages.
+///
+/// # Errors
+///
+/// Returns an error if:
+/// - `400 BAD_REQUEST` - Limit outside of 1..=10
+/// - `401 UNAUTHORIZED` - Invalid or missing authentication
+/// - `403 FORBIDDEN` - Token doesn't grant the admin scope
+/// - `404 NOT_FOUND` - No dead-letter queue is configured
+/// - `500 INTERNAL_SERVER_ERROR` - Reading the queue failed
+pub async fn peek_dead_letters(
+    Extension(dead_letter_queue): Extension<Option<NotificationDeadLetterQueue>>,
+    Query(query): Query<PeekDeadLettersQuery>,
+) -> Result<Json<PeekDeadLettersResponse>, AppError> {
+    let Some(NotificationDeadLetterQueue(queue)) = dead_letter_queue else {
+        return Err(AppError::new(
+            StatusCode::NOT_FOUND,
+            "dead_letter_queue_not_configured",
+            "Dead-letter queue is not configured",
+            false,
+        ));
+    };
+
+    let limit = query.limit.unwrap_or(MAX_PEEK_LIMIT);
+    if !(1..=MAX_PEEK_LIMIT).contains(&limit) {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "invalid_limit",
+            "Limit must be between 1 and 10",
+            false,
+        ));
+    }
+
+    let peeked = queue.peek_messages(limit).await.map_err(|e| {
+        tracing::error!("Failed to peek dead letters: {e:?}");
+        AppError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "internal_error",
+            "Internal server error",
+            false,
+        )
+    })?;
+
+    let now_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| i64::try_from(now.as_millis()).unwrap_or(i64::MAX));
+    let messages = peeked
+        .into_iter()
+        .map(|message| {
+            let (topic, raw_body) = match message.body {
+                Some(notification) => (Some(notification.topic), None),
+                None => (None, Some(message.raw_body)),
+            };
+
+            DeadLetter {
+                message_id: message.message_id,
+                topic,
+                raw_body,
+                receive_count: message.receive_count,
+                age_secs: message
+                    .sent_timestamp_ms
+                    .and_then(|sent_ms| u64::try_from(now_ms - sent_ms).ok())
+                    .map(|age_ms| age_ms / 1000),
+            }
+        })
+        .collect();
+
+    Ok(Json(PeekDeadLettersResponse { messages }))
+}
@@ -4,7 +4,11 @@ use axum::{Extension, Json};
 use schemars::JsonSchema;
 use serde::Serialize;
 
-use crate::{enclave_worker_api::EnclaveWorkerApi, types::AppError};
+use crate::{
+    enclave_worker_api::EnclaveWorkerApi,
+    middleware::{server_timing::PHASE_ENCLAVE, ServerTiming},
+    types::AppError,
+};
 
 #[derive(Debug, Serialize, JsonSchema)]
 pub struct AttestationDocumentResponse {
@@ -18,9 +22,12 @@ pub struct AttestationDocumentResponse {
 ///
 /// If the enclave worker API returns an error, it will be returned.
 pub async fn handler(
+    timing: ServerTiming,
     Extension(enclave_worker_api): Extension<Arc<dyn EnclaveWorkerApi>>,
 ) -> Result<Json<AttestationDocumentResponse>, AppError> {
-    let response = enclave_worker_api.get_attestation_document().await?;
+    let response = timing
+        .time(PHASE_ENCLAVE, enclave_worker_api.get_attestation_document())
+        .await?;
 
     Ok(Json(AttestationDocumentResponse {
         attestation_doc_base64: response.attestation_doc_base64,
@@ -1,7 +1,10 @@
 use std::sync::Arc;
 
 use axum::{http::StatusCode, Extension, Json};
-use backend_storage::auth_proof::{AuthProofInsertRequest, AuthProofStorage};
+use backend_storage::{
+    auth_proof::{AuthProofInsertRequest, AuthProofStorage},
+    ids::{EncryptedPushId, Nullifier},
+};
 use chrono::Utc;
 use common_types::EnclaveTrack;
 use schemars::JsonSchema;
@@ -12,6 +15,10 @@ use walletkit_core::CredentialType;
 use crate::{
     enclave_worker_api::EnclaveWorkerApi,
     jwt::{JwsPayload, JwtManager},
+    middleware::{
+        server_timing::{PHASE_ENCLAVE, PHASE_STORAGE},
+        ServerTiming,
+    },
     types::{AppError, Environment},
     world_id::{error::WorldIdError, verifier::verify_world_id_proof},
 };
@@ -54,6 +61,7 @@ pub struct AuthResponse {
 /// - `AuthProofStorageError` - Database operation failed
 /// - `AppError` - JWT generation failed
 pub async fn authorize_handler(
+    timing: ServerTiming,
     Extension(jwt_manager): Extension<Arc<JwtManager>>,
     Extension(auth_proof_storage): Extension<Arc<AuthProofStorage>>,
     Extension(environment): Extension<Environment>,
@@ -78,11 +86,14 @@ pub async fn authorize_handler(
     .await?;
 
     // 3. Fetch or create the auth-proof record
-    let auth_proof = auth_proof_storage
-        .get_or_insert(AuthProofInsertRequest {
-            nullifier: nullifier_hash,
-            encrypted_push_id: request.encrypted_push_id.clone(),
-        })
+    let auth_proof = timing
+        .time(
+            PHASE_STORAGE,
+            auth_proof_storage.get_or_insert(AuthProofInsertRequest {
+                nullifier: nullifier_hash,
+                encrypted_push_id: request.encrypted_push_id.clone(),
+            }),
+        )
         .await?;
 
     // 4. Decide the push id action
@@ -90,10 +101,13 @@ pub async fn authorize_handler(
     // - If the push ids don't match, but the push id rotation is within the threshold, reject the rotation
     // - Otherwise, rotate the push id and issue a JWT token with the new encrypted push id
     let push_id_action = {
-        let push_ids_match = enclave_worker_api
-            .challenge_push_ids(
-                auth_proof.encrypted_push_id.clone(),
-                request.encrypted_push_id.clone(),
+        let push_ids_match = timing
+            .time(
+                PHASE_ENCLAVE,
+                enclave_worker_api.challenge_push_ids(
+                    auth_proof.encrypted_push_id.clone(),
+                    request.encrypted_push_id.clone(),
+                ),
             )
             .await?;
         let is_push_id_rotation_within_threshold = Utc::now().timestamp()
@@ -119,8 +133,16 @@ pub async fn authorize_handler(
             false,
         )),
         PushIdAction::RotateAndIssue(encrypted_push_id) => {
-            auth_proof_storage
-                .update_encrypted_push_id(&auth_proof.nullifier, &encrypted_push_id)
+            timing
+                .time(
+                    PHASE_STORAGE,
+                    // Rechecks the threshold atomically, in case of a concurrent rotation
+                    auth_proof_storage.update_encrypted_push_id_with_cooldown(
+                        &Nullifier::from(auth_proof.nullifier),
+                        &EncryptedPushId::from(encrypted_push_id.as_str()),
+                        PUSH_ID_ROTATION_THRESHOLD_SECS,
+                    ),
+                )
                 .await?;
             issue_jwt_token(&jwt_manager, encrypted_push_id).await
         }
@@ -1,7 +1,18 @@
-use axum::{http::HeaderMap, Extension, Json};
+use aide::OperationIo;
+use axum::{
+    body::Bytes,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
 use schemars::JsonSchema;
 use serde::Serialize;
-use std::cmp::Ordering;
+use sha2::{Digest, Sha256};
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
 
 use crate::{
     routes::v1::media::{MAX_ASSETS_PER_MESSAGE, MAX_IMAGE_SIZE_BYTES, MAX_VIDEO_SIZE_BYTES},
@@ -129,24 +140,132 @@ pub struct ConfigResponse {
     notification_server_version: String,
 }
 
+/// Serialized config response and its `ETag`
+pub struct CachedConfig {
+    body: Bytes,
+    etag: String,
+}
+
+impl CachedConfig {
+    fn new(config: &ConfigResponse) -> Self {
+        let body = serde_json::to_vec(config).expect("config response is serializable");
+        let digest = Sha256::digest(&body);
+        let etag = format!("\"{}\"", hex::encode(&digest[..16]));
+
+        Self {
+            body: body.into(),
+            etag,
+        }
+    }
+}
+
+/// Server-side cache of the config response, per notification server version
+///
+/// The response is built from compile-time limits and the environment, so entries never go stale
+/// while the process runs.
+#[derive(Default)]
+pub struct ConfigCache {
+    entries: RwLock<HashMap<String, Arc<CachedConfig>>>,
+}
+
+impl ConfigCache {
+    fn get_or_insert_with(
+        &self,
+        notification_server_version: &str,
+        build: impl FnOnce() -> ConfigResponse,
+    ) -> Arc<CachedConfig> {
+        if let Some(cached) = self
+            .entries
+            .read()
+            .expect("config cache lock poisoned")
+            .get(notification_server_version)
+        {
+            return cached.clone();
+        }
+
+        self.entries
+            .write()
+            .expect("config cache lock poisoned")
+            .entry(notification_server_version.to_string())
+            .or_insert_with(|| Arc::new(CachedConfig::new(&build())))
+            .clone()
+    }
+}
+
+/// Config response, or `304 Not Modified` if the client's `If-None-Match` matches
+#[derive(OperationIo)]
+#[aide(output_with = "Json<ConfigResponse>")]
+pub enum GetConfigResponse {
+    Ok(Arc<CachedConfig>),
+    NotModified(Arc<CachedConfig>),
+}
+
+impl IntoResponse for GetConfigResponse {
+    fn into_response(self) -> Response {
+        let (status, cached, body) = match self {
+            Self::Ok(cached) => (StatusCode::OK, cached.clone(), cached.body.clone()),
+            Self::NotModified(cached) => (StatusCode::NOT_MODIFIED, cached, Bytes::new()),
+        };
+
+        let mut response = (status, body).into_response();
+        let headers = response.headers_mut();
+        if status == StatusCode::OK {
+            headers.insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("application/json"),
+            );
+        }
+        // Clients must revalidate, so config changes from a deploy are picked up on the next fetch
+        headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+        if let Ok(etag) = HeaderValue::from_str(&cached.etag) {
+            headers.insert(header::ETAG, etag);
+        }
+        response
+    }
+}
+
+/// Returns whether an `If-None-Match` header matches `etag`, using weak comparison
+fn if_none_match_matches(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .any(|candidate| candidate == "*" || candidate.trim_start_matches("W/") == etag)
+}
+
+/// Get the client configuration
+///
+/// Responses carry an `ETag`; requests with a matching `If-None-Match` get `304 Not Modified`.
+///
+/// # Panics
+///
+/// If the config cache lock is poisoned
 pub async fn get_config(
     headers: HeaderMap,
     Extension(environment): Extension<Environment>,
-) -> Json<ConfigResponse> {
+    Extension(config_cache): Extension<Arc<ConfigCache>>,
+) -> GetConfigResponse {
     let client = ClientInfo::from_headers(&headers);
 
     let notification_server_version = if client.version_is_at_least(4, 0, 0) {
         "v4"
     } else {
         "v1"
-    }
-    .to_string();
+    };
 
-    Json(ConfigResponse {
+    let cached = config_cache.get_or_insert_with(notification_server_version, || ConfigResponse {
         max_assets_per_message: MAX_ASSETS_PER_MESSAGE,
         max_image_size_bytes: MAX_IMAGE_SIZE_BYTES,
         max_video_size_bytes: MAX_VIDEO_SIZE_BYTES,
         trusted_cdn_url: environment.cdn_url(),
-        notification_server_version,
-    })
+        notification_server_version: notification_server_version.to_string(),
+    });
+
+    if if_none_match_matches(&headers, &cached.etag) {
+        GetConfigResponse::NotModified(cached)
+    } else {
+        GetConfigResponse::Ok(cached)
+    }
 }
@@ -2,8 +2,14 @@ use std::sync::Arc;
 
 use aide::OperationIo;
 use axum::Json;
-use axum::{http::StatusCode, response::IntoResponse, Extension};
+use axum::{
+    extract::{FromRequestParts, Path},
+    http::{header, request::Parts, HeaderName, HeaderValue, StatusCode},
+    response::IntoResponse,
+    Extension,
+};
 use axum_valid::Valid;
+use chrono::{DateTime, Utc};
 use mime::Mime;
 use regex::Regex;
 use schemars::JsonSchema;
@@ -13,6 +19,7 @@ use validator::Validate;
 
 use crate::{
     media_storage::MediaStorage,
+    middleware::{server_timing::PHASE_STORAGE, ServerTiming},
     types::{AppError, Environment},
 };
 
@@ -24,6 +31,46 @@ pub const MAX_VIDEO_SIZE_BYTES: i64 = 15 * 1024 * 1024;
 pub const MAX_ASSETS_PER_MESSAGE: usize = 10;
 /// Regex for lowercase SHA-256 digest
 static DIGEST_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"[a-f0-9]{64}$").unwrap());
+/// Header carrying the client idempotency key
+pub const IDEMPOTENCY_KEY: HeaderName = HeaderName::from_static("idempotency-key");
+/// Maximum length of an idempotency key
+const MAX_IDEMPOTENCY_KEY_LENGTH: usize = 128;
+
+/// Optional client idempotency key from the `Idempotency-Key` header
+///
+/// Retries sharing a key get the same presigned URL within a short window.
+#[derive(Debug, Clone, OperationIo)]
+pub struct IdempotencyKey(pub Option<String>);
+
+impl<S> FromRequestParts<S> for IdempotencyKey
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let Some(value) = parts.headers.get(IDEMPOTENCY_KEY) else {
+            return Ok(Self(None));
+        };
+
+        value
+            .to_str()
+            .ok()
+            .filter(|key| {
+                (1..=MAX_IDEMPOTENCY_KEY_LENGTH).contains(&key.len())
+                    && key.bytes().all(|b| b.is_ascii_graphic())
+            })
+            .map(|key| Self(Some(key.to_string())))
+            .ok_or_else(|| {
+                AppError::new(
+                    StatusCode::BAD_REQUEST,
+                    "invalid_idempotency_key",
+                    "Idempotency key must be 1 to 128 visible ASCII characters",
+                    false,
+                )
+            })
+    }
+}
 
 #[derive(Debug, Deserialize, JsonSchema, Validate)]
 #[serde(deny_unknown_fields)]
@@ -81,6 +128,10 @@ pub struct SuccessResponse {
 pub struct ConflictResponse {
     /// CDN URL of the existing asset
     pub asset_url: String,
+    /// `ETag` of the existing asset, for conditional GETs of the asset URL
+    ///
+    /// Also returned in the `ETag` response header
+    pub etag: String,
 }
 
 #[derive(Debug, Serialize, JsonSchema, OperationIo)]
@@ -96,7 +147,14 @@ impl IntoResponse for MediaUploadResponse {
     fn into_response(self) -> axum::response::Response {
         match self {
             Self::Success(resp) => (StatusCode::OK, Json(resp)).into_response(),
-            Self::Conflict(resp) => (StatusCode::CONFLICT, Json(resp)).into_response(),
+            Self::Conflict(resp) => {
+                let etag = HeaderValue::from_str(&resp.etag).ok();
+                let mut response = (StatusCode::CONFLICT, Json(resp)).into_response();
+                if let Some(etag) = etag.filter(|etag| !etag.is_empty()) {
+                    response.headers_mut().insert(header::ETAG, etag);
+                }
+                response
+            }
         }
     }
 }
@@ -105,12 +163,16 @@ impl IntoResponse for MediaUploadResponse {
 ///
 /// This function implements a secure media upload workflow with deduplication:
 /// 1. Maps the SHA-256 content digest to an S3 key
-/// 2. Checks if the object already exists in S3 (deduplication)
+/// 2. Checks if the object already exists in S3 (deduplication), returning its `ETag` if so
 /// 3. Generates a presigned PUT URL for the upload if object doesn't exist
 ///
+/// Requests with an `Idempotency-Key` header get the same presigned URL as previous requests
+/// with the same key and parameters, for a short window.
+///
 /// # Arguments
 ///
 /// * `media_storage` - The media storage service instance
+/// * `idempotency_key` - Optional client idempotency key
 /// * `payload` - Upload request containing content digest and length
 ///
 /// # Returns
@@ -129,6 +191,8 @@ impl IntoResponse for MediaUploadResponse {
 /// - `BucketError::ConfigError` - Failed to create presigning configuration
 /// - `BucketError::InvalidInput` - Invalid SHA-256 format (not 64-character hex string)
 pub async fn create_presigned_upload_url(
+    timing: ServerTiming,
+    idempotency_key: IdempotencyKey,
     Extension(media_storage): Extension<Arc<MediaStorage>>,
     Extension(environment): Extension<Environment>,
     Valid(Json(payload)): Valid<Json<UploadRequest>>,
@@ -137,22 +201,40 @@ pub async fn create_presigned_upload_url(
     validate_asset_size(&payload.content_type, payload.content_length)?;
 
     // Step 2: De-duplication Probe
-    let exists = media_storage.check_object_exists(&s3_key).await?;
-    if exists {
+    if let Some(etag) = timing
+        .time(PHASE_STORAGE, media_storage.head_object_etag(&s3_key))
+        .await?
+    {
         let asset_url = format!("{}/{}", environment.cdn_url(), s3_key);
         return Ok(MediaUploadResponse::Conflict(ConflictResponse {
             asset_url,
+            etag,
         }));
     }
 
     // Step 3: Generate Presigned URL
-    let presigned_url = media_storage
-        .generate_presigned_put_url(
-            &payload.content_digest_sha256,
-            payload.content_length,
-            payload.content_type.to_string().as_str(),
-        )
-        .await?;
+    let content_type = payload.content_type.to_string();
+    let presigned_url = match idempotency_key.0.as_deref() {
+        Some(idempotency_key) => {
+            media_storage
+                .generate_idempotent_presigned_put_url(
+                    idempotency_key,
+                    &payload.content_digest_sha256,
+                    payload.content_length,
+                    &content_type,
+                )
+                .await?
+        }
+        None => {
+            media_storage
+                .generate_presigned_put_url(
+                    &payload.content_digest_sha256,
+                    payload.content_length,
+                    &content_type,
+                )
+                .await?
+        }
+    };
 
     let asset_url = format!("{}/{}", environment.cdn_url(), s3_key);
     let content_digest_base64 = MediaStorage::map_sha256_to_b64(&payload.content_digest_sha256)?;
@@ -164,6 +246,49 @@ pub async fn create_presigned_upload_url(
     }))
 }
 
+/// Path of a media asset
+#[derive(Debug, Deserialize, JsonSchema, Validate)]
+pub struct AssetPath {
+    /// 64-character lowercase hex string (SHA-256 of encrypted blob)
+    #[validate(regex(path = *DIGEST_REGEX))]
+    pub content_digest_sha256: String,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct DownloadResponse {
+    /// Presigned URL to download the asset from S3
+    ///
+    /// The asset is served with an immutable `Cache-Control`, as it is content-addressed
+    pub presigned_url: String,
+    /// ISO-8601 UTC timestamp when the URL expires
+    #[schemars(with = "String")]
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Creates a presigned URL for downloading media content from S3
+///
+/// A fallback for clients that can't reach the asset through the CDN. The URL is generated
+/// without checking that the asset exists, S3 answers `404` for missing assets.
+///
+/// # Errors
+///
+/// - `BucketError::InvalidInput` - Invalid SHA-256 format (not 64-character hex string)
+/// - `BucketError::S3Error` - Presigned URL generation failed
+/// - `BucketError::ConfigError` - Failed to create presigning configuration
+pub async fn create_presigned_download_url(
+    Extension(media_storage): Extension<Arc<MediaStorage>>,
+    Valid(Path(path)): Valid<Path<AssetPath>>,
+) -> Result<Json<DownloadResponse>, AppError> {
+    let presigned_url = media_storage
+        .generate_presigned_get_url(&path.content_digest_sha256)
+        .await?;
+
+    Ok(Json(DownloadResponse {
+        presigned_url: presigned_url.url,
+        expires_at: presigned_url.expires_at,
+    }))
+}
+
 fn validate_asset_size(content_type: &Mime, content_length: i64) -> Result<(), AppError> {
     match content_type.type_() {
         mime::VIDEO if content_length > MAX_VIDEO_SIZE_BYTES => Err(AppError::new(
@@ -1,3 +1,4 @@
+pub mod admin;
 pub mod attestation;
 pub mod auth;
 pub mod config;
@@ -5,36 +6,64 @@ pub mod media;
 pub mod subscriptions;
 
 use aide::axum::{
-    routing::{get, post},
+    routing::{delete, get, post},
     ApiRouter,
 };
 use axum::middleware;
 
-use crate::middleware::auth::auth_middleware;
+use crate::{
+    jwt::{SCOPE_ADMIN, SCOPE_USER},
+    middleware::{
+        auth::{auth_middleware, require_scope},
+        timeout::{timeout_layer, DEFAULT_ROUTE_TIMEOUT, ENCLAVE_ROUTE_TIMEOUT},
+    },
+};
 
 /// Creates the v1 API router with all v1 handler routes
 pub fn handler() -> ApiRouter {
-    let public_routes = ApiRouter::new()
+    // Routes calling the enclave worker, given enough time for its calls to time out first
+    let enclave_routes = ApiRouter::new()
         .api_route("/attestation-document", get(attestation::handler))
         .api_route("/authorize", post(auth::authorize_handler))
-        .api_route("/config", get(config::get_config));
+        .layer(timeout_layer(ENCLAVE_ROUTE_TIMEOUT));
+
+    let public_routes = ApiRouter::new().api_route("/config", get(config::get_config));
 
     let protected_routes = ApiRouter::new()
         .api_route(
             "/media/presigned-urls",
             post(media::create_presigned_upload_url),
         )
+        .api_route(
+            "/media/presigned-urls/{content_digest_sha256}",
+            get(media::create_presigned_download_url),
+        )
         // TODO: This endpoint is deprecated, replaced by /config
         .api_route("/media/config", get(media::get_media_config))
         .api_route(
             "/subscriptions",
             post(subscriptions::subscribe).delete(subscriptions::unsubscribe),
         )
+        .api_route("/subscriptions/bulk", post(subscriptions::bulk_subscribe))
         .api_route(
             "/subscriptions/delete",
             post(subscriptions::batch_unsubscribe),
         )
+        .layer(middleware::from_fn_with_state(SCOPE_USER, require_scope))
+        .layer(middleware::from_fn(auth_middleware));
+
+    let admin_routes = ApiRouter::new()
+        .api_route(
+            "/admin/subscriptions",
+            delete(admin::purge_topic_subscriptions),
+        )
+        .api_route("/admin/dead-letters", get(admin::peek_dead_letters))
+        .layer(middleware::from_fn_with_state(SCOPE_ADMIN, require_scope))
         .layer(middleware::from_fn(auth_middleware));
 
-    public_routes.merge(protected_routes)
+    public_routes
+        .merge(protected_routes)
+        .merge(admin_routes)
+        .layer(timeout_layer(DEFAULT_ROUTE_TIMEOUT))
+        .merge(enclave_routes)
 }
@@ -7,8 +7,17 @@ use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use validator::Validate;
 
-use crate::{middleware::AuthenticatedUser, types::AppError};
-use backend_storage::push_subscription::{PushSubscription, PushSubscriptionStorage};
+use crate::{
+    middleware::{server_timing::PHASE_STORAGE, AuthenticatedUser, ServerTiming},
+    types::{AppError, FieldError},
+};
+use backend_storage::{
+    ids::{EncryptedPushId, TopicId},
+    push_subscription::{
+        is_well_formed_hmac_key, PushSubscription, PushSubscriptionStorage, SubscriptionKey,
+        SubscriptionWriteOutcome,
+    },
+};
 
 /// In the context of XMTP hmac keys for a conversation are rotated every 30-day epoch cycle
 /// We set a maximum of 40 days to prevent bad actors subscribing to a topic for a longer period of time
@@ -18,14 +27,48 @@ const MAX_TTL_SECS: i64 = 40 * 24 * 60 * 60; // 40 days
 #[serde(deny_unknown_fields)]
 pub struct CreateSubscriptionRequest {
     /// Topic for the subscription
-    #[validate(length(min = 1))]
+    #[validate(length(min = 1, message = "Topic cannot be empty"))]
     pub topic: String,
     /// HMAC key for subscription validation (42 bytes or 84 hex characters)
-    #[validate(length(equal = 84))]
+    #[validate(custom(function = "validate_hmac_key"))]
     pub hmac_key: String,
     /// TTL as unix timestamp
     #[validate(custom(function = "validate_ttl"))]
     pub ttl: i64,
+    /// Optional ID of the app installation (device) subscribing, used to notify each device once
+    #[serde(default)]
+    #[validate(length(
+        min = 1,
+        max = 128,
+        message = "Installation ID must be between 1 and 128 characters"
+    ))]
+    pub installation_id: Option<String>,
+}
+
+/// Result of a single subscription in a bulk subscribe request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkSubscriptionStatus {
+    /// The subscription did not exist and was created
+    Subscribed,
+    /// The subscription already existed and its TTL was refreshed
+    Refreshed,
+    /// The subscription could not be stored, the client should retry it
+    Failed,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct BulkSubscriptionResult {
+    /// Topic of the subscription, as presented in the request
+    pub topic: String,
+    /// Outcome of the subscription
+    pub status: BulkSubscriptionStatus,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct BulkSubscribeResponse {
+    /// Per-subscription results, in the same order as the request
+    pub results: Vec<BulkSubscriptionResult>,
 }
 
 #[derive(Debug, Deserialize, JsonSchema, Validate)]
@@ -38,6 +81,19 @@ pub struct UnsubscribeQuery {
     pub topic: String,
 }
 
+// Custom validator for HMAC keys, so only keys the storage accepts are stored
+fn validate_hmac_key(hmac_key: &str) -> Result<(), validator::ValidationError> {
+    if is_well_formed_hmac_key(hmac_key) {
+        return Ok(());
+    }
+
+    let mut error = validator::ValidationError::new("invalid_hmac_key");
+    error.message = Some(std::borrow::Cow::Borrowed(
+        "HMAC key must be exactly 84 hex characters",
+    ));
+    Err(error)
+}
+
 // Custom validator for TTL
 fn validate_ttl(ttl: i64) -> Result<(), validator::ValidationError> {
     let now = chrono::Utc::now().timestamp();
@@ -63,6 +119,33 @@ fn validate_ttl(ttl: i64) -> Result<(), validator::ValidationError> {
     Ok(())
 }
 
+/// Validates each subscription request, reporting every invalid field
+///
+/// Fields are reported with the index of their subscription, e.g. `[1].hmac_key`.
+///
+/// # Errors
+///
+/// Returns a `400 BAD_REQUEST` `AppError` listing the invalid fields
+fn validate_subscriptions(payload: &[CreateSubscriptionRequest]) -> Result<(), AppError> {
+    let errors: Vec<FieldError> = payload
+        .iter()
+        .enumerate()
+        .filter_map(|(i, subscription)| {
+            subscription
+                .validate()
+                .err()
+                .map(|errors| FieldError::from_validation_errors(&format!("[{i}]."), &errors))
+        })
+        .flatten()
+        .collect();
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(AppError::validation(errors))
+    }
+}
+
 /// Subscribe to push notifications for multiple topics
 ///
 /// Creates push notification subscriptions for the authenticated user. Each subscription
@@ -70,10 +153,8 @@ fn validate_ttl(ttl: i64) -> Result<(), validator::ValidationError> {
 ///
 /// ## Idempotent Behavior
 ///
-/// If a subscription already exists for the same topic and HMAC key, the error is logged
-/// but the operation continues successfully. This provides idempotent behavior while maintaining
-/// security - users cannot update their encrypted push ID for existing subscriptions to prevent
-/// distinguishing between legitimate key rotation and potential security risks.
+/// Subscriptions are upserted: if a subscription already exists for the same topic and HMAC key,
+/// it is overwritten with the request and its TTL refreshed, so re-subscribing always succeeds.
 ///
 /// ## Push ID Rotation Security
 ///
@@ -84,7 +165,8 @@ fn validate_ttl(ttl: i64) -> Result<(), validator::ValidationError> {
 ///
 /// * `user` - The authenticated user making the subscription request
 /// * `push_storage` - `DynamoDB` storage handler for push subscriptions
-/// * `payload` - Array of subscription requests, each containing topic, HMAC key, and TTL
+/// * `payload` - Array of subscription requests, each containing topic, HMAC key, TTL and
+///   optionally the installation ID
 ///
 /// # Returns
 ///
@@ -94,14 +176,15 @@ fn validate_ttl(ttl: i64) -> Result<(), validator::ValidationError> {
 /// # Errors
 ///
 /// Returns an error if:
-/// - `400 BAD_REQUEST` - Empty payload array
+/// - `400 BAD_REQUEST` - Empty payload array, or invalid fields detailed in the `errors` body
 /// - `401 UNAUTHORIZED` - Invalid or missing authentication
 /// - `503 SERVICE_UNAVAILABLE` - Database connectivity issues
 /// - `500 INTERNAL_SERVER_ERROR` - Other unexpected errors during storage operations
 pub async fn subscribe(
     user: AuthenticatedUser,
+    timing: ServerTiming,
     Extension(push_storage): Extension<Arc<PushSubscriptionStorage>>,
-    Valid(Json(payload)): Valid<Json<Vec<CreateSubscriptionRequest>>>,
+    Json(payload): Json<Vec<CreateSubscriptionRequest>>,
 ) -> Result<StatusCode, AppError> {
     // Validate that the payload is not empty
     if payload.is_empty() {
@@ -112,15 +195,17 @@ pub async fn subscribe(
             false,
         ));
     }
+    validate_subscriptions(&payload)?;
 
     let push_subscriptions = payload
         .into_iter()
         .map(|s| PushSubscription {
             hmac_key: s.hmac_key,
             deletion_request: None,
-            topic: s.topic,
+            topic: TopicId::normalize(&s.topic).into_inner(),
             ttl: s.ttl,
             encrypted_push_id: user.encrypted_push_id.clone(),
+            installation_id: s.installation_id,
         })
         .collect::<Vec<PushSubscription>>();
 
@@ -129,7 +214,7 @@ pub async fn subscribe(
         .map(|subscription| push_storage.upsert(subscription));
 
     // Run all upserts concurrently
-    let results = join_all(db_operations).await;
+    let results = timing.time(PHASE_STORAGE, join_all(db_operations)).await;
 
     for result in results {
         if let Err(e) = result {
@@ -140,6 +225,90 @@ pub async fn subscribe(
     Ok(StatusCode::CREATED)
 }
 
+/// Subscribe to push notifications for multiple topics, reporting the result per topic
+///
+/// Like `subscribe`, but a failing subscription does not fail the request. Instead, the response
+/// reports for each subscription whether it was newly subscribed, refreshed or failed, so clients
+/// joining several groups at once can retry only the failed ones.
+///
+/// # Arguments
+///
+/// * `user` - The authenticated user making the subscription request
+/// * `push_storage` - `DynamoDB` storage handler for push subscriptions
+/// * `payload` - Array of subscription requests, each containing topic, HMAC key, TTL and
+///   optionally the installation ID
+///
+/// # Returns
+///
+/// Returns `200 OK` with the per-subscription results, in the same order as the request.
+///
+/// # Errors
+///
+/// Returns an error if:
+/// - `400 BAD_REQUEST` - Empty payload array, or invalid fields detailed in the `errors` body
+/// - `401 UNAUTHORIZED` - Invalid or missing authentication
+pub async fn bulk_subscribe(
+    user: AuthenticatedUser,
+    timing: ServerTiming,
+    Extension(push_storage): Extension<Arc<PushSubscriptionStorage>>,
+    Json(payload): Json<Vec<CreateSubscriptionRequest>>,
+) -> Result<Json<BulkSubscribeResponse>, AppError> {
+    if payload.is_empty() {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "empty_payload",
+            "Empty payload",
+            false,
+        ));
+    }
+    validate_subscriptions(&payload)?;
+
+    // Results echo the topics as requested, so clients can match them with their request
+    let requested_topics: Vec<String> = payload.iter().map(|s| s.topic.clone()).collect();
+    let push_subscriptions = payload
+        .into_iter()
+        .map(|s| PushSubscription {
+            hmac_key: s.hmac_key,
+            deletion_request: None,
+            topic: TopicId::normalize(&s.topic).into_inner(),
+            ttl: s.ttl,
+            encrypted_push_id: user.encrypted_push_id.clone(),
+            installation_id: s.installation_id,
+        })
+        .collect::<Vec<PushSubscription>>();
+
+    let outcomes = timing
+        .time(PHASE_STORAGE, push_storage.insert_many(&push_subscriptions))
+        .await;
+
+    let results = push_subscriptions
+        .into_iter()
+        .zip(requested_topics)
+        .zip(outcomes)
+        .map(|((subscription, requested_topic), outcome)| {
+            let status = match outcome {
+                Ok(SubscriptionWriteOutcome::Created) => BulkSubscriptionStatus::Subscribed,
+                Ok(SubscriptionWriteOutcome::Refreshed) => BulkSubscriptionStatus::Refreshed,
+                Err(e) => {
+                    tracing::error!(
+                        topic = %subscription.topic,
+                        error = ?e,
+                        "Failed to store subscription"
+                    );
+                    BulkSubscriptionStatus::Failed
+                }
+            };
+
+            BulkSubscriptionResult {
+                topic: requested_topic,
+                status,
+            }
+        })
+        .collect();
+
+    Ok(Json(BulkSubscribeResponse { results }))
+}
+
 /// Unsubscribe from push notifications for a specific topic
 ///
 /// Removes or marks for deletion a push notification subscription. The behavior depends on
@@ -168,6 +337,7 @@ pub async fn subscribe(
 /// - `500 INTERNAL_SERVER_ERROR` - Other unexpected errors during storage operations
 pub async fn unsubscribe(
     user: AuthenticatedUser,
+    timing: ServerTiming,
     Extension(push_storage): Extension<Arc<PushSubscriptionStorage>>,
     Query(query): Query<UnsubscribeQuery>,
 ) -> Result<StatusCode, AppError> {
@@ -190,24 +360,39 @@ pub async fn unsubscribe(
         ));
     }
 
-    let push_subscription = push_storage
-        .get_one(&query.topic, &query.hmac_key)
-        .await?
-        .ok_or_else(|| {
-            AppError::new(
-                StatusCode::NOT_FOUND,
-                "push_subscription_not_found",
-                "Push subscription not found",
-                false,
-            )
-        })?;
+    // Subscriptions created before topics were normalized are stored under the raw topic
+    let mut found = None;
+    for topic in TopicId::stored_variants(&query.topic) {
+        let key = SubscriptionKey::new(topic, query.hmac_key.as_str());
+        if let Some(push_subscription) = timing
+            .time(PHASE_STORAGE, push_storage.get_one(&key))
+            .await?
+        {
+            found = Some((key, push_subscription));
+            break;
+        }
+    }
+    let (key, push_subscription) = found.ok_or_else(|| {
+        AppError::new(
+            StatusCode::NOT_FOUND,
+            "push_subscription_not_found",
+            "Push subscription not found",
+            false,
+        )
+    })?;
 
     if push_subscription.encrypted_push_id == user.encrypted_push_id {
-        push_storage.delete(&query.topic, &query.hmac_key).await?;
+        timing
+            .time(PHASE_STORAGE, push_storage.delete(&key))
+            .await?;
     } else {
         // Add the user's encrypted push id to the deletion request using native DynamoDB string set ADD
-        push_storage
-            .append_delete_request(&query.topic, &query.hmac_key, &user.encrypted_push_id)
+        timing
+            .time(
+                PHASE_STORAGE,
+                push_storage
+                    .append_delete_request(&key, &EncryptedPushId::from(user.encrypted_push_id)),
+            )
             .await?;
     }
 
@@ -249,6 +434,7 @@ pub async fn unsubscribe(
 /// - `500 INTERNAL_SERVER_ERROR` - Database operation failures
 pub async fn batch_unsubscribe(
     user: AuthenticatedUser,
+    timing: ServerTiming,
     Extension(push_storage): Extension<Arc<PushSubscriptionStorage>>,
     Valid(Json(payload)): Valid<Json<Vec<UnsubscribeQuery>>>,
 ) -> Result<StatusCode, AppError> {
@@ -262,13 +448,20 @@ pub async fn batch_unsubscribe(
         ));
     }
 
-    // Step 1: Batch fetch all subscriptions
-    let subscription_keys: Vec<(&str, &str)> = payload
+    // Step 1: Batch fetch all subscriptions, including those created before topics were
+    // normalized, which are stored under the raw topic
+    let subscription_keys: Vec<_> = payload
         .iter()
-        .map(|p| (p.topic.as_str(), p.hmac_key.as_str()))
+        .flat_map(|p| {
+            TopicId::stored_variants(&p.topic)
+                .into_iter()
+                .map(|topic| SubscriptionKey::new(topic, p.hmac_key.as_str()))
+        })
         .collect();
 
-    let subscriptions = push_storage.batch_get(&subscription_keys).await?;
+    let subscriptions = timing
+        .time(PHASE_STORAGE, push_storage.batch_get(&subscription_keys))
+        .await?;
 
     // Step 2: Partition into delete vs tombstone based on push ID match
     // Subscriptions not found are silently skipped (idempotent behavior)
@@ -276,10 +469,7 @@ pub async fn batch_unsubscribe(
         .iter()
         .partition(|s| s.encrypted_push_id == user.encrypted_push_id);
 
-    let to_delete: Vec<_> = to_delete
-        .iter()
-        .map(|s| (s.topic.as_str(), s.hmac_key.as_str()))
-        .collect();
+    let to_delete: Vec<_> = to_delete.iter().map(|s| s.key()).collect();
 
     // Step 3: Execute deletions and tombstones concurrently
     let delete_future = push_storage.batch_delete_many(&to_delete);
@@ -287,15 +477,11 @@ pub async fn batch_unsubscribe(
     let tombstone_future = async {
         // Tombstones are best-effort - log errors but don't propagate
         // DynamoDB has no batch update, so we run individual updates in parallel
-        let futures: Vec<_> = to_tombstone
+        let encrypted_push_id = EncryptedPushId::from(user.encrypted_push_id.as_str());
+        let keys: Vec<_> = to_tombstone.iter().map(|s| s.key()).collect();
+        let futures: Vec<_> = keys
             .iter()
-            .map(|subscription| {
-                push_storage.append_delete_request(
-                    &subscription.topic,
-                    &subscription.hmac_key,
-                    &user.encrypted_push_id,
-                )
-            })
+            .map(|key| push_storage.append_delete_request(key, &encrypted_push_id))
             .collect();
 
         let results = join_all(futures).await;
@@ -312,7 +498,11 @@ pub async fn batch_unsubscribe(
         }
     };
 
-    let (delete_result, ()) = tokio::join!(delete_future, tombstone_future);
+    let (delete_result, ()) = timing
+        .time(PHASE_STORAGE, async {
+            tokio::join!(delete_future, tombstone_future)
+        })
+        .await;
     delete_result?;
 
     Ok(StatusCode::NO_CONTENT)
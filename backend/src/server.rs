@@ -1,14 +1,21 @@
 use std::sync::Arc;
 
 use aide::openapi::OpenApi;
-use axum::Extension;
+use axum::{middleware, Extension};
 use backend_storage::auth_proof::AuthProofStorage;
 use backend_storage::push_subscription::PushSubscriptionStorage;
 use datadog_tracing::axum::{shutdown_signal, OtelAxumLayer, OtelInResponseLayer};
 use tokio::net::TcpListener;
 
 use crate::enclave_worker_api::EnclaveWorkerApi;
-use crate::routes;
+use crate::middleware::{
+    concurrency_limit::{concurrency_limit_middleware, ConcurrencyLimit},
+    request_id::request_id_middleware,
+};
+use crate::routes::{
+    self,
+    v1::{admin::NotificationDeadLetterQueue, config::ConfigCache},
+};
 use crate::{jwt::JwtManager, media_storage::MediaStorage, types::Environment};
 
 /// Starts the server with the given environment and dependencies
@@ -23,8 +30,10 @@ pub async fn start(
     auth_proof_storage: Arc<AuthProofStorage>,
     push_subscription_storage: Arc<PushSubscriptionStorage>,
     enclave_worker_api: Arc<dyn EnclaveWorkerApi>,
+    notification_dead_letter_queue: Option<NotificationDeadLetterQueue>,
 ) -> anyhow::Result<()> {
     let mut openapi = OpenApi::default();
+    let concurrency_limit = ConcurrencyLimit::new(environment.max_in_flight_requests());
 
     let router = routes::handler()
         .finish_api(&mut openapi)
@@ -35,13 +44,19 @@ pub async fn start(
         .layer(Extension(auth_proof_storage))
         .layer(Extension(push_subscription_storage))
         .layer(Extension(enclave_worker_api))
+        .layer(Extension(notification_dead_letter_queue))
+        .layer(Extension(Arc::new(ConfigCache::default())))
         // Include trace context as header into the response
         .route_layer(OtelInResponseLayer)
         // Start OpenTelemetry trace on incoming request
         .route_layer(OtelAxumLayer::default())
-        .layer(tower_http::timeout::TimeoutLayer::new(
-            std::time::Duration::from_secs(5),
-        ));
+        // Shed load before any work is done for the request
+        .layer(middleware::from_fn_with_state(
+            concurrency_limit,
+            concurrency_limit_middleware,
+        ))
+        // Outermost, so every response carries a request id, including shed requests
+        .layer(middleware::from_fn(request_id_middleware));
 
     let addr = std::net::SocketAddr::from((
         [0, 0, 0, 0],
@@ -49,7 +64,11 @@ pub async fn start(
     ));
 
     let listener = TcpListener::bind(&addr).await?;
-    tracing::info!("🔄 World Chat Backend started on http://{addr}");
+    tracing::info!(
+        version = env!("CARGO_PKG_VERSION"),
+        rev = option_env!("GIT_REV"),
+        "🔄 World Chat Backend started on http://{addr}"
+    );
 
     axum::serve(listener, router.into_make_service())
         .with_graceful_shutdown(shutdown_signal())
@@ -0,0 +1,112 @@
+//! AWS service clients shared across the backend
+
+use std::sync::Arc;
+
+use aws_config::SdkConfig;
+use aws_sdk_dynamodb::Client as DynamoDbClient;
+use aws_sdk_kms::Client as KmsClient;
+use aws_sdk_s3::Client as S3Client;
+use aws_sdk_sqs::Client as SqsClient;
+
+use super::Environment;
+
+/// AWS service clients, all built from a single shared `SdkConfig`
+#[derive(Clone)]
+pub struct AwsClients {
+    /// `DynamoDB` client for auth proofs and push subscriptions
+    pub dynamodb: Arc<DynamoDbClient>,
+    /// S3 client for media storage
+    pub s3: Arc<S3Client>,
+    /// KMS client for JWT signing
+    pub kms: Arc<KmsClient>,
+    /// SQS client for peeking the notification dead-letter queue
+    pub sqs: Arc<SqsClient>,
+}
+
+impl AwsClients {
+    /// Loads the AWS configuration once and builds every client from it
+    pub async fn load(environment: &Environment) -> Self {
+        Self::from_config(environment, &environment.aws_config().await)
+    }
+
+    /// Builds every client from an already loaded AWS configuration
+    #[must_use]
+    pub fn from_config(environment: &Environment, aws_config: &SdkConfig) -> Self {
+        Self {
+            dynamodb: Arc::new(DynamoDbClient::new(aws_config)),
+            s3: Arc::new(S3Client::from_conf(
+                environment.s3_client_config(aws_config),
+            )),
+            kms: Arc::new(KmsClient::new(aws_config)),
+            sqs: Arc::new(SqsClient::new(aws_config)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use aws_config::{BehaviorVersion, Region};
+    use serial_test::serial;
+
+    use super::*;
+
+    #[test]
+    fn test_clients_share_config() {
+        let environment = Environment::Development {
+            presign_expiry_override: None,
+            disable_auth: false,
+        };
+        let region = Region::new("eu-central-1");
+        let aws_config = SdkConfig::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(region.clone())
+            .build();
+
+        let clients = AwsClients::from_config(&environment, &aws_config);
+
+        assert_eq!(clients.dynamodb.config().region(), Some(&region));
+        assert_eq!(clients.s3.config().region(), Some(&region));
+        assert_eq!(clients.kms.config().region(), Some(&region));
+        assert_eq!(clients.sqs.config().region(), Some(&region));
+    }
+
+    #[test]
+    #[serial]
+    fn test_clients_apply_retry_and_timeout_settings() {
+        let environment = Environment::Development {
+            presign_expiry_override: None,
+            disable_auth: false,
+        };
+        let aws_config = environment.tune_aws_config(
+            &SdkConfig::builder()
+                .behavior_version(BehaviorVersion::latest())
+                .region(Region::new("eu-central-1"))
+                .build(),
+        );
+
+        let clients = AwsClients::from_config(&environment, &aws_config);
+
+        let retry_config = clients.dynamodb.config().retry_config().unwrap();
+        assert_eq!(retry_config.max_attempts(), 3);
+        assert_eq!(clients.s3.config().retry_config(), Some(retry_config));
+        assert_eq!(clients.kms.config().retry_config(), Some(retry_config));
+
+        let timeout_config = clients.dynamodb.config().timeout_config().unwrap();
+        assert_eq!(
+            timeout_config.operation_timeout(),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            timeout_config.operation_attempt_timeout(),
+            Some(Duration::from_secs(25))
+        );
+        assert_eq!(
+            timeout_config.connect_timeout(),
+            Some(Duration::from_secs(3))
+        );
+        assert_eq!(clients.s3.config().timeout_config(), Some(timeout_config));
+        assert_eq!(clients.kms.config().timeout_config(), Some(timeout_config));
+    }
+}
@@ -1,9 +1,12 @@
 //! Environment configuration for different deployment stages
 
 use std::env;
-use std::time::Duration;
 
-use aws_config::{retry::RetryConfig, timeout::TimeoutConfig, BehaviorVersion};
+use backend_storage::queue::QueueConfig;
+use common_types::{
+    aws,
+    logging::{InvalidLogFormat, LogFormat},
+};
 
 /// Application environment configuration
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -52,6 +55,16 @@ impl Environment {
         }
     }
 
+    /// Log format, from `LOG_FORMAT` or pretty in development and JSON otherwise
+    ///
+    /// # Errors
+    ///
+    /// If `LOG_FORMAT` holds an unknown format
+    pub fn log_format(&self) -> Result<LogFormat, InvalidLogFormat> {
+        let development = matches!(self, Self::Development { .. });
+        LogFormat::from_env(LogFormat::default_for(development))
+    }
+
     /// Returns the S3 bucket name for the environment
     ///
     /// # Panics
@@ -86,33 +99,22 @@ impl Environment {
         }
     }
 
-    /// AWS configuration with retry and timeout settings
+    /// AWS configuration shared by every SDK client, with the environment's retry, timeout and
+    /// endpoint settings
     pub async fn aws_config(&self) -> aws_config::SdkConfig {
-        let retry_config = RetryConfig::standard()
-            .with_max_attempts(3)
-            .with_initial_backoff(Duration::from_millis(50));
-
-        let timeout_config = TimeoutConfig::builder()
-            .operation_timeout(Duration::from_secs(30))
-            .build();
-
-        let mut config_builder = aws_config::load_defaults(BehaviorVersion::latest())
-            .await
-            .to_builder()
-            .retry_config(retry_config)
-            .timeout_config(timeout_config);
-
-        if let Some(endpoint_url) = self.override_aws_endpoint_url() {
-            config_builder = config_builder.endpoint_url(endpoint_url);
-        }
+        aws::load_config(self.override_aws_endpoint_url()).await
+    }
 
-        config_builder.build()
+    /// Applies the retry, timeout and endpoint settings of the environment to `aws_config`
+    #[must_use]
+    pub fn tune_aws_config(&self, aws_config: &aws_config::SdkConfig) -> aws_config::SdkConfig {
+        aws::tune_config(aws_config, self.override_aws_endpoint_url())
     }
 
-    /// AWS S3 service configuration
-    pub async fn s3_client_config(&self) -> aws_sdk_s3::Config {
-        let aws_config = self.aws_config().await;
-        let s3_config: aws_sdk_s3::Config = (&aws_config).into();
+    /// AWS S3 service configuration, derived from the shared AWS configuration
+    #[must_use]
+    pub fn s3_client_config(&self, aws_config: &aws_config::SdkConfig) -> aws_sdk_s3::Config {
+        let s3_config: aws_sdk_s3::Config = aws_config.into();
         let mut builder = s3_config.to_builder();
 
         // Override "force path style" to true for compatibility with LocalStack
@@ -207,6 +209,29 @@ impl Environment {
         env::var("JWT_KMS_KEY_ARN").expect("JWT_KMS_KEY_ARN environment variable is not set")
     }
 
+    /// Returns the last-known-good JWT public key (base64 DER), used if KMS is unreachable
+    #[must_use]
+    pub fn jwt_public_key_fallback(&self) -> Option<String> {
+        env::var("JWT_PUBLIC_KEY_FALLBACK")
+            .ok()
+            .filter(|key| !key.is_empty())
+    }
+
+    /// Returns the maximum size of a JWT in bytes, tokens above it are neither issued nor
+    /// parsed
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `JWT_MAX_TOKEN_BYTES` environment variable is set but not a valid size
+    #[must_use]
+    pub fn jwt_max_token_bytes(&self) -> usize {
+        env::var("JWT_MAX_TOKEN_BYTES").map_or(crate::jwt::DEFAULT_MAX_TOKEN_BYTES, |v| {
+            v.trim()
+                .parse()
+                .unwrap_or_else(|_| panic!("Invalid JWT_MAX_TOKEN_BYTES: {v}"))
+        })
+    }
+
     /// Returns the Dynamo DB table name for auth proofs
     ///
     /// # Panics
@@ -244,6 +269,55 @@ impl Environment {
         }
     }
 
+    /// Returns the Dynamo DB GSI name for the push subscriptions `encrypted_push_id` index
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `DYNAMODB_PUSH_PUSH_ID_INDEX_NAME` environment variable is not set in production/staging
+    #[must_use]
+    pub fn dynamodb_push_subscription_push_id_index_name(&self) -> String {
+        match self {
+            Self::Production | Self::Staging => env::var("DYNAMODB_PUSH_PUSH_ID_INDEX_NAME")
+                .expect("DYNAMODB_PUSH_PUSH_ID_INDEX_NAME environment variable is not set"),
+            Self::Development { .. } => "encrypted-push-id-index".to_string(),
+        }
+    }
+
+    /// Returns the notification dead-letter queue configuration, if one is configured
+    ///
+    /// Only used to peek messages for on-call, never to consume them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the configuration is outside SQS limits
+    #[must_use]
+    pub fn notification_dlq_config(&self) -> Option<QueueConfig> {
+        let queue_url = match self {
+            Self::Production | Self::Staging => env::var("NOTIFICATION_DLQ_URL").ok()?,
+            Self::Development { .. } => {
+                "http://localhost:4566/000000000000/notification-dlq.fifo".to_string()
+            }
+        };
+
+        Some(
+            QueueConfig::new(queue_url, 10, 0, 0)
+                .expect("Invalid notification dead-letter queue configuration"),
+        )
+    }
+
+    /// Returns the Redis URL used for the token revocation list, to publish subscription change
+    /// events and to cache presigned URLs for idempotent requests
+    ///
+    /// In production/staging, all of them are disabled when the `REDIS_URL` environment variable
+    /// is not set.
+    #[must_use]
+    pub fn redis_url(&self) -> Option<String> {
+        match self {
+            Self::Production | Self::Staging => env::var("REDIS_URL").ok(),
+            Self::Development { .. } => Some("redis://localhost:6379".to_string()),
+        }
+    }
+
     /// Returns the Dynamo DB table name for group invites
     ///
     /// # Panics
@@ -302,6 +376,22 @@ impl Environment {
         }
     }
 
+    /// Returns the Dynamo DB GSI name for group join requests by status and notification time
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `DYNAMODB_GROUP_JOIN_REQUESTS_NOTIFICATION_SENT_INDEX_NAME` environment variable is not set in production/staging
+    #[must_use]
+    pub fn dynamodb_group_join_requests_notification_sent_index_name(&self) -> String {
+        match self {
+            Self::Production | Self::Staging => {
+                env::var("DYNAMODB_GROUP_JOIN_REQUESTS_NOTIFICATION_SENT_INDEX_NAME")
+                    .expect("DYNAMODB_GROUP_JOIN_REQUESTS_NOTIFICATION_SENT_INDEX_NAME environment variable is not set")
+            }
+            Self::Development { .. } => "status-notification-sent-index".to_string(),
+        }
+    }
+
     /// Returns the Enclave Worker HTTP URL that is used to challenge push IDs
     ///
     /// # Panics
@@ -329,6 +419,27 @@ impl Environment {
             }
         }
     }
+
+    /// Returns the maximum number of requests handled concurrently, requests above it are shed
+    /// with a 503
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `MAX_IN_FLIGHT_REQUESTS` environment variable is set but not a positive
+    /// number, as zero would shed every request
+    #[must_use]
+    pub fn max_in_flight_requests(&self) -> usize {
+        env::var("MAX_IN_FLIGHT_REQUESTS").map_or(
+            crate::middleware::concurrency_limit::DEFAULT_MAX_IN_FLIGHT_REQUESTS,
+            |v| {
+                v.trim()
+                    .parse()
+                    .ok()
+                    .filter(|&max| max > 0)
+                    .unwrap_or_else(|| panic!("Invalid MAX_IN_FLIGHT_REQUESTS: {v}"))
+            },
+        )
+    }
 }
 
 #[cfg(test)]
@@ -377,6 +488,33 @@ mod tests {
         let _ = Environment::from_env();
     }
 
+    #[test]
+    #[serial]
+    fn test_jwt_max_token_bytes() {
+        let env = Environment::Production;
+        assert_eq!(
+            env.jwt_max_token_bytes(),
+            crate::jwt::DEFAULT_MAX_TOKEN_BYTES
+        );
+
+        env::set_var("JWT_MAX_TOKEN_BYTES", "4096");
+        assert_eq!(env.jwt_max_token_bytes(), 4096);
+
+        // Cleanup
+        env::remove_var("JWT_MAX_TOKEN_BYTES");
+    }
+
+    #[test]
+    #[serial]
+    fn test_invalid_jwt_max_token_bytes() {
+        env::set_var("JWT_MAX_TOKEN_BYTES", "8kb");
+        let result = std::panic::catch_unwind(|| Environment::Production.jwt_max_token_bytes());
+
+        // Cleanup
+        env::remove_var("JWT_MAX_TOKEN_BYTES");
+        result.expect_err("Expected panic for an invalid JWT_MAX_TOKEN_BYTES");
+    }
+
     #[test]
     #[serial]
     fn test_presigned_url_expiry_secs() {
@@ -478,4 +616,35 @@ mod tests {
         };
         assert_eq!(env.jwt_issuer_url(), "chat-staging.toolsforhumanity.com");
     }
+
+    #[test]
+    #[serial]
+    fn test_max_in_flight_requests() {
+        let env = Environment::Production;
+
+        env::remove_var("MAX_IN_FLIGHT_REQUESTS");
+        assert_eq!(env.max_in_flight_requests(), 512);
+
+        env::set_var("MAX_IN_FLIGHT_REQUESTS", "64");
+        assert_eq!(env.max_in_flight_requests(), 64);
+
+        // Cleanup
+        env::remove_var("MAX_IN_FLIGHT_REQUESTS");
+    }
+
+    #[test]
+    #[serial]
+    fn test_invalid_max_in_flight_requests() {
+        // Zero would shed every request
+        let results = ["0", "many"].map(|value| {
+            env::set_var("MAX_IN_FLIGHT_REQUESTS", value);
+            std::panic::catch_unwind(|| Environment::Production.max_in_flight_requests())
+        });
+
+        // Cleanup
+        env::remove_var("MAX_IN_FLIGHT_REQUESTS");
+        for result in results {
+            result.expect_err("Expected panic for an invalid MAX_IN_FLIGHT_REQUESTS");
+        }
+    }
 }
@@ -13,6 +13,7 @@ use serde::Serialize;
 
 use crate::jwt::error::JwtError;
 use crate::media_storage::BucketError;
+use crate::middleware::request_id::RequestId;
 use crate::world_id::error::WorldIdError;
 
 /// API error response envelope that matches mobile client expectations
@@ -23,6 +24,12 @@ pub struct ApiErrorResponse {
     pub allow_retry: bool,
     /// Error details
     error: ErrorBody,
+    /// Field-level details of a request validation failure
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<FieldError>,
+    /// Id of the failed request, for users to quote when reporting the error
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reference: Option<String>,
 }
 
 /// Error body containing code and message
@@ -35,6 +42,39 @@ struct ErrorBody {
     pub message: &'static str,
 }
 
+/// Validation failure of a single request field
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct FieldError {
+    /// Path of the invalid field, e.g. `[0].topic` for the first item of an array body
+    pub field: String,
+    /// Human-readable description of the failure
+    pub message: String,
+}
+
+impl FieldError {
+    /// Collects the field errors of `errors`, prefixing each field with `prefix`
+    ///
+    /// Errors are sorted by field, so responses are deterministic.
+    #[must_use]
+    pub fn from_validation_errors(prefix: &str, errors: &validator::ValidationErrors) -> Vec<Self> {
+        let mut field_errors: Vec<Self> = errors
+            .field_errors()
+            .into_iter()
+            .flat_map(|(field, errors)| {
+                errors.iter().map(move |error| Self {
+                    field: format!("{prefix}{field}"),
+                    message: error
+                        .message
+                        .as_ref()
+                        .map_or_else(|| format!("Invalid {field}"), ToString::to_string),
+                })
+            })
+            .collect();
+        field_errors.sort_by(|a, b| a.field.cmp(&b.field));
+        field_errors
+    }
+}
+
 /// Application error type that wraps the API error response
 #[derive(Debug)]
 pub struct AppError {
@@ -56,21 +96,42 @@ impl AppError {
             inner: ApiErrorResponse {
                 allow_retry: retry,
                 error: ErrorBody { code, message: msg },
+                errors: Vec::new(),
+                reference: None,
             },
         }
     }
+
+    /// Create a `400 BAD_REQUEST` error detailing the invalid request fields
+    #[must_use]
+    pub fn validation(errors: Vec<FieldError>) -> Self {
+        let mut error = Self::new(
+            StatusCode::BAD_REQUEST,
+            "validation_error",
+            "Request validation failed",
+            false,
+        );
+        error.inner.errors = errors;
+        error
+    }
 }
 
 impl IntoResponse for AppError {
-    fn into_response(self) -> Response {
-        // Log the error based on status code
+    fn into_response(mut self) -> Response {
+        self.inner.reference = RequestId::current().map(|id| id.to_string());
+
+        // Log the error based on status code, with the reference in case it's outside the
+        // request's span
+        let reference = self.inner.reference.as_deref().unwrap_or_default();
         match self.status.as_u16() {
             400..=499 => tracing::warn!(
+                reference,
                 "Client error: {} - {}",
                 self.inner.error.code,
                 self.inner.error.message
             ),
             500..=599 => tracing::error!(
+                reference,
                 "Server error: {} - {}",
                 self.inner.error.code,
                 self.inner.error.message
@@ -104,7 +165,8 @@ impl From<BucketError> for AppError {
     #[allow(clippy::cognitive_complexity)]
     fn from(err: BucketError) -> Self {
         use BucketError::{
-            AwsError, ConfigError, InvalidInput, ObjectExists, S3Error, UpstreamError,
+            AwsError, ConfigError, DigestMismatch, InvalidInput, ObjectExists, S3Error,
+            UpstreamError,
         };
 
         match &err {
@@ -144,6 +206,15 @@ impl From<BucketError> for AppError {
                     false,
                 )
             }
+            DigestMismatch { expected, actual } => {
+                tracing::warn!("Digest mismatch: expected {expected}, got {actual}");
+                Self::new(
+                    StatusCode::BAD_REQUEST,
+                    "digest_mismatch",
+                    "Content does not match the provided SHA-256 digest",
+                    false,
+                )
+            }
             InvalidInput(msg) => {
                 tracing::warn!("Invalid input: {msg}");
                 Self::new(
@@ -171,8 +242,9 @@ impl OperationOutput for AppError {
 impl From<AuthProofStorageError> for AppError {
     fn from(err: AuthProofStorageError) -> Self {
         use AuthProofStorageError::{
-            AuthProofExists, DynamoDbDeleteError, DynamoDbGetError, DynamoDbPutError,
-            DynamoDbQueryError, DynamoDbUpdateError, SerializationError,
+            AuthProofExists, DynamoDbBatchGetError, DynamoDbDeleteError, DynamoDbGetError,
+            DynamoDbPutError, DynamoDbQueryError, DynamoDbUpdateError, RotationCooldownActive,
+            SerializationError, UnprocessedNullifiers,
         };
 
         match &err {
@@ -185,11 +257,22 @@ impl From<AuthProofStorageError> for AppError {
                     false,
                 )
             }
+            RotationCooldownActive => {
+                tracing::debug!("Push id rotated within the cooldown");
+                Self::new(
+                    StatusCode::FORBIDDEN,
+                    "push_ids_mismatch",
+                    "Push IDs mismatch",
+                    false,
+                )
+            }
             DynamoDbPutError(_)
             | DynamoDbDeleteError(_)
             | DynamoDbGetError(_)
             | DynamoDbQueryError(_)
-            | DynamoDbUpdateError(_) => {
+            | DynamoDbUpdateError(_)
+            | DynamoDbBatchGetError(_)
+            | UnprocessedNullifiers(_) => {
                 tracing::error!("DynamoDB error: {err}");
                 Self::new(
                     StatusCode::SERVICE_UNAVAILABLE,
@@ -216,8 +299,8 @@ impl From<PushSubscriptionStorageError> for AppError {
     fn from(err: PushSubscriptionStorageError) -> Self {
         use PushSubscriptionStorageError::{
             DynamoDbBatchGetError, DynamoDbBatchWriteError, DynamoDbDeleteError, DynamoDbGetError,
-            DynamoDbPutError, DynamoDbQueryError, DynamoDbUpdateError, ParseSubscriptionError,
-            PushSubscriptionExists, SerializationError,
+            DynamoDbPutError, DynamoDbQueryError, DynamoDbUpdateError, InvalidKey, NotFound,
+            ParseSubscriptionError, PushSubscriptionExists, SerializationError, UnprocessedItems,
         };
 
         match &err {
@@ -227,7 +310,8 @@ impl From<PushSubscriptionStorageError> for AppError {
             | DynamoDbQueryError(_)
             | DynamoDbUpdateError(_)
             | DynamoDbBatchWriteError(_)
-            | DynamoDbBatchGetError(_) => {
+            | DynamoDbBatchGetError(_)
+            | UnprocessedItems(_) => {
                 tracing::error!("DynamoDB error: {err}");
                 Self::new(
                     StatusCode::SERVICE_UNAVAILABLE,
@@ -236,6 +320,15 @@ impl From<PushSubscriptionStorageError> for AppError {
                     true,
                 )
             }
+            InvalidKey(e) => {
+                tracing::warn!("Invalid subscription key: {e}");
+                Self::new(
+                    StatusCode::BAD_REQUEST,
+                    "invalid_subscription_key",
+                    "Topic must not be empty and HMAC key must be 84 hex characters",
+                    false,
+                )
+            }
             // This should never happen, mapping this for completeness
             PushSubscriptionExists => {
                 tracing::error!("Push subscription already exists");
@@ -246,6 +339,12 @@ impl From<PushSubscriptionStorageError> for AppError {
                     false,
                 )
             }
+            NotFound => Self::new(
+                StatusCode::NOT_FOUND,
+                "not_found",
+                "Push subscription not found",
+                false,
+            ),
             SerializationError(msg) | ParseSubscriptionError(msg) => {
                 tracing::error!("Serialization/Parse error: {msg}");
                 Self::new(
@@ -340,7 +439,10 @@ impl From<WorldIdError> for AppError {
 impl From<JwtError> for AppError {
     #[allow(clippy::cognitive_complexity)]
     fn from(err: JwtError) -> Self {
-        use JwtError::{InvalidSignature, InvalidToken, Kms, Other, SigningInput};
+        use JwtError::{
+            InvalidSignature, InvalidToken, Kms, Other, PresentedTokenTooLarge, Revoked,
+            SigningInput, TokenTooLarge,
+        };
 
         match &err {
             InvalidToken => Self::new(
@@ -364,6 +466,30 @@ impl From<JwtError> for AppError {
                 "Invalid or expired token",
                 false,
             ),
+            Revoked => Self::new(
+                StatusCode::UNAUTHORIZED,
+                "revoked_token",
+                "Token has been revoked",
+                false,
+            ),
+            PresentedTokenTooLarge { .. } => {
+                tracing::warn!("JWT size error: {err}");
+                Self::new(
+                    StatusCode::UNAUTHORIZED,
+                    "invalid_token",
+                    "Invalid or malformed token",
+                    false,
+                )
+            }
+            TokenTooLarge { .. } => {
+                tracing::error!("JWT size error: {err}");
+                Self::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "internal_error",
+                    "Internal server error",
+                    false,
+                )
+            }
             SigningInput(msg) => {
                 tracing::error!("JWT signing input error: {msg}");
                 Self::new(
@@ -1,5 +1,7 @@
+mod aws_clients;
 mod environment;
 mod error;
 
+pub use aws_clients::AwsClients;
 pub use environment::Environment;
-pub use error::AppError;
+pub use error::{AppError, FieldError};
@@ -0,0 +1,90 @@
+//! Startup configuration validation
+//!
+//! Started with `--validate-config`, the binary loads its configuration, builds its clients and
+//! checks every dependency is reachable, then exits without binding the HTTP server.
+
+use anyhow::Context;
+use common_types::validate_config::{run_checks, Check};
+use redis::aio::ConnectionManager;
+
+use crate::{
+    enclave_worker_api::EnclaveWorkerApi,
+    jwt::JwtManager,
+    types::{AwsClients, Environment},
+};
+
+pub use common_types::validate_config::{is_requested, ValidationReport};
+
+/// Checks the backend can reach every dependency it needs to serve traffic
+pub async fn validate(
+    environment: &Environment,
+    aws_clients: &AwsClients,
+    redis_connection_manager: anyhow::Result<Option<ConnectionManager>>,
+    enclave_worker_api: &dyn EnclaveWorkerApi,
+) -> ValidationReport {
+    let mut checks = vec![
+        Check::new("kms", async {
+            JwtManager::new(aws_clients.kms.clone(), environment)
+                .await
+                .context("Failed to read the JWT signing key")?;
+            Ok(())
+        }),
+        Check::new("s3", async {
+            let bucket = environment.s3_bucket();
+            aws_clients
+                .s3
+                .head_bucket()
+                .bucket(&bucket)
+                .send()
+                .await
+                .with_context(|| format!("Failed to access bucket {bucket}"))?;
+            Ok(())
+        }),
+        Check::new(
+            "dynamodb_auth_table",
+            describe_table(aws_clients, environment.dynamodb_auth_table_name()),
+        ),
+        Check::new(
+            "dynamodb_push_subscription_table",
+            describe_table(
+                aws_clients,
+                environment.dynamodb_push_subscription_table_name(),
+            ),
+        ),
+        Check::new("enclave_worker", async {
+            enclave_worker_api
+                .get_attestation_document()
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to get attestation document: {e:?}"))?;
+            Ok(())
+        }),
+    ];
+
+    match redis_connection_manager {
+        Ok(Some(mut conn)) => checks.push(Check::new("redis", async move {
+            redis::cmd("PING")
+                .query_async::<String>(&mut conn)
+                .await
+                .context("Failed to ping Redis")?;
+            Ok(())
+        })),
+        Ok(None) => {}
+        // A failed connection is reported like any other check instead of aborting the validation
+        Err(e) => checks.push(Check::new("redis", async move {
+            Err(e.context("Failed to connect to Redis"))
+        })),
+    }
+
+    run_checks(checks).await
+}
+
+async fn describe_table(aws_clients: &AwsClients, table_name: String) -> anyhow::Result<()> {
+    aws_clients
+        .dynamodb
+        .describe_table()
+        .table_name(&table_name)
+        .send()
+        .await
+        .with_context(|| format!("Failed to describe table {table_name}"))?;
+    Ok(())
+}
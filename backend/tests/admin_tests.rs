@@ -0,0 +1,156 @@
+mod common;
+
+use backend::jwt::{SCOPE_ADMIN, SCOPE_USER};
+use http::{Method, StatusCode};
+use uuid::Uuid;
+
+use crate::common::{create_subscription, generate_hmac_key, subscription_exists, TestSetup};
+
+#[tokio::test]
+async fn test_purge_topic_subscriptions() {
+    let context = TestSetup::new(None, false).await; // Auth enabled
+    let token = context
+        .issue_scoped_token(vec![SCOPE_ADMIN.to_string()])
+        .await;
+
+    let topic = format!("topic-{}", Uuid::new_v4());
+    let other_topic = format!("topic-{}", Uuid::new_v4());
+    let mut subscriptions = Vec::new();
+    for _ in 0..30 {
+        let hmac_key = generate_hmac_key();
+        let encrypted_push_id = format!("encrypted-push-{}", Uuid::new_v4());
+        create_subscription(&context, &topic, &hmac_key, &encrypted_push_id).await;
+        subscriptions.push((hmac_key, encrypted_push_id));
+    }
+    let other_hmac_key = generate_hmac_key();
+    create_subscription(
+        &context,
+        &other_topic,
+        &other_hmac_key,
+        "encrypted-push-other",
+    )
+    .await;
+
+    let response = context
+        .send_request(
+            Method::DELETE,
+            &format!("/v1/admin/subscriptions?topic={topic}"),
+            None,
+            Some(vec![("Authorization", &format!("Bearer {token}"))]),
+        )
+        .await
+        .expect("Failed to send request");
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = context.parse_response_body(response).await.unwrap();
+    assert_eq!(body["deleted"], 30);
+
+    for (hmac_key, encrypted_push_id) in &subscriptions {
+        assert!(!subscription_exists(&context, &topic, hmac_key, encrypted_push_id).await);
+    }
+    // Other topics are left untouched
+    assert!(
+        subscription_exists(
+            &context,
+            &other_topic,
+            &other_hmac_key,
+            "encrypted-push-other"
+        )
+        .await
+    );
+}
+
+#[tokio::test]
+async fn test_issued_admin_token_reaches_admin_routes() {
+    let context = TestSetup::new(None, false).await; // Auth enabled
+    let token = context.issue_admin_token("on-call").await;
+
+    let topic = format!("topic-{}", Uuid::new_v4());
+    let response = context
+        .send_request(
+            Method::DELETE,
+            &format!("/v1/admin/subscriptions?topic={topic}"),
+            None,
+            Some(vec![("Authorization", &format!("Bearer {token}"))]),
+        )
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Admin tokens only grant the admin scope
+    let response = context
+        .send_request(
+            Method::GET,
+            "/v1/media/config",
+            None,
+            Some(vec![("Authorization", &format!("Bearer {token}"))]),
+        )
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn test_purge_topic_subscriptions_without_admin_scope() {
+    let context = TestSetup::new(None, false).await; // Auth enabled
+    let token = context
+        .issue_scoped_token(vec![SCOPE_USER.to_string()])
+        .await;
+
+    let topic = format!("topic-{}", Uuid::new_v4());
+    let hmac_key = generate_hmac_key();
+    create_subscription(&context, &topic, &hmac_key, "encrypted-push-user").await;
+
+    let response = context
+        .send_request(
+            Method::DELETE,
+            &format!("/v1/admin/subscriptions?topic={topic}"),
+            None,
+            Some(vec![("Authorization", &format!("Bearer {token}"))]),
+        )
+        .await
+        .expect("Failed to send request");
+
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert!(subscription_exists(&context, &topic, &hmac_key, "encrypted-push-user").await);
+}
+
+#[tokio::test]
+async fn test_peek_dead_letters_without_admin_scope() {
+    let context = TestSetup::new(None, false).await; // Auth enabled
+    let token = context
+        .issue_scoped_token(vec![SCOPE_USER.to_string()])
+        .await;
+
+    let response = context
+        .send_request(
+            Method::GET,
+            "/v1/admin/dead-letters",
+            None,
+            Some(vec![("Authorization", &format!("Bearer {token}"))]),
+        )
+        .await
+        .expect("Failed to send request");
+
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn test_peek_dead_letters_without_dead_letter_queue() {
+    let context = TestSetup::new(None, false).await; // Auth enabled
+    let token = context.issue_admin_token("on-call").await;
+
+    let response = context
+        .send_request(
+            Method::GET,
+            "/v1/admin/dead-letters",
+            None,
+            Some(vec![("Authorization", &format!("Bearer {token}"))]),
+        )
+        .await
+        .expect("Failed to send request");
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let body = context.parse_response_body(response).await.unwrap();
+    assert_eq!(body["error"]["code"], "dead_letter_queue_not_configured");
+}
@@ -294,6 +294,7 @@ async fn test_authorize_jwt_is_validatable_by_manager() {
         .expect("failed to build JwtManager");
     let claims = manager
         .validate(token, None)
+        .await
         .expect("token should validate");
     assert_eq!(claims.subject, encrypted_push_id);
     assert_eq!(claims.issuer, context.environment.jwt_issuer_url());
@@ -345,7 +346,7 @@ async fn test_validate_rejects_wrong_alg() {
     let manager = backend::jwt::JwtManager::new(context.kms_client.clone(), &context.environment)
         .await
         .expect("failed to build JwtManager");
-    let result = manager.validate(&tampered, None);
+    let result = manager.validate(&tampered, None).await;
     assert!(result.is_err(), "wrong alg should be rejected");
 }
 
@@ -394,7 +395,7 @@ async fn test_validate_rejects_wrong_kid() {
     let manager = backend::jwt::JwtManager::new(context.kms_client.clone(), &context.environment)
         .await
         .expect("failed to build JwtManager");
-    let result = manager.validate(&tampered, None);
+    let result = manager.validate(&tampered, None).await;
     assert!(result.is_err(), "wrong kid should be rejected");
 }
 
@@ -447,7 +448,7 @@ async fn test_validate_rejects_payload_tamper() {
     let manager = backend::jwt::JwtManager::new(context.kms_client.clone(), &context.environment)
         .await
         .expect("failed to build JwtManager");
-    let result = manager.validate(&tampered, None);
+    let result = manager.validate(&tampered, None).await;
     assert!(result.is_err(), "payload tamper should be rejected");
 }
 
@@ -693,3 +694,48 @@ async fn test_protected_endpoint_with_valid_token() {
         "Response should contain either presigned_url or asset_url"
     );
 }
+
+#[tokio::test]
+async fn test_revoked_subject_token_fails_validation() {
+    let context = TestSetup::default().await;
+
+    let redis_client = redis::Client::open(context.environment.redis_url().expect("redis url"))
+        .expect("Failed to create Redis client");
+    let connection_manager = redis::aio::ConnectionManager::new(redis_client)
+        .await
+        .expect("Failed to connect to Redis");
+    let manager = backend::jwt::JwtManager::new(context.kms_client.clone(), &context.environment)
+        .await
+        .expect("failed to build JwtManager")
+        .with_revocation_list(backend::jwt::TokenRevocationList::new(connection_manager));
+
+    let issue_token = |subject: String| {
+        let manager = &manager;
+        async move {
+            let payload = backend::jwt::JwsPayload::from_encrypted_push_id(
+                subject,
+                &manager.issuer,
+                common_types::EnclaveTrack::default(),
+            );
+            manager
+                .issue_token(&payload)
+                .await
+                .expect("failed to issue token")
+        }
+    };
+
+    let revoked_subject = format!("encrypted-push-{}", Uuid::new_v4());
+    let revoked_token = issue_token(revoked_subject.clone()).await;
+    let other_token = issue_token(format!("encrypted-push-{}", Uuid::new_v4())).await;
+
+    manager
+        .revoke(&revoked_subject)
+        .await
+        .expect("failed to revoke subject");
+
+    assert!(matches!(
+        manager.validate(&revoked_token, None).await,
+        Err(backend::jwt::error::JwtError::Revoked)
+    ));
+    assert!(manager.validate(&other_token, None).await.is_ok());
+}
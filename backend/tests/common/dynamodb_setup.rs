@@ -8,6 +8,9 @@ use std::sync::Arc;
 use std::time::Duration;
 use uuid::Uuid;
 
+/// Name of the `encrypted_push_id` GSI on the push subscriptions table
+pub const PUSH_ID_INDEX_NAME: &str = "encrypted-push-id-index";
+
 /// Helper for creating and managing DynamoDB tables in tests
 ///
 /// Creates every table used in backend server.
@@ -95,6 +98,13 @@ impl DynamoDbTestSetup {
                     .build()
                     .unwrap(),
             )
+            .attribute_definitions(
+                AttributeDefinition::builder()
+                    .attribute_name(PushSubscriptionAttribute::EncryptedPushId.to_string())
+                    .attribute_type(ScalarAttributeType::S)
+                    .build()
+                    .unwrap(),
+            )
             .key_schema(
                 KeySchemaElement::builder()
                     .attribute_name(PushSubscriptionAttribute::Topic.to_string())
@@ -109,6 +119,24 @@ impl DynamoDbTestSetup {
                     .build()
                     .unwrap(),
             )
+            .global_secondary_indexes(
+                GlobalSecondaryIndex::builder()
+                    .index_name(PUSH_ID_INDEX_NAME)
+                    .key_schema(
+                        KeySchemaElement::builder()
+                            .attribute_name(PushSubscriptionAttribute::EncryptedPushId.to_string())
+                            .key_type(KeyType::Hash)
+                            .build()
+                            .unwrap(),
+                    )
+                    .projection(
+                        Projection::builder()
+                            .projection_type(ProjectionType::KeysOnly)
+                            .build(),
+                    )
+                    .build()
+                    .unwrap(),
+            )
             .billing_mode(aws_sdk_dynamodb::types::BillingMode::PayPerRequest)
             .send()
             .await
@@ -1,13 +1,13 @@
 use super::TestSetup;
+use backend_storage::push_subscription::{SubscriptionKey, HMAC_KEY_HEX_LENGTH};
 use chrono::Utc;
-use rand::{distributions::Alphanumeric, Rng};
+use rand::Rng;
 
+/// Generates a random hex encoded HMAC key
 pub fn generate_hmac_key() -> String {
-    rand::thread_rng()
-        .sample_iter(&Alphanumeric)
-        .take(84)
-        .map(char::from)
-        .collect()
+    let mut bytes = [0u8; HMAC_KEY_HEX_LENGTH / 2];
+    rand::thread_rng().fill(&mut bytes[..]);
+    hex::encode(bytes)
 }
 
 pub async fn subscription_exists(
@@ -18,7 +18,7 @@ pub async fn subscription_exists(
 ) -> bool {
     context
         .push_subscription_storage
-        .get_one(topic, hmac_key)
+        .get_one(&SubscriptionKey::new(topic, hmac_key))
         .await
         .expect("Failed to get subscription")
         // ensure subscription exists and encrypted_push_id matches
@@ -39,6 +39,7 @@ pub async fn create_subscription(
         ttl: Utc::now().timestamp() + 3600,
         encrypted_push_id: encrypted_push_id.to_string(),
         deletion_request: None,
+        installation_id: None,
     };
 
     context
@@ -56,7 +57,7 @@ pub async fn subscription_has_deletion_request(
 ) -> bool {
     context
         .push_subscription_storage
-        .get_one(topic, hmac_key)
+        .get_one(&SubscriptionKey::new(topic, hmac_key))
         .await
         .expect("Failed to get subscription")
         .is_some_and(|sub| {
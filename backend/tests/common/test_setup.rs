@@ -1,20 +1,29 @@
 use aws_config::{BehaviorVersion, Region};
 use aws_credential_types::Credentials;
-use aws_sdk_dynamodb::Client as DynamoDbClient;
 use aws_sdk_kms::types::{KeySpec, KeyUsageType};
 use aws_sdk_kms::Client as KmsClient;
-use aws_sdk_s3::Client as S3Client;
-use axum::{body::Body, http::Request, response::Response, Extension, Router};
+use axum::{body::Body, http::Request, middleware, response::Response, Extension, Router};
 use backend::enclave_worker_api::mock::MockEnclaveWorkerApiClient;
 use backend::enclave_worker_api::EnclaveWorkerApi;
-use backend::{jwt::JwtManager, media_storage::MediaStorage, routes, types::Environment};
+use backend::media_storage::{PresignedUrlCache, DEFAULT_IDEMPOTENCY_WINDOW};
+use backend::routes::v1::{admin::NotificationDeadLetterQueue, config::ConfigCache};
+use backend::{
+    jwt::{JwsPayload, JwtManager},
+    media_storage::MediaStorage,
+    middleware::request_id::request_id_middleware,
+    routes,
+    types::{AwsClients, Environment},
+};
 use backend_storage::auth_proof::AuthProofStorage;
 use backend_storage::push_subscription::PushSubscriptionStorage;
+use common_types::EnclaveTrack;
 use http::Method;
+use redis::aio::ConnectionManager;
 use std::sync::Arc;
 use tower::ServiceExt;
+use uuid::Uuid;
 
-use super::dynamodb_setup::DynamoDbTestSetup;
+use super::dynamodb_setup::{DynamoDbTestSetup, PUSH_ID_INDEX_NAME};
 
 /// Setup test environment variables with all the required configuration
 pub fn setup_test_env() {
@@ -48,7 +57,22 @@ impl TestSetup {
         Self::new(None, true).await
     }
 
+    /// Create a default test setup caching presigned URLs in Redis, for the idempotency tests
+    #[must_use]
+    pub async fn with_presigned_url_cache() -> Self {
+        Self::build(None, true, true).await
+    }
+
     pub async fn new(presign_expiry_override: Option<u64>, disable_auth: bool) -> Self {
+        Self::build(presign_expiry_override, disable_auth, false).await
+    }
+
+    /// Only connects to Redis with `presigned_url_cache`, so other tests don't need it running
+    async fn build(
+        presign_expiry_override: Option<u64>,
+        disable_auth: bool,
+        presigned_url_cache: bool,
+    ) -> Self {
         setup_test_env();
 
         let environment = Environment::Development {
@@ -56,21 +80,33 @@ impl TestSetup {
             disable_auth,
         };
 
-        let s3_config = environment.s3_client_config().await;
-        let s3_client = Arc::new(S3Client::from_conf(s3_config));
+        let aws_clients = AwsClients::load(&environment).await;
+        let s3_client = aws_clients.s3;
         let bucket_name = environment.s3_bucket();
 
-        let media_storage = Arc::new(MediaStorage::new(
+        let mut media_storage = MediaStorage::new(
             s3_client.clone(),
             bucket_name.clone(),
             environment.presigned_url_expiry_secs(),
-        ));
+        );
+        if presigned_url_cache {
+            let redis_client = redis::Client::open(environment.redis_url().expect("redis url"))
+                .expect("Failed to create Redis client");
+            let redis_connection_manager = ConnectionManager::new(redis_client)
+                .await
+                .expect("Failed to connect to Redis");
+            media_storage = media_storage.with_presigned_url_cache(PresignedUrlCache::new(
+                redis_connection_manager,
+                DEFAULT_IDEMPOTENCY_WINDOW,
+            ));
+        }
+        let media_storage = Arc::new(media_storage);
 
-        let dynamodb_client = Arc::new(DynamoDbClient::new(&environment.aws_config().await));
+        let dynamodb_client = aws_clients.dynamodb;
         let dynamodb_test_setup = DynamoDbTestSetup::new(dynamodb_client.clone()).await;
 
         // Initialize JWT manager (KMS-backed)
-        let kms_client = Arc::new(KmsClient::new(&environment.aws_config().await));
+        let kms_client = aws_clients.kms;
         let jwt_manager = Arc::new(
             JwtManager::new(kms_client.clone(), &environment)
                 .await
@@ -85,6 +121,7 @@ impl TestSetup {
         let push_subscription_storage = Arc::new(PushSubscriptionStorage::new(
             dynamodb_client.clone(),
             dynamodb_test_setup.push_subscriptions_table_name.clone(),
+            PUSH_ID_INDEX_NAME.to_string(),
         ));
 
         let enclave_worker_api: Arc<dyn EnclaveWorkerApi> =
@@ -97,6 +134,9 @@ impl TestSetup {
             .layer(Extension(jwt_manager.clone()))
             .layer(Extension(push_subscription_storage.clone()))
             .layer(Extension(enclave_worker_api.clone()))
+            .layer(Extension(None::<NotificationDeadLetterQueue>))
+            .layer(Extension(Arc::new(ConfigCache::default())))
+            .layer(middleware::from_fn(request_id_middleware))
             .into();
 
         Self {
@@ -156,6 +196,36 @@ impl TestSetup {
             .await
     }
 
+    /// Issues a JWT for a new encrypted push ID with the given scopes
+    pub async fn issue_scoped_token(&self, scope: Vec<String>) -> String {
+        let manager = JwtManager::new(self.kms_client.clone(), &self.environment)
+            .await
+            .expect("failed to build JwtManager");
+        let payload = JwsPayload {
+            scope: Some(scope),
+            ..JwsPayload::from_encrypted_push_id(
+                format!("encrypted-push-{}", Uuid::new_v4()),
+                &manager.issuer,
+                EnclaveTrack::default(),
+            )
+        };
+
+        manager
+            .issue_token(&payload)
+            .await
+            .expect("failed to issue token")
+    }
+
+    /// Issues an admin token for `operator`, as `--issue-admin-token` does
+    pub async fn issue_admin_token(&self, operator: &str) -> String {
+        JwtManager::new(self.kms_client.clone(), &self.environment)
+            .await
+            .expect("failed to build JwtManager")
+            .issue_admin_token(operator)
+            .await
+            .expect("failed to issue admin token")
+    }
+
     /// Send a request with the specified HTTP method and optional headers
     pub async fn send_request(
         &self,
@@ -0,0 +1,68 @@
+mod common;
+
+use common::TestSetup;
+use http::{header, Method, StatusCode};
+
+#[tokio::test]
+async fn test_config_conditional_request_returns_not_modified() {
+    let setup = TestSetup::default().await;
+
+    let response = setup
+        .send_get_request("/v1/config")
+        .await
+        .expect("Failed to send GET /v1/config");
+    assert_eq!(response.status(), StatusCode::OK);
+    let etag = response
+        .headers()
+        .get(header::ETAG)
+        .expect("ETag header should be present")
+        .to_str()
+        .unwrap()
+        .to_string();
+
+    let response = setup
+        .send_request(
+            Method::GET,
+            "/v1/config",
+            None,
+            Some(vec![("If-None-Match", etag.as_str())]),
+        )
+        .await
+        .expect("Failed to send conditional GET /v1/config");
+
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(response.headers().get(header::ETAG).unwrap(), etag.as_str());
+}
+
+#[tokio::test]
+async fn test_config_etag_differs_per_notification_server_version() {
+    let setup = TestSetup::default().await;
+
+    let response = setup
+        .send_request(
+            Method::GET,
+            "/v1/config",
+            None,
+            Some(vec![("client-version", "3.0.0")]),
+        )
+        .await
+        .expect("Failed to send GET /v1/config");
+    let v1_etag = response.headers().get(header::ETAG).unwrap().clone();
+
+    // A client on the rotated key doesn't match the previous ETag
+    let response = setup
+        .send_request(
+            Method::GET,
+            "/v1/config",
+            None,
+            Some(vec![
+                ("client-version", "4.0.0"),
+                ("If-None-Match", v1_etag.to_str().unwrap()),
+            ]),
+        )
+        .await
+        .expect("Failed to send conditional GET /v1/config");
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_ne!(response.headers().get(header::ETAG).unwrap(), v1_etag);
+}
@@ -114,6 +114,12 @@ async fn test_unsubscribe_empty_string_fields() {
             "/v1/subscriptions?topic={}&hmac_key=abc123",
             format!("topic-{}", Uuid::new_v4())
         ),
+        // Non-hex hmac_key
+        format!(
+            "/v1/subscriptions?topic={}&hmac_key={}",
+            format!("topic-{}", Uuid::new_v4()),
+            "z".repeat(84)
+        ),
     ];
 
     for url in test_cases {
@@ -229,6 +235,34 @@ async fn test_unsubscribe_matching_push_id_deletes_document() {
     assert!(!subscription_exists(&context, &topic, &hmac_key, &encrypted_push_id).await);
 }
 
+#[tokio::test]
+async fn test_unsubscribe_deletes_subscription_stored_under_raw_topic() {
+    let context = TestSetup::default().await;
+    let encrypted_push_id = format!("encrypted-push-{}", Uuid::new_v4());
+
+    // Created before topics were normalized, stored under the topic the client presented
+    let topic = format!("G-{}", Uuid::new_v4().simple().to_string().to_uppercase());
+    let hmac_key = generate_hmac_key();
+    create_subscription(&context, &topic, &hmac_key, &encrypted_push_id).await;
+
+    let url = format!("/v1/subscriptions?topic={}&hmac_key={}", topic, hmac_key);
+    let response = context
+        .send_request(
+            Method::DELETE,
+            &url,
+            None,
+            Some(vec![(
+                "Authorization",
+                &format!("Bearer {}", encrypted_push_id),
+            )]),
+        )
+        .await
+        .expect("Failed to send request");
+
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert!(!subscription_exists(&context, &topic, &hmac_key, &encrypted_push_id).await);
+}
+
 #[tokio::test]
 async fn test_unsubscribe_nonmatching_push_id_appends_deletion_request() {
     let context = TestSetup::default().await;
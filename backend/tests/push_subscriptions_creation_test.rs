@@ -1,6 +1,8 @@
 mod common;
 
+use backend::jwt::{SCOPE_ADMIN, SCOPE_USER};
 use backend::routes::v1::subscriptions::CreateSubscriptionRequest;
+use backend_storage::push_subscription::SubscriptionKey;
 use chrono::Utc;
 use http::StatusCode;
 use serde_json::json;
@@ -36,6 +38,140 @@ async fn test_subscribe_happy_path_single_subscription() {
     assert!(subscription_exists(&context, &topic, &hmac_key, &encrypted_push_id).await);
 }
 
+#[tokio::test]
+async fn test_subscribe_stores_normalized_topic() {
+    let context = TestSetup::default().await;
+
+    let encrypted_push_id = format!("encrypted-push-{}", Uuid::new_v4());
+    let group_id = Uuid::new_v4().simple().to_string();
+    let hmac_key = generate_hmac_key();
+
+    let response = context
+        .send_post_request_with_headers(
+            "/v1/subscriptions",
+            json!([{
+                "topic": format!(" G-{}", group_id.to_uppercase()),
+                "hmac_key": hmac_key,
+                "ttl": Utc::now().timestamp() + 3600,
+            }]),
+            vec![("Authorization", &format!("Bearer {}", encrypted_push_id))],
+        )
+        .await
+        .expect("Failed to send request");
+
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    // Stored under the topic of the XMTP envelopes, where fan-out looks it up
+    let topic = format!("/xmtp/mls/1/g-{group_id}/proto");
+    assert!(subscription_exists(&context, &topic, &hmac_key, &encrypted_push_id).await);
+}
+
+#[tokio::test]
+async fn test_bulk_subscribe_echoes_requested_topic() {
+    let context = TestSetup::default().await;
+
+    let encrypted_push_id = format!("encrypted-push-{}", Uuid::new_v4());
+    let group_id = Uuid::new_v4().simple().to_string();
+    let requested_topic = format!("g-{group_id}");
+    let hmac_key = generate_hmac_key();
+
+    let response = context
+        .send_post_request_with_headers(
+            "/v1/subscriptions/bulk",
+            json!([{
+                "topic": requested_topic.clone(),
+                "hmac_key": hmac_key,
+                "ttl": Utc::now().timestamp() + 3600,
+            }]),
+            vec![("Authorization", &format!("Bearer {}", encrypted_push_id))],
+        )
+        .await
+        .expect("Failed to send request");
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = context
+        .parse_response_body(response)
+        .await
+        .expect("Failed to parse response body");
+    assert_eq!(
+        body,
+        json!({ "results": [{ "topic": requested_topic, "status": "subscribed" }] })
+    );
+
+    // Stored under the normalized topic all the same
+    let topic = format!("/xmtp/mls/1/g-{group_id}/proto");
+    assert!(subscription_exists(&context, &topic, &hmac_key, &encrypted_push_id).await);
+}
+
+#[tokio::test]
+async fn test_subscribe_stores_installation_id() {
+    let context = TestSetup::default().await;
+
+    let encrypted_push_id = format!("encrypted-push-{}", Uuid::new_v4());
+    let topic = format!("topic-{}", Uuid::new_v4());
+    let hmac_key = generate_hmac_key();
+    let installation_id = format!("installation-{}", Uuid::new_v4());
+
+    let response = context
+        .send_post_request_with_headers(
+            "/v1/subscriptions",
+            json!([{
+                "topic": topic,
+                "hmac_key": hmac_key,
+                "ttl": Utc::now().timestamp() + 3600,
+                "installation_id": installation_id,
+            }]),
+            vec![("Authorization", &format!("Bearer {}", encrypted_push_id))],
+        )
+        .await
+        .expect("Failed to send request");
+
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    let subscription = context
+        .push_subscription_storage
+        .get_one(&SubscriptionKey::new(topic.as_str(), hmac_key.as_str()))
+        .await
+        .expect("Failed to get subscription")
+        .expect("Subscription should exist");
+    assert_eq!(subscription.installation_id, Some(installation_id));
+}
+
+#[tokio::test]
+async fn test_subscribe_reports_storage_server_timing() {
+    let context = TestSetup::default().await;
+
+    let response = context
+        .send_post_request_with_headers(
+            "/v1/subscriptions",
+            json!([{
+                "topic": format!("topic-{}", Uuid::new_v4()),
+                "hmac_key": generate_hmac_key(),
+                "ttl": Utc::now().timestamp() + 3600,
+            }]),
+            vec![(
+                "Authorization",
+                &format!("Bearer encrypted-push-{}", Uuid::new_v4()),
+            )],
+        )
+        .await
+        .expect("Failed to send request");
+
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let server_timing = response
+        .headers()
+        .get("server-timing")
+        .expect("Server-Timing header should be present")
+        .to_str()
+        .unwrap();
+    assert!(
+        server_timing
+            .split(", ")
+            .any(|phase| phase.starts_with("storage;dur=")),
+        "Server-Timing should contain the storage phase, got {server_timing}"
+    );
+}
+
 #[tokio::test]
 async fn test_subscribe_happy_path_batch_subscriptions() {
     let context = TestSetup::default().await;
@@ -48,6 +184,7 @@ async fn test_subscribe_happy_path_batch_subscriptions() {
             topic: format!("topic-{}", i),
             hmac_key: generate_hmac_key(),
             ttl: Utc::now().timestamp() + 3600,
+            installation_id: None,
         });
     }
 
@@ -123,6 +260,175 @@ async fn test_subscribe_with_existing_push_id() {
     assert!(subscription_exists(&context, &topic, &hmac_key, &other_encrypted_push_id).await);
 }
 
+#[tokio::test]
+async fn test_resubscribe_refreshes_ttl() {
+    let context = TestSetup::default().await;
+    let encrypted_push_id = format!("encrypted-push-{}", Uuid::new_v4());
+
+    let topic = format!("topic-{}", Uuid::new_v4());
+    let hmac_key = generate_hmac_key();
+    let initial_ttl = Utc::now().timestamp() + 3600;
+    // Further out than the max random TTL offset, so the refresh is observable
+    let refreshed_ttl = initial_ttl + 7 * 24 * 3600;
+
+    for ttl in [initial_ttl, refreshed_ttl] {
+        let response = context
+            .send_post_request_with_headers(
+                "/v1/subscriptions",
+                json!([{
+                    "topic": topic.clone(),
+                    "hmac_key": hmac_key.clone(),
+                    "ttl": ttl,
+                }]),
+                vec![("Authorization", &format!("Bearer {}", encrypted_push_id))],
+            )
+            .await
+            .expect("Failed to send request");
+
+        assert_eq!(response.status(), StatusCode::CREATED);
+    }
+
+    let subscription = context
+        .push_subscription_storage
+        .get_one(&SubscriptionKey::new(topic.as_str(), hmac_key.as_str()))
+        .await
+        .expect("Failed to get subscription")
+        .expect("Subscription should exist");
+    assert_eq!(subscription.encrypted_push_id, encrypted_push_id);
+    assert!(
+        subscription.ttl >= refreshed_ttl,
+        "TTL should be refreshed, got {}",
+        subscription.ttl
+    );
+}
+
+#[tokio::test]
+async fn test_bulk_subscribe_reports_per_topic_results() {
+    let context = TestSetup::default().await;
+    let encrypted_push_id = format!("encrypted-push-{}", Uuid::new_v4());
+    let auth_header = format!("Bearer {}", encrypted_push_id);
+    let ttl = Utc::now().timestamp() + 3600;
+
+    let existing_topic = format!("topic-{}", Uuid::new_v4());
+    let existing_hmac_key = generate_hmac_key();
+    let response = context
+        .send_post_request_with_headers(
+            "/v1/subscriptions",
+            json!([{
+                "topic": existing_topic.clone(),
+                "hmac_key": existing_hmac_key.clone(),
+                "ttl": ttl,
+            }]),
+            vec![("Authorization", &auth_header)],
+        )
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    let new_topic = format!("topic-{}", Uuid::new_v4());
+    let new_hmac_key = generate_hmac_key();
+    // Exceeds the DynamoDB partition key size limit of 2048 bytes, so the write fails
+    let failing_topic = "t".repeat(4096);
+
+    let response = context
+        .send_post_request_with_headers(
+            "/v1/subscriptions/bulk",
+            json!([
+                {
+                    "topic": existing_topic.clone(),
+                    "hmac_key": existing_hmac_key.clone(),
+                    "ttl": ttl,
+                },
+                {
+                    "topic": new_topic.clone(),
+                    "hmac_key": new_hmac_key.clone(),
+                    "ttl": ttl,
+                },
+                {
+                    "topic": failing_topic.clone(),
+                    "hmac_key": generate_hmac_key(),
+                    "ttl": ttl,
+                },
+            ]),
+            vec![("Authorization", &auth_header)],
+        )
+        .await
+        .expect("Failed to send request");
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = context
+        .parse_response_body(response)
+        .await
+        .expect("Failed to parse response body");
+    assert_eq!(
+        body,
+        json!({
+            "results": [
+                { "topic": existing_topic, "status": "refreshed" },
+                { "topic": new_topic, "status": "subscribed" },
+                { "topic": failing_topic, "status": "failed" },
+            ]
+        })
+    );
+
+    assert!(
+        subscription_exists(
+            &context,
+            &existing_topic,
+            &existing_hmac_key,
+            &encrypted_push_id
+        )
+        .await
+    );
+    assert!(subscription_exists(&context, &new_topic, &new_hmac_key, &encrypted_push_id).await);
+}
+
+#[tokio::test]
+async fn test_subscribe_with_required_scope() {
+    let context = TestSetup::new(None, false).await; // Auth enabled
+    let token = context
+        .issue_scoped_token(vec![SCOPE_USER.to_string()])
+        .await;
+
+    let response = context
+        .send_post_request_with_headers(
+            "/v1/subscriptions",
+            json!([{
+                "topic": format!("topic-{}", Uuid::new_v4()),
+                "hmac_key": generate_hmac_key(),
+                "ttl": Utc::now().timestamp() + 3600,
+            }]),
+            vec![("Authorization", &format!("Bearer {}", token))],
+        )
+        .await
+        .expect("Failed to send request");
+
+    assert_eq!(response.status(), StatusCode::CREATED);
+}
+
+#[tokio::test]
+async fn test_subscribe_without_required_scope() {
+    let context = TestSetup::new(None, false).await; // Auth enabled
+    let token = context
+        .issue_scoped_token(vec![SCOPE_ADMIN.to_string()])
+        .await;
+
+    let response = context
+        .send_post_request_with_headers(
+            "/v1/subscriptions",
+            json!([{
+                "topic": format!("topic-{}", Uuid::new_v4()),
+                "hmac_key": generate_hmac_key(),
+                "ttl": Utc::now().timestamp() + 3600,
+            }]),
+            vec![("Authorization", &format!("Bearer {}", token))],
+        )
+        .await
+        .expect("Failed to send request");
+
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
 #[tokio::test]
 async fn test_subscribe_without_auth_header() {
     let context = TestSetup::new(None, false).await; // Auth enabled
@@ -397,3 +703,108 @@ async fn test_subscribe_empty_string_fields() {
         assert_eq!(response.status(), StatusCode::BAD_REQUEST);
     }
 }
+
+#[tokio::test]
+async fn test_subscribe_reports_invalid_fields() {
+    let context = TestSetup::default().await;
+    let encrypted_push_id = format!("encrypted-push-{}", Uuid::new_v4());
+    let valid = || {
+        json!({
+            "topic": format!("topic-{}", Uuid::new_v4()),
+            "hmac_key": generate_hmac_key(),
+            "ttl": Utc::now().timestamp() + 3600,
+        })
+    };
+
+    let test_cases = vec![
+        ("topic", json!(""), "Topic cannot be empty"),
+        (
+            "hmac_key",
+            json!("abc123"),
+            "HMAC key must be exactly 84 hex characters",
+        ),
+        ("ttl", json!(0), "TTL must be greater than now + 1 second"),
+        (
+            "installation_id",
+            json!(""),
+            "Installation ID must be between 1 and 128 characters",
+        ),
+    ];
+
+    for (field, value, message) in test_cases {
+        let mut invalid = valid();
+        invalid[field] = value;
+
+        let response = context
+            .send_post_request_with_headers(
+                "/v1/subscriptions",
+                json!([valid(), invalid]),
+                vec![("Authorization", &format!("Bearer {}", encrypted_push_id))],
+            )
+            .await
+            .expect("Failed to send request");
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{field}");
+        let body = context.parse_response_body(response).await.unwrap();
+        assert_eq!(body["error"]["code"], "validation_error");
+        assert_eq!(
+            body["errors"],
+            json!([{ "field": format!("[1].{field}"), "message": message }]),
+            "{field}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_subscribe_reports_every_invalid_field() {
+    let context = TestSetup::default().await;
+    let encrypted_push_id = format!("encrypted-push-{}", Uuid::new_v4());
+
+    let response = context
+        .send_post_request_with_headers(
+            "/v1/subscriptions",
+            json!([{
+                "topic": "",
+                "hmac_key": "abc123",
+                "ttl": Utc::now().timestamp() + 3600,
+            }]),
+            vec![("Authorization", &format!("Bearer {}", encrypted_push_id))],
+        )
+        .await
+        .expect("Failed to send request");
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body = context.parse_response_body(response).await.unwrap();
+    let fields: Vec<_> = body["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|error| error["field"].as_str().unwrap())
+        .collect();
+    assert_eq!(fields, vec!["[0].hmac_key", "[0].topic"]);
+}
+
+#[tokio::test]
+async fn test_subscribe_valid_request_has_no_validation_errors() {
+    let context = TestSetup::default().await;
+    let encrypted_push_id = format!("encrypted-push-{}", Uuid::new_v4());
+    let topic = format!("topic-{}", Uuid::new_v4());
+    let hmac_key = generate_hmac_key();
+
+    let response = context
+        .send_post_request_with_headers(
+            "/v1/subscriptions",
+            json!([{
+                "topic": topic,
+                "hmac_key": hmac_key,
+                "ttl": Utc::now().timestamp() + 3600,
+                "installation_id": format!("installation-{}", Uuid::new_v4()),
+            }]),
+            vec![("Authorization", &format!("Bearer {}", encrypted_push_id))],
+        )
+        .await
+        .expect("Failed to send request");
+
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert!(subscription_exists(&context, &topic, &hmac_key, &encrypted_push_id).await);
+}
@@ -2,6 +2,7 @@ mod common;
 
 use common::*;
 
+use backend::media_storage::{MediaStorage, IMMUTABLE_CACHE_CONTROL};
 use http::StatusCode;
 use serde_json::json;
 
@@ -87,6 +88,57 @@ async fn test_upload_media_happy_path() {
 
 // Validation error tests (schemars validation - expect 400 instead of custom errors)
 
+#[tokio::test]
+async fn test_upload_media_idempotency_key_returns_same_url() {
+    let setup = TestSetup::with_presigned_url_cache().await;
+
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+    let payload = create_upload_request(create_valid_sha256(), 1024, None);
+
+    let mut presigned_urls = Vec::new();
+    for attempt in 0..2 {
+        if attempt > 0 {
+            // Presigned URLs are signed with a second-resolution timestamp,
+            // so without the cache the retry would get a different URL
+            tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+        }
+
+        let response = setup
+            .send_post_request_with_headers(
+                "/v1/media/presigned-urls",
+                payload.clone(),
+                vec![("Idempotency-Key", idempotency_key.as_str())],
+            )
+            .await
+            .expect("Failed to send request");
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = parse_response_body(response).await;
+        presigned_urls.push(body["presigned_url"].as_str().unwrap().to_string());
+    }
+
+    assert_eq!(presigned_urls[0], presigned_urls[1]);
+}
+
+#[tokio::test]
+async fn test_upload_media_invalid_idempotency_key() {
+    let setup = TestSetup::default().await;
+
+    let payload = create_upload_request(create_valid_sha256(), 1024, None);
+    let idempotency_key = "k".repeat(129);
+
+    let response = setup
+        .send_post_request_with_headers(
+            "/v1/media/presigned-urls",
+            payload,
+            vec![("Idempotency-Key", idempotency_key.as_str())],
+        )
+        .await
+        .expect("Failed to send request");
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
 #[tokio::test]
 async fn test_upload_media_invalid_sha256_format() {
     let setup = TestSetup::default().await;
@@ -439,6 +491,43 @@ async fn test_upload_media_extra_fields() {
     assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
 }
 
+#[tokio::test]
+async fn test_presigned_download_url_overrides_cache_control() {
+    let setup = TestSetup::default().await;
+    let content_digest_sha256 = create_valid_sha256();
+
+    let response = setup
+        .send_get_request(&format!("/v1/media/presigned-urls/{content_digest_sha256}"))
+        .await
+        .expect("Failed to send request");
+
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = parse_response_body(response).await;
+    let presigned_url = url::Url::parse(body["presigned_url"].as_str().unwrap()).unwrap();
+    assert!(presigned_url
+        .path()
+        .ends_with(&MediaStorage::map_sha256_to_s3_key(&content_digest_sha256)));
+    assert!(presigned_url.query_pairs().any(|(key, value)| {
+        key == "response-cache-control" && value == IMMUTABLE_CACHE_CONTROL
+    }));
+    assert!(body["expires_at"].is_string());
+}
+
+#[tokio::test]
+async fn test_presigned_download_url_invalid_sha256() {
+    let setup = TestSetup::default().await;
+
+    for content_digest_sha256 in ["invalid_sha256_digest", "abc123"] {
+        let response = setup
+            .send_get_request(&format!("/v1/media/presigned-urls/{content_digest_sha256}"))
+            .await
+            .expect("Failed to send request");
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
+
 // Testing e2e upload flows
 
 #[tokio::test]
@@ -590,6 +679,13 @@ async fn test_e2e_upload_happy_path() {
         StatusCode::CONFLICT,
         "Expected 409 Conflict for duplicate SHA-256"
     );
+    let etag_header = duplicate_response
+        .headers()
+        .get(http::header::ETAG)
+        .expect("Expected ETag header for existing asset")
+        .to_str()
+        .expect("ETag header is not valid ASCII")
+        .to_string();
 
     let duplicate_response_body = setup
         .parse_response_body(duplicate_response)
@@ -601,6 +697,17 @@ async fn test_e2e_upload_happy_path() {
         "Expected asset_url to be the same as the original"
     );
 
+    // The ETag of the existing object is returned for client-side caching
+    let etag = setup
+        .media_storage
+        .head_object_etag(&MediaStorage::map_sha256_to_s3_key(&sha256))
+        .await
+        .expect("Failed to head object")
+        .expect("Object should exist");
+    assert!(!etag.is_empty(), "Expected a non-empty ETag");
+    assert_eq!(duplicate_response_body["etag"], etag);
+    assert_eq!(etag_header, etag);
+
     println!("✅ Deduplication works correctly (409 Conflict)");
 
     println!("🎉 E2E upload happy path test completed successfully!");
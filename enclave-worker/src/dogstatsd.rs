@@ -0,0 +1,126 @@
+//! `DogStatsD` metrics recorder setup and known metric names
+
+use backend_storage::push_subscription::EXPIRED_SUBSCRIPTIONS_SKIPPED_METRIC;
+pub use common_types::dogstatsd::{init, is_initialized, GIT_REV, VERSION};
+
+/// Metrics reported by the enclave worker
+///
+/// Emit metrics through these names rather than string literals, so a typo can't silently
+/// create a new, unmonitored metric.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    /// Counter of notifications delivered to the enclave, including partial deliveries, labelled
+    /// with `topic_bucket`
+    NotificationDelivered,
+    /// Gauge of the configured recipients per enclave batch
+    NotificationRecipientsPerBatch,
+    /// Counter of enclave retries suppressed because the retry budget was exhausted
+    EnclaveRetryBudgetExhausted,
+    /// Counter of enclave metric samples dropped because the enclave's store was full
+    EnclaveMetricSamplesDropped,
+    /// Counter of subscriptions deleted because the enclave can't decrypt their push ID
+    UndecryptableSubscriptionsPruned,
+    /// Counter of recipients skipped because their push ID isn't valid hex
+    InvalidPushIds,
+    /// Counter of recipients dropped after being re-enqueued `MAX_RECIPIENT_REQUEUES` times
+    RequeuedRecipientsDropped,
+    /// Counter of expired subscriptions skipped while reading a topic, not yet deleted by TTL
+    ExpiredSubscriptionsSkipped,
+    /// Counter of push IDs the enclave failed to decrypt, forwarded from the enclave
+    EnclaveDecryptFailures,
+    /// Counter of failed Braze requests, forwarded from the enclave
+    EnclaveBrazeFailures,
+    /// Histogram of the Braze request latency in milliseconds, forwarded from the enclave
+    EnclaveBrazeLatency,
+}
+
+impl Metric {
+    /// Every known metric
+    pub const ALL: &[Self] = &[
+        Self::NotificationDelivered,
+        Self::NotificationRecipientsPerBatch,
+        Self::EnclaveRetryBudgetExhausted,
+        Self::EnclaveMetricSamplesDropped,
+        Self::UndecryptableSubscriptionsPruned,
+        Self::InvalidPushIds,
+        Self::RequeuedRecipientsDropped,
+        Self::ExpiredSubscriptionsSkipped,
+        Self::EnclaveDecryptFailures,
+        Self::EnclaveBrazeFailures,
+        Self::EnclaveBrazeLatency,
+    ];
+
+    /// The metric name, without the global prefix
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::NotificationDelivered => "notification_delivered",
+            Self::NotificationRecipientsPerBatch => "notification_recipients_per_batch",
+            Self::EnclaveRetryBudgetExhausted => "enclave_retry_budget_exhausted",
+            Self::EnclaveMetricSamplesDropped => "enclave_metric_samples_dropped",
+            Self::UndecryptableSubscriptionsPruned => "undecryptable_subscriptions_pruned",
+            Self::InvalidPushIds => "invalid_push_ids",
+            Self::RequeuedRecipientsDropped => "requeued_recipients_dropped",
+            Self::ExpiredSubscriptionsSkipped => EXPIRED_SUBSCRIPTIONS_SKIPPED_METRIC,
+            Self::EnclaveDecryptFailures => "enclave.decrypt_failures",
+            Self::EnclaveBrazeFailures => "enclave.braze_failures",
+            Self::EnclaveBrazeLatency => "enclave.braze_latency",
+        }
+    }
+
+    /// Looks up a known metric by name, `None` for unknown names
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|metric| metric.name() == name)
+    }
+}
+
+/// Number of buckets topics are hashed into for the `topic_bucket` label
+pub const TOPIC_BUCKETS: u64 = 64;
+
+/// Buckets `topic` into one of `TOPIC_BUCKETS` values for the `topic_bucket` label
+///
+/// Topics are unbounded, so tagging metrics with them would blow up the tag cardinality. Buckets
+/// still show whether a single topic dominates traffic. Uses FNV-1a, which unlike the std hasher
+/// is stable across builds, so a topic keeps its bucket between deploys.
+#[must_use]
+pub fn topic_bucket(topic: &str) -> String {
+    const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    let hash = topic.bytes().fold(FNV_OFFSET_BASIS, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME)
+    });
+    (hash % TOPIC_BUCKETS).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_topic_bucket_is_stable_and_spread() {
+        let topic = "/xmtp/mls/1/g-0123456789abcdef/proto";
+        assert_eq!(topic_bucket(topic), topic_bucket(topic));
+
+        let buckets = (0..1000)
+            .map(|i| topic_bucket(&format!("/xmtp/mls/1/g-{i}/proto")))
+            .collect::<std::collections::HashSet<_>>();
+        assert!(buckets.len() > 1);
+        assert!(buckets
+            .iter()
+            .all(|bucket| bucket.parse::<u64>().unwrap() < TOPIC_BUCKETS));
+    }
+
+    #[test]
+    fn test_metric_names_are_known() {
+        for metric in Metric::ALL {
+            assert_eq!(Metric::from_name(metric.name()), Some(*metric));
+        }
+
+        assert_eq!(Metric::from_name("notification_delivred"), None);
+    }
+}
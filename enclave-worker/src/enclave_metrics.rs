@@ -0,0 +1,189 @@
+//! Export of the enclave's operational metrics
+//!
+//! The enclave is isolated and can't reach the `DogStatsD` agent, so it accumulates its metrics
+//! in memory. This task periodically pulls them over pontifex and reports them from the worker.
+
+use std::time::Duration;
+
+use enclave_types::{
+    EnclaveMetricsRequest, EnclaveMetricsSnapshot, ENCLAVE_METRIC_BRAZE_FAILURES,
+    ENCLAVE_METRIC_BRAZE_LATENCY, ENCLAVE_METRIC_DECRYPT_FAILURES,
+};
+use metrics::{counter, histogram};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+use crate::dogstatsd::Metric;
+
+/// Interval between two pulls of the enclave's metrics
+pub const ENCLAVE_METRICS_PULL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Pulls the enclave's metrics every `interval` and reports them, until `shutdown` is cancelled
+pub async fn run(
+    connection_details: pontifex::client::ConnectionDetails,
+    interval: Duration,
+    shutdown: CancellationToken,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            () = shutdown.cancelled() => break,
+        }
+
+        match pontifex::client::send::<EnclaveMetricsRequest>(
+            connection_details,
+            &EnclaveMetricsRequest,
+        )
+        .await
+        {
+            Ok(Ok(snapshot)) => {
+                forward_snapshot(&snapshot);
+            }
+            Ok(Err(e)) => warn!("Enclave failed to return metrics: {e}"),
+            Err(e) => warn!("Failed to pull enclave metrics: {e}"),
+        }
+    }
+
+    info!("Enclave metrics export stopped");
+}
+
+/// Worker metric reporting the enclave metric `name`, `None` for names the worker doesn't know
+fn forwarded_metric(name: &str) -> Option<Metric> {
+    match name {
+        ENCLAVE_METRIC_DECRYPT_FAILURES => Some(Metric::EnclaveDecryptFailures),
+        ENCLAVE_METRIC_BRAZE_FAILURES => Some(Metric::EnclaveBrazeFailures),
+        ENCLAVE_METRIC_BRAZE_LATENCY => Some(Metric::EnclaveBrazeLatency),
+        _ => None,
+    }
+}
+
+/// Reports the known metrics of `snapshot`, returning the number of values reported
+///
+/// Metrics the worker doesn't know, from a newer enclave, are skipped with a warning until they
+/// are added to `Metric`.
+fn forward_snapshot(snapshot: &EnclaveMetricsSnapshot) -> usize {
+    let mut forwarded = 0;
+
+    for (name, value) in &snapshot.counters {
+        let Some(metric) = forwarded_metric(name) else {
+            warn!(name, "Skipping unknown enclave counter");
+            continue;
+        };
+        counter!(metric.name()).increment(*value);
+        forwarded += 1;
+    }
+
+    for (name, samples) in &snapshot.timings_ms {
+        let Some(metric) = forwarded_metric(name) else {
+            warn!(name, "Skipping unknown enclave timing");
+            continue;
+        };
+        let histogram = histogram!(metric.name());
+        for sample in samples {
+            histogram.record(*sample);
+        }
+        forwarded += samples.len();
+    }
+
+    if snapshot.dropped_samples > 0 {
+        counter!(Metric::EnclaveMetricSamplesDropped.name()).increment(snapshot.dropped_samples);
+    }
+
+    forwarded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use metrics::Label;
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder, Snapshotter};
+    use std::collections::BTreeMap;
+
+    /// Name, labels and value of every metric recorded by `snapshotter`, sorted by name
+    fn recorded(snapshotter: &Snapshotter) -> Vec<(String, Vec<Label>, DebugValue)> {
+        let mut recorded: Vec<_> = snapshotter
+            .snapshot()
+            .into_vec()
+            .into_iter()
+            .map(|(key, .., value)| {
+                let key = key.key();
+                (
+                    key.name().to_string(),
+                    key.labels().cloned().collect(),
+                    value,
+                )
+            })
+            .collect();
+        recorded.sort_by(|a, b| a.0.cmp(&b.0));
+        recorded
+    }
+
+    #[test]
+    fn test_forwards_every_pulled_value() {
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        let _metrics = metrics::set_default_local_recorder(&recorder);
+
+        let snapshot = EnclaveMetricsSnapshot {
+            counters: BTreeMap::from([
+                (ENCLAVE_METRIC_DECRYPT_FAILURES.to_string(), 3),
+                (ENCLAVE_METRIC_BRAZE_FAILURES.to_string(), 1),
+            ]),
+            timings_ms: BTreeMap::from([(
+                ENCLAVE_METRIC_BRAZE_LATENCY.to_string(),
+                vec![12.0, 30.5],
+            )]),
+            dropped_samples: 2,
+        };
+
+        assert_eq!(forward_snapshot(&snapshot), 4);
+        assert_eq!(
+            recorded(&snapshotter),
+            vec![
+                (
+                    Metric::EnclaveBrazeFailures.name().to_string(),
+                    vec![],
+                    DebugValue::Counter(1)
+                ),
+                (
+                    Metric::EnclaveBrazeLatency.name().to_string(),
+                    vec![],
+                    DebugValue::Histogram(vec![12.0.into(), 30.5.into()])
+                ),
+                (
+                    Metric::EnclaveDecryptFailures.name().to_string(),
+                    vec![],
+                    DebugValue::Counter(3)
+                ),
+                (
+                    Metric::EnclaveMetricSamplesDropped.name().to_string(),
+                    vec![],
+                    DebugValue::Counter(2)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_skips_unknown_enclave_metrics() {
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        let _metrics = metrics::set_default_local_recorder(&recorder);
+
+        let snapshot = EnclaveMetricsSnapshot {
+            counters: BTreeMap::from([("decrypt_failrues".to_string(), 3)]),
+            timings_ms: BTreeMap::from([("unknown_latency".to_string(), vec![12.0])]),
+            dropped_samples: 0,
+        };
+
+        assert_eq!(forward_snapshot(&snapshot), 0);
+        assert!(recorded(&snapshotter).is_empty());
+        assert_eq!(
+            forwarded_metric(ENCLAVE_METRIC_BRAZE_LATENCY),
+            Some(Metric::EnclaveBrazeLatency)
+        );
+    }
+}
@@ -0,0 +1,392 @@
+//! Health of the enclave worker's dependencies
+//!
+//! Every dependency is checked in the background and the latest outcome of each check is kept,
+//! so the health endpoint tells on-call exactly which dependency is broken and since when it was
+//! last checked, without hitting every dependency on each probe.
+
+use std::{
+    sync::{Arc, PoisonError, RwLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use enclave_types::EnclaveHealthCheckRequest;
+use futures::future::join_all;
+use schemars::JsonSchema;
+use serde::Serialize;
+use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+use crate::{
+    redis::RedisClient,
+    types::{AwsClients, Environment},
+};
+
+/// Interval between two checks of the dependencies
+pub const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Timeout of a single dependency check
+const CHECK_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// A dependency of the enclave worker
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dependency {
+    /// The secure enclave, reached over pontifex
+    Enclave,
+    /// Redis, caching the attestation document
+    Redis,
+    /// SQS notification queue
+    Sqs,
+    /// `DynamoDB` push subscription table
+    DynamoDb,
+}
+
+impl Dependency {
+    /// Every dependency, in the order they are reported
+    pub const ALL: [Self; 4] = [Self::Enclave, Self::Redis, Self::Sqs, Self::DynamoDb];
+
+    /// Name of the dependency in the health response
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Enclave => "enclave",
+            Self::Redis => "redis",
+            Self::Sqs => "sqs",
+            Self::DynamoDb => "dynamodb",
+        }
+    }
+
+    /// Whether notifications can't be delivered while the dependency is down
+    ///
+    /// Redis only caches the attestation document, which is fetched from the enclave on a miss.
+    #[must_use]
+    pub const fn is_critical(self) -> bool {
+        !matches!(self, Self::Redis)
+    }
+}
+
+/// Status of a single dependency
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DependencyStatus {
+    /// The last check passed
+    Up,
+    /// The last check failed
+    Down,
+    /// The dependency hasn't been checked yet
+    Unknown,
+}
+
+/// Aggregate status of the enclave worker
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthStatus {
+    /// Every dependency is up
+    Healthy,
+    /// A non-critical dependency is down
+    Degraded,
+    /// A critical dependency is down
+    Unhealthy,
+}
+
+/// Latest health of a single dependency
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct DependencyHealth {
+    /// Name of the dependency
+    pub name: &'static str,
+    /// Status of the dependency as of its last check
+    pub status: DependencyStatus,
+    /// Whether the worker is unhealthy while the dependency is down
+    pub critical: bool,
+    /// When the dependency was last checked (Unix timestamp in seconds)
+    pub last_checked_at: Option<u64>,
+    /// Why the last check failed, if it did
+    pub error: Option<String>,
+}
+
+impl DependencyHealth {
+    const fn unknown(dependency: Dependency) -> Self {
+        Self {
+            name: dependency.name(),
+            status: DependencyStatus::Unknown,
+            critical: dependency.is_critical(),
+            last_checked_at: None,
+            error: None,
+        }
+    }
+}
+
+/// Latest health of every dependency, shared between the checks and the health endpoint
+#[derive(Debug, Clone)]
+pub struct HealthMonitor {
+    dependencies: Arc<RwLock<Vec<DependencyHealth>>>,
+}
+
+impl HealthMonitor {
+    /// Creates a monitor with every dependency not checked yet
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            dependencies: Arc::new(RwLock::new(
+                Dependency::ALL.map(DependencyHealth::unknown).to_vec(),
+            )),
+        }
+    }
+
+    /// Records the outcome of a check of `dependency`, made at `checked_at`
+    pub fn record(&self, dependency: Dependency, outcome: Result<(), String>, checked_at: u64) {
+        let mut dependencies = self
+            .dependencies
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        if let Some(health) = dependencies
+            .iter_mut()
+            .find(|health| health.name == dependency.name())
+        {
+            health.last_checked_at = Some(checked_at);
+            match outcome {
+                Ok(()) => {
+                    health.status = DependencyStatus::Up;
+                    health.error = None;
+                }
+                Err(e) => {
+                    health.status = DependencyStatus::Down;
+                    health.error = Some(e);
+                }
+            }
+        }
+    }
+
+    /// Latest health of `dependency`
+    #[must_use]
+    pub fn dependency(&self, dependency: Dependency) -> DependencyHealth {
+        self.dependencies
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .find(|health| health.name == dependency.name())
+            .cloned()
+            .unwrap_or_else(|| DependencyHealth::unknown(dependency))
+    }
+
+    /// Latest health of every dependency
+    #[must_use]
+    pub fn dependencies(&self) -> Vec<DependencyHealth> {
+        self.dependencies
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+}
+
+impl Default for HealthMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Aggregates the health of `dependencies`
+///
+/// The worker is unhealthy if any critical dependency is down, and degraded if only
+/// non-critical ones are. Dependencies that haven't been checked yet count as down, so the
+/// worker isn't reported healthy before its first checks complete.
+#[must_use]
+pub fn aggregate_status(dependencies: &[DependencyHealth]) -> HealthStatus {
+    let is_down = |health: &DependencyHealth| health.status != DependencyStatus::Up;
+
+    if dependencies
+        .iter()
+        .any(|health| health.critical && is_down(health))
+    {
+        HealthStatus::Unhealthy
+    } else if dependencies.iter().any(is_down) {
+        HealthStatus::Degraded
+    } else {
+        HealthStatus::Healthy
+    }
+}
+
+/// Checks the reachability of each dependency
+#[derive(Clone)]
+pub struct DependencyChecks {
+    aws_clients: AwsClients,
+    redis_client: RedisClient,
+    enclave_connection_details: pontifex::client::ConnectionDetails,
+    queue_url: String,
+    table_name: String,
+}
+
+impl DependencyChecks {
+    /// Creates the checks of the dependencies configured in `environment`
+    #[must_use]
+    pub fn new(
+        environment: &Environment,
+        aws_clients: AwsClients,
+        redis_client: RedisClient,
+        enclave_connection_details: pontifex::client::ConnectionDetails,
+    ) -> Self {
+        Self {
+            aws_clients,
+            redis_client,
+            enclave_connection_details,
+            queue_url: environment.notification_queue_config().queue_url,
+            table_name: environment.push_subscription_table_name(),
+        }
+    }
+
+    /// Checks `dependency` is reachable
+    async fn check(&self, dependency: Dependency) -> anyhow::Result<()> {
+        match dependency {
+            Dependency::Enclave => {
+                pontifex::client::send::<EnclaveHealthCheckRequest>(
+                    self.enclave_connection_details,
+                    &EnclaveHealthCheckRequest,
+                )
+                .await
+                .map_err(|e| anyhow::anyhow!("Transport error: {e}"))?
+                .map_err(|e| anyhow::anyhow!("Enclave error: {e:?}"))?;
+            }
+            Dependency::Redis => {
+                redis::cmd("PING")
+                    .query_async::<String>(&mut self.redis_client.conn())
+                    .await
+                    .context("Failed to ping Redis")?;
+            }
+            Dependency::Sqs => {
+                self.aws_clients
+                    .sqs
+                    .get_queue_attributes()
+                    .queue_url(&self.queue_url)
+                    .send()
+                    .await
+                    .with_context(|| format!("Failed to access queue {}", self.queue_url))?;
+            }
+            Dependency::DynamoDb => {
+                self.aws_clients
+                    .dynamodb
+                    .describe_table()
+                    .table_name(&self.table_name)
+                    .send()
+                    .await
+                    .with_context(|| format!("Failed to describe table {}", self.table_name))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks every dependency concurrently and records the outcomes in `monitor`
+    pub async fn check_all(&self, monitor: &HealthMonitor) {
+        join_all(Dependency::ALL.map(|dependency| async move {
+            let outcome = match timeout(CHECK_TIMEOUT, self.check(dependency)).await {
+                Ok(Ok(())) => Ok(()),
+                Ok(Err(e)) => Err(format!("{e:#}")),
+                Err(_) => Err(format!("timed out after {CHECK_TIMEOUT:?}")),
+            };
+
+            if let Err(e) = &outcome {
+                warn!(
+                    dependency = dependency.name(),
+                    "Dependency check failed: {e}"
+                );
+            }
+            monitor.record(dependency, outcome, unix_now());
+        }))
+        .await;
+    }
+}
+
+/// Checks every dependency every `interval` and records the outcomes in `monitor`, until
+/// `shutdown` is cancelled
+pub async fn run(
+    checks: DependencyChecks,
+    monitor: HealthMonitor,
+    interval: Duration,
+    shutdown: CancellationToken,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            () = shutdown.cancelled() => break,
+        }
+
+        checks.check_all(&monitor).await;
+    }
+
+    info!("Dependency health checks stopped");
+}
+
+/// Current Unix timestamp in seconds
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dependencies_start_unknown() {
+        let dependencies = HealthMonitor::new().dependencies();
+
+        assert_eq!(dependencies.len(), Dependency::ALL.len());
+        assert!(dependencies
+            .iter()
+            .all(|health| health.status == DependencyStatus::Unknown));
+        assert_eq!(aggregate_status(&dependencies), HealthStatus::Unhealthy);
+    }
+
+    #[test]
+    fn test_unchecked_dependencies_are_not_healthy() {
+        let monitor = HealthMonitor::new();
+        for dependency in Dependency::ALL {
+            if dependency.is_critical() {
+                monitor.record(dependency, Ok(()), 1);
+            }
+        }
+
+        // Only the non-critical Redis hasn't been checked yet
+        assert_eq!(
+            aggregate_status(&monitor.dependencies()),
+            HealthStatus::Degraded
+        );
+
+        monitor.record(Dependency::Redis, Ok(()), 2);
+        assert_eq!(
+            aggregate_status(&monitor.dependencies()),
+            HealthStatus::Healthy
+        );
+    }
+
+    #[test]
+    fn test_critical_dependency_down_is_unhealthy() {
+        let monitor = HealthMonitor::new();
+        for dependency in Dependency::ALL {
+            monitor.record(dependency, Ok(()), 1);
+        }
+        assert_eq!(
+            aggregate_status(&monitor.dependencies()),
+            HealthStatus::Healthy
+        );
+
+        monitor.record(Dependency::Redis, Err("connection refused".to_string()), 2);
+        assert_eq!(
+            aggregate_status(&monitor.dependencies()),
+            HealthStatus::Degraded
+        );
+
+        monitor.record(Dependency::Sqs, Err("access denied".to_string()), 3);
+        assert_eq!(
+            aggregate_status(&monitor.dependencies()),
+            HealthStatus::Unhealthy
+        );
+    }
+}
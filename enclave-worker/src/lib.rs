@@ -1,8 +1,14 @@
 #![deny(clippy::all, clippy::pedantic, clippy::nursery, dead_code)]
 
 pub mod cache;
+pub mod dogstatsd;
+pub mod enclave_metrics;
+pub mod health;
 pub mod notification_processor;
 pub mod redis;
+pub mod retry_budget;
 pub mod routes;
 pub mod server;
+pub mod shutdown;
 pub mod types;
+pub mod validate_config;
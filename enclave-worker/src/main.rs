@@ -2,49 +2,55 @@ use std::sync::Arc;
 
 use anyhow::Result;
 use backend_storage::{push_subscription::PushSubscriptionStorage, queue::NotificationQueue};
+use common_types::logging;
 use datadog_tracing::axum::shutdown_signal;
 use enclave_worker::{
-    cache::CacheManager, notification_processor::NotificationProcessor, redis::RedisClient, server,
-    types::Environment,
+    cache::CacheManager,
+    dogstatsd, enclave_metrics,
+    health::{self, DependencyChecks, HealthMonitor},
+    notification_processor::{NotificationProcessor, PontifexEnclave},
+    redis::RedisClient,
+    retry_budget::RetryBudget,
+    server,
+    shutdown::ShutdownPhases,
+    types::{AwsClients, Environment},
+    validate_config,
 };
-use metrics_exporter_dogstatsd::DogStatsDBuilder;
-use tokio_util::sync::CancellationToken;
 use tracing::info;
 
-use aws_sdk_dynamodb::Client as DynamoDbClient;
-use aws_sdk_sqs::Client as SqsClient;
-
 #[tokio::main]
 async fn main() -> Result<()> {
     let env = Environment::from_env();
 
-    info!("Starting Enclave Worker in {:?} environment", env);
+    // Initialize logging, JSON logs also set up OpenTelemetry with Datadog exporter
+    // The telemetry must be kept alive for the duration of the program
+    let mut telemetry = logging::init(env.log_format()?)?;
+
+    info!(
+        version = dogstatsd::VERSION,
+        rev = dogstatsd::GIT_REV,
+        "Starting Enclave Worker in {:?} environment",
+        env
+    );
 
-    // Initialize Datadog tracing
-    // This will set up OpenTelemetry with Datadog exporter
-    // The _guard must be kept alive for the duration of the program
-    let (_guard, tracer_shutdown) = datadog_tracing::init()?;
+    dogstatsd::init("world_chat.enclave_worker", env.metrics_addr());
 
-    DogStatsDBuilder::default()
-        .set_global_prefix("world_chat.enclave_worker")
-        .with_remote_address(env.metrics_addr())
-        .expect("failed to set remote address")
-        .install()
-        .expect("failed to install DogStatsD recorder");
+    // Connect to Redis while loading the AWS config, which is shared by all AWS clients
+    let (redis_client, aws_clients) =
+        tokio::join!(RedisClient::new(&env.redis_url()), AwsClients::load(&env));
 
     // Initialize notification queue
-    let sqs_client = Arc::new(SqsClient::new(&env.aws_config().await));
     let notification_queue = Arc::new(NotificationQueue::new(
-        sqs_client,
+        aws_clients.sqs.clone(),
         env.notification_queue_config(),
     ));
     info!("✅ Initialized notification queue");
 
     // Initialise Push Notification Subscription storage
-    let dynamodb_client = Arc::new(DynamoDbClient::new(&env.aws_config().await));
     let subscription_storage = Arc::new(PushSubscriptionStorage::new(
-        dynamodb_client,
+        aws_clients.dynamodb.clone(),
         env.push_subscription_table_name(),
+        env.push_subscription_push_id_index_name(),
     ));
     info!("✅ Initialized push subscription storage");
 
@@ -52,25 +58,46 @@ async fn main() -> Result<()> {
     let enclave_connection_details =
         pontifex::client::ConnectionDetails::new(env.enclave_cid(), env.enclave_port());
 
+    // With `--validate-config`, check every dependency and exit without serving traffic
+    if validate_config::is_requested() {
+        let report = validate_config::validate(
+            &env,
+            &aws_clients,
+            redis_client.as_ref(),
+            enclave_connection_details,
+        )
+        .await;
+        report.log();
+        telemetry.shutdown();
+
+        anyhow::ensure!(report.is_ok(), "Configuration validation failed");
+        return Ok(());
+    }
+
     // Initialize Redis client
-    let redis_client = RedisClient::new(&env.redis_url()).await?;
+    let redis_client = redis_client?;
+
+    let dependency_checks = DependencyChecks::new(
+        &env,
+        aws_clients,
+        redis_client.clone(),
+        enclave_connection_details,
+    );
+
     let cache_manager = CacheManager::new(redis_client);
     info!("✅ Initialized Cache Manager");
 
-    // Single shutdown token for everything
-    let shutdown_token = CancellationToken::new();
-    let signal_token = shutdown_token.clone();
-    tokio::spawn(async move {
-        shutdown_signal().await;
-        info!("Shutting down Enclave Worker...");
-        signal_token.cancel();
-    });
+    // Every enclave-directed retry draws from this budget, capping retries during an outage
+    let retry_budget = Arc::new(RetryBudget::default());
+
+    // Shutdown stops intake, drains processing and then stops the server
+    let shutdown_phases = ShutdownPhases::default();
 
     // Start notification processor
     let notification_processor_handle = {
         let queue = notification_queue.clone();
         let storage = subscription_storage.clone();
-        let token = shutdown_token.clone();
+        let token = shutdown_phases.intake.clone();
         let recipients_per_batch = env.recipients_per_batch();
 
         tokio::spawn(async move {
@@ -78,30 +105,55 @@ async fn main() -> Result<()> {
                 queue,
                 storage,
                 token,
-                enclave_connection_details,
+                PontifexEnclave::new(enclave_connection_details),
                 recipients_per_batch,
+                retry_budget,
             )
             .start()
             .await;
         })
     };
 
-    // Start HTTP server (blocks until shutdown)
-    let server_result = server::start(
+    // Periodically pull the enclave's metrics, it can't report them itself
+    tokio::spawn(enclave_metrics::run(
+        enclave_connection_details,
+        enclave_metrics::ENCLAVE_METRICS_PULL_INTERVAL,
+        shutdown_phases.intake.clone(),
+    ));
+
+    // Keep checking every dependency for the health endpoint until the server stops
+    let health_monitor = HealthMonitor::new();
+    tokio::spawn(health::run(
+        dependency_checks,
+        health_monitor.clone(),
+        health::HEALTH_CHECK_INTERVAL,
+        shutdown_phases.server.clone(),
+    ));
+
+    // Start HTTP server
+    let server_handle = tokio::spawn(server::start(
         env,
         notification_queue,
         subscription_storage,
         enclave_connection_details,
         cache_manager,
-        shutdown_token,
-    )
-    .await;
-
-    // Wait for processor to finish
-    notification_processor_handle.await.ok();
+        health_monitor,
+        shutdown_phases.server.clone(),
+    ));
 
-    // Ensure the tracer is properly shut down
-    tracer_shutdown.shutdown();
+    // Wait for the shutdown signal and shut down in order
+    let server_result = shutdown_phases
+        .run_until_shutdown(
+            shutdown_signal(),
+            notification_processor_handle,
+            server_handle,
+        )
+        .await
+        .map_err(anyhow::Error::from)
+        .and_then(|result| result);
+
+    // Ensure the tracer and logs are properly flushed
+    telemetry.shutdown();
 
     info!("✅ Enclave Worker shutdown complete");
 
@@ -0,0 +1,114 @@
+use std::io::ErrorKind;
+
+use backend_storage::queue::QueueError;
+use enclave_types::EnclaveError;
+use thiserror::Error;
+
+/// Errors that can occur while processing notifications
+///
+/// Variants identify the failure source so retry and dead-letter decisions can match on them.
+#[derive(Debug, Error)]
+pub enum ProcessError {
+    /// Failed to reach the enclave over pontifex
+    #[error("Transport error: {0}")]
+    Transport(#[from] pontifex::client::Error),
+    /// The enclave received the request but failed to process it
+    #[error("Enclave error: {0}")]
+    Enclave(#[from] EnclaveError),
+    /// Failed to poll or acknowledge messages on the notification queue
+    #[error("Queue error: {0}")]
+    Queue(#[source] QueueError),
+    /// Failed to serialize or deserialize a queue message
+    #[error("Serialization error: {0}")]
+    Serialization(#[source] serde_json::Error),
+    /// The enclave couldn't decrypt any recipient of a large enough batch
+    ///
+    /// A key rotation only strands some push IDs, so this rather points at the enclave using
+    /// the wrong key. The recipients are kept and the batch is retried.
+    #[error("Enclave couldn't decrypt any of the {0} recipients")]
+    NothingDecrypted(usize),
+}
+
+impl From<QueueError> for ProcessError {
+    fn from(err: QueueError) -> Self {
+        match err {
+            QueueError::SerializationError(e) => Self::Serialization(e),
+            other => Self::Queue(other),
+        }
+    }
+}
+
+impl ProcessError {
+    /// Whether the enclave lost its state, e.g. after a restart
+    ///
+    /// The failure is recoverable: requests succeed again once the enclave is re-initialized.
+    #[must_use]
+    pub const fn is_enclave_uninitialized(&self) -> bool {
+        matches!(
+            self,
+            Self::Enclave(EnclaveError::NotInitialized | EnclaveError::SecureModuleNotInitialized)
+        )
+    }
+
+    /// Whether the request never reached the enclave, so retrying can't deliver it twice
+    ///
+    /// Only failures to connect qualify. A transport error after connecting may have happened
+    /// once the enclave already received the request, e.g. while reading its response.
+    #[must_use]
+    pub fn is_unsent_transport_error(&self) -> bool {
+        match self {
+            Self::Transport(e) => is_connect_failure(e),
+            _ => false,
+        }
+    }
+}
+
+/// Whether the first I/O error in the source chain of `err` is a failure to connect
+fn is_connect_failure(err: &(dyn std::error::Error + 'static)) -> bool {
+    let mut source = Some(err);
+    while let Some(err) = source {
+        if let Some(io_error) = err.downcast_ref::<std::io::Error>() {
+            return matches!(
+                io_error.kind(),
+                ErrorKind::ConnectionRefused | ErrorKind::AddrNotAvailable
+            );
+        }
+        source = err.source();
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Error)]
+    #[error("transport failed")]
+    struct WrappedError(#[source] std::io::Error);
+
+    #[test]
+    fn test_connect_failures_are_detected_through_the_source_chain() {
+        let refused = WrappedError(std::io::Error::from(ErrorKind::ConnectionRefused));
+        assert!(is_connect_failure(&refused));
+
+        let unavailable = std::io::Error::from(ErrorKind::AddrNotAvailable);
+        assert!(is_connect_failure(&unavailable));
+    }
+
+    #[test]
+    fn test_failures_after_connecting_are_not_connect_failures() {
+        for kind in [
+            ErrorKind::ConnectionReset,
+            ErrorKind::BrokenPipe,
+            ErrorKind::UnexpectedEof,
+            ErrorKind::TimedOut,
+        ] {
+            assert!(!is_connect_failure(&WrappedError(std::io::Error::from(
+                kind
+            ))));
+        }
+
+        assert!(!is_connect_failure(&std::fmt::Error));
+    }
+}
@@ -1,26 +1,163 @@
-use anyhow::Context;
+mod error;
+
+pub use error::ProcessError;
+
 use backend_storage::{
+    ids::EncryptedPushId,
     push_subscription::PushSubscriptionStorage,
-    queue::{Notification, NotificationQueue, QueueMessage},
+    queue::{trace::parent_context, Notification, NotificationQueue, QueueMessage},
+};
+use enclave_types::{
+    EnclaveError, EnclaveNotificationRequest, EnclaveNotificationResponse, TraceContext,
 };
-use enclave_types::EnclaveNotificationRequest;
 use futures::future::join_all;
-use metrics::counter;
-use std::sync::Arc;
+use metrics::{counter, gauge};
+use opentelemetry::trace::TraceContextExt;
+use std::{future::Future, num::NonZeroUsize, slice::Chunks, sync::Arc, time::Duration};
 use tokio_util::sync::CancellationToken;
 use tracing::{error, info, instrument, warn};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+use crate::{
+    dogstatsd::{topic_bucket, Metric},
+    retry_budget::RetryBudget,
+};
+
+/// Delay applied after the first failed poll
+const INITIAL_POLL_BACKOFF_MS: u64 = 100;
+/// Upper bound for the delay between failed polls
+const MAX_POLL_BACKOFF_MS: u64 = 30_000;
+/// Number of consecutive failures after which errors are only logged periodically
+const SUSTAINED_FAILURE_THRESHOLD: u32 = 10;
+/// While failures are sustained, only every Nth failure is logged
+const SUSTAINED_FAILURE_LOG_INTERVAL: u32 = 20;
+/// Maximum number of retries of a batch that failed to reach the enclave
+const MAX_ENCLAVE_RETRIES: u32 = 2;
+/// Delay before a notification is retried when the enclave wasn't initialized, leaving time for
+/// the enclave initializer to run after an enclave restart
+const ENCLAVE_UNINITIALIZED_REQUEUE_DELAY_SECS: i32 = 30;
+/// Upper bound for the delay before re-enqueued recipients are retried, SQS's maximum delay
+const MAX_RECIPIENT_REQUEUE_DELAY_SECS: i32 = 900;
+/// Number of times the recipients of a notification are re-enqueued before they are dropped
+const MAX_RECIPIENT_REQUEUES: u32 = 5;
+/// Minimum number of recipients a batch needs for a batch without any decrypted recipient to
+/// point at the enclave's key. Smaller batches are often all stale push IDs, so theirs are pruned.
+const NOTHING_DECRYPTED_MIN_RECIPIENTS: usize = 5;
+
+/// What to do with a message once all of its batches were sent to the enclave
+#[derive(Debug)]
+enum Disposition {
+    /// At least one batch was delivered, the message is done
+    Ack,
+    /// The enclave lost its state before any batch was delivered, retry the whole message once
+    /// it is re-initialized
+    Requeue,
+    /// The enclave lost its state after some batches were delivered, only these recipients of
+    /// the failed batches are retried, as a new notification
+    RequeueRecipients(Vec<String>),
+    /// Every batch failed, propagate the first error and leave the message on the queue
+    Fail(ProcessError),
+}
+
+/// Tracks consecutive poll failures to back off exponentially and throttle error logs
+#[derive(Debug, Default)]
+struct PollBackoff {
+    consecutive_failures: u32,
+}
+
+impl PollBackoff {
+    /// Records a failed poll and returns how long to wait before polling again
+    const fn record_failure(&mut self) -> Duration {
+        self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+        self.delay()
+    }
 
-pub struct NotificationProcessor {
+    /// Resets the backoff after a successful poll, returning the length of the recovered streak
+    const fn record_success(&mut self) -> u32 {
+        let failures = self.consecutive_failures;
+        self.consecutive_failures = 0;
+        failures
+    }
+
+    /// Exponential delay for the current failure streak, capped at `MAX_POLL_BACKOFF_MS`
+    const fn delay(&self) -> Duration {
+        if self.consecutive_failures == 0 {
+            return Duration::ZERO;
+        }
+
+        // Cap the exponent so the shift can't overflow; the cap below kicks in long before
+        let exponent = if self.consecutive_failures > 16 {
+            15
+        } else {
+            self.consecutive_failures - 1
+        };
+        let millis = INITIAL_POLL_BACKOFF_MS.saturating_mul(1 << exponent);
+
+        if millis > MAX_POLL_BACKOFF_MS {
+            Duration::from_millis(MAX_POLL_BACKOFF_MS)
+        } else {
+            Duration::from_millis(millis)
+        }
+    }
+
+    /// Whether the current failure should be logged
+    ///
+    /// Every failure is logged until the streak becomes sustained, after which only every
+    /// `SUSTAINED_FAILURE_LOG_INTERVAL`th failure is, to avoid flooding logs during an outage.
+    const fn should_log(&self) -> bool {
+        self.consecutive_failures <= SUSTAINED_FAILURE_THRESHOLD
+            || self.consecutive_failures % SUSTAINED_FAILURE_LOG_INTERVAL == 0
+    }
+}
+
+/// The enclave notifications are sent to
+pub trait NotificationEnclave: Send + Sync {
+    /// Sends a batch of a notification to the enclave
+    fn send_notification(
+        &self,
+        request: &EnclaveNotificationRequest,
+    ) -> impl Future<Output = Result<EnclaveNotificationResponse, ProcessError>> + Send;
+}
+
+/// Secure enclave reached over pontifex
+#[derive(Debug, Clone, Copy)]
+pub struct PontifexEnclave {
+    connection_details: pontifex::client::ConnectionDetails,
+}
+
+impl PontifexEnclave {
+    /// Creates a client of the enclave listening at `connection_details`
+    #[must_use]
+    pub const fn new(connection_details: pontifex::client::ConnectionDetails) -> Self {
+        Self { connection_details }
+    }
+}
+
+impl NotificationEnclave for PontifexEnclave {
+    async fn send_notification(
+        &self,
+        request: &EnclaveNotificationRequest,
+    ) -> Result<EnclaveNotificationResponse, ProcessError> {
+        flatten_enclave_response(
+            pontifex::client::send::<EnclaveNotificationRequest>(self.connection_details, request)
+                .await,
+        )
+    }
+}
+
+pub struct NotificationProcessor<E = PontifexEnclave> {
     queue: Arc<NotificationQueue>,
-    #[allow(dead_code)] // Will be used for nitro enclave integration to delete subscriptions
+    /// Prunes the subscriptions of push IDs the enclave can't decrypt
     storage: Arc<PushSubscriptionStorage>,
-    pontifex_connection_details: pontifex::client::ConnectionDetails,
+    enclave: E,
     shutdown: CancellationToken,
     /// Maximum number of recipients per batch when sending to pontifex
-    recipients_per_batch: usize,
+    recipients_per_batch: NonZeroUsize,
+    /// Budget shared by all enclave retries
+    retry_budget: Arc<RetryBudget>,
 }
 
-impl NotificationProcessor {
+impl<E: NotificationEnclave> NotificationProcessor<E> {
     /// Creates a new `NotificationProcessor`
     ///
     /// # Panics
@@ -31,33 +168,60 @@ impl NotificationProcessor {
         queue: Arc<NotificationQueue>,
         storage: Arc<PushSubscriptionStorage>,
         shutdown: CancellationToken,
-        pontifex_connection_details: pontifex::client::ConnectionDetails,
-        recipients_per_batch: usize,
+        enclave: E,
+        recipients_per_batch: NonZeroUsize,
+        retry_budget: Arc<RetryBudget>,
     ) -> Self {
         Self {
             queue,
             storage,
-            pontifex_connection_details,
+            enclave,
             shutdown,
             recipients_per_batch,
+            retry_budget,
         }
     }
 
     pub async fn start(self) {
-        info!("Starting NotificationProcessor");
+        info!(
+            recipients_per_batch = %self.recipients_per_batch,
+            "Starting NotificationProcessor"
+        );
+        gauge!(Metric::NotificationRecipientsPerBatch.name()).set(
+            u32::try_from(self.recipients_per_batch.get()).map_or(f64::from(u32::MAX), f64::from),
+        );
+
+        let mut backoff = PollBackoff::default();
 
-        // Poll queue until shutdown
+        // Poll queue until shutdown. Only waiting for messages is interrupted by shutdown,
+        // messages already received are processed before returning.
         while !self.shutdown.is_cancelled() {
-            tokio::select! {
-                result = self.poll_once() => match result {
-                    Ok(()) => {}
-                    Err(e) => {
-                        error!(error = ?e, "Failed to poll messages");
+            match self.poll_once().await {
+                Ok(()) => {
+                    let recovered_from = backoff.record_success();
+                    if recovered_from > SUSTAINED_FAILURE_THRESHOLD {
+                        info!(
+                            consecutive_failures = recovered_from,
+                            "Polling recovered after sustained failures"
+                        );
+                    }
+                }
+                Err(e) => {
+                    let delay = backoff.record_failure();
+                    if backoff.should_log() {
+                        error!(
+                            error = ?e,
+                            consecutive_failures = backoff.consecutive_failures,
+                            retry_in = ?delay,
+                            "Failed to poll messages"
+                        );
+                    }
+
+                    // Wait before the next poll, unless we're asked to shut down
+                    tokio::select! {
+                        () = tokio::time::sleep(delay) => {}
+                        () = self.shutdown.cancelled() => {}
                     }
-                },
-                () = self.shutdown.cancelled() => {
-                    info!("Queue poller shutting down");
-                    break;
                 }
             }
         }
@@ -65,12 +229,14 @@ impl NotificationProcessor {
         info!("NotificationProcessor shutdown complete");
     }
 
-    async fn poll_once(&self) -> anyhow::Result<()> {
-        let messages = self
-            .queue
-            .poll_messages()
-            .await
-            .context("Failed to poll messages")?;
+    async fn poll_once(&self) -> Result<(), ProcessError> {
+        let messages = tokio::select! {
+            messages = self.queue.poll_messages() => messages?,
+            () = self.shutdown.cancelled() => {
+                info!("Queue poller shutting down");
+                return Ok(());
+            }
+        };
 
         // TODO: Make these requests in parallel to improve performance
         for message in messages {
@@ -82,7 +248,15 @@ impl NotificationProcessor {
     }
 
     #[instrument(skip(self, message), fields(message_id = %message.message_id))]
-    async fn process_and_ack(&self, message: QueueMessage<Notification>) -> anyhow::Result<()> {
+    async fn process_and_ack(
+        &self,
+        message: QueueMessage<Notification>,
+    ) -> Result<(), ProcessError> {
+        // Continue the trace of the notification worker that enqueued the message
+        if let Some(parent) = message.trace_parent.as_deref().and_then(parent_context) {
+            tracing::Span::current().set_parent(parent);
+        }
+
         let notification = message.body;
         let receipt_handle = message.receipt_handle;
 
@@ -90,73 +264,100 @@ impl NotificationProcessor {
         if notification.subscribed_encrypted_push_ids.is_empty() {
             warn!("No recipients found for notification, acknowledging message");
             self.queue.ack_message(&receipt_handle).await?;
-            counter!("notification_delivered").increment(1);
+            counter!(
+                Metric::NotificationDelivered.name(),
+                "topic_bucket" => topic_bucket(&notification.topic)
+            )
+            .increment(1);
             return Ok(());
         }
 
         // Split recipients into batches
-        let batches = notification
-            .subscribed_encrypted_push_ids
-            .chunks(self.recipients_per_batch);
+        let batches = split_into_batches(
+            &notification.subscribed_encrypted_push_ids,
+            self.recipients_per_batch,
+        );
+
+        // Let the enclave continue this trace across the pontifex boundary
+        let trace_context = current_trace_context();
 
         // Create futures for each batch
         let batch_futures = batches
             .into_iter()
             .enumerate()
             .map(|(batch_idx, batch_recipients)| {
-                let topic = notification.topic.clone();
-                let message = notification.encrypted_message_base64.clone();
-                let connection_details = self.pontifex_connection_details;
+                let request = EnclaveNotificationRequest {
+                    topic: notification.topic.clone(),
+                    subscribed_encrypted_push_ids: batch_recipients.to_vec(),
+                    encrypted_message_base64: notification.encrypted_message_base64.clone(),
+                    trace_context: trace_context.clone(),
+                };
 
                 async move {
-                    let result = pontifex::client::send::<EnclaveNotificationRequest>(
-                        connection_details,
-                        &EnclaveNotificationRequest {
-                            topic,
-                            subscribed_encrypted_push_ids: batch_recipients.to_vec(),
-                            encrypted_message_base64: message,
-                        },
-                    )
-                    .await
-                    .map_err(|e| anyhow::anyhow!("Transport error: {}", e))
-                    .and_then(|inner| inner.map_err(|e| anyhow::anyhow!("Enclave error: {:?}", e)));
-
-                    (batch_idx, batch_recipients.len(), result)
+                    // Only requests that never reached the enclave are retried, anything else may
+                    // already have been delivered
+                    let result = self
+                        .retry_budget
+                        .retry(
+                            MAX_ENCLAVE_RETRIES,
+                            ProcessError::is_unsent_transport_error,
+                            || self.enclave.send_notification(&request),
+                        )
+                        .await;
+
+                    (batch_idx, request.subscribed_encrypted_push_ids, result)
                 }
             });
 
         // Execute all batches in parallel
         let results = join_all(batch_futures).await;
 
-        // Process results and count failures
+        // Process results and collect failures
         let total_batches = results.len();
-        let mut failed_batches = 0;
+        let (failures, undecryptable_push_ids) = partition_batch_results(results);
+        let failed_batches = failures.len();
 
-        for (batch_idx, recipient_count, result) in results {
-            match result {
-                Ok(()) => {
-                    info!(
-                        batch_idx,
-                        recipient_count, "Successfully delivered notification batch"
-                    );
-                }
-                Err(e) => {
-                    failed_batches += 1;
+        // Decryption failures are permanent, so they don't fail the batch: the recipients are
+        // pruned instead of retrying a notification they can never receive. Only batches where
+        // the enclave decrypted other recipients report them, so a wrong key prunes nothing.
+        self.prune_undecryptable(&undecryptable_push_ids).await;
+
+        match disposition(total_batches, failures) {
+            Disposition::Ack => {}
+            Disposition::RequeueRecipients(encrypted_push_ids) => {
+                // Requeueing the whole message would notify the delivered recipients twice
+                warn!(
+                    failed_batches,
+                    total_batches,
+                    "Enclave not initialized, re-enqueueing the recipients of failed batches"
+                );
+                self.requeue_recipients(&notification, encrypted_push_ids)
+                    .await?;
+            }
+            Disposition::Requeue => {
+                // Requeueing retries the enclave, so it draws from the retry budget. Once it is
+                // exhausted the message is left to its visibility timeout instead.
+                if !self.retry_budget.try_acquire() {
                     warn!(
-                        batch_idx,
-                        recipient_count,
-                        error = ?e,
-                        "Transport error while delivering notification batch"
+                        failed_batches,
+                        total_batches,
+                        "Enclave not initialized, leaving notification to its visibility timeout"
                     );
+                    return Ok(());
                 }
+                // Not acking would also retry the message, but only after the visibility timeout
+                warn!(
+                    failed_batches,
+                    total_batches,
+                    retry_in_secs = ENCLAVE_UNINITIALIZED_REQUEUE_DELAY_SECS,
+                    "Enclave not initialized, requeueing notification"
+                );
+                self.queue
+                    .requeue_message(&receipt_handle, ENCLAVE_UNINITIALIZED_REQUEUE_DELAY_SECS)
+                    .await?;
+                return Ok(());
             }
-        }
-
-        // If all batches failed, propagate the error
-        if failed_batches == total_batches {
-            return Err(anyhow::anyhow!(
-                "All notification batches failed to deliver"
-            ));
+            Disposition::Fail(e) => return Err(e),
         }
 
         // Log if we had partial failures
@@ -168,8 +369,509 @@ impl NotificationProcessor {
         self.queue.ack_message(&receipt_handle).await?;
 
         // Increment the counter for delivered notifications (even for partial success)
-        counter!("notification_delivered").increment(1);
+        counter!(
+            Metric::NotificationDelivered.name(),
+            "topic_bucket" => topic_bucket(&notification.topic)
+        )
+        .increment(1);
 
         Ok(())
     }
+
+    /// Enqueues `notification` again for only `encrypted_push_ids`, after a delay growing with
+    /// each attempt
+    ///
+    /// The recipients are dropped once they were re-enqueued `MAX_RECIPIENT_REQUEUES` times, so
+    /// an enclave that never gets re-initialized doesn't keep them cycling through the queue.
+    /// Each re-enqueue draws from the retry budget, past which the longest delay is used.
+    async fn requeue_recipients(
+        &self,
+        notification: &Notification,
+        encrypted_push_ids: Vec<String>,
+    ) -> Result<(), ProcessError> {
+        let Some(delay_secs) = recipient_requeue_delay_secs(notification.requeue_attempt) else {
+            error!(
+                dropped_recipients = encrypted_push_ids.len(),
+                requeue_attempt = notification.requeue_attempt,
+                "Dropping recipients re-enqueued too many times"
+            );
+            counter!(Metric::RequeuedRecipientsDropped.name())
+                .increment(u64::try_from(encrypted_push_ids.len()).unwrap_or(u64::MAX));
+            return Ok(());
+        };
+        // Other recipients were delivered, so the message can't be left on the queue instead
+        let delay_secs = if self.retry_budget.try_acquire() {
+            delay_secs
+        } else {
+            MAX_RECIPIENT_REQUEUE_DELAY_SECS
+        };
+
+        info!(
+            requeued_recipients = encrypted_push_ids.len(),
+            requeue_attempt = notification.requeue_attempt,
+            retry_in_secs = delay_secs,
+            "Re-enqueueing recipients"
+        );
+        let requeued = Notification {
+            topic: notification.topic.clone(),
+            subscribed_encrypted_push_ids: encrypted_push_ids,
+            encrypted_message_base64: notification.encrypted_message_base64.clone(),
+            requeue_attempt: notification.requeue_attempt + 1,
+        };
+        self.queue
+            .send_message_delayed(&requeued, delay_secs)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Deletes every subscription of push IDs the enclave can't decrypt
+    ///
+    /// Failures are only logged, the push IDs are reported again by the next notification.
+    async fn prune_undecryptable(&self, encrypted_push_ids: &[String]) {
+        for encrypted_push_id in encrypted_push_ids {
+            match self
+                .storage
+                .delete_all_by_push_id(&EncryptedPushId::from(encrypted_push_id.as_str()))
+                .await
+            {
+                Ok(pruned) => {
+                    warn!(pruned, "Pruned subscriptions of undecryptable push ID");
+                    counter!(Metric::UndecryptableSubscriptionsPruned.name())
+                        .increment(u64::try_from(pruned).unwrap_or(u64::MAX));
+                }
+                Err(e) => error!("Failed to prune subscriptions of undecryptable push ID: {e}"),
+            }
+        }
+    }
+}
+
+/// Result of a batch: its index, recipients and the enclave's response
+type BatchResult = (
+    usize,
+    Vec<String>,
+    Result<EnclaveNotificationResponse, ProcessError>,
+);
+
+/// A batch the enclave failed to deliver
+#[derive(Debug)]
+struct BatchFailure {
+    /// Encrypted push IDs of the batch's recipients
+    recipients: Vec<String>,
+    error: ProcessError,
+}
+
+/// Splits batch results into the failed batches and the push IDs the enclave couldn't decrypt
+/// in delivered batches
+///
+/// A batch of at least `NOTHING_DECRYPTED_MIN_RECIPIENTS` where the enclave decrypted none of the
+/// recipients is failed instead, as that points at the enclave rather than at the push IDs.
+/// Push IDs that aren't valid hex are a validation failure and are only reported.
+fn partition_batch_results(results: Vec<BatchResult>) -> (Vec<BatchFailure>, Vec<String>) {
+    let mut failures = Vec::new();
+    let mut undecryptable_push_ids = Vec::new();
+
+    for (batch_idx, recipients, result) in results {
+        let result = result.and_then(|response| {
+            let skipped = response.undecryptable_push_ids.len() + response.invalid_push_ids.len();
+            if response.undecryptable_push_ids.is_empty()
+                || skipped < recipients.len()
+                || recipients.len() < NOTHING_DECRYPTED_MIN_RECIPIENTS
+            {
+                Ok(response)
+            } else {
+                Err(ProcessError::NothingDecrypted(recipients.len()))
+            }
+        });
+
+        match result {
+            Ok(response) => {
+                if !response.invalid_push_ids.is_empty() {
+                    error!(
+                        batch_idx,
+                        invalid = response.invalid_push_ids.len(),
+                        "Skipped recipients whose push ID isn't valid hex"
+                    );
+                    counter!(Metric::InvalidPushIds.name())
+                        .increment(u64::try_from(response.invalid_push_ids.len()).unwrap_or(0));
+                }
+                info!(
+                    batch_idx,
+                    recipient_count = recipients.len(),
+                    undecryptable = response.undecryptable_push_ids.len(),
+                    "Successfully delivered notification batch"
+                );
+                undecryptable_push_ids.extend(response.undecryptable_push_ids);
+            }
+            Err(error) => {
+                warn!(
+                    batch_idx,
+                    recipient_count = recipients.len(),
+                    error = ?error,
+                    "Failed to deliver notification batch"
+                );
+                failures.push(BatchFailure { recipients, error });
+            }
+        }
+    }
+
+    (failures, undecryptable_push_ids)
+}
+
+/// Decides what to do with a message given the `failures` of its `total_batches` batches
+///
+/// Batches that hit an uninitialized enclave are retried once it is re-initialized: the whole
+/// message if no batch was delivered, otherwise only the recipients of those batches, so
+/// recipients of delivered batches aren't notified twice.
+fn disposition(total_batches: usize, failures: Vec<BatchFailure>) -> Disposition {
+    let all_failed = failures.len() == total_batches;
+    let (uninitialized, failures): (Vec<_>, Vec<_>) = failures
+        .into_iter()
+        .partition(|failure| failure.error.is_enclave_uninitialized());
+
+    if !uninitialized.is_empty() {
+        if all_failed {
+            return Disposition::Requeue;
+        }
+        return Disposition::RequeueRecipients(
+            uninitialized
+                .into_iter()
+                .flat_map(|failure| failure.recipients)
+                .collect(),
+        );
+    }
+
+    // If all batches failed, propagate the first error so callers can act on its source
+    if all_failed {
+        if let Some(failure) = failures.into_iter().next() {
+            return Disposition::Fail(failure.error);
+        }
+    }
+
+    Disposition::Ack
+}
+
+/// Delay before recipients already re-enqueued `requeue_attempt` times are retried again,
+/// `None` once they were re-enqueued `MAX_RECIPIENT_REQUEUES` times
+///
+/// Doubles from `ENCLAVE_UNINITIALIZED_REQUEUE_DELAY_SECS` with every attempt, up to
+/// `MAX_RECIPIENT_REQUEUE_DELAY_SECS`.
+fn recipient_requeue_delay_secs(requeue_attempt: u32) -> Option<i32> {
+    (requeue_attempt < MAX_RECIPIENT_REQUEUES).then(|| {
+        ENCLAVE_UNINITIALIZED_REQUEUE_DELAY_SECS
+            .saturating_mul(1 << requeue_attempt.min(16))
+            .min(MAX_RECIPIENT_REQUEUE_DELAY_SECS)
+    })
+}
+
+/// Splits recipients into batches of at most `batch_size` recipients each
+fn split_into_batches(recipients: &[String], batch_size: NonZeroUsize) -> Chunks<'_, String> {
+    recipients.chunks(batch_size.get())
+}
+
+/// Trace context of the current span, `None` when it isn't part of a trace
+fn current_trace_context() -> Option<TraceContext> {
+    let context = tracing::Span::current().context();
+    let span = context.span();
+    let span_context = span.span_context();
+
+    span_context.is_valid().then(|| TraceContext {
+        trace_id: span_context.trace_id().to_string(),
+        parent_span_id: span_context.span_id().to_string(),
+    })
+}
+
+/// Flattens a pontifex response into a single result, keeping the failure source
+fn flatten_enclave_response<T>(
+    response: Result<Result<T, EnclaveError>, pontifex::client::Error>,
+) -> Result<T, ProcessError> {
+    Ok(response??)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_sdk_sqs::error::SdkError;
+    use backend_storage::queue::QueueError;
+
+    #[test]
+    fn test_consecutive_failures_increase_delay() {
+        let mut backoff = PollBackoff::default();
+
+        let first = backoff.record_failure();
+        let second = backoff.record_failure();
+        let third = backoff.record_failure();
+
+        assert_eq!(first, Duration::from_millis(INITIAL_POLL_BACKOFF_MS));
+        assert!(second > first);
+        assert!(third > second);
+    }
+
+    #[test]
+    fn test_delay_is_capped() {
+        let mut backoff = PollBackoff::default();
+
+        for _ in 0..100 {
+            assert!(backoff.record_failure() <= Duration::from_millis(MAX_POLL_BACKOFF_MS));
+        }
+        assert_eq!(backoff.delay(), Duration::from_millis(MAX_POLL_BACKOFF_MS));
+    }
+
+    #[test]
+    fn test_success_resets_delay() {
+        let mut backoff = PollBackoff::default();
+        backoff.record_failure();
+        backoff.record_failure();
+
+        assert_eq!(backoff.record_success(), 2);
+        assert_eq!(backoff.delay(), Duration::ZERO);
+        assert_eq!(
+            backoff.record_failure(),
+            Duration::from_millis(INITIAL_POLL_BACKOFF_MS)
+        );
+    }
+
+    #[test]
+    fn test_sustained_failures_log_at_reduced_frequency() {
+        let mut backoff = PollBackoff::default();
+        let mut logged = 0;
+
+        for _ in 0..(SUSTAINED_FAILURE_THRESHOLD + SUSTAINED_FAILURE_LOG_INTERVAL * 2) {
+            backoff.record_failure();
+            if backoff.should_log() {
+                logged += 1;
+            }
+        }
+
+        // Every failure up to the threshold, then one per interval
+        assert_eq!(logged, SUSTAINED_FAILURE_THRESHOLD + 2);
+    }
+
+    #[test]
+    fn test_enclave_failure_maps_to_enclave_variant() {
+        let response: Result<Result<(), EnclaveError>, pontifex::client::Error> =
+            Ok(Err(EnclaveError::NotInitialized));
+
+        assert!(matches!(
+            flatten_enclave_response(response),
+            Err(ProcessError::Enclave(EnclaveError::NotInitialized))
+        ));
+    }
+
+    #[test]
+    fn test_enclave_success_is_ok() {
+        let response: Result<Result<(), EnclaveError>, pontifex::client::Error> = Ok(Ok(()));
+
+        assert!(flatten_enclave_response(response).is_ok());
+    }
+
+    #[test]
+    fn test_queue_failure_maps_to_queue_variant() {
+        let err = QueueError::ReceiveMessage(SdkError::timeout_error("timed out"));
+
+        assert!(matches!(
+            ProcessError::from(err),
+            ProcessError::Queue(QueueError::ReceiveMessage(_))
+        ));
+    }
+
+    #[test]
+    fn test_queue_serialization_failure_maps_to_serialization_variant() {
+        let json_err = serde_json::from_str::<Notification>("not json").unwrap_err();
+        let err = QueueError::SerializationError(json_err);
+
+        assert!(matches!(
+            ProcessError::from(err),
+            ProcessError::Serialization(_)
+        ));
+    }
+
+    fn failure(recipients: &[&str], error: EnclaveError) -> BatchFailure {
+        BatchFailure {
+            recipients: recipients.iter().map(ToString::to_string).collect(),
+            error: ProcessError::Enclave(error),
+        }
+    }
+
+    #[test]
+    fn test_uninitialized_enclave_requeues_whole_message_when_nothing_was_delivered() {
+        for error in [
+            EnclaveError::NotInitialized,
+            EnclaveError::SecureModuleNotInitialized,
+        ] {
+            let failures = vec![failure(&["push_id"], error.clone())];
+            assert!(matches!(disposition(1, failures), Disposition::Requeue));
+
+            let failures = vec![
+                failure(&["push_id_0"], error),
+                failure(&["push_id_1"], EnclaveError::KeyPairCreationFailed),
+            ];
+            assert!(matches!(disposition(2, failures), Disposition::Requeue));
+        }
+    }
+
+    #[test]
+    fn test_uninitialized_enclave_requeues_only_failed_recipients() {
+        for error in [
+            EnclaveError::NotInitialized,
+            EnclaveError::SecureModuleNotInitialized,
+        ] {
+            // Another batch was delivered, its recipients must not be notified again
+            let failures = vec![
+                failure(&["push_id_0", "push_id_1"], error.clone()),
+                failure(&["push_id_2"], EnclaveError::KeyPairCreationFailed),
+                failure(&["push_id_3"], error),
+            ];
+
+            let Disposition::RequeueRecipients(recipients) = disposition(4, failures) else {
+                panic!("Expected only the failed recipients to be requeued");
+            };
+            assert_eq!(recipients, vec!["push_id_0", "push_id_1", "push_id_3"]);
+        }
+    }
+
+    #[test]
+    fn test_recipient_requeue_delay_backs_off_until_recipients_are_dropped() {
+        assert_eq!(
+            recipient_requeue_delay_secs(0),
+            Some(ENCLAVE_UNINITIALIZED_REQUEUE_DELAY_SECS)
+        );
+        assert_eq!(
+            recipient_requeue_delay_secs(1),
+            Some(2 * ENCLAVE_UNINITIALIZED_REQUEUE_DELAY_SECS)
+        );
+        for attempt in 0..MAX_RECIPIENT_REQUEUES {
+            let delay = recipient_requeue_delay_secs(attempt).unwrap();
+            assert!(delay <= MAX_RECIPIENT_REQUEUE_DELAY_SECS);
+        }
+        assert_eq!(recipient_requeue_delay_secs(MAX_RECIPIENT_REQUEUES), None);
+        assert_eq!(recipient_requeue_delay_secs(u32::MAX), None);
+    }
+
+    #[test]
+    fn test_other_enclave_errors_keep_their_disposition() {
+        assert!(matches!(disposition(2, Vec::new()), Disposition::Ack));
+
+        let failures = vec![failure(&["push_id"], EnclaveError::KeyPairCreationFailed)];
+        assert!(matches!(disposition(2, failures), Disposition::Ack));
+
+        let failures = vec![failure(&["push_id"], EnclaveError::KeyPairCreationFailed)];
+        assert!(matches!(
+            disposition(1, failures),
+            Disposition::Fail(ProcessError::Enclave(EnclaveError::KeyPairCreationFailed))
+        ));
+    }
+
+    #[test]
+    fn test_custom_batch_size_chunks_recipients() {
+        let recipients: Vec<String> = (0..7).map(|i| format!("push_id_{i}")).collect();
+        let batch_size = NonZeroUsize::new(3).unwrap();
+
+        let batches: Vec<&[String]> = split_into_batches(&recipients, batch_size).collect();
+
+        assert_eq!(
+            batches.iter().map(|b| b.len()).collect::<Vec<_>>(),
+            vec![3, 3, 1]
+        );
+        assert_eq!(batches.concat(), recipients);
+    }
+
+    #[test]
+    fn test_undecryptable_push_ids_are_pruned_without_failing_the_batch() {
+        let results = vec![
+            (
+                0,
+                vec!["push_id".to_string(), "rotated_push_id".to_string()],
+                Ok(EnclaveNotificationResponse {
+                    undecryptable_push_ids: vec!["rotated_push_id".to_string()],
+                    ..Default::default()
+                }),
+            ),
+            (
+                1,
+                vec!["other_push_id".to_string()],
+                Ok(EnclaveNotificationResponse::default()),
+            ),
+        ];
+
+        let (failures, undecryptable_push_ids) = partition_batch_results(results);
+
+        assert_eq!(undecryptable_push_ids, vec!["rotated_push_id".to_string()]);
+        assert!(matches!(disposition(2, failures), Disposition::Ack));
+    }
+
+    #[test]
+    fn test_batches_without_any_decrypted_recipient_fail_instead_of_pruning() {
+        let recipients: Vec<String> = (0..NOTHING_DECRYPTED_MIN_RECIPIENTS)
+            .map(|i| format!("push_id_{i}"))
+            .collect();
+        let results = vec![(
+            0,
+            recipients.clone(),
+            Ok(EnclaveNotificationResponse {
+                undecryptable_push_ids: recipients[1..].to_vec(),
+                invalid_push_ids: recipients[..1].to_vec(),
+            }),
+        )];
+
+        let (failures, undecryptable_push_ids) = partition_batch_results(results);
+
+        assert!(undecryptable_push_ids.is_empty());
+        assert!(matches!(
+            disposition(1, failures),
+            Disposition::Fail(ProcessError::NothingDecrypted(
+                NOTHING_DECRYPTED_MIN_RECIPIENTS
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_undecryptable_single_recipient_is_pruned() {
+        let results = vec![(
+            0,
+            vec!["rotated_push_id".to_string()],
+            Ok(EnclaveNotificationResponse {
+                undecryptable_push_ids: vec!["rotated_push_id".to_string()],
+                ..Default::default()
+            }),
+        )];
+
+        let (failures, undecryptable_push_ids) = partition_batch_results(results);
+
+        assert_eq!(undecryptable_push_ids, vec!["rotated_push_id".to_string()]);
+        assert!(matches!(disposition(1, failures), Disposition::Ack));
+    }
+
+    #[test]
+    fn test_invalid_push_ids_are_not_pruned() {
+        let results = vec![(
+            0,
+            vec!["push_id".to_string(), "not hex".to_string()],
+            Ok(EnclaveNotificationResponse {
+                invalid_push_ids: vec!["not hex".to_string()],
+                ..Default::default()
+            }),
+        )];
+
+        let (failures, undecryptable_push_ids) = partition_batch_results(results);
+
+        assert!(undecryptable_push_ids.is_empty());
+        assert!(matches!(disposition(1, failures), Disposition::Ack));
+    }
+
+    #[test]
+    fn test_failed_batches_are_not_pruned() {
+        let results = vec![(
+            0,
+            vec!["push_id".to_string()],
+            Err(ProcessError::Enclave(EnclaveError::BrazeRequestFailed(
+                "unavailable".to_string(),
+            ))),
+        )];
+
+        let (failures, undecryptable_push_ids) = partition_batch_results(results);
+
+        assert!(undecryptable_push_ids.is_empty());
+        assert!(matches!(disposition(1, failures), Disposition::Fail(_)));
+    }
 }
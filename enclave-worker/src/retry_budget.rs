@@ -0,0 +1,251 @@
+//! Retry budget shared by every enclave-directed call
+//!
+//! Each retry takes a token from a bucket that refills at a fixed rate, capping the aggregate
+//! retry volume across callers. During an enclave outage the bucket runs dry and calls fail fast
+//! instead of piling retries onto the enclave. Retries are also spaced out with an exponential
+//! backoff so a struggling enclave isn't hit again right away.
+//!
+//! The budget covers every enclave retry of the worker: batches that failed to reach the enclave
+//! and notifications re-enqueued because the enclave wasn't initialized. Other processes can't
+//! share it and bound their own retries: the backend's retries of the worker API are capped by
+//! a time budget and the enclave initializer by a fixed number of attempts.
+
+use std::{
+    future::Future,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use metrics::counter;
+use tracing::warn;
+
+use crate::dogstatsd::Metric;
+
+/// Default number of retries that can be made in a burst
+pub const DEFAULT_RETRY_BUDGET_CAPACITY: u32 = 20;
+/// Default interval at which a retry token is returned to the budget
+pub const DEFAULT_RETRY_BUDGET_REFILL_INTERVAL: Duration = Duration::from_millis(500);
+/// Default delay before the first retry, doubled for every subsequent one
+pub const DEFAULT_RETRY_INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+/// Upper bound for the delay between retries
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(5);
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: u32,
+    last_refill: Instant,
+}
+
+/// Token bucket limiting retries of enclave calls
+///
+/// Share a single instance (behind an `Arc`) between all callers so the cap is global.
+#[derive(Debug)]
+pub struct RetryBudget {
+    capacity: u32,
+    refill_interval: Duration,
+    initial_backoff: Duration,
+    bucket: Mutex<Bucket>,
+}
+
+impl Default for RetryBudget {
+    fn default() -> Self {
+        Self::new(
+            DEFAULT_RETRY_BUDGET_CAPACITY,
+            DEFAULT_RETRY_BUDGET_REFILL_INTERVAL,
+        )
+    }
+}
+
+impl RetryBudget {
+    /// Creates a full budget
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - Maximum number of retries that can be made in a burst
+    /// * `refill_interval` - Interval at which a single token is returned to the budget
+    #[must_use]
+    pub fn new(capacity: u32, refill_interval: Duration) -> Self {
+        Self {
+            capacity,
+            refill_interval,
+            initial_backoff: DEFAULT_RETRY_INITIAL_BACKOFF,
+            bucket: Mutex::new(Bucket {
+                tokens: capacity,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    /// Sets the delay before the first retry, doubled for every subsequent one
+    #[must_use]
+    pub const fn with_initial_backoff(mut self, initial_backoff: Duration) -> Self {
+        self.initial_backoff = initial_backoff;
+        self
+    }
+
+    /// Delay before the retry following `retries` earlier ones, capped at `MAX_RETRY_BACKOFF`
+    fn backoff(&self, retries: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(1 << retries.min(16))
+            .min(MAX_RETRY_BACKOFF)
+    }
+
+    /// Takes a token for a single retry
+    ///
+    /// Returns `false` if the budget is exhausted, in which case the caller must not retry.
+    ///
+    /// # Panics
+    ///
+    /// If the lock is poisoned
+    pub fn try_acquire(&self) -> bool {
+        let acquired = self.try_acquire_at(Instant::now());
+        if !acquired {
+            counter!(Metric::EnclaveRetryBudgetExhausted.name()).increment(1);
+        }
+        acquired
+    }
+
+    fn try_acquire_at(&self, now: Instant) -> bool {
+        let mut bucket = self.bucket.lock().expect("retry budget lock poisoned");
+
+        // Return the tokens earned since the last refill, carrying over the partial interval
+        let elapsed = now.saturating_duration_since(bucket.last_refill);
+        let earned = u32::try_from(elapsed.as_nanos() / self.refill_interval.as_nanos().max(1))
+            .unwrap_or(u32::MAX);
+        if earned > 0 {
+            bucket.tokens = bucket.tokens.saturating_add(earned).min(self.capacity);
+            bucket.last_refill = bucket
+                .last_refill
+                .checked_add(self.refill_interval.saturating_mul(earned))
+                .unwrap_or(now);
+        }
+
+        if bucket.tokens == 0 {
+            return false;
+        }
+        bucket.tokens -= 1;
+        true
+    }
+
+    /// Runs `call`, retrying up to `max_retries` times while `is_retryable` holds for the error
+    ///
+    /// Every retry draws from the budget and waits for an exponentially growing backoff first.
+    /// Once the budget is exhausted the last error is returned immediately.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the last attempt
+    pub async fn retry<T, E, F, Fut>(
+        &self,
+        max_retries: u32,
+        is_retryable: impl Fn(&E) -> bool,
+        mut call: F,
+    ) -> Result<T, E>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut retries = 0;
+        loop {
+            match call().await {
+                Ok(value) => return Ok(value),
+                Err(e) if retries < max_retries && is_retryable(&e) => {
+                    if !self.try_acquire() {
+                        warn!("Enclave retry budget exhausted, failing fast");
+                        return Err(e);
+                    }
+                    tokio::time::sleep(self.backoff(retries)).await;
+                    retries += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[tokio::test]
+    async fn test_burst_of_failures_exhausts_budget() {
+        let budget =
+            RetryBudget::new(3, Duration::from_secs(3600)).with_initial_backoff(Duration::ZERO);
+        let attempts = AtomicU32::new(0);
+        let failing_call = || async {
+            attempts.fetch_add(1, Ordering::Relaxed);
+            Err::<(), _>("enclave unavailable")
+        };
+
+        // The first call retries twice, the second only once before the budget runs dry
+        assert!(budget.retry(2, |_| true, failing_call).await.is_err());
+        assert_eq!(attempts.swap(0, Ordering::Relaxed), 3);
+        assert!(budget.retry(2, |_| true, failing_call).await.is_err());
+        assert_eq!(attempts.swap(0, Ordering::Relaxed), 2);
+
+        // Subsequent retries are suppressed entirely
+        assert!(budget.retry(2, |_| true, failing_call).await.is_err());
+        assert_eq!(attempts.load(Ordering::Relaxed), 1);
+        assert!(!budget.try_acquire());
+    }
+
+    #[test]
+    fn test_budget_refills_over_time() {
+        let budget = RetryBudget::new(2, Duration::from_secs(1));
+        let start = budget.bucket.lock().unwrap().last_refill;
+
+        assert!(budget.try_acquire_at(start));
+        assert!(budget.try_acquire_at(start));
+        assert!(!budget.try_acquire_at(start));
+
+        // One token per interval, never more than the capacity
+        assert!(budget.try_acquire_at(start + Duration::from_millis(1500)));
+        assert!(!budget.try_acquire_at(start + Duration::from_millis(1500)));
+        assert!(budget.try_acquire_at(start + Duration::from_secs(2)));
+        assert!(budget.try_acquire_at(start + Duration::from_secs(60)));
+        assert!(budget.try_acquire_at(start + Duration::from_secs(60)));
+        assert!(!budget.try_acquire_at(start + Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn test_backoff_grows_exponentially_up_to_the_cap() {
+        let budget = RetryBudget::default();
+
+        assert_eq!(budget.backoff(0), DEFAULT_RETRY_INITIAL_BACKOFF);
+        assert_eq!(budget.backoff(1), DEFAULT_RETRY_INITIAL_BACKOFF * 2);
+        assert_eq!(budget.backoff(2), DEFAULT_RETRY_INITIAL_BACKOFF * 4);
+        assert_eq!(budget.backoff(u32::MAX), MAX_RETRY_BACKOFF);
+    }
+
+    #[tokio::test]
+    async fn test_retries_wait_for_the_backoff() {
+        let budget = RetryBudget::new(3, Duration::from_secs(3600))
+            .with_initial_backoff(Duration::from_millis(20));
+        let start = Instant::now();
+
+        let result = budget
+            .retry(
+                2,
+                |_| true,
+                || async { Err::<(), _>("enclave unavailable") },
+            )
+            .await;
+
+        // 20ms before the first retry, 40ms before the second
+        assert!(result.is_err());
+        assert!(start.elapsed() >= Duration::from_millis(60));
+    }
+
+    #[tokio::test]
+    async fn test_non_retryable_errors_do_not_draw_from_budget() {
+        let budget = RetryBudget::new(1, Duration::from_secs(3600));
+
+        let result = budget
+            .retry(3, |_| false, || async { Err::<(), _>("bad request") })
+            .await;
+
+        assert!(result.is_err());
+        assert!(budget.try_acquire());
+    }
+}
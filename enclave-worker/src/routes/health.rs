@@ -1,36 +1,140 @@
-use axum::{Extension, Json};
-use enclave_types::EnclaveHealthCheckRequest;
+use axum::{http::StatusCode, Extension, Json};
 use schemars::JsonSchema;
 use serde::Serialize;
 
-use crate::types::AppError;
+use crate::{
+    dogstatsd,
+    health::{
+        aggregate_status, Dependency, DependencyHealth, DependencyStatus, HealthMonitor,
+        HealthStatus,
+    },
+};
 
 #[derive(Debug, Serialize, JsonSchema)]
 pub struct HealthResponse {
-    status: String,
+    /// Aggregate status, unhealthy if any critical dependency is down or not checked yet
+    status: HealthStatus,
     /// Current version of the application
     semver: String,
     /// Commit hash of the current build (if available)
     rev: Option<String>,
+    /// Whether metrics are reported to `DogStatsD`
+    metrics_enabled: bool,
+    /// Health of each dependency as of its last check
+    dependencies: Vec<DependencyHealth>,
+}
+
+impl HealthResponse {
+    fn from_monitor(monitor: &HealthMonitor) -> Self {
+        let dependencies = monitor.dependencies();
+
+        Self {
+            status: aggregate_status(&dependencies),
+            semver: env!("CARGO_PKG_VERSION").to_string(),
+            rev: option_env!("GIT_REV").map(ToString::to_string),
+            metrics_enabled: dogstatsd::is_initialized(),
+            dependencies,
+        }
+    }
 }
 
 /// Health check endpoint
 ///
-/// Returns the current status and version information of the service.
-/// This endpoint can be used for monitoring and deployment verification.
+/// Returns the current status and version information of the service, along with the health of
+/// each dependency. Responds with 503 while a critical dependency is down or hasn't been checked
+/// yet, so this endpoint can be used for monitoring and deployment verification.
 pub async fn handler(
-    Extension(pontifex_connection_details): Extension<pontifex::client::ConnectionDetails>,
-) -> Result<Json<HealthResponse>, AppError> {
-    // Verify we can reach the enclave and it's healthy
-    pontifex::client::send::<EnclaveHealthCheckRequest>(
-        pontifex_connection_details,
-        &EnclaveHealthCheckRequest,
-    )
-    .await??;
-
-    Ok(Json(HealthResponse {
-        status: "ok".to_string(),
-        semver: env!("CARGO_PKG_VERSION").to_string(),
-        rev: option_env!("GIT_REV").map(ToString::to_string),
-    }))
+    Extension(monitor): Extension<HealthMonitor>,
+) -> (StatusCode, Json<HealthResponse>) {
+    let response = HealthResponse::from_monitor(&monitor);
+    let status = match response.status {
+        HealthStatus::Healthy | HealthStatus::Degraded => StatusCode::OK,
+        HealthStatus::Unhealthy => StatusCode::SERVICE_UNAVAILABLE,
+    };
+
+    (status, Json(response))
+}
+
+/// Enclave health endpoint
+///
+/// Returns the health of the secure enclave alone, ignoring the worker's other dependencies.
+/// Responds with 503 unless the last check of the enclave passed, so callers can tell an
+/// enclave outage apart from an outage of e.g. SQS.
+pub async fn enclave_handler(
+    Extension(monitor): Extension<HealthMonitor>,
+) -> (StatusCode, Json<DependencyHealth>) {
+    let health = monitor.dependency(Dependency::Enclave);
+    let status = match health.status {
+        DependencyStatus::Up => StatusCode::OK,
+        DependencyStatus::Down | DependencyStatus::Unknown => StatusCode::SERVICE_UNAVAILABLE,
+    };
+
+    (status, Json(health))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_enclave_health_ignores_other_dependencies() {
+        let monitor = HealthMonitor::new();
+        let (status, _) = enclave_handler(Extension(monitor.clone())).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+
+        monitor.record(Dependency::Enclave, Ok(()), 100);
+        monitor.record(Dependency::Sqs, Err("unreachable".to_string()), 100);
+        let (status, Json(health)) = enclave_handler(Extension(monitor.clone())).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(health.name, "enclave");
+
+        monitor.record(Dependency::Enclave, Err("timed out".to_string()), 101);
+        let (status, _) = enclave_handler(Extension(monitor)).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[test]
+    fn test_response_lists_each_dependency_with_its_status() {
+        let monitor = HealthMonitor::new();
+        monitor.record(Dependency::Enclave, Ok(()), 100);
+        monitor.record(Dependency::Sqs, Ok(()), 100);
+        monitor.record(Dependency::DynamoDb, Err("throttled".to_string()), 101);
+
+        let json = serde_json::to_value(HealthResponse::from_monitor(&monitor)).unwrap();
+
+        assert_eq!(json["status"], "unhealthy");
+        assert_eq!(
+            json["dependencies"],
+            serde_json::json!([
+                {
+                    "name": "enclave",
+                    "status": "up",
+                    "critical": true,
+                    "last_checked_at": 100,
+                    "error": null,
+                },
+                {
+                    "name": "redis",
+                    "status": "unknown",
+                    "critical": false,
+                    "last_checked_at": null,
+                    "error": null,
+                },
+                {
+                    "name": "sqs",
+                    "status": "up",
+                    "critical": true,
+                    "last_checked_at": 100,
+                    "error": null,
+                },
+                {
+                    "name": "dynamodb",
+                    "status": "down",
+                    "critical": true,
+                    "last_checked_at": 101,
+                    "error": "throttled",
+                },
+            ])
+        );
+    }
 }
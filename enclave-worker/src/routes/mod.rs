@@ -13,6 +13,7 @@ pub fn handler() -> ApiRouter {
     ApiRouter::new()
         .merge(docs::handler())
         .api_route("/health", get(health::handler))
+        .api_route("/health/enclave", get(health::enclave_handler))
         .api_route("/v1/push-id-challenge", post(push_id_challenge::handler))
         .api_route("/v1/attestation-document", get(attestation::handler))
 }
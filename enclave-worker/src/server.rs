@@ -9,6 +9,7 @@ use tokio::net::TcpListener;
 use tokio_util::sync::CancellationToken;
 
 use crate::cache::CacheManager;
+use crate::health::HealthMonitor;
 use crate::routes;
 use crate::types::Environment;
 
@@ -23,6 +24,7 @@ pub async fn start(
     push_subscription_storage: Arc<PushSubscriptionStorage>,
     enclave_connection_details: pontifex::client::ConnectionDetails,
     cache_manager: CacheManager,
+    health_monitor: HealthMonitor,
     shutdown_token: CancellationToken,
 ) -> anyhow::Result<()> {
     let mut openapi = OpenApi::default();
@@ -35,6 +37,7 @@ pub async fn start(
         .layer(Extension(notification_queue))
         .layer(Extension(enclave_connection_details))
         .layer(Extension(cache_manager))
+        .layer(Extension(health_monitor))
         // Include trace context as header into the response
         .route_layer(OtelInResponseLayer)
         // Start OpenTelemetry trace on incoming request
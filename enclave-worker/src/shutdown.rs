@@ -0,0 +1,130 @@
+//! Ordered shutdown of the enclave worker
+//!
+//! On shutdown the worker first stops taking notifications off the queue, then waits for the
+//! in-flight batches to be delivered, and only then stops the HTTP server. Cancelling everything
+//! at once could tear down shared resources while a batch is still being delivered.
+
+use std::future::Future;
+
+use tokio::task::{JoinError, JoinHandle};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info};
+
+/// Cancellation phases of the shutdown, cancelled in order
+#[derive(Debug, Clone, Default)]
+pub struct ShutdownPhases {
+    /// Stops the notification processor from polling the queue
+    pub intake: CancellationToken,
+    /// Gracefully stops the HTTP server, once processing has drained
+    pub server: CancellationToken,
+}
+
+impl ShutdownPhases {
+    /// Waits for `signal`, then shuts down in order:
+    /// stop intake → drain processing → stop server
+    ///
+    /// If the server stops on its own (e.g. it failed to bind), processing is drained as well.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `JoinError` if the server task panicked or was aborted
+    pub async fn run_until_shutdown<T>(
+        &self,
+        signal: impl Future<Output = ()>,
+        processor: JoinHandle<()>,
+        mut server: JoinHandle<T>,
+    ) -> Result<T, JoinError> {
+        tokio::select! {
+            () = signal => info!("Shutting down Enclave Worker..."),
+            result = &mut server => {
+                error!("HTTP server stopped unexpectedly, draining notification processing");
+                self.drain_processing(processor).await;
+                return result;
+            }
+        }
+
+        self.drain_processing(processor).await;
+
+        info!("Stopping HTTP server");
+        self.server.cancel();
+        server.await
+    }
+
+    async fn drain_processing(&self, processor: JoinHandle<()>) {
+        info!("Stopping notification intake");
+        self.intake.cancel();
+
+        if let Err(e) = processor.await {
+            error!("Notification processor failed while draining: {e}");
+        }
+        info!("Notification processing drained");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_processor_drains_before_server_stops() {
+        let phases = ShutdownPhases::default();
+        let drained = Arc::new(AtomicBool::new(false));
+
+        // Processor finishes an in-flight batch after intake stops
+        let processor = {
+            let intake = phases.intake.clone();
+            let drained = drained.clone();
+            tokio::spawn(async move {
+                intake.cancelled().await;
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                drained.store(true, Ordering::SeqCst);
+            })
+        };
+
+        // Server reports whether processing had drained when it was told to stop
+        let server = {
+            let server_token = phases.server.clone();
+            let drained = drained.clone();
+            tokio::spawn(async move {
+                server_token.cancelled().await;
+                drained.load(Ordering::SeqCst)
+            })
+        };
+
+        let drained_before_server_stopped = phases
+            .run_until_shutdown(async {}, processor, server)
+            .await
+            .unwrap();
+
+        assert!(drained_before_server_stopped);
+        assert!(phases.intake.is_cancelled());
+        assert!(phases.server.is_cancelled());
+    }
+
+    #[tokio::test]
+    async fn test_server_exit_drains_processor() {
+        let phases = ShutdownPhases::default();
+
+        let processor = {
+            let intake = phases.intake.clone();
+            tokio::spawn(async move { intake.cancelled().await })
+        };
+        let server = tokio::spawn(async { "bind failed" });
+
+        let result = phases
+            .run_until_shutdown(std::future::pending(), processor, server)
+            .await
+            .unwrap();
+
+        assert_eq!(result, "bind failed");
+        assert!(phases.intake.is_cancelled());
+    }
+}
@@ -0,0 +1,64 @@
+//! AWS service clients shared across the enclave worker
+
+use std::sync::Arc;
+
+use aws_config::SdkConfig;
+use aws_sdk_dynamodb::Client as DynamoDbClient;
+use aws_sdk_sqs::Client as SqsClient;
+
+use super::Environment;
+
+/// AWS service clients, all built from a single shared `SdkConfig`
+#[derive(Clone)]
+pub struct AwsClients {
+    /// `DynamoDB` client for push subscriptions
+    pub dynamodb: Arc<DynamoDbClient>,
+    /// SQS client for the notification queue
+    pub sqs: Arc<SqsClient>,
+}
+
+impl AwsClients {
+    /// Loads the AWS configuration once and builds every client from it
+    pub async fn load(environment: &Environment) -> Self {
+        Self::from_config(&environment.aws_config().await)
+    }
+
+    /// Builds every client from an already loaded AWS configuration
+    #[must_use]
+    pub fn from_config(aws_config: &SdkConfig) -> Self {
+        Self {
+            dynamodb: Arc::new(DynamoDbClient::new(aws_config)),
+            sqs: Arc::new(SqsClient::new(aws_config)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aws_config::{BehaviorVersion, Region};
+
+    use super::*;
+
+    #[test]
+    fn test_clients_share_config() {
+        let region = Region::new("eu-central-1");
+        let aws_config = Environment::Development.tune_aws_config(
+            &SdkConfig::builder()
+                .behavior_version(BehaviorVersion::latest())
+                .region(region.clone())
+                .build(),
+        );
+
+        let clients = AwsClients::from_config(&aws_config);
+
+        let dynamodb_config = clients.dynamodb.config();
+        let sqs_config = clients.sqs.config();
+        assert_eq!(dynamodb_config.region(), Some(&region));
+        assert_eq!(sqs_config.region(), Some(&region));
+        assert_eq!(sqs_config.retry_config(), dynamodb_config.retry_config());
+        assert_eq!(
+            sqs_config.timeout_config(),
+            dynamodb_config.timeout_config()
+        );
+    }
+}
@@ -1,7 +1,13 @@
-use std::{env, time::Duration};
+use std::{env, num::NonZeroUsize};
 
-use aws_config::{retry::RetryConfig, timeout::TimeoutConfig, BehaviorVersion};
 use backend_storage::queue::QueueConfig;
+use common_types::{
+    aws,
+    logging::{InvalidLogFormat, LogFormat},
+};
+
+/// Default number of recipients per batch when sending to pontifex
+const DEFAULT_RECIPIENTS_PER_BATCH: NonZeroUsize = NonZeroUsize::new(50).unwrap();
 
 /// Application environment configuration
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -35,6 +41,15 @@ impl Environment {
         }
     }
 
+    /// Log format, from `LOG_FORMAT` or pretty in development and JSON otherwise
+    ///
+    /// # Errors
+    ///
+    /// If `LOG_FORMAT` holds an unknown format
+    pub fn log_format(&self) -> Result<LogFormat, InvalidLogFormat> {
+        LogFormat::from_env(LogFormat::default_for(matches!(self, Self::Development)))
+    }
+
     /// Returns the endpoint URL to use for AWS services
     #[must_use]
     pub const fn override_aws_endpoint_url(&self) -> Option<&str> {
@@ -46,27 +61,16 @@ impl Environment {
         }
     }
 
-    /// AWS configuration with retry and timeout settings
+    /// AWS configuration shared by every SDK client, with the environment's retry, timeout and
+    /// endpoint settings
     pub async fn aws_config(&self) -> aws_config::SdkConfig {
-        let retry_config = RetryConfig::standard()
-            .with_max_attempts(3)
-            .with_initial_backoff(Duration::from_millis(50));
-
-        let timeout_config = TimeoutConfig::builder()
-            .operation_timeout(Duration::from_secs(30))
-            .build();
-
-        let mut config_builder = aws_config::load_defaults(BehaviorVersion::latest())
-            .await
-            .to_builder()
-            .retry_config(retry_config)
-            .timeout_config(timeout_config);
-
-        if let Some(endpoint_url) = self.override_aws_endpoint_url() {
-            config_builder = config_builder.endpoint_url(endpoint_url);
-        }
+        aws::load_config(self.override_aws_endpoint_url()).await
+    }
 
-        config_builder.build()
+    /// Applies the retry, timeout and endpoint settings of the environment to `aws_config`
+    #[must_use]
+    pub fn tune_aws_config(&self, aws_config: &aws_config::SdkConfig) -> aws_config::SdkConfig {
+        aws::tune_config(aws_config, self.override_aws_endpoint_url())
     }
 
     /// Returns the notification queue configuration
@@ -74,6 +78,7 @@ impl Environment {
     /// # Panics
     ///
     /// Panics if the `NOTIFICATION_QUEUE_URL` environment variable is not set in production/staging
+    /// or the configuration is outside SQS limits
     #[must_use]
     pub fn notification_queue_config(&self) -> QueueConfig {
         let queue_url = match self {
@@ -84,12 +89,13 @@ impl Environment {
             }
         };
 
-        QueueConfig {
-            queue_url,
-            default_max_messages: 10,
-            default_visibility_timeout: 60, // 60 seconds - Longer timeout for notifications
-            default_wait_time_seconds: 20,  // Enable long polling by default
-        }
+        // Longer timeout for notifications
+        let visibility_timeout = 60;
+        // Enable long polling by default
+        let wait_time_seconds = 20;
+
+        QueueConfig::new(queue_url, 10, visibility_timeout, wait_time_seconds)
+            .expect("Invalid notification queue configuration")
     }
 
     /// Returns the Push Notification Subscription storage table name
@@ -106,6 +112,20 @@ impl Environment {
         }
     }
 
+    /// Returns the Dynamo DB GSI name for the push subscriptions `encrypted_push_id` index
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `DYNAMODB_PUSH_PUSH_ID_INDEX_NAME` environment variable is not set in production/staging
+    #[must_use]
+    pub fn push_subscription_push_id_index_name(&self) -> String {
+        match self {
+            Self::Production | Self::Staging => env::var("DYNAMODB_PUSH_PUSH_ID_INDEX_NAME")
+                .expect("DYNAMODB_PUSH_PUSH_ID_INDEX_NAME environment variable is not set"),
+            Self::Development => "encrypted-push-id-index".to_string(),
+        }
+    }
+
     /// Whether to show API docs
     #[must_use]
     pub const fn show_api_docs(&self) -> bool {
@@ -206,11 +226,15 @@ impl Environment {
     /// Returns the maximum number of recipients per batch when sending to pontifex
     ///
     /// Default is 50 per [Braze docs](https://www.braze.com/docs/api/endpoints/messaging/send_messages/post_send_messages)
+    ///
+    /// # Panics
+    ///
+    /// Panics if `RECIPIENTS_PER_BATCH` is set but is not a positive integer
     #[must_use]
-    pub fn recipients_per_batch(&self) -> usize {
-        env::var("RECIPIENTS_PER_BATCH")
-            .ok()
-            .and_then(|v| v.parse().ok())
-            .unwrap_or(50)
+    pub fn recipients_per_batch(&self) -> NonZeroUsize {
+        env::var("RECIPIENTS_PER_BATCH").map_or(DEFAULT_RECIPIENTS_PER_BATCH, |v| {
+            v.parse()
+                .expect("RECIPIENTS_PER_BATCH environment variable is not a positive integer")
+        })
     }
 }
@@ -108,8 +108,8 @@ impl From<PushSubscriptionStorageError> for AppError {
     fn from(err: PushSubscriptionStorageError) -> Self {
         use PushSubscriptionStorageError::{
             DynamoDbBatchGetError, DynamoDbBatchWriteError, DynamoDbDeleteError, DynamoDbGetError,
-            DynamoDbPutError, DynamoDbQueryError, DynamoDbUpdateError, ParseSubscriptionError,
-            PushSubscriptionExists, SerializationError,
+            DynamoDbPutError, DynamoDbQueryError, DynamoDbUpdateError, InvalidKey, NotFound,
+            ParseSubscriptionError, PushSubscriptionExists, SerializationError, UnprocessedItems,
         };
 
         match &err {
@@ -124,13 +124,23 @@ impl From<PushSubscriptionStorageError> for AppError {
                     false,
                 )
             }
+            InvalidKey(e) => {
+                tracing::warn!("Invalid subscription key: {e}");
+                Self::new(
+                    StatusCode::BAD_REQUEST,
+                    "invalid_subscription_key",
+                    "Invalid subscription key",
+                    false,
+                )
+            }
             DynamoDbPutError(_)
             | DynamoDbDeleteError(_)
             | DynamoDbGetError(_)
             | DynamoDbQueryError(_)
             | DynamoDbUpdateError(_)
             | DynamoDbBatchWriteError(_)
-            | DynamoDbBatchGetError(_) => {
+            | DynamoDbBatchGetError(_)
+            | UnprocessedItems(_) => {
                 tracing::error!("DynamoDB error: {err}");
                 Self::new(
                     StatusCode::SERVICE_UNAVAILABLE,
@@ -139,6 +149,12 @@ impl From<PushSubscriptionStorageError> for AppError {
                     true,
                 )
             }
+            NotFound => Self::new(
+                StatusCode::NOT_FOUND,
+                "not_found",
+                "Push subscription not found",
+                false,
+            ),
             SerializationError(msg) | ParseSubscriptionError(msg) => {
                 tracing::error!("Serialization/Parse error: {msg}");
                 Self::new(
@@ -169,8 +185,10 @@ impl From<enclave_types::EnclaveError> for AppError {
     fn from(err: enclave_types::EnclaveError) -> Self {
         use enclave_types::EnclaveError::{
             AlreadyInitialized, AttestationFailed, AttestationVerificationFailed,
-            BrazeRequestFailed, DecryptPushIdFailed, DecryptSecretKeyFailed, KeyPairCreationFailed,
-            MissingStateField, NotInitialized, PontifexError, SecureModuleNotInitialized,
+            BrazeRequestFailed, DecryptPushIdFailed, DecryptSecretKeyFailed,
+            InitializeChallengeMismatch, InvalidBrazeRegion, InvalidMessagePayload,
+            InvalidStateSnapshot, KeyPairCreationFailed, MissingStateField, NotInitialized,
+            PontifexError, SecureModuleNotInitialized, TooManyRecipients,
         };
 
         match &err {
@@ -228,6 +246,26 @@ impl From<enclave_types::EnclaveError> for AppError {
                     false,
                 )
             }
+            TooManyRecipients { count, max } => {
+                tracing::error!(
+                    "Notification has {count} recipients, enclave accepts at most {max}"
+                );
+                Self::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "internal_error",
+                    "Internal server error",
+                    false,
+                )
+            }
+            InvalidMessagePayload(msg) => {
+                tracing::error!("Invalid notification message payload: {msg}");
+                Self::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "internal_error",
+                    "Internal server error",
+                    false,
+                )
+            }
             KeyPairCreationFailed => {
                 tracing::error!("Key pair creation failed");
                 Self::new(
@@ -247,6 +285,24 @@ impl From<enclave_types::EnclaveError> for AppError {
                     false,
                 )
             }
+            InitializeChallengeMismatch => {
+                tracing::error!("Enclave initialize request without a valid challenge nonce");
+                Self::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "internal_error",
+                    "Internal server error",
+                    false,
+                )
+            }
+            InvalidBrazeRegion(region) => {
+                tracing::error!("Enclave initialize with invalid Braze region: {region}");
+                Self::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "internal_error",
+                    "Internal server error",
+                    false,
+                )
+            }
             AttestationVerificationFailed(msg) | DecryptSecretKeyFailed(msg) => {
                 tracing::error!("Enclave initialize error: {msg}");
                 Self::new(
@@ -256,6 +312,15 @@ impl From<enclave_types::EnclaveError> for AppError {
                     false,
                 )
             }
+            InvalidStateSnapshot(msg) => {
+                tracing::error!("Enclave state snapshot error: {msg}");
+                Self::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "internal_error",
+                    "Internal server error",
+                    false,
+                )
+            }
             MissingStateField(field) => {
                 tracing::error!("Enclave missing state field: {field}");
                 Self::new(
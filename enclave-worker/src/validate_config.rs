@@ -0,0 +1,70 @@
+//! Startup configuration validation
+//!
+//! Started with `--validate-config`, the binary loads its configuration, builds its clients and
+//! checks every dependency is reachable, then exits without binding the HTTP server.
+
+use anyhow::Context;
+use common_types::validate_config::{run_checks, Check};
+use enclave_types::EnclaveHealthCheckRequest;
+
+use crate::{
+    redis::RedisClient,
+    types::{AwsClients, Environment},
+};
+
+pub use common_types::validate_config::{is_requested, ValidationReport};
+
+/// Checks the enclave worker can reach every dependency it needs to process notifications
+pub async fn validate(
+    environment: &Environment,
+    aws_clients: &AwsClients,
+    redis_client: Result<&RedisClient, &anyhow::Error>,
+    enclave_connection_details: pontifex::client::ConnectionDetails,
+) -> ValidationReport {
+    let checks = vec![
+        Check::new("sqs_notification_queue", async {
+            let queue_url = environment.notification_queue_config().queue_url;
+            aws_clients
+                .sqs
+                .get_queue_attributes()
+                .queue_url(&queue_url)
+                .send()
+                .await
+                .with_context(|| format!("Failed to access queue {queue_url}"))?;
+            Ok(())
+        }),
+        Check::new("dynamodb_push_subscription_table", async {
+            let table_name = environment.push_subscription_table_name();
+            aws_clients
+                .dynamodb
+                .describe_table()
+                .table_name(&table_name)
+                .send()
+                .await
+                .with_context(|| format!("Failed to describe table {table_name}"))?;
+            Ok(())
+        }),
+        Check::new("redis", async {
+            // A failed connection is reported like any other check instead of aborting
+            let redis_client =
+                redis_client.map_err(|e| anyhow::anyhow!("Failed to connect to Redis: {e:#}"))?;
+            redis::cmd("PING")
+                .query_async::<String>(&mut redis_client.conn())
+                .await
+                .context("Failed to ping Redis")?;
+            Ok(())
+        }),
+        Check::new("enclave", async move {
+            pontifex::client::send::<EnclaveHealthCheckRequest>(
+                enclave_connection_details,
+                &EnclaveHealthCheckRequest,
+            )
+            .await
+            .map_err(|e| anyhow::anyhow!("Transport error: {e}"))?
+            .map_err(|e| anyhow::anyhow!("Enclave error: {e:?}"))?;
+            Ok(())
+        }),
+    ];
+
+    run_checks(checks).await
+}
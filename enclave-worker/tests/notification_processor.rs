@@ -0,0 +1,105 @@
+mod utils;
+
+use std::{num::NonZeroUsize, time::Duration};
+
+use backend_storage::queue::Notification;
+use enclave_types::EnclaveError;
+use enclave_worker::{dogstatsd::Metric, retry_budget::RetryBudget};
+use metrics_util::debugging::DebuggingRecorder;
+use pretty_assertions::assert_eq;
+use utils::notification_processor::{counter_value, MockEnclave, ProcessorHarness};
+
+fn notification(recipients: usize) -> Notification {
+    Notification {
+        topic: "/xmtp/mls/1/g-test/proto".to_string(),
+        subscribed_encrypted_push_ids: (0..recipients)
+            .map(|i| format!("encrypted-push-id-{i}"))
+            .collect(),
+        encrypted_message_base64: "ZW5jcnlwdGVkIG1lc3NhZ2U=".to_string(),
+        requeue_attempt: 0,
+    }
+}
+
+#[tokio::test]
+async fn test_notification_is_delivered_and_acked() {
+    // The processor runs on the test's single thread, so it records to the local recorder
+    let recorder = DebuggingRecorder::new();
+    let snapshotter = recorder.snapshotter();
+    let _metrics = metrics::set_default_local_recorder(&recorder);
+
+    let mut harness =
+        ProcessorHarness::start(MockEnclave::delivering(), NonZeroUsize::new(2).unwrap()).await;
+    let notification = notification(3);
+    harness.queue.send_message(&notification).await.unwrap();
+
+    // 3 recipients are sent in batches of 2
+    harness.enclave.wait_for_requests(2).await;
+    let message_counts = harness.stop().await;
+
+    let requests = harness.enclave.requests();
+    assert_eq!(requests.len(), 2);
+    let mut recipients: Vec<String> = requests
+        .into_iter()
+        .flat_map(|request| request.subscribed_encrypted_push_ids)
+        .collect();
+    recipients.sort();
+    assert_eq!(recipients, notification.subscribed_encrypted_push_ids);
+
+    // The message was acked
+    assert_eq!(message_counts, (0, 0));
+    assert_eq!(
+        counter_value(&snapshotter, Metric::NotificationDelivered.name()),
+        1
+    );
+}
+
+#[tokio::test]
+async fn test_failed_notification_is_not_acked() {
+    let recorder = DebuggingRecorder::new();
+    let snapshotter = recorder.snapshotter();
+    let _metrics = metrics::set_default_local_recorder(&recorder);
+
+    let enclave = MockEnclave::failing(EnclaveError::BrazeRequestFailed(
+        "503 Service Unavailable".to_string(),
+    ));
+    let mut harness = ProcessorHarness::start(enclave, NonZeroUsize::new(50).unwrap()).await;
+    harness.queue.send_message(&notification(1)).await.unwrap();
+
+    harness.enclave.wait_for_requests(1).await;
+    let message_counts = harness.stop().await;
+
+    // The enclave saw the request, so its error isn't retried
+    assert_eq!(harness.enclave.requests().len(), 1);
+
+    // The message stays in flight until its visibility timeout, then is retried
+    assert_eq!(message_counts, (0, 1));
+    assert_eq!(
+        counter_value(&snapshotter, Metric::NotificationDelivered.name()),
+        0
+    );
+}
+
+#[tokio::test]
+async fn test_uninitialized_enclave_requeue_draws_from_retry_budget() {
+    let recorder = DebuggingRecorder::new();
+    let snapshotter = recorder.snapshotter();
+    let _metrics = metrics::set_default_local_recorder(&recorder);
+
+    let mut harness = ProcessorHarness::start_with_retry_budget(
+        MockEnclave::failing(EnclaveError::NotInitialized),
+        NonZeroUsize::new(50).unwrap(),
+        RetryBudget::new(0, Duration::from_secs(3600)),
+    )
+    .await;
+    harness.queue.send_message(&notification(1)).await.unwrap();
+
+    harness.enclave.wait_for_requests(1).await;
+    let message_counts = harness.stop().await;
+
+    // Without budget the message isn't requeued, it stays in flight until its visibility timeout
+    assert_eq!(message_counts, (0, 1));
+    assert_eq!(
+        counter_value(&snapshotter, Metric::EnclaveRetryBudgetExhausted.name()),
+        1
+    );
+}
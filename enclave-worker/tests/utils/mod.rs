@@ -1,3 +1,8 @@
+// Not every test uses every utility
+#![allow(dead_code)]
+
+pub mod notification_processor;
+
 use anyhow::Result;
 use enclave_worker::{cache::CacheManager, redis::RedisClient, types::Environment};
 
@@ -0,0 +1,272 @@
+//! End-to-end harness of the notification processor
+//!
+//! Runs a `NotificationProcessor` between a dedicated LocalStack queue and a mock enclave, so
+//! tests can enqueue notifications and observe what the enclave received, which messages were
+//! acked and which metrics were emitted.
+//!
+//! The enclave is mocked behind `NotificationEnclave` rather than as a pontifex responder:
+//! pontifex only serves over vsock, which CI hosts don't have. The vsock transport is thus not
+//! covered, the enclave's handling of the requests is tested in the secure enclave.
+
+use std::{
+    num::NonZeroUsize,
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+
+use aws_config::{BehaviorVersion, Region};
+use aws_credential_types::Credentials;
+use aws_sdk_sqs::{types::QueueAttributeName, Client as SqsClient};
+use backend_storage::{
+    push_subscription::PushSubscriptionStorage,
+    queue::{NotificationQueue, QueueConfig},
+};
+use enclave_types::{EnclaveError, EnclaveNotificationRequest, EnclaveNotificationResponse};
+use enclave_worker::{
+    notification_processor::{NotificationEnclave, NotificationProcessor, ProcessError},
+    retry_budget::RetryBudget,
+    types::{AwsClients, Environment},
+};
+use metrics_util::debugging::{DebugValue, Snapshotter};
+use tokio::{sync::Notify, task::JoinHandle};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+const TEST_REGION: &str = "us-east-1";
+/// Visibility timeout of the test queue, long enough for unacked messages to stay in flight
+/// until the test ends
+const VISIBILITY_TIMEOUT_SECONDS: i32 = 60;
+/// Short long poll, so an idle processor doesn't spin on empty receives
+const WAIT_TIME_SECONDS: i32 = 1;
+/// How long the enclave and the processor are given to handle a notification
+const PROCESSING_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Stand-in for the pontifex enclave, answering every notification batch with the same outcome
+#[derive(Debug, Clone)]
+pub struct MockEnclave {
+    outcome: Result<EnclaveNotificationResponse, EnclaveError>,
+    requests: Arc<Mutex<Vec<EnclaveNotificationRequest>>>,
+    received: Arc<Notify>,
+}
+
+impl MockEnclave {
+    /// Enclave delivering every batch
+    pub fn delivering() -> Self {
+        Self::answering(Ok(EnclaveNotificationResponse::default()))
+    }
+
+    /// Enclave failing every batch with `error`
+    pub fn failing(error: EnclaveError) -> Self {
+        Self::answering(Err(error))
+    }
+
+    fn answering(outcome: Result<EnclaveNotificationResponse, EnclaveError>) -> Self {
+        Self {
+            outcome,
+            requests: Arc::default(),
+            received: Arc::default(),
+        }
+    }
+
+    /// Requests received so far, in order
+    pub fn requests(&self) -> Vec<EnclaveNotificationRequest> {
+        self.requests
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Waits until at least `count` requests were received
+    ///
+    /// # Panics
+    ///
+    /// If fewer requests are received within `PROCESSING_TIMEOUT`
+    pub async fn wait_for_requests(&self, count: usize) {
+        tokio::time::timeout(PROCESSING_TIMEOUT, async {
+            loop {
+                // Registered before checking, so a request received in between isn't missed
+                let received = self.received.notified();
+                if self.requests().len() >= count {
+                    return;
+                }
+                received.await;
+            }
+        })
+        .await
+        .expect("Enclave didn't receive the expected requests in time");
+    }
+}
+
+impl NotificationEnclave for MockEnclave {
+    async fn send_notification(
+        &self,
+        request: &EnclaveNotificationRequest,
+    ) -> Result<EnclaveNotificationResponse, ProcessError> {
+        self.requests
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(request.clone());
+        self.received.notify_waiters();
+
+        Ok(self.outcome.clone()?)
+    }
+}
+
+/// A `NotificationProcessor` running against a unique queue and a `MockEnclave`
+pub struct ProcessorHarness {
+    pub queue: Arc<NotificationQueue>,
+    pub enclave: MockEnclave,
+    sqs_client: Arc<SqsClient>,
+    queue_url: String,
+    shutdown: CancellationToken,
+    processor: Option<JoinHandle<()>>,
+}
+
+impl ProcessorHarness {
+    /// Creates a unique FIFO queue and starts a processor sending its notifications to
+    /// `enclave` in batches of `recipients_per_batch`
+    pub async fn start(enclave: MockEnclave, recipients_per_batch: NonZeroUsize) -> Self {
+        Self::start_with_retry_budget(enclave, recipients_per_batch, RetryBudget::default()).await
+    }
+
+    /// Like `start`, with the processor's retries drawing from `retry_budget`
+    pub async fn start_with_retry_budget(
+        enclave: MockEnclave,
+        recipients_per_batch: NonZeroUsize,
+        retry_budget: RetryBudget,
+    ) -> Self {
+        super::setup_test_env();
+
+        // Setup LocalStack clients with hardcoded credentials for CI
+        let environment = Environment::Development;
+        let aws_config = aws_config::defaults(BehaviorVersion::latest())
+            .region(Region::new(TEST_REGION))
+            .credentials_provider(Credentials::from_keys("test", "test", None))
+            .load()
+            .await;
+        let aws_clients = AwsClients::from_config(&environment.tune_aws_config(&aws_config));
+
+        let queue_url = aws_clients
+            .sqs
+            .create_queue()
+            .queue_name(format!("notification-processor-{}.fifo", Uuid::new_v4()))
+            .attributes(QueueAttributeName::FifoQueue, "true")
+            .attributes(QueueAttributeName::ContentBasedDeduplication, "true")
+            .send()
+            .await
+            .expect("Failed to create test queue")
+            .queue_url()
+            .expect("Queue URL not returned")
+            .to_string();
+
+        let queue = Arc::new(NotificationQueue::new(
+            aws_clients.sqs.clone(),
+            QueueConfig::new(
+                queue_url.clone(),
+                10,
+                VISIBILITY_TIMEOUT_SECONDS,
+                WAIT_TIME_SECONDS,
+            )
+            .expect("Invalid test queue configuration"),
+        ));
+        // Only used to prune undecryptable push IDs, which the mock enclave never reports
+        let storage = Arc::new(PushSubscriptionStorage::new(
+            aws_clients.dynamodb.clone(),
+            environment.push_subscription_table_name(),
+            environment.push_subscription_push_id_index_name(),
+        ));
+
+        let shutdown = CancellationToken::new();
+        let processor = NotificationProcessor::new(
+            queue.clone(),
+            storage,
+            shutdown.clone(),
+            enclave.clone(),
+            recipients_per_batch,
+            Arc::new(retry_budget),
+        );
+
+        Self {
+            queue,
+            enclave,
+            sqs_client: aws_clients.sqs,
+            queue_url,
+            shutdown,
+            processor: Some(tokio::spawn(processor.start())),
+        }
+    }
+
+    /// Shuts the processor down, once it finished processing the messages it received, and
+    /// deletes the queue
+    ///
+    /// Returns the approximate number of messages left waiting on the queue and in flight, i.e.
+    /// received but not acked.
+    ///
+    /// # Panics
+    ///
+    /// If the processor doesn't stop within `PROCESSING_TIMEOUT` or the queue can't be deleted
+    pub async fn stop(&mut self) -> (u64, u64) {
+        self.shutdown.cancel();
+        if let Some(processor) = self.processor.take() {
+            tokio::time::timeout(PROCESSING_TIMEOUT, processor)
+                .await
+                .expect("Processor didn't stop in time")
+                .expect("Processor panicked");
+        }
+
+        let message_counts = self.message_counts().await;
+        self.sqs_client
+            .delete_queue()
+            .queue_url(&self.queue_url)
+            .send()
+            .await
+            .expect("Failed to delete test queue");
+
+        message_counts
+    }
+
+    async fn message_counts(&self) -> (u64, u64) {
+        let output = self
+            .sqs_client
+            .get_queue_attributes()
+            .queue_url(&self.queue_url)
+            .attribute_names(QueueAttributeName::ApproximateNumberOfMessages)
+            .attribute_names(QueueAttributeName::ApproximateNumberOfMessagesNotVisible)
+            .send()
+            .await
+            .expect("Failed to get queue attributes");
+
+        let count = |name: QueueAttributeName| {
+            output
+                .attributes()
+                .and_then(|attributes| attributes.get(&name))
+                .map_or(0, |count| count.parse().expect("Invalid message count"))
+        };
+
+        (
+            count(QueueAttributeName::ApproximateNumberOfMessages),
+            count(QueueAttributeName::ApproximateNumberOfMessagesNotVisible),
+        )
+    }
+}
+
+impl Drop for ProcessorHarness {
+    fn drop(&mut self) {
+        // Stops the processor of a test that panicked before calling `stop`
+        self.shutdown.cancel();
+    }
+}
+
+/// Value of the counter `name` recorded by `snapshotter`, summed over its labels
+pub fn counter_value(snapshotter: &Snapshotter, name: &str) -> u64 {
+    snapshotter
+        .snapshot()
+        .into_vec()
+        .into_iter()
+        .filter(|(key, ..)| key.key().name() == name)
+        .map(|(.., value)| match value {
+            DebugValue::Counter(value) => value,
+            _ => 0,
+        })
+        .sum()
+}
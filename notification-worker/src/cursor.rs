@@ -0,0 +1,478 @@
+//! Persistence of the XMTP subscription cursors
+//!
+//! The XMTP stream starts at "now", so messages sent while the worker is down (e.g. during a
+//! deploy) would never be fanned out. The cursor of each topic is persisted to a Redis hash
+//! periodically and on shutdown. On startup the cursors are loaded and the messages sent since
+//! are queried from the XMTP node, so a restarted worker resumes where it left off.
+//!
+//! A cursor is a low-water mark: every message of the topic up to it was processed. Messages
+//! still being processed or that failed to process hold it back, so they are processed again
+//! after a restart rather than skipped.
+//!
+//! XMTP can only be queried by topic, so only topics whose cursor was persisted are resumed.
+//! Cursors are kept for a retention period, longer than the lookback, so quiet topics are
+//! resumed as well. Resuming is bounded by a max lookback: no topic is resumed from further back.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Mutex, PoisonError},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use futures::StreamExt;
+use redis::{aio::ConnectionManager, AsyncCommands};
+use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
+use tonic::transport::Channel;
+use tracing::{error, info, warn};
+
+use crate::xmtp::message_api::v1::{
+    message_api_client::MessageApiClient, Envelope, PagingInfo, QueryRequest, SortDirection,
+};
+
+/// Default Redis hash holding the cursor of each topic
+pub const DEFAULT_CURSOR_KEY: &str = "notification_worker:xmtp_cursors";
+/// Interval between two writes of the cursors to Redis
+pub const CURSOR_FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+/// Default time a topic's cursor is kept after it was last updated
+pub const DEFAULT_CURSOR_RETENTION: Duration = Duration::from_secs(24 * 60 * 60);
+
+const REDIS_TIMEOUT: Duration = Duration::from_secs(3);
+/// Envelopes queried per page when resuming a topic
+const RESUME_PAGE_SIZE: u32 = 100;
+/// Topics resumed concurrently
+const RESUME_CONCURRENCY: usize = 16;
+
+/// Cursor of each topic, not yet persisted
+#[derive(Debug, Default)]
+pub struct CursorTracker {
+    topics: Mutex<HashMap<String, TopicCursor>>,
+}
+
+/// Messages of a topic processed since its cursor was last persisted
+#[derive(Debug, Default)]
+struct TopicCursor {
+    /// Timestamps of the messages being processed, with how many share each
+    in_flight: BTreeMap<u64, usize>,
+    /// Latest timestamp of the processed messages
+    processed: Option<u64>,
+    /// Earliest timestamp of the messages that failed to process
+    failed: Option<u64>,
+    /// Whether the cursor changed since it was last taken
+    changed: bool,
+}
+
+impl TopicCursor {
+    /// Timestamp up to which every message was processed, `None` if no message was
+    fn low_water_mark(&self) -> Option<u64> {
+        let oldest_in_flight = self.in_flight.keys().next().copied();
+        match oldest_in_flight.into_iter().chain(self.failed).min() {
+            Some(held_back_at) => Some(held_back_at.saturating_sub(1)),
+            None => self.processed,
+        }
+    }
+
+    /// Whether the cursor no longer needs to be tracked once persisted
+    fn is_settled(&self) -> bool {
+        self.in_flight.is_empty() && self.failed.is_none()
+    }
+}
+
+impl CursorTracker {
+    /// Records that processing a message of `topic` sent at `timestamp_ns` started
+    pub fn start(&self, topic: &str, timestamp_ns: u64) {
+        let mut topics = self.topics.lock().unwrap_or_else(PoisonError::into_inner);
+        let cursor = topics.entry(topic.to_string()).or_default();
+        *cursor.in_flight.entry(timestamp_ns).or_default() += 1;
+        cursor.changed = true;
+    }
+
+    /// Records that a message of `topic` sent at `timestamp_ns` was processed, successfully or
+    /// not, see `start`
+    ///
+    /// The cursor of the topic never advances past a message that failed, until the worker
+    /// restarts and processes it again.
+    pub fn finish(&self, topic: &str, timestamp_ns: u64, succeeded: bool) {
+        let mut topics = self.topics.lock().unwrap_or_else(PoisonError::into_inner);
+        let cursor = topics.entry(topic.to_string()).or_default();
+        if let Some(count) = cursor.in_flight.get_mut(&timestamp_ns) {
+            *count -= 1;
+            if *count == 0 {
+                cursor.in_flight.remove(&timestamp_ns);
+            }
+        }
+        if succeeded {
+            cursor.processed = cursor.processed.max(Some(timestamp_ns));
+        } else {
+            cursor.failed = Some(cursor.failed.map_or(timestamp_ns, |t| t.min(timestamp_ns)));
+        }
+        cursor.changed = true;
+    }
+
+    /// Takes the cursors changed since the last call
+    #[must_use]
+    pub fn take_pending(&self) -> HashMap<String, u64> {
+        let mut topics = self.topics.lock().unwrap_or_else(PoisonError::into_inner);
+        let pending = topics
+            .iter_mut()
+            .filter(|(_, cursor)| cursor.changed)
+            .filter_map(|(topic, cursor)| {
+                cursor.changed = false;
+                Some((topic.clone(), cursor.low_water_mark()?))
+            })
+            .collect();
+        topics.retain(|_, cursor| !cursor.is_settled());
+
+        pending
+    }
+
+    /// Keeps `cursors` that couldn't be persisted for the next call to `take_pending`
+    fn restore(&self, cursors: HashMap<String, u64>) {
+        let mut topics = self.topics.lock().unwrap_or_else(PoisonError::into_inner);
+        for (topic, timestamp_ns) in cursors {
+            let cursor = topics.entry(topic).or_default();
+            // Cursors recorded since are newer, unless they are held back
+            if cursor.is_settled() {
+                cursor.processed = cursor.processed.max(Some(timestamp_ns));
+            }
+            cursor.changed = true;
+        }
+    }
+}
+
+/// Stores the cursor of each topic in a Redis hash
+///
+/// When each cursor was last updated is kept in a sorted set next to the hash, to prune the
+/// cursors not updated within the retention period. Both keys expire after the retention period
+/// as well, if no cursor is persisted in the meantime.
+#[derive(Clone)]
+pub struct RedisCursorStore {
+    connection_manager: ConnectionManager,
+    key: String,
+    retention: Duration,
+}
+
+impl RedisCursorStore {
+    /// Creates a store of the cursors in the Redis hash `key`, see `DEFAULT_CURSOR_RETENTION`
+    #[must_use]
+    pub const fn new(connection_manager: ConnectionManager, key: String) -> Self {
+        Self {
+            connection_manager,
+            key,
+            retention: DEFAULT_CURSOR_RETENTION,
+        }
+    }
+
+    /// Keeps each cursor for `retention` after it was last updated
+    #[must_use]
+    pub const fn with_retention(mut self, retention: Duration) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    /// Sorted set of when each cursor was last updated, in seconds since epoch
+    fn updated_at_key(&self) -> String {
+        format!("{}:updated_at", self.key)
+    }
+
+    /// Persists `cursors`, overwriting the stored cursor of their topics, and prunes the
+    /// cursors not updated within the retention period
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if Redis times out or fails
+    pub async fn save(&self, cursors: &HashMap<String, u64>) -> anyhow::Result<()> {
+        if cursors.is_empty() {
+            return Ok(());
+        }
+
+        let now_secs = unix_now_ns() / 1_000_000_000;
+        let retention_secs = i64::try_from(self.retention.as_secs()).unwrap_or(i64::MAX);
+        let items: Vec<(&String, u64)> = cursors.iter().map(|(k, v)| (k, *v)).collect();
+        let updated_at: Vec<(u64, &String)> = cursors.keys().map(|k| (now_secs, k)).collect();
+        let updated_at_key = self.updated_at_key();
+
+        let mut conn = self.connection_manager.clone();
+        timeout(
+            REDIS_TIMEOUT,
+            redis::pipe()
+                .atomic()
+                .hset_multiple(&self.key, &items)
+                .zadd_multiple(&updated_at_key, &updated_at)
+                .expire(&self.key, retention_secs)
+                .expire(&updated_at_key, retention_secs)
+                .query_async::<()>(&mut conn),
+        )
+        .await
+        .context("Redis timeout")?
+        .context("Failed to save cursors")?;
+
+        let stale: Vec<String> = timeout(
+            REDIS_TIMEOUT,
+            conn.zrangebyscore(
+                &updated_at_key,
+                "-inf",
+                now_secs.saturating_sub(self.retention.as_secs()),
+            ),
+        )
+        .await
+        .context("Redis timeout")?
+        .context("Failed to find stale cursors")?;
+        self.delete(&mut conn, &stale).await
+    }
+
+    /// Loads the cursors, deleting the ones older than the retention period
+    ///
+    /// Cursors older than `max_lookback` are moved up to `max_lookback` ago, so no topic is
+    /// resumed from further back.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if Redis times out or fails
+    pub async fn load(&self, max_lookback: Duration) -> anyhow::Result<HashMap<String, u64>> {
+        let mut conn = self.connection_manager.clone();
+        let stored: HashMap<String, u64> = timeout(REDIS_TIMEOUT, conn.hgetall(&self.key))
+            .await
+            .context("Redis timeout")?
+            .context("Failed to load cursors")?;
+
+        let oldest_ns = lookback_start_ns(self.retention);
+        let (cursors, stale): (HashMap<_, _>, HashMap<_, _>) = stored
+            .into_iter()
+            .partition(|(_, cursor)| *cursor >= oldest_ns);
+        self.delete(&mut conn, &stale.into_keys().collect::<Vec<_>>())
+            .await?;
+
+        let lookback_start_ns = lookback_start_ns(max_lookback);
+        Ok(cursors
+            .into_iter()
+            .map(|(topic, cursor)| (topic, cursor.max(lookback_start_ns)))
+            .collect())
+    }
+
+    /// Deletes the cursors of `topics`
+    async fn delete(&self, conn: &mut ConnectionManager, topics: &[String]) -> anyhow::Result<()> {
+        if topics.is_empty() {
+            return Ok(());
+        }
+
+        timeout(
+            REDIS_TIMEOUT,
+            redis::pipe()
+                .atomic()
+                .hdel(&self.key, topics)
+                .zrem(self.updated_at_key(), topics)
+                .query_async::<()>(conn),
+        )
+        .await
+        .context("Redis timeout")?
+        .context("Failed to delete stale cursors")
+    }
+}
+
+/// Current Unix timestamp in nanoseconds
+fn unix_now_ns() -> u64 {
+    let now_ns = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+
+    u64::try_from(now_ns).unwrap_or(u64::MAX)
+}
+
+/// Unix timestamp in nanoseconds `max_lookback` ago
+fn lookback_start_ns(max_lookback: Duration) -> u64 {
+    unix_now_ns().saturating_sub(u64::try_from(max_lookback.as_nanos()).unwrap_or(u64::MAX))
+}
+
+/// Persists the cursors changed in `tracker` to `store` every `interval`, and a last time once
+/// `shutdown` is cancelled
+pub async fn run(
+    tracker: &CursorTracker,
+    store: &RedisCursorStore,
+    interval: Duration,
+    shutdown: CancellationToken,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        let stopping = tokio::select! {
+            _ = ticker.tick() => false,
+            () = shutdown.cancelled() => true,
+        };
+
+        let cursors = tracker.take_pending();
+        if let Err(e) = store.save(&cursors).await {
+            warn!(
+                topics = cursors.len(),
+                "Failed to persist XMTP cursors: {e:#}"
+            );
+            tracker.restore(cursors);
+        }
+
+        if stopping {
+            break;
+        }
+    }
+
+    info!("XMTP cursor persistence stopped");
+}
+
+/// Sends the messages sent on each topic after its cursor in `cursors` to `message_tx`
+///
+/// Messages are queried up to the time resuming starts, the stream delivers the later ones.
+/// Up to `RESUME_CONCURRENCY` topics are queried at once. Returns the number of messages
+/// resumed.
+pub async fn resume(
+    client: MessageApiClient<Channel>,
+    cursors: HashMap<String, u64>,
+    message_tx: flume::Sender<Envelope>,
+    shutdown: CancellationToken,
+) -> usize {
+    let end_time_ns = unix_now_ns();
+    let topics = cursors.len();
+
+    let resumed = futures::stream::iter(cursors)
+        .map(|(topic, cursor)| {
+            let (client, message_tx, shutdown) = (client.clone(), &message_tx, &shutdown);
+            async move {
+                if shutdown.is_cancelled() {
+                    return 0;
+                }
+                resume_topic(client, &topic, cursor, end_time_ns, message_tx)
+                    .await
+                    .unwrap_or_else(|e| {
+                        error!(%topic, "Failed to resume XMTP topic: {e:#}");
+                        0
+                    })
+            }
+        })
+        .buffer_unordered(RESUME_CONCURRENCY)
+        .fold(0, |total, count| async move { total + count })
+        .await;
+
+    info!(topics, resumed, "Resumed XMTP topics from their cursors");
+    resumed
+}
+
+/// Sends the messages of `topic` sent after `cursor` and up to `end_time_ns` to `message_tx`
+async fn resume_topic(
+    mut client: MessageApiClient<Channel>,
+    topic: &str,
+    cursor: u64,
+    end_time_ns: u64,
+    message_tx: &flume::Sender<Envelope>,
+) -> anyhow::Result<usize> {
+    let mut paging_cursor = None;
+    let mut resumed = 0;
+
+    loop {
+        let response = client
+            .query(QueryRequest {
+                content_topics: vec![topic.to_string()],
+                // The message at the cursor was already processed
+                start_time_ns: cursor.saturating_add(1),
+                end_time_ns,
+                paging_info: Some(PagingInfo {
+                    limit: RESUME_PAGE_SIZE,
+                    cursor: paging_cursor,
+                    direction: SortDirection::Ascending.into(),
+                }),
+            })
+            .await?
+            .into_inner();
+
+        if response.envelopes.is_empty() {
+            return Ok(resumed);
+        }
+        for envelope in response.envelopes {
+            message_tx
+                .send_async(envelope)
+                .await
+                .context("Message channel closed")?;
+            resumed += 1;
+        }
+
+        paging_cursor = response.paging_info.and_then(|paging| paging.cursor);
+        if paging_cursor.is_none() {
+            return Ok(resumed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tracker_keeps_latest_cursor() {
+        let tracker = CursorTracker::default();
+
+        for (topic, timestamp_ns) in [("topic-a", 20), ("topic-a", 10), ("topic-b", 5)] {
+            tracker.start(topic, timestamp_ns);
+            tracker.finish(topic, timestamp_ns, true);
+        }
+
+        assert_eq!(
+            tracker.take_pending(),
+            HashMap::from([("topic-a".to_string(), 20), ("topic-b".to_string(), 5)])
+        );
+        assert!(tracker.take_pending().is_empty());
+    }
+
+    #[test]
+    fn test_cursor_is_held_back_by_messages_in_flight() {
+        let tracker = CursorTracker::default();
+
+        tracker.start("topic", 10);
+        tracker.start("topic", 20);
+        tracker.finish("topic", 20, true);
+        assert_eq!(
+            tracker.take_pending(),
+            HashMap::from([("topic".to_string(), 9)])
+        );
+
+        tracker.finish("topic", 10, true);
+        assert_eq!(
+            tracker.take_pending(),
+            HashMap::from([("topic".to_string(), 20)])
+        );
+    }
+
+    #[test]
+    fn test_cursor_does_not_advance_past_failed_messages() {
+        let tracker = CursorTracker::default();
+
+        tracker.start("topic", 10);
+        tracker.finish("topic", 10, false);
+        tracker.start("topic", 20);
+        tracker.finish("topic", 20, true);
+
+        assert_eq!(
+            tracker.take_pending(),
+            HashMap::from([("topic".to_string(), 9)])
+        );
+
+        // Still held back once persisted
+        tracker.start("topic", 30);
+        tracker.finish("topic", 30, true);
+        assert_eq!(
+            tracker.take_pending(),
+            HashMap::from([("topic".to_string(), 9)])
+        );
+    }
+
+    #[test]
+    fn test_cursors_failing_to_persist_are_restored() {
+        let tracker = CursorTracker::default();
+        tracker.start("topic", 10);
+        tracker.finish("topic", 10, true);
+
+        let pending = tracker.take_pending();
+        tracker.restore(pending.clone());
+
+        assert_eq!(tracker.take_pending(), pending);
+    }
+}
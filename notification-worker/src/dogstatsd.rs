@@ -0,0 +1,73 @@
+//! `DogStatsD` metrics recorder setup and known metric names
+
+use backend_storage::push_subscription::EXPIRED_SUBSCRIPTIONS_SKIPPED_METRIC;
+pub use common_types::dogstatsd::{init, is_initialized, GIT_REV, VERSION};
+
+/// Metrics reported by the notification worker
+///
+/// Emit metrics through these names rather than string literals, so a typo can't silently
+/// create a new, unmonitored metric.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    /// Counter of notifications sent to the notification queue
+    NotificationQueued,
+    /// Counter of messages pushed to the fan-out retry queue because subscriptions couldn't be
+    /// read or the enclave was down
+    FanOutDeferred,
+    /// Gauge of the approximate number of push subscriptions
+    SubscriptionsTotal,
+    /// Gauge of the approximate number of auth proofs
+    AuthProofsTotal,
+    /// Gauge of the fraction of time the message processors are busy
+    ProcessorUtilization,
+    /// Counter of expired subscriptions skipped while reading a topic, not yet deleted by TTL
+    ExpiredSubscriptionsSkipped,
+}
+
+impl Metric {
+    /// Every known metric
+    pub const ALL: &[Self] = &[
+        Self::NotificationQueued,
+        Self::FanOutDeferred,
+        Self::SubscriptionsTotal,
+        Self::AuthProofsTotal,
+        Self::ProcessorUtilization,
+        Self::ExpiredSubscriptionsSkipped,
+    ];
+
+    /// The metric name, without the global prefix
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::NotificationQueued => "notification_queued",
+            Self::FanOutDeferred => "fan_out_deferred",
+            Self::SubscriptionsTotal => "subscriptions_total",
+            Self::AuthProofsTotal => "auth_proofs_total",
+            Self::ProcessorUtilization => "processor_utilization",
+            Self::ExpiredSubscriptionsSkipped => EXPIRED_SUBSCRIPTIONS_SKIPPED_METRIC,
+        }
+    }
+
+    /// Looks up a known metric by name, `None` for unknown names
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|metric| metric.name() == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metric_names_are_known() {
+        for metric in Metric::ALL {
+            assert_eq!(Metric::from_name(metric.name()), Some(*metric));
+        }
+
+        assert_eq!(Metric::from_name("notification_queud"), None);
+    }
+}
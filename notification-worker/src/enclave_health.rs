@@ -0,0 +1,179 @@
+//! Circuit pausing notification enqueuing while the enclave is down
+//!
+//! Notifications queued while the enclave is down all fail downstream, so an extended outage
+//! would only build a backlog on the notification queue. The enclave's health is pinged through
+//! the enclave-worker's enclave health endpoint, which ignores the worker's other dependencies,
+//! and once it has been down for longer than a threshold the message processors stop receiving
+//! messages, before reading any subscription. Paused processors stop draining the message
+//! channel, which fills up and applies backpressure to the XMTP listener, and the fan-out retrier
+//! stops polling its queue. Processing resumes as soon as a ping succeeds again.
+
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+/// Interval between two pings of the enclave's health
+pub const ENCLAVE_HEALTH_PING_INTERVAL: Duration = Duration::from_secs(5);
+/// How long the enclave must be down before enqueuing is paused
+pub const ENCLAVE_DOWN_THRESHOLD: Duration = Duration::from_secs(60);
+/// Timeout of a single health ping
+const PING_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Health signal of the enclave shared by the message processors
+#[derive(Debug)]
+pub struct EnclaveHealth {
+    down_threshold: Duration,
+    /// When the current outage started, `None` while the enclave is up
+    down_since: Mutex<Option<Instant>>,
+    /// Whether enqueuing is paused
+    paused: watch::Sender<bool>,
+}
+
+impl EnclaveHealth {
+    /// Creates a signal, initially up, pausing enqueuing after `down_threshold` of downtime
+    #[must_use]
+    pub fn new(down_threshold: Duration) -> Self {
+        Self {
+            down_threshold,
+            down_since: Mutex::new(None),
+            paused: watch::Sender::new(false),
+        }
+    }
+
+    /// Records a successful health ping, resuming enqueuing if it was paused
+    pub fn record_up(&self) {
+        *self
+            .down_since
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner) = None;
+
+        if self.paused.send_replace(false) {
+            info!("Enclave recovered, resuming notification enqueuing");
+        }
+    }
+
+    /// Records a failed health ping, pausing enqueuing once the outage exceeds the threshold
+    pub fn record_down(&self) {
+        let down_for = self
+            .down_since
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .get_or_insert_with(Instant::now)
+            .elapsed();
+
+        if down_for >= self.down_threshold && !self.paused.send_replace(true) {
+            warn!(
+                ?down_for,
+                "Enclave down beyond threshold, pausing notification enqueuing"
+            );
+        }
+    }
+
+    /// Whether enqueuing is paused
+    #[must_use]
+    pub fn is_paused(&self) -> bool {
+        *self.paused.borrow()
+    }
+
+    /// Waits until enqueuing is no longer paused
+    pub async fn wait_until_available(&self) {
+        let mut paused = self.paused.subscribe();
+        // Can't fail, the sender lives as long as `self`
+        let _ = paused.wait_for(|paused| !paused).await;
+    }
+}
+
+impl Default for EnclaveHealth {
+    fn default() -> Self {
+        Self::new(ENCLAVE_DOWN_THRESHOLD)
+    }
+}
+
+/// Pings `health_url` every `interval` and records the result in `health`, until `shutdown` is
+/// cancelled
+///
+/// The enclave is considered up when the endpoint answers with a success status.
+pub async fn run(
+    health: &EnclaveHealth,
+    health_url: &str,
+    interval: Duration,
+    shutdown: CancellationToken,
+) {
+    let client = reqwest::Client::new();
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    info!(health_url, "Starting enclave health pings");
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            () = shutdown.cancelled() => break,
+        }
+
+        match client.get(health_url).timeout(PING_TIMEOUT).send().await {
+            Ok(response) if response.status().is_success() => health.record_up(),
+            Ok(response) => {
+                warn!(status = %response.status(), "Enclave health ping failed");
+                health.record_down();
+            }
+            Err(e) => {
+                warn!("Failed to ping enclave health: {e}");
+                health.record_down();
+            }
+        }
+    }
+
+    info!("Enclave health pings stopped");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pauses_once_down_beyond_threshold() {
+        let health = EnclaveHealth::new(Duration::ZERO);
+        assert!(!health.is_paused());
+
+        health.record_down();
+        assert!(health.is_paused());
+
+        health.record_up();
+        assert!(!health.is_paused());
+    }
+
+    #[test]
+    fn test_short_outage_does_not_pause() {
+        let health = EnclaveHealth::new(Duration::from_secs(3600));
+
+        health.record_down();
+        health.record_down();
+
+        assert!(!health.is_paused());
+    }
+
+    #[tokio::test]
+    async fn test_waiters_resume_on_recovery() {
+        let health = std::sync::Arc::new(EnclaveHealth::new(Duration::ZERO));
+        health.record_down();
+
+        let waiter = tokio::spawn({
+            let health = health.clone();
+            async move { health.wait_until_available().await }
+        });
+        tokio::task::yield_now().await;
+        assert!(!waiter.is_finished());
+
+        health.record_up();
+        tokio::time::timeout(Duration::from_secs(1), waiter)
+            .await
+            .expect("waiter should resume once the enclave is up")
+            .unwrap();
+    }
+}
@@ -6,6 +6,8 @@ use tokio::net::TcpListener;
 use tokio_util::sync::CancellationToken;
 use tracing::info;
 
+use crate::dogstatsd;
+
 /// Simple health check endpoint
 ///
 /// Returns 200 OK for now. In the future, this will check:
@@ -21,6 +23,7 @@ async fn health() -> impl IntoResponse {
         Json(json!({
             "status": "healthy",
             "service": "notification-worker",
+            "metrics_enabled": dogstatsd::is_initialized(),
         })),
     )
 }
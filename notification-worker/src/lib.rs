@@ -77,7 +77,14 @@ pub mod xmtp {
     }
 }
 
+pub mod cursor;
+pub mod dogstatsd;
+pub mod enclave_health;
 pub mod health;
+pub mod replay;
+pub mod shutdown;
+pub mod table_metrics;
 pub mod types;
+pub mod validate_config;
 pub mod worker;
 pub mod xmtp_utils;
@@ -1,33 +1,40 @@
-use metrics_exporter_dogstatsd::DogStatsDBuilder;
 use std::sync::Arc;
 use tracing::{error, info};
 
 use aws_sdk_dynamodb::Client as DynamoDbClient;
 use aws_sdk_sqs::Client as SqsClient;
 
+use backend_storage::processed_envelope::ProcessedEnvelopeStorage;
 use backend_storage::push_subscription::PushSubscriptionStorage;
-use backend_storage::queue::NotificationQueue;
-use notification_worker::health;
+use backend_storage::queue::{FanOutRetryQueue, NotificationQueue};
+use common_types::logging;
+use notification_worker::cursor::{RedisCursorStore, DEFAULT_CURSOR_KEY};
+use notification_worker::dogstatsd::{self, Metric};
+use notification_worker::enclave_health::{self, EnclaveHealth};
+use notification_worker::shutdown;
+use notification_worker::table_metrics::{self, MonitoredTable};
 use notification_worker::types::environment::Environment;
+use notification_worker::validate_config;
 use notification_worker::worker::XmtpWorker;
+use notification_worker::{health, replay};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     // Get environment
     let env = Environment::from_env();
-    info!("Starting XMTP Notification Worker in {:?} environment", env);
 
-    // Initialize Datadog tracing
-    // This will set up OpenTelemetry with Datadog exporter
-    // The _guard must be kept alive for the duration of the program
-    let (_guard, tracer_shutdown) = datadog_tracing::init()?;
+    // Initialize logging, JSON logs also set up OpenTelemetry with Datadog exporter
+    // The telemetry must be kept alive for the duration of the program
+    let mut telemetry = logging::init(env.log_format()?)?;
 
-    DogStatsDBuilder::default()
-        .set_global_prefix("world_chat.notification_worker")
-        .with_remote_address(env.metrics_addr())
-        .expect("failed to set remote address")
-        .install()
-        .expect("failed to install DogStatsD recorder");
+    info!(
+        version = dogstatsd::VERSION,
+        rev = dogstatsd::GIT_REV,
+        "Starting XMTP Notification Worker in {:?} environment",
+        env
+    );
+
+    dogstatsd::init("world_chat.notification_worker", env.metrics_addr());
 
     // Initialize rustls crypto provider
     rustls::crypto::ring::default_provider()
@@ -35,27 +42,164 @@ async fn main() -> anyhow::Result<()> {
         .expect("Failed to install rustls crypto provider");
 
     // Initialize notification queue
-    let sqs_client = Arc::new(SqsClient::new(&env.aws_config().await));
+    let aws_config = env.aws_config().await;
+    let sqs_client = Arc::new(SqsClient::new(&aws_config));
     let notification_queue = Arc::new(NotificationQueue::new(
-        sqs_client,
+        sqs_client.clone(),
         env.notification_queue_config(),
     ));
+    let fanout_retry_queue = Arc::new(FanOutRetryQueue::new(
+        sqs_client.clone(),
+        env.fanout_retry_queue_config(),
+    ));
 
     // Initialise Push Notification Subscription storage
-    let dynamodb_client = Arc::new(DynamoDbClient::new(&env.aws_config().await));
+    let dynamodb_client = Arc::new(DynamoDbClient::from_conf(
+        env.dynamodb_client_config(&aws_config),
+    ));
     let subscription_storage = Arc::new(PushSubscriptionStorage::new(
-        dynamodb_client,
+        dynamodb_client.clone(),
         env.push_subscription_table_name(),
+        env.push_subscription_push_id_index_name(),
     ));
 
+    // Optionally record processed messages, to replay them after fixing a fan-out bug
+    let processed_envelope_storage = env.processed_envelope_table_name().map(|table_name| {
+        Arc::new(ProcessedEnvelopeStorage::new(
+            dynamodb_client.clone(),
+            table_name,
+        ))
+    });
+
+    // Optionally restrict processing to test topics, outside of production
+    let topic_allowlist = env.topic_allowlist();
+    if let Some(allowlist) = &topic_allowlist {
+        info!(
+            topics = allowlist.len(),
+            "Processing only allowlisted topics"
+        );
+    }
+
+    // With `--validate-config`, check every dependency and exit without starting the worker
+    if validate_config::is_requested() {
+        let report = validate_config::validate(
+            &env,
+            &sqs_client,
+            &dynamodb_client,
+            notification_queue,
+            fanout_retry_queue,
+            subscription_storage,
+        )
+        .await;
+        report.log();
+        telemetry.shutdown();
+
+        anyhow::ensure!(report.is_ok(), "Configuration validation failed");
+        return Ok(());
+    }
+
+    // Optionally persist the XMTP cursors, so a restarted worker resumes where it left off
+    let cursor_store = match env.xmtp_cursor_redis_url() {
+        Some(url) => match connect_redis(&url).await {
+            Ok(connection_manager) => Some(
+                RedisCursorStore::new(connection_manager, DEFAULT_CURSOR_KEY.to_string())
+                    .with_retention(env.xmtp_cursor_retention()),
+            ),
+            Err(e) => {
+                error!("Failed to connect to cursor Redis, topics won't be resumed: {e}");
+                None
+            }
+        },
+        None => {
+            info!("XMTP_CURSOR_REDIS_URL not set, topics won't be resumed after a restart");
+            None
+        }
+    };
+
+    // Optionally skip topics without subscriptions, as long as their changes are published
+    let invalidation_client = match env.subscription_invalidation_redis_url() {
+        Some(url) => match redis::Client::open(url) {
+            Ok(client) => Some(client),
+            Err(e) => {
+                error!("Invalid invalidation Redis URL, not caching empty topics: {e}");
+                None
+            }
+        },
+        None => {
+            info!("SUBSCRIPTION_INVALIDATION_REDIS_URL not set, not caching empty topics");
+            None
+        }
+    };
+
+    // With `--replay <from> <to>`, replay the fan-outs of that window and exit
+    let replay_window = replay::requested().transpose()?;
+
     // Create and start the worker
-    match XmtpWorker::new(env.clone(), notification_queue, subscription_storage).await {
+    let enclave_health = Arc::new(EnclaveHealth::default());
+    match XmtpWorker::new(
+        env.clone(),
+        notification_queue,
+        fanout_retry_queue,
+        subscription_storage,
+        enclave_health.clone(),
+    )
+    .await
+    .map(|worker| {
+        worker
+            .with_processed_envelope_storage(processed_envelope_storage)
+            .with_topic_allowlist(topic_allowlist)
+            .with_cursor_store(cursor_store)
+            .with_subscription_invalidations(invalidation_client)
+    }) {
         Ok(worker) => {
             info!("Successfully connected to XMTP node");
 
+            if let Some(window) = replay_window {
+                let report = worker.replay(window).await;
+                telemetry.shutdown();
+                info!(report = ?report?, "Replay complete");
+                return Ok(());
+            }
+
             // Get shutdown token for signal handling
             let shutdown_token = worker.shutdown_token();
 
+            // Pause processing while the enclave is down
+            if let Some(health_url) = env.enclave_worker_health_url() {
+                let health_shutdown = shutdown_token.clone();
+                tokio::spawn(async move {
+                    enclave_health::run(
+                        &enclave_health,
+                        &health_url,
+                        enclave_health::ENCLAVE_HEALTH_PING_INTERVAL,
+                        health_shutdown,
+                    )
+                    .await;
+                });
+            } else {
+                info!(
+                    "ENCLAVE_WORKER_HEALTH_URL not set, processing won't pause on enclave outages"
+                );
+            }
+
+            // Report table sizes, to spot unbounded growth if TTL deletion breaks
+            let mut monitored_tables = vec![MonitoredTable {
+                metric: Metric::SubscriptionsTotal,
+                table_name: env.push_subscription_table_name(),
+            }];
+            if let Some(table_name) = env.auth_proof_table_name() {
+                monitored_tables.push(MonitoredTable {
+                    metric: Metric::AuthProofsTotal,
+                    table_name,
+                });
+            }
+            tokio::spawn(table_metrics::run(
+                dynamodb_client,
+                monitored_tables,
+                table_metrics::TABLE_METRICS_INTERVAL,
+                shutdown_token.clone(),
+            ));
+
             // Start health check server
             let health_shutdown = shutdown_token.clone();
             tokio::spawn(async move {
@@ -78,10 +222,29 @@ async fn main() -> anyhow::Result<()> {
                 }
             });
 
-            // Run the worker
-            if let Err(e) = worker.start().await {
-                error!("Worker error: {}", e);
-                return Err(e);
+            // Run the worker, exiting anyway if it doesn't stop in time once shutdown starts
+            let running = worker.running_components();
+            match shutdown::run_with_deadline(
+                worker.start(),
+                &shutdown_token,
+                env.shutdown_timeout(),
+            )
+            .await
+            {
+                Some(Ok(())) => {}
+                Some(Err(e)) => {
+                    error!("Worker error: {}", e);
+                    return Err(e);
+                }
+                None => {
+                    error!(
+                        still_running = ?running.names(),
+                        "Worker didn't stop before the shutdown deadline, exiting anyway"
+                    );
+                    telemetry.shutdown();
+                    // Stuck tasks could also block the runtime's shutdown, exit right away
+                    std::process::exit(1);
+                }
             }
         }
         Err(e) => {
@@ -92,8 +255,13 @@ async fn main() -> anyhow::Result<()> {
 
     info!("XMTP Notification Worker stopped");
 
-    // Ensure the tracer is properly shut down
-    tracer_shutdown.shutdown();
+    // Ensure the tracer and logs are properly flushed
+    telemetry.shutdown();
 
     Ok(())
 }
+
+/// Connects to the Redis at `url`
+async fn connect_redis(url: &str) -> redis::RedisResult<redis::aio::ConnectionManager> {
+    redis::aio::ConnectionManager::new(redis::Client::open(url)?).await
+}
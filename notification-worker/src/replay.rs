@@ -0,0 +1,233 @@
+//! Replay of recent fan-outs
+//!
+//! Started with `--replay <from> <to>` (inclusive Unix timestamps in seconds), the binary fans
+//! out again the messages processed in that window which didn't queue a notification, then
+//! exits. Use it after deploying a fix for a fan-out bug to recover the missed notifications.
+//! Messages that already queued a notification are skipped, so replaying never notifies twice.
+//!
+//! Replays rely on the processed envelope storage, see
+//! `Environment::processed_envelope_table_name`. Only metadata is stored, the envelopes are
+//! fetched again from XMTP.
+
+use std::future::Future;
+
+use anyhow::{bail, Context};
+use backend_storage::processed_envelope::{ProcessedEnvelope, ProcessedEnvelopeStorage};
+use tonic::transport::Channel;
+use tracing::{error, info, warn};
+
+use crate::{
+    worker::message_processor::{envelope_message_id, MessageProcessor},
+    xmtp::message_api::v1::{message_api_client::MessageApiClient, Envelope, QueryRequest},
+};
+
+/// Command line flag that replays the fan-outs of a time window and exits
+pub const REPLAY_FLAG: &str = "--replay";
+
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+
+/// Time window of the messages to replay
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReplayWindow {
+    /// Start of the window (in nanoseconds, inclusive)
+    pub from_ns: u64,
+    /// End of the window (in nanoseconds, inclusive)
+    pub to_ns: u64,
+}
+
+impl ReplayWindow {
+    /// Parses the window following `--replay` in `args`, `None` if no replay was requested
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the window isn't two ordered Unix timestamps in seconds
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Option<anyhow::Result<Self>> {
+        let mut args = args.into_iter().skip_while(|arg| arg != REPLAY_FLAG);
+        // Skip the flag itself
+        args.next()?;
+
+        Some(Self::parse(args.next(), args.next()))
+    }
+
+    fn parse(from: Option<String>, to: Option<String>) -> anyhow::Result<Self> {
+        let (Some(from), Some(to)) = (from, to) else {
+            bail!("{REPLAY_FLAG} expects <from> <to> Unix timestamps in seconds");
+        };
+        let from_ns = seconds_to_nanos(&from).context("Invalid replay window start")?;
+        let to_ns = seconds_to_nanos(&to).context("Invalid replay window end")?;
+        if from_ns > to_ns {
+            bail!("Replay window starts after it ends");
+        }
+
+        Ok(Self {
+            from_ns,
+            // Include the whole last second
+            to_ns: to_ns.saturating_add(NANOS_PER_SECOND - 1),
+        })
+    }
+}
+
+/// Converts a Unix timestamp in seconds to nanoseconds
+fn seconds_to_nanos(seconds: &str) -> anyhow::Result<u64> {
+    seconds
+        .parse::<u64>()?
+        .checked_mul(NANOS_PER_SECOND)
+        .context("Timestamp out of range")
+}
+
+/// The replay window, if the binary was started with `--replay`
+#[must_use]
+pub fn requested() -> Option<anyhow::Result<ReplayWindow>> {
+    ReplayWindow::from_args(std::env::args().skip(1))
+}
+
+/// Source of the envelopes of processed messages
+pub trait EnvelopeSource {
+    /// Fetches the envelope of `processed`, `None` if it can't be found anymore
+    fn fetch(
+        &self,
+        processed: &ProcessedEnvelope,
+    ) -> impl Future<Output = anyhow::Result<Option<Envelope>>> + Send;
+}
+
+/// Fetches envelopes from the XMTP node
+pub struct XmtpEnvelopeSource {
+    client: MessageApiClient<Channel>,
+}
+
+impl XmtpEnvelopeSource {
+    /// Creates a source querying the XMTP node of `client`
+    #[must_use]
+    pub const fn new(client: MessageApiClient<Channel>) -> Self {
+        Self { client }
+    }
+}
+
+impl EnvelopeSource for XmtpEnvelopeSource {
+    async fn fetch(&self, processed: &ProcessedEnvelope) -> anyhow::Result<Option<Envelope>> {
+        // Widen the window by a nanosecond on each side, the message ID picks the envelope
+        let response = self
+            .client
+            .clone()
+            .query(QueryRequest {
+                content_topics: vec![processed.topic.clone()],
+                start_time_ns: processed.timestamp_ns.saturating_sub(1),
+                end_time_ns: processed.timestamp_ns.saturating_add(1),
+                paging_info: None,
+            })
+            .await?
+            .into_inner();
+
+        Ok(response
+            .envelopes
+            .into_iter()
+            .find(|envelope| envelope_message_id(envelope) == processed.message_id))
+    }
+}
+
+/// Outcome of a replay
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ReplayReport {
+    /// Messages that queued a notification when replayed
+    pub replayed: usize,
+    /// Messages skipped because they already queued a notification
+    pub duplicates: usize,
+    /// Messages replayed without queuing a notification, e.g. without recipients
+    pub without_notification: usize,
+    /// Messages whose envelope couldn't be found anymore
+    pub missing: usize,
+    /// Messages that failed to replay
+    pub failed: usize,
+}
+
+/// Fans out again the messages processed in `window` which didn't queue a notification
+///
+/// # Errors
+///
+/// Returns an error if the processed messages of the window can't be read
+pub async fn replay(
+    storage: &ProcessedEnvelopeStorage,
+    processor: &MessageProcessor,
+    source: &impl EnvelopeSource,
+    window: ReplayWindow,
+) -> anyhow::Result<ReplayReport> {
+    let processed = storage
+        .get_window(window.from_ns, window.to_ns)
+        .await
+        .context("Failed to read processed envelopes")?;
+    info!(messages = processed.len(), ?window, "Replaying fan-outs");
+
+    let mut report = ReplayReport::default();
+    for processed in processed {
+        if processed.notified {
+            report.duplicates += 1;
+            continue;
+        }
+
+        let envelope = match source.fetch(&processed).await {
+            Ok(Some(envelope)) => envelope,
+            Ok(None) => {
+                warn!(message_id = %processed.message_id, "Envelope to replay not found");
+                report.missing += 1;
+                continue;
+            }
+            Err(e) => {
+                error!(message_id = %processed.message_id, "Failed to fetch envelope: {e}");
+                report.failed += 1;
+                continue;
+            }
+        };
+
+        match processor.fan_out(&envelope).await {
+            Ok(true) => {
+                report.replayed += 1;
+                if let Err(e) = storage.mark_notified(&processed).await {
+                    error!(
+                        message_id = %processed.message_id,
+                        "Failed to mark replayed envelope notified, a new replay would notify it \
+                         again: {e}"
+                    );
+                }
+            }
+            Ok(false) => report.without_notification += 1,
+            Err(e) => {
+                error!(message_id = %processed.message_id, "Failed to replay fan-out: {e}");
+                report.failed += 1;
+            }
+        }
+    }
+
+    info!(?report, "Replay finished");
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn test_parses_replay_window() {
+        let window = ReplayWindow::from_args(args(&["--replay", "10", "20"]))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(window.from_ns, 10 * NANOS_PER_SECOND);
+        assert_eq!(window.to_ns, 21 * NANOS_PER_SECOND - 1);
+    }
+
+    #[test]
+    fn test_no_replay_without_flag() {
+        assert!(ReplayWindow::from_args(args(&["--validate-config"])).is_none());
+    }
+
+    #[test]
+    fn test_rejects_invalid_replay_window() {
+        assert!(ReplayWindow::parse(Some("20".to_string()), Some("10".to_string())).is_err());
+        assert!(ReplayWindow::parse(Some("10".to_string()), None).is_err());
+        assert!(ReplayWindow::parse(Some("ten".to_string()), Some("20".to_string())).is_err());
+    }
+}
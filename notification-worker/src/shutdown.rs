@@ -0,0 +1,141 @@
+//! Bounded shutdown of the notification worker
+//!
+//! Once shutdown starts the worker drains its components, but a stuck XMTP stream or processor
+//! could keep it from ever stopping and the container would be killed at the end of its grace
+//! period without a trace. The worker is instead given a deadline, after which the components
+//! still running are logged and the process exits anyway.
+
+use std::{
+    collections::BTreeSet,
+    future::Future,
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+
+/// Default time the worker is given to stop once shutdown starts, below the usual 30 seconds
+/// grace period of a container
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(25);
+
+/// Names of the worker's components that are still running
+#[derive(Debug, Clone, Default)]
+pub struct RunningComponents(Arc<Mutex<BTreeSet<String>>>);
+
+impl RunningComponents {
+    /// Marks the component `name` as running until `future` completes or is dropped
+    pub fn track<F: Future>(
+        &self,
+        name: impl Into<String>,
+        future: F,
+    ) -> impl Future<Output = F::Output> {
+        let guard = RunningGuard::new(self.clone(), name.into());
+
+        async move {
+            let _guard = guard;
+            future.await
+        }
+    }
+
+    /// Names of the components still running, sorted
+    #[must_use]
+    pub fn names(&self) -> Vec<String> {
+        self.lock().iter().cloned().collect()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeSet<String>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Marks a component as running while alive
+struct RunningGuard {
+    components: RunningComponents,
+    name: String,
+}
+
+impl RunningGuard {
+    fn new(components: RunningComponents, name: String) -> Self {
+        components.lock().insert(name.clone());
+        Self { components, name }
+    }
+}
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        self.components.lock().remove(&self.name);
+    }
+}
+
+/// Runs `work` to completion, unless it's still running `timeout` after `shutdown` is cancelled
+///
+/// Returns the output of `work`, or `None` if the deadline passed first.
+pub async fn run_with_deadline<T>(
+    work: impl Future<Output = T>,
+    shutdown: &CancellationToken,
+    timeout: Duration,
+) -> Option<T> {
+    tokio::pin!(work);
+
+    tokio::select! {
+        output = &mut work => return Some(output),
+        () = shutdown.cancelled() => {}
+    }
+
+    info!(?timeout, "Shutdown started, waiting for the worker to stop");
+    tokio::time::timeout(timeout, work).await.ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_shutdown_completes_within_deadline_when_processor_hangs() {
+        let running = RunningComponents::default();
+        let shutdown = CancellationToken::new();
+
+        // One processor stops on shutdown, the other hangs
+        let stopping = tokio::spawn(running.track("message_processor_0", {
+            let shutdown = shutdown.clone();
+            async move { shutdown.cancelled().await }
+        }));
+        let hanging =
+            tokio::spawn(running.track("message_processor_1", std::future::pending::<()>()));
+        let work = async {
+            let _ = stopping.await;
+            let _ = hanging.await;
+        };
+
+        shutdown.cancel();
+        let started_at = Instant::now();
+        let output = run_with_deadline(work, &shutdown, Duration::from_millis(50)).await;
+
+        assert!(output.is_none());
+        assert!(started_at.elapsed() < Duration::from_secs(1));
+        assert_eq!(running.names(), vec!["message_processor_1".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_returns_output_when_work_stops_in_time() {
+        let running = RunningComponents::default();
+        let shutdown = CancellationToken::new();
+
+        let work = running.track("xmtp_listener", {
+            let shutdown = shutdown.clone();
+            async move {
+                shutdown.cancelled().await;
+                "stopped"
+            }
+        });
+
+        shutdown.cancel();
+        let output = run_with_deadline(work, &shutdown, Duration::from_secs(5)).await;
+
+        assert_eq!(output, Some("stopped"));
+        assert!(running.names().is_empty());
+    }
+}
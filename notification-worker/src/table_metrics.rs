@@ -0,0 +1,79 @@
+//! Size gauges of the `DynamoDB` tables
+//!
+//! Subscriptions and auth proofs are only cleaned up by `DynamoDB`'s TTL deletion, so if it ever
+//! breaks the tables grow without bound. Their item counts are reported periodically as an early
+//! warning. Counts come from `DescribeTable`, which `DynamoDB` refreshes about every six hours,
+//! so they're approximate but free to read.
+
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Context;
+use aws_sdk_dynamodb::Client as DynamoDbClient;
+use metrics::gauge;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+use crate::dogstatsd::Metric;
+
+/// Interval between two reports of the table sizes
+pub const TABLE_METRICS_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+/// Table whose size is reported as a gauge
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MonitoredTable {
+    /// Gauge the item count is reported to
+    pub metric: Metric,
+    /// Name of the `DynamoDB` table
+    pub table_name: String,
+}
+
+/// Reports the size of every table in `tables` every `interval`, until `shutdown` is cancelled
+pub async fn run(
+    client: Arc<DynamoDbClient>,
+    tables: Vec<MonitoredTable>,
+    interval: Duration,
+    shutdown: CancellationToken,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            () = shutdown.cancelled() => break,
+        }
+
+        for table in &tables {
+            if let Err(e) = record_table_size(&client, table).await {
+                warn!(table = %table.table_name, "Failed to report table size: {e:#}");
+            }
+        }
+    }
+
+    info!("Table metrics reporting stopped");
+}
+
+/// Reports the approximate item count of `table` to its gauge, returning the count
+///
+/// # Errors
+///
+/// Returns an error if the table can't be described
+pub async fn record_table_size(
+    client: &DynamoDbClient,
+    table: &MonitoredTable,
+) -> anyhow::Result<i64> {
+    let item_count = client
+        .describe_table()
+        .table_name(&table.table_name)
+        .send()
+        .await
+        .context("Failed to describe table")?
+        .table
+        .and_then(|description| description.item_count)
+        .context("Table description has no item count")?;
+
+    #[allow(clippy::cast_precision_loss)] // Counts are far below 2^52
+    gauge!(table.metric.name()).set(item_count as f64);
+
+    Ok(item_count)
+}
@@ -1,14 +1,47 @@
 //! Environment configuration for different deployment stages
 
-use std::{env, time::Duration};
+use std::{collections::HashSet, env, time::Duration};
 
-use aws_config::{retry::RetryConfig, timeout::TimeoutConfig, BehaviorVersion};
-use backend_storage::queue::QueueConfig;
+use backend_storage::{ids::TopicId, queue::QueueConfig};
+use common_types::{
+    aws,
+    logging::{InvalidLogFormat, LogFormat},
+};
+
+use crate::shutdown::DEFAULT_SHUTDOWN_TIMEOUT;
 
 const DEFAULT_RECONNECT_DELAY_MS: u64 = 100;
 const DEFAULT_MAX_RECONNECT_DELAY_MS: u64 = 30_000;
 const DEFAULT_REQUEST_TIMEOUT_MS: u64 = 30_000;
 const DEFAULT_CONNECTION_TIMEOUT_MS: u64 = 5_000;
+/// Default interval between two HTTP/2 pings of the XMTP connection, below the idle timeout of
+/// common load balancers
+const DEFAULT_KEEP_ALIVE_INTERVAL_MS: u64 = 30_000;
+/// Default time to wait for a ping acknowledgement before the XMTP connection is considered dead
+const DEFAULT_KEEP_ALIVE_TIMEOUT_MS: u64 = 10_000;
+/// Default limit on how far back a restarted worker resumes the XMTP topics
+const DEFAULT_CURSOR_MAX_LOOKBACK_SECS: u64 = 10 * 60;
+/// Default time the XMTP cursor of a topic is kept after it was last updated
+const DEFAULT_CURSOR_RETENTION_SECS: u64 = 24 * 60 * 60;
+
+/// Default number of attempts of a `DynamoDB` request, fewer than other AWS requests as a failed
+/// fan-out is deferred to the fan-out retry queue
+const DEFAULT_DYNAMODB_MAX_ATTEMPTS: u32 = 2;
+/// Default timeout of a `DynamoDB` operation, including its retries
+const DEFAULT_DYNAMODB_OPERATION_TIMEOUT_MS: u64 = 5_000;
+/// Default timeout of a single attempt of a `DynamoDB` operation
+const DEFAULT_DYNAMODB_OPERATION_ATTEMPT_TIMEOUT_MS: u64 = 2_000;
+
+/// HTTP/2 keepalive of the XMTP connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct XmtpKeepAlive {
+    /// Interval between two pings
+    pub interval: Duration,
+    /// Time to wait for a ping acknowledgement before closing the connection
+    pub timeout: Duration,
+    /// Whether to ping while no stream is open
+    pub while_idle: bool,
+}
 
 /// Application environment configuration
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -42,6 +75,15 @@ impl Environment {
         }
     }
 
+    /// Log format, from `LOG_FORMAT` or pretty in development and JSON otherwise
+    ///
+    /// # Errors
+    ///
+    /// If `LOG_FORMAT` holds an unknown format
+    pub fn log_format(&self) -> Result<LogFormat, InvalidLogFormat> {
+        LogFormat::from_env(LogFormat::default_for(matches!(self, Self::Development)))
+    }
+
     /// Returns the XMTP gRPC endpoint for this environment
     ///
     /// # Panics
@@ -76,19 +118,26 @@ impl Environment {
         }
     }
 
-    /// Returns the default number of workers for this environment
+    /// Returns the number of message processors
+    ///
+    /// Defaults to a per-environment value, overridable with `NUM_WORKERS` to tune it against the
+    /// `processor_utilization` gauge.
     #[must_use]
-    pub const fn num_workers(&self) -> usize {
-        match self {
-            Self::Production => 50,
-            Self::Staging => 20,
-            Self::Development => 10,
-        }
+    pub fn num_workers(&self) -> usize {
+        env::var("NUM_WORKERS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|&num_workers| num_workers > 0)
+            .unwrap_or(match self {
+                Self::Production => 50,
+                Self::Staging => 20,
+                Self::Development => 10,
+            })
     }
 
     /// Returns the channel capacity for this environment
     #[must_use]
-    pub const fn channel_capacity(&self) -> usize {
+    pub fn channel_capacity(&self) -> usize {
         self.num_workers() * 2
     }
 
@@ -132,6 +181,114 @@ impl Environment {
             .unwrap_or(DEFAULT_CONNECTION_TIMEOUT_MS)
     }
 
+    /// HTTP/2 keepalive of the XMTP connection, so idle streams aren't silently dropped by load
+    /// balancers and dead connections are detected
+    ///
+    /// Configured with `XMTP_KEEP_ALIVE_INTERVAL_MS`, `XMTP_KEEP_ALIVE_TIMEOUT_MS` and
+    /// `XMTP_KEEP_ALIVE_WHILE_IDLE`. An interval of 0 disables keepalive.
+    #[must_use]
+    pub fn xmtp_keep_alive(&self) -> Option<XmtpKeepAlive> {
+        let interval_ms = env::var("XMTP_KEEP_ALIVE_INTERVAL_MS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_KEEP_ALIVE_INTERVAL_MS);
+        if interval_ms == 0 {
+            return None;
+        }
+
+        Some(XmtpKeepAlive {
+            interval: Duration::from_millis(interval_ms),
+            timeout: Duration::from_millis(
+                env::var("XMTP_KEEP_ALIVE_TIMEOUT_MS")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(DEFAULT_KEEP_ALIVE_TIMEOUT_MS),
+            ),
+            while_idle: env::var("XMTP_KEEP_ALIVE_WHILE_IDLE")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(true),
+        })
+    }
+
+    /// Redis URL the XMTP cursors are persisted to, so a restarted worker resumes where it left
+    /// off, see `cursor`
+    ///
+    /// Read from `XMTP_CURSOR_REDIS_URL`, cursors aren't persisted if `None`.
+    #[must_use]
+    pub fn xmtp_cursor_redis_url(&self) -> Option<String> {
+        env::var("XMTP_CURSOR_REDIS_URL").ok()
+    }
+
+    /// Redis URL the backend publishes subscription invalidation events on, to skip reading
+    /// topics known to have no subscriptions, see `worker::empty_topics`
+    ///
+    /// Read from `SUBSCRIPTION_INVALIDATION_REDIS_URL`, must be the backend's `REDIS_URL`. Every
+    /// topic is read if `None`.
+    #[must_use]
+    pub fn subscription_invalidation_redis_url(&self) -> Option<String> {
+        env::var("SUBSCRIPTION_INVALIDATION_REDIS_URL").ok()
+    }
+
+    /// How far back a restarted worker resumes the XMTP topics from their persisted cursors
+    ///
+    /// Read from `XMTP_CURSOR_MAX_LOOKBACK_SECS`, defaults to 10 minutes.
+    #[must_use]
+    pub fn xmtp_cursor_max_lookback(&self) -> Duration {
+        Duration::from_secs(
+            env::var("XMTP_CURSOR_MAX_LOOKBACK_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_CURSOR_MAX_LOOKBACK_SECS),
+        )
+    }
+
+    /// How long the XMTP cursor of a topic is kept after it was last updated, only topics with
+    /// a cursor are resumed after a restart
+    ///
+    /// Read from `XMTP_CURSOR_RETENTION_SECS`, defaults to 24 hours.
+    #[must_use]
+    pub fn xmtp_cursor_retention(&self) -> Duration {
+        Duration::from_secs(
+            env::var("XMTP_CURSOR_RETENTION_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_CURSOR_RETENTION_SECS),
+        )
+    }
+
+    /// Path of the PEM root certificate the XMTP node's certificate is verified against
+    ///
+    /// Read from `XMTP_TLS_CA_CERT_PATH`, for nodes with a private CA. The webpki roots are used
+    /// if `None`.
+    #[must_use]
+    pub fn xmtp_tls_ca_cert_path(&self) -> Option<String> {
+        env::var("XMTP_TLS_CA_CERT_PATH").ok()
+    }
+
+    /// Paths of the PEM client certificate and key the worker authenticates to the XMTP node
+    /// with (mTLS)
+    ///
+    /// Read from `XMTP_TLS_CLIENT_CERT_PATH` and `XMTP_TLS_CLIENT_KEY_PATH`, no client
+    /// certificate is sent if `None`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if only one of the certificate and key paths is set
+    #[must_use]
+    pub fn xmtp_tls_client_identity_paths(&self) -> Option<(String, String)> {
+        match (
+            env::var("XMTP_TLS_CLIENT_CERT_PATH").ok(),
+            env::var("XMTP_TLS_CLIENT_KEY_PATH").ok(),
+        ) {
+            (Some(cert_path), Some(key_path)) => Some((cert_path, key_path)),
+            (None, None) => None,
+            _ => panic!(
+                "XMTP_TLS_CLIENT_CERT_PATH and XMTP_TLS_CLIENT_KEY_PATH must be set together"
+            ),
+        }
+    }
+
     /// Returns the endpoint URL to use for AWS services
     #[must_use]
     pub const fn override_aws_endpoint_url(&self) -> Option<&str> {
@@ -143,27 +300,51 @@ impl Environment {
         }
     }
 
-    /// AWS configuration with retry and timeout settings
+    /// AWS configuration shared by every SDK client, with the environment's retry, timeout and
+    /// endpoint settings
     pub async fn aws_config(&self) -> aws_config::SdkConfig {
-        let retry_config = RetryConfig::standard()
-            .with_max_attempts(3)
-            .with_initial_backoff(Duration::from_millis(50));
+        aws::load_config(self.override_aws_endpoint_url()).await
+    }
+
+    /// Applies the retry, timeout and endpoint settings of the environment to `aws_config`
+    #[must_use]
+    pub fn tune_aws_config(&self, aws_config: &aws_config::SdkConfig) -> aws_config::SdkConfig {
+        aws::tune_config(aws_config, self.override_aws_endpoint_url())
+    }
 
-        let timeout_config = TimeoutConfig::builder()
-            .operation_timeout(Duration::from_secs(30))
+    /// `DynamoDB` service configuration, derived from the shared AWS configuration
+    ///
+    /// `DynamoDB` fails faster than the other AWS services, so a slow table doesn't hold up the
+    /// fan-outs. `DYNAMODB_MAX_ATTEMPTS`, `DYNAMODB_OPERATION_TIMEOUT_MS` and
+    /// `DYNAMODB_OPERATION_ATTEMPT_TIMEOUT_MS` override its retry and timeout settings.
+    #[must_use]
+    pub fn dynamodb_client_config(
+        &self,
+        aws_config: &aws_config::SdkConfig,
+    ) -> aws_sdk_dynamodb::Config {
+        let retry_config = aws::retry_config().with_max_attempts(
+            env::var("DYNAMODB_MAX_ATTEMPTS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_DYNAMODB_MAX_ATTEMPTS),
+        );
+        let timeout_config = aws::timeout_config()
+            .to_builder()
+            .operation_timeout(aws::duration_ms_from_env(
+                "DYNAMODB_OPERATION_TIMEOUT_MS",
+                DEFAULT_DYNAMODB_OPERATION_TIMEOUT_MS,
+            ))
+            .operation_attempt_timeout(aws::duration_ms_from_env(
+                "DYNAMODB_OPERATION_ATTEMPT_TIMEOUT_MS",
+                DEFAULT_DYNAMODB_OPERATION_ATTEMPT_TIMEOUT_MS,
+            ))
             .build();
 
-        let mut config_builder = aws_config::load_defaults(BehaviorVersion::latest())
-            .await
+        aws_sdk_dynamodb::Config::from(aws_config)
             .to_builder()
             .retry_config(retry_config)
-            .timeout_config(timeout_config);
-
-        if let Some(endpoint_url) = self.override_aws_endpoint_url() {
-            config_builder = config_builder.endpoint_url(endpoint_url);
-        }
-
-        config_builder.build()
+            .timeout_config(timeout_config)
+            .build()
     }
 
     /// Returns the notification queue configuration
@@ -171,6 +352,7 @@ impl Environment {
     /// # Panics
     ///
     /// Panics if the `NOTIFICATION_QUEUE_URL` environment variable is not set in production/staging
+    /// or the configuration is outside SQS limits
     #[must_use]
     pub fn notification_queue_config(&self) -> QueueConfig {
         let queue_url = match self {
@@ -181,12 +363,38 @@ impl Environment {
             }
         };
 
-        QueueConfig {
-            queue_url,
-            default_max_messages: 10,
-            default_visibility_timeout: 60, // 60 seconds - Longer timeout for notifications
-            default_wait_time_seconds: 20,  // Enable long polling by default
-        }
+        // Longer timeout for notifications
+        let visibility_timeout = 60;
+        // Enable long polling by default
+        let wait_time_seconds = 20;
+
+        QueueConfig::new(queue_url, 10, visibility_timeout, wait_time_seconds)
+            .expect("Invalid notification queue configuration")
+    }
+
+    /// Returns the fan-out retry queue configuration
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `FANOUT_RETRY_QUEUE_URL` environment variable is not set in production/staging
+    /// or the configuration is outside SQS limits
+    #[must_use]
+    pub fn fanout_retry_queue_config(&self) -> QueueConfig {
+        let queue_url = match self {
+            Self::Production | Self::Staging => env::var("FANOUT_RETRY_QUEUE_URL")
+                .expect("FANOUT_RETRY_QUEUE_URL environment variable is not set"),
+            Self::Development => {
+                "http://localhost:4566/000000000000/fanout-retry-queue.fifo".to_string()
+            }
+        };
+
+        // Delay before a failed fan-out is retried
+        let visibility_timeout = 60;
+        // Enable long polling by default
+        let wait_time_seconds = 20;
+
+        QueueConfig::new(queue_url, 10, visibility_timeout, wait_time_seconds)
+            .expect("Invalid fan-out retry queue configuration")
     }
 
     /// Returns the Push Notification Subscription storage table name
@@ -203,6 +411,91 @@ impl Environment {
         }
     }
 
+    /// Returns the Dynamo DB GSI name for the push subscriptions `encrypted_push_id` index
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `DYNAMODB_PUSH_PUSH_ID_INDEX_NAME` environment variable is not set in production/staging
+    #[must_use]
+    pub fn push_subscription_push_id_index_name(&self) -> String {
+        match self {
+            Self::Production | Self::Staging => env::var("DYNAMODB_PUSH_PUSH_ID_INDEX_NAME")
+                .expect("DYNAMODB_PUSH_PUSH_ID_INDEX_NAME environment variable is not set"),
+            Self::Development => "encrypted-push-id-index".to_string(),
+        }
+    }
+
+    /// Returns the auth proof table name, whose size is reported next to the subscriptions'
+    ///
+    /// Read from `DYNAMODB_AUTH_TABLE_NAME`, `None` skips reporting it.
+    #[must_use]
+    pub fn auth_proof_table_name(&self) -> Option<String> {
+        env::var("DYNAMODB_AUTH_TABLE_NAME")
+            .ok()
+            .filter(|name| !name.trim().is_empty())
+    }
+
+    /// Returns the processed envelope storage table name, recording processed messages to
+    /// replay them
+    ///
+    /// Read from `DYNAMODB_PROCESSED_ENVELOPE_TABLE_NAME`, `None` disables the recording.
+    #[must_use]
+    pub fn processed_envelope_table_name(&self) -> Option<String> {
+        env::var("DYNAMODB_PROCESSED_ENVELOPE_TABLE_NAME")
+            .ok()
+            .filter(|name| !name.trim().is_empty())
+    }
+
+    /// Enclave health endpoint of the enclave-worker (`/health/enclave`), pinged to defer
+    /// notifications while the enclave is down
+    ///
+    /// Read from `ENCLAVE_WORKER_HEALTH_URL`, `None` disables the circuit.
+    #[must_use]
+    pub fn enclave_worker_health_url(&self) -> Option<String> {
+        env::var("ENCLAVE_WORKER_HEALTH_URL")
+            .ok()
+            .filter(|url| !url.trim().is_empty())
+    }
+
+    /// Topics the worker is restricted to, so staging only notifies test conversations
+    ///
+    /// Read from `XMTP_TOPIC_ALLOWLIST` as comma-separated topics, `None` processes every topic.
+    /// Topics are normalized like subscription topics, so they match the envelopes' topics.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the allowlist is set in Production, where every topic must be processed
+    #[must_use]
+    pub fn topic_allowlist(&self) -> Option<HashSet<String>> {
+        let allowlist: HashSet<String> = env::var("XMTP_TOPIC_ALLOWLIST")
+            .ok()?
+            .split(',')
+            .map(str::trim)
+            .filter(|topic| !topic.is_empty())
+            .map(|topic| TopicId::normalize(topic).into_inner())
+            .collect();
+        if allowlist.is_empty() {
+            return None;
+        }
+
+        assert!(
+            *self != Self::Production,
+            "XMTP_TOPIC_ALLOWLIST must not be set in Production environment"
+        );
+        Some(allowlist)
+    }
+
+    /// Time the worker is given to stop once shutdown starts, before the process exits anyway
+    ///
+    /// Read from `SHUTDOWN_TIMEOUT_SECS`, defaults to `DEFAULT_SHUTDOWN_TIMEOUT`.
+    #[must_use]
+    pub fn shutdown_timeout(&self) -> Duration {
+        env::var("SHUTDOWN_TIMEOUT_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .map_or(DEFAULT_SHUTDOWN_TIMEOUT, Duration::from_secs)
+    }
+
     /// Metrics addr (host:port) for `DogStatsD`
     ///
     /// # Panics
@@ -223,6 +516,7 @@ impl Environment {
 
 #[cfg(test)]
 mod tests {
+    use aws_config::{retry::RetryMode, BehaviorVersion};
     use serial_test::serial;
 
     use super::*;
@@ -389,4 +683,158 @@ mod tests {
         // Cleanup
         env::remove_var("XMTP_ENDPOINT_URL");
     }
+
+    #[test]
+    #[serial]
+    fn test_topic_allowlist() {
+        let staging_env = Environment::Staging;
+
+        env::remove_var("XMTP_TOPIC_ALLOWLIST");
+        assert_eq!(staging_env.topic_allowlist(), None);
+
+        env::set_var("XMTP_TOPIC_ALLOWLIST", " , ");
+        assert_eq!(staging_env.topic_allowlist(), None);
+
+        env::set_var(
+            "XMTP_TOPIC_ALLOWLIST",
+            "/xmtp/mls/1/g-a/proto, /xmtp/mls/1/g-b/proto",
+        );
+        assert_eq!(
+            staging_env.topic_allowlist(),
+            Some(HashSet::from([
+                "/xmtp/mls/1/g-a/proto".to_string(),
+                "/xmtp/mls/1/g-b/proto".to_string(),
+            ]))
+        );
+
+        // Entries are normalized to the topics of the envelopes
+        env::set_var("XMTP_TOPIC_ALLOWLIST", "G-A, /xmtp/mls/1/g-b/");
+        assert_eq!(
+            staging_env.topic_allowlist(),
+            Some(HashSet::from([
+                "/xmtp/mls/1/g-a/proto".to_string(),
+                "/xmtp/mls/1/g-b/proto".to_string(),
+            ]))
+        );
+
+        // Cleanup
+        env::remove_var("XMTP_TOPIC_ALLOWLIST");
+    }
+
+    #[test]
+    #[serial]
+    fn test_num_workers_override() {
+        let staging_env = Environment::Staging;
+
+        env::remove_var("NUM_WORKERS");
+        assert_eq!(staging_env.num_workers(), 20);
+
+        env::set_var("NUM_WORKERS", "35");
+        assert_eq!(staging_env.num_workers(), 35);
+        assert_eq!(staging_env.channel_capacity(), 70);
+
+        // Invalid values fall back to the default
+        env::set_var("NUM_WORKERS", "0");
+        assert_eq!(staging_env.num_workers(), 20);
+
+        // Cleanup
+        env::remove_var("NUM_WORKERS");
+    }
+
+    #[test]
+    #[serial]
+    fn test_shutdown_timeout_override() {
+        let prod_env = Environment::Production;
+
+        env::remove_var("SHUTDOWN_TIMEOUT_SECS");
+        assert_eq!(prod_env.shutdown_timeout(), DEFAULT_SHUTDOWN_TIMEOUT);
+
+        env::set_var("SHUTDOWN_TIMEOUT_SECS", "10");
+        assert_eq!(prod_env.shutdown_timeout(), Duration::from_secs(10));
+
+        // Cleanup
+        env::remove_var("SHUTDOWN_TIMEOUT_SECS");
+    }
+
+    #[test]
+    #[serial]
+    #[should_panic(expected = "XMTP_TOPIC_ALLOWLIST must not be set in Production environment")]
+    fn test_production_rejects_topic_allowlist() {
+        let prod_env = Environment::Production;
+
+        env::set_var("XMTP_TOPIC_ALLOWLIST", "/xmtp/mls/1/g-a/proto");
+        let _ = prod_env.topic_allowlist();
+
+        // Cleanup - won't be reached due to panic
+        env::remove_var("XMTP_TOPIC_ALLOWLIST");
+    }
+
+    fn test_aws_config() -> aws_config::SdkConfig {
+        Environment::Development.tune_aws_config(
+            &aws_config::SdkConfig::builder()
+                .behavior_version(BehaviorVersion::latest())
+                .region(aws_config::Region::new("us-east-1"))
+                .build(),
+        )
+    }
+
+    #[test]
+    #[serial]
+    fn test_dynamodb_client_fails_faster() {
+        let env_instance = Environment::Development;
+        let aws_config = test_aws_config();
+
+        let client =
+            aws_sdk_dynamodb::Client::from_conf(env_instance.dynamodb_client_config(&aws_config));
+
+        let retry_config = client.config().retry_config().unwrap();
+        assert_eq!(retry_config.max_attempts(), 2);
+        assert_eq!(aws_config.retry_config().unwrap().max_attempts(), 3);
+
+        let timeout_config = client.config().timeout_config().unwrap();
+        assert_eq!(
+            timeout_config.operation_timeout(),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(
+            timeout_config.operation_attempt_timeout(),
+            Some(Duration::from_secs(2))
+        );
+        // Settings not specific to DynamoDB are shared
+        assert_eq!(
+            timeout_config.connect_timeout(),
+            Some(Duration::from_secs(3))
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn test_aws_settings_overrides() {
+        let env_instance = Environment::Development;
+
+        env::set_var("AWS_CLIENT_RETRY_MODE", "adaptive");
+        env::set_var("AWS_CLIENT_MAX_ATTEMPTS", "5");
+        env::set_var("AWS_OPERATION_TIMEOUT_MS", "10000");
+        env::set_var("DYNAMODB_MAX_ATTEMPTS", "1");
+
+        let aws_config = test_aws_config();
+        let retry_config = aws_config.retry_config().unwrap();
+        assert_eq!(retry_config.mode(), RetryMode::Adaptive);
+        assert_eq!(retry_config.max_attempts(), 5);
+        assert_eq!(
+            aws_config.timeout_config().unwrap().operation_timeout(),
+            Some(Duration::from_secs(10))
+        );
+
+        let dynamodb_config = env_instance.dynamodb_client_config(&aws_config);
+        let dynamodb_retry_config = dynamodb_config.retry_config().unwrap();
+        assert_eq!(dynamodb_retry_config.mode(), RetryMode::Adaptive);
+        assert_eq!(dynamodb_retry_config.max_attempts(), 1);
+
+        // Cleanup
+        env::remove_var("AWS_CLIENT_RETRY_MODE");
+        env::remove_var("AWS_CLIENT_MAX_ATTEMPTS");
+        env::remove_var("AWS_OPERATION_TIMEOUT_MS");
+        env::remove_var("DYNAMODB_MAX_ATTEMPTS");
+    }
 }
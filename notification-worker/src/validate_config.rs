@@ -0,0 +1,76 @@
+//! Startup configuration validation
+//!
+//! Started with `--validate-config`, the binary loads its configuration, builds its clients and
+//! checks every dependency is reachable, then exits without starting the worker.
+
+use std::sync::Arc;
+
+use anyhow::Context;
+use aws_sdk_dynamodb::Client as DynamoDbClient;
+use aws_sdk_sqs::Client as SqsClient;
+use backend_storage::{
+    push_subscription::PushSubscriptionStorage,
+    queue::{FanOutRetryQueue, NotificationQueue},
+};
+use common_types::validate_config::{run_checks, Check};
+
+use crate::{types::Environment, worker::XmtpWorker};
+
+pub use common_types::validate_config::{is_requested, ValidationReport};
+
+/// Checks the notification worker can reach every dependency it needs to forward messages
+pub async fn validate(
+    env: &Environment,
+    sqs_client: &SqsClient,
+    dynamodb_client: &DynamoDbClient,
+    notification_queue: Arc<NotificationQueue>,
+    fanout_retry_queue: Arc<FanOutRetryQueue>,
+    subscription_storage: Arc<PushSubscriptionStorage>,
+) -> ValidationReport {
+    let checks = vec![
+        Check::new("sqs_notification_queue", async {
+            let queue_url = env.notification_queue_config().queue_url;
+            sqs_client
+                .get_queue_attributes()
+                .queue_url(&queue_url)
+                .send()
+                .await
+                .with_context(|| format!("Failed to access queue {queue_url}"))?;
+            Ok(())
+        }),
+        Check::new("sqs_fanout_retry_queue", async {
+            let queue_url = env.fanout_retry_queue_config().queue_url;
+            sqs_client
+                .get_queue_attributes()
+                .queue_url(&queue_url)
+                .send()
+                .await
+                .with_context(|| format!("Failed to access queue {queue_url}"))?;
+            Ok(())
+        }),
+        Check::new("dynamodb_push_subscription_table", async {
+            let table_name = env.push_subscription_table_name();
+            dynamodb_client
+                .describe_table()
+                .table_name(&table_name)
+                .send()
+                .await
+                .with_context(|| format!("Failed to describe table {table_name}"))?;
+            Ok(())
+        }),
+        Check::new("xmtp", async {
+            XmtpWorker::new(
+                env.clone(),
+                notification_queue,
+                fanout_retry_queue,
+                subscription_storage,
+                Arc::default(),
+            )
+            .await
+            .with_context(|| format!("Failed to connect to {}", env.xmtp_endpoint()))?;
+            Ok(())
+        }),
+    ];
+
+    run_checks(checks).await
+}
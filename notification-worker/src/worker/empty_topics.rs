@@ -0,0 +1,239 @@
+//! Cache of the topics without push subscriptions
+//!
+//! Most XMTP topics have no subscriber, so the processors remember the topics whose
+//! subscriptions were found empty and skip reading them again. The backend publishes the topic
+//! of every subscription write (see `backend_storage::push_subscription::subscribe_invalidations`),
+//! which drops the topic from the cache. The cache is only used while subscribed to these events,
+//! since a topic could gain subscribers unnoticed otherwise, and entries expire after a TTL in
+//! case an event is lost.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+
+use backend_storage::{ids::TopicId, push_subscription::subscribe_invalidations};
+use futures::StreamExt;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+/// How long a topic is remembered as empty without an invalidation event
+pub const EMPTY_TOPIC_TTL: Duration = Duration::from_secs(300);
+
+/// Maximum number of topics remembered as empty
+pub const EMPTY_TOPIC_MAX_ENTRIES: usize = 100_000;
+
+/// Delay before subscribing again to the invalidation events after the subscription ends
+pub const INVALIDATION_RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Topics known to have no push subscriptions, shared by the message processors
+#[derive(Debug)]
+pub struct EmptyTopicCache {
+    ttl: Duration,
+    max_entries: usize,
+    state: Mutex<CacheState>,
+}
+
+#[derive(Debug, Default)]
+struct CacheState {
+    /// Whether invalidation events are received, the cache is bypassed otherwise
+    subscribed: bool,
+    /// When each empty topic was found empty, by normalized topic
+    topics: HashMap<String, Instant>,
+    /// Incremented by every invalidation, see `generation`
+    generation: u64,
+}
+
+impl Default for EmptyTopicCache {
+    fn default() -> Self {
+        Self::new(EMPTY_TOPIC_TTL, EMPTY_TOPIC_MAX_ENTRIES)
+    }
+}
+
+impl EmptyTopicCache {
+    /// Creates a cache remembering up to `max_entries` empty topics for `ttl`
+    #[must_use]
+    pub fn new(ttl: Duration, max_entries: usize) -> Self {
+        Self {
+            ttl,
+            max_entries,
+            state: Mutex::new(CacheState::default()),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, CacheState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Current generation, to read before the subscriptions of a topic, see `mark_empty`
+    #[must_use]
+    pub fn generation(&self) -> u64 {
+        self.state().generation
+    }
+
+    /// Whether `topic` is known to have no subscriptions
+    #[must_use]
+    pub fn is_empty(&self, topic: &str) -> bool {
+        let state = self.state();
+        state.subscribed
+            && state
+                .topics
+                .get(TopicId::normalize(topic).as_str())
+                .is_some_and(|found_at| found_at.elapsed() < self.ttl)
+    }
+
+    /// Remembers that `topic` had no subscriptions when read at `generation`
+    ///
+    /// Ignored if an invalidation happened since `generation`, as the read may predate the
+    /// subscription write.
+    pub fn mark_empty(&self, topic: &str, generation: u64) {
+        let mut state = self.state();
+        if !state.subscribed || state.generation != generation {
+            return;
+        }
+        if state.topics.len() >= self.max_entries {
+            let ttl = self.ttl;
+            state.topics.retain(|_, found_at| found_at.elapsed() < ttl);
+            if state.topics.len() >= self.max_entries {
+                return;
+            }
+        }
+        state
+            .topics
+            .insert(TopicId::normalize(topic).into_inner(), Instant::now());
+    }
+
+    /// Drops `topic` from the cache, as its subscriptions changed
+    pub fn invalidate(&self, topic: &str) {
+        let mut state = self.state();
+        state.topics.remove(TopicId::normalize(topic).as_str());
+        state.generation = state.generation.wrapping_add(1);
+    }
+
+    /// Enables or disables the cache as invalidation events start or stop being received
+    ///
+    /// The cache is cleared either way, since events may have been missed in between.
+    pub fn set_subscribed(&self, subscribed: bool) {
+        let mut state = self.state();
+        state.subscribed = subscribed;
+        state.topics.clear();
+        state.generation = state.generation.wrapping_add(1);
+    }
+}
+
+/// Drops the topics of the invalidation events published on `channel` from `cache`, until
+/// `shutdown` is cancelled
+///
+/// The subscription is retried after `reconnect_delay` if it fails or ends, the cache being
+/// disabled meanwhile.
+pub async fn run(
+    cache: Arc<EmptyTopicCache>,
+    client: redis::Client,
+    channel: String,
+    reconnect_delay: Duration,
+    shutdown: CancellationToken,
+) {
+    loop {
+        match subscribe_invalidations(&client, &channel).await {
+            Ok(events) => {
+                info!(%channel, "Subscribed to subscription invalidation events");
+                cache.set_subscribed(true);
+                let mut events = std::pin::pin!(events);
+                loop {
+                    tokio::select! {
+                        event = events.next() => match event {
+                            Some(topic) => cache.invalidate(&topic),
+                            None => break,
+                        },
+                        () = shutdown.cancelled() => break,
+                    }
+                }
+                cache.set_subscribed(false);
+                if !shutdown.is_cancelled() {
+                    warn!(
+                        "Subscription invalidation events ended, bypassing the empty topic cache"
+                    );
+                }
+            }
+            Err(e) => warn!("Failed to subscribe to subscription invalidation events: {e}"),
+        }
+
+        tokio::select! {
+            () = tokio::time::sleep(reconnect_delay) => {}
+            () = shutdown.cancelled() => break,
+        }
+    }
+
+    info!("Subscription invalidation listener stopped");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOPIC: &str = "/xmtp/mls/1/g-abc/proto";
+
+    fn subscribed_cache() -> EmptyTopicCache {
+        let cache = EmptyTopicCache::default();
+        cache.set_subscribed(true);
+        cache
+    }
+
+    #[test]
+    fn test_invalidation_drops_empty_topic() {
+        let cache = subscribed_cache();
+        cache.mark_empty(TOPIC, cache.generation());
+        assert!(cache.is_empty(TOPIC));
+
+        cache.invalidate(TOPIC);
+
+        assert!(!cache.is_empty(TOPIC));
+    }
+
+    #[test]
+    fn test_read_predating_invalidation_is_not_cached() {
+        let cache = subscribed_cache();
+        let generation = cache.generation();
+
+        // A subscription is written while the topic is being read
+        cache.invalidate(TOPIC);
+        cache.mark_empty(TOPIC, generation);
+
+        assert!(!cache.is_empty(TOPIC));
+    }
+
+    #[test]
+    fn test_cache_is_bypassed_without_invalidation_events() {
+        let cache = EmptyTopicCache::default();
+        cache.mark_empty(TOPIC, cache.generation());
+        assert!(!cache.is_empty(TOPIC));
+
+        let cache = subscribed_cache();
+        cache.mark_empty(TOPIC, cache.generation());
+        cache.set_subscribed(false);
+        cache.set_subscribed(true);
+
+        assert!(!cache.is_empty(TOPIC));
+    }
+
+    #[test]
+    fn test_empty_topic_expires() {
+        let cache = EmptyTopicCache::new(Duration::ZERO, EMPTY_TOPIC_MAX_ENTRIES);
+        cache.set_subscribed(true);
+        cache.mark_empty(TOPIC, cache.generation());
+
+        assert!(!cache.is_empty(TOPIC));
+    }
+
+    #[test]
+    fn test_cache_is_bounded() {
+        let cache = EmptyTopicCache::new(EMPTY_TOPIC_TTL, 1);
+        cache.set_subscribed(true);
+        cache.mark_empty(TOPIC, cache.generation());
+        cache.mark_empty("/xmtp/mls/1/g-def/proto", cache.generation());
+
+        assert!(cache.is_empty(TOPIC));
+        assert!(!cache.is_empty("/xmtp/mls/1/g-def/proto"));
+    }
+}
@@ -0,0 +1,109 @@
+use std::{sync::Arc, time::Duration};
+
+use backend_storage::queue::{FanOutRetry, FanOutRetryQueue, QueueMessage};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, instrument, warn};
+
+use super::message_processor::{envelope_from_retry, MessageProcessor};
+
+/// Delay before polling the fan-out retry queue again after a failed poll
+const POLL_ERROR_DELAY: Duration = Duration::from_secs(5);
+
+/// `FanOutRetrier` fans out messages deferred to the fan-out retry queue
+///
+/// Messages that still can't be fanned out are left on the queue, so they are redelivered once
+/// their visibility timeout expires, until the queue's redrive policy moves them to its
+/// dead-letter queue. The queue isn't polled while the enclave is down, so an outage doesn't use
+/// up the receives of the deferred messages.
+pub struct FanOutRetrier {
+    queue: Arc<FanOutRetryQueue>,
+    processor: MessageProcessor,
+    shutdown_token: CancellationToken,
+}
+
+impl FanOutRetrier {
+    /// Creates a new `FanOutRetrier`
+    #[must_use]
+    pub const fn new(
+        queue: Arc<FanOutRetryQueue>,
+        processor: MessageProcessor,
+        shutdown_token: CancellationToken,
+    ) -> Self {
+        Self {
+            queue,
+            processor,
+            shutdown_token,
+        }
+    }
+
+    /// Polls the fan-out retry queue until shutdown
+    pub async fn run(self) {
+        info!("Fan-out retrier started");
+
+        while !self.shutdown_token.is_cancelled() {
+            if !self
+                .processor
+                .wait_until_enclave_available(&self.shutdown_token)
+                .await
+            {
+                break;
+            }
+
+            let messages = tokio::select! {
+                () = self.shutdown_token.cancelled() => break,
+                messages = self.queue.poll_messages() => messages,
+            };
+
+            match messages {
+                Ok(messages) => {
+                    for message in messages {
+                        self.retry(message).await;
+                    }
+                }
+                Err(e) => {
+                    error!("Failed to poll fan-out retry queue: {e}");
+                    tokio::select! {
+                        () = self.shutdown_token.cancelled() => break,
+                        () = tokio::time::sleep(POLL_ERROR_DELAY) => {}
+                    }
+                }
+            }
+        }
+
+        info!("Fan-out retrier stopped");
+    }
+
+    /// Fans out a deferred message, acknowledging it unless the fan-out should be retried again
+    #[instrument(skip(self, message), fields(message_id = %message.message_id))]
+    async fn retry(&self, message: QueueMessage<FanOutRetry>) {
+        let envelope = match envelope_from_retry(&message.body) {
+            Ok(envelope) => envelope,
+            Err(e) => {
+                error!("Dropping deferred fan-out of invalid message: {e}");
+                self.ack(&message).await;
+                return;
+            }
+        };
+
+        match self.processor.fan_out(&envelope).await {
+            Ok(notified) => {
+                info!("Deferred fan-out succeeded");
+                self.processor.record_processed(&envelope, notified).await;
+            }
+            Err(e) if e.is_retryable() => {
+                warn!("Deferred fan-out failed, retrying after visibility timeout: {e}");
+                return;
+            }
+            Err(e) => error!("Dropping deferred fan-out of invalid message: {e}"),
+        }
+
+        self.ack(&message).await;
+    }
+
+    /// Acknowledges a deferred fan-out, so it isn't retried again
+    async fn ack(&self, message: &QueueMessage<FanOutRetry>) {
+        if let Err(e) = self.queue.ack_message(&message.receipt_handle).await {
+            error!("Failed to acknowledge deferred fan-out: {e}");
+        }
+    }
+}
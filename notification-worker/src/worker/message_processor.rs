@@ -1,25 +1,78 @@
-use std::{collections::HashSet, sync::Arc};
+use std::{collections::HashSet, pin::pin, sync::Arc};
 
-use crate::{xmtp::message_api::v1::Envelope, xmtp_utils::MessageContext};
+use crate::{
+    cursor::CursorTracker, dogstatsd::Metric, enclave_health::EnclaveHealth,
+    xmtp::message_api::v1::Envelope, xmtp_utils::MessageContext,
+};
 use anyhow::Context;
 use backend_storage::{
-    push_subscription::PushSubscriptionStorage,
-    queue::{Notification, NotificationQueue},
+    ids::TopicId,
+    processed_envelope::{ProcessedEnvelope, ProcessedEnvelopeStorage},
+    push_subscription::{PushSubscription, PushSubscriptionStorage, PushSubscriptionStorageError},
+    queue::{FanOutRetry, FanOutRetryQueue, Notification, NotificationQueue, QueueError},
 };
 use base64::{engine::general_purpose::STANDARD, Engine as _};
+use futures::TryStreamExt;
 use metrics::counter;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
 use tokio_util::sync::CancellationToken;
 
-use tracing::{debug, error, info, instrument, Span};
+use tracing::{debug, error, info, instrument, warn, Span};
 use uuid::Uuid;
 
 use crate::xmtp_utils::is_v3_topic;
 
+use super::{empty_topics::EmptyTopicCache, utilization::ProcessorUtilization};
+
+/// Errors that can occur while fanning a message out to its subscribers
+#[derive(Debug, Error)]
+pub enum FanOutError {
+    /// Failed to read the subscriptions of the topic
+    #[error("Failed to read subscriptions: {0}")]
+    Storage(#[from] PushSubscriptionStorageError),
+    /// Failed to send the notification to the notification queue
+    #[error("Failed to send message to notification queue: {0}")]
+    Queue(#[from] QueueError),
+    /// The enclave is down, the notification would only fail downstream
+    #[error("Enclave down, not enqueuing the notification")]
+    EnclaveDown,
+    /// The message can't be parsed, retrying won't help
+    #[error("Invalid message: {0}")]
+    InvalidMessage(#[from] anyhow::Error),
+}
+
+impl FanOutError {
+    /// Whether fanning out the same message again may succeed
+    #[must_use]
+    pub const fn is_retryable(&self) -> bool {
+        !matches!(self, Self::InvalidMessage(_))
+    }
+}
+
+/// Maximum number of recipients of a single notification enqueued by a fan-out, larger topics
+/// are split over several notifications
+pub const FAN_OUT_CHUNK_RECIPIENTS: usize = 500;
+
 /// `MessageProcessor` handles individual message processing
 pub struct MessageProcessor {
     worker_id: usize,
     notification_queue: Arc<NotificationQueue>,
+    /// Holds messages whose subscriptions couldn't be read, to fan them out later
+    fanout_retry_queue: Arc<FanOutRetryQueue>,
     subscription_storage: Arc<PushSubscriptionStorage>,
+    /// Pauses receiving messages while the enclave is known to be down
+    enclave_health: Arc<EnclaveHealth>,
+    /// Records fanned out messages to replay them, disabled if `None`
+    processed_envelope_storage: Option<Arc<ProcessedEnvelopeStorage>>,
+    /// Topics messages are processed for, every topic if `None`
+    topic_allowlist: Option<Arc<HashSet<String>>>,
+    /// Records the time spent processing messages, disabled if `None`
+    utilization: Option<Arc<ProcessorUtilization>>,
+    /// Records the cursor of processed messages' topics, disabled if `None`
+    cursors: Option<Arc<CursorTracker>>,
+    /// Skips reading the subscriptions of topics known to have none, disabled if `None`
+    empty_topics: Option<Arc<EmptyTopicCache>>,
 }
 
 impl MessageProcessor {
@@ -29,17 +82,93 @@ impl MessageProcessor {
     pub fn new(
         worker_id: usize,
         notification_queue: Arc<NotificationQueue>,
+        fanout_retry_queue: Arc<FanOutRetryQueue>,
         subscription_storage: Arc<PushSubscriptionStorage>,
+        enclave_health: Arc<EnclaveHealth>,
     ) -> Self {
         Self {
             worker_id,
             notification_queue,
+            fanout_retry_queue,
             subscription_storage,
+            enclave_health,
+            processed_envelope_storage: None,
+            topic_allowlist: None,
+            utilization: None,
+            cursors: None,
+            empty_topics: None,
+        }
+    }
+
+    /// Records fanned out messages in `storage`, so they can be replayed
+    #[must_use]
+    pub fn with_processed_envelope_storage(
+        mut self,
+        storage: Option<Arc<ProcessedEnvelopeStorage>>,
+    ) -> Self {
+        self.processed_envelope_storage = storage;
+        self
+    }
+
+    /// Only processes messages of the topics in `allowlist`, dropping the others
+    ///
+    /// Meant for non-production environments, to only notify test conversations.
+    #[must_use]
+    pub fn with_topic_allowlist(mut self, allowlist: Option<Arc<HashSet<String>>>) -> Self {
+        self.topic_allowlist = allowlist;
+        self
+    }
+
+    /// Records the time spent processing messages received by `run` in `utilization`
+    #[must_use]
+    pub fn with_utilization(mut self, utilization: Option<Arc<ProcessorUtilization>>) -> Self {
+        self.utilization = utilization;
+        self
+    }
+
+    /// Records the cursor of the topic of messages received by `run` in `cursors`
+    #[must_use]
+    pub fn with_cursor_tracker(mut self, cursors: Option<Arc<CursorTracker>>) -> Self {
+        self.cursors = cursors;
+        self
+    }
+
+    /// Skips reading the subscriptions of the topics `cache` knows to have none
+    #[must_use]
+    pub fn with_empty_topic_cache(mut self, cache: Option<Arc<EmptyTopicCache>>) -> Self {
+        self.empty_topics = cache;
+        self
+    }
+
+    /// Waits until the enclave is no longer known to be down, see `EnclaveHealth`
+    ///
+    /// Returns `false` if `shutdown_token` is cancelled first.
+    pub async fn wait_until_enclave_available(&self, shutdown_token: &CancellationToken) -> bool {
+        if !self.enclave_health.is_paused() {
+            return true;
         }
+
+        info!("Enclave down, pausing message processing");
+        tokio::select! {
+            () = shutdown_token.cancelled() => false,
+            () = self.enclave_health.wait_until_available() => {
+                info!("Enclave available, resuming message processing");
+                true
+            }
+        }
+    }
+
+    /// Whether messages of `topic` are processed, see `with_topic_allowlist`
+    fn is_topic_allowed(&self, topic: &str) -> bool {
+        self.topic_allowlist
+            .as_ref()
+            .is_none_or(|allowlist| allowlist.contains(topic))
     }
 
     /// Runs the message processor loop
-    #[allow(clippy::cognitive_complexity)]
+    ///
+    /// No messages are received while the enclave is down, so they aren't enqueued only to fail
+    /// downstream. The message channel then fills up and applies backpressure to the listener.
     pub async fn run(
         &self,
         receiver: flume::Receiver<Envelope>,
@@ -48,6 +177,11 @@ impl MessageProcessor {
         info!("Message processor started");
 
         loop {
+            if !self.wait_until_enclave_available(&shutdown_token).await {
+                info!("Message processor received shutdown signal");
+                break;
+            }
+
             tokio::select! {
                 () = shutdown_token.cancelled() => {
                     info!("Message processor received shutdown signal");
@@ -56,9 +190,22 @@ impl MessageProcessor {
                 result = receiver.recv_async() => {
                     match result {
                         Ok(message) => {
-                            if let Err(e) = self.process_message(&message).await {
+                            if let Some(cursors) = &self.cursors {
+                                cursors.start(&message.content_topic, message.timestamp_ns);
+                            }
+                            let busy = self.utilization.as_deref().map(ProcessorUtilization::busy);
+                            let result = self.process_message(&message).await;
+                            drop(busy);
+                            if let Err(e) = &result {
                                 error!("Failed to process message: {}", e);
                             }
+                            if let Some(cursors) = &self.cursors {
+                                cursors.finish(
+                                    &message.content_topic,
+                                    message.timestamp_ns,
+                                    result.is_ok(),
+                                );
+                            }
                         }
                         Err(flume::RecvError::Disconnected) => {
                             info!("Message channel closed");
@@ -74,14 +221,82 @@ impl MessageProcessor {
 
     /// Processes a single message
     ///
+    /// Messages can't be redelivered by the XMTP stream, so if the subscriptions of the topic
+    /// can't be read (e.g. during a `DynamoDB` outage) or the enclave went down after the message
+    /// was received, the message is pushed to the fan-out retry queue instead of being dropped.
+    ///
     /// # Errors
     ///
-    /// Returns an error if the message cannot be processed.
+    /// Returns an error if the message can neither be processed nor deferred to the retry queue.
     #[instrument(skip(self, envelope), fields(worker_id = self.worker_id, content_topic = %envelope.content_topic, message_id = tracing::field::Empty, request_id = %Uuid::new_v4()))]
     pub async fn process_message(&self, envelope: &Envelope) -> anyhow::Result<()> {
+        let result = self.fan_out(envelope).await;
+        // Failed fan-outs are recorded as not notified, so they can still be replayed
+        self.record_processed(envelope, matches!(result, Ok(true)))
+            .await;
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(e @ (FanOutError::Storage(_) | FanOutError::EnclaveDown)) => {
+                warn!("Deferring fan-out: {e}");
+                self.fanout_retry_queue
+                    .send_message(&fan_out_retry(envelope))
+                    .await
+                    .context("Failed to send message to fan-out retry queue")?;
+                counter!(Metric::FanOutDeferred.name()).increment(1);
+                Ok(())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Records a processed message in the processed envelope storage, if enabled
+    ///
+    /// Messages are recorded whether or not their fan-out succeeded. A deferred fan-out that
+    /// succeeds later records the message again, replacing the previous record. Only V3 messages
+    /// are recorded, others are never fanned out. Failures are logged, they only affect replays.
+    pub async fn record_processed(&self, envelope: &Envelope, notified: bool) {
+        let Some(storage) = &self.processed_envelope_storage else {
+            return;
+        };
+        if !is_v3_topic(&envelope.content_topic) || !self.is_topic_allowed(&envelope.content_topic)
+        {
+            return;
+        }
+
+        let processed = ProcessedEnvelope::new(
+            envelope.content_topic.clone(),
+            envelope_message_id(envelope),
+            envelope.timestamp_ns,
+            notified,
+        );
+        if let Err(e) = storage.insert(&processed).await {
+            warn!("Failed to record processed envelope: {e}");
+        }
+    }
+
+    /// Fans a message out to the subscribers of its topic, by enqueuing a notification for them
+    ///
+    /// Subscriptions are streamed and their recipients enqueued in notifications of up to
+    /// `FAN_OUT_CHUNK_RECIPIENTS` as they are read. If the fan-out fails midway, retrying it
+    /// enqueues the first chunks again, which the notification queue's content-based
+    /// deduplication drops if the retry comes within its deduplication interval.
+    ///
+    /// Returns whether a notification was enqueued, messages that shouldn't be pushed or
+    /// without recipients aren't.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `FanOutError` if the subscriptions can't be read, the enclave is down, the
+    /// notification can't be enqueued or the message is invalid.
+    pub async fn fan_out(&self, envelope: &Envelope) -> Result<bool, FanOutError> {
         // Step 1: Filter out messages that are not V3, following example from XMTP
         if !is_v3_topic(&envelope.content_topic) {
-            return Ok(());
+            return Ok(false);
+        }
+        if !self.is_topic_allowed(&envelope.content_topic) {
+            debug!("Dropping message of a topic outside the allowlist");
+            return Ok(false);
         }
 
         debug!(
@@ -94,50 +309,249 @@ impl MessageProcessor {
 
         // Step 2: Filter out messages that should not be pushed
         if Some(false) == message_context.should_push {
-            return Ok(());
+            return Ok(false);
         }
 
-        // Step 3: Filter out self-notifications, a user should not receive a notification for their own message
-        let subscriptions = self
-            .subscription_storage
-            .get_all_by_topic(&envelope.content_topic)
-            .await?;
-        let subscribed_encrypted_push_ids = subscriptions
-            .into_iter()
-            .filter_map(|s| match message_context.is_sender(&s.hmac_key) {
-                Ok(true) => None, // Filter out self-notifications (sender matches subscription)
-                Ok(false) => Some(s.encrypted_push_id),
-                // Don't block notification for valid HMACs but log error
-                Err(e) => {
-                    error!(
-                        "Failed to check sender for subscription {}: {}. Message context: {:?}",
-                        s.hmac_key, e, message_context
-                    );
-                    Some(s.encrypted_push_id) // Include on error to be safe
-                }
-            })
-            .collect::<HashSet<_>>();
-        if subscribed_encrypted_push_ids.is_empty() {
-            return Ok(());
+        // Notifications enqueued while the enclave is down would only fail downstream, don't
+        // spend subscription reads on them
+        if self.enclave_health.is_paused() {
+            return Err(FanOutError::EnclaveDown);
+        }
+
+        // Step 3: Stream the subscriptions, filtering out self-notifications, a user should not
+        // receive a notification for their own message, and notifying each push ID once
+        let empty_topics = self.empty_topics.as_deref();
+        if empty_topics.is_some_and(|cache| cache.is_empty(&envelope.content_topic)) {
+            return Ok(false);
         }
+        let generation = empty_topics.map(EmptyTopicCache::generation);
+        // XMTP envelopes carry canonical topics, so only the canonical one is read: the raw
+        // topics of subscriptions created before topics were normalized never match them
+        let topic = TopicId::normalize(&envelope.content_topic);
+        let mut subscriptions = pin!(self.subscription_storage.stream_by_topic(&topic, None));
+
+        let encrypted_message_base64 = STANDARD.encode(envelope.message.as_slice());
+        let mut recipients = UniqueRecipients::default();
+        let mut chunk = Vec::new();
+        let mut found_subscriptions = false;
+        let mut enqueued = false;
+        while let Some(subscription) = subscriptions.try_next().await? {
+            found_subscriptions = true;
+            if !is_recipient(&message_context, &subscription) {
+                continue;
+            }
+            chunk.extend(recipients.admit(subscription));
+
+            // Step 4: Publish each full chunk to the notification queue, so a large topic is
+            // never held in memory at once
+            if chunk.len() >= FAN_OUT_CHUNK_RECIPIENTS {
+                self.enqueue(
+                    envelope,
+                    std::mem::take(&mut chunk),
+                    &encrypted_message_base64,
+                )
+                .await?;
+                enqueued = true;
+            }
+        }
+
+        if !found_subscriptions {
+            if let (Some(cache), Some(generation)) = (empty_topics, generation) {
+                cache.mark_empty(&envelope.content_topic, generation);
+            }
+        }
+        if !chunk.is_empty() {
+            self.enqueue(envelope, chunk, &encrypted_message_base64)
+                .await?;
+            enqueued = true;
+        }
+
+        Ok(enqueued)
+    }
 
-        // Convert XMTP envelope to notification
+    /// Enqueues a notification of `envelope` for `recipients`
+    async fn enqueue(
+        &self,
+        envelope: &Envelope,
+        recipients: Vec<String>,
+        encrypted_message_base64: &str,
+    ) -> Result<(), FanOutError> {
         let notification = Notification {
             topic: envelope.content_topic.clone(),
-            subscribed_encrypted_push_ids: subscribed_encrypted_push_ids.into_iter().collect(),
-            encrypted_message_base64: STANDARD.encode(envelope.message.as_slice()),
+            subscribed_encrypted_push_ids: recipients,
+            encrypted_message_base64: encrypted_message_base64.to_string(),
+            requeue_attempt: 0,
         };
-
-        // Step 4: Publish to notification queue
-        let message_id = self
-            .notification_queue
-            .send_message(&notification)
-            .await
-            .context("Failed to send message to notification queue")?;
+        let message_id = self.notification_queue.send_message(&notification).await?;
 
         Span::current().record("message_id", message_id);
-        counter!("notification_queued").increment(1);
+        counter!(Metric::NotificationQueued.name()).increment(1);
 
         Ok(())
     }
 }
+
+/// Identifies a message within its topic and timestamp, as the hex SHA-256 of its bytes
+#[must_use]
+pub fn envelope_message_id(envelope: &Envelope) -> String {
+    hex::encode(Sha256::digest(&envelope.message))
+}
+
+/// Fan-out retry message of `envelope`, see `envelope_from_retry` for the reverse
+fn fan_out_retry(envelope: &Envelope) -> FanOutRetry {
+    FanOutRetry {
+        content_topic: envelope.content_topic.clone(),
+        timestamp_ns: envelope.timestamp_ns,
+        message_base64: STANDARD.encode(envelope.message.as_slice()),
+    }
+}
+
+/// Restores the envelope of a message whose fan-out was deferred
+///
+/// # Errors
+///
+/// Returns an error if the message isn't valid Base64
+pub fn envelope_from_retry(retry: &FanOutRetry) -> anyhow::Result<Envelope> {
+    Ok(Envelope {
+        content_topic: retry.content_topic.clone(),
+        timestamp_ns: retry.timestamp_ns,
+        message: STANDARD
+            .decode(&retry.message_base64)
+            .context("Invalid fan-out retry message")?,
+    })
+}
+
+/// Whether `subscription` should be notified of the message of `message_context`, i.e. it
+/// isn't the sender's own subscription
+fn is_recipient(message_context: &MessageContext, subscription: &PushSubscription) -> bool {
+    match message_context.is_sender(&subscription.hmac_key) {
+        // Filter out self-notifications (sender matches subscription)
+        Ok(is_sender) => !is_sender,
+        // Don't block notification for valid HMACs but log error
+        Err(e) => {
+            error!(
+                "Failed to check sender for subscription {}: {}. Message context: {:?}",
+                subscription.hmac_key, e, message_context
+            );
+            true // Include on error to be safe
+        }
+    }
+}
+
+/// Recipients already notified of a message, to notify each push ID and installation once
+///
+/// A subscription is skipped if its encrypted push ID or, when present, its installation ID was
+/// already admitted. The latter merges the subscriptions a device made with different push IDs,
+/// e.g. before and after its push token was rotated. Installation IDs are supplied by the client,
+/// so a member of the topic claiming another device's installation ID can suppress that device's
+/// notifications for the topic.
+#[derive(Debug, Default)]
+struct UniqueRecipients {
+    push_ids: HashSet<String>,
+    installation_ids: HashSet<String>,
+}
+
+impl UniqueRecipients {
+    /// Returns the push ID to notify for `subscription`, `None` if it was already notified
+    fn admit(&mut self, subscription: PushSubscription) -> Option<String> {
+        if self.push_ids.contains(&subscription.encrypted_push_id)
+            || subscription
+                .installation_id
+                .as_ref()
+                .is_some_and(|id| self.installation_ids.contains(id))
+        {
+            return None;
+        }
+
+        self.installation_ids.extend(subscription.installation_id);
+        self.push_ids.insert(subscription.encrypted_push_id.clone());
+        Some(subscription.encrypted_push_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unique_recipients(
+        subscriptions: impl IntoIterator<Item = PushSubscription>,
+    ) -> HashSet<String> {
+        let mut recipients = UniqueRecipients::default();
+        subscriptions
+            .into_iter()
+            .filter_map(|subscription| recipients.admit(subscription))
+            .collect()
+    }
+
+    fn subscription(
+        hmac_key: &str,
+        ttl: i64,
+        push_id: &str,
+        installation_id: Option<&str>,
+    ) -> PushSubscription {
+        PushSubscription {
+            topic: "topic".to_string(),
+            hmac_key: hmac_key.to_string(),
+            ttl,
+            encrypted_push_id: push_id.to_string(),
+            deletion_request: None,
+            installation_id: installation_id.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_fan_out_retry_round_trips_envelope() {
+        let envelope = Envelope {
+            content_topic: "/xmtp/mls/1/g-topic/proto".to_string(),
+            timestamp_ns: 1_700_000_000_000_000_000,
+            message: vec![0, 1, 2, 255],
+        };
+
+        let restored = envelope_from_retry(&fan_out_retry(&envelope)).unwrap();
+
+        assert_eq!(restored, envelope);
+    }
+
+    #[test]
+    fn test_subscriptions_dedup_by_push_id() {
+        let recipients = unique_recipients([
+            subscription("hmac-1", 100, "push-a", None),
+            subscription("hmac-2", 200, "push-a", Some("phone")),
+            subscription("hmac-3", 100, "push-b", None),
+        ]);
+
+        assert_eq!(
+            recipients,
+            HashSet::from(["push-a".to_string(), "push-b".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_subscriptions_dedup_by_installation_id() {
+        let recipients = unique_recipients([
+            subscription("hmac-1", 100, "push-old", Some("phone")),
+            subscription("hmac-2", 200, "push-new", Some("phone")),
+            subscription("hmac-3", 100, "push-tablet", Some("tablet")),
+            subscription("hmac-4", 100, "push-legacy", None),
+        ]);
+
+        assert_eq!(
+            recipients,
+            HashSet::from([
+                "push-old".to_string(),
+                "push-tablet".to_string(),
+                "push-legacy".to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn test_installations_sharing_push_id_are_notified_once() {
+        let recipients = unique_recipients([
+            subscription("hmac-1", 100, "push-a", Some("phone")),
+            subscription("hmac-2", 200, "push-a", Some("tablet")),
+        ]);
+
+        assert_eq!(recipients, HashSet::from(["push-a".to_string()]));
+    }
+}
@@ -1,35 +1,123 @@
+pub mod empty_topics;
+pub mod fanout_retrier;
 pub mod message_processor;
+pub mod utilization;
 pub mod xmtp_listener;
 
+use std::collections::HashSet;
 use std::sync::Arc;
 use std::time::Duration;
 
-use crate::types::environment::Environment;
+use crate::cursor::{self, CursorTracker, RedisCursorStore};
+use crate::enclave_health::EnclaveHealth;
+use crate::replay::{self, ReplayReport, ReplayWindow, XmtpEnvelopeSource};
+use crate::shutdown::RunningComponents;
+use crate::types::environment::{Environment, XmtpKeepAlive};
 use crate::worker::xmtp_listener::XmtpListenerConfig;
 use crate::xmtp::message_api::v1::Envelope;
 
 /// Result type for worker operations
 pub type WorkerResult<T> = anyhow::Result<T>;
 
-use backend_storage::push_subscription::PushSubscriptionStorage;
-use backend_storage::queue::NotificationQueue;
+use anyhow::Context;
+use backend_storage::processed_envelope::ProcessedEnvelopeStorage;
+use backend_storage::push_subscription::{
+    PushSubscriptionStorage, SUBSCRIPTION_INVALIDATION_CHANNEL,
+};
+use backend_storage::queue::{FanOutRetryQueue, NotificationQueue};
 use tokio::task::JoinHandle;
 use tokio_util::sync::CancellationToken;
-use tonic::transport::{Channel, ClientTlsConfig};
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Endpoint, Identity};
 use tracing::{error, info, instrument};
 
 use crate::xmtp::message_api::v1::message_api_client::MessageApiClient;
 
+use self::empty_topics::EmptyTopicCache;
+use self::fanout_retrier::FanOutRetrier;
 use self::message_processor::MessageProcessor;
+use self::utilization::ProcessorUtilization;
 use self::xmtp_listener::XmtpListener;
 
+/// TLS configuration of the XMTP channel
+///
+/// The node's certificate is verified against the root of `Environment::xmtp_tls_ca_cert_path`,
+/// or the webpki roots if unset, and the client certificate of
+/// `Environment::xmtp_tls_client_identity_paths` is presented if set.
+///
+/// # Errors
+///
+/// Returns an error if a configured certificate or key can't be read
+pub fn xmtp_tls_config(env: &Environment) -> anyhow::Result<ClientTlsConfig> {
+    let mut tls_config = match env.xmtp_tls_ca_cert_path() {
+        Some(path) => {
+            let pem = std::fs::read(&path)
+                .with_context(|| format!("Failed to read XMTP CA certificate {path}"))?;
+            info!(%path, "Using custom CA certificate for XMTP");
+            ClientTlsConfig::new().ca_certificate(Certificate::from_pem(pem))
+        }
+        None => ClientTlsConfig::new().with_webpki_roots(),
+    };
+
+    if let Some((cert_path, key_path)) = env.xmtp_tls_client_identity_paths() {
+        let cert = std::fs::read(&cert_path)
+            .with_context(|| format!("Failed to read XMTP client certificate {cert_path}"))?;
+        let key = std::fs::read(&key_path)
+            .with_context(|| format!("Failed to read XMTP client key {key_path}"))?;
+        info!(%cert_path, "Using client certificate for XMTP");
+        tls_config = tls_config.identity(Identity::from_pem(cert, key));
+    }
+
+    Ok(tls_config)
+}
+
+/// Endpoint of the XMTP node, with the TLS, timeout and keepalive settings of `env`
+///
+/// # Errors
+///
+/// Returns an error if the endpoint URL or the TLS configuration is invalid
+pub fn xmtp_endpoint(env: &Environment) -> anyhow::Result<Endpoint> {
+    let mut endpoint = Channel::from_shared(env.xmtp_endpoint())?;
+
+    if env.use_tls() {
+        endpoint = endpoint.tls_config(xmtp_tls_config(env)?)?;
+    }
+
+    endpoint = endpoint
+        .timeout(Duration::from_millis(env.request_timeout_ms()))
+        .connect_timeout(Duration::from_millis(env.connection_timeout_ms()));
+
+    Ok(match env.xmtp_keep_alive() {
+        Some(keep_alive) => with_keep_alive(endpoint, keep_alive),
+        None => endpoint,
+    })
+}
+
+/// Pings the XMTP node over HTTP/2 as configured by `keep_alive`
+fn with_keep_alive(endpoint: Endpoint, keep_alive: XmtpKeepAlive) -> Endpoint {
+    endpoint
+        .http2_keep_alive_interval(keep_alive.interval)
+        .keep_alive_timeout(keep_alive.timeout)
+        .keep_alive_while_idle(keep_alive.while_idle)
+}
+
 /// XMTP worker that manages message streaming and processing
 pub struct XmtpWorker {
     env: Environment,
     client: MessageApiClient<Channel>,
     shutdown_token: CancellationToken,
     notification_queue: Arc<NotificationQueue>,
+    fanout_retry_queue: Arc<FanOutRetryQueue>,
     subscription_storage: Arc<PushSubscriptionStorage>,
+    enclave_health: Arc<EnclaveHealth>,
+    processed_envelope_storage: Option<Arc<ProcessedEnvelopeStorage>>,
+    topic_allowlist: Option<Arc<HashSet<String>>>,
+    /// Persists the cursor of each topic to resume after a restart, disabled if `None`
+    cursor_store: Option<(RedisCursorStore, Arc<CursorTracker>)>,
+    /// Skips topics without subscriptions, invalidated by the events of the Redis client,
+    /// disabled if `None`
+    empty_topics: Option<(redis::Client, Arc<EmptyTopicCache>)>,
+    /// Components still running, logged if shutdown doesn't complete in time
+    running: RunningComponents,
 }
 
 impl XmtpWorker {
@@ -41,7 +129,9 @@ impl XmtpWorker {
     pub async fn new(
         env: Environment,
         notification_queue: Arc<NotificationQueue>,
+        fanout_retry_queue: Arc<FanOutRetryQueue>,
         subscription_storage: Arc<PushSubscriptionStorage>,
+        enclave_health: Arc<EnclaveHealth>,
     ) -> anyhow::Result<Self> {
         info!(
             "Connecting to XMTP node at {}, TLS enabled: {}",
@@ -49,19 +139,7 @@ impl XmtpWorker {
             env.use_tls()
         );
 
-        // Create the endpoint with proper configuration
-        let endpoint = {
-            let mut ep = Channel::from_shared(env.xmtp_endpoint())?;
-
-            if env.use_tls() {
-                let tls_config = ClientTlsConfig::new().with_webpki_roots();
-                ep = ep.tls_config(tls_config)?;
-            }
-
-            ep.timeout(Duration::from_millis(env.request_timeout_ms()))
-                .connect_timeout(Duration::from_millis(env.connection_timeout_ms()))
-        };
-        let channel = endpoint.connect().await?;
+        let channel = xmtp_endpoint(&env)?.connect().await?;
         let client = MessageApiClient::new(channel);
 
         Ok(Self {
@@ -69,18 +147,85 @@ impl XmtpWorker {
             client,
             shutdown_token: CancellationToken::new(),
             notification_queue,
+            fanout_retry_queue,
             subscription_storage,
+            enclave_health,
+            processed_envelope_storage: None,
+            topic_allowlist: None,
+            cursor_store: None,
+            empty_topics: None,
+            running: RunningComponents::default(),
         })
     }
 
+    /// Records processed messages in `storage`, so they can be replayed, see `replay`
+    #[must_use]
+    pub fn with_processed_envelope_storage(
+        mut self,
+        storage: Option<Arc<ProcessedEnvelopeStorage>>,
+    ) -> Self {
+        self.processed_envelope_storage = storage;
+        self
+    }
+
+    /// Restricts processing to the topics in `allowlist`, see `Environment::topic_allowlist`
+    #[must_use]
+    pub fn with_topic_allowlist(mut self, allowlist: Option<HashSet<String>>) -> Self {
+        self.topic_allowlist = allowlist.map(Arc::new);
+        self
+    }
+
+    /// Persists the cursor of each topic in `store`, so a restarted worker resumes the topics
+    /// where it left off, see `cursor`
+    #[must_use]
+    pub fn with_cursor_store(mut self, store: Option<RedisCursorStore>) -> Self {
+        self.cursor_store = store.map(|store| (store, Arc::new(CursorTracker::default())));
+        self
+    }
+
+    /// Skips reading the subscriptions of topics found without any, as long as the subscription
+    /// invalidation events published on `client` are received, see `empty_topics`
+    #[must_use]
+    pub fn with_subscription_invalidations(mut self, client: Option<redis::Client>) -> Self {
+        self.empty_topics = client.map(|client| (client, Arc::new(EmptyTopicCache::default())));
+        self
+    }
+
     /// Returns a clone of the shutdown token for external control
     #[must_use]
     pub fn shutdown_token(&self) -> CancellationToken {
         self.shutdown_token.clone()
     }
 
+    /// Returns the components of the worker that are still running, see `start`
+    #[must_use]
+    pub fn running_components(&self) -> RunningComponents {
+        self.running.clone()
+    }
+
+    /// Fans out again the messages processed in `window` which didn't queue a notification
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the processed envelope storage isn't configured or can't be read.
+    pub async fn replay(&self, window: ReplayWindow) -> anyhow::Result<ReplayReport> {
+        let Some(storage) = &self.processed_envelope_storage else {
+            anyhow::bail!("Replaying requires the processed envelope storage");
+        };
+
+        replay::replay(
+            storage,
+            &self.message_processor(0),
+            &XmtpEnvelopeSource::new(self.client.clone()),
+            window,
+        )
+        .await
+    }
+
     /// Starts the worker and all components
     ///
+    /// Components are tracked in `running_components` until they stop.
+    ///
     /// # Errors
     ///
     /// Returns an error if stream listening fails or processor tasks panic.
@@ -91,9 +236,23 @@ impl XmtpWorker {
         );
 
         let (message_tx, message_rx) = self.create_message_channel();
-        let processor_handles = self.spawn_processors(&message_rx);
+        let utilization = Arc::new(ProcessorUtilization::new(self.env.num_workers()));
+        let mut processor_handles = self.spawn_processors(&message_rx, &utilization);
+        processor_handles.push(self.spawn_fanout_retrier());
+        processor_handles.extend(self.spawn_cursor_tasks(&message_tx).await);
+        processor_handles.extend(self.spawn_invalidation_listener());
+        processor_handles.push(tokio::spawn(self.running.track(
+            "utilization_reporter",
+            utilization::run(
+                utilization,
+                utilization::UTILIZATION_REPORT_INTERVAL,
+                self.shutdown_token.clone(),
+            ),
+        )));
 
-        self.run_xmtp_listener(message_tx).await;
+        self.running
+            .track("xmtp_listener", self.run_xmtp_listener(message_tx))
+            .await;
         self.shutdown_and_cleanup(processor_handles).await;
 
         Ok(())
@@ -141,22 +300,120 @@ impl XmtpWorker {
         info!("All XMTP worker components stopped");
     }
 
-    /// Spawns message processor tasks
-    fn spawn_processors(&self, receiver: &flume::Receiver<Envelope>) -> Vec<JoinHandle<()>> {
+    /// Creates a message processor identified by `worker_id`
+    fn message_processor(&self, worker_id: usize) -> MessageProcessor {
+        MessageProcessor::new(
+            worker_id,
+            Arc::clone(&self.notification_queue),
+            Arc::clone(&self.fanout_retry_queue),
+            Arc::clone(&self.subscription_storage),
+            Arc::clone(&self.enclave_health),
+        )
+        .with_processed_envelope_storage(self.processed_envelope_storage.clone())
+        .with_topic_allowlist(self.topic_allowlist.clone())
+        .with_cursor_tracker(
+            self.cursor_store
+                .as_ref()
+                .map(|(_, tracker)| Arc::clone(tracker)),
+        )
+        .with_empty_topic_cache(
+            self.empty_topics
+                .as_ref()
+                .map(|(_, cache)| Arc::clone(cache)),
+        )
+    }
+
+    /// Spawns the task invalidating the empty topic cache, if enabled
+    fn spawn_invalidation_listener(&self) -> Option<JoinHandle<()>> {
+        let (client, cache) = self.empty_topics.clone()?;
+
+        Some(tokio::spawn(self.running.track(
+            "subscription_invalidations",
+            empty_topics::run(
+                cache,
+                client,
+                SUBSCRIPTION_INVALIDATION_CHANNEL.to_string(),
+                empty_topics::INVALIDATION_RECONNECT_DELAY,
+                self.shutdown_token.clone(),
+            ),
+        )))
+    }
+
+    /// Spawns the tasks resuming the topics from their persisted cursors and persisting the
+    /// cursors of the processed messages, if cursors are persisted
+    async fn spawn_cursor_tasks(
+        &self,
+        message_tx: &flume::Sender<Envelope>,
+    ) -> Vec<JoinHandle<()>> {
+        let Some((store, tracker)) = self.cursor_store.clone() else {
+            return Vec::new();
+        };
+        let mut handles = Vec::new();
+
+        match store.load(self.env.xmtp_cursor_max_lookback()).await {
+            Ok(cursors) => {
+                let resume = cursor::resume(
+                    self.client.clone(),
+                    cursors,
+                    message_tx.clone(),
+                    self.shutdown_token.clone(),
+                );
+                handles.push(tokio::spawn(self.running.track("cursor_resume", async {
+                    resume.await;
+                })));
+            }
+            Err(e) => error!("Failed to load XMTP cursors, not resuming topics: {e:#}"),
+        }
+
+        let shutdown_token = self.shutdown_token.clone();
+        handles.push(tokio::spawn(self.running.track(
+            "cursor_persistence",
+            async move {
+                cursor::run(
+                    &tracker,
+                    &store,
+                    cursor::CURSOR_FLUSH_INTERVAL,
+                    shutdown_token,
+                )
+                .await;
+            },
+        )));
+
+        handles
+    }
+
+    /// Spawns the task fanning out messages deferred to the fan-out retry queue
+    fn spawn_fanout_retrier(&self) -> JoinHandle<()> {
+        let retrier = FanOutRetrier::new(
+            Arc::clone(&self.fanout_retry_queue),
+            self.message_processor(self.env.num_workers()),
+            self.shutdown_token.clone(),
+        );
+
+        tokio::spawn(self.running.track("fanout_retrier", retrier.run()))
+    }
+
+    /// Spawns message processor tasks, recording their busy time in `utilization`
+    fn spawn_processors(
+        &self,
+        receiver: &flume::Receiver<Envelope>,
+        utilization: &Arc<ProcessorUtilization>,
+    ) -> Vec<JoinHandle<()>> {
         let mut handles = Vec::new();
 
         for i in 0..self.env.num_workers() {
-            let processor = MessageProcessor::new(
-                i,
-                Arc::clone(&self.notification_queue),
-                Arc::clone(&self.subscription_storage),
-            );
+            let processor = self
+                .message_processor(i)
+                .with_utilization(Some(Arc::clone(utilization)));
             let rx = receiver.clone();
             let shutdown_token = self.shutdown_token.clone();
 
-            let handle = tokio::spawn(async move {
-                processor.run(rx, shutdown_token).await;
-            });
+            let handle = tokio::spawn(self.running.track(
+                format!("message_processor_{i}"),
+                async move {
+                    processor.run(rx, shutdown_token).await;
+                },
+            ));
 
             handles.push(handle);
         }
@@ -164,3 +421,150 @@ impl XmtpWorker {
         handles
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use serial_test::serial;
+
+    use super::*;
+
+    fn fixture(name: &str) -> String {
+        format!("{}/tests/fixtures/{name}", env!("CARGO_MANIFEST_DIR"))
+    }
+
+    fn endpoint_with(tls_config: ClientTlsConfig) -> Result<Endpoint, tonic::transport::Error> {
+        Channel::from_static("https://xmtp.internal:5556").tls_config(tls_config)
+    }
+
+    fn clear_tls_env() {
+        env::remove_var("XMTP_TLS_CA_CERT_PATH");
+        env::remove_var("XMTP_TLS_CLIENT_CERT_PATH");
+        env::remove_var("XMTP_TLS_CLIENT_KEY_PATH");
+    }
+
+    #[test]
+    #[serial]
+    fn test_channel_uses_custom_root_and_client_certificate() {
+        clear_tls_env();
+        env::set_var("XMTP_TLS_CA_CERT_PATH", fixture("xmtp-ca.pem"));
+        env::set_var("XMTP_TLS_CLIENT_CERT_PATH", fixture("xmtp-client.pem"));
+        env::set_var("XMTP_TLS_CLIENT_KEY_PATH", fixture("xmtp-client-key.pem"));
+
+        let tls_config = xmtp_tls_config(&Environment::Staging).unwrap();
+        assert!(endpoint_with(tls_config).is_ok());
+
+        // The root is applied to the channel, so an invalid one is rejected when building it
+        let invalid_root = env::temp_dir().join("xmtp-invalid-ca.pem");
+        std::fs::write(
+            &invalid_root,
+            "-----BEGIN CERTIFICATE-----\nAAAA\n-----END CERTIFICATE-----\n",
+        )
+        .unwrap();
+        env::set_var("XMTP_TLS_CA_CERT_PATH", &invalid_root);
+
+        let tls_config = xmtp_tls_config(&Environment::Staging).unwrap();
+        assert!(endpoint_with(tls_config).is_err());
+
+        // Cleanup
+        std::fs::remove_file(invalid_root).unwrap();
+        clear_tls_env();
+    }
+
+    #[test]
+    #[serial]
+    fn test_channel_falls_back_to_webpki_roots() {
+        clear_tls_env();
+
+        let tls_config = xmtp_tls_config(&Environment::Staging).unwrap();
+        assert!(endpoint_with(tls_config).is_ok());
+    }
+
+    #[test]
+    #[serial]
+    fn test_missing_custom_root_is_an_error() {
+        clear_tls_env();
+        env::set_var("XMTP_TLS_CA_CERT_PATH", fixture("missing.pem"));
+
+        assert!(xmtp_tls_config(&Environment::Staging).is_err());
+
+        // Cleanup
+        clear_tls_env();
+    }
+
+    /// Accepts a single HTTP/2 connection and waits for the client to send a PING frame
+    async fn receive_ping(listener: tokio::net::TcpListener) -> std::io::Result<()> {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        const PREFACE_LEN: usize = 24;
+        const FRAME_HEADER_LEN: usize = 9;
+        const PING_FRAME_TYPE: u8 = 0x6;
+        // Empty SETTINGS frame followed by the acknowledgement of the client's SETTINGS
+        const SERVER_SETTINGS: [u8; 18] = [0, 0, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 4, 1, 0, 0, 0, 0];
+
+        let (mut stream, _) = listener.accept().await?;
+        stream.read_exact(&mut [0; PREFACE_LEN]).await?;
+        stream.write_all(&SERVER_SETTINGS).await?;
+
+        loop {
+            let mut header = [0; FRAME_HEADER_LEN];
+            stream.read_exact(&mut header).await?;
+            if header[3] == PING_FRAME_TYPE {
+                return Ok(());
+            }
+
+            let payload_len = (usize::from(header[0]) << 16)
+                | (usize::from(header[1]) << 8)
+                | usize::from(header[2]);
+            let mut payload = vec![0; payload_len];
+            stream.read_exact(&mut payload).await?;
+        }
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_endpoint_pings_idle_connection() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        env::set_var("XMTP_ENDPOINT_URL", format!("http://{addr}"));
+        env::set_var("XMTP_KEEP_ALIVE_INTERVAL_MS", "50");
+        env::set_var("XMTP_KEEP_ALIVE_WHILE_IDLE", "true");
+
+        let env = Environment::Development;
+        assert_eq!(
+            env.xmtp_keep_alive(),
+            Some(XmtpKeepAlive {
+                interval: Duration::from_millis(50),
+                timeout: Duration::from_secs(10),
+                while_idle: true,
+            })
+        );
+
+        let server = tokio::spawn(receive_ping(listener));
+        let _channel = xmtp_endpoint(&env).unwrap().connect().await.unwrap();
+
+        // No request is ever sent, so the only PING comes from the keepalive
+        tokio::time::timeout(Duration::from_secs(2), server)
+            .await
+            .expect("idle connection should be pinged")
+            .unwrap()
+            .unwrap();
+
+        // Cleanup
+        env::remove_var("XMTP_ENDPOINT_URL");
+        env::remove_var("XMTP_KEEP_ALIVE_INTERVAL_MS");
+        env::remove_var("XMTP_KEEP_ALIVE_WHILE_IDLE");
+    }
+
+    #[test]
+    #[serial]
+    fn test_keep_alive_can_be_disabled() {
+        env::set_var("XMTP_KEEP_ALIVE_INTERVAL_MS", "0");
+
+        assert_eq!(Environment::Development.xmtp_keep_alive(), None);
+
+        // Cleanup
+        env::remove_var("XMTP_KEEP_ALIVE_INTERVAL_MS");
+    }
+}
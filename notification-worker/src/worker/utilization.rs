@@ -0,0 +1,217 @@
+//! Utilization of the message processors
+//!
+//! Every processor records how long it spends processing messages, and the fraction of time the
+//! processors were busy is reported periodically as a gauge. Utilization that stays close to 1
+//! means messages wait in the channel and `NUM_WORKERS` should be raised, while utilization close
+//! to 0 means processors sit idle.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+
+use metrics::gauge;
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+
+use crate::dogstatsd::Metric;
+
+/// Interval between two reports of the processor utilization
+pub const UTILIZATION_REPORT_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Busy time of the message processors, shared by all of them
+#[derive(Debug)]
+pub struct ProcessorUtilization {
+    processors: usize,
+    window: Mutex<Window>,
+}
+
+/// Busy time of the current window
+#[derive(Debug)]
+struct Window {
+    /// When the window started
+    start: Instant,
+    /// Time spent processing messages since the window started, summed over the processors
+    busy: Duration,
+    /// When each message still being processed started counting towards `busy`
+    in_flight: HashMap<u64, Instant>,
+    next_id: u64,
+}
+
+impl ProcessorUtilization {
+    /// Creates a tracker of the utilization of `processors` processors
+    #[must_use]
+    pub fn new(processors: usize) -> Self {
+        Self {
+            processors,
+            window: Mutex::new(Window {
+                start: Instant::now(),
+                busy: Duration::ZERO,
+                in_flight: HashMap::new(),
+                next_id: 0,
+            }),
+        }
+    }
+
+    /// Records that a processor is busy until the returned guard is dropped
+    #[must_use]
+    pub fn busy(&self) -> Busy<'_> {
+        Busy {
+            utilization: self,
+            id: self.start_at(Instant::now()),
+        }
+    }
+
+    /// Fraction of time the processors were busy since the last call, between 0 and 1
+    #[must_use]
+    pub fn take(&self) -> f64 {
+        self.take_at(Instant::now())
+    }
+
+    fn window(&self) -> MutexGuard<'_, Window> {
+        self.window.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Starts tracking a message processed from `now`, returning its id
+    fn start_at(&self, now: Instant) -> u64 {
+        let mut window = self.window();
+        let id = window.next_id;
+        window.next_id = window.next_id.wrapping_add(1);
+        window.in_flight.insert(id, now);
+        id
+    }
+
+    /// Records the message `id` was processed until `now`
+    fn finish_at(&self, id: u64, now: Instant) {
+        let mut window = self.window();
+        if let Some(started_at) = window.in_flight.remove(&id) {
+            window.busy += now.saturating_duration_since(started_at);
+        }
+    }
+
+    /// Fraction of time the processors were busy between the window start and `now`, starting a
+    /// new window at `now`
+    ///
+    /// Messages still being processed count up to `now`, and the rest of their processing
+    /// towards the next window.
+    fn take_at(&self, now: Instant) -> f64 {
+        let (elapsed, busy) = {
+            let mut window = self.window();
+            let mut busy = std::mem::take(&mut window.busy);
+            for started_at in window.in_flight.values_mut() {
+                busy += now.saturating_duration_since(*started_at);
+                *started_at = now;
+            }
+            let elapsed = now.saturating_duration_since(window.start);
+            window.start = now;
+            (elapsed, busy)
+        };
+        if elapsed.is_zero() || self.processors == 0 {
+            return 0.0;
+        }
+
+        #[allow(clippy::cast_precision_loss)] // Precision loss is irrelevant for a ratio
+        let utilization =
+            busy.as_nanos() as f64 / (elapsed.as_nanos() as f64 * self.processors as f64);
+        utilization.min(1.0)
+    }
+}
+
+/// Guard of a message being processed, see `ProcessorUtilization::busy`
+#[derive(Debug)]
+pub struct Busy<'a> {
+    utilization: &'a ProcessorUtilization,
+    id: u64,
+}
+
+impl Drop for Busy<'_> {
+    fn drop(&mut self) {
+        self.utilization.finish_at(self.id, Instant::now());
+    }
+}
+
+/// Reports the utilization of the processors every `interval`, until `shutdown` is cancelled
+pub async fn run(
+    utilization: Arc<ProcessorUtilization>,
+    interval: Duration,
+    shutdown: CancellationToken,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    // The first tick completes immediately, start the first window on it
+    ticker.tick().await;
+    let _ = utilization.take();
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            () = shutdown.cancelled() => break,
+        }
+
+        gauge!(Metric::ProcessorUtilization.name()).set(utilization.take());
+    }
+
+    info!("Processor utilization reporting stopped");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window_start(utilization: &ProcessorUtilization) -> Instant {
+        utilization.window().start
+    }
+
+    #[test]
+    fn test_utilization_reflects_busy_and_idle_processors() {
+        let utilization = ProcessorUtilization::new(2);
+        let start = window_start(&utilization);
+
+        // One processor kept busy for the whole window, the other idle
+        let id = utilization.start_at(start);
+        utilization.finish_at(id, start + Duration::from_secs(10));
+        let busy_and_idle = utilization.take_at(start + Duration::from_secs(10));
+        assert!((busy_and_idle - 0.5).abs() < f64::EPSILON);
+
+        // Both processors idle for the next window
+        let idle = utilization.take_at(start + Duration::from_secs(20));
+        assert!(idle.abs() < f64::EPSILON);
+
+        // Both processors kept busy for the next window
+        for _ in 0..2 {
+            let id = utilization.start_at(start + Duration::from_secs(20));
+            utilization.finish_at(id, start + Duration::from_secs(30));
+        }
+        let busy = utilization.take_at(start + Duration::from_secs(30));
+        assert!((busy - 1.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_messages_in_flight_count_towards_each_window() {
+        let utilization = ProcessorUtilization::new(1);
+        let start = window_start(&utilization);
+
+        // A message processed across two windows counts in both, for the time spent in each
+        let id = utilization.start_at(start + Duration::from_secs(5));
+        let first = utilization.take_at(start + Duration::from_secs(10));
+        assert!((first - 0.5).abs() < f64::EPSILON);
+
+        utilization.finish_at(id, start + Duration::from_secs(12));
+        let second = utilization.take_at(start + Duration::from_secs(20));
+        assert!((second - 0.2).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_busy_guard_records_until_dropped() {
+        let utilization = ProcessorUtilization::new(1);
+
+        let busy = utilization.busy();
+        assert_eq!(utilization.window().in_flight.len(), 1);
+        drop(busy);
+
+        let window = utilization.window();
+        assert!(window.in_flight.is_empty());
+        assert!(!window.busy.is_zero());
+    }
+}
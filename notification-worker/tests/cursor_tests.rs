@@ -0,0 +1,112 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use anyhow::Result;
+use notification_worker::cursor::{self, CursorTracker, RedisCursorStore};
+use pretty_assertions::assert_eq;
+use redis::{aio::ConnectionManager, AsyncCommands};
+use tokio_util::sync::CancellationToken;
+
+const MAX_LOOKBACK: Duration = Duration::from_secs(600);
+const RETENTION: Duration = Duration::from_secs(3600);
+
+async fn connection_manager() -> Result<ConnectionManager> {
+    dotenvy::from_path(".env.test").ok();
+    let url = std::env::var("XMTP_CURSOR_REDIS_URL")
+        .unwrap_or_else(|_| "redis://localhost:6379".to_string());
+
+    Ok(ConnectionManager::new(redis::Client::open(url)?).await?)
+}
+
+fn now_ns() -> u64 {
+    u64::try_from(chrono::Utc::now().timestamp_nanos_opt().unwrap()).unwrap()
+}
+
+#[tokio::test]
+async fn test_cursor_persisted_before_shutdown_is_loaded_on_next_start() -> Result<()> {
+    let conn = connection_manager().await?;
+    let key = format!("test_xmtp_cursors_{}", uuid::Uuid::new_v4());
+    let cursor_ns = now_ns();
+
+    // First run: messages are processed, then the worker shuts down
+    {
+        let store = RedisCursorStore::new(conn.clone(), key.clone()).with_retention(RETENTION);
+        let tracker = Arc::new(CursorTracker::default());
+        let shutdown = CancellationToken::new();
+        let persistence = tokio::spawn({
+            let tracker = tracker.clone();
+            let shutdown = shutdown.clone();
+            async move {
+                cursor::run(&tracker, &store, Duration::from_secs(3600), shutdown).await;
+            }
+        });
+
+        for (topic, timestamp_ns) in [
+            ("/xmtp/mls/1/g-active/proto", cursor_ns - 1),
+            ("/xmtp/mls/1/g-active/proto", cursor_ns),
+            // Beyond the retention, not resumed on the next start
+            ("/xmtp/mls/1/g-stale/proto", 1),
+        ] {
+            tracker.start(topic, timestamp_ns);
+            tracker.finish(topic, timestamp_ns, true);
+        }
+
+        shutdown.cancel();
+        persistence.await?;
+    }
+
+    // Next start: the cursors are loaded from Redis
+    let store = RedisCursorStore::new(conn.clone(), key.clone()).with_retention(RETENTION);
+    let cursors = store.load(MAX_LOOKBACK).await?;
+
+    assert_eq!(
+        cursors.into_iter().collect::<Vec<_>>(),
+        vec![("/xmtp/mls/1/g-active/proto".to_string(), cursor_ns)]
+    );
+
+    // The stale cursor was deleted
+    let mut conn = conn;
+    let stored: usize = conn.hlen(&key).await?;
+    assert_eq!(stored, 1);
+
+    // Cleanup
+    conn.del::<_, ()>(&[&key, &format!("{key}:updated_at")])
+        .await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_quiet_topics_are_resumed_from_the_max_lookback() -> Result<()> {
+    let conn = connection_manager().await?;
+    let key = format!("test_xmtp_cursors_{}", uuid::Uuid::new_v4());
+    let store = RedisCursorStore::new(conn.clone(), key.clone()).with_retention(RETENTION);
+
+    // Older than the max lookback but within the retention
+    let quiet_cursor_ns = now_ns() - 2 * u64::try_from(MAX_LOOKBACK.as_nanos())?;
+    store
+        .save(&HashMap::from([(
+            "/xmtp/mls/1/g-quiet/proto".to_string(),
+            quiet_cursor_ns,
+        )]))
+        .await?;
+
+    let cursors = store.load(MAX_LOOKBACK).await?;
+
+    let lookback_start_ns = now_ns() - u64::try_from(MAX_LOOKBACK.as_nanos())?;
+    let resumed_from = cursors["/xmtp/mls/1/g-quiet/proto"];
+    assert!(resumed_from > quiet_cursor_ns);
+    assert!(resumed_from <= lookback_start_ns);
+
+    // The cursor and when it was updated both expire after the retention
+    let mut conn = conn;
+    let ttl: i64 = conn.ttl(&key).await?;
+    assert!(ttl > 0 && ttl <= i64::try_from(RETENTION.as_secs())?);
+    let updated_at: usize = conn.zcard(format!("{key}:updated_at")).await?;
+    assert_eq!(updated_at, 1);
+
+    // Cleanup
+    conn.del::<_, ()>(&[&key, &format!("{key}:updated_at")])
+        .await?;
+
+    Ok(())
+}
@@ -49,6 +49,7 @@ async fn setup_test_subscriptions(ctx: &TestContext) -> Result<TestSubscriptions
         ttl: now + 86400, // Valid for 1 day
         encrypted_push_id: "push_id_x".to_string(),
         deletion_request: None,
+        installation_id: None,
     };
 
     // Topic B with multiple subscribers
@@ -58,6 +59,7 @@ async fn setup_test_subscriptions(ctx: &TestContext) -> Result<TestSubscriptions
         ttl: now + 86400,
         encrypted_push_id: "push_id_x".to_string(),
         deletion_request: None,
+        installation_id: None,
     };
 
     let sub_b_y1 = PushSubscription {
@@ -66,6 +68,7 @@ async fn setup_test_subscriptions(ctx: &TestContext) -> Result<TestSubscriptions
         ttl: now + 86400,
         encrypted_push_id: "push_id_y".to_string(),
         deletion_request: None,
+        installation_id: None,
     };
 
     // Same push_id as y1 (same device, different installation)
@@ -75,6 +78,7 @@ async fn setup_test_subscriptions(ctx: &TestContext) -> Result<TestSubscriptions
         ttl: now + 86400,
         encrypted_push_id: "push_id_y".to_string(),
         deletion_request: None,
+        installation_id: None,
     };
 
     // Insert all subscriptions
@@ -357,6 +361,7 @@ async fn test_welcome_messages() -> Result<()> {
         ttl: chrono::Utc::now().timestamp() + 86400,
         encrypted_push_id: "welcome_push_id".to_string(),
         deletion_request: None,
+        installation_id: None,
     };
     ctx.subscription_storage.insert(&subscription).await?;
 
@@ -385,6 +390,7 @@ async fn test_ignores_non_v3_topics() -> Result<()> {
         ttl: chrono::Utc::now().timestamp() + 86400,
         encrypted_push_id: "legacy_push_id".to_string(),
         deletion_request: None,
+        installation_id: None,
     };
     ctx.subscription_storage.insert(&subscription).await?;
 
@@ -471,6 +477,7 @@ async fn test_duplicate_push_ids_deduplicated() -> Result<()> {
             ttl: now + 86400,
             encrypted_push_id: "duplicate_push_id".to_string(),
             deletion_request: None,
+            installation_id: None,
         };
         ctx.subscription_storage.insert(&sub).await?;
     }
@@ -490,3 +497,296 @@ async fn test_duplicate_push_ids_deduplicated() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_large_topic_is_enqueued_in_chunks() -> Result<()> {
+    use notification_worker::worker::message_processor::FAN_OUT_CHUNK_RECIPIENTS;
+    use std::collections::HashSet;
+
+    let ctx = TestContext::new().await;
+    let now = chrono::Utc::now().timestamp();
+    const TOPIC_LARGE: &str = "/xmtp/mls/1/g-large-topic/proto";
+
+    let subscriptions: Vec<PushSubscription> = (0..=FAN_OUT_CHUNK_RECIPIENTS)
+        .map(|i| PushSubscription {
+            hmac_key: hex::encode(create_test_hmac_key(format!("device_{i}").as_bytes())),
+            topic: TOPIC_LARGE.to_string(),
+            ttl: now + 86400,
+            encrypted_push_id: format!("push_id_{i}"),
+            deletion_request: None,
+            installation_id: None,
+        })
+        .collect();
+    ctx.subscription_storage
+        .batch_insert(&subscriptions)
+        .await?;
+
+    send_group_message(
+        &ctx,
+        TOPIC_LARGE,
+        b"Test chunking",
+        true,
+        create_test_hmac_key(b"external"),
+    )
+    .await?;
+
+    let messages = ctx.notification_queue.poll_messages().await?;
+    let mut chunk_sizes: Vec<usize> = messages
+        .iter()
+        .map(|message| message.body.subscribed_encrypted_push_ids.len())
+        .collect();
+    chunk_sizes.sort_unstable();
+    assert_eq!(chunk_sizes, vec![1, FAN_OUT_CHUNK_RECIPIENTS]);
+
+    let push_ids: HashSet<&String> = messages
+        .iter()
+        .flat_map(|message| &message.body.subscribed_encrypted_push_ids)
+        .collect();
+    assert_eq!(push_ids.len(), FAN_OUT_CHUNK_RECIPIENTS + 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_enqueuing_pauses_while_enclave_down() -> Result<()> {
+    let ctx = TestContext::new().await;
+    let subs = setup_test_subscriptions(&ctx).await?;
+    ctx.enclave_health.record_down();
+
+    let (message_tx, message_rx) = flume::bounded(1);
+    let envelope =
+        create_group_message_envelope(&subs.topic_a, b"Delayed", true, subs.hmac_external.clone())
+            .await?;
+    message_tx.send_async(envelope).await?;
+
+    let shutdown_token = tokio_util::sync::CancellationToken::new();
+    let processor = ctx
+        .message_processor
+        .run(message_rx, shutdown_token.clone());
+
+    let checks = async {
+        // Stop the processor even if an assertion fails
+        let _shutdown = shutdown_token.clone().drop_guard();
+
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        assert_no_notification(&ctx).await?;
+        assert_eq!(message_tx.len(), 1, "Paused processor should not receive");
+
+        ctx.enclave_health.record_up();
+        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+        assert_notification_queued(&ctx, &subs.topic_a, vec!["push_id_x"]).await
+    };
+
+    let ((), result) = tokio::join!(processor, checks);
+    result
+}
+
+#[tokio::test]
+async fn test_storage_failure_routes_envelope_to_retry_queue() -> Result<()> {
+    use backend_storage::push_subscription::PushSubscriptionStorage;
+    use notification_worker::worker::message_processor::{envelope_from_retry, MessageProcessor};
+    use std::sync::Arc;
+
+    let ctx = TestContext::new().await;
+    let subs = setup_test_subscriptions(&ctx).await?;
+
+    // Reading subscriptions from a missing table fails like a DynamoDB outage
+    let dynamodb_client = Arc::new(aws_sdk_dynamodb::Client::new(
+        &ctx.environment.aws_config().await,
+    ));
+    let unavailable_storage = Arc::new(PushSubscriptionStorage::new(
+        dynamodb_client,
+        format!("missing-table-{}", uuid::Uuid::new_v4()),
+        "missing-index".to_string(),
+    ));
+    let processor = MessageProcessor::new(
+        0,
+        ctx.notification_queue.clone(),
+        ctx.fanout_retry_queue.clone(),
+        unavailable_storage,
+        ctx.enclave_health.clone(),
+    );
+
+    let envelope =
+        create_group_message_envelope(&subs.topic_a, b"Deferred", true, subs.hmac_external.clone())
+            .await?;
+    processor.process_message(&envelope).await?;
+
+    assert_no_notification(&ctx).await?;
+    let deferred = ctx.fanout_retry_queue.poll_messages().await?;
+    assert_eq!(
+        deferred.len(),
+        1,
+        "Expected the envelope on the retry queue"
+    );
+    assert_eq!(envelope_from_retry(&deferred[0].body)?, envelope);
+
+    // Once storage is back, the deferred envelope fans out as usual
+    ctx.message_processor.fan_out(&envelope).await?;
+    assert_notification_queued(&ctx, &subs.topic_a, vec!["push_id_x"]).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_fan_out_checks_enclave_before_reading_subscriptions() -> Result<()> {
+    use backend_storage::push_subscription::PushSubscriptionStorage;
+    use notification_worker::worker::message_processor::{FanOutError, MessageProcessor};
+    use std::sync::Arc;
+
+    let ctx = TestContext::new().await;
+    let subs = setup_test_subscriptions(&ctx).await?;
+    ctx.enclave_health.record_down();
+
+    // Reading subscriptions would fail, so an `EnclaveDown` error means they weren't read
+    let dynamodb_client = Arc::new(aws_sdk_dynamodb::Client::new(
+        &ctx.environment.aws_config().await,
+    ));
+    let unavailable_storage = Arc::new(PushSubscriptionStorage::new(
+        dynamodb_client,
+        format!("missing-table-{}", uuid::Uuid::new_v4()),
+        "missing-index".to_string(),
+    ));
+    let processor = MessageProcessor::new(
+        0,
+        ctx.notification_queue.clone(),
+        ctx.fanout_retry_queue.clone(),
+        unavailable_storage,
+        ctx.enclave_health.clone(),
+    );
+
+    let envelope =
+        create_group_message_envelope(&subs.topic_a, b"Paused", true, subs.hmac_external.clone())
+            .await?;
+
+    assert!(matches!(
+        processor.fan_out(&envelope).await,
+        Err(FanOutError::EnclaveDown)
+    ));
+    assert_no_notification(&ctx).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_drops_topics_outside_allowlist() -> Result<()> {
+    use notification_worker::worker::message_processor::MessageProcessor;
+    use std::sync::Arc;
+
+    let ctx = TestContext::new().await;
+    let subs = setup_test_subscriptions(&ctx).await?;
+
+    let processor = MessageProcessor::new(
+        0,
+        ctx.notification_queue.clone(),
+        ctx.fanout_retry_queue.clone(),
+        ctx.subscription_storage.clone(),
+        ctx.enclave_health.clone(),
+    )
+    .with_topic_allowlist(Some(Arc::new([subs.topic_b.clone()].into())));
+
+    // Topic A has subscribers but isn't allowlisted
+    let envelope =
+        create_group_message_envelope(&subs.topic_a, b"Dropped", true, subs.hmac_external.clone())
+            .await?;
+    processor.process_message(&envelope).await?;
+    assert_no_notification(&ctx).await?;
+
+    let envelope =
+        create_group_message_envelope(&subs.topic_b, b"Allowed", true, subs.hmac_external.clone())
+            .await?;
+    processor.process_message(&envelope).await?;
+    assert_notification_queued(&ctx, &subs.topic_b, vec!["push_id_x", "push_id_y"]).await?;
+
+    Ok(())
+}
+
+/// Envelopes of the replay tests, by message ID
+struct StoredEnvelopes(std::collections::HashMap<String, Envelope>);
+
+impl notification_worker::replay::EnvelopeSource for StoredEnvelopes {
+    async fn fetch(
+        &self,
+        processed: &backend_storage::processed_envelope::ProcessedEnvelope,
+    ) -> Result<Option<Envelope>> {
+        Ok(self.0.get(&processed.message_id).cloned())
+    }
+}
+
+#[tokio::test]
+async fn test_replay_requeues_missed_notifications_and_skips_duplicates() -> Result<()> {
+    use backend_storage::processed_envelope::ProcessedEnvelope;
+    use notification_worker::replay::{replay, ReplayReport, ReplayWindow};
+    use notification_worker::worker::message_processor::envelope_message_id;
+
+    let ctx = TestContext::new().await;
+    let subs = setup_test_subscriptions(&ctx).await?;
+    let from_ns = chrono::Utc::now().timestamp_nanos_opt().unwrap() as u64;
+
+    // Notified when processed
+    let notified =
+        create_group_message_envelope(&subs.topic_a, b"Notified", true, subs.hmac_external.clone())
+            .await?;
+    send_envelope(&ctx, notified.clone()).await?;
+    assert_notification_queued(&ctx, &subs.topic_a, vec!["push_id_x"]).await?;
+
+    // Processed by a buggy fan-out that didn't queue a notification
+    let missed =
+        create_group_message_envelope(&subs.topic_b, b"Missed", true, subs.hmac_external.clone())
+            .await?;
+    ctx.processed_envelope_storage
+        .insert(&ProcessedEnvelope::new(
+            missed.content_topic.clone(),
+            envelope_message_id(&missed),
+            missed.timestamp_ns,
+            false,
+        ))
+        .await?;
+
+    let source = StoredEnvelopes(
+        [&notified, &missed]
+            .into_iter()
+            .map(|envelope| (envelope_message_id(envelope), envelope.clone()))
+            .collect(),
+    );
+    let window = ReplayWindow {
+        from_ns,
+        to_ns: chrono::Utc::now().timestamp_nanos_opt().unwrap() as u64,
+    };
+
+    let report = replay(
+        &ctx.processed_envelope_storage,
+        &ctx.message_processor,
+        &source,
+        window,
+    )
+    .await?;
+    assert_eq!(
+        report,
+        ReplayReport {
+            replayed: 1,
+            duplicates: 1,
+            ..ReplayReport::default()
+        }
+    );
+    assert_notification_queued(&ctx, &subs.topic_b, vec!["push_id_x", "push_id_y"]).await?;
+
+    // Replaying again doesn't notify twice
+    let report = replay(
+        &ctx.processed_envelope_storage,
+        &ctx.message_processor,
+        &source,
+        window,
+    )
+    .await?;
+    assert_eq!(
+        report,
+        ReplayReport {
+            duplicates: 2,
+            ..ReplayReport::default()
+        }
+    );
+    assert_no_notification(&ctx).await?;
+
+    Ok(())
+}
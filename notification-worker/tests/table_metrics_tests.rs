@@ -0,0 +1,63 @@
+mod utils;
+
+use anyhow::Result;
+use backend_storage::push_subscription::PushSubscription;
+use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+use notification_worker::dogstatsd::Metric;
+use notification_worker::table_metrics::{record_table_size, MonitoredTable};
+use pretty_assertions::assert_eq;
+use utils::TestContext;
+
+#[tokio::test]
+async fn test_reports_subscription_table_size() -> Result<()> {
+    let ctx = TestContext::new().await;
+
+    let now = chrono::Utc::now().timestamp();
+    for i in 0..3 {
+        ctx.subscription_storage
+            .insert(&PushSubscription {
+                hmac_key: format!("hmac-{i}"),
+                topic: "/xmtp/mls/1/g-table-size/proto".to_string(),
+                ttl: now + 86400,
+                encrypted_push_id: format!("push_id_{i}"),
+                deletion_request: None,
+                installation_id: None,
+            })
+            .await?;
+    }
+
+    let recorder = DebuggingRecorder::new();
+    let snapshotter = recorder.snapshotter();
+    let _guard = metrics::set_default_local_recorder(&recorder);
+
+    let table = MonitoredTable {
+        metric: Metric::SubscriptionsTotal,
+        table_name: ctx.push_subscriptions_table_name.clone(),
+    };
+    let count = record_table_size(&ctx.dynamodb_client, &table).await?;
+    assert_eq!(count, 3);
+
+    let gauge = snapshotter
+        .snapshot()
+        .into_vec()
+        .into_iter()
+        .find(|(key, ..)| key.key().name() == Metric::SubscriptionsTotal.name())
+        .map(|(.., value)| value);
+    assert_eq!(gauge, Some(DebugValue::Gauge(3.0.into())));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_missing_table_size_is_an_error() {
+    let ctx = TestContext::new().await;
+
+    let table = MonitoredTable {
+        metric: Metric::AuthProofsTotal,
+        table_name: format!("missing-table-{}", uuid::Uuid::new_v4()),
+    };
+
+    assert!(record_table_size(&ctx.dynamodb_client, &table)
+        .await
+        .is_err());
+}
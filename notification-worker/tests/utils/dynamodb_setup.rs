@@ -3,29 +3,82 @@ use aws_sdk_dynamodb::types::{
     ProjectionType, ScalarAttributeType,
 };
 use aws_sdk_dynamodb::Client as DynamoDbClient;
+use backend_storage::processed_envelope::ProcessedEnvelopeAttribute;
 use backend_storage::push_subscription::PushSubscriptionAttribute;
 use std::sync::Arc;
 use std::time::Duration;
 use uuid::Uuid;
 
+/// Name of the `encrypted_push_id` GSI on the push subscriptions table
+pub const PUSH_ID_INDEX_NAME: &str = "encrypted-push-id-index";
+
 /// Helper for creating and managing DynamoDB tables in tests
 ///
 /// Creates every table used in backend server.
 pub struct DynamoDbTestSetup {
     client: Arc<DynamoDbClient>,
     pub push_subscriptions_table_name: String,
+    pub processed_envelopes_table_name: String,
 }
 
 impl DynamoDbTestSetup {
     pub async fn new(client: Arc<DynamoDbClient>) -> Self {
         let push_subscriptions_table_name = Self::create_push_subscriptions_table(&client).await;
+        let processed_envelopes_table_name = Self::create_processed_envelopes_table(&client).await;
 
         Self {
             client,
             push_subscriptions_table_name,
+            processed_envelopes_table_name,
         }
     }
 
+    /// Creates a test processed envelopes table with a unique name
+    async fn create_processed_envelopes_table(client: &DynamoDbClient) -> String {
+        let table_name = format!("test-processed-envelopes-{}", Uuid::new_v4());
+
+        client
+            .create_table()
+            .table_name(&table_name)
+            .attribute_definitions(
+                AttributeDefinition::builder()
+                    .attribute_name(ProcessedEnvelopeAttribute::HourBucket.to_string())
+                    .attribute_type(ScalarAttributeType::N)
+                    .build()
+                    .unwrap(),
+            )
+            .attribute_definitions(
+                AttributeDefinition::builder()
+                    .attribute_name(ProcessedEnvelopeAttribute::SortKey.to_string())
+                    .attribute_type(ScalarAttributeType::S)
+                    .build()
+                    .unwrap(),
+            )
+            .key_schema(
+                KeySchemaElement::builder()
+                    .attribute_name(ProcessedEnvelopeAttribute::HourBucket.to_string())
+                    .key_type(KeyType::Hash)
+                    .build()
+                    .unwrap(),
+            )
+            .key_schema(
+                KeySchemaElement::builder()
+                    .attribute_name(ProcessedEnvelopeAttribute::SortKey.to_string())
+                    .key_type(KeyType::Range)
+                    .build()
+                    .unwrap(),
+            )
+            .billing_mode(aws_sdk_dynamodb::types::BillingMode::PayPerRequest)
+            .send()
+            .await
+            .expect("Failed to create test table");
+
+        // Wait for table to be ready
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        table_name
+    }
+
     /// Creates a test auth proofs table with a unique name
     async fn create_push_subscriptions_table(client: &DynamoDbClient) -> String {
         let table_name = format!("test-push-subscriptions-{}", Uuid::new_v4());
@@ -48,6 +101,13 @@ impl DynamoDbTestSetup {
                     .build()
                     .unwrap(),
             )
+            .attribute_definitions(
+                AttributeDefinition::builder()
+                    .attribute_name(PushSubscriptionAttribute::EncryptedPushId.to_string())
+                    .attribute_type(ScalarAttributeType::S)
+                    .build()
+                    .unwrap(),
+            )
             .key_schema(
                 KeySchemaElement::builder()
                     .attribute_name(PushSubscriptionAttribute::Topic.to_string())
@@ -62,6 +122,24 @@ impl DynamoDbTestSetup {
                     .build()
                     .unwrap(),
             )
+            .global_secondary_indexes(
+                GlobalSecondaryIndex::builder()
+                    .index_name(PUSH_ID_INDEX_NAME)
+                    .key_schema(
+                        KeySchemaElement::builder()
+                            .attribute_name(PushSubscriptionAttribute::EncryptedPushId.to_string())
+                            .key_type(KeyType::Hash)
+                            .build()
+                            .unwrap(),
+                    )
+                    .projection(
+                        Projection::builder()
+                            .projection_type(ProjectionType::KeysOnly)
+                            .build(),
+                    )
+                    .build()
+                    .unwrap(),
+            )
             .billing_mode(aws_sdk_dynamodb::types::BillingMode::PayPerRequest)
             .send()
             .await
@@ -103,17 +181,18 @@ impl Drop for DynamoDbTestSetup {
     fn drop(&mut self) {
         // Clean up all tables
         let client = self.client.clone();
-        let push_subscriptions_table_name = self.push_subscriptions_table_name.clone();
+        let table_names = [
+            self.push_subscriptions_table_name.clone(),
+            self.processed_envelopes_table_name.clone(),
+        ];
 
-        // Use tokio runtime to delete table
+        // Use tokio runtime to delete tables
         let handle = tokio::runtime::Handle::try_current();
         if let Ok(handle) = handle {
             handle.spawn(async move {
-                let _ = client
-                    .delete_table()
-                    .table_name(&push_subscriptions_table_name)
-                    .send()
-                    .await;
+                for table_name in table_names {
+                    let _ = client.delete_table().table_name(&table_name).send().await;
+                }
             });
         }
     }
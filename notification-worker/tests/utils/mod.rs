@@ -3,16 +3,18 @@
 mod dynamodb_setup;
 mod sqs_setup;
 
+use backend_storage::processed_envelope::ProcessedEnvelopeStorage;
 use backend_storage::push_subscription::PushSubscriptionStorage;
-use dynamodb_setup::DynamoDbTestSetup;
+use dynamodb_setup::{DynamoDbTestSetup, PUSH_ID_INDEX_NAME};
 
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use aws_sdk_dynamodb::Client as DynamoDbClient;
 use aws_sdk_sqs::Client as SqsClient;
-use backend_storage::queue::{NotificationQueue, QueueConfig};
+use backend_storage::queue::{FanOutRetryQueue, NotificationQueue, QueueConfig};
 use notification_worker::types::environment::Environment;
 
+use notification_worker::enclave_health::EnclaveHealth;
 use notification_worker::worker::message_processor::MessageProcessor;
 
 use crate::utils::sqs_setup::SqsSetup;
@@ -33,11 +35,18 @@ fn setup_test_env() {
 pub struct TestContext {
     pub environment: Environment,
     pub notification_queue: Arc<NotificationQueue>,
+    pub fanout_retry_queue: Arc<FanOutRetryQueue>,
     pub subscription_storage: Arc<PushSubscriptionStorage>,
+    pub dynamodb_client: Arc<DynamoDbClient>,
+    pub push_subscriptions_table_name: String,
+    pub processed_envelope_storage: Arc<ProcessedEnvelopeStorage>,
     pub message_processor: MessageProcessor,
+    /// Pauses the message processor on the first `record_down`
+    pub enclave_health: Arc<EnclaveHealth>,
     // Background handles for test duration
     _dynamodb_setup: DynamoDbTestSetup,
     _sqs_setup: SqsSetup,
+    _fanout_retry_sqs_setup: SqsSetup,
 }
 
 impl TestContext {
@@ -51,8 +60,13 @@ impl TestContext {
         let dynamodb_client = Arc::new(DynamoDbClient::new(&environment.aws_config().await));
         let dynamodb_test_setup = DynamoDbTestSetup::new(dynamodb_client.clone()).await;
         let subscription_storage = Arc::new(PushSubscriptionStorage::new(
-            dynamodb_client,
+            dynamodb_client.clone(),
             dynamodb_test_setup.push_subscriptions_table_name.clone(),
+            PUSH_ID_INDEX_NAME.to_string(),
+        ));
+        let processed_envelope_storage = Arc::new(ProcessedEnvelopeStorage::new(
+            dynamodb_client.clone(),
+            dynamodb_test_setup.processed_envelopes_table_name.clone(),
         ));
 
         // Initialize notification queue
@@ -68,20 +82,44 @@ impl TestContext {
             },
         ));
 
+        // Initialize fan-out retry queue
+        let fanout_retry_sqs_setup = SqsSetup::new(sqs_client.clone(), "fanout-retry-queue").await;
+        let fanout_retry_queue = Arc::new(FanOutRetryQueue::new(
+            sqs_client.clone(),
+            QueueConfig {
+                queue_url: fanout_retry_sqs_setup.queue_url.clone(),
+                default_max_messages: 10,
+                default_visibility_timeout: 60,
+                default_wait_time_seconds: 0,
+            },
+        ));
+
         // Create MessageProcessor directly (no background worker needed)
+        let enclave_health = Arc::new(EnclaveHealth::new(Duration::ZERO));
         let message_processor = MessageProcessor::new(
             0, // worker_id
             notification_queue.clone(),
+            fanout_retry_queue.clone(),
             subscription_storage.clone(),
-        );
+            enclave_health.clone(),
+        )
+        .with_processed_envelope_storage(Some(processed_envelope_storage.clone()));
 
         Self {
             environment,
             notification_queue,
+            fanout_retry_queue,
             subscription_storage,
+            dynamodb_client,
+            push_subscriptions_table_name: dynamodb_test_setup
+                .push_subscriptions_table_name
+                .clone(),
+            processed_envelope_storage,
             message_processor,
+            enclave_health,
             _dynamodb_setup: dynamodb_test_setup,
             _sqs_setup: sqs_setup,
+            _fanout_retry_sqs_setup: fanout_retry_sqs_setup,
         }
     }
 }
@@ -1,5 +1,11 @@
 use anyhow::Result;
-use enclave_types::EnclaveInitializeRequest;
+use attestation_verifier::EnclaveAttestationVerifier;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use common_types::logging::{self, LogFormat};
+use enclave_types::{
+    EnclaveAttestationDocRequest, EnclaveError, EnclaveInitializeChallengeRequest,
+    EnclaveInitializeRequest, DEFAULT_MAX_NOTIFICATION_RECIPIENTS,
+};
 use std::env;
 use std::time::Duration;
 use tracing::{error, info, warn};
@@ -15,11 +21,19 @@ const RETRY_DELAY_SECS: u64 = 2;
 /// If the enclave initialization fails, it will exit with a non-zero exit code.
 ///
 /// Uses Redis to coordinate key generation between enclaves.
+///
+/// This sidecar is the only one initializing the enclave, the enclave worker only verifies it's
+/// initialized through health checks.
 #[tokio::main]
 async fn main() -> Result<()> {
-    let (_guard, _tracer_shutdown) = datadog_tracing::init()?;
+    // `LOG_FORMAT=json|pretty` selects the format, JSON unless `APP_ENV=development`
+    let _telemetry = logging::init(LogFormat::from_env(LogFormat::default_from_app_env())?)?;
 
-    info!("Starting enclave initialization");
+    info!(
+        version = env!("CARGO_PKG_VERSION"),
+        rev = option_env!("GIT_REV"),
+        "Starting enclave initialization"
+    );
 
     // Read environment variables
     let enclave_cid: u32 = env::var("NITRO_CID")
@@ -48,6 +62,14 @@ async fn main() -> Result<()> {
         .parse()
         .expect("Invalid ENCLAVE_CLUSTER_PROXY_PORT value");
 
+    let max_notification_recipients: u32 = env::var("MAX_NOTIFICATION_RECIPIENTS")
+        .map(|value| {
+            value
+                .parse()
+                .expect("Invalid MAX_NOTIFICATION_RECIPIENTS value")
+        })
+        .unwrap_or(DEFAULT_MAX_NOTIFICATION_RECIPIENTS);
+
     // Get track identifier
     let track = env::var("ENCLAVE_TRACK").expect("ENCLAVE_TRACK environment variable not set");
     info!("Initializing enclave for track: {}", track);
@@ -76,35 +98,47 @@ async fn main() -> Result<()> {
         braze_http_proxy_port,
         enclave_cluster_proxy_port,
         can_generate_key_pair,
+        // Set on every attempt from a fresh initialize challenge
+        attestation_nonce: Vec::new(),
+        max_notification_recipients,
     };
 
     // Retry loop for initialization
     for attempt in 1..=MAX_RETRIES {
         info!("Initialization attempt {attempt}/{MAX_RETRIES}");
 
-        // Flatten the double Result and convert to a single error type
-        let result =
-            pontifex::client::send::<EnclaveInitializeRequest>(connection_details, &init_request)
-                .await
-                .map_err(|e| anyhow::anyhow!("Transport error: {}", e))
-                .and_then(|inner| inner.map_err(|e| anyhow::anyhow!("Enclave error: {:?}", e)));
-
-        match result {
+        match initialize_enclave(connection_details, init_request.clone()).await {
             Ok(()) => {
                 info!("✅ Enclave initialized successfully, track: {track}, can_generate_key_pair: {can_generate_key_pair}");
 
-                // If we generated a key, mark it as loaded in Redis
-                if can_generate_key_pair {
-                    if let Err(e) = key_manager.mark_key_loaded().await {
-                        error!("Failed to mark key as loaded in Redis: {}", e);
-                        // Continue anyway - the key was generated successfully
+                // If we generated a key, record it as the track's key, otherwise check we loaded
+                // the same key as the other enclaves of the track
+                let result = match fetch_key_fingerprint(connection_details).await {
+                    Ok(fingerprint) if can_generate_key_pair => {
+                        key_manager.mark_key_loaded(&fingerprint).await
+                    }
+                    Ok(fingerprint) => key_manager.verify_key_fingerprint(&fingerprint).await,
+                    Err(e) => Err(e.context("Failed to fetch the enclave key fingerprint")),
+                };
+                if let Err(e) = result {
+                    // Without a recorded fingerprint split-brain key generation goes undetected,
+                    // so fail and let the initialization be retried
+                    if can_generate_key_pair {
+                        if let Err(e) = key_manager.release_lock().await {
+                            error!("Failed to release key generation lock: {}", e);
+                        }
                     }
+                    error!("FATAL: Failed to verify the loaded key: {e:?}");
+                    std::process::exit(1);
                 }
 
                 return Ok(());
             }
             Err(e) => {
-                if attempt < MAX_RETRIES {
+                // Re-initializing with the same config is a no-op in the enclave, so this means it
+                // runs with another config and retrying can't succeed
+                let already_initialized = is_already_initialized(&e);
+                if attempt < MAX_RETRIES && !already_initialized {
                     error!(
                         "Initialization attempt {attempt} failed: {e:?}. Retrying in {RETRY_DELAY_SECS} seconds...",
                     );
@@ -117,9 +151,15 @@ async fn main() -> Result<()> {
                         }
                     }
 
-                    error!(
-                        "FATAL: Failed to initialize enclave after {MAX_RETRIES} attempts: {e:?}",
-                    );
+                    if already_initialized {
+                        error!(
+                            "FATAL: Enclave already initialized with a different config, re-deploy it: {e:?}",
+                        );
+                    } else {
+                        error!(
+                            "FATAL: Failed to initialize enclave after {MAX_RETRIES} attempts: {e:?}",
+                        );
+                    }
                     std::process::exit(1);
                 }
             }
@@ -128,3 +168,86 @@ async fn main() -> Result<()> {
 
     unreachable!()
 }
+
+/// Attaches the nonce of a fresh initialize challenge and sends the request to the enclave
+async fn initialize_enclave(
+    connection_details: pontifex::client::ConnectionDetails,
+    mut init_request: EnclaveInitializeRequest,
+) -> Result<()> {
+    init_request.attestation_nonce = fetch_initialize_challenge(connection_details).await?;
+
+    // Flatten the double Result and convert to a single error type
+    pontifex::client::send::<EnclaveInitializeRequest>(connection_details, &init_request)
+        .await
+        .map_err(|e| anyhow::anyhow!("Transport error: {}", e))
+        .and_then(|inner| inner.map_err(|e| anyhow::Error::new(e).context("Enclave error")))
+}
+
+/// Whether initializing failed because the enclave is already initialized with another config
+fn is_already_initialized(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<EnclaveError>(),
+        Some(EnclaveError::AlreadyInitialized)
+    )
+}
+
+/// Requests an initialize challenge and verifies its attestation document,
+/// returning the nonce the initialize request must carry.
+async fn fetch_initialize_challenge(
+    connection_details: pontifex::client::ConnectionDetails,
+) -> Result<Vec<u8>> {
+    let response = pontifex::client::send::<EnclaveInitializeChallengeRequest>(
+        connection_details,
+        &EnclaveInitializeChallengeRequest,
+    )
+    .await
+    .map_err(|e| anyhow::anyhow!("Transport error: {}", e))?
+    .map_err(|e| anyhow::anyhow!("Enclave error: {:?}", e))?;
+
+    // PCRs aren't known outside the enclave, so we only verify it's a genuine and fresh attestation
+    let verified = EnclaveAttestationVerifier::new(vec![])
+        .verify_attestation_document(&response.attestation)
+        .map_err(|e| anyhow::anyhow!("Challenge attestation verification failed: {e}"))?;
+
+    let nonce = verified
+        .nonce
+        .ok_or_else(|| anyhow::anyhow!("Challenge attestation has no nonce"))?;
+
+    Ok(STANDARD.decode(nonce)?)
+}
+
+/// Returns the fingerprint of the key loaded by the enclave, its attested public key
+async fn fetch_key_fingerprint(
+    connection_details: pontifex::client::ConnectionDetails,
+) -> Result<String> {
+    let response = pontifex::client::send::<EnclaveAttestationDocRequest>(
+        connection_details,
+        &EnclaveAttestationDocRequest,
+    )
+    .await
+    .map_err(|e| anyhow::anyhow!("Transport error: {}", e))?
+    .map_err(|e| anyhow::anyhow!("Enclave error: {:?}", e))?;
+
+    let verified = EnclaveAttestationVerifier::new(vec![])
+        .verify_attestation_document(&response.attestation)
+        .map_err(|e| anyhow::anyhow!("Attestation verification failed: {e}"))?;
+
+    Ok(verified.enclave_public_key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_already_initialized_is_detected() {
+        let error = anyhow::Error::new(EnclaveError::AlreadyInitialized).context("Enclave error");
+        assert!(is_already_initialized(&error));
+
+        let error = anyhow::Error::new(EnclaveError::NotInitialized).context("Enclave error");
+        assert!(!is_already_initialized(&error));
+
+        let error = anyhow::anyhow!("Transport error: connection refused");
+        assert!(!is_already_initialized(&error));
+    }
+}
@@ -1,17 +1,43 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
 use redis::{
-    aio::ConnectionManager, AsyncTypedCommands, Client, ExistenceCheck, SetExpiry, SetOptions,
+    aio::ConnectionManager, AsyncTypedCommands, Client, ExistenceCheck, Script, SetExpiry,
+    SetOptions,
 };
-use std::time::Duration;
+use std::{fmt, future::Future, time::Duration};
 use tracing::{info, warn};
 
 const LOCK_TTL_SECS: u64 = 60; // 1 minute for key generation
 const REDIS_TIMEOUT: Duration = Duration::from_secs(600); // 10 minutes
+const IN_PROGRESS: &str = "in-progress";
+const CONNECT_MAX_ATTEMPTS: u32 = 6;
+const CONNECT_INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const CONNECT_MAX_BACKOFF: Duration = Duration::from_secs(8);
+
+/// Deletes `KEYS[1]` only if it still holds `ARGV[1]`, returns the number of deleted keys
+const COMPARE_AND_DELETE_SCRIPT: &str = r#"
+if redis.call("GET", KEYS[1]) == ARGV[1] then
+    return redis.call("DEL", KEYS[1])
+end
+return 0
+"#;
+
+/// Records the fingerprint `ARGV[2]` in `KEYS[2]` and marks the key `KEYS[1]` as loaded, only if
+/// `KEYS[1]` still holds the lock `ARGV[1]`. Returns 1 if recorded, 0 if the lock was lost.
+const RECORD_GENERATED_KEY_SCRIPT: &str = r#"
+if redis.call("GET", KEYS[1]) == ARGV[1] then
+    redis.call("SET", KEYS[2], ARGV[2])
+    redis.call("SET", KEYS[1], "loaded")
+    return 1
+end
+return 0
+"#;
 
 #[derive(Clone)]
 pub struct RedisKeyManager {
     connection_manager: ConnectionManager,
     track: String,
+    /// Identifies the lock acquired by this instance, so it never releases another one's
+    lock_token: String,
 }
 
 /// Key Manager powered by Redis
@@ -21,13 +47,23 @@ pub struct RedisKeyManager {
 /// Subsequent enclaves will check if the lock is acquired and if not, they will wait for the lock to be released.
 impl RedisKeyManager {
     /// Create a new Redis key manager with connection manager
+    ///
+    /// Connecting is retried with exponential backoff, so Redis starting alongside the enclaves
+    /// doesn't fail the initialization.
     pub async fn new(redis_url: &str, track: &str) -> Result<Self> {
         let client = Client::open(redis_url)?;
-        let connection_manager = ConnectionManager::new(client).await?;
+        let connection_manager = retry_with_backoff(
+            CONNECT_MAX_ATTEMPTS,
+            CONNECT_INITIAL_BACKOFF,
+            CONNECT_MAX_BACKOFF,
+            || ConnectionManager::new(client.clone()),
+        )
+        .await?;
 
         Ok(Self {
             connection_manager,
             track: track.to_string(),
+            lock_token: uuid::Uuid::new_v4().to_string(),
         })
     }
 
@@ -50,7 +86,7 @@ impl RedisKeyManager {
                 );
                 self.acquire_generation_lock().await
             }
-            Some("in-progress") => {
+            Some(state) if state.starts_with(IN_PROGRESS) => {
                 info!(
                     "Key generation already in progress for track {}",
                     self.track
@@ -78,7 +114,7 @@ impl RedisKeyManager {
             REDIS_TIMEOUT,
             conn.set_options(
                 &key,
-                "in-progress",
+                self.lock_value(),
                 SetOptions::default()
                     .conditional_set(ExistenceCheck::NX)
                     .with_expiration(SetExpiry::EX(LOCK_TTL_SECS)),
@@ -102,30 +138,290 @@ impl RedisKeyManager {
         Ok(acquired)
     }
 
-    /// Mark key as successfully loaded
-    pub async fn mark_key_loaded(&self) -> Result<()> {
+    /// Mark the key generated by this instance as loaded and record its fingerprint
+    ///
+    /// The fingerprint of a previous key is overwritten, as the track's key was regenerated. Both
+    /// are written atomically, and only while this instance still holds the generation lock, so
+    /// an instance whose lock expired can't overwrite the key of the one that took over.
+    pub async fn mark_key_loaded(&self, fingerprint: &str) -> Result<()> {
         let key = format!("enclave-key:{}", self.track);
+        let fingerprint_key = format!("enclave-key-fingerprint:{}", self.track);
         let mut conn = self.connection_manager.clone();
 
-        // Set to "loaded" without expiration (permanent)
-        tokio::time::timeout(REDIS_TIMEOUT, conn.set(&key, "loaded")).await??;
+        let recorded: i64 = tokio::time::timeout(
+            REDIS_TIMEOUT,
+            Script::new(RECORD_GENERATED_KEY_SCRIPT)
+                .key(&key)
+                .key(&fingerprint_key)
+                .arg(self.lock_value())
+                .arg(fingerprint)
+                .invoke_async(&mut conn),
+        )
+        .await??;
+
+        if recorded == 0 {
+            bail!(
+                "Key generation lock for track {} expired before the key was recorded",
+                self.track
+            );
+        }
 
         info!("Marked key as loaded for track {}", self.track);
         Ok(())
     }
 
+    /// Check this instance loaded the same key as the enclave that generated it
+    ///
+    /// The fingerprint is recorded by the generating enclave, see `mark_key_loaded`. Tracks whose
+    /// key was generated before fingerprints were introduced have none, in which case the first
+    /// enclave to report records its fingerprint. A mismatch means several enclaves generated a
+    /// key (split-brain) and fails with a `SplitBrainError`.
+    pub async fn verify_key_fingerprint(&self, fingerprint: &str) -> Result<()> {
+        let key = format!("enclave-key-fingerprint:{}", self.track);
+        let mut conn = self.connection_manager.clone();
+
+        // Only records the fingerprint if none exists yet (NX)
+        tokio::time::timeout(
+            REDIS_TIMEOUT,
+            conn.set_options(
+                &key,
+                fingerprint,
+                SetOptions::default().conditional_set(ExistenceCheck::NX),
+            ),
+        )
+        .await??;
+
+        let recorded: Option<String> =
+            tokio::time::timeout(REDIS_TIMEOUT, conn.get(&key)).await??;
+        check_fingerprint(recorded.as_deref(), fingerprint)?;
+
+        info!("Key fingerprint verified for track {}", self.track);
+        Ok(())
+    }
+
     /// Release the lock in case of failure
+    ///
+    /// Only releases the lock if it is still held by this instance: once it expired another
+    /// initializer may have acquired it, and that lock must be left alone.
     pub async fn release_lock(&self) -> Result<()> {
         let key = format!("enclave-key:{}", self.track);
         let mut conn = self.connection_manager.clone();
 
         // Delete the key to allow another enclave to try
-        tokio::time::timeout(REDIS_TIMEOUT, conn.del(&key)).await??;
+        let deleted: i64 = tokio::time::timeout(
+            REDIS_TIMEOUT,
+            Script::new(COMPARE_AND_DELETE_SCRIPT)
+                .key(&key)
+                .arg(self.lock_value())
+                .invoke_async(&mut conn),
+        )
+        .await??;
 
-        warn!(
-            "Released key generation lock for track {} due to failure",
-            self.track
-        );
+        if deleted == 0 {
+            warn!(
+                "Key generation lock for track {} is not held by this instance, not releasing it",
+                self.track
+            );
+        } else {
+            warn!(
+                "Released key generation lock for track {} due to failure",
+                self.track
+            );
+        }
         Ok(())
     }
+
+    /// Value of the lock while held by this instance
+    fn lock_value(&self) -> String {
+        format!("{IN_PROGRESS}:{}", self.lock_token)
+    }
+}
+
+/// Enclaves of the same track loaded different keys
+#[derive(Debug)]
+pub struct SplitBrainError {
+    recorded: String,
+    loaded: String,
+}
+
+impl fmt::Display for SplitBrainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Split-brain key generation detected: enclave loaded key {} but the track's key is {}",
+            self.loaded, self.recorded
+        )
+    }
+}
+
+impl std::error::Error for SplitBrainError {}
+
+/// Errors if the `recorded` fingerprint of the track differs from the `loaded` one
+fn check_fingerprint(recorded: Option<&str>, loaded: &str) -> Result<()> {
+    match recorded {
+        Some(recorded) if recorded != loaded => Err(SplitBrainError {
+            recorded: recorded.to_string(),
+            loaded: loaded.to_string(),
+        }
+        .into()),
+        Some(_) => Ok(()),
+        None => bail!("No key fingerprint recorded"),
+    }
+}
+
+/// Runs `connect` until it succeeds or `max_attempts` attempts failed
+///
+/// The delay between attempts starts at `initial_backoff` and doubles up to `max_backoff`.
+async fn retry_with_backoff<T, E, F, Fut>(
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    mut connect: F,
+) -> Result<T, E>
+where
+    E: std::fmt::Display,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut backoff = initial_backoff;
+    let mut attempt = 1;
+    loop {
+        match connect().await {
+            Ok(value) => return Ok(value),
+            Err(e) if attempt < max_attempts => {
+                warn!(
+                    "Redis connection attempt {attempt}/{max_attempts} failed: {e}. Retrying in {backoff:?}..."
+                );
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(max_backoff);
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[test]
+    fn test_detects_split_brain_key_generation() {
+        assert!(check_fingerprint(Some("key-a"), "key-a").is_ok());
+
+        let err = check_fingerprint(Some("key-a"), "key-b").unwrap_err();
+        assert!(err.is::<SplitBrainError>());
+
+        let err = check_fingerprint(None, "key-a").unwrap_err();
+        assert!(!err.is::<SplitBrainError>());
+    }
+
+    // Requires the local Redis from the docker compose setup
+    #[tokio::test]
+    async fn test_release_with_wrong_token_is_noop() {
+        let track = format!("test-{}", uuid::Uuid::new_v4());
+        let holder = RedisKeyManager::new("redis://localhost:6379", &track)
+            .await
+            .unwrap();
+        let other = RedisKeyManager::new("redis://localhost:6379", &track)
+            .await
+            .unwrap();
+        let key = format!("enclave-key:{track}");
+        let mut conn = holder.connection_manager.clone();
+
+        assert!(holder.should_generate_key().await.unwrap());
+        assert!(!other.should_generate_key().await.unwrap());
+
+        // Another instance can't release the holder's lock
+        other.release_lock().await.unwrap();
+        assert_eq!(conn.get(&key).await.unwrap(), Some(holder.lock_value()));
+
+        holder.release_lock().await.unwrap();
+        assert_eq!(conn.get(&key).await.unwrap(), None);
+    }
+
+    // Requires the local Redis from the docker compose setup
+    #[tokio::test]
+    async fn test_regenerated_key_overwrites_fingerprint() {
+        let track = format!("test-{}", uuid::Uuid::new_v4());
+        let first = RedisKeyManager::new("redis://localhost:6379", &track)
+            .await
+            .unwrap();
+        let regenerated = RedisKeyManager::new("redis://localhost:6379", &track)
+            .await
+            .unwrap();
+        let mut conn = first.connection_manager.clone();
+
+        assert!(first.should_generate_key().await.unwrap());
+        first.mark_key_loaded("key-a").await.unwrap();
+        first.verify_key_fingerprint("key-a").await.unwrap();
+
+        // The key is regenerated, e.g. after the track's state was reset
+        let _: () = conn.del(format!("enclave-key:{track}")).await.unwrap();
+        assert!(regenerated.should_generate_key().await.unwrap());
+        regenerated.mark_key_loaded("key-b").await.unwrap();
+
+        regenerated.verify_key_fingerprint("key-b").await.unwrap();
+        let err = first.verify_key_fingerprint("key-a").await.unwrap_err();
+        assert!(err.is::<SplitBrainError>());
+    }
+
+    // Requires the local Redis from the docker compose setup
+    #[tokio::test]
+    async fn test_mark_key_loaded_requires_the_lock() {
+        let track = format!("test-{}", uuid::Uuid::new_v4());
+        let manager = RedisKeyManager::new("redis://localhost:6379", &track)
+            .await
+            .unwrap();
+        let mut conn = manager.connection_manager.clone();
+
+        assert!(manager.mark_key_loaded("key-a").await.is_err());
+        let fingerprint: Option<String> = conn
+            .get(format!("enclave-key-fingerprint:{track}"))
+            .await
+            .unwrap();
+        assert_eq!(fingerprint, None);
+    }
+
+    #[tokio::test]
+    async fn test_connection_succeeds_after_transient_failure() {
+        let attempts = AtomicU32::new(0);
+
+        let result = retry_with_backoff(
+            3,
+            Duration::from_millis(1),
+            Duration::from_millis(2),
+            || async {
+                if attempts.fetch_add(1, Ordering::Relaxed) == 0 {
+                    Err("connection refused")
+                } else {
+                    Ok("connected")
+                }
+            },
+        )
+        .await;
+
+        assert_eq!(result, Ok("connected"));
+        assert_eq!(attempts.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn test_connection_gives_up_after_max_attempts() {
+        let attempts = AtomicU32::new(0);
+
+        let result = retry_with_backoff(
+            3,
+            Duration::from_millis(1),
+            Duration::from_millis(2),
+            || async {
+                attempts.fetch_add(1, Ordering::Relaxed);
+                Err::<(), _>("connection refused")
+            },
+        )
+        .await;
+
+        assert_eq!(result, Err("connection refused"));
+        assert_eq!(attempts.load(Ordering::Relaxed), 3);
+    }
 }
@@ -0,0 +1,100 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use enclave_types::SecretKeyReleaseAuditEntry;
+
+/// Maximum number of entries kept, the oldest entries are evicted first
+pub const MAX_AUDIT_ENTRIES: usize = 256;
+
+/// In-memory ring buffer recording every time the enclave released its secret key.
+///
+/// Entries never contain key material, only who received it and when.
+pub struct SecretKeyAuditLog {
+    entries: VecDeque<SecretKeyReleaseAuditEntry>,
+    capacity: usize,
+}
+
+impl Default for SecretKeyAuditLog {
+    fn default() -> Self {
+        Self::with_capacity(MAX_AUDIT_ENTRIES)
+    }
+}
+
+impl SecretKeyAuditLog {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Records a key release to the enclave identified by its attested PCRs and module ID
+    pub fn record_release(&mut self, requester_pcrs: &BTreeMap<usize, String>, module_id: &str) {
+        let released_at_millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| u64::try_from(d.as_millis()).unwrap_or(u64::MAX))
+            .unwrap_or_default();
+
+        let entry = SecretKeyReleaseAuditEntry {
+            requester_pcr0: requester_pcrs.get(&0).cloned().unwrap_or_default(),
+            requester_module_id: module_id.to_string(),
+            released_at_millis,
+        };
+
+        tracing::info!(
+            requester_pcr0 = %entry.requester_pcr0,
+            requester_module_id = %entry.requester_module_id,
+            "Released secret key to enclave"
+        );
+
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    /// Returns the recorded entries, oldest first
+    pub fn entries(&self) -> Vec<SecretKeyReleaseAuditEntry> {
+        self.entries.iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pcrs(pcr0: &str) -> BTreeMap<usize, String> {
+        BTreeMap::from([
+            (0, pcr0.to_string()),
+            (1, "pcr1".to_string()),
+            (2, "pcr2".to_string()),
+        ])
+    }
+
+    #[test]
+    fn test_release_records_entry() {
+        let mut log = SecretKeyAuditLog::default();
+
+        log.record_release(&pcrs("abcd"), "i-0123-enc0123");
+
+        let entries = log.entries();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].requester_pcr0, "abcd");
+        assert_eq!(entries[0].requester_module_id, "i-0123-enc0123");
+        assert!(entries[0].released_at_millis > 0);
+    }
+
+    #[test]
+    fn test_oldest_entries_are_evicted() {
+        let mut log = SecretKeyAuditLog::with_capacity(2);
+
+        log.record_release(&pcrs("first"), "enclave-1");
+        log.record_release(&pcrs("second"), "enclave-2");
+        log.record_release(&pcrs("third"), "enclave-3");
+
+        let entries = log.entries();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].requester_module_id, "enclave-2");
+        assert_eq!(entries[1].requester_module_id, "enclave-3");
+    }
+}
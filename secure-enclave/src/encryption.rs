@@ -1,4 +1,4 @@
-use std::{fs, path::Path};
+use std::{fs, io, path::Path};
 
 use crypto_box::{aead::OsRng, PublicKey, SecretKey};
 use enclave_types::EnclaveError;
@@ -36,31 +36,181 @@ impl KeyPair {
     }
 }
 
+/// Locations of the kernel's current HW RNG source
+pub const RNG_CURRENT_SYSFS_PATHS: [&str; 2] = [
+    "/sys/class/misc/hw_random/rng_current",
+    "/sys/devices/virtual/misc/hw_random/rng_current",
+];
+
+/// Expected content of `rng_current` when the Nitro Secure Module HW RNG is in use
+const NSM_HWRNG: &str = "nsm-hwrng";
+
+/// Reasons the kernel HW RNG source could not be verified
+#[derive(Debug, thiserror::Error)]
+pub enum RngVerificationError {
+    #[error("rng_current sysfs path not found, is the hw_random driver loaded?")]
+    SourceNotFound,
+    #[error("failed to read {path}: {source}")]
+    Unreadable {
+        path: String,
+        #[source]
+        source: io::Error,
+    },
+    #[error("{path} has unexpected content {content:?}")]
+    UnexpectedContent { path: String, content: String },
+    #[error("rng_current is '{current}', expected 'nsm-hwrng'")]
+    WrongSource { current: String },
+}
+
 /// Verify that the kernel's HW RNG source in use is `nsm-hwrng`.
 /// This ensures the AWS Nitro RNG was registered and is periodically feeding entropy.
 /// See Randomness Section in:
 /// `<https://blog.trailofbits.com/2024/09/24/notes-on-aws-nitro-enclaves-attack-surface>`
-pub fn verify_nsm_hwrng_current() -> anyhow::Result<()> {
-    const SYSFS_PATHS: [&str; 2] = [
-        "/sys/class/misc/hw_random/rng_current",
-        "/sys/devices/virtual/misc/hw_random/rng_current",
-    ];
-
-    for path in SYSFS_PATHS {
-        if Path::new(path).exists() {
-            let contents = fs::read_to_string(path)?;
-            let current = contents.trim();
-            tracing::info!("rng_current={current}");
-
-            return if current == "nsm-hwrng" {
-                Ok(())
-            } else {
-                Err(anyhow::anyhow!(
-                    "rng_current is '{current}', expected 'nsm-hwrng'"
-                ))
-            };
+///
+/// # Errors
+/// Returns an `RngVerificationError` describing why the RNG source could not be verified
+pub fn verify_nsm_hwrng_current() -> Result<(), RngVerificationError> {
+    verify_nsm_hwrng_current_at(&RNG_CURRENT_SYSFS_PATHS.map(Path::new))
+}
+
+/// Same as [`verify_nsm_hwrng_current`] but reads the first existing path of `paths`
+/// instead of the real sysfs locations, so the check can be exercised outside an enclave.
+///
+/// # Errors
+/// Returns an `RngVerificationError` describing why the RNG source could not be verified
+pub fn verify_nsm_hwrng_current_at(paths: &[&Path]) -> Result<(), RngVerificationError> {
+    for path in paths {
+        if path.exists() {
+            return check_rng_current(&path.display().to_string(), fs::read_to_string(path));
         }
     }
 
-    Err(anyhow::anyhow!("rng_current sysfs path not found"))
+    Err(RngVerificationError::SourceNotFound)
+}
+
+/// Checks the result of reading `rng_current` at `path`
+fn check_rng_current(path: &str, contents: io::Result<String>) -> Result<(), RngVerificationError> {
+    let contents = contents.map_err(|source| RngVerificationError::Unreadable {
+        path: path.to_string(),
+        source,
+    })?;
+    let current = contents.trim();
+    tracing::info!("rng_current={current}");
+
+    // The sysfs attribute holds a single driver name
+    let is_driver_name = !current.is_empty()
+        && current
+            .chars()
+            .all(|c| c.is_ascii_graphic() && !c.is_ascii_whitespace());
+    if !is_driver_name {
+        return Err(RngVerificationError::UnexpectedContent {
+            path: path.to_string(),
+            content: contents,
+        });
+    }
+
+    if current == NSM_HWRNG {
+        Ok(())
+    } else {
+        Err(RngVerificationError::WrongSource {
+            current: current.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    const PATH: &str = "/sys/class/misc/hw_random/rng_current";
+
+    fn rng_current_file(contents: &str) -> NamedTempFile {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(contents.as_bytes()).unwrap();
+        file
+    }
+
+    #[test]
+    fn test_nsm_hwrng_is_accepted() {
+        assert!(check_rng_current(PATH, Ok("nsm-hwrng\n".to_string())).is_ok());
+    }
+
+    #[test]
+    fn test_other_source_is_wrong_source() {
+        let err = check_rng_current(PATH, Ok("tpm-rng-0\n".to_string())).unwrap_err();
+        assert!(matches!(
+            err,
+            RngVerificationError::WrongSource { ref current } if current == "tpm-rng-0"
+        ));
+    }
+
+    #[test]
+    fn test_none_source_is_wrong_source() {
+        let err = check_rng_current(PATH, Ok("none\n".to_string())).unwrap_err();
+        assert!(matches!(err, RngVerificationError::WrongSource { .. }));
+    }
+
+    #[test]
+    fn test_read_failure_is_unreadable() {
+        let err = check_rng_current(PATH, Err(io::Error::from(io::ErrorKind::PermissionDenied)))
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            RngVerificationError::Unreadable { ref path, .. } if path == PATH
+        ));
+    }
+
+    #[test]
+    fn test_empty_content_is_unexpected() {
+        let err = check_rng_current(PATH, Ok("\n".to_string())).unwrap_err();
+        assert!(matches!(
+            err,
+            RngVerificationError::UnexpectedContent { .. }
+        ));
+    }
+
+    #[test]
+    fn test_multiple_tokens_are_unexpected() {
+        let err = check_rng_current(PATH, Ok("nsm-hwrng tpm-rng-0\n".to_string())).unwrap_err();
+        assert!(matches!(
+            err,
+            RngVerificationError::UnexpectedContent { .. }
+        ));
+    }
+
+    #[test]
+    fn test_verify_at_matching_source() {
+        let file = rng_current_file("nsm-hwrng\n");
+
+        assert!(verify_nsm_hwrng_current_at(&[file.path()]).is_ok());
+    }
+
+    #[test]
+    fn test_verify_at_mismatching_source() {
+        let file = rng_current_file("virtio_rng.0\n");
+
+        let err = verify_nsm_hwrng_current_at(&[file.path()]).unwrap_err();
+        assert!(matches!(
+            err,
+            RngVerificationError::WrongSource { ref current } if current == "virtio_rng.0"
+        ));
+    }
+
+    #[test]
+    fn test_verify_at_uses_first_existing_path() {
+        let missing = Path::new("/nonexistent/hw_random/rng_current");
+        let file = rng_current_file("nsm-hwrng\n");
+
+        assert!(verify_nsm_hwrng_current_at(&[missing, file.path()]).is_ok());
+    }
+
+    #[test]
+    fn test_verify_at_no_existing_path() {
+        let missing = Path::new("/nonexistent/hw_random/rng_current");
+
+        let err = verify_nsm_hwrng_current_at(&[missing]).unwrap_err();
+        assert!(matches!(err, RngVerificationError::SourceNotFound));
+    }
 }
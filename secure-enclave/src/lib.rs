@@ -1,3 +1,6 @@
+pub mod audit;
 pub mod encryption;
+pub mod logging;
+pub mod metrics;
 pub mod pontifex_server;
 pub mod state;
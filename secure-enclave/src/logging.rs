@@ -0,0 +1,44 @@
+use common_types::logging::{InvalidLogFormat, LogFormat};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_sdk::trace::{Tracer, TracerProvider};
+use tracing::level_filters::LevelFilter;
+use tracing_opentelemetry::OpenTelemetryLayer;
+use tracing_subscriber::{layer::SubscriberExt, registry::LookupSpan, util::SubscriberInitExt};
+
+/// Installs the global tracing subscriber with the format selected by `LOG_FORMAT`
+///
+/// # Errors
+///
+/// If `LOG_FORMAT` holds an unknown format
+pub fn init() -> Result<(), InvalidLogFormat> {
+    let format = LogFormat::from_env(LogFormat::default_from_app_env())?;
+
+    let registry = tracing_subscriber::registry()
+        .with(LevelFilter::INFO)
+        .with(trace_layer());
+    let fmt = tracing_subscriber::fmt::layer().with_level(true);
+
+    match format {
+        LogFormat::Json => registry
+            .with(fmt.json().with_current_span(true).with_span_list(true))
+            .init(),
+        LogFormat::Pretty => registry.with(fmt.pretty()).init(),
+    }
+    Ok(())
+}
+
+/// Layer giving spans an OpenTelemetry context, so requests can continue the caller's trace
+///
+/// The enclave can't reach the Datadog agent, so spans aren't exported: spans that continue a
+/// trace record its IDs as fields instead, correlating their logs with the caller's trace.
+pub fn trace_layer<S>() -> OpenTelemetryLayer<S, Tracer>
+where
+    S: tracing::Subscriber + for<'span> LookupSpan<'span>,
+{
+    let provider = TracerProvider::builder().build();
+    let tracer = provider.tracer("secure-enclave");
+    // The tracer only holds a weak reference to its provider, keep it alive globally
+    opentelemetry::global::set_tracer_provider(provider);
+
+    tracing_opentelemetry::layer().with_tracer(tracer)
+}
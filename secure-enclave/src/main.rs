@@ -14,18 +14,20 @@ const EXIT_RNG_MISCONFIG: i32 = 78;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // We use tracing for logging, this is only useful when the enclave runs on DEBUG MODE
-    tracing_subscriber::fmt()
-        .with_max_level(tracing::Level::INFO)
-        .with_level(true)
-        .pretty()
-        .init();
+    // We use tracing for logging, this is only useful when the enclave runs on DEBUG MODE.
+    // `LOG_FORMAT=json|pretty` selects the format, JSON unless `APP_ENV=development`.
+    secure_enclave::logging::init()?;
 
-    info!("Starting Secure Enclave");
+    info!(
+        version = env!("CARGO_PKG_VERSION"),
+        rev = option_env!("GIT_REV"),
+        "Starting Secure Enclave"
+    );
 
     // Ensure kernel RNG is backed by the Nitro Secure Module HW RNG.
     // Otherwise, hard fail.
-    if let Err(_e) = verify_nsm_hwrng_current() {
+    if let Err(e) = verify_nsm_hwrng_current() {
+        error!("HW RNG verification failed: {e}");
         std::process::exit(EXIT_RNG_MISCONFIG);
     }
 
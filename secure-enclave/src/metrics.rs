@@ -0,0 +1,109 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use enclave_types::EnclaveMetricsSnapshot;
+
+/// Maximum number of distinct metric names kept, samples for new names are dropped beyond it
+pub const MAX_METRIC_NAMES: usize = 32;
+/// Maximum number of timing samples kept per metric between two pulls
+pub const MAX_TIMING_SAMPLES: usize = 1024;
+
+/// Bounded in-memory aggregator of the enclave's operational metrics.
+///
+/// The enclave can't reach the metrics agent, so metrics accumulate here until the worker pulls
+/// them with `EnclaveMetricsRequest`, which resets the store. Recording only needs `&self`, so
+/// handlers holding a read lock on the state can record.
+#[derive(Default)]
+pub struct EnclaveMetrics {
+    snapshot: Mutex<EnclaveMetricsSnapshot>,
+}
+
+impl EnclaveMetrics {
+    /// Adds `value` to the counter `name`
+    pub fn increment(&self, name: &str, value: u64) {
+        let mut snapshot = self.lock();
+        if !snapshot.counters.contains_key(name) && snapshot.counters.len() >= MAX_METRIC_NAMES {
+            snapshot.dropped_samples += 1;
+            return;
+        }
+
+        let counter = snapshot.counters.entry(name.to_string()).or_default();
+        *counter = counter.saturating_add(value);
+    }
+
+    /// Records a timing sample for `name`
+    pub fn record_timing(&self, name: &str, duration: Duration) {
+        let mut snapshot = self.lock();
+        let full = snapshot
+            .timings_ms
+            .get(name)
+            .map_or(snapshot.timings_ms.len() >= MAX_METRIC_NAMES, |samples| {
+                samples.len() >= MAX_TIMING_SAMPLES
+            });
+        if full {
+            snapshot.dropped_samples += 1;
+            return;
+        }
+
+        snapshot
+            .timings_ms
+            .entry(name.to_string())
+            .or_default()
+            .push(duration.as_secs_f64() * 1000.0);
+    }
+
+    /// Returns the metrics accumulated since the previous call and resets the store
+    pub fn take_snapshot(&self) -> EnclaveMetricsSnapshot {
+        std::mem::take(&mut *self.lock())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, EnclaveMetricsSnapshot> {
+        // Metrics are best-effort, a panic while recording must not disable them
+        self.snapshot
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pull_returns_and_resets_metrics() {
+        let metrics = EnclaveMetrics::default();
+        metrics.increment("decrypt_failures", 2);
+        metrics.increment("decrypt_failures", 1);
+        metrics.record_timing("braze_latency", Duration::from_millis(12));
+
+        let snapshot = metrics.take_snapshot();
+        assert_eq!(snapshot.counters["decrypt_failures"], 3);
+        assert_eq!(snapshot.timings_ms["braze_latency"], vec![12.0]);
+
+        // Pulled metrics are not reported twice
+        assert_eq!(metrics.take_snapshot(), EnclaveMetricsSnapshot::default());
+
+        metrics.increment("decrypt_failures", 1);
+        assert_eq!(metrics.take_snapshot().counters["decrypt_failures"], 1);
+    }
+
+    #[test]
+    fn test_store_is_bounded() {
+        let metrics = EnclaveMetrics::default();
+        for i in 0..=MAX_METRIC_NAMES {
+            metrics.increment(&format!("counter_{i}"), 1);
+        }
+        for _ in 0..=MAX_TIMING_SAMPLES {
+            metrics.record_timing("braze_latency", Duration::from_millis(1));
+        }
+
+        let snapshot = metrics.take_snapshot();
+        assert_eq!(snapshot.counters.len(), MAX_METRIC_NAMES);
+        assert_eq!(
+            snapshot.timings_ms["braze_latency"].len(),
+            MAX_TIMING_SAMPLES
+        );
+        assert_eq!(snapshot.dropped_samples, 2);
+    }
+}
@@ -1,28 +1,45 @@
 use std::sync::Arc;
 use std::time::Duration;
 
-use crate::{encryption::KeyPair, state::EnclaveState};
+use crate::{
+    encryption::KeyPair,
+    state::{EnclaveState, EnclaveStateSnapshot, InitializeConfig},
+};
 use enclave_types::{EnclaveError, EnclaveInitializeRequest, EnclaveSecretKeyRequest};
+use subtle::ConstantTimeEq;
 use tokio::sync::RwLock;
 use tracing::info;
 
 /// Parent CID
 const PARENT_CID: u32 = 3;
+/// Braze region prefixes served from the EU domain, `https://rest.{region}.braze.eu`
+const BRAZE_EU_REGION_PREFIXES: [&str; 1] = ["fra"];
 
 pub async fn handler(
     state: Arc<RwLock<EnclaveState>>,
     config: EnclaveInitializeRequest,
 ) -> Result<(), EnclaveError> {
+    // Only accept requests echoing the challenge we issued, the nonce is single-use. This keeps
+    // stale or replayed requests out, it doesn't authenticate who sent the request: the initializer
+    // runs on the parent instance, which has no secure module to attest itself with. Callers are
+    // trusted by virtue of reaching the enclave's vsock, which only the parent instance can.
+    let expected_nonce = state.write().await.pending_initialize_nonce.take();
+    verify_initialize_nonce(expected_nonce.as_deref(), &config.attestation_nonce)?;
+
+    let braze_api_url = braze_api_url(&config.braze_api_region)?;
+    let requested_config = InitializeConfig::from(&config);
+    {
+        let state_guard = state.read().await;
+        if state_guard.initialized {
+            return check_reinitialize(state_guard.initialize_config.as_ref(), &requested_config);
+        }
+    }
+
     let client = pontifex::http::client_http2_only(
         config.braze_http_proxy_port,
         &pontifex::http::Http2ClientConfig::default(),
     );
 
-    let initialized = state.read().await.initialized;
-    if initialized {
-        return Err(EnclaveError::AlreadyInitialized);
-    }
-
     // Panic if ephemeral_key_pair is None, this is not a valid path
     let ephemeral_key_pair =
         state
@@ -35,7 +52,7 @@ pub async fn handler(
             ))?;
     let attestation_doc_with_ephemeral_pk =
         state.read().await.attestation_doc_with_ephemeral_pk.clone();
-    let encryption_keys = try_retrieve_key_pair(
+    let snapshot = try_retrieve_key_pair(
         config.enclave_cluster_proxy_port,
         config.can_generate_key_pair,
         ephemeral_key_pair,
@@ -44,14 +61,17 @@ pub async fn handler(
     .await?;
 
     let mut state_guard = state.write().await;
+    // A concurrent initialize may have completed while we were retrieving the key pair
+    if state_guard.initialized {
+        return check_reinitialize(state_guard.initialize_config.as_ref(), &requested_config);
+    }
+    state_guard.restore(&snapshot)?;
     state_guard.http_proxy_client = Some(client);
     state_guard.braze_api_key = Some(config.braze_api_key);
-    state_guard.braze_api_url = Some(format!(
-        "https://rest.{}.braze.com",
-        config.braze_api_region
-    ));
-    state_guard.encryption_keys = Some(encryption_keys);
+    state_guard.braze_api_url = Some(braze_api_url);
+    state_guard.max_notification_recipients = config.max_notification_recipients as usize;
     state_guard.ephemeral_key_pair = None; // Drop the ephemeral key pair after initialization
+    state_guard.initialize_config = Some(requested_config);
     state_guard.initialized = true;
 
     info!("✅ Enclave initialized successfully");
@@ -59,14 +79,61 @@ pub async fn handler(
     Ok(())
 }
 
-/// This function tries to retrieve the key pair from the enclaves cluster.
+/// Checks the initialize request carries the nonce of the outstanding initialize challenge.
+fn verify_initialize_nonce(expected: Option<&[u8]>, provided: &[u8]) -> Result<(), EnclaveError> {
+    match expected {
+        Some(expected) if bool::from(expected.ct_eq(provided)) => Ok(()),
+        _ => {
+            tracing::warn!("Rejected initialize request without a valid initialize challenge");
+            Err(EnclaveError::InitializeChallengeMismatch)
+        }
+    }
+}
+
+/// Builds the Braze REST API base URL, rejecting regions that aren't shaped like a Braze
+/// instance (`<location>-<number>`, e.g. `iad-05` or `fra-01`).
+fn braze_api_url(region: &str) -> Result<String, EnclaveError> {
+    let Some((location, number)) = region.split_once('-').filter(|(location, number)| {
+        location.len() == 3
+            && location.bytes().all(|b| b.is_ascii_lowercase())
+            && number.len() == 2
+            && number.bytes().all(|b| b.is_ascii_digit())
+    }) else {
+        return Err(EnclaveError::InvalidBrazeRegion(region.to_string()));
+    };
+
+    let domain = if BRAZE_EU_REGION_PREFIXES.contains(&location) {
+        "braze.eu"
+    } else {
+        "braze.com"
+    };
+
+    Ok(format!("https://rest.{location}-{number}.{domain}"))
+}
+
+/// Handles an initialize request for an already initialized enclave.
+///
+/// Re-initializing with the applied config is a no-op, any other config is rejected.
+fn check_reinitialize(
+    applied: Option<&InitializeConfig>,
+    requested: &InitializeConfig,
+) -> Result<(), EnclaveError> {
+    if applied == Some(requested) {
+        info!("Enclave already initialized with identical config");
+        Ok(())
+    } else {
+        Err(EnclaveError::AlreadyInitialized)
+    }
+}
+
+/// This function tries to retrieve the key versions from the enclaves cluster.
 /// If it fails and `can_generate_key_pair` is true, it generates a new key pair.
 async fn try_retrieve_key_pair(
     enclave_cluster_proxy_port: u32,
     can_generate_key_pair: bool,
     ephemeral_key_pair: KeyPair,
     attestation_doc_with_ephemeral_pk: Vec<u8>,
-) -> Result<KeyPair, EnclaveError> {
+) -> Result<EnclaveStateSnapshot, EnclaveError> {
     match request_key_pair_from_enclaves_cluster(
         enclave_cluster_proxy_port,
         ephemeral_key_pair,
@@ -74,14 +141,14 @@ async fn try_retrieve_key_pair(
     )
     .await
     {
-        Ok(key_pair) => Ok(key_pair),
+        Ok(snapshot) => Ok(snapshot),
         Err(e) => {
             tracing::error!("Error retrieving key pair from enclaves cluster: {e:?}");
 
             if can_generate_key_pair {
                 tracing::info!("Generating new key pair");
 
-                Ok(KeyPair::generate())
+                Ok(EnclaveStateSnapshot::from(&KeyPair::generate()))
             } else {
                 tracing::error!("Cannot generate key pair");
                 Err(e)
@@ -93,12 +160,12 @@ async fn try_retrieve_key_pair(
 /// Requests the secret key from other enclaves in the cluster via Pontifex.
 ///
 /// It sends it's own attestation document containing its ephemeral public key,
-/// and expects to receive the secret key sealed to that ephemeral public key.
+/// and expects to receive the key versions sealed to that ephemeral public key.
 async fn request_key_pair_from_enclaves_cluster(
     enclave_cluster_proxy_port: u32,
     ephemeral_key_pair: KeyPair,
     attestation_doc_with_ephemeral_pk: Vec<u8>,
-) -> Result<KeyPair, EnclaveError> {
+) -> Result<EnclaveStateSnapshot, EnclaveError> {
     let proxy_connection_details =
         pontifex::client::ConnectionDetails::new(PARENT_CID, enclave_cluster_proxy_port);
 
@@ -121,11 +188,133 @@ async fn request_key_pair_from_enclaves_cluster(
     .map_err(|e| EnclaveError::PontifexError(e.to_string()))??;
 
     let ephemeral_sk = ephemeral_key_pair.private_key;
-    let secret_key = ephemeral_sk
+    let snapshot = ephemeral_sk
         .unseal(&sealed_key)
         .map_err(|e| EnclaveError::DecryptSecretKeyFailed(format!("Unseal failed: {e:?}")))?;
 
-    let key_pair = KeyPair::from_secret_key_bytes(&secret_key)?;
+    let snapshot = EnclaveStateSnapshot::from_bytes(&snapshot)?;
+    // Validate the keys now so a bad snapshot goes through the retry path
+    snapshot.active_key_pair()?;
+
+    Ok(snapshot)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    Ok(key_pair)
+    #[test]
+    fn test_initialize_with_challenge_nonce_accepted() {
+        let nonce = b"challenge-nonce".to_vec();
+        assert!(verify_initialize_nonce(Some(&nonce), &nonce).is_ok());
+    }
+
+    #[test]
+    fn test_initialize_without_challenge_rejected() {
+        // No challenge was issued
+        assert!(matches!(
+            verify_initialize_nonce(None, b"challenge-nonce"),
+            Err(EnclaveError::InitializeChallengeMismatch)
+        ));
+        assert!(matches!(
+            verify_initialize_nonce(None, &[]),
+            Err(EnclaveError::InitializeChallengeMismatch)
+        ));
+    }
+
+    #[test]
+    fn test_initialize_with_wrong_nonce_rejected() {
+        let nonce = b"challenge-nonce".to_vec();
+
+        assert!(matches!(
+            verify_initialize_nonce(Some(&nonce), b"challenge-nonc3"),
+            Err(EnclaveError::InitializeChallengeMismatch)
+        ));
+        assert!(matches!(
+            verify_initialize_nonce(Some(&nonce), &[]),
+            Err(EnclaveError::InitializeChallengeMismatch)
+        ));
+    }
+
+    fn initialize_request(
+        braze_api_key: &str,
+        can_generate_key_pair: bool,
+    ) -> EnclaveInitializeRequest {
+        EnclaveInitializeRequest {
+            braze_api_key: braze_api_key.to_string(),
+            braze_api_region: "iad-01".to_string(),
+            braze_http_proxy_port: 8080,
+            can_generate_key_pair,
+            enclave_cluster_proxy_port: 8081,
+            attestation_nonce: b"challenge-nonce".to_vec(),
+            max_notification_recipients: 50,
+        }
+    }
+
+    #[test]
+    fn test_reinitialize_with_identical_config_is_ok() {
+        let applied = InitializeConfig::from(&initialize_request("api-key", true));
+        // Key generation eligibility changes between attempts and is not part of the config
+        let requested = InitializeConfig::from(&initialize_request("api-key", false));
+
+        assert!(check_reinitialize(Some(&applied), &requested).is_ok());
+    }
+
+    #[test]
+    fn test_reinitialize_with_conflicting_config_is_rejected() {
+        let applied = InitializeConfig::from(&initialize_request("api-key", true));
+        let requested = InitializeConfig::from(&initialize_request("other-api-key", true));
+
+        assert!(matches!(
+            check_reinitialize(Some(&applied), &requested),
+            Err(EnclaveError::AlreadyInitialized)
+        ));
+    }
+
+    #[test]
+    fn test_reinitialize_without_applied_config_is_rejected() {
+        let requested = InitializeConfig::from(&initialize_request("api-key", true));
+
+        assert!(matches!(
+            check_reinitialize(None, &requested),
+            Err(EnclaveError::AlreadyInitialized)
+        ));
+    }
+
+    #[test]
+    fn test_braze_api_url_for_known_region() {
+        assert_eq!(
+            braze_api_url("iad-05").unwrap(),
+            "https://rest.iad-05.braze.com"
+        );
+    }
+
+    #[test]
+    fn test_braze_api_url_for_eu_region() {
+        assert_eq!(
+            braze_api_url("fra-02").unwrap(),
+            "https://rest.fra-02.braze.eu"
+        );
+    }
+
+    #[test]
+    fn test_braze_api_url_rejects_malformed_region() {
+        for region in [
+            "",
+            "iad-5",
+            "IAD-05",
+            "iad-05.evil.com/",
+            " iad-05",
+            "iad-0a",
+            "ia-05",
+        ] {
+            assert!(
+                matches!(
+                    braze_api_url(region),
+                    Err(EnclaveError::InvalidBrazeRegion(ref r)) if r == region
+                ),
+                "region {region:?} should be rejected"
+            );
+        }
+    }
 }
@@ -0,0 +1,46 @@
+use std::sync::Arc;
+
+use crate::state::EnclaveState;
+use crypto_box::{
+    aead::{AeadCore, OsRng},
+    SalsaBox,
+};
+use enclave_types::{
+    EnclaveAttestationDocResponse, EnclaveError, EnclaveInitializeChallengeRequest,
+};
+use pontifex::SecureModule;
+use tokio::sync::RwLock;
+
+/// Issues a single-use nonce embedded in a fresh attestation document.
+///
+/// The caller echoes the nonce back in `EnclaveInitializeRequest`. Issuing a new challenge
+/// invalidates any previous one. The nonce only proves the request is fresh, any client on
+/// the parent instance can request a challenge.
+pub async fn handler(
+    state: Arc<RwLock<EnclaveState>>,
+    _: EnclaveInitializeChallengeRequest,
+) -> Result<EnclaveAttestationDocResponse, EnclaveError> {
+    let mut state = state.write().await;
+
+    // Before initialization only the ephemeral key pair exists, afterwards only the encryption keys
+    let public_key = state
+        .ephemeral_key_pair
+        .as_ref()
+        .or(state.encryption_keys.as_ref())
+        .ok_or(EnclaveError::MissingStateField("Key Pair".to_string()))?
+        .public_key
+        .to_bytes();
+    let nonce = SalsaBox::generate_nonce(&mut OsRng).to_vec();
+
+    let nsm = SecureModule::try_global().ok_or(EnclaveError::SecureModuleNotInitialized)?;
+    let attestation = nsm
+        .raw_attest(None::<Vec<u8>>, Some(nonce.clone()), Some(public_key))
+        .map_err(|e| {
+            tracing::error!("failed to attest: {e:?}");
+            EnclaveError::AttestationFailed()
+        })?;
+
+    state.pending_initialize_nonce = Some(nonce);
+
+    Ok(EnclaveAttestationDocResponse { attestation })
+}
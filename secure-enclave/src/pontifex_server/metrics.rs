@@ -0,0 +1,13 @@
+use std::sync::Arc;
+
+use crate::state::EnclaveState;
+use enclave_types::{EnclaveError, EnclaveMetricsRequest, EnclaveMetricsSnapshot};
+use tokio::sync::RwLock;
+
+/// Returns the metrics accumulated since the previous pull and resets them
+pub async fn handler(
+    state: Arc<RwLock<EnclaveState>>,
+    _: EnclaveMetricsRequest,
+) -> Result<EnclaveMetricsSnapshot, EnclaveError> {
+    Ok(state.read().await.metrics.take_snapshot())
+}
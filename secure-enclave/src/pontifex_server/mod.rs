@@ -2,8 +2,9 @@ use std::sync::Arc;
 
 use anyhow::Context;
 use enclave_types::{
-    EnclaveAttestationDocRequest, EnclaveHealthCheckRequest, EnclaveInitializeRequest,
-    EnclaveNotificationRequest, EnclavePushIdChallengeRequest, EnclaveSecretKeyRequest,
+    EnclaveAttestationDocRequest, EnclaveHealthCheckRequest, EnclaveInitializeChallengeRequest,
+    EnclaveInitializeRequest, EnclaveMetricsRequest, EnclaveNotificationRequest,
+    EnclavePushIdChallengeRequest, EnclaveSecretKeyAuditRequest, EnclaveSecretKeyRequest,
 };
 use pontifex::Router;
 use tokio::sync::RwLock;
@@ -11,9 +12,12 @@ use tokio::sync::RwLock;
 mod attestation_doc;
 mod health;
 mod initialize;
+mod initialize_challenge;
+mod metrics;
 mod notification;
 mod push_id_challenge;
 mod secret_key;
+mod secret_key_audit;
 
 use crate::state::EnclaveState;
 
@@ -23,12 +27,15 @@ pub async fn start_pontifex_server(
 ) -> anyhow::Result<()> {
     // Build pontifex router
     let router = Router::with_state(state)
+        .route::<EnclaveInitializeChallengeRequest, _, _>(initialize_challenge::handler)
         .route::<EnclaveInitializeRequest, _, _>(initialize::handler)
         .route::<EnclaveHealthCheckRequest, _, _>(health::handler)
         .route::<EnclaveAttestationDocRequest, _, _>(attestation_doc::handler)
         .route::<EnclavePushIdChallengeRequest, _, _>(push_id_challenge::handler)
         .route::<EnclaveNotificationRequest, _, _>(notification::handler)
-        .route::<EnclaveSecretKeyRequest, _, _>(secret_key::handler);
+        .route::<EnclaveSecretKeyRequest, _, _>(secret_key::handler)
+        .route::<EnclaveSecretKeyAuditRequest, _, _>(secret_key_audit::handler)
+        .route::<EnclaveMetricsRequest, _, _>(metrics::handler);
 
     // Start pontifex server
     router
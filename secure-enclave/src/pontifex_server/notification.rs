@@ -1,28 +1,92 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Instant};
 
 use crate::state::EnclaveState;
+use base64::{engine::general_purpose::STANDARD, Engine};
 use crypto_box::SecretKey;
-use enclave_types::{EnclaveError, EnclaveNotificationRequest};
+use enclave_types::{
+    EnclaveError, EnclaveNotificationRequest, EnclaveNotificationResponse, TraceContext,
+    ENCLAVE_METRIC_BRAZE_FAILURES, ENCLAVE_METRIC_BRAZE_LATENCY, ENCLAVE_METRIC_DECRYPT_FAILURES,
+};
 use hyper::{Body, Method, Request, Version};
+use opentelemetry::trace::{SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState};
 use pontifex::http::HttpClient;
 use serde::Serialize;
 use serde_json::json;
 use tokio::sync::RwLock;
+use tracing::{field, info_span, Instrument, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 
 pub async fn handler(
     state: Arc<RwLock<EnclaveState>>,
     request: EnclaveNotificationRequest,
-) -> Result<(), EnclaveError> {
+) -> Result<EnclaveNotificationResponse, EnclaveError> {
+    let span = request_span(&request);
+    handle(state, request).instrument(span).await
+}
+
+/// Span of a notification request, continuing the caller's trace if it sent one
+///
+/// The span is a child of the caller's span, and records the IDs of the trace and of itself for
+/// the logs, see `logging::trace_layer`.
+fn request_span(request: &EnclaveNotificationRequest) -> Span {
+    let trace_context = request.trace_context.as_ref();
+    let span = info_span!(
+        "enclave_notification",
+        trace_id = field::Empty,
+        span_id = field::Empty,
+        parent_span_id = trace_context.map(|context| context.parent_span_id.as_str()),
+        topic = %request.topic,
+    );
+
+    if let Some(parent) = trace_context.and_then(parent_context) {
+        span.set_parent(parent);
+    }
+    let context = span.context();
+    let otel_span = context.span();
+    let span_context = otel_span.span_context();
+    if span_context.is_valid() {
+        span.record("trace_id", field::display(span_context.trace_id()));
+        span.record("span_id", field::display(span_context.span_id()));
+    }
+
+    span
+}
+
+/// Remote parent context of the caller's span, `None` if its IDs are invalid
+fn parent_context(trace_context: &TraceContext) -> Option<opentelemetry::Context> {
+    let span_context = SpanContext::new(
+        TraceId::from_hex(&trace_context.trace_id).ok()?,
+        SpanId::from_hex(&trace_context.parent_span_id).ok()?,
+        TraceFlags::SAMPLED,
+        true,
+        TraceState::default(),
+    );
+
+    span_context
+        .is_valid()
+        .then(|| opentelemetry::Context::new().with_remote_span_context(span_context))
+}
+
+async fn handle(
+    state: Arc<RwLock<EnclaveState>>,
+    request: EnclaveNotificationRequest,
+) -> Result<EnclaveNotificationResponse, EnclaveError> {
     let state = state.read().await;
     if !state.initialized {
         return Err(EnclaveError::NotInitialized);
     }
 
-    let encryption_key = &state
-        .encryption_keys
-        .as_ref()
-        .ok_or(EnclaveError::NotInitialized)?
-        .private_key;
+    // Reject oversized requests before doing any decryption work
+    check_recipient_count(
+        request.subscribed_encrypted_push_ids.len(),
+        state.max_notification_recipients,
+    )?;
+    validate_encrypted_message(&request.encrypted_message_base64)?;
+
+    let decryption_keys = state.decryption_keys();
+    if decryption_keys.is_empty() {
+        return Err(EnclaveError::NotInitialized);
+    }
 
     let client = state
         .http_proxy_client
@@ -38,13 +102,24 @@ pub async fn handler(
         .ok_or(EnclaveError::MissingStateField("Http Client".to_string()))?;
     let braze_api_endpoint = format!("{braze_api_endpoint}/messages/send");
 
-    let user_aliases = request
-        .subscribed_encrypted_push_ids
-        .iter()
-        .map(|id| decrypt_push_id_and_create_alias(id.clone(), encryption_key))
-        .collect::<Result<Vec<UserAlias>, EnclaveError>>()?;
+    let (user_aliases, undecryptable_push_ids, invalid_push_ids) =
+        decrypt_recipients(request.subscribed_encrypted_push_ids, &decryption_keys);
+    if !undecryptable_push_ids.is_empty() {
+        state.metrics.increment(
+            ENCLAVE_METRIC_DECRYPT_FAILURES,
+            undecryptable_push_ids.len() as u64,
+        );
+    }
+    let response = EnclaveNotificationResponse {
+        undecryptable_push_ids,
+        invalid_push_ids,
+    };
+    if user_aliases.is_empty() {
+        return Ok(response);
+    }
 
-    send_braze_notification(
+    let started_at = Instant::now();
+    let result = send_braze_notification(
         client,
         braze_api_key,
         braze_api_endpoint,
@@ -52,24 +127,90 @@ pub async fn handler(
         user_aliases,
         request.encrypted_message_base64,
     )
-    .await?;
+    .await;
+    state
+        .metrics
+        .record_timing(ENCLAVE_METRIC_BRAZE_LATENCY, started_at.elapsed());
+    if result.is_err() {
+        state.metrics.increment(ENCLAVE_METRIC_BRAZE_FAILURES, 1);
+    }
+
+    result.map(|()| response)
+}
+
+/// Checks the number of recipients of a notification request is within `max`
+const fn check_recipient_count(count: usize, max: usize) -> Result<(), EnclaveError> {
+    if count > max {
+        return Err(EnclaveError::TooManyRecipients { count, max });
+    }
 
     Ok(())
 }
 
+/// Checks the encrypted message is valid, non-empty base64 before it is forwarded to Braze
+fn validate_encrypted_message(encrypted_message_base64: &str) -> Result<(), EnclaveError> {
+    let decoded = STANDARD
+        .decode(encrypted_message_base64)
+        .map_err(|e| EnclaveError::InvalidMessagePayload(format!("Base64 decode failed: {e}")))?;
+
+    if decoded.is_empty() {
+        return Err(EnclaveError::InvalidMessagePayload(
+            "Encrypted message is empty".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Decrypts the recipients' push IDs into Braze aliases
+///
+/// A push ID that can't be decrypted never will be, so it is returned apart instead of failing
+/// the other recipients, as are push IDs that aren't valid hex. Each push ID is tried with
+/// `decryption_keys` in order, so push IDs encrypted before a key rotation still decrypt. Returns
+/// the aliases, the undecryptable push IDs and the invalid ones.
+fn decrypt_recipients(
+    encrypted_push_ids: Vec<String>,
+    decryption_keys: &[&SecretKey],
+) -> (Vec<UserAlias>, Vec<String>, Vec<String>) {
+    let mut user_aliases = Vec::with_capacity(encrypted_push_ids.len());
+    let mut undecryptable_push_ids = Vec::new();
+    let mut invalid_push_ids = Vec::new();
+
+    for encrypted_push_id in encrypted_push_ids {
+        let Ok(sealed_push_id) = hex::decode(&encrypted_push_id) else {
+            tracing::warn!("Skipping recipient: push ID is not valid hex");
+            invalid_push_ids.push(encrypted_push_id);
+            continue;
+        };
+
+        match decrypt_push_id_and_create_alias(&sealed_push_id, decryption_keys) {
+            Ok(user_alias) => user_aliases.push(user_alias),
+            Err(e) => {
+                tracing::warn!("Skipping recipient: {e}");
+                undecryptable_push_ids.push(encrypted_push_id);
+            }
+        }
+    }
+
+    (user_aliases, undecryptable_push_ids, invalid_push_ids)
+}
+
 fn decrypt_push_id_and_create_alias(
-    encrypted_push_id: String,
-    encryption_key: &SecretKey,
+    encrypted_push_id: &[u8],
+    decryption_keys: &[&SecretKey],
 ) -> Result<UserAlias, EnclaveError> {
-    let encrypted_push_id = hex::decode(encrypted_push_id)
-        .map_err(|e| EnclaveError::BrazeRequestFailed(format!("Hex decode failed: {e:?}")))?;
-
-    let push_id = encryption_key
-        .unseal(&encrypted_push_id)
-        .map(hex::encode)
-        .map_err(|e| EnclaveError::BrazeRequestFailed(format!("Unseal failed: {e:?}")))?;
+    let mut last_error = None;
+    for decryption_key in decryption_keys {
+        match decryption_key.unseal(encrypted_push_id) {
+            Ok(push_id) => return Ok(UserAlias::push_id_alias(hex::encode(push_id))),
+            Err(e) => last_error = Some(e),
+        }
+    }
 
-    Ok(UserAlias::push_id_alias(push_id))
+    Err(EnclaveError::DecryptPushIdFailed(last_error.map_or_else(
+        || "No decryption key".to_string(),
+        |e| format!("Unseal failed: {e:?}"),
+    )))
 }
 
 #[derive(Serialize)]
@@ -144,3 +285,166 @@ async fn send_braze_notification(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crypto_box::aead::OsRng;
+    use std::{
+        io,
+        sync::{Arc, Mutex},
+    };
+    use tracing_subscriber::layer::SubscriberExt;
+
+    /// Writer capturing the logs emitted during a test
+    #[derive(Clone, Default)]
+    struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for CapturedLogs {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_handler_span_continues_caller_trace() {
+        let request = EnclaveNotificationRequest {
+            topic: "topic".to_string(),
+            subscribed_encrypted_push_ids: vec![],
+            encrypted_message_base64: String::new(),
+            trace_context: Some(TraceContext {
+                trace_id: "4bf92f3577b34da6a3ce929d0e0e4736".to_string(),
+                parent_span_id: "00f067aa0ba902b7".to_string(),
+            }),
+        };
+        let logs = CapturedLogs::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::registry()
+            .with(crate::logging::trace_layer())
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .json()
+                    .with_current_span(true)
+                    .with_writer(move || writer.clone()),
+            );
+
+        let span_id = tracing::subscriber::with_default(subscriber, || {
+            let span = request_span(&request);
+            let span_id = span.context().span().span_context().span_id();
+            let _entered = span.entered();
+            tracing::info!("handling notification");
+            span_id
+        });
+
+        let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        assert!(logs.contains(r#""trace_id":"4bf92f3577b34da6a3ce929d0e0e4736""#));
+        assert!(logs.contains(r#""parent_span_id":"00f067aa0ba902b7""#));
+        // The enclave's span is a new span of the caller's trace
+        assert_ne!(span_id, SpanId::from_hex("00f067aa0ba902b7").unwrap());
+        assert!(logs.contains(&format!(r#""span_id":"{span_id}""#)));
+    }
+
+    #[test]
+    fn test_invalid_trace_context_is_not_continued() {
+        for (trace_id, parent_span_id) in [
+            ("not-hex", "00f067aa0ba902b7"),
+            ("4bf92f3577b34da6a3ce929d0e0e4736", "0000000000000000"),
+        ] {
+            assert!(parent_context(&TraceContext {
+                trace_id: trace_id.to_string(),
+                parent_span_id: parent_span_id.to_string(),
+            })
+            .is_none());
+        }
+    }
+
+    #[test]
+    fn test_recipient_count_at_limit_is_accepted() {
+        assert!(check_recipient_count(50, 50).is_ok());
+        assert!(check_recipient_count(0, 50).is_ok());
+    }
+
+    #[test]
+    fn test_recipient_count_above_limit_is_rejected() {
+        assert!(matches!(
+            check_recipient_count(51, 50),
+            Err(EnclaveError::TooManyRecipients { count: 51, max: 50 })
+        ));
+    }
+
+    #[test]
+    fn test_valid_base64_message_is_accepted() {
+        let message = STANDARD.encode(b"encrypted message");
+
+        assert!(validate_encrypted_message(&message).is_ok());
+    }
+
+    #[test]
+    fn test_invalid_base64_message_is_rejected() {
+        for message in ["not base64!", "YWJj=", "YWJjZA"] {
+            assert!(
+                matches!(
+                    validate_encrypted_message(message),
+                    Err(EnclaveError::InvalidMessagePayload(_))
+                ),
+                "message {message:?} should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn test_empty_message_is_rejected() {
+        assert!(matches!(
+            validate_encrypted_message(""),
+            Err(EnclaveError::InvalidMessagePayload(_))
+        ));
+    }
+
+    #[test]
+    fn test_undecryptable_push_ids_are_skipped() {
+        let encryption_key = SecretKey::generate(&mut OsRng);
+        let rotated_key = SecretKey::generate(&mut OsRng);
+        let seal =
+            |key: &SecretKey| hex::encode(key.public_key().seal(&mut OsRng, b"push-id").unwrap());
+        let decryptable = seal(&encryption_key);
+        let sealed_with_rotated_key = seal(&rotated_key);
+
+        let (user_aliases, undecryptable_push_ids, invalid_push_ids) = decrypt_recipients(
+            vec![
+                decryptable,
+                sealed_with_rotated_key.clone(),
+                "not hex".to_string(),
+            ],
+            &[&encryption_key],
+        );
+
+        assert_eq!(user_aliases.len(), 1);
+        assert_eq!(user_aliases[0].alias_name, hex::encode(b"push-id"));
+        assert_eq!(undecryptable_push_ids, vec![sealed_with_rotated_key]);
+        assert_eq!(invalid_push_ids, vec!["not hex".to_string()]);
+    }
+
+    #[test]
+    fn test_push_ids_sealed_to_retired_key_are_decrypted() {
+        let active_key = SecretKey::generate(&mut OsRng);
+        let retired_key = SecretKey::generate(&mut OsRng);
+        let sealed_with_retired_key = hex::encode(
+            retired_key
+                .public_key()
+                .seal(&mut OsRng, b"push-id")
+                .unwrap(),
+        );
+
+        let (user_aliases, undecryptable_push_ids, _) =
+            decrypt_recipients(vec![sealed_with_retired_key], &[&active_key, &retired_key]);
+
+        assert_eq!(user_aliases.len(), 1);
+        assert_eq!(user_aliases[0].alias_name, hex::encode(b"push-id"));
+        assert!(undecryptable_push_ids.is_empty());
+    }
+}
@@ -5,32 +5,118 @@ use enclave_types::{EnclaveError, EnclaveSecretKeyRequest};
 use tokio::sync::RwLock;
 
 /// This pontifex route handles incoming requests for the secret key.
+/// The key versions are sent as an `EnclaveStateSnapshot` sealed to the requesting enclave.
 ///
 /// It uses the attestation verifier to verify the attestation document sent in the request,
 /// ensuring incoming attestation come from enclaves running the same bytecode.
+/// Every release is recorded in the secret key audit log.
 pub async fn handler(
     state: Arc<RwLock<EnclaveState>>,
     request: EnclaveSecretKeyRequest,
 ) -> Result<Vec<u8>, EnclaveError> {
-    let state = state.read().await;
-
-    let secret_key = state
-        .encryption_keys
-        .clone()
-        .ok_or(EnclaveError::NotInitialized)?
-        .private_key
-        .to_bytes();
-
-    let response = state
-        .attestation_verifier
-        .verify_attestation_document_and_encrypt(&request.attestation_doc, &secret_key)
-        .map_err(|e| {
-            EnclaveError::AttestationVerificationFailed(format!(
-                "Failed to verify attestation document: {}",
-                e
-            ))
-        })?;
+    let response = {
+        let state = state.read().await;
+        let snapshot = state.snapshot()?.to_bytes()?;
+
+        state
+            .attestation_verifier
+            .verify_attestation_document_and_encrypt(&request.attestation_doc, &snapshot)
+            .map_err(|e| {
+                EnclaveError::AttestationVerificationFailed(format!(
+                    "Failed to verify attestation document: {}",
+                    e
+                ))
+            })?
+    };
+
+    // The requester's PCRs come from its verified attestation document, not our own config
+    let verified = response.verified_attestation;
+    state
+        .write()
+        .await
+        .secret_key_audit_log
+        .record_release(&verified.attested_pcrs, &verified.module_id);
+
     let sealed_key = response.ciphertext;
 
     Ok(sealed_key)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        audit::SecretKeyAuditLog, encryption::KeyPair, metrics::EnclaveMetrics,
+        pontifex_server::secret_key_audit,
+    };
+    use attestation_verifier::{
+        test_utils::{SAMPLE_ATTESTATION_DOC, SAMPLE_MODULE_ID, SAMPLE_ROOT_CERT},
+        EnclaveAttestationVerifier,
+    };
+    use enclave_types::{EnclaveSecretKeyAuditRequest, DEFAULT_MAX_NOTIFICATION_RECIPIENTS};
+
+    /// Initialized enclave state trusting the sample root and enclaves with PCR0 filled with `pcr0`
+    fn initialized_state(pcr0: u8) -> Arc<RwLock<EnclaveState>> {
+        Arc::new(RwLock::new(EnclaveState {
+            braze_api_key: None,
+            braze_api_url: None,
+            max_notification_recipients: DEFAULT_MAX_NOTIFICATION_RECIPIENTS as usize,
+            http_proxy_client: None,
+            initialized: true,
+            initialize_config: None,
+            encryption_keys: Some(KeyPair::generate()),
+            retired_encryption_keys: Vec::new(),
+            ephemeral_key_pair: None,
+            attestation_doc_with_ephemeral_pk: Vec::new(),
+            pending_initialize_nonce: None,
+            attestation_verifier: EnclaveAttestationVerifier::with_root_certificate(
+                SAMPLE_ROOT_CERT.to_vec(),
+                vec![
+                    (0, vec![pcr0; 48]),
+                    (1, vec![0x02; 48]),
+                    (2, vec![0x03; 48]),
+                ],
+            ),
+            secret_key_audit_log: SecretKeyAuditLog::default(),
+            metrics: EnclaveMetrics::default(),
+        }))
+    }
+
+    fn secret_key_request() -> EnclaveSecretKeyRequest {
+        EnclaveSecretKeyRequest {
+            attestation_doc: SAMPLE_ATTESTATION_DOC.to_vec(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_key_release_is_audited() {
+        let state = initialized_state(0x01);
+
+        handler(state.clone(), secret_key_request()).await.unwrap();
+
+        let entries = secret_key_audit::handler(state, EnclaveSecretKeyAuditRequest)
+            .await
+            .unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].requester_pcr0, "01".repeat(48));
+        assert_eq!(entries[0].requester_module_id, SAMPLE_MODULE_ID);
+        assert!(entries[0].released_at_millis > 0);
+    }
+
+    #[tokio::test]
+    async fn test_refused_key_release_is_not_audited() {
+        // The sample attestation reports another PCR0
+        let state = initialized_state(0xff);
+
+        let result = handler(state.clone(), secret_key_request()).await;
+
+        assert!(matches!(
+            result,
+            Err(EnclaveError::AttestationVerificationFailed(_))
+        ));
+        let entries = secret_key_audit::handler(state, EnclaveSecretKeyAuditRequest)
+            .await
+            .unwrap();
+        assert!(entries.is_empty());
+    }
+}
@@ -0,0 +1,13 @@
+use std::sync::Arc;
+
+use crate::state::EnclaveState;
+use enclave_types::{EnclaveError, EnclaveSecretKeyAuditRequest, SecretKeyReleaseAuditEntry};
+use tokio::sync::RwLock;
+
+/// Returns the audit trail of secret key releases to other enclaves
+pub async fn handler(
+    state: Arc<RwLock<EnclaveState>>,
+    _: EnclaveSecretKeyAuditRequest,
+) -> Result<Vec<SecretKeyReleaseAuditEntry>, EnclaveError> {
+    Ok(state.read().await.secret_key_audit_log.entries())
+}
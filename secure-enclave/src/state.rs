@@ -1,28 +1,156 @@
 use anyhow::anyhow;
+use crypto_box::SecretKey;
 use pontifex::{http::HttpClient, SecureModule};
 
-use crate::encryption::KeyPair;
+use crate::{audit::SecretKeyAuditLog, encryption::KeyPair, metrics::EnclaveMetrics};
 
 use attestation_verifier::EnclaveAttestationVerifier;
+use enclave_types::{EnclaveError, EnclaveInitializeRequest, DEFAULT_MAX_NOTIFICATION_RECIPIENTS};
+use serde::{Deserialize, Serialize};
+
+/// Configuration applied by the initialize request.
+///
+/// Kept to tell an identical re-initialization (e.g. after a worker restart) from a conflicting one.
+/// Not `Debug`, so the Braze API key can't end up in logs.
+#[derive(Clone, PartialEq, Eq)]
+pub struct InitializeConfig {
+    pub braze_api_key: String,
+    pub braze_api_region: String,
+    pub braze_http_proxy_port: u32,
+    pub enclave_cluster_proxy_port: u32,
+    pub max_notification_recipients: u32,
+}
+
+impl From<&EnclaveInitializeRequest> for InitializeConfig {
+    fn from(request: &EnclaveInitializeRequest) -> Self {
+        // `can_generate_key_pair` and the nonce are per-attempt coordination, not configuration
+        Self {
+            braze_api_key: request.braze_api_key.clone(),
+            braze_api_region: request.braze_api_region.clone(),
+            braze_http_proxy_port: request.braze_http_proxy_port,
+            enclave_cluster_proxy_port: request.enclave_cluster_proxy_port,
+            max_notification_recipients: request.max_notification_recipients,
+        }
+    }
+}
+
+/// Current version of the encoded `EnclaveStateSnapshot`
+const SNAPSHOT_VERSION: u8 = 1;
+
+/// Key material handed off to a replacement enclave over the attested secret key channel.
+///
+/// Not `Debug`, so secret keys can't end up in logs.
+#[derive(Serialize, Deserialize)]
+pub struct EnclaveStateSnapshot {
+    /// Encoding version, bumped on incompatible changes
+    pub version: u8,
+    /// Secret keys ordered from oldest to newest, the newest is the active key
+    pub key_versions: Vec<Vec<u8>>,
+}
+
+impl EnclaveStateSnapshot {
+    /// Serializes the snapshot, to be sealed to the receiving enclave.
+    ///
+    /// A single key version is sent as the raw secret key, the format enclaves exchanged before
+    /// snapshots existed, so enclaves of both versions can hand off to each other during a rollout.
+    /// Anything else is MessagePack encoded, like the rest of the pontifex protocol.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, EnclaveError> {
+        if let [secret_key] = self.key_versions.as_slice() {
+            return Ok(secret_key.clone());
+        }
+
+        rmp_serde::to_vec_named(self)
+            .map_err(|e| EnclaveError::InvalidStateSnapshot(format!("Encoding failed: {e}")))
+    }
+
+    /// Deserializes a snapshot unsealed from another enclave
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, EnclaveError> {
+        if bytes.len() == crypto_box::KEY_SIZE {
+            return Ok(Self {
+                version: SNAPSHOT_VERSION,
+                key_versions: vec![bytes.to_vec()],
+            });
+        }
+
+        let snapshot: Self = rmp_serde::from_slice(bytes)
+            .map_err(|e| EnclaveError::InvalidStateSnapshot(format!("Decoding failed: {e}")))?;
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(EnclaveError::InvalidStateSnapshot(format!(
+                "Unsupported version {}",
+                snapshot.version
+            )));
+        }
+
+        Ok(snapshot)
+    }
+
+    /// Snapshot of the `active` key and the `retired` ones, ordered from oldest to newest
+    pub fn from_key_pairs(active: &KeyPair, retired: &[KeyPair]) -> Self {
+        Self {
+            version: SNAPSHOT_VERSION,
+            key_versions: retired
+                .iter()
+                .chain([active])
+                .map(|key_pair| key_pair.private_key.to_bytes().to_vec())
+                .collect(),
+        }
+    }
+
+    /// Rebuilds the active key pair and the retired ones, ordered from oldest to newest,
+    /// validating every key version first
+    pub fn key_pairs(&self) -> Result<(KeyPair, Vec<KeyPair>), EnclaveError> {
+        let mut key_pairs = self
+            .key_versions
+            .iter()
+            .map(|key| KeyPair::from_secret_key_bytes(key))
+            .collect::<Result<Vec<_>, _>>()?;
+        let active = key_pairs.pop().ok_or(EnclaveError::KeyPairCreationFailed)?;
+
+        Ok((active, key_pairs))
+    }
+
+    /// Rebuilds the active key pair, validating every key version first
+    pub fn active_key_pair(&self) -> Result<KeyPair, EnclaveError> {
+        self.key_pairs().map(|(active, _)| active)
+    }
+}
+
+impl From<&KeyPair> for EnclaveStateSnapshot {
+    fn from(key_pair: &KeyPair) -> Self {
+        Self::from_key_pairs(key_pair, &[])
+    }
+}
 
 pub struct EnclaveState {
     /// Braze API key
     pub braze_api_key: Option<String>,
     /// Braze API Url
     pub braze_api_url: Option<String>,
+    /// Maximum number of recipients accepted in a single notification request
+    pub max_notification_recipients: usize,
     /// HTTP client configured to use the HTTP proxy for Braze
     pub http_proxy_client: Option<HttpClient>,
     /// Whether the enclave has been initialized by creating a private key or receiving a key from another enclave
     pub initialized: bool,
+    /// Configuration the enclave was initialized with
+    pub initialize_config: Option<InitializeConfig>,
     /// Encryption key pair used for encrypting/decrypting push IDs
     pub encryption_keys: Option<KeyPair>,
+    /// Previous encryption key pairs, oldest first, to decrypt push IDs encrypted before a rotation
+    pub retired_encryption_keys: Vec<KeyPair>,
     /// Ephemeral key pair used for exchanging keys, destroyed after initialization
     pub ephemeral_key_pair: Option<KeyPair>,
     /// Attestation document generated with the enclave's ephemeral public key.
     pub attestation_doc_with_ephemeral_pk: Vec<u8>,
+    /// Nonce of the outstanding initialize challenge, consumed by the next initialize request
+    pub pending_initialize_nonce: Option<Vec<u8>>,
     /// Attestation verifier initialized with the enclave's attestation document.
     /// Used for verifying incoming attestation documents come from enclaves running the same bytecode.
     pub attestation_verifier: EnclaveAttestationVerifier,
+    /// Record of secret key releases to other enclaves
+    pub secret_key_audit_log: SecretKeyAuditLog,
+    /// Operational metrics, pulled and forwarded by the worker
+    pub metrics: EnclaveMetrics,
 }
 
 impl EnclaveState {
@@ -46,13 +174,161 @@ impl EnclaveState {
 
         Ok(Self {
             encryption_keys: None,
+            retired_encryption_keys: Vec::new(),
             braze_api_key: None,
             braze_api_url: None,
+            max_notification_recipients: DEFAULT_MAX_NOTIFICATION_RECIPIENTS as usize,
             http_proxy_client: None,
             initialized: false,
+            initialize_config: None,
             ephemeral_key_pair: Some(ephemeral_key_pair),
             attestation_doc_with_ephemeral_pk: raw_attestation_doc,
+            pending_initialize_nonce: None,
             attestation_verifier,
+            secret_key_audit_log: SecretKeyAuditLog::default(),
+            metrics: EnclaveMetrics::default(),
         })
     }
+
+    /// Produces the key versions to transfer to a replacement enclave
+    pub fn snapshot(&self) -> Result<EnclaveStateSnapshot, EnclaveError> {
+        self.encryption_keys
+            .as_ref()
+            .map(|active| {
+                EnclaveStateSnapshot::from_key_pairs(active, &self.retired_encryption_keys)
+            })
+            .ok_or(EnclaveError::NotInitialized)
+    }
+
+    /// Loads the key versions of a snapshot.
+    ///
+    /// All keys are validated before anything is applied,
+    /// so a bad snapshot leaves the state untouched.
+    pub fn restore(&mut self, snapshot: &EnclaveStateSnapshot) -> Result<(), EnclaveError> {
+        let (active, retired) = snapshot.key_pairs()?;
+        self.encryption_keys = Some(active);
+        self.retired_encryption_keys = retired;
+        Ok(())
+    }
+
+    /// Keys to decrypt push IDs with, the active key first and then the retired ones from newest
+    /// to oldest. Empty before initialization.
+    pub fn decryption_keys(&self) -> Vec<&SecretKey> {
+        self.encryption_keys
+            .iter()
+            .chain(self.retired_encryption_keys.iter().rev())
+            .map(|key_pair| &key_pair.private_key)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crypto_box::aead::OsRng;
+
+    #[test]
+    fn test_restored_snapshot_decrypts_source_ciphertexts() {
+        let source = KeyPair::generate();
+        let ciphertext = source.public_key.seal(&mut OsRng, b"push-id").unwrap();
+
+        let bytes = EnclaveStateSnapshot::from(&source).to_bytes().unwrap();
+        let restored = EnclaveStateSnapshot::from_bytes(&bytes)
+            .unwrap()
+            .active_key_pair()
+            .unwrap();
+
+        assert_eq!(restored.public_key, source.public_key);
+        assert_eq!(
+            restored.private_key.unseal(&ciphertext).unwrap(),
+            b"push-id"
+        );
+    }
+
+    #[test]
+    fn test_snapshot_with_invalid_key_is_rejected() {
+        let snapshot = EnclaveStateSnapshot {
+            version: SNAPSHOT_VERSION,
+            key_versions: vec![
+                KeyPair::generate().private_key.to_bytes().to_vec(),
+                vec![1, 2, 3],
+            ],
+        };
+
+        assert!(matches!(
+            snapshot.active_key_pair(),
+            Err(EnclaveError::KeyPairCreationFailed)
+        ));
+    }
+
+    #[test]
+    fn test_empty_snapshot_is_rejected() {
+        let snapshot = EnclaveStateSnapshot {
+            version: SNAPSHOT_VERSION,
+            key_versions: vec![],
+        };
+
+        assert!(snapshot.active_key_pair().is_err());
+    }
+
+    #[test]
+    fn test_single_key_snapshot_uses_the_raw_secret_key() {
+        let source = KeyPair::generate();
+
+        let bytes = EnclaveStateSnapshot::from(&source).to_bytes().unwrap();
+
+        assert_eq!(bytes, source.private_key.to_bytes());
+    }
+
+    #[test]
+    fn test_multi_key_snapshot_round_trips() {
+        let key_versions = vec![
+            KeyPair::generate().private_key.to_bytes().to_vec(),
+            KeyPair::generate().private_key.to_bytes().to_vec(),
+        ];
+        let snapshot = EnclaveStateSnapshot {
+            version: SNAPSHOT_VERSION,
+            key_versions: key_versions.clone(),
+        };
+
+        let decoded = EnclaveStateSnapshot::from_bytes(&snapshot.to_bytes().unwrap()).unwrap();
+
+        assert_eq!(decoded.key_versions, key_versions);
+    }
+
+    #[test]
+    fn test_multi_key_snapshot_restores_retired_keys() {
+        let retired = KeyPair::generate();
+        let active = KeyPair::generate();
+        let ciphertext = retired.public_key.seal(&mut OsRng, b"push-id").unwrap();
+
+        let bytes = EnclaveStateSnapshot::from_key_pairs(&active, &[retired.clone()])
+            .to_bytes()
+            .unwrap();
+        let (restored_active, restored_retired) = EnclaveStateSnapshot::from_bytes(&bytes)
+            .unwrap()
+            .key_pairs()
+            .unwrap();
+
+        assert_eq!(restored_active.public_key, active.public_key);
+        assert_eq!(restored_retired.len(), 1);
+        assert_eq!(restored_retired[0].public_key, retired.public_key);
+        assert_eq!(
+            restored_retired[0].private_key.unseal(&ciphertext).unwrap(),
+            b"push-id"
+        );
+    }
+
+    #[test]
+    fn test_snapshot_with_unknown_version_is_rejected() {
+        let snapshot = EnclaveStateSnapshot {
+            version: SNAPSHOT_VERSION + 1,
+            key_versions: vec![vec![1; 32], vec![2; 32]],
+        };
+
+        assert!(matches!(
+            EnclaveStateSnapshot::from_bytes(&snapshot.to_bytes().unwrap()),
+            Err(EnclaveError::InvalidStateSnapshot(_))
+        ));
+    }
 }
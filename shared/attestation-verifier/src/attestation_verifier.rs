@@ -1,21 +1,23 @@
+use std::collections::BTreeMap;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use aws_nitro_enclaves_nsm_api::api::AttestationDoc;
 use base64::engine::general_purpose::STANDARD;
 use base64::Engine;
 use coset::{AsCborValue, CborSerializable, CoseSign1};
-use crypto_box::{aead::OsRng, PublicKey};
+use crypto_box::{aead::OsRng, PublicKey, SecretKey};
 use p384::ecdsa::{signature::Verifier as _, Signature, VerifyingKey};
 use webpki::{EndEntityCert, TrustAnchor};
 use x509_cert::{der::Decode, Certificate};
 
 pub use crate::types::{
-    EnclaveAttestationError, EnclaveAttestationResult, VerifiedAttestation,
+    AttestationCertificate, EnclaveAttestationError, EnclaveAttestationResult, VerifiedAttestation,
     VerifiedAttestationWithCiphertext,
 };
 
 use crate::constants::{
-    get_expected_pcr_length, AWS_NITRO_ROOT_CERT, MAX_ATTESTATION_AGE_MILLISECONDS,
+    get_expected_pcr_length, AWS_NITRO_ROOT_CERT, INSECURE_DEV_ENCLAVE_SECRET_KEY,
+    INSECURE_DEV_MODULE_ID, MAX_ATTESTATION_AGE_MILLISECONDS, SKIP_ATTESTATION,
 };
 
 /// Verifies AWS Nitro Enclave attestation documents
@@ -57,6 +59,23 @@ impl EnclaveAttestationVerifier {
         }
     }
 
+    /// Creates a verifier trusting `root_certificate` instead of the AWS Nitro root and accepting
+    /// documents of any age, to verify `test_utils::SAMPLE_ATTESTATION_DOC`
+    #[cfg(any(test, feature = "test-utils"))]
+    #[must_use]
+    pub const fn with_root_certificate(
+        root_certificate: Vec<u8>,
+        allowed_pcr_measurements: Vec<(usize, Vec<u8>)>,
+    ) -> Self {
+        Self {
+            root_certificate,
+            max_age_millis: u64::MAX,
+            #[cfg(test)]
+            skip_certificate_time_check: false,
+            allowed_pcr_measurements,
+        }
+    }
+
     /// Create a new instance from an attestation document using its PCR values as the allowed measurements (PCR0, PCR1, PCR2)
     ///
     /// This ensures that only attestation documents from enclaves running the same bytecode will be accepted.
@@ -130,6 +149,10 @@ impl EnclaveAttestationVerifier {
         &self,
         attestation_doc_bytes: &[u8],
     ) -> EnclaveAttestationResult<()> {
+        if SKIP_ATTESTATION {
+            return Ok(());
+        }
+
         // 1. Syntactical validation
         let cose_sign1 = Self::parse_cose_sign1(attestation_doc_bytes)?;
         let attestation = Self::parse_cbor_payload(&cose_sign1)?;
@@ -144,17 +167,23 @@ impl EnclaveAttestationVerifier {
 
         Ok(())
     }
-}
 
-impl EnclaveAttestationVerifier {
     /// Verifies the attestation document from the enclave.
     ///
     /// Follows the AWS Nitro Enclave Attestation Document Specification:
     /// <https://docs.aws.amazon.com/enclaves/latest/user/nitro-enclave-attestation-document.html>
-    fn verify_attestation_document(
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the attestation document verification fails or it has no public key.
+    pub fn verify_attestation_document(
         &self,
         attestation_doc_bytes: &[u8],
     ) -> EnclaveAttestationResult<VerifiedAttestation> {
+        if SKIP_ATTESTATION {
+            return Ok(self.insecure_dev_attestation());
+        }
+
         // 1. Syntactical validation
         let cose_sign1 = Self::parse_cose_sign1(attestation_doc_bytes)?;
         let attestation = Self::parse_cbor_payload(&cose_sign1)?;
@@ -167,14 +196,21 @@ impl EnclaveAttestationVerifier {
         self.check_attestation_freshness(&attestation)?;
         self.validate_pcr_values(&attestation)?;
         let public_key = Self::extract_public_key(&attestation)?;
+        let certificate = Self::certificate_details(&leaf_cert, &attestation.cabundle)?;
+        let attested_pcrs = Self::attested_pcrs(&attestation);
 
         Ok(VerifiedAttestation::new(
             STANDARD.encode(public_key),
             attestation.timestamp,
             attestation.module_id,
+            attestation.nonce.map(|nonce| STANDARD.encode(nonce)),
+            attested_pcrs,
+            certificate,
         ))
     }
+}
 
+impl EnclaveAttestationVerifier {
     fn parse_cose_sign1(bytes: &[u8]) -> EnclaveAttestationResult<CoseSign1> {
         // Validate before loading into buffer
         if bytes.is_empty() {
@@ -412,6 +448,66 @@ impl EnclaveAttestationVerifier {
         Ok(())
     }
 
+    /// PCR values reported by the attestation document itself, hex encoded by index
+    fn attested_pcrs(attestation: &AttestationDoc) -> BTreeMap<usize, String> {
+        attestation
+            .pcrs
+            .iter()
+            .map(|(pcr_index, pcr_value)| (*pcr_index, hex::encode(pcr_value)))
+            .collect()
+    }
+
+    /// Collects the validity window and subjects of an already verified certificate chain
+    fn certificate_details<C: AsRef<[u8]>>(
+        leaf_cert: &Certificate,
+        cabundle: &[C],
+    ) -> EnclaveAttestationResult<AttestationCertificate> {
+        let ca_chain_subjects = cabundle
+            .iter()
+            .map(|cert| {
+                Certificate::from_der(cert.as_ref())
+                    .map(|cert| cert.tbs_certificate.subject.to_string())
+                    .map_err(|e| {
+                        EnclaveAttestationError::AttestationChainInvalid(format!(
+                            "Failed to parse CA bundle certificate: {e}"
+                        ))
+                    })
+            })
+            .collect::<EnclaveAttestationResult<Vec<_>>>()?;
+
+        let validity = &leaf_cert.tbs_certificate.validity;
+        Ok(AttestationCertificate {
+            subject: leaf_cert.tbs_certificate.subject.to_string(),
+            not_before: validity.not_before.to_unix_duration().as_secs(),
+            not_after: validity.not_after.to_unix_duration().as_secs(),
+            ca_chain_subjects,
+        })
+    }
+
+    /// Canned attestation returned when verification is skipped for local development
+    ///
+    /// Its enclave public key matches `INSECURE_DEV_ENCLAVE_SECRET_KEY`.
+    fn insecure_dev_attestation(&self) -> VerifiedAttestation {
+        let public_key = SecretKey::from(INSECURE_DEV_ENCLAVE_SECRET_KEY).public_key();
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| u64::try_from(now.as_millis()).unwrap_or(u64::MAX));
+
+        VerifiedAttestation::new(
+            STANDARD.encode(public_key.as_bytes()),
+            timestamp,
+            INSECURE_DEV_MODULE_ID.to_string(),
+            None,
+            BTreeMap::new(),
+            AttestationCertificate {
+                subject: format!("CN={INSECURE_DEV_MODULE_ID}"),
+                not_before: 0,
+                not_after: 0,
+                ca_chain_subjects: vec![],
+            },
+        )
+    }
+
     fn check_attestation_freshness(
         &self,
         attestation: &AttestationDoc,
@@ -475,3 +571,170 @@ impl EnclaveAttestationVerifier {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{SAMPLE_ATTESTATION_DOC, SAMPLE_MODULE_ID, SAMPLE_ROOT_CERT};
+
+    const AWS_NITRO_ROOT_SUBJECT: &str = "CN=aws.nitro-enclaves,OU=AWS,O=Amazon,C=US";
+
+    fn sample_verifier(
+        allowed_pcr_measurements: Vec<(usize, Vec<u8>)>,
+    ) -> EnclaveAttestationVerifier {
+        EnclaveAttestationVerifier {
+            skip_certificate_time_check: true,
+            ..EnclaveAttestationVerifier::with_root_certificate(
+                SAMPLE_ROOT_CERT.to_vec(),
+                allowed_pcr_measurements,
+            )
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "insecure-skip-attestation"))]
+    fn test_verifies_sample_attestation_document() {
+        let verifier = sample_verifier(vec![
+            (0, vec![0x01; 48]),
+            (1, vec![0x02; 48]),
+            (2, vec![0x03; 48]),
+        ]);
+
+        let verified = verifier
+            .verify_attestation_document(SAMPLE_ATTESTATION_DOC)
+            .unwrap();
+
+        assert_eq!(verified.module_id, SAMPLE_MODULE_ID);
+        // 2025-10-09T08:53:20Z
+        assert_eq!(verified.timestamp, 1_760_000_000_000);
+        assert_eq!(verified.nonce, Some(STANDARD.encode("sample-nonce")));
+        assert_eq!(
+            verified.enclave_public_key,
+            STANDARD.encode(SecretKey::from([7; 32]).public_key().as_bytes())
+        );
+        assert_eq!(
+            verified.attested_pcrs,
+            BTreeMap::from([
+                (0, "01".repeat(48)),
+                (1, "02".repeat(48)),
+                (2, "03".repeat(48)),
+            ])
+        );
+        assert_eq!(
+            verified.certificate,
+            AttestationCertificate {
+                subject: format!("CN={SAMPLE_MODULE_ID}"),
+                // 2025-01-01T00:00:00Z
+                not_before: 1_735_689_600,
+                // 2035-01-01T00:00:00Z
+                not_after: 2_051_222_400,
+                ca_chain_subjects: vec!["CN=sample.nitro-enclaves".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "insecure-skip-attestation"))]
+    fn test_sample_attestation_document_with_untrusted_pcr() {
+        let verifier = sample_verifier(vec![(0, vec![0xff; 48])]);
+
+        let result = verifier.verify_attestation_document(SAMPLE_ATTESTATION_DOC);
+
+        assert!(matches!(
+            result,
+            Err(EnclaveAttestationError::CodeUntrusted { pcr_index: 0, .. })
+        ));
+    }
+
+    #[test]
+    #[cfg(not(feature = "insecure-skip-attestation"))]
+    fn test_sample_attestation_document_is_not_trusted_by_the_nitro_root() {
+        let verifier = EnclaveAttestationVerifier::new(vec![]);
+
+        let result = verifier.verify_attestation_document(SAMPLE_ATTESTATION_DOC);
+
+        assert!(matches!(
+            result,
+            Err(EnclaveAttestationError::AttestationChainInvalid(_))
+        ));
+    }
+
+    #[test]
+    fn test_certificate_details_from_certificate_chain() {
+        let root_cert = Certificate::from_der(AWS_NITRO_ROOT_CERT).unwrap();
+
+        let certificate =
+            EnclaveAttestationVerifier::certificate_details(&root_cert, &[AWS_NITRO_ROOT_CERT])
+                .unwrap();
+
+        assert_eq!(
+            certificate,
+            AttestationCertificate {
+                subject: AWS_NITRO_ROOT_SUBJECT.to_string(),
+                // 2019-10-28T13:28:05Z
+                not_before: 1_572_269_285,
+                // 2049-10-28T14:28:05Z
+                not_after: 2_519_044_085,
+                ca_chain_subjects: vec![AWS_NITRO_ROOT_SUBJECT.to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn test_certificate_details_rejects_invalid_ca_bundle() {
+        let root_cert = Certificate::from_der(AWS_NITRO_ROOT_CERT).unwrap();
+
+        let result = EnclaveAttestationVerifier::certificate_details(&root_cert, &[b"not a cert"]);
+
+        assert!(matches!(
+            result,
+            Err(EnclaveAttestationError::AttestationChainInvalid(_))
+        ));
+    }
+
+    #[test]
+    fn test_attestation_skipped_only_with_dev_feature() {
+        let verifier = EnclaveAttestationVerifier::new(vec![]);
+
+        let result = verifier.verify_attestation_document(b"not an attestation document");
+
+        if cfg!(feature = "insecure-skip-attestation") {
+            let verified = result.unwrap();
+            assert_eq!(verified.module_id, INSECURE_DEV_MODULE_ID);
+            assert_eq!(
+                verified.enclave_public_key,
+                STANDARD.encode(
+                    SecretKey::from(INSECURE_DEV_ENCLAVE_SECRET_KEY)
+                        .public_key()
+                        .as_bytes()
+                )
+            );
+        } else {
+            assert!(matches!(
+                result,
+                Err(EnclaveAttestationError::AttestationDocumentParseError(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_attested_pcrs_come_from_the_document() {
+        let attestation = AttestationDoc::new(
+            "i-0123-enc0123".to_string(),
+            aws_nitro_enclaves_nsm_api::api::Digest::SHA384,
+            0,
+            BTreeMap::from([(0, vec![0xab; 48]), (2, vec![0xcd; 48])]),
+            vec![],
+            vec![],
+            None,
+            None,
+            None,
+        );
+
+        let attested_pcrs = EnclaveAttestationVerifier::attested_pcrs(&attestation);
+
+        assert_eq!(attested_pcrs.len(), 2);
+        assert_eq!(attested_pcrs[&0], "ab".repeat(48));
+        assert_eq!(attested_pcrs[&2], "cd".repeat(48));
+    }
+}
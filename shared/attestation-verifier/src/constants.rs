@@ -9,6 +9,16 @@ pub const AWS_NITRO_ROOT_CERT_PROD: &[u8] = include_bytes!("aws_nitro_root_g1.de
 /// Source: <https://aws-nitro-enclaves.amazonaws.com/AWS_NitroEnclaves_Root-G1.zip>
 pub const AWS_NITRO_ROOT_CERT: &[u8] = AWS_NITRO_ROOT_CERT_PROD;
 
+/// Whether attestation verification is skipped, see the `insecure-skip-attestation` feature
+pub const SKIP_ATTESTATION: bool = cfg!(feature = "insecure-skip-attestation");
+
+/// Module ID of the canned attestation returned when verification is skipped
+pub const INSECURE_DEV_MODULE_ID: &str = "insecure-dev-attestation";
+
+/// Secret key matching the enclave public key of the canned attestation.
+/// Publicly known, only meant for local development.
+pub const INSECURE_DEV_ENCLAVE_SECRET_KEY: [u8; 32] = [0x42; 32];
+
 /// Maximum age for attestation documents (in milliseconds)
 pub const MAX_ATTESTATION_AGE_MILLISECONDS: u64 = 3 * 60 * 60 * 1000; // 3 hours
 
@@ -1,7 +1,12 @@
 #![deny(clippy::all, clippy::pedantic, clippy::nursery, dead_code)]
 
+#[cfg(all(feature = "insecure-skip-attestation", not(debug_assertions)))]
+compile_error!("The `insecure-skip-attestation` feature must not be enabled in release builds");
+
 pub mod attestation_verifier;
 pub mod constants;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
 pub mod types;
 
 pub use attestation_verifier::EnclaveAttestationVerifier;
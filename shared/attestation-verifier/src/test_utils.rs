@@ -0,0 +1,13 @@
+//! Sample attestation document, to test code verifying attestations without a Nitro enclave
+
+/// Attestation document signed by a throwaway P-384 CA standing in for the AWS Nitro root
+///
+/// Laid out like a Nitro document: an untagged ES384 COSE Sign1 whose payload reports PCR0,
+/// PCR1 and PCR2 filled with `0x01`, `0x02` and `0x03`, the nonce `sample-nonce` and the
+/// public key of the X25519 secret key `[7; 32]`. Its certificates are valid from 2025 to 2035.
+pub const SAMPLE_ATTESTATION_DOC: &[u8] =
+    include_bytes!("../tests/fixtures/sample_attestation_doc.cbor");
+/// Root certificate of the CA that signed `SAMPLE_ATTESTATION_DOC`
+pub const SAMPLE_ROOT_CERT: &[u8] = include_bytes!("../tests/fixtures/sample_root.der");
+/// Module ID of the enclave that produced `SAMPLE_ATTESTATION_DOC`
+pub const SAMPLE_MODULE_ID: &str = "i-0123456789abcdef0-enc0123456789abcdef";
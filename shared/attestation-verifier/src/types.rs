@@ -3,6 +3,8 @@
 //! This module contains the core types used for AWS Nitro Enclave attestation
 //! document parsing, verification, and PCR configuration management.
 
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
@@ -55,6 +57,19 @@ pub enum EnclaveAttestationError {
 /// Result type for enclave attestation operations
 pub type EnclaveAttestationResult<T, E = EnclaveAttestationError> = Result<T, E>;
 
+/// Details of the certificate chain an attestation was verified against
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AttestationCertificate {
+    /// Subject of the leaf certificate that signed the attestation
+    pub subject: String,
+    /// Start of the leaf certificate validity window, in seconds since the Unix epoch
+    pub not_before: u64,
+    /// End of the leaf certificate validity window, in seconds since the Unix epoch
+    pub not_after: u64,
+    /// Subjects of the CA bundle certificates, from the root to the closest intermediate
+    pub ca_chain_subjects: Vec<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 /// Verified attestation data from the enclave.
 pub struct VerifiedAttestation {
@@ -65,6 +80,14 @@ pub struct VerifiedAttestation {
     pub timestamp: u64,
     /// The module ID of the enclave
     pub module_id: String,
+    /// The base64 encoded nonce included in the attestation, if any
+    pub nonce: Option<String>,
+    /// The hex encoded PCR values reported by the attestation document, by index
+    ///
+    /// Empty when attestation verification is skipped for local development.
+    pub attested_pcrs: BTreeMap<usize, String>,
+    /// The certificate chain the attestation was verified against
+    pub certificate: AttestationCertificate,
 }
 
 impl VerifiedAttestation {
@@ -74,12 +97,26 @@ impl VerifiedAttestation {
     /// * `enclave_public_key` - The hex encoded public key of the enclave
     /// * `pcr_values` - The PCR values of the enclave
     /// * `timestamp` - The timestamp of the attestation
+    /// * `module_id` - The module ID of the enclave
+    /// * `nonce` - The base64 encoded nonce included in the attestation, if any
+    /// * `attested_pcrs` - The hex encoded PCR values reported by the document, by index
+    /// * `certificate` - The certificate chain the attestation was verified against
     #[must_use]
-    pub const fn new(enclave_public_key: String, timestamp: u64, module_id: String) -> Self {
+    pub const fn new(
+        enclave_public_key: String,
+        timestamp: u64,
+        module_id: String,
+        nonce: Option<String>,
+        attested_pcrs: BTreeMap<usize, String>,
+        certificate: AttestationCertificate,
+    ) -> Self {
         Self {
             enclave_public_key,
             timestamp,
             module_id,
+            nonce,
+            attested_pcrs,
+            certificate,
         }
     }
 }
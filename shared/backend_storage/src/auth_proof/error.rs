@@ -3,7 +3,8 @@
 use aws_sdk_dynamodb::error::SdkError;
 use aws_sdk_dynamodb::operation::update_item::UpdateItemError;
 use aws_sdk_dynamodb::operation::{
-    delete_item::DeleteItemError, get_item::GetItemError, put_item::PutItemError, query::QueryError,
+    batch_get_item::BatchGetItemError, delete_item::DeleteItemError, get_item::GetItemError,
+    put_item::PutItemError, query::QueryError,
 };
 use thiserror::Error;
 
@@ -25,6 +26,14 @@ pub enum AuthProofStorageError {
     #[error("Failed to get auth proof from DynamoDB: {0}")]
     DynamoDbGetError(#[from] SdkError<GetItemError>),
 
+    /// Failed to batch get auth proofs from Dynamo DB
+    #[error("Failed to batch get auth proofs from DynamoDB: {0}")]
+    DynamoDbBatchGetError(#[from] SdkError<BatchGetItemError>),
+
+    /// Dynamo DB left nullifiers unprocessed after every retry
+    #[error("{0} nullifiers still unprocessed after retries")]
+    UnprocessedNullifiers(usize),
+
     /// Failed to query auth proofs from Dynamo DB
     #[error("Failed to query auth proofs from DynamoDB: {0}")]
     DynamoDbQueryError(#[from] SdkError<QueryError>),
@@ -37,6 +46,10 @@ pub enum AuthProofStorageError {
     #[error("Auth proof already exists")]
     AuthProofExists,
 
+    /// The push id was rotated within the cooldown period
+    #[error("Push id rotation cooldown is active")]
+    RotationCooldownActive,
+
     /// Serialization error for `serde_dynamo`
     #[error("Serialization error: {0}")]
     SerializationError(String),
@@ -4,22 +4,44 @@
 
 mod error;
 
-use std::sync::Arc;
-
-use aws_sdk_dynamodb::{error::SdkError, types::AttributeValue, Client as DynamoDbClient};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use aws_sdk_dynamodb::{
+    operation::update_item::builders::UpdateItemFluentBuilder, types::AttributeValue,
+    Client as DynamoDbClient,
+};
 use chrono::Utc;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 
 pub use error::{AuthProofStorageError, AuthProofStorageResult};
-use strum::Display;
+use strum::{Display, EnumIter};
+
+use crate::{
+    dynamodb::{batch_get_all, is_conditional_check_failed},
+    ids::{EncryptedPushId, Nullifier},
+};
 
 /// TTL boundaries for random selection (in seconds)
 const TTL_MIN_SECONDS: i64 = 6 * 30 * 24 * 60 * 60; // 6 months in seconds
 const TTL_MAX_SECONDS: i64 = 8 * 30 * 24 * 60 * 60; // 8 months in seconds
 
+/// Maximum number of keys of a `batch_get_item` call
+const BATCH_GET_MAX_KEYS: usize = 100;
+
+/// Source of the current Unix timestamp in seconds
+pub type Clock = fn() -> i64;
+
+/// Current Unix timestamp in seconds, the default `Clock`
+fn system_clock() -> i64 {
+    Utc::now().timestamp()
+}
+
 /// Attribute names for auth proof table
-#[derive(Debug, Clone, Display)]
+#[derive(Debug, Clone, Display, EnumIter)]
 #[strum(serialize_all = "snake_case")]
 pub enum AuthProofAttribute {
     /// Nullifier (Primary Key)
@@ -37,14 +59,18 @@ pub enum AuthProofAttribute {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AuthProof {
     /// World ID Nullifier (Primary Key)
+    #[serde(rename = "nullifier")]
     pub nullifier: String,
     /// Encrypted Push notification ID
     /// It's used to identify the user and send notifications, see Push Subscription storage for more details.
     /// It's encrypted with the enclave's public key with an added nonce, only the enclave can decrypt it.
+    #[serde(rename = "encrypted_push_id")]
     pub encrypted_push_id: String,
     /// Push ID Rotated At - timestamp when push ID was last changed (rounded to nearest day)
+    #[serde(rename = "push_id_rotated_at")]
     pub push_id_rotated_at: i64,
     /// TTL timestamp
+    #[serde(rename = "ttl")]
     pub ttl: i64,
 }
 
@@ -66,12 +92,14 @@ pub struct AuthProofInsertRequest {
 /// There is a 6-8 month randomly picked TTL to avoid keeping user's data forever.
 /// The TTL is refreshed every time the user issues a new JWT using the `ping_auth_proof` method.
 ///
-/// The `push_id_rotated_at` is used to track when the push ID was last changed. In the app layer we will handle cooldown period to avoid
+/// The `push_id_rotated_at` is used to track when the push ID was last changed. Rotations within a
+/// cooldown period are rejected by `update_encrypted_push_id_with_cooldown` to avoid
 /// impersonation attacks.
 #[derive(Clone)]
 pub struct AuthProofStorage {
     dynamodb_client: Arc<DynamoDbClient>,
     table_name: String,
+    clock: Clock,
 }
 
 impl AuthProofStorage {
@@ -86,9 +114,23 @@ impl AuthProofStorage {
         Self {
             dynamodb_client,
             table_name,
+            clock: system_clock,
         }
     }
 
+    /// Reads the current time from `clock` instead of the system clock
+    ///
+    /// Pins the rotation timestamps and TTL windows, e.g. in tests.
+    ///
+    /// # Arguments
+    ///
+    /// * `clock` - Returns the current Unix timestamp in seconds
+    #[must_use]
+    pub const fn with_clock(mut self, clock: Clock) -> Self {
+        self.clock = clock;
+        self
+    }
+
     /// Rounds a timestamp to the nearest day (midnight UTC)
     ///
     /// This improves privacy by not storing exact activity times.
@@ -123,9 +165,12 @@ impl AuthProofStorage {
     ///
     /// This period is chosen to proactively delete stale user data. If a user hasn't used chat from World App
     /// in this period, we consider their data stale and delete it. Once the user log ins again, they will create a new auth proof row.
-    fn generate_ttl() -> i64 {
-        let now = Utc::now().timestamp();
-        let mut rng = rand::thread_rng();
+    fn generate_ttl(&self) -> i64 {
+        Self::generate_ttl_at((self.clock)(), &mut rand::thread_rng())
+    }
+
+    /// Generates a random TTL between 6-8 months from `now`, drawn from `rng`
+    fn generate_ttl_at(now: i64, rng: &mut impl Rng) -> i64 {
         let ttl_seconds = rng.gen_range(TTL_MIN_SECONDS..=TTL_MAX_SECONDS);
         now + ttl_seconds
     }
@@ -143,9 +188,8 @@ impl AuthProofStorage {
         &self,
         auth_proof_request: AuthProofInsertRequest,
     ) -> AuthProofStorageResult<AuthProof> {
-        let now = Utc::now().timestamp();
-        let rounded_now = Self::round_to_nearest_day(now);
-        let ttl = Self::generate_ttl();
+        let rounded_now = Self::round_to_nearest_day((self.clock)());
+        let ttl = self.generate_ttl();
 
         let auth_proof = AuthProof {
             nullifier: auth_proof_request.nullifier.clone(),
@@ -167,10 +211,7 @@ impl AuthProofStorage {
             .send()
             .await
             .map_err(|err| {
-                if matches!(
-                    err,
-                    SdkError::ServiceError(ref svc) if svc.err().is_conditional_check_failed_exception()
-                ) {
+                if is_conditional_check_failed(&err) {
                     AuthProofStorageError::AuthProofExists
                 } else {
                     err.into()
@@ -199,12 +240,69 @@ impl AuthProofStorage {
     /// Returns `AuthProofStorageError` if the Dynamo DB operation fails
     pub async fn update_encrypted_push_id(
         &self,
-        nullifier: &str,
-        encrypted_push_id: &str,
+        nullifier: &Nullifier,
+        encrypted_push_id: &EncryptedPushId,
+    ) -> AuthProofStorageResult<()> {
+        self.rotate_push_id(nullifier, encrypted_push_id)
+            .send()
+            .await?;
+
+        Ok(())
+    }
+
+    /// Updates the encrypted push id like `update_encrypted_push_id`, unless it was rotated
+    /// within the cooldown
+    ///
+    /// The cooldown is checked atomically with the update, so concurrent rotations can't both
+    /// pass it. It's measured from the stored `push_id_rotated_at`, which is rounded to the
+    /// nearest day. An auth proof that doesn't exist is created, like on
+    /// `update_encrypted_push_id`.
+    ///
+    /// # Arguments
+    ///
+    /// * `nullifier` - The nullifier of the auth proof to update
+    /// * `encrypted_push_id` - The new encrypted push id
+    /// * `cooldown_secs` - Minimum time between two rotations, in seconds
+    ///
+    /// # Errors
+    ///
+    /// Returns `AuthProofStorageError::RotationCooldownActive` if the push id was rotated less
+    /// than `cooldown_secs` ago, or other `AuthProofStorageError` if the Dynamo DB operation fails
+    pub async fn update_encrypted_push_id_with_cooldown(
+        &self,
+        nullifier: &Nullifier,
+        encrypted_push_id: &EncryptedPushId,
+        cooldown_secs: i64,
     ) -> AuthProofStorageResult<()> {
-        let now = Utc::now().timestamp();
-        let rounded_now = Self::round_to_nearest_day(now);
-        let ttl = Self::generate_ttl();
+        let cutoff = (self.clock)().saturating_sub(cooldown_secs);
+
+        self.rotate_push_id(nullifier, encrypted_push_id)
+            .condition_expression(
+                "attribute_not_exists(#nullifier) OR #push_id_rotated_at <= :cutoff",
+            )
+            .expression_attribute_names("#nullifier", AuthProofAttribute::Nullifier.to_string())
+            .expression_attribute_values(":cutoff", AttributeValue::N(cutoff.to_string()))
+            .send()
+            .await
+            .map_err(|err| {
+                if is_conditional_check_failed(&err) {
+                    AuthProofStorageError::RotationCooldownActive
+                } else {
+                    err.into()
+                }
+            })?;
+
+        Ok(())
+    }
+
+    /// Builds the update setting a new encrypted push id, rotation timestamp and TTL
+    fn rotate_push_id(
+        &self,
+        nullifier: &Nullifier,
+        encrypted_push_id: &EncryptedPushId,
+    ) -> UpdateItemFluentBuilder {
+        let rounded_now = Self::round_to_nearest_day((self.clock)());
+        let ttl = self.generate_ttl();
 
         self.dynamodb_client
             .update_item()
@@ -225,10 +323,6 @@ impl AuthProofStorage {
             .expression_attribute_values(":push_id_rotated_at", AttributeValue::N(rounded_now.to_string()))
             .expression_attribute_names("#ttl", AuthProofAttribute::Ttl.to_string())
             .expression_attribute_values(":ttl", AttributeValue::N(ttl.to_string()))
-            .send()
-            .await?;
-
-        Ok(())
     }
 
     /// Gets a auth proof by nullifier
@@ -242,7 +336,7 @@ impl AuthProofStorage {
     /// Returns `AuthProofStorageError` if the Dynamo DB operation fails    
     pub async fn get_by_nullifier(
         &self,
-        nullifier: &str,
+        nullifier: &Nullifier,
     ) -> AuthProofStorageResult<Option<AuthProof>> {
         let response = self
             .dynamodb_client
@@ -264,6 +358,67 @@ impl AuthProofStorage {
         Ok(item)
     }
 
+    /// Gets the auth proofs of multiple nullifiers with `batch_get_item`, 100 per request
+    ///
+    /// Nullifiers left unprocessed by Dynamo DB are retried with exponential backoff. Duplicate
+    /// nullifiers are fetched once.
+    ///
+    /// # Arguments
+    ///
+    /// * `nullifiers` - The nullifiers of the auth proofs to get
+    ///
+    /// # Returns
+    ///
+    /// The auth proofs found, in no particular order. Nullifiers without an auth proof are
+    /// absent.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AuthProofStorageError::UnprocessedNullifiers` if nullifiers are still
+    /// unprocessed after the last attempt, or other `AuthProofStorageError` if the Dynamo DB
+    /// operation fails
+    pub async fn batch_get_by_nullifiers(
+        &self,
+        nullifiers: &[Nullifier],
+    ) -> AuthProofStorageResult<Vec<AuthProof>> {
+        // Dynamo DB rejects a batch getting the same key twice
+        let unique_nullifiers: Vec<&Nullifier> = {
+            let mut seen = HashSet::new();
+            nullifiers
+                .iter()
+                .filter(|nullifier| seen.insert(*nullifier))
+                .collect()
+        };
+
+        let mut auth_proofs = Vec::with_capacity(unique_nullifiers.len());
+        for chunk in unique_nullifiers.chunks(BATCH_GET_MAX_KEYS) {
+            let keys = chunk
+                .iter()
+                .map(|nullifier| {
+                    HashMap::from([(
+                        AuthProofAttribute::Nullifier.to_string(),
+                        AttributeValue::S(nullifier.to_string()),
+                    )])
+                })
+                .collect();
+
+            let (items, unprocessed) =
+                batch_get_all(&self.dynamodb_client, &self.table_name, keys).await?;
+            if !unprocessed.is_empty() {
+                return Err(AuthProofStorageError::UnprocessedNullifiers(
+                    unprocessed.len(),
+                ));
+            }
+            for item in items {
+                let auth_proof = serde_dynamo::from_item(item)
+                    .map_err(|e| AuthProofStorageError::SerializationError(e.to_string()))?;
+                auth_proofs.push(auth_proof);
+            }
+        }
+
+        Ok(auth_proofs)
+    }
+
     /// Atomically gets an existing auth proof or inserts a new one if it doesn't exist
     ///
     /// This method performs an atomic get-or-insert operation in a single `DynamoDB` request
@@ -286,9 +441,8 @@ impl AuthProofStorage {
         &self,
         auth_proof_request: AuthProofInsertRequest,
     ) -> AuthProofStorageResult<AuthProof> {
-        let now = Utc::now().timestamp();
-        let rounded_now = Self::round_to_nearest_day(now);
-        let ttl = Self::generate_ttl();
+        let rounded_now = Self::round_to_nearest_day((self.clock)());
+        let ttl = self.generate_ttl();
 
         let response = self
             .dynamodb_client
@@ -353,8 +507,8 @@ impl AuthProofStorage {
     /// # Errors
     ///
     /// Returns `AuthProofStorageError` if the Dynamo DB operation fails
-    pub async fn ping_auth_proof(&self, nullifier: &str) -> AuthProofStorageResult<()> {
-        let ttl = Self::generate_ttl();
+    pub async fn ping_auth_proof(&self, nullifier: &Nullifier) -> AuthProofStorageResult<()> {
+        let ttl = self.generate_ttl();
 
         self.dynamodb_client
             .update_item()
@@ -368,11 +522,40 @@ impl AuthProofStorage {
 
         Ok(())
     }
+
+    /// Deletes the auth proof of a nullifier, e.g. when its user requests account deletion
+    ///
+    /// Succeeds if the auth proof doesn't exist, so deletions can be retried safely. Runs in a
+    /// span recording the nullifier, so deletions are auditable.
+    ///
+    /// # Arguments
+    ///
+    /// * `nullifier` - The nullifier of the auth proof to delete
+    ///
+    /// # Errors
+    ///
+    /// Returns `AuthProofStorageError` if the Dynamo DB operation fails
+    #[tracing::instrument(skip(self), fields(nullifier = %nullifier))]
+    pub async fn delete_by_nullifier(&self, nullifier: &Nullifier) -> AuthProofStorageResult<()> {
+        self.dynamodb_client
+            .delete_item()
+            .table_name(&self.table_name)
+            .key(
+                AuthProofAttribute::Nullifier.to_string(),
+                AttributeValue::S(nullifier.to_string()),
+            )
+            .send()
+            .await?;
+
+        tracing::info!("Deleted auth proof");
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::dynamodb::assert_stored_attributes_match;
 
     #[test]
     fn test_round_to_nearest_day() {
@@ -427,4 +610,43 @@ mod tests {
             next_midnight
         );
     }
+
+    #[test]
+    fn test_generate_ttl_within_window() {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let now = 1_700_000_000;
+        let mut rng = StdRng::seed_from_u64(42);
+
+        for _ in 0..1000 {
+            let ttl = AuthProofStorage::generate_ttl_at(now, &mut rng);
+            assert!(ttl >= now + TTL_MIN_SECONDS);
+            assert!(ttl <= now + TTL_MAX_SECONDS);
+        }
+    }
+
+    #[test]
+    fn test_generate_ttl_is_randomized() {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let now = 1_700_000_000;
+        let mut rng = StdRng::seed_from_u64(42);
+
+        // Same clock, the TTL doesn't reveal when the user was last active
+        let first = AuthProofStorage::generate_ttl_at(now, &mut rng);
+        let second = AuthProofStorage::generate_ttl_at(now, &mut rng);
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn test_stored_attributes_match_attribute_names() {
+        let auth_proof = AuthProof {
+            nullifier: "nullifier".to_string(),
+            encrypted_push_id: "encrypted-push-id".to_string(),
+            push_id_rotated_at: 1_700_000_000,
+            ttl: 1_700_000_000,
+        };
+
+        assert_stored_attributes_match::<AuthProofAttribute, _>(&auth_proof);
+    }
 }
@@ -0,0 +1,386 @@
+//! Helpers shared by the `DynamoDB` backed storages
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Mutex, PoisonError},
+    time::Duration,
+};
+
+use aws_sdk_dynamodb::{
+    error::SdkError,
+    operation::{
+        batch_get_item::BatchGetItemError, batch_write_item::BatchWriteItemError,
+        delete_item::DeleteItemError, put_item::PutItemError, update_item::UpdateItemError,
+    },
+    types::{AttributeValue, KeysAndAttributes, WriteRequest},
+    Client as DynamoDbClient,
+};
+
+/// Maximum number of calls made for a single batch, including the first
+pub const BATCH_MAX_ATTEMPTS: u32 = 6;
+/// Delay before the first resubmission of a batch, doubled after every attempt
+const BATCH_INITIAL_BACKOFF: Duration = Duration::from_millis(50);
+
+/// Write requests of a `batch_write_item` call, by table name
+pub type BatchWriteItems = HashMap<String, Vec<WriteRequest>>;
+
+/// Attributes of an item, or of the key of an item
+pub type Item = HashMap<String, AttributeValue>;
+
+/// Pending items of a batch operation, resubmitted by `batch_with_retry` until processed
+pub trait BatchItems: Clone + Default {
+    /// Number of items still to process
+    fn count(&self) -> usize;
+}
+
+impl<T: Clone> BatchItems for Vec<T> {
+    fn count(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<T: Clone> BatchItems for HashMap<String, Vec<T>> {
+    fn count(&self) -> usize {
+        self.values().map(Vec::len).sum()
+    }
+}
+
+/// Operation errors that can report a failed condition expression
+pub trait ConditionalCheckError {
+    /// Whether the condition expression of the write evaluated to false
+    fn is_conditional_check_failed(&self) -> bool;
+}
+
+macro_rules! impl_conditional_check_error {
+    ($($error:ty),+) => {
+        $(
+            impl ConditionalCheckError for $error {
+                fn is_conditional_check_failed(&self) -> bool {
+                    self.is_conditional_check_failed_exception()
+                }
+            }
+        )+
+    };
+}
+
+impl_conditional_check_error!(PutItemError, UpdateItemError, DeleteItemError);
+
+/// Whether a conditional write failed because its condition expression evaluated to false
+///
+/// Works for the errors of every conditional write operation (put, update and delete).
+pub fn is_conditional_check_failed<E: ConditionalCheckError, R>(err: &SdkError<E, R>) -> bool {
+    matches!(err, SdkError::ServiceError(svc) if svc.err().is_conditional_check_failed())
+}
+
+/// Whether a batch write was rejected because the table or account is throttled
+pub fn is_batch_write_throttled<R>(err: &SdkError<BatchWriteItemError, R>) -> bool {
+    matches!(
+        err,
+        SdkError::ServiceError(svc)
+            if svc.err().is_provisioned_throughput_exceeded_exception()
+                || svc.err().is_request_limit_exceeded()
+    )
+}
+
+/// Whether a batch get was rejected because the table or account is throttled
+pub fn is_batch_get_throttled<R>(err: &SdkError<BatchGetItemError, R>) -> bool {
+    matches!(
+        err,
+        SdkError::ServiceError(svc)
+            if svc.err().is_provisioned_throughput_exceeded_exception()
+                || svc.err().is_request_limit_exceeded()
+    )
+}
+
+/// Writes `request_items` with `batch_write_item`, resubmitting unprocessed items
+///
+/// See `batch_with_retry`.
+///
+/// # Errors
+///
+/// Returns the last `SdkError` if the batch failed or was still throttled after the last attempt
+pub async fn batch_write_all(
+    client: &DynamoDbClient,
+    request_items: BatchWriteItems,
+) -> Result<BatchWriteItems, SdkError<BatchWriteItemError>> {
+    batch_with_retry(
+        request_items,
+        is_batch_write_throttled,
+        |items| async move {
+            client
+                .batch_write_item()
+                .set_request_items(Some(items))
+                .send()
+                .await
+                .map(|output| output.unprocessed_items().cloned().unwrap_or_default())
+        },
+    )
+    .await
+}
+
+/// Gets the items of `keys` from `table_name` with `batch_get_item`, resubmitting unprocessed
+/// keys
+///
+/// At most 100 keys can be requested at once. See `batch_with_retry`.
+///
+/// Returns the items found, in no particular order, and the keys still unprocessed after the
+/// last attempt.
+///
+/// # Errors
+///
+/// Returns the last `SdkError` if the batch failed or was still throttled after the last attempt
+pub async fn batch_get_all(
+    client: &DynamoDbClient,
+    table_name: &str,
+    keys: Vec<Item>,
+) -> Result<(Vec<Item>, Vec<Item>), SdkError<BatchGetItemError>> {
+    let found = Mutex::new(Vec::with_capacity(keys.len()));
+    let unprocessed = batch_with_retry(keys, is_batch_get_throttled, |keys| {
+        let found = &found;
+        async move {
+            let keys_and_attributes = KeysAndAttributes::builder()
+                .set_keys(Some(keys))
+                .build()
+                .map_err(SdkError::<BatchGetItemError>::construction_failure)?;
+            let output = client
+                .batch_get_item()
+                .request_items(table_name, keys_and_attributes)
+                .send()
+                .await?;
+
+            if let Some(items) = output
+                .responses()
+                .and_then(|responses| responses.get(table_name))
+            {
+                found
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .extend_from_slice(items);
+            }
+            Ok::<_, SdkError<BatchGetItemError>>(
+                output
+                    .unprocessed_keys()
+                    .and_then(|unprocessed| unprocessed.get(table_name))
+                    .map(|unprocessed| unprocessed.keys().to_vec())
+                    .unwrap_or_default(),
+            )
+        }
+    })
+    .await?;
+
+    let found = found.into_inner().unwrap_or_else(PoisonError::into_inner);
+    Ok((found, unprocessed))
+}
+
+/// Sends `items` with `send`, resubmitting the items it returns as unprocessed
+///
+/// `DynamoDB` may process only part of a batch, so unprocessed items are resubmitted with
+/// exponential backoff, up to `BATCH_MAX_ATTEMPTS` calls. Errors matching `is_throttled` are
+/// retried the same way, while any other error fails immediately.
+///
+/// Returns the items still unprocessed after the last attempt, empty once everything is
+/// processed.
+///
+/// # Errors
+///
+/// Returns the last error if the batch failed or was still throttled after the last attempt
+pub async fn batch_with_retry<I, E, T, F, Fut>(
+    mut items: I,
+    is_throttled: T,
+    mut send: F,
+) -> Result<I, E>
+where
+    I: BatchItems,
+    T: Fn(&E) -> bool,
+    F: FnMut(I) -> Fut,
+    Fut: Future<Output = Result<I, E>>,
+{
+    let mut backoff = BATCH_INITIAL_BACKOFF;
+    for attempt in 1..=BATCH_MAX_ATTEMPTS {
+        let is_last_attempt = attempt == BATCH_MAX_ATTEMPTS;
+
+        match send(items.clone()).await {
+            Ok(unprocessed) if unprocessed.count() == 0 => return Ok(I::default()),
+            Ok(unprocessed) => {
+                let count = unprocessed.count();
+                if is_last_attempt {
+                    tracing::warn!("{count} batch items still unprocessed, giving up");
+                    return Ok(unprocessed);
+                }
+                tracing::debug!("Resubmitting {count} unprocessed batch items");
+                items = unprocessed;
+            }
+            Err(e) if is_throttled(&e) && !is_last_attempt => {
+                tracing::warn!("Batch throttled, retrying in {backoff:?}");
+            }
+            Err(e) => return Err(e),
+        }
+
+        tokio::time::sleep(backoff).await;
+        backoff *= 2;
+    }
+
+    Ok(items)
+}
+
+/// Asserts the attributes `item` is stored with are exactly the names of the attribute enum `A`
+///
+/// `item` must have every optional field set, so each attribute is serialized. On mismatch the
+/// failure lists the attribute names without a field and the fields without an attribute name.
+#[cfg(test)]
+pub(crate) fn assert_stored_attributes_match<A, T>(item: &T)
+where
+    A: strum::IntoEnumIterator + std::fmt::Display,
+    T: serde::Serialize,
+{
+    use std::collections::{BTreeSet, HashMap};
+
+    let stored: HashMap<String, aws_sdk_dynamodb::types::AttributeValue> =
+        serde_dynamo::to_item(item).unwrap();
+    let fields = stored.into_keys().collect::<BTreeSet<_>>();
+    let attributes = A::iter()
+        .map(|attribute| attribute.to_string())
+        .collect::<BTreeSet<_>>();
+
+    let missing_fields = attributes.difference(&fields).collect::<Vec<_>>();
+    let unknown_fields = fields.difference(&attributes).collect::<Vec<_>>();
+    assert!(
+        missing_fields.is_empty() && unknown_fields.is_empty(),
+        "{}: attribute names without a field: {missing_fields:?}, fields without an attribute \
+         name: {unknown_fields:?}",
+        std::any::type_name::<T>(),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_sdk_dynamodb::types::{
+        error::{
+            ConditionalCheckFailedException, ProvisionedThroughputExceededException,
+            ResourceNotFoundException,
+        },
+        AttributeValue, DeleteRequest,
+    };
+
+    fn service_error<E>(err: E) -> SdkError<E, ()> {
+        SdkError::service_error(err, ())
+    }
+
+    #[test]
+    fn test_detects_conditional_check_failures() {
+        let put = PutItemError::ConditionalCheckFailedException(
+            ConditionalCheckFailedException::builder().build(),
+        );
+        let update = UpdateItemError::ConditionalCheckFailedException(
+            ConditionalCheckFailedException::builder().build(),
+        );
+        let delete = DeleteItemError::ConditionalCheckFailedException(
+            ConditionalCheckFailedException::builder().build(),
+        );
+
+        assert!(is_conditional_check_failed(&service_error(put)));
+        assert!(is_conditional_check_failed(&service_error(update)));
+        assert!(is_conditional_check_failed(&service_error(delete)));
+    }
+
+    fn delete_request(id: &str) -> WriteRequest {
+        WriteRequest::builder()
+            .delete_request(
+                DeleteRequest::builder()
+                    .key("id", AttributeValue::S(id.to_string()))
+                    .build()
+                    .unwrap(),
+            )
+            .build()
+    }
+
+    #[tokio::test]
+    async fn test_batch_write_resubmits_unprocessed_items() {
+        let items = BatchWriteItems::from([(
+            "table".to_string(),
+            vec![delete_request("1"), delete_request("2")],
+        )]);
+        let mut sent = Vec::new();
+
+        let unprocessed = batch_with_retry(items, is_batch_write_throttled, |items| {
+            let first_call = sent.is_empty();
+            sent.push(items["table"].len());
+            async move {
+                if first_call {
+                    // Only the first item was processed
+                    Ok::<_, SdkError<BatchWriteItemError, ()>>(BatchWriteItems::from([(
+                        "table".to_string(),
+                        vec![delete_request("2")],
+                    )]))
+                } else {
+                    Ok(BatchWriteItems::new())
+                }
+            }
+        })
+        .await
+        .unwrap();
+
+        assert!(unprocessed.is_empty());
+        assert_eq!(sent, vec![2, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_batch_write_retries_throttling() {
+        let items = BatchWriteItems::from([("table".to_string(), vec![delete_request("1")])]);
+        let mut calls = 0;
+
+        let result = batch_with_retry(items, is_batch_write_throttled, |_| {
+            calls += 1;
+            let throttled = calls == 1;
+            async move {
+                if throttled {
+                    Err(service_error(
+                        BatchWriteItemError::ProvisionedThroughputExceededException(
+                            ProvisionedThroughputExceededException::builder().build(),
+                        ),
+                    ))
+                } else {
+                    Ok(BatchWriteItems::new())
+                }
+            }
+        })
+        .await;
+
+        assert!(result.unwrap().is_empty());
+        assert_eq!(calls, 2);
+    }
+
+    #[tokio::test]
+    async fn test_batch_write_fails_on_other_errors() {
+        let items = BatchWriteItems::from([("table".to_string(), vec![delete_request("1")])]);
+        let mut calls = 0;
+
+        let result = batch_with_retry(items, is_batch_write_throttled, |_| {
+            calls += 1;
+            async {
+                Err::<BatchWriteItems, _>(service_error(
+                    BatchWriteItemError::ResourceNotFoundException(
+                        ResourceNotFoundException::builder().build(),
+                    ),
+                ))
+            }
+        })
+        .await;
+
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn test_ignores_other_errors() {
+        let not_found =
+            PutItemError::ResourceNotFoundException(ResourceNotFoundException::builder().build());
+
+        assert!(!is_conditional_check_failed(&service_error(not_found)));
+        assert!(!is_conditional_check_failed(
+            &SdkError::<PutItemError, ()>::timeout_error("timed out")
+        ));
+    }
+}
@@ -2,7 +2,8 @@
 
 use aws_sdk_dynamodb::error::SdkError;
 use aws_sdk_dynamodb::operation::{
-    delete_item::DeleteItemError, get_item::GetItemError, put_item::PutItemError, query::QueryError,
+    delete_item::DeleteItemError, get_item::GetItemError, put_item::PutItemError,
+    query::QueryError, update_item::UpdateItemError,
 };
 use thiserror::Error;
 
@@ -28,6 +29,18 @@ pub enum GroupInviteStorageError {
     #[error("Failed to delete group invite from DynamoDB: {0:?}")]
     DynamoDbDeleteError(#[from] SdkError<DeleteItemError>),
 
+    /// Failed to update group invite in `DynamoDB`
+    #[error("Failed to update group invite in DynamoDB: {0:?}")]
+    DynamoDbUpdateError(#[from] SdkError<UpdateItemError>),
+
+    /// Group invite doesn't exist
+    #[error("Group invite not found")]
+    InviteNotFound,
+
+    /// Group invite has no use left
+    #[error("Group invite has no use left")]
+    InviteExhausted,
+
     /// Failed to parse group invite from `DynamoDB` item
     #[error("Failed to parse group invite: {0}")]
     SerializationError(String),
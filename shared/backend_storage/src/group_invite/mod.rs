@@ -2,35 +2,56 @@
 
 mod error;
 
-use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::types::{AttributeValue, ReturnValue};
 use aws_sdk_dynamodb::Client as DynamoDbClient;
 pub use error::{GroupInviteStorageError, GroupInviteStorageResult};
 use serde::{Deserialize, Serialize};
 use serde_dynamo::{from_items, to_item};
 use std::sync::Arc;
-use strum::Display;
+use strum::{Display, EnumIter};
+
+use crate::dynamodb::is_conditional_check_failed;
 
 /// `DynamoDB` table for group invites
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GroupInvite {
     /// Primary key - unique invite ID (UUID v4)
+    #[serde(rename = "id")]
     pub id: String,
     /// XMTP topic
+    #[serde(rename = "topic")]
     pub topic: String,
     /// Group Name used in invite link
+    #[serde(rename = "group_name")]
     pub group_name: String,
     /// Encrypted push of the inviter used to send silent push notification
+    #[serde(rename = "creator_encrypted_push_id")]
     pub creator_encrypted_push_id: String,
     /// Timestamp of invite creation
+    #[serde(rename = "created_at")]
     pub created_at: i64,
     /// Optional max uses of the invite
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "max_uses", skip_serializing_if = "Option::is_none")]
     pub max_uses: Option<i64>,
+    /// Uses left before the invite is exhausted, `None` for unlimited invites
+    #[serde(rename = "remaining_uses", skip_serializing_if = "Option::is_none")]
+    pub remaining_uses: Option<i64>,
     /// Optional timestamp expiration of the invite
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "expires_at", skip_serializing_if = "Option::is_none")]
     pub expires_at: Option<i64>,
 }
 
+impl GroupInvite {
+    /// Whether the invite is expired at `now` (Unix timestamp in seconds)
+    ///
+    /// Invites without `expires_at` never expire, and an invite is already expired at its
+    /// `expires_at`.
+    #[must_use]
+    pub fn is_expired_at(&self, now: i64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
 /// Request to create a new group invite
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GroupInviteCreateRequest {
@@ -49,7 +70,7 @@ pub struct GroupInviteCreateRequest {
 }
 
 /// `DynamoDB` attribute names for the group invite table
-#[derive(Debug, Display)]
+#[derive(Debug, Display, EnumIter)]
 #[strum(serialize_all = "snake_case")]
 pub enum GroupInviteAttribute {
     /// Primary key - unique invite ID
@@ -64,6 +85,8 @@ pub enum GroupInviteAttribute {
     CreatedAt,
     /// Maximum number of uses for the invite
     MaxUses,
+    /// Uses left before the invite is exhausted
+    RemainingUses,
     /// Expiration timestamp
     ExpiresAt,
 }
@@ -160,6 +183,20 @@ impl GroupInviteStorage {
             .transpose()
     }
 
+    /// Get a single group invite by ID, unless it's expired
+    ///
+    /// # Errors
+    ///
+    /// Returns `GroupInviteStorageError` if the `DynamoDB` get operation fails
+    pub async fn get_one_active(&self, id: &str) -> GroupInviteStorageResult<Option<GroupInvite>> {
+        let now = chrono::Utc::now().timestamp();
+
+        Ok(self
+            .get_one(id)
+            .await?
+            .filter(|invite| !invite.is_expired_at(now)))
+    }
+
     /// Create a new group invite with generated UUID
     ///
     /// # Errors
@@ -178,6 +215,7 @@ impl GroupInviteStorage {
             group_name: request.group_name,
             creator_encrypted_push_id: request.creator_encrypted_push_id,
             max_uses: request.max_uses,
+            remaining_uses: request.max_uses,
             expires_at: request.expires_at,
             created_at: chrono::Utc::now().timestamp(),
         };
@@ -194,6 +232,63 @@ impl GroupInviteStorage {
         Ok(invite)
     }
 
+    /// Consume a use of a group invite, returning the updated invite
+    ///
+    /// The remaining uses are decremented atomically, so concurrent joins can't use the invite
+    /// more than `max_uses` times. Invites without `max_uses` are unlimited and returned as is.
+    ///
+    /// # Errors
+    ///
+    /// Returns `GroupInviteStorageError::InviteNotFound` if the invite doesn't exist,
+    /// `GroupInviteStorageError::InviteExhausted` if it has no use left, or another
+    /// `GroupInviteStorageError` if a `DynamoDB` operation fails
+    pub async fn consume_use(&self, id: &str) -> GroupInviteStorageResult<GroupInvite> {
+        let result = self
+            .dynamodb_client
+            .update_item()
+            .table_name(&self.table_name)
+            .key(
+                GroupInviteAttribute::Id.to_string(),
+                AttributeValue::S(id.to_string()),
+            )
+            // Invites created before remaining uses were tracked start from their max uses
+            .update_expression(
+                "SET #remaining_uses = if_not_exists(#remaining_uses, #max_uses) - :one",
+            )
+            .condition_expression(
+                "attribute_exists(#max_uses) AND (#remaining_uses > :zero OR \
+                 (attribute_not_exists(#remaining_uses) AND #max_uses > :zero))",
+            )
+            .expression_attribute_names("#max_uses", GroupInviteAttribute::MaxUses.to_string())
+            .expression_attribute_names(
+                "#remaining_uses",
+                GroupInviteAttribute::RemainingUses.to_string(),
+            )
+            .expression_attribute_values(":one", AttributeValue::N("1".to_string()))
+            .expression_attribute_values(":zero", AttributeValue::N("0".to_string()))
+            .return_values(ReturnValue::AllNew)
+            .send()
+            .await;
+
+        match result {
+            Ok(response) => {
+                let item = response.attributes().ok_or_else(|| {
+                    GroupInviteStorageError::SerializationError(
+                        "No attributes returned from update operation".to_string(),
+                    )
+                })?;
+                Ok(serde_dynamo::from_item(item.clone())?)
+            }
+            // The invite is missing, unlimited or exhausted
+            Err(e) if is_conditional_check_failed(&e) => match self.get_one(id).await? {
+                None => Err(GroupInviteStorageError::InviteNotFound),
+                Some(invite) if invite.max_uses.is_none() => Ok(invite),
+                Some(_) => Err(GroupInviteStorageError::InviteExhausted),
+            },
+            Err(e) => Err(e.into()),
+        }
+    }
+
     /// Delete a group invite by ID
     ///
     /// # Errors
@@ -217,6 +312,7 @@ impl GroupInviteStorage {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::dynamodb::assert_stored_attributes_match;
 
     #[test]
     fn test_group_invite_serialization() {
@@ -226,6 +322,7 @@ mod tests {
             group_name: "Test Group".to_string(),
             creator_encrypted_push_id: "encrypted-push-id".to_string(),
             max_uses: Some(10),
+            remaining_uses: Some(10),
             expires_at: Some(1_234_567_890),
             created_at: chrono::Utc::now().timestamp(),
         };
@@ -252,6 +349,7 @@ mod tests {
             group_name: "Test Group".to_string(),
             creator_encrypted_push_id: "encrypted-push-id".to_string(),
             max_uses: None,
+            remaining_uses: None,
             expires_at: None,
             created_at: chrono::Utc::now().timestamp(),
         };
@@ -262,4 +360,44 @@ mod tests {
         assert!(json.get("max_uses").is_none());
         assert!(json.get("expires_at").is_none());
     }
+
+    #[test]
+    fn test_group_invite_expiry() {
+        let now = 1_700_000_000;
+        let invite = |expires_at| GroupInvite {
+            id: "test-id".to_string(),
+            topic: "test-topic".to_string(),
+            group_name: "Test Group".to_string(),
+            creator_encrypted_push_id: "encrypted-push-id".to_string(),
+            max_uses: None,
+            remaining_uses: None,
+            expires_at,
+            created_at: 1_600_000_000,
+        };
+
+        // Never expires
+        assert!(!invite(None).is_expired_at(now));
+        // Expires exactly now
+        assert!(invite(Some(now)).is_expired_at(now));
+        // Expires later
+        assert!(!invite(Some(now + 1)).is_expired_at(now));
+        // Expired earlier
+        assert!(invite(Some(now - 1)).is_expired_at(now));
+    }
+
+    #[test]
+    fn test_stored_attributes_match_attribute_names() {
+        let invite = GroupInvite {
+            id: "test-id".to_string(),
+            topic: "test-topic".to_string(),
+            group_name: "Test Group".to_string(),
+            creator_encrypted_push_id: "encrypted-push-id".to_string(),
+            max_uses: Some(10),
+            remaining_uses: Some(10),
+            expires_at: Some(1_234_567_890),
+            created_at: 1_234_567_890,
+        };
+
+        assert_stored_attributes_match::<GroupInviteAttribute, _>(&invite);
+    }
 }
@@ -3,10 +3,12 @@
 use aws_sdk_dynamodb::error::SdkError;
 use aws_sdk_dynamodb::operation::{
     batch_write_item::BatchWriteItemError, delete_item::DeleteItemError, get_item::GetItemError,
-    put_item::PutItemError, query::QueryError,
+    put_item::PutItemError, query::QueryError, update_item::UpdateItemError,
 };
 use thiserror::Error;
 
+use super::JoinRequestStatus;
+
 /// Result type alias for storage operations
 pub type GroupJoinRequestStorageResult<T> = Result<T, GroupJoinRequestStorageError>;
 
@@ -25,6 +27,10 @@ pub enum GroupJoinRequestStorageError {
     #[error("Failed to query group join requests from DynamoDB: {0:?}")]
     DynamoDbQueryError(#[from] SdkError<QueryError>),
 
+    /// Failed to update group join request in `DynamoDB`
+    #[error("Failed to update group join request in DynamoDB: {0:?}")]
+    DynamoDbUpdateError(#[from] SdkError<UpdateItemError>),
+
     /// Failed to delete group join request from `DynamoDB`
     #[error("Failed to delete group join request from DynamoDB: {0:?}")]
     DynamoDbDeleteError(#[from] SdkError<DeleteItemError>),
@@ -33,6 +39,23 @@ pub enum GroupJoinRequestStorageError {
     #[error("Failed to batch write group join requests to DynamoDB: {0:?}")]
     DynamoDbBatchWriteError(#[from] SdkError<BatchWriteItemError>),
 
+    /// `DynamoDB` left part of a batch write unprocessed after all retries
+    #[error("{0} batch write items were left unprocessed by DynamoDB")]
+    UnprocessedItems(usize),
+
+    /// The join request can't move from its current status to the requested one
+    #[error("Invalid group join request status transition from {from} to {to}")]
+    InvalidStatusTransition {
+        /// Current status of the join request
+        from: JoinRequestStatus,
+        /// Requested status
+        to: JoinRequestStatus,
+    },
+
+    /// Every request that can be created under the idempotency key was already resolved
+    #[error("The idempotency key already has {0} resolved group join requests")]
+    IdempotencyKeyExhausted(u32),
+
     /// Failed to parse group join request from `DynamoDB` item
     #[error("Failed to parse group join request: {0}")]
     SerializationError(String),
@@ -2,15 +2,27 @@
 
 mod error;
 
-use aws_sdk_dynamodb::types::{AttributeValue, DeleteRequest, WriteRequest};
+use aws_sdk_dynamodb::error::SdkError;
+use aws_sdk_dynamodb::operation::put_item::PutItemError;
+use aws_sdk_dynamodb::operation::update_item::builders::UpdateItemFluentBuilder;
+use aws_sdk_dynamodb::types::{AttributeValue, DeleteRequest, ReturnValue, WriteRequest};
 use aws_sdk_dynamodb::Client as DynamoDbClient;
 pub use error::{GroupJoinRequestStorageError, GroupJoinRequestStorageResult};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use serde_dynamo::{from_item, to_item};
+use serde_dynamo::{from_item, to_attribute_value, to_item};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 use std::sync::Arc;
-use strum::Display;
+use strum::{Display, EnumIter};
+
+use crate::dynamodb::{batch_write_all, is_conditional_check_failed};
+
+/// Join requests deleted per page by `delete_by_group_invite_id`, `DynamoDB`'s batch write limit
+const DELETE_PAGE_SIZE: i32 = 25;
+
+/// Maximum number of join requests `create` stores under one idempotency key, see `create`
+const MAX_REQUESTS_PER_IDEMPOTENCY_KEY: u32 = 32;
 
 /// Status of a group join request
 #[derive(Debug, Clone, Display, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
@@ -26,21 +38,49 @@ pub enum JoinRequestStatus {
     Rejected,
 }
 
+impl JoinRequestStatus {
+    /// Whether a join request can move from `self` to `next`
+    ///
+    /// Requests only move forward: `Pending` -> `NotificationSent` -> `Accepted` or `Rejected`.
+    /// Moving to `NotificationSent` again is allowed, so a retried notification doesn't fail.
+    #[must_use]
+    pub fn can_transition_to(&self, next: &Self) -> bool {
+        next.previous_statuses().contains(self)
+    }
+
+    /// Statuses a join request can move to `self` from
+    const fn previous_statuses(&self) -> &'static [Self] {
+        match self {
+            Self::Pending => &[],
+            Self::NotificationSent => &[Self::Pending, Self::NotificationSent],
+            Self::Accepted | Self::Rejected => &[Self::NotificationSent],
+        }
+    }
+}
+
 /// `DynamoDB` table for group join requests
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GroupJoinRequest {
     /// Primary key - unique join request ID (UUID v4)
+    #[serde(rename = "id")]
     pub id: String,
     /// Group Invite ID linked to `GroupInvites` table
+    #[serde(rename = "group_invite_id")]
     pub group_invite_id: String,
     /// Encrypted inbox id of the invitee with enclave's public key
+    #[serde(rename = "encrypted_inbox_id")]
     pub encrypted_inbox_id: String,
     /// Status of the join request
+    #[serde(rename = "status")]
     pub status: JoinRequestStatus,
     /// Creation timestamp
+    #[serde(rename = "created_at")]
     pub created_at: i64,
     /// Optional timestamp when notification was sent
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(
+        rename = "notification_sent_at",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub notification_sent_at: Option<i64>,
 }
 
@@ -51,6 +91,10 @@ pub struct CreateGroupJoinRequest {
     pub group_invite_id: String,
     /// Encrypted inbox id of the invitee with enclave's public key
     pub encrypted_inbox_id: String,
+    /// Client-supplied key shared by all retries of the same join request
+    ///
+    /// The encrypted inbox ID is randomized on every encryption, so it can't identify retries.
+    pub idempotency_key: String,
     /// Status of the join request
     pub status: JoinRequestStatus,
     /// Optional timestamp when notification was sent
@@ -59,7 +103,7 @@ pub struct CreateGroupJoinRequest {
 }
 
 /// `DynamoDB` attribute names for the group join request table
-#[derive(Debug, Display)]
+#[derive(Debug, Display, EnumIter)]
 #[strum(serialize_all = "snake_case")]
 pub enum GroupJoinRequestAttribute {
     /// Primary key - unique join request ID
@@ -76,11 +120,25 @@ pub enum GroupJoinRequestAttribute {
     CreatedAt,
 }
 
+/// Opaque position in the join requests of a group invite, used to resume a paginated query
+#[derive(Debug, Clone)]
+pub struct GroupJoinRequestCursor(HashMap<String, AttributeValue>);
+
+/// A page of group join requests
+#[derive(Debug, Clone)]
+pub struct GroupJoinRequestPage {
+    /// Join requests in this page
+    pub requests: Vec<GroupJoinRequest>,
+    /// Cursor of the next page, `None` if this is the last page
+    pub next_cursor: Option<GroupJoinRequestCursor>,
+}
+
 /// Storage client for group join request operations
 pub struct GroupJoinRequestStorage {
     dynamodb_client: Arc<DynamoDbClient>,
     table_name: String,
     group_invite_index_name: String,
+    notification_sent_index_name: String,
 }
 
 impl GroupJoinRequestStorage {
@@ -91,16 +149,19 @@ impl GroupJoinRequestStorage {
     /// * `dynamodb_client` - Pre-configured `DynamoDB` client
     /// * `table_name` - `DynamoDB` table name for group join requests
     /// * `group_invite_index_name` - Name of the GSI for group invite queries
+    /// * `notification_sent_index_name` - Name of the GSI on `status` and `notification_sent_at`
     #[must_use]
     pub const fn new(
         dynamodb_client: Arc<DynamoDbClient>,
         table_name: String,
         group_invite_index_name: String,
+        notification_sent_index_name: String,
     ) -> Self {
         Self {
             dynamodb_client,
             table_name,
             group_invite_index_name,
+            notification_sent_index_name,
         }
     }
 
@@ -135,6 +196,8 @@ impl GroupJoinRequestStorage {
 
     /// Get all group join requests for a given group invite ID
     ///
+    /// Follows the query pages until every request is returned.
+    ///
     /// # Errors
     ///
     /// Returns `GroupJoinRequestStorageError` if the `DynamoDB` query operation fails
@@ -142,6 +205,45 @@ impl GroupJoinRequestStorage {
         &self,
         group_invite_id: &str,
     ) -> GroupJoinRequestStorageResult<Vec<GroupJoinRequest>> {
+        let mut requests = Vec::new();
+        let mut cursor = None;
+
+        loop {
+            let page = self
+                .get_page_by_group_invite_id(group_invite_id, None, cursor)
+                .await?;
+            requests.extend(page.requests);
+
+            cursor = page.next_cursor;
+            if cursor.is_none() {
+                break;
+            }
+        }
+
+        Ok(requests)
+    }
+
+    /// Get a single page of group join requests for a given group invite ID
+    ///
+    /// # Arguments
+    ///
+    /// * `group_invite_id` - The group invite to get join requests for
+    /// * `page_size` - Maximum number of requests in the page, `None` for `DynamoDB`'s page limit
+    /// * `cursor` - Cursor returned with the previous page, `None` for the first page
+    ///
+    /// # Returns
+    ///
+    /// The page of requests, with the cursor of the next page if there are more requests
+    ///
+    /// # Errors
+    ///
+    /// Returns `GroupJoinRequestStorageError` if the `DynamoDB` query operation fails
+    pub async fn get_page_by_group_invite_id(
+        &self,
+        group_invite_id: &str,
+        page_size: Option<i32>,
+        cursor: Option<GroupJoinRequestCursor>,
+    ) -> GroupJoinRequestStorageResult<GroupJoinRequestPage> {
         let response = self
             .dynamodb_client
             .query()
@@ -156,17 +258,245 @@ impl GroupJoinRequestStorage {
                 ":group_invite_id",
                 AttributeValue::S(group_invite_id.to_string()),
             )
+            .set_limit(page_size)
+            .set_exclusive_start_key(cursor.map(|cursor| cursor.0))
             .send()
             .await?;
 
-        response
+        let requests = response
             .items()
             .iter()
             .map(|item| {
                 from_item(item.clone())
                     .map_err(|e| GroupJoinRequestStorageError::SerializationError(e.to_string()))
             })
-            .collect()
+            .collect::<GroupJoinRequestStorageResult<_>>()?;
+
+        Ok(GroupJoinRequestPage {
+            requests,
+            next_cursor: response
+                .last_evaluated_key()
+                .cloned()
+                .map(GroupJoinRequestCursor),
+        })
+    }
+
+    /// Get all group join requests with `status` that were notified before `cutoff`
+    ///
+    /// Used to find requests to re-notify or escalate. Requests that were never notified are not
+    /// in the index and never returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `status` - Status of the requests
+    /// * `cutoff` - Unix timestamp (seconds), only requests notified strictly before are returned
+    ///
+    /// # Errors
+    ///
+    /// Returns `GroupJoinRequestStorageError` if the `DynamoDB` query operation fails
+    pub async fn get_notified_before(
+        &self,
+        status: &JoinRequestStatus,
+        cutoff: i64,
+    ) -> GroupJoinRequestStorageResult<Vec<GroupJoinRequest>> {
+        // Match the status as it is serialized in stored items
+        let status: AttributeValue = to_attribute_value(status)
+            .map_err(|e| GroupJoinRequestStorageError::SerializationError(e.to_string()))?;
+
+        let mut requests = Vec::new();
+        let mut exclusive_start_key = None;
+
+        loop {
+            let response = self
+                .dynamodb_client
+                .query()
+                .table_name(&self.table_name)
+                .index_name(&self.notification_sent_index_name)
+                .key_condition_expression("#status = :status AND #notification_sent_at < :cutoff")
+                .expression_attribute_names(
+                    "#status",
+                    GroupJoinRequestAttribute::Status.to_string(),
+                )
+                .expression_attribute_names(
+                    "#notification_sent_at",
+                    GroupJoinRequestAttribute::NotificationSentAt.to_string(),
+                )
+                .expression_attribute_values(":status", status.clone())
+                .expression_attribute_values(":cutoff", AttributeValue::N(cutoff.to_string()))
+                .set_exclusive_start_key(exclusive_start_key)
+                .send()
+                .await?;
+
+            for item in response.items() {
+                requests.push(from_item(item.clone()).map_err(|e| {
+                    GroupJoinRequestStorageError::SerializationError(e.to_string())
+                })?);
+            }
+
+            exclusive_start_key = response.last_evaluated_key().cloned();
+            if exclusive_start_key.is_none() {
+                break;
+            }
+        }
+
+        Ok(requests)
+    }
+
+    /// Update the status of a join request
+    ///
+    /// Only the transitions allowed by `JoinRequestStatus::can_transition_to` are applied, the
+    /// current status is checked by the update's condition so concurrent updates can't race.
+    ///
+    /// Moving to `NotificationSent` stamps `notification_sent_at` only if it isn't set yet, so
+    /// retries keep the time of the first notification. Use `restamp_notification_sent_at` when
+    /// the inviter is genuinely notified again.
+    ///
+    /// # Returns
+    ///
+    /// The updated join request, `None` if it doesn't exist
+    ///
+    /// # Errors
+    ///
+    /// Returns `GroupJoinRequestStorageError::InvalidStatusTransition` if the join request can't
+    /// move from its current status to `status`, or `GroupJoinRequestStorageError` if the
+    /// `DynamoDB` operations fail
+    pub async fn update_status(
+        &self,
+        id: &str,
+        status: &JoinRequestStatus,
+    ) -> GroupJoinRequestStorageResult<Option<GroupJoinRequest>> {
+        let previous_statuses = status.previous_statuses();
+        if previous_statuses.is_empty() {
+            return self.reject_transition(id, status).await;
+        }
+
+        let placeholders = (0..previous_statuses.len())
+            .map(|i| format!(":previous_status_{i}"))
+            .collect::<Vec<_>>();
+
+        let mut update = self
+            .dynamodb_client
+            .update_item()
+            .table_name(&self.table_name)
+            .key(
+                GroupJoinRequestAttribute::Id.to_string(),
+                AttributeValue::S(id.to_string()),
+            )
+            .condition_expression(format!(
+                "attribute_exists(#id) AND #status IN ({})",
+                placeholders.join(", ")
+            ))
+            .expression_attribute_names("#id", GroupJoinRequestAttribute::Id.to_string())
+            .expression_attribute_names("#status", GroupJoinRequestAttribute::Status.to_string())
+            .expression_attribute_values(":status", Self::status_value(status)?)
+            .return_values(ReturnValue::AllNew);
+        for (placeholder, previous_status) in placeholders.into_iter().zip(previous_statuses) {
+            update = update
+                .expression_attribute_values(placeholder, Self::status_value(previous_status)?);
+        }
+
+        update = if *status == JoinRequestStatus::NotificationSent {
+            update
+                .update_expression(
+                    "SET #status = :status, \
+                     #notification_sent_at = if_not_exists(#notification_sent_at, :now)",
+                )
+                .expression_attribute_names(
+                    "#notification_sent_at",
+                    GroupJoinRequestAttribute::NotificationSentAt.to_string(),
+                )
+                .expression_attribute_values(
+                    ":now",
+                    AttributeValue::N(chrono::Utc::now().timestamp().to_string()),
+                )
+        } else {
+            update.update_expression("SET #status = :status")
+        };
+
+        match self.send_update(update).await? {
+            Some(join_request) => Ok(Some(join_request)),
+            // Either the join request doesn't exist or its status doesn't allow the transition
+            None => self.reject_transition(id, status).await,
+        }
+    }
+
+    /// Fails a status update of a join request that can't move to `status`
+    ///
+    /// # Returns
+    ///
+    /// `None` if the join request doesn't exist
+    ///
+    /// # Errors
+    ///
+    /// Returns `GroupJoinRequestStorageError::InvalidStatusTransition` if the join request exists,
+    /// or `GroupJoinRequestStorageError` if the `DynamoDB` get operation fails
+    async fn reject_transition(
+        &self,
+        id: &str,
+        status: &JoinRequestStatus,
+    ) -> GroupJoinRequestStorageResult<Option<GroupJoinRequest>> {
+        match self.get_one(id).await? {
+            Some(join_request) => Err(GroupJoinRequestStorageError::InvalidStatusTransition {
+                from: join_request.status,
+                to: status.clone(),
+            }),
+            None => Ok(None),
+        }
+    }
+
+    /// Set `notification_sent_at` of a join request to now, overwriting any previous value
+    ///
+    /// Only for when the inviter is notified again, see `update_status`.
+    ///
+    /// # Returns
+    ///
+    /// The updated join request, `None` if it doesn't exist
+    ///
+    /// # Errors
+    ///
+    /// Returns `GroupJoinRequestStorageError` if the `DynamoDB` update operation fails
+    pub async fn restamp_notification_sent_at(
+        &self,
+        id: &str,
+    ) -> GroupJoinRequestStorageResult<Option<GroupJoinRequest>> {
+        let update = self
+            .dynamodb_client
+            .update_item()
+            .table_name(&self.table_name)
+            .key(
+                GroupJoinRequestAttribute::Id.to_string(),
+                AttributeValue::S(id.to_string()),
+            )
+            .condition_expression("attribute_exists(#id)")
+            .update_expression("SET #notification_sent_at = :now")
+            .expression_attribute_names("#id", GroupJoinRequestAttribute::Id.to_string())
+            .expression_attribute_names(
+                "#notification_sent_at",
+                GroupJoinRequestAttribute::NotificationSentAt.to_string(),
+            )
+            .expression_attribute_values(
+                ":now",
+                AttributeValue::N(chrono::Utc::now().timestamp().to_string()),
+            )
+            .return_values(ReturnValue::AllNew);
+
+        self.send_update(update).await
+    }
+
+    /// Sends a conditional update, mapping a failed condition to `None`
+    async fn send_update(
+        &self,
+        update: UpdateItemFluentBuilder,
+    ) -> GroupJoinRequestStorageResult<Option<GroupJoinRequest>> {
+        match update.send().await {
+            Ok(output) => output
+                .attributes()
+                .map(|item| from_item(item.clone()))
+                .transpose()
+                .map_err(|e| GroupJoinRequestStorageError::SerializationError(e.to_string())),
+            Err(e) if is_conditional_check_failed(&e) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
     }
 
     /// Count approved join requests for a given group invite ID
@@ -206,6 +536,9 @@ impl GroupJoinRequestStorage {
 
     /// Delete all group join requests linked to a given group invite ID
     ///
+    /// Each page of requests is deleted before the next one is queried, so the requests of a
+    /// viral invite are never all held in memory.
+    ///
     /// # Errors
     ///
     /// Returns `GroupJoinRequestStorageError` if the `DynamoDB` operations fail
@@ -213,18 +546,23 @@ impl GroupJoinRequestStorage {
         &self,
         group_invite_id: &str,
     ) -> GroupJoinRequestStorageResult<()> {
-        // Query all join requests for this group invite
-        let group_join_requests = self.get_by_group_invite_id(group_invite_id).await?;
+        let mut cursor = None;
 
-        if group_join_requests.is_empty() {
-            return Ok(());
+        loop {
+            let page = self
+                .get_page_by_group_invite_id(group_invite_id, Some(DELETE_PAGE_SIZE), cursor)
+                .await?;
+            let ids: Vec<String> = page.requests.into_iter().map(|jr| jr.id).collect();
+            self.batch_delete(&ids).await?;
+
+            // The cursor stays valid once the request it points to is deleted
+            cursor = page.next_cursor;
+            if cursor.is_none() {
+                break;
+            }
         }
 
-        // Extract IDs for batch deletion
-        let ids: Vec<String> = group_join_requests.into_iter().map(|jr| jr.id).collect();
-
-        // Use batch delete
-        self.batch_delete(&ids).await
+        Ok(())
     }
 
     /// Batch delete multiple join requests by their IDs
@@ -240,11 +578,16 @@ impl GroupJoinRequestStorage {
                 .map(|id| Self::build_delete_request(id.clone()))
                 .collect::<Result<Vec<_>, _>>()?;
 
-            self.dynamodb_client
-                .batch_write_item()
-                .request_items(&self.table_name, write_requests)
-                .send()
-                .await?;
+            let unprocessed = batch_write_all(
+                &self.dynamodb_client,
+                HashMap::from([(self.table_name.clone(), write_requests)]),
+            )
+            .await?;
+            if !unprocessed.is_empty() {
+                return Err(GroupJoinRequestStorageError::UnprocessedItems(
+                    unprocessed.values().map(Vec::len).sum(),
+                ));
+            }
         }
 
         Ok(())
@@ -275,20 +618,28 @@ impl GroupJoinRequestStorage {
             .build())
     }
 
-    /// Create a new group join request with generated UUID
+    /// Create a new group join request, idempotently
+    ///
+    /// The ID is derived from the group invite and the client's idempotency key, and the put is
+    /// conditional on no request with that ID existing. A retried create thus returns the
+    /// existing request instead of storing a duplicate, which would notify the inviter twice.
+    ///
+    /// Only unresolved (pending or notified) requests are returned for a retry. Once a request
+    /// was accepted or rejected, a create with the same key stores a new request under the ID of
+    /// the next generation of the key, and so on. Retries of that create walk the same
+    /// generations, so they return the new request rather than storing another one.
     ///
     /// # Errors
     ///
-    /// Returns `GroupJoinRequestStorageError` if the `DynamoDB` put operation fails
+    /// Returns `GroupJoinRequestStorageError::IdempotencyKeyExhausted` if the key already has
+    /// `MAX_REQUESTS_PER_IDEMPOTENCY_KEY` resolved requests, or `GroupJoinRequestStorageError` if
+    /// the `DynamoDB` put or get operation fails
     pub async fn create(
         &self,
         request: CreateGroupJoinRequest,
     ) -> GroupJoinRequestStorageResult<GroupJoinRequest> {
-        // Generate UUID v4 for the join request ID
-        let id = uuid::Uuid::new_v4().to_string();
-
-        let join_request = GroupJoinRequest {
-            id: id.clone(),
+        let mut join_request = GroupJoinRequest {
+            id: String::new(),
             group_invite_id: request.group_invite_id,
             encrypted_inbox_id: request.encrypted_inbox_id,
             status: request.status,
@@ -296,22 +647,92 @@ impl GroupJoinRequestStorage {
             created_at: chrono::Utc::now().timestamp(),
         };
 
-        let item = to_item(&join_request)?;
+        for generation in 0..MAX_REQUESTS_PER_IDEMPOTENCY_KEY {
+            join_request.id = Self::idempotent_id(
+                &join_request.group_invite_id,
+                &request.idempotency_key,
+                generation,
+            );
+
+            match self.put_new(to_item(&join_request)?).await {
+                Ok(()) => return Ok(join_request),
+                Err(e) if is_conditional_check_failed(&e) => {
+                    match self.get_one(&join_request.id).await? {
+                        Some(existing) if Self::is_unresolved(&existing.status) => {
+                            return Ok(existing)
+                        }
+                        // Resolved, the next generation holds the request created after it
+                        Some(_) => {}
+                        // Deleted in between the put and the get, the caller may retry
+                        None => return Err(e.into()),
+                    }
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Err(GroupJoinRequestStorageError::IdempotencyKeyExhausted(
+            MAX_REQUESTS_PER_IDEMPOTENCY_KEY,
+        ))
+    }
 
+    /// Stores the join request `item`, conditional on no request with its ID existing
+    async fn put_new(
+        &self,
+        item: HashMap<String, AttributeValue>,
+    ) -> Result<(), SdkError<PutItemError>> {
         self.dynamodb_client
             .put_item()
             .table_name(&self.table_name)
             .set_item(Some(item))
+            .condition_expression("attribute_not_exists(#id)")
+            .expression_attribute_names("#id", GroupJoinRequestAttribute::Id.to_string())
             .send()
             .await?;
 
-        Ok(join_request)
+        Ok(())
+    }
+
+    /// Whether a join request is still waiting for the inviter's decision
+    const fn is_unresolved(status: &JoinRequestStatus) -> bool {
+        matches!(
+            status,
+            JoinRequestStatus::Pending | JoinRequestStatus::NotificationSent
+        )
+    }
+
+    /// Status as it is serialized in stored items
+    fn status_value(status: &JoinRequestStatus) -> GroupJoinRequestStorageResult<AttributeValue> {
+        to_attribute_value(status)
+            .map_err(|e| GroupJoinRequestStorageError::SerializationError(e.to_string()))
+    }
+
+    /// Derives the join request ID from the group invite, the client's idempotency key and the
+    /// generation of the key, see `create`
+    ///
+    /// Formatted as a UUID, like randomly generated IDs.
+    fn idempotent_id(group_invite_id: &str, idempotency_key: &str, generation: u32) -> String {
+        let mut hasher = Sha256::new();
+        // Length-prefix the strings so distinct inputs can't concatenate to the same bytes
+        hasher.update((group_invite_id.len() as u64).to_be_bytes());
+        hasher.update(group_invite_id.as_bytes());
+        hasher.update((idempotency_key.len() as u64).to_be_bytes());
+        hasher.update(idempotency_key.as_bytes());
+        hasher.update(generation.to_be_bytes());
+        let digest = hasher.finalize();
+
+        let mut bytes = [0; 16];
+        bytes.copy_from_slice(&digest[..16]);
+        uuid::Builder::from_random_bytes(bytes)
+            .into_uuid()
+            .to_string()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::dynamodb::assert_stored_attributes_match;
 
     #[test]
     fn test_group_join_request_serialization() {
@@ -349,6 +770,27 @@ mod tests {
         assert_eq!(serialized, "\"NotificationSent\"");
     }
 
+    #[test]
+    fn test_status_only_moves_forward() {
+        use JoinRequestStatus::{Accepted, NotificationSent, Pending, Rejected};
+
+        let allowed = [
+            (Pending, NotificationSent),
+            (NotificationSent, NotificationSent),
+            (NotificationSent, Accepted),
+            (NotificationSent, Rejected),
+        ];
+        for from in [Pending, NotificationSent, Accepted, Rejected] {
+            for to in [Pending, NotificationSent, Accepted, Rejected] {
+                assert_eq!(
+                    from.can_transition_to(&to),
+                    allowed.contains(&(from.clone(), to.clone())),
+                    "{from} -> {to}"
+                );
+            }
+        }
+    }
+
     #[test]
     fn test_group_join_request_optional_fields() {
         let request = GroupJoinRequest {
@@ -365,4 +807,41 @@ mod tests {
 
         assert!(json.get("notification_sent_at").is_none());
     }
+
+    #[test]
+    fn test_idempotent_id_is_stable_per_invite_key_and_generation() {
+        let id = GroupJoinRequestStorage::idempotent_id("invite", "key", 0);
+
+        assert_eq!(
+            id,
+            GroupJoinRequestStorage::idempotent_id("invite", "key", 0)
+        );
+        assert_ne!(
+            id,
+            GroupJoinRequestStorage::idempotent_id("invite", "other", 0)
+        );
+        assert_ne!(
+            id,
+            GroupJoinRequestStorage::idempotent_id("invit", "ekey", 0)
+        );
+        assert_ne!(
+            id,
+            GroupJoinRequestStorage::idempotent_id("invite", "key", 1)
+        );
+        assert!(uuid::Uuid::parse_str(&id).is_ok());
+    }
+
+    #[test]
+    fn test_stored_attributes_match_attribute_names() {
+        let request = GroupJoinRequest {
+            id: "test-id".to_string(),
+            group_invite_id: "invite-123".to_string(),
+            encrypted_inbox_id: "encrypted-inbox".to_string(),
+            status: JoinRequestStatus::Pending,
+            notification_sent_at: Some(1_234_567_890),
+            created_at: 1_234_567_890,
+        };
+
+        assert_stored_attributes_match::<GroupJoinRequestAttribute, _>(&request);
+    }
 }
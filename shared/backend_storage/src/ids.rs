@@ -0,0 +1,213 @@
+//! Typed identifiers used in storage signatures
+//!
+//! Topics, HMAC keys, encrypted push IDs and nullifiers are all strings, so passing them
+//! positionally makes it easy to swap two arguments. Wrapping them in distinct types turns such
+//! swaps into compile errors:
+//!
+//! ```no_run
+//! # use backend_storage::ids::{HmacKey, TopicId};
+//! # use backend_storage::push_subscription::{PushSubscriptionStorage, SubscriptionKey};
+//! # async fn get(storage: &PushSubscriptionStorage) {
+//! let topic = TopicId::from("topic");
+//! let hmac_key = HmacKey::from("hmac_key");
+//! let subscription = storage.get_one(&SubscriptionKey::new(topic, hmac_key)).await;
+//! # }
+//! ```
+//!
+//! ```compile_fail
+//! # use backend_storage::ids::{HmacKey, TopicId};
+//! # use backend_storage::push_subscription::{PushSubscriptionStorage, SubscriptionKey};
+//! # async fn get(storage: &PushSubscriptionStorage) {
+//! let topic = TopicId::from("topic");
+//! let hmac_key = HmacKey::from("hmac_key");
+//! let subscription = storage.get_one(&SubscriptionKey::new(hmac_key, topic)).await;
+//! # }
+//! ```
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+macro_rules! string_id {
+    ($(#[$meta:meta])* $name:ident) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+        #[serde(transparent)]
+        pub struct $name(String);
+
+        impl $name {
+            /// Returns the identifier as a string slice
+            #[must_use]
+            pub fn as_str(&self) -> &str {
+                &self.0
+            }
+
+            /// Returns the underlying string
+            #[must_use]
+            pub fn into_inner(self) -> String {
+                self.0
+            }
+        }
+
+        impl From<String> for $name {
+            fn from(value: String) -> Self {
+                Self(value)
+            }
+        }
+
+        impl From<&str> for $name {
+            fn from(value: &str) -> Self {
+                Self(value.to_string())
+            }
+        }
+
+        impl AsRef<str> for $name {
+            fn as_ref(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(&self.0)
+            }
+        }
+    };
+}
+
+string_id!(
+    /// XMTP topic (conversation) a push subscription is for
+    TopicId
+);
+
+/// Prefix of XMTP MLS content topics
+const XMTP_TOPIC_PREFIX: &str = "/xmtp/mls/1/";
+/// Suffix of XMTP MLS content topics
+const XMTP_TOPIC_SUFFIX: &str = "/proto";
+
+impl TopicId {
+    /// Canonical form of `topic`, so the variants clients present match the topics of XMTP
+    /// envelopes
+    ///
+    /// Subscriptions are stored and looked up by this form, see `stored_variants` for the ones
+    /// created before topics were normalized. The topic is:
+    /// 1. Trimmed of surrounding whitespace and lowercased, XMTP topic IDs are lowercase hex
+    /// 2. Prefixed with `/xmtp/mls/1/` if it's a bare group (`g-`) or welcome (`w-`) topic
+    /// 3. Stripped of trailing `/` and given a single `/proto` suffix if it's an XMTP MLS topic
+    ///
+    /// Other topics are only trimmed and lowercased. Normalizing is idempotent.
+    #[must_use]
+    pub fn normalize(topic: &str) -> Self {
+        let topic = topic.trim().to_ascii_lowercase();
+        let path = if topic.starts_with("g-") || topic.starts_with("w-") {
+            topic.as_str()
+        } else if let Some(path) = topic.strip_prefix(XMTP_TOPIC_PREFIX) {
+            path
+        } else {
+            return Self(topic);
+        };
+
+        let path = path.trim_end_matches('/');
+        let path = path.strip_suffix(XMTP_TOPIC_SUFFIX).unwrap_or(path);
+        Self(format!("{XMTP_TOPIC_PREFIX}{path}{XMTP_TOPIC_SUFFIX}"))
+    }
+
+    /// Topics the subscriptions to `topic` may be stored under, its canonical form first
+    ///
+    /// Subscriptions created before topics were normalized are stored under the topic exactly as
+    /// the client presented it, so it is included as well if it isn't canonical. Only needed
+    /// until those subscriptions have expired, see `normalize`.
+    #[must_use]
+    pub fn stored_variants(topic: &str) -> Vec<Self> {
+        let normalized = Self::normalize(topic);
+        if normalized.as_str() == topic {
+            vec![normalized]
+        } else {
+            vec![normalized, Self::from(topic)]
+        }
+    }
+}
+
+string_id!(
+    /// HMAC key of a push subscription, rotated every 30-day epoch
+    HmacKey
+);
+
+string_id!(
+    /// Push ID encrypted with the enclave's public key
+    EncryptedPushId
+);
+
+string_id!(
+    /// World ID nullifier identifying a user
+    Nullifier
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serializes_as_plain_string() {
+        let topic = TopicId::from("topic");
+
+        assert_eq!(serde_json::to_string(&topic).unwrap(), "\"topic\"");
+        assert_eq!(serde_json::from_str::<TopicId>("\"topic\"").unwrap(), topic);
+        assert_eq!(topic.to_string(), "topic");
+    }
+
+    #[test]
+    fn test_topic_variants_normalize_to_the_same_topic() {
+        let canonical = "/xmtp/mls/1/g-0123456789abcdef/proto";
+
+        for variant in [
+            canonical,
+            "  /xmtp/mls/1/g-0123456789abcdef/proto\n",
+            "/XMTP/MLS/1/G-0123456789ABCDEF/PROTO",
+            "/xmtp/mls/1/g-0123456789abcdef",
+            "/xmtp/mls/1/g-0123456789abcdef/",
+            "/xmtp/mls/1/g-0123456789abcdef/proto/",
+            "g-0123456789abcdef",
+            "G-0123456789abcdef/proto",
+        ] {
+            assert_eq!(
+                TopicId::normalize(variant).as_str(),
+                canonical,
+                "{variant:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_normalize_is_idempotent() {
+        for topic in ["w-ABCDEF", " /xmtp/mls/1/g-abc ", "Test-Topic", ""] {
+            let normalized = TopicId::normalize(topic);
+
+            assert_eq!(TopicId::normalize(normalized.as_str()), normalized);
+        }
+
+        assert_eq!(
+            TopicId::normalize("w-ABCDEF").as_str(),
+            "/xmtp/mls/1/w-abcdef/proto"
+        );
+        // Topics that aren't XMTP MLS topics keep their shape
+        assert_eq!(TopicId::normalize(" Test-Topic ").as_str(), "test-topic");
+    }
+
+    #[test]
+    fn test_stored_variants_include_the_raw_topic_if_not_canonical() {
+        let canonical = "/xmtp/mls/1/g-0123456789abcdef/proto";
+
+        assert_eq!(
+            TopicId::stored_variants(canonical),
+            vec![TopicId::from(canonical)]
+        );
+        assert_eq!(
+            TopicId::stored_variants("g-0123456789abcdef"),
+            vec![
+                TopicId::from(canonical),
+                TopicId::from("g-0123456789abcdef")
+            ]
+        );
+    }
+}
@@ -1,7 +1,8 @@
 //! Backend storage services for World Chat
 //!
 //! This crate provides storage functionality shared between the backend and enclave-worker,
-//! including push notification subscriptions, auth proofs and SQS queue operations.
+//! including push notification subscriptions, auth proofs, processed envelopes and SQS queue
+//! operations.
 
 #![deny(
     clippy::all,
@@ -12,7 +13,10 @@
 )]
 
 pub mod auth_proof;
+mod dynamodb;
 pub mod group_invite;
 pub mod group_join_request;
+pub mod ids;
+pub mod processed_envelope;
 pub mod push_subscription;
 pub mod queue;
@@ -0,0 +1,30 @@
+//! Error types for processed envelope storage operations
+
+use aws_sdk_dynamodb::error::SdkError;
+use aws_sdk_dynamodb::operation::{
+    put_item::PutItemError, query::QueryError, update_item::UpdateItemError,
+};
+use thiserror::Error;
+
+/// Result type for processed envelope storage operations
+pub type ProcessedEnvelopeStorageResult<T> = Result<T, ProcessedEnvelopeStorageError>;
+
+/// Errors that can occur during processed envelope storage operations
+#[derive(Error, Debug)]
+pub enum ProcessedEnvelopeStorageError {
+    /// Failed to insert processed envelope into Dynamo DB
+    #[error("Failed to insert processed envelope into DynamoDB: {0}")]
+    DynamoDbPutError(#[from] SdkError<PutItemError>),
+
+    /// Failed to query processed envelopes from Dynamo DB
+    #[error("Failed to query processed envelopes from DynamoDB: {0}")]
+    DynamoDbQueryError(#[from] SdkError<QueryError>),
+
+    /// Failed to update processed envelope in Dynamo DB
+    #[error("Failed to update processed envelope in DynamoDB: {0}")]
+    DynamoDbUpdateError(#[from] SdkError<UpdateItemError>),
+
+    /// Serialization error for `serde_dynamo`
+    #[error("Serialization error: {0}")]
+    SerializationError(String),
+}
@@ -0,0 +1,322 @@
+//! Processed envelope storage integration using Dynamo DB
+//!
+//! Processed Envelope Storage keeps short-lived metadata of the XMTP envelopes fanned out by the
+//! notification worker, so recent fan-outs can be replayed after fixing a fan-out bug.
+
+mod error;
+
+use std::sync::Arc;
+
+use aws_sdk_dynamodb::{types::AttributeValue, Client as DynamoDbClient};
+use chrono::Utc;
+use futures::future::try_join_all;
+use serde::{Deserialize, Serialize};
+use serde_dynamo::{from_item, to_item};
+use sha2::{Digest, Sha256};
+use strum::{Display, EnumIter};
+
+pub use error::{ProcessedEnvelopeStorageError, ProcessedEnvelopeStorageResult};
+
+/// How long processed envelopes are kept (in seconds)
+pub const RETENTION_SECONDS: i64 = 7 * 24 * 60 * 60; // 7 days in seconds
+
+/// Envelopes are partitioned by the hour they were created in
+const NANOS_PER_HOUR: u64 = 60 * 60 * 1_000_000_000;
+
+/// Number of partitions each hour is spread over, by the hash of the envelope's topic
+///
+/// A single partition per hour would take every write of that hour, beyond the throughput of a
+/// `DynamoDB` partition under load.
+const SHARDS_PER_HOUR: u8 = 16;
+
+/// Attribute names for processed envelope table
+#[derive(Debug, Clone, Display, EnumIter)]
+#[strum(serialize_all = "snake_case")]
+pub enum ProcessedEnvelopeAttribute {
+    /// Hour the envelope was created in and shard of its topic (Primary Key)
+    Bucket,
+    /// Creation timestamp and message ID (Sort Key), orders envelopes by time within an hour
+    SortKey,
+    /// XMTP content topic
+    Topic,
+    /// Message ID, identifies the envelope within its topic and timestamp
+    MessageId,
+    /// Envelope creation timestamp (in nanoseconds)
+    TimestampNs,
+    /// Whether a notification was queued for the envelope
+    Notified,
+    /// TTL timestamp
+    Ttl,
+}
+
+/// Processed envelope metadata
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ProcessedEnvelope {
+    /// Hour the envelope was created in and shard of its topic, see `bucket` (Primary Key)
+    #[serde(rename = "bucket")]
+    pub bucket: String,
+    /// Zero-padded creation timestamp and message ID, see `sort_key` (Sort Key)
+    #[serde(rename = "sort_key")]
+    pub sort_key: String,
+    /// XMTP content topic
+    #[serde(rename = "topic")]
+    pub topic: String,
+    /// Message ID
+    #[serde(rename = "message_id")]
+    pub message_id: String,
+    /// Envelope creation timestamp (in nanoseconds)
+    #[serde(rename = "timestamp_ns")]
+    pub timestamp_ns: u64,
+    /// Whether a notification was queued for the envelope
+    #[serde(rename = "notified")]
+    pub notified: bool,
+    /// TTL timestamp
+    #[serde(rename = "ttl")]
+    pub ttl: i64,
+}
+
+impl ProcessedEnvelope {
+    /// Creates the metadata of an envelope processed now, expiring after `RETENTION_SECONDS`
+    #[must_use]
+    pub fn new(topic: String, message_id: String, timestamp_ns: u64, notified: bool) -> Self {
+        Self {
+            bucket: bucket(hour_bucket(timestamp_ns), topic_shard(&topic)),
+            sort_key: sort_key(timestamp_ns, &message_id),
+            topic,
+            message_id,
+            timestamp_ns,
+            notified,
+            ttl: Utc::now().timestamp() + RETENTION_SECONDS,
+        }
+    }
+}
+
+/// Hour bucket of a timestamp (in nanoseconds)
+fn hour_bucket(timestamp_ns: u64) -> i64 {
+    i64::try_from(timestamp_ns / NANOS_PER_HOUR).unwrap_or(i64::MAX)
+}
+
+/// Shard of the envelopes of `topic` within an hour
+fn topic_shard(topic: &str) -> u8 {
+    Sha256::digest(topic.as_bytes())[0] % SHARDS_PER_HOUR
+}
+
+/// Partition key of the envelopes of a shard created in an hour
+fn bucket(hour_bucket: i64, shard: u8) -> String {
+    format!("{hour_bucket}#{shard:02}")
+}
+
+/// Sort key of an envelope, zero-padded so keys sort by timestamp
+fn sort_key(timestamp_ns: u64, message_id: &str) -> String {
+    format!("{timestamp_ns:020}#{message_id}")
+}
+
+/// Inclusive sort key bounds of the envelopes created between `from_ns` and `to_ns` (inclusive)
+///
+/// The upper bound is the bare timestamp following `to_ns`, which sorts before every sort key
+/// of that timestamp.
+fn sort_key_bounds(from_ns: u64, to_ns: u64) -> (String, String) {
+    (
+        format!("{from_ns:020}"),
+        format!("{:020}", to_ns.saturating_add(1)),
+    )
+}
+
+/// `DynamoDB` storage for the metadata of processed XMTP envelopes.
+///
+/// Only metadata is stored, envelopes are fetched again from XMTP when replayed. Rows are
+/// partitioned by hour to query time windows, each hour spread over `SHARDS_PER_HOUR` partitions
+/// by topic, and expire after `RETENTION_SECONDS`.
+#[derive(Clone)]
+pub struct ProcessedEnvelopeStorage {
+    dynamodb_client: Arc<DynamoDbClient>,
+    table_name: String,
+}
+
+impl ProcessedEnvelopeStorage {
+    /// Creates a new processed envelope storage client
+    ///
+    /// # Arguments
+    ///
+    /// * `dynamodb_client` - Pre-configured Dynamo DB client
+    /// * `table_name` - Dynamo DB table name for processed envelopes
+    #[must_use]
+    pub const fn new(dynamodb_client: Arc<DynamoDbClient>, table_name: String) -> Self {
+        Self {
+            dynamodb_client,
+            table_name,
+        }
+    }
+
+    /// Records a processed envelope, replacing a previous record of the same envelope
+    ///
+    /// # Errors
+    ///
+    /// Returns `ProcessedEnvelopeStorageError` if the Dynamo DB operation fails
+    pub async fn insert(&self, envelope: &ProcessedEnvelope) -> ProcessedEnvelopeStorageResult<()> {
+        let item = to_item(envelope)
+            .map_err(|e| ProcessedEnvelopeStorageError::SerializationError(e.to_string()))?;
+
+        self.dynamodb_client
+            .put_item()
+            .table_name(&self.table_name)
+            .set_item(Some(item))
+            .send()
+            .await?;
+
+        Ok(())
+    }
+
+    /// Marks that a notification was queued for a processed envelope
+    ///
+    /// # Errors
+    ///
+    /// Returns `ProcessedEnvelopeStorageError` if the Dynamo DB operation fails
+    pub async fn mark_notified(
+        &self,
+        envelope: &ProcessedEnvelope,
+    ) -> ProcessedEnvelopeStorageResult<()> {
+        self.dynamodb_client
+            .update_item()
+            .table_name(&self.table_name)
+            .key(
+                ProcessedEnvelopeAttribute::Bucket.to_string(),
+                AttributeValue::S(envelope.bucket.clone()),
+            )
+            .key(
+                ProcessedEnvelopeAttribute::SortKey.to_string(),
+                AttributeValue::S(envelope.sort_key.clone()),
+            )
+            .update_expression("SET #notified = :notified")
+            .expression_attribute_names(
+                "#notified",
+                ProcessedEnvelopeAttribute::Notified.to_string(),
+            )
+            .expression_attribute_values(":notified", AttributeValue::Bool(true))
+            .send()
+            .await?;
+
+        Ok(())
+    }
+
+    /// Gets the envelopes created between `from_ns` and `to_ns` (inclusive), oldest first
+    ///
+    /// The shards of each hour are queried concurrently.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ProcessedEnvelopeStorageError` if the Dynamo DB query operation fails
+    pub async fn get_window(
+        &self,
+        from_ns: u64,
+        to_ns: u64,
+    ) -> ProcessedEnvelopeStorageResult<Vec<ProcessedEnvelope>> {
+        let (from_key, to_key) = sort_key_bounds(from_ns, to_ns);
+        let mut envelopes = Vec::new();
+
+        for hour in hour_bucket(from_ns)..=hour_bucket(to_ns) {
+            let shards = (0..SHARDS_PER_HOUR)
+                .map(|shard| self.query_bucket(bucket(hour, shard), &from_key, &to_key));
+            for shard_envelopes in try_join_all(shards).await? {
+                envelopes.extend(shard_envelopes);
+            }
+        }
+
+        envelopes.sort_by(|a, b| a.sort_key.cmp(&b.sort_key));
+        Ok(envelopes)
+    }
+
+    /// Gets the envelopes of `bucket` whose sort key is between `from_key` and `to_key`
+    async fn query_bucket(
+        &self,
+        bucket: String,
+        from_key: &str,
+        to_key: &str,
+    ) -> ProcessedEnvelopeStorageResult<Vec<ProcessedEnvelope>> {
+        let mut envelopes = Vec::new();
+        let mut cursor = None;
+        loop {
+            let response = self
+                .dynamodb_client
+                .query()
+                .table_name(&self.table_name)
+                .key_condition_expression("#bucket = :bucket AND #sort_key BETWEEN :from AND :to")
+                .expression_attribute_names(
+                    "#bucket",
+                    ProcessedEnvelopeAttribute::Bucket.to_string(),
+                )
+                .expression_attribute_names(
+                    "#sort_key",
+                    ProcessedEnvelopeAttribute::SortKey.to_string(),
+                )
+                .expression_attribute_values(":bucket", AttributeValue::S(bucket.clone()))
+                .expression_attribute_values(":from", AttributeValue::S(from_key.to_string()))
+                .expression_attribute_values(":to", AttributeValue::S(to_key.to_string()))
+                .set_exclusive_start_key(cursor)
+                .send()
+                .await?;
+
+            for item in response.items() {
+                envelopes.push(from_item(item.clone()).map_err(|e| {
+                    ProcessedEnvelopeStorageError::SerializationError(e.to_string())
+                })?);
+            }
+
+            cursor = response.last_evaluated_key().cloned();
+            if cursor.is_none() {
+                return Ok(envelopes);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dynamodb::assert_stored_attributes_match;
+
+    #[test]
+    fn test_attribute_names_match_stored_fields() {
+        let envelope = ProcessedEnvelope::new(
+            "/xmtp/mls/1/g-topic/proto".to_string(),
+            "message-id".to_string(),
+            1_700_000_000_000_000_000,
+            true,
+        );
+
+        assert_stored_attributes_match::<ProcessedEnvelopeAttribute, _>(&envelope);
+    }
+
+    #[test]
+    fn test_sort_key_bounds_cover_window() {
+        let (from, to) = sort_key_bounds(100, 200);
+
+        for (timestamp_ns, in_window) in [(99, false), (100, true), (200, true), (201, false)] {
+            let key = sort_key(timestamp_ns, "ffff");
+            assert_eq!(
+                from <= key && key <= to,
+                in_window,
+                "timestamp {timestamp_ns}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_topics_are_spread_over_the_shards_of_an_hour() {
+        let shards: std::collections::HashSet<u8> = (0..200)
+            .map(|i| topic_shard(&format!("/xmtp/mls/1/g-topic-{i}/proto")))
+            .collect();
+
+        assert!(shards.iter().all(|shard| *shard < SHARDS_PER_HOUR));
+        assert_eq!(shards.len(), usize::from(SHARDS_PER_HOUR));
+        assert_eq!(topic_shard("topic"), topic_shard("topic"));
+        assert_eq!(bucket(12, 3), "12#03");
+    }
+
+    #[test]
+    fn test_hour_bucket() {
+        assert_eq!(hour_bucket(0), 0);
+        assert_eq!(hour_bucket(NANOS_PER_HOUR - 1), 0);
+        assert_eq!(hour_bucket(NANOS_PER_HOUR), 1);
+    }
+}
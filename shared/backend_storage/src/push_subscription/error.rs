@@ -8,6 +8,8 @@ use aws_sdk_dynamodb::operation::{
 };
 use thiserror::Error;
 
+use super::InvalidSubscriptionKey;
+
 /// Result type for push notification storage operations
 pub type PushSubscriptionStorageResult<T> = Result<T, PushSubscriptionStorageError>;
 
@@ -38,6 +40,10 @@ pub enum PushSubscriptionStorageError {
     #[error("Failed to batch write items to DynamoDB: {0}")]
     DynamoDbBatchWriteError(#[from] SdkError<BatchWriteItemError>),
 
+    /// Dynamo DB left part of a batch write unprocessed after all retries
+    #[error("{0} batch write items were left unprocessed by DynamoDB")]
+    UnprocessedItems(usize),
+
     /// Failed to batch get items from Dynamo DB
     #[error("Failed to batch get items from DynamoDB: {0}")]
     DynamoDbBatchGetError(#[from] SdkError<BatchGetItemError>),
@@ -46,10 +52,18 @@ pub enum PushSubscriptionStorageError {
     #[error("Failed to parse subscription: {0:?}")]
     ParseSubscriptionError(String),
 
+    /// The subscription key is malformed, rejected before reaching Dynamo DB
+    #[error("Invalid subscription key: {0}")]
+    InvalidKey(#[from] InvalidSubscriptionKey),
+
     /// Push subscription already exists
     #[error("Push subscription already exists")]
     PushSubscriptionExists,
 
+    /// Push subscription doesn't exist
+    #[error("Push subscription not found")]
+    NotFound,
+
     /// Serialization error for `serde_dynamo`
     #[error("Serialization error: {0}")]
     SerializationError(String),
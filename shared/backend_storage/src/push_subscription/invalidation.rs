@@ -0,0 +1,81 @@
+//! Subscription change events over Redis pub/sub
+//!
+//! Writes to the push subscription table publish the affected `topic`, so caches holding
+//! subscriptions (e.g. in other worker pods) can drop their entry for that topic.
+
+use std::time::Duration;
+
+use futures::{future, Stream, StreamExt};
+use redis::{aio::ConnectionManager, AsyncCommands, Client, RedisResult};
+use tokio::time::timeout;
+
+/// Default Redis channel for subscription invalidation events
+pub const SUBSCRIPTION_INVALIDATION_CHANNEL: &str = "push_subscription_invalidation";
+
+const PUBLISH_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Publishes the topic of changed subscriptions on a Redis channel
+#[derive(Clone)]
+pub struct SubscriptionInvalidationPublisher {
+    connection_manager: ConnectionManager,
+    channel: String,
+}
+
+impl SubscriptionInvalidationPublisher {
+    /// Creates a new publisher
+    ///
+    /// # Arguments
+    ///
+    /// * `connection_manager` - Redis connection manager used to publish
+    /// * `channel` - Redis channel to publish on
+    #[must_use]
+    pub const fn new(connection_manager: ConnectionManager, channel: String) -> Self {
+        Self {
+            connection_manager,
+            channel,
+        }
+    }
+
+    /// Publishes an invalidation event for `topic`
+    ///
+    /// Best-effort: failures are logged and never surfaced,
+    /// as the subscription write already succeeded.
+    pub async fn publish(&self, topic: &str) {
+        let mut conn = self.connection_manager.clone();
+        match timeout(
+            PUBLISH_TIMEOUT,
+            conn.publish::<_, _, ()>(&self.channel, topic),
+        )
+        .await
+        {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => {
+                tracing::warn!("Failed to publish subscription invalidation for {topic}: {e}");
+            }
+            Err(_) => {
+                tracing::warn!("Timed out publishing subscription invalidation for {topic}");
+            }
+        }
+    }
+}
+
+/// Subscribes to invalidation events on `channel`
+///
+/// # Returns
+///
+/// A stream of topics whose subscriptions changed. Caches should drop their entry for each topic.
+///
+/// # Errors
+///
+/// Returns a `RedisError` if connecting or subscribing to the channel fails
+pub async fn subscribe_invalidations(
+    client: &Client,
+    channel: &str,
+) -> RedisResult<impl Stream<Item = String>> {
+    let mut pubsub = client.get_async_pubsub().await?;
+    pubsub.subscribe(channel).await?;
+
+    Ok(pubsub
+        .into_on_message()
+        .filter_map(|message| future::ready(message.get_payload::<String>().ok())))
+}
@@ -0,0 +1,103 @@
+//! Primary key of a push subscription
+//!
+//! Storage methods addressing a single subscription take a `SubscriptionKey` and validate it
+//! before reaching Dynamo DB, so a malformed key fails fast instead of missing every row, or
+//! creating a phantom row on update.
+
+use thiserror::Error;
+
+use crate::ids::{HmacKey, TopicId};
+
+/// Length of a hex encoded HMAC key (42 bytes)
+pub const HMAC_KEY_HEX_LENGTH: usize = 84;
+
+/// Reasons a `SubscriptionKey` is malformed
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidSubscriptionKey {
+    /// The topic is empty
+    #[error("Subscription topic is empty")]
+    EmptyTopic,
+
+    /// The HMAC key isn't `HMAC_KEY_HEX_LENGTH` hex characters
+    #[error("Subscription HMAC key must be {HMAC_KEY_HEX_LENGTH} hex characters")]
+    MalformedHmacKey,
+}
+
+/// Primary key of a push subscription: its topic (partition key) and HMAC key (sort key)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SubscriptionKey {
+    /// Topic of the subscription
+    pub topic: TopicId,
+    /// HMAC key of the subscription
+    pub hmac_key: HmacKey,
+}
+
+impl SubscriptionKey {
+    /// Creates a key, see `validate` for the expected format
+    #[must_use]
+    pub fn new(topic: impl Into<TopicId>, hmac_key: impl Into<HmacKey>) -> Self {
+        Self {
+            topic: topic.into(),
+            hmac_key: hmac_key.into(),
+        }
+    }
+
+    /// Checks the topic isn't empty and the HMAC key is `HMAC_KEY_HEX_LENGTH` hex characters
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidSubscriptionKey` describing the first malformed part of the key
+    pub fn validate(&self) -> Result<(), InvalidSubscriptionKey> {
+        if self.topic.as_str().is_empty() {
+            return Err(InvalidSubscriptionKey::EmptyTopic);
+        }
+        if !is_well_formed_hmac_key(self.hmac_key.as_str()) {
+            return Err(InvalidSubscriptionKey::MalformedHmacKey);
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether `hmac_key` is `HMAC_KEY_HEX_LENGTH` hex characters
+#[must_use]
+pub fn is_well_formed_hmac_key(hmac_key: &str) -> bool {
+    hmac_key.len() == HMAC_KEY_HEX_LENGTH && hmac_key.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HMAC_KEY: &str = "0123456789abcdef0123456789ABCDEF0123456789abcdef0123456789abcdef\
+                            0123456789abcdef0123";
+
+    #[test]
+    fn test_valid_key() {
+        assert_eq!(HMAC_KEY.len(), HMAC_KEY_HEX_LENGTH);
+        assert_eq!(SubscriptionKey::new("topic", HMAC_KEY).validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_empty_topic() {
+        assert_eq!(
+            SubscriptionKey::new("", HMAC_KEY).validate(),
+            Err(InvalidSubscriptionKey::EmptyTopic)
+        );
+    }
+
+    #[test]
+    fn test_malformed_hmac_keys() {
+        let too_short = &HMAC_KEY[1..];
+        let too_long = format!("{HMAC_KEY}0");
+        let not_hex = format!("{}g", &HMAC_KEY[1..]);
+
+        for hmac_key in ["", too_short, &too_long, &not_hex] {
+            assert_eq!(
+                SubscriptionKey::new("topic", hmac_key).validate(),
+                Err(InvalidSubscriptionKey::MalformedHmacKey),
+                "{hmac_key}"
+            );
+        }
+    }
+}
@@ -1,27 +1,61 @@
 //! Push notification storage integration using Dynamo DB
 //!
 //! Push Notification Storage holds subscription to topics, used by the backend and enclave worker
+//!
+//! This is the only subscription schema, every service goes through this module. The table is
+//! keyed by `topic` (partition key) and `hmac_key` (sort key), so a topic fans out with a single
+//! query and a subscriber can hold one subscription per topic. Subscriptions of a push ID are
+//! found through the `encrypted_push_id` GSI.
 
 mod error;
+mod invalidation;
+mod key;
 
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{hash_map::Entry, HashMap, HashSet},
+    ops::RangeInclusive,
+    sync::Arc,
+};
 
 use aws_sdk_dynamodb::{
-    error::SdkError,
-    types::{AttributeValue, DeleteRequest, KeysAndAttributes, Select, WriteRequest},
+    types::{
+        AttributeValue, DeleteRequest, KeysAndAttributes, PutRequest, ReturnValue, Select,
+        WriteRequest,
+    },
     Client as DynamoDbClient,
 };
+use futures::{stream, Stream, StreamExt, TryStreamExt};
+use metrics::counter;
 use rand::Rng;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, Serializer};
 
 pub use error::{PushSubscriptionStorageError, PushSubscriptionStorageResult};
-use strum::Display;
+pub use invalidation::{
+    subscribe_invalidations, SubscriptionInvalidationPublisher, SUBSCRIPTION_INVALIDATION_CHANNEL,
+};
+pub use key::{
+    is_well_formed_hmac_key, InvalidSubscriptionKey, SubscriptionKey, HMAC_KEY_HEX_LENGTH,
+};
+use strum::{Display, EnumIter};
+
+use crate::{
+    dynamodb::{batch_write_all, is_conditional_check_failed},
+    ids::{EncryptedPushId, TopicId},
+};
+
+/// Default range of the random offset (in seconds) added to subscription TTLs: 1 minute to 24 hours
+///
+/// Spreads out TTL deletions of subscriptions created at the same time.
+pub const DEFAULT_TTL_OFFSET_SECS: RangeInclusive<i64> = 60..=86_400;
 
-/// A subscription key consisting of (topic, `hmac_key`)
-pub type SubscriptionKey<'a> = (&'a str, &'a str);
+/// Maximum number of subscriptions written at once by `insert_many`
+const INSERT_MANY_CONCURRENCY: usize = 16;
+
+/// Counter of subscriptions skipped by `get_all_by_topic` because their TTL already passed
+pub const EXPIRED_SUBSCRIPTIONS_SKIPPED_METRIC: &str = "push_subscription_expired_skipped";
 
 /// Attribute names for push subscription table
-#[derive(Debug, Clone, Display)]
+#[derive(Debug, Clone, Display, EnumIter)]
 #[strum(serialize_all = "snake_case")]
 pub enum PushSubscriptionAttribute {
     /// Topic (Primary Key)
@@ -43,28 +77,100 @@ pub enum PushSubscriptionAttribute {
     EncryptedPushId,
     /// Optional set of deletion request strings
     DeletionRequest,
+    /// Optional ID of the app installation (device) that created the subscription
+    InstallationId,
+}
+
+/// Outcome of writing a single subscription with `insert_many`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubscriptionWriteOutcome {
+    /// No subscription existed for the `topic` and `hmac_key`
+    Created,
+    /// An existing subscription was overwritten and its TTL refreshed
+    Refreshed,
+}
+
+/// Summary of a `batch_insert`
+#[derive(Debug, Clone, Default)]
+pub struct BatchInsertSummary {
+    /// Number of subscriptions written
+    pub written: usize,
+    /// Subscriptions Dynamo DB left unprocessed after all retries, as given so they can be
+    /// retried
+    pub unprocessed: Vec<PushSubscription>,
+}
+
+/// Primary key of a subscription, as projected in the `encrypted_push_id` GSI
+#[derive(Deserialize)]
+struct PushSubscriptionKey {
+    #[serde(rename = "topic")]
+    topic: String,
+    #[serde(rename = "hmac_key")]
+    hmac_key: String,
 }
 
 /// Push subscription data structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PushSubscription {
     /// Topic name (Primary Key)
+    #[serde(rename = "topic")]
     pub topic: String,
     /// HMAC key (Sort Key)
+    #[serde(rename = "hmac_key")]
     pub hmac_key: String,
     /// TTL timestamp (Unix timestamp in seconds, rounded to minute)
+    #[serde(rename = "ttl")]
     pub ttl: i64,
     /// Encrypted Push ID
+    #[serde(rename = "encrypted_push_id")]
     pub encrypted_push_id: String,
     /// Optional set of deletion request strings
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(
+        rename = "deletion_request",
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_deletion_request"
+    )]
     pub deletion_request: Option<std::collections::HashSet<String>>,
+    /// Optional ID of the app installation (device) that created the subscription
+    ///
+    /// Subscriptions stored before installation IDs were introduced have none.
+    /// It is supplied by the client and unverified.
+    #[serde(
+        rename = "installation_id",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub installation_id: Option<String>,
+}
+
+/// Serializes the deletion requests as a Dynamo DB string set rather than a list, so `ADD` can
+/// append to them
+#[allow(clippy::ref_option)]
+fn serialize_deletion_request<S: Serializer>(
+    deletion_request: &Option<HashSet<String>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match deletion_request {
+        Some(deletion_request) => serde_dynamo::string_set::serialize(deletion_request, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+impl PushSubscription {
+    /// The primary key of the subscription
+    #[must_use]
+    pub fn key(&self) -> SubscriptionKey {
+        SubscriptionKey::new(self.topic.as_str(), self.hmac_key.as_str())
+    }
 }
 
 /// Push notification storage client for Dynamo DB operations
 pub struct PushSubscriptionStorage {
     dynamodb_client: Arc<DynamoDbClient>,
     table_name: String,
+    push_id_index_name: String,
+    invalidation_publisher: Option<SubscriptionInvalidationPublisher>,
+    ttl_offset_secs: Option<RangeInclusive<i64>>,
 }
 
 impl PushSubscriptionStorage {
@@ -74,15 +180,79 @@ impl PushSubscriptionStorage {
     ///
     /// * `dynamodb_client` - Pre-configured Dynamo DB client
     /// * `table_name` - Dynamo DB table name for push subscriptions
+    /// * `push_id_index_name` - Name of the GSI for `encrypted_push_id` queries
     #[must_use]
-    pub const fn new(dynamodb_client: Arc<DynamoDbClient>, table_name: String) -> Self {
+    pub const fn new(
+        dynamodb_client: Arc<DynamoDbClient>,
+        table_name: String,
+        push_id_index_name: String,
+    ) -> Self {
         Self {
             dynamodb_client,
             table_name,
+            push_id_index_name,
+            invalidation_publisher: None,
+            ttl_offset_secs: Some(DEFAULT_TTL_OFFSET_SECS),
         }
     }
 
-    /// Gets all push subscriptions for a specific topic
+    /// Creates a new push notification storage client that stores TTLs as given,
+    /// without adding a random offset
+    ///
+    /// # Arguments
+    ///
+    /// * `dynamodb_client` - Pre-configured Dynamo DB client
+    /// * `table_name` - Dynamo DB table name for push subscriptions
+    /// * `push_id_index_name` - Name of the GSI for `encrypted_push_id` queries
+    #[must_use]
+    pub const fn new_without_ttl_offset(
+        dynamodb_client: Arc<DynamoDbClient>,
+        table_name: String,
+        push_id_index_name: String,
+    ) -> Self {
+        Self {
+            dynamodb_client,
+            table_name,
+            push_id_index_name,
+            invalidation_publisher: None,
+            ttl_offset_secs: None,
+        }
+    }
+
+    /// Sets the range of the random offset (in seconds) added to subscription TTLs
+    ///
+    /// # Arguments
+    ///
+    /// * `ttl_offset_secs` - Inclusive range the offset is uniformly sampled from
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is empty
+    #[must_use]
+    pub fn with_ttl_offset_range(mut self, ttl_offset_secs: RangeInclusive<i64>) -> Self {
+        assert!(!ttl_offset_secs.is_empty(), "TTL offset range is empty");
+        self.ttl_offset_secs = Some(ttl_offset_secs);
+        self
+    }
+
+    /// Publishes an invalidation event for the topic on every subscription write
+    ///
+    /// # Arguments
+    ///
+    /// * `publisher` - Publisher used to notify caches of changed topics
+    #[must_use]
+    pub fn with_invalidation_publisher(
+        mut self,
+        publisher: SubscriptionInvalidationPublisher,
+    ) -> Self {
+        self.invalidation_publisher = Some(publisher);
+        self
+    }
+
+    /// Gets all live push subscriptions for a specific topic
+    ///
+    /// `DynamoDB` TTL deletion lags behind, so subscriptions whose TTL already passed are
+    /// filtered out and counted in `EXPIRED_SUBSCRIPTIONS_SKIPPED_METRIC`.
     ///
     /// # Arguments
     ///
@@ -90,27 +260,94 @@ impl PushSubscriptionStorage {
     ///
     /// # Returns
     ///
-    /// A vector of push subscriptions for the given topic
+    /// A vector of unexpired push subscriptions for the given topic, across all pages. Use
+    /// `stream_by_topic` for large topics.
     ///
     /// # Errors
     ///
     /// Returns `PushSubscriptionStorageError` if the Dynamo DB operation fails
     pub async fn get_all_by_topic(
         &self,
-        topic: &str,
+        topic: &TopicId,
     ) -> PushSubscriptionStorageResult<Vec<PushSubscription>> {
+        self.stream_by_topic(topic, None).try_collect().await
+    }
+
+    /// Streams all unexpired push subscriptions for a topic, page by page
+    ///
+    /// Pages are only fetched once the previous one was consumed, so a large topic is never
+    /// held in memory at once. Expired subscriptions are skipped as in `get_all_by_topic`.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The topic to stream subscriptions for
+    /// * `page_size` - Maximum number of items evaluated per page, `None` for `DynamoDB`'s page
+    ///   limit
+    ///
+    /// # Errors
+    ///
+    /// Yields `PushSubscriptionStorageError` if the Dynamo DB operation fails, ending the stream
+    pub fn stream_by_topic<'a>(
+        &'a self,
+        topic: &'a TopicId,
+        page_size: Option<i32>,
+    ) -> impl Stream<Item = PushSubscriptionStorageResult<PushSubscription>> + 'a {
+        // `None` once the last page was fetched, the inner `None` is the first page
+        stream::try_unfold(Some(None), move |exclusive_start_key| async move {
+            let Some(exclusive_start_key) = exclusive_start_key else {
+                return Ok::<_, PushSubscriptionStorageError>(None);
+            };
+            let (page, last_evaluated_key) = self
+                .query_topic_page(topic, page_size, exclusive_start_key)
+                .await?;
+
+            Ok(Some((
+                stream::iter(page.into_iter().map(Ok)),
+                last_evaluated_key.map(Some),
+            )))
+        })
+        .try_flatten()
+    }
+
+    /// Queries a single page of unexpired subscriptions for a topic
+    ///
+    /// Returns the page and the key to continue from, `None` after the last page.
+    async fn query_topic_page(
+        &self,
+        topic: &TopicId,
+        page_size: Option<i32>,
+        exclusive_start_key: Option<HashMap<String, AttributeValue>>,
+    ) -> PushSubscriptionStorageResult<(
+        Vec<PushSubscription>,
+        Option<HashMap<String, AttributeValue>>,
+    )> {
         let response = self
             .dynamodb_client
             .query()
             .table_name(&self.table_name)
             .key_condition_expression("#topic = :topic")
+            .filter_expression("#ttl >= :now")
             .expression_attribute_names("#topic", PushSubscriptionAttribute::Topic.to_string())
+            .expression_attribute_names("#ttl", PushSubscriptionAttribute::Ttl.to_string())
             .expression_attribute_values(":topic", AttributeValue::S(topic.to_string()))
+            .expression_attribute_values(
+                ":now",
+                AttributeValue::N(chrono::Utc::now().timestamp().to_string()),
+            )
             .select(Select::AllAttributes)
+            .set_limit(page_size)
+            .set_exclusive_start_key(exclusive_start_key)
             .send()
             .await?;
 
-        response
+        let expired =
+            u64::try_from(response.scanned_count() - response.count()).unwrap_or_default();
+        if expired > 0 {
+            tracing::debug!("Skipped {expired} expired subscriptions for topic {topic}");
+            counter!(EXPIRED_SUBSCRIPTIONS_SKIPPED_METRIC).increment(expired);
+        }
+
+        let page = response
             .items()
             .iter()
             .map(|item| {
@@ -118,40 +355,86 @@ impl PushSubscriptionStorage {
                     PushSubscriptionStorageError::ParseSubscriptionError(e.to_string())
                 })
             })
-            .collect()
+            .collect::<PushSubscriptionStorageResult<_>>()?;
+
+        Ok((page, response.last_evaluated_key().cloned()))
     }
 
-    /// Gets a single push subscription by topic and HMAC key
+    /// Counts the unexpired subscriptions for a topic, without reading them
+    ///
+    /// Follows pagination, as Dynamo DB counts at most 1 MB of subscriptions per query.
     ///
     /// # Arguments
     ///
-    /// * `topic` - The topic of the subscription
-    /// * `hmac_key` - The HMAC key identifier
+    /// * `topic` - The topic to count subscriptions for
     ///
     /// # Returns
     ///
-    /// An optional push subscription if found
+    /// The number of subscriptions, 0 for a topic without any
     ///
     /// # Errors
     ///
     /// Returns `PushSubscriptionStorageError` if the Dynamo DB operation fails
+    pub async fn count_by_topic(&self, topic: &TopicId) -> PushSubscriptionStorageResult<u64> {
+        // Dynamo DB rejects an empty key value, and no subscription can have one
+        if topic.as_str().is_empty() {
+            return Ok(0);
+        }
+
+        let now = chrono::Utc::now().timestamp();
+        let mut count = 0;
+        let mut exclusive_start_key = None;
+
+        loop {
+            let response = self
+                .dynamodb_client
+                .query()
+                .table_name(&self.table_name)
+                .key_condition_expression("#topic = :topic")
+                .filter_expression("#ttl >= :now")
+                .expression_attribute_names("#topic", PushSubscriptionAttribute::Topic.to_string())
+                .expression_attribute_names("#ttl", PushSubscriptionAttribute::Ttl.to_string())
+                .expression_attribute_values(":topic", AttributeValue::S(topic.to_string()))
+                .expression_attribute_values(":now", AttributeValue::N(now.to_string()))
+                .select(Select::Count)
+                .set_exclusive_start_key(exclusive_start_key)
+                .send()
+                .await?;
+
+            count += u64::try_from(response.count()).unwrap_or_default();
+
+            exclusive_start_key = response.last_evaluated_key().cloned();
+            if exclusive_start_key.is_none() {
+                return Ok(count);
+            }
+        }
+    }
+
+    /// Gets a push subscription by its key
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The topic and HMAC key of the subscription
+    ///
+    /// # Returns
+    ///
+    /// An optional push subscription if found
+    ///
+    /// # Errors
+    ///
+    /// Returns `PushSubscriptionStorageError::InvalidKey` if the key is malformed, or other
+    /// `PushSubscriptionStorageError` if the Dynamo DB operation fails
     pub async fn get_one(
         &self,
-        topic: &str,
-        hmac_key: &str,
+        key: &SubscriptionKey,
     ) -> PushSubscriptionStorageResult<Option<PushSubscription>> {
+        key.validate()?;
+
         let response = self
             .dynamodb_client
             .get_item()
             .table_name(&self.table_name)
-            .key(
-                PushSubscriptionAttribute::Topic.to_string(),
-                AttributeValue::S(topic.to_string()),
-            )
-            .key(
-                PushSubscriptionAttribute::HmacKey.to_string(),
-                AttributeValue::S(hmac_key.to_string()),
-            )
+            .set_key(Some(Self::key_item(key)))
             .send()
             .await?;
 
@@ -165,11 +448,11 @@ impl PushSubscriptionStorage {
             .transpose()
     }
 
-    /// Batch get multiple push subscriptions by their (topic, `hmac_key`) pairs
+    /// Batch get multiple push subscriptions by their keys
     ///
     /// # Arguments
     ///
-    /// * `subscription_keys` - A slice of `(topic, hmac_key)` tuples to fetch
+    /// * `subscription_keys` - The keys of the subscriptions to fetch
     ///
     /// # Returns
     ///
@@ -178,34 +461,25 @@ impl PushSubscriptionStorage {
     ///
     /// # Errors
     ///
-    /// Returns `PushSubscriptionStorageError` if the Dynamo DB operation fails
+    /// Returns `PushSubscriptionStorageError::InvalidKey` if any key is malformed, or other
+    /// `PushSubscriptionStorageError` if the Dynamo DB operation fails
     pub async fn batch_get(
         &self,
-        subscription_keys: &[SubscriptionKey<'_>],
+        subscription_keys: &[SubscriptionKey],
     ) -> PushSubscriptionStorageResult<Vec<PushSubscription>> {
         if subscription_keys.is_empty() {
             return Ok(Vec::new());
         }
+        subscription_keys
+            .iter()
+            .try_for_each(SubscriptionKey::validate)?;
 
         let mut all_subscriptions = Vec::with_capacity(subscription_keys.len());
 
         // DynamoDB batch_get_item has a limit of 25 keys per request
         for chunk in subscription_keys.chunks(25) {
-            let dynamo_keys: Vec<HashMap<String, AttributeValue>> = chunk
-                .iter()
-                .map(|(topic, hmac_key)| {
-                    HashMap::from([
-                        (
-                            PushSubscriptionAttribute::Topic.to_string(),
-                            AttributeValue::S((*topic).to_string()),
-                        ),
-                        (
-                            PushSubscriptionAttribute::HmacKey.to_string(),
-                            AttributeValue::S((*hmac_key).to_string()),
-                        ),
-                    ])
-                })
-                .collect();
+            let dynamo_keys: Vec<HashMap<String, AttributeValue>> =
+                chunk.iter().map(Self::key_item).collect();
 
             let keys_and_attributes = KeysAndAttributes::builder()
                 .set_keys(Some(dynamo_keys))
@@ -258,16 +532,9 @@ impl PushSubscriptionStorage {
         &self,
         subscription: &PushSubscription,
     ) -> PushSubscriptionStorageResult<()> {
-        // Add random offset: 1 minute to 24 hours (uniform distribution)
-        let random_offset = {
-            let mut rng = rand::thread_rng();
-            rng.gen_range(60..=86400) // 60 seconds to 24 hours
-        };
-        let distributed_ttl = subscription.ttl + random_offset;
-
         // Create a modified subscription with distributed TTL
         let subscription_to_store = PushSubscription {
-            ttl: distributed_ttl,
+            ttl: self.distributed_ttl(subscription.ttl),
             ..subscription.clone()
         };
 
@@ -276,8 +543,7 @@ impl PushSubscriptionStorage {
             .map_err(|e| PushSubscriptionStorageError::SerializationError(e.to_string()))?;
 
         // Create only if *no item with this PK+SK* exists.
-        self
-            .dynamodb_client
+        self.dynamodb_client
             .put_item()
             .table_name(&self.table_name)
             .set_item(Some(item))
@@ -287,16 +553,15 @@ impl PushSubscriptionStorage {
             .send()
             .await
             .map_err(|err| {
-                if matches!(
-                    err,
-                    SdkError::ServiceError(ref svc) if svc.err().is_conditional_check_failed_exception()
-                ) {
+                if is_conditional_check_failed(&err) {
                     PushSubscriptionStorageError::PushSubscriptionExists
                 } else {
                     err.into()
                 }
             })?;
 
+        self.publish_invalidation(&subscription.topic).await;
+
         Ok(())
     }
 
@@ -316,16 +581,140 @@ impl PushSubscriptionStorage {
         &self,
         subscription: &PushSubscription,
     ) -> PushSubscriptionStorageResult<()> {
-        // Add random offset: 1 minute to 24 hours (uniform distribution)
-        let random_offset = {
-            let mut rng = rand::thread_rng();
-            rng.gen_range(60..=86400) // 60 seconds to 24 hours
-        };
-        let distributed_ttl = subscription.ttl + random_offset;
+        self.upsert_with_outcome(subscription).await.map(|_| ())
+    }
+
+    /// Upserts multiple push subscriptions concurrently
+    ///
+    /// Each subscription is written independently, so a failure does not affect the others. Up
+    /// to `INSERT_MANY_CONCURRENCY` subscriptions are written at once.
+    ///
+    /// # Arguments
+    ///
+    /// * `subscriptions` - The push subscriptions to insert or update
+    ///
+    /// # Returns
+    ///
+    /// The outcome of each write, in the same order as `subscriptions`
+    pub async fn insert_many(
+        &self,
+        subscriptions: &[PushSubscription],
+    ) -> Vec<PushSubscriptionStorageResult<SubscriptionWriteOutcome>> {
+        let mut outcomes: Vec<_> = stream::iter(subscriptions.iter().enumerate())
+            .map(|(position, subscription)| async move {
+                (position, self.upsert_with_outcome(subscription).await)
+            })
+            .buffer_unordered(INSERT_MANY_CONCURRENCY)
+            .collect()
+            .await;
+
+        outcomes.sort_unstable_by_key(|(position, _)| *position);
+        outcomes.into_iter().map(|(_, outcome)| outcome).collect()
+    }
+
+    /// Upserts multiple push subscriptions with `batch_write_item`, 25 per request
+    ///
+    /// Far fewer round trips than `insert_many`, but without reporting whether each subscription
+    /// already existed. Like `upsert`, existing subscriptions are overwritten and each TTL gets
+    /// its own random offset. Subscriptions sharing a key are written once, the last one wins.
+    ///
+    /// Each request of 25 subscriptions is applied independently, not atomically.
+    ///
+    /// # Arguments
+    ///
+    /// * `subscriptions` - The push subscriptions to insert or update
+    ///
+    /// # Returns
+    ///
+    /// How many subscriptions were written and which ones were left unprocessed
+    ///
+    /// # Errors
+    ///
+    /// Returns `PushSubscriptionStorageError::InvalidKey` if any key is malformed, or other
+    /// `PushSubscriptionStorageError` if a Dynamo DB batch write fails, after the previous
+    /// requests were written and their topics invalidated
+    pub async fn batch_insert(
+        &self,
+        subscriptions: &[PushSubscription],
+    ) -> PushSubscriptionStorageResult<BatchInsertSummary> {
+        // Dynamo DB rejects a batch writing the same key twice
+        let mut unique_subscriptions: Vec<&PushSubscription> = Vec::new();
+        let mut positions = HashMap::new();
+        for subscription in subscriptions {
+            let key = subscription.key();
+            key.validate()?;
+            match positions.entry(key) {
+                Entry::Occupied(position) => unique_subscriptions[*position.get()] = subscription,
+                Entry::Vacant(position) => {
+                    position.insert(unique_subscriptions.len());
+                    unique_subscriptions.push(subscription);
+                }
+            }
+        }
+
+        let mut summary = BatchInsertSummary::default();
+        let mut result = Ok(());
+        let mut topics = HashSet::new();
+
+        // DynamoDB batch write has a limit of 25 items per request
+        for chunk in unique_subscriptions.chunks(25) {
+            // A failed request may still have written part of its chunk
+            topics.extend(chunk.iter().map(|subscription| subscription.topic.as_str()));
 
+            let unprocessed_keys = match self.batch_write_chunk(chunk).await {
+                Ok(unprocessed_keys) => unprocessed_keys,
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            };
+            for subscription in chunk {
+                if unprocessed_keys.contains(&subscription.key()) {
+                    summary.unprocessed.push((*subscription).clone());
+                } else {
+                    summary.written += 1;
+                }
+            }
+        }
+
+        // Also on failure, so the caches drop the topics of the previous requests
+        for topic in topics {
+            self.publish_invalidation(topic).await;
+        }
+
+        result.map(|()| summary)
+    }
+
+    /// Writes one `batch_write_item` request of `batch_insert`, returning the unprocessed keys
+    async fn batch_write_chunk(
+        &self,
+        chunk: &[&PushSubscription],
+    ) -> PushSubscriptionStorageResult<HashSet<SubscriptionKey>> {
+        let write_requests = chunk
+            .iter()
+            .map(|subscription| self.build_put_req(subscription))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let unprocessed = batch_write_all(
+            &self.dynamodb_client,
+            HashMap::from([(self.table_name.clone(), write_requests)]),
+        )
+        .await?;
+        Ok(unprocessed
+            .into_values()
+            .flatten()
+            .filter_map(|request| Self::put_req_key(&request))
+            .collect())
+    }
+
+    /// Upserts a push subscription, reporting whether it already existed
+    async fn upsert_with_outcome(
+        &self,
+        subscription: &PushSubscription,
+    ) -> PushSubscriptionStorageResult<SubscriptionWriteOutcome> {
         // Create a modified subscription with distributed TTL
         let subscription_to_store = PushSubscription {
-            ttl: distributed_ttl,
+            ttl: self.distributed_ttl(subscription.ttl),
             ..subscription.clone()
         };
 
@@ -334,41 +723,47 @@ impl PushSubscriptionStorage {
             .map_err(|e| PushSubscriptionStorageError::SerializationError(e.to_string()))?;
 
         // Put without condition - will overwrite if exists
-        self.dynamodb_client
+        let output = self
+            .dynamodb_client
             .put_item()
             .table_name(&self.table_name)
             .set_item(Some(item))
+            .return_values(ReturnValue::AllOld)
             .send()
             .await?;
 
-        Ok(())
+        self.publish_invalidation(&subscription.topic).await;
+
+        // The previous item is only returned if one was overwritten
+        if output.attributes().is_some_and(|old| !old.is_empty()) {
+            Ok(SubscriptionWriteOutcome::Refreshed)
+        } else {
+            Ok(SubscriptionWriteOutcome::Created)
+        }
     }
 
-    /// Deletes a push subscription by topic and HMAC key
+    /// Deletes a push subscription by its key
     ///
     /// # Arguments
     ///
-    /// * `topic` - The topic of the subscription to delete
-    /// * `hmac_key` - The HMAC key of the subscription to delete
+    /// * `key` - The topic and HMAC key of the subscription to delete
     ///
     /// # Errors
     ///
-    /// Returns `PushSubscriptionStorageError` if the Dynamo DB operation fails
-    pub async fn delete(&self, topic: &str, hmac_key: &str) -> PushSubscriptionStorageResult<()> {
+    /// Returns `PushSubscriptionStorageError::InvalidKey` if the key is malformed, or other
+    /// `PushSubscriptionStorageError` if the Dynamo DB operation fails
+    pub async fn delete(&self, key: &SubscriptionKey) -> PushSubscriptionStorageResult<()> {
+        key.validate()?;
+
         self.dynamodb_client
             .delete_item()
             .table_name(&self.table_name)
-            .key(
-                PushSubscriptionAttribute::Topic.to_string(),
-                AttributeValue::S(topic.to_string()),
-            )
-            .key(
-                PushSubscriptionAttribute::HmacKey.to_string(),
-                AttributeValue::S(hmac_key.to_string()),
-            )
+            .set_key(Some(Self::key_item(key)))
             .send()
             .await?;
 
+        self.publish_invalidation(key.topic.as_str()).await;
+
         Ok(())
     }
 
@@ -376,30 +771,24 @@ impl PushSubscriptionStorage {
     ///
     /// # Arguments
     ///
-    /// * `topic` - The topic of the subscription
-    /// * `hmac_key` - The HMAC key identifier
+    /// * `key` - The topic and HMAC key of the subscription
     /// * `encrypted_push_id` - The encrypted push ID to add to deletion requests
     ///
     /// # Errors
     ///
-    /// Returns `PushSubscriptionStorageError` if the Dynamo DB operation fails
+    /// Returns `PushSubscriptionStorageError::InvalidKey` if the key is malformed, or other
+    /// `PushSubscriptionStorageError` if the Dynamo DB operation fails
     pub async fn append_delete_request(
         &self,
-        topic: &str,
-        hmac_key: &str,
-        encrypted_push_id: &str,
+        key: &SubscriptionKey,
+        encrypted_push_id: &EncryptedPushId,
     ) -> PushSubscriptionStorageResult<()> {
+        key.validate()?;
+
         self.dynamodb_client
             .update_item()
             .table_name(&self.table_name)
-            .key(
-                PushSubscriptionAttribute::Topic.to_string(),
-                AttributeValue::S(topic.to_string()),
-            )
-            .key(
-                PushSubscriptionAttribute::HmacKey.to_string(),
-                AttributeValue::S(hmac_key.to_string()),
-            )
+            .set_key(Some(Self::key_item(key)))
             .update_expression("ADD #deletion_request :new_request")
             .expression_attribute_names(
                 "#deletion_request",
@@ -415,6 +804,99 @@ impl PushSubscriptionStorage {
         Ok(())
     }
 
+    /// Clears the deletion request set of a subscription, once its requests were reconciled
+    ///
+    /// Succeeds if the subscription has no deletion requests.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The topic and HMAC key of the subscription
+    ///
+    /// # Errors
+    ///
+    /// Returns `PushSubscriptionStorageError::NotFound` if the subscription doesn't exist,
+    /// `PushSubscriptionStorageError::InvalidKey` if the key is malformed, or other
+    /// `PushSubscriptionStorageError` if the Dynamo DB operation fails
+    pub async fn clear_deletion_requests(
+        &self,
+        key: &SubscriptionKey,
+    ) -> PushSubscriptionStorageResult<()> {
+        key.validate()?;
+
+        self.dynamodb_client
+            .update_item()
+            .table_name(&self.table_name)
+            .set_key(Some(Self::key_item(key)))
+            .update_expression("REMOVE #deletion_request")
+            .condition_expression("attribute_exists(#pk) AND attribute_exists(#sk)")
+            .expression_attribute_names(
+                "#deletion_request",
+                PushSubscriptionAttribute::DeletionRequest.to_string(),
+            )
+            .expression_attribute_names("#pk", PushSubscriptionAttribute::Topic.to_string())
+            .expression_attribute_names("#sk", PushSubscriptionAttribute::HmacKey.to_string())
+            .send()
+            .await
+            .map_err(|err| {
+                if is_conditional_check_failed(&err) {
+                    PushSubscriptionStorageError::NotFound
+                } else {
+                    err.into()
+                }
+            })?;
+
+        Ok(())
+    }
+
+    /// Refreshes the TTL of an existing subscription, keeping an active subscriber's
+    /// subscription alive without re-inserting it
+    ///
+    /// The random offset is added to `ttl` like on `insert`, so refreshes don't reveal when the
+    /// subscriber was last active.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The topic and HMAC key of the subscription
+    /// * `ttl` - The new TTL (unix timestamp in seconds), before the random offset
+    ///
+    /// # Errors
+    ///
+    /// Returns `PushSubscriptionStorageError::NotFound` if the subscription doesn't exist,
+    /// `PushSubscriptionStorageError::InvalidKey` if the key is malformed, or other
+    /// `PushSubscriptionStorageError` if the Dynamo DB operation fails
+    pub async fn refresh_ttl(
+        &self,
+        key: &SubscriptionKey,
+        ttl: i64,
+    ) -> PushSubscriptionStorageResult<()> {
+        key.validate()?;
+
+        self.dynamodb_client
+            .update_item()
+            .table_name(&self.table_name)
+            .set_key(Some(Self::key_item(key)))
+            .update_expression("SET #ttl = :ttl")
+            .condition_expression("attribute_exists(#pk) AND attribute_exists(#sk)")
+            .expression_attribute_names("#ttl", PushSubscriptionAttribute::Ttl.to_string())
+            .expression_attribute_names("#pk", PushSubscriptionAttribute::Topic.to_string())
+            .expression_attribute_names("#sk", PushSubscriptionAttribute::HmacKey.to_string())
+            .expression_attribute_values(
+                ":ttl",
+                AttributeValue::N(self.distributed_ttl(ttl).to_string()),
+            )
+            .send()
+            .await
+            .map_err(|err| {
+                if is_conditional_check_failed(&err) {
+                    PushSubscriptionStorageError::NotFound
+                } else {
+                    err.into()
+                }
+            })?;
+
+        Ok(())
+    }
+
     /// Gets all push subscriptions for a specific `topic` and `encrypted_push_id`
     ///
     /// # Arguments
@@ -431,8 +913,8 @@ impl PushSubscriptionStorage {
     /// Returns `PushSubscriptionStorageError` if the Dynamo DB operation fails
     pub async fn get_all_by_topic_and_push_id(
         &self,
-        topic: &str,
-        encrypted_push_id: &str,
+        topic: &TopicId,
+        encrypted_push_id: &EncryptedPushId,
     ) -> PushSubscriptionStorageResult<Vec<PushSubscription>> {
         let response = self
             .dynamodb_client
@@ -465,71 +947,220 @@ impl PushSubscriptionStorage {
             .collect()
     }
 
-    /// Batch delete multiple subscriptions across different topics
+    /// Gets the `(topic, hmac_key)` of every subscription for an encrypted push ID
+    ///
+    /// Queries the `encrypted_push_id` GSI, following pagination until all results are read.
     ///
     /// # Arguments
     ///
-    /// * `subscription_keys` - A slice of `(topic, hmac_key)` tuples to delete
+    /// * `encrypted_push_id` - The encrypted push ID to find subscriptions for
+    ///
+    /// # Returns
+    ///
+    /// A vector of `(topic, hmac_key)` tuples
+    ///
+    /// # Errors
+    ///
+    /// Returns `PushSubscriptionStorageError` if the Dynamo DB operation fails
+    pub async fn get_topics_for_push_id(
+        &self,
+        encrypted_push_id: &EncryptedPushId,
+    ) -> PushSubscriptionStorageResult<Vec<(String, String)>> {
+        let mut subscription_keys = Vec::new();
+        let mut exclusive_start_key = None;
+
+        loop {
+            let response = self
+                .dynamodb_client
+                .query()
+                .table_name(&self.table_name)
+                .index_name(&self.push_id_index_name)
+                .key_condition_expression("#encrypted_push_id = :encrypted_push_id")
+                .expression_attribute_names(
+                    "#encrypted_push_id",
+                    PushSubscriptionAttribute::EncryptedPushId.to_string(),
+                )
+                .expression_attribute_values(
+                    ":encrypted_push_id",
+                    AttributeValue::S(encrypted_push_id.to_string()),
+                )
+                .set_exclusive_start_key(exclusive_start_key)
+                .send()
+                .await?;
+
+            for item in response.items() {
+                let key: PushSubscriptionKey =
+                    serde_dynamo::from_item(item.clone()).map_err(|e| {
+                        PushSubscriptionStorageError::ParseSubscriptionError(e.to_string())
+                    })?;
+                subscription_keys.push((key.topic, key.hmac_key));
+            }
+
+            exclusive_start_key = response.last_evaluated_key().cloned();
+            if exclusive_start_key.is_none() {
+                break;
+            }
+        }
+
+        Ok(subscription_keys)
+    }
+
+    /// Deletes every subscription of an encrypted push ID, across all topics
+    ///
+    /// # Arguments
+    ///
+    /// * `encrypted_push_id` - The encrypted push ID to delete subscriptions for
+    ///
+    /// # Returns
+    ///
+    /// The number of deleted subscriptions
     ///
     /// # Errors
     ///
     /// Returns `PushSubscriptionStorageError` if the Dynamo DB operation fails
+    pub async fn delete_all_by_push_id(
+        &self,
+        encrypted_push_id: &EncryptedPushId,
+    ) -> PushSubscriptionStorageResult<usize> {
+        let subscription_keys: Vec<SubscriptionKey> = self
+            .get_topics_for_push_id(encrypted_push_id)
+            .await?
+            .into_iter()
+            .map(|(topic, hmac_key)| SubscriptionKey::new(topic, hmac_key))
+            .collect();
+
+        self.batch_delete_many(&subscription_keys).await?;
+
+        Ok(subscription_keys.len())
+    }
+
+    /// Batch delete multiple subscriptions across different topics
+    ///
+    /// # Arguments
+    ///
+    /// * `subscription_keys` - The keys of the subscriptions to delete
+    ///
+    /// # Errors
+    ///
+    /// Returns `PushSubscriptionStorageError::InvalidKey` if any key is malformed, or other
+    /// `PushSubscriptionStorageError` if the Dynamo DB operation fails
     pub async fn batch_delete_many(
         &self,
-        subscription_keys: &[SubscriptionKey<'_>],
+        subscription_keys: &[SubscriptionKey],
     ) -> PushSubscriptionStorageResult<()> {
         if subscription_keys.is_empty() {
             return Ok(());
         }
+        subscription_keys
+            .iter()
+            .try_for_each(SubscriptionKey::validate)?;
 
         // DynamoDB batch delete has a limit of 25 items per request
         for chunk in subscription_keys.chunks(25) {
             let write_requests = chunk
                 .iter()
-                .map(|(topic, hmac_key)| {
-                    Self::build_delete_req((*topic).to_string(), (*hmac_key).to_string())
-                })
+                .map(Self::build_delete_req)
                 .collect::<Result<Vec<_>, _>>()?;
 
-            self.dynamodb_client
-                .batch_write_item()
-                .request_items(&self.table_name, write_requests)
-                .send()
-                .await?;
+            let unprocessed = batch_write_all(
+                &self.dynamodb_client,
+                HashMap::from([(self.table_name.clone(), write_requests)]),
+            )
+            .await?;
+            if !unprocessed.is_empty() {
+                return Err(PushSubscriptionStorageError::UnprocessedItems(
+                    unprocessed.values().map(Vec::len).sum(),
+                ));
+            }
+        }
+
+        let topics: HashSet<&str> = subscription_keys
+            .iter()
+            .map(|key| key.topic.as_str())
+            .collect();
+        for topic in topics {
+            self.publish_invalidation(topic).await;
         }
 
         Ok(())
     }
 
+    /// Adds the random offset to `ttl`, if enabled
+    fn distributed_ttl(&self, ttl: i64) -> i64 {
+        self.ttl_offset_secs
+            .clone()
+            .map_or(ttl, |range| ttl + rand::thread_rng().gen_range(range))
+    }
+
+    /// Notifies caches that the subscriptions of `topic` changed, if a publisher is configured
+    async fn publish_invalidation(&self, topic: &str) {
+        if let Some(publisher) = &self.invalidation_publisher {
+            publisher.publish(topic).await;
+        }
+    }
+
+    /// Builds the Dynamo DB primary key of a subscription
+    fn key_item(key: &SubscriptionKey) -> HashMap<String, AttributeValue> {
+        HashMap::from([
+            (
+                PushSubscriptionAttribute::Topic.to_string(),
+                AttributeValue::S(key.topic.to_string()),
+            ),
+            (
+                PushSubscriptionAttribute::HmacKey.to_string(),
+                AttributeValue::S(key.hmac_key.to_string()),
+            ),
+        ])
+    }
+
+    /// Builds a put request for a subscription, with its TTL offset
+    fn build_put_req(
+        &self,
+        subscription: &PushSubscription,
+    ) -> PushSubscriptionStorageResult<WriteRequest> {
+        let subscription_to_store = PushSubscription {
+            ttl: self.distributed_ttl(subscription.ttl),
+            ..subscription.clone()
+        };
+        let item = serde_dynamo::to_item(&subscription_to_store)
+            .map_err(|e| PushSubscriptionStorageError::SerializationError(e.to_string()))?;
+
+        Ok(WriteRequest::builder()
+            .put_request(
+                PutRequest::builder()
+                    .set_item(Some(item))
+                    .build()
+                    .map_err(|e| {
+                        PushSubscriptionStorageError::SerializationError(format!(
+                            "Failed to build put request: {e:?}",
+                        ))
+                    })?,
+            )
+            .build())
+    }
+
+    /// Key of the subscription written by a put request, `None` for other requests
+    fn put_req_key(request: &WriteRequest) -> Option<SubscriptionKey> {
+        let item = request.put_request()?.item().clone();
+        let key: PushSubscriptionKey = serde_dynamo::from_item(item).ok()?;
+
+        Some(SubscriptionKey::new(key.topic, key.hmac_key))
+    }
+
     /// Builds a delete request for a subscription
     ///
     /// # Arguments
     ///
-    /// * `topic` - The topic of the subscription
-    /// * `hmac_key` - The HMAC key of the subscription
+    /// * `key` - The topic and HMAC key of the subscription
     ///
     /// # Returns
     ///
     /// A delete request for the subscription
-    fn build_delete_req(
-        topic: String,
-        hmac_key: String,
-    ) -> PushSubscriptionStorageResult<WriteRequest> {
-        let key = HashMap::from([
-            (
-                PushSubscriptionAttribute::Topic.to_string(),
-                AttributeValue::S(topic),
-            ),
-            (
-                PushSubscriptionAttribute::HmacKey.to_string(),
-                AttributeValue::S(hmac_key),
-            ),
-        ]);
-
+    fn build_delete_req(key: &SubscriptionKey) -> PushSubscriptionStorageResult<WriteRequest> {
         Ok(WriteRequest::builder()
             .delete_request(
                 DeleteRequest::builder()
-                    .set_key(Some(key))
+                    .set_key(Some(Self::key_item(key)))
                     .build()
                     .map_err(|e| {
                         PushSubscriptionStorageError::SerializationError(format!(
@@ -540,3 +1171,169 @@ impl PushSubscriptionStorage {
             .build())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dynamodb::assert_stored_attributes_match;
+
+    fn dynamodb_client() -> Arc<DynamoDbClient> {
+        let config = aws_sdk_dynamodb::Config::builder()
+            .behavior_version(aws_sdk_dynamodb::config::BehaviorVersion::latest())
+            .build();
+        Arc::new(DynamoDbClient::from_conf(config))
+    }
+
+    #[test]
+    fn test_default_ttl_offset_range() {
+        let storage = PushSubscriptionStorage::new(
+            dynamodb_client(),
+            "table".to_string(),
+            "index".to_string(),
+        );
+
+        let ttl = 1_700_000_000;
+        for _ in 0..100 {
+            let offset = storage.distributed_ttl(ttl) - ttl;
+            assert!(DEFAULT_TTL_OFFSET_SECS.contains(&offset), "offset {offset}");
+        }
+    }
+
+    #[test]
+    fn test_custom_ttl_offset_range() {
+        let storage = PushSubscriptionStorage::new(
+            dynamodb_client(),
+            "table".to_string(),
+            "index".to_string(),
+        )
+        .with_ttl_offset_range(10..=20);
+
+        let ttl = 1_700_000_000;
+        for _ in 0..100 {
+            let offset = storage.distributed_ttl(ttl) - ttl;
+            assert!((10..=20).contains(&offset), "offset {offset}");
+        }
+    }
+
+    #[test]
+    fn test_disabled_ttl_offset() {
+        let storage = PushSubscriptionStorage::new_without_ttl_offset(
+            dynamodb_client(),
+            "table".to_string(),
+            "index".to_string(),
+        );
+
+        assert_eq!(storage.distributed_ttl(1_700_000_000), 1_700_000_000);
+    }
+
+    #[test]
+    fn test_subscription_without_installation_id_deserializes() {
+        let item: HashMap<String, AttributeValue> = HashMap::from([
+            ("topic".to_string(), AttributeValue::S("topic".to_string())),
+            (
+                "hmac_key".to_string(),
+                AttributeValue::S("hmac".to_string()),
+            ),
+            (
+                "ttl".to_string(),
+                AttributeValue::N("1700000000".to_string()),
+            ),
+            (
+                "encrypted_push_id".to_string(),
+                AttributeValue::S("push-id".to_string()),
+            ),
+        ]);
+
+        let subscription: PushSubscription = serde_dynamo::from_item(item).unwrap();
+
+        assert_eq!(subscription.installation_id, None);
+    }
+
+    #[test]
+    fn test_installation_id_round_trips() {
+        let subscription = PushSubscription {
+            topic: "topic".to_string(),
+            hmac_key: "hmac".to_string(),
+            ttl: 1_700_000_000,
+            encrypted_push_id: "push-id".to_string(),
+            deletion_request: None,
+            installation_id: None,
+        };
+
+        // Subscriptions without an installation ID are stored as before
+        let item: HashMap<String, AttributeValue> = serde_dynamo::to_item(&subscription).unwrap();
+        assert!(!item.contains_key(&PushSubscriptionAttribute::InstallationId.to_string()));
+
+        let subscription = PushSubscription {
+            installation_id: Some("installation".to_string()),
+            ..subscription
+        };
+        let item: HashMap<String, AttributeValue> = serde_dynamo::to_item(&subscription).unwrap();
+        let stored: PushSubscription = serde_dynamo::from_item(item).unwrap();
+
+        assert_eq!(stored.installation_id.as_deref(), Some("installation"));
+    }
+
+    #[test]
+    fn test_deletion_requests_are_stored_as_string_set() {
+        let subscription = PushSubscription {
+            topic: "topic".to_string(),
+            hmac_key: "hmac".to_string(),
+            ttl: 1_700_000_000,
+            encrypted_push_id: "push-id".to_string(),
+            deletion_request: Some(HashSet::from([
+                "push-id-1".to_string(),
+                "push-id-2".to_string(),
+            ])),
+            installation_id: None,
+        };
+
+        // A string set, which `ADD` in `append_delete_request` appends to
+        let item: HashMap<String, AttributeValue> = serde_dynamo::to_item(&subscription).unwrap();
+        let Some(AttributeValue::Ss(stored)) =
+            item.get(&PushSubscriptionAttribute::DeletionRequest.to_string())
+        else {
+            panic!("Deletion requests aren't stored as a string set: {item:?}");
+        };
+        assert_eq!(
+            stored.iter().cloned().collect::<HashSet<_>>(),
+            subscription.deletion_request.clone().unwrap()
+        );
+
+        let stored: PushSubscription = serde_dynamo::from_item(item).unwrap();
+        assert_eq!(stored.deletion_request, subscription.deletion_request);
+    }
+
+    #[test]
+    fn test_stored_attributes_match_attribute_names() {
+        let subscription = PushSubscription {
+            topic: "topic".to_string(),
+            hmac_key: "hmac".to_string(),
+            ttl: 1_700_000_000,
+            encrypted_push_id: "encrypted-push-id".to_string(),
+            deletion_request: Some(HashSet::from(["deletion".to_string()])),
+            installation_id: Some("installation".to_string()),
+        };
+
+        assert_stored_attributes_match::<PushSubscriptionAttribute, _>(&subscription);
+    }
+
+    #[test]
+    fn test_gsi_key_fields_match_attribute_names() {
+        let item = HashMap::from([
+            (
+                PushSubscriptionAttribute::Topic.to_string(),
+                AttributeValue::S("topic".to_string()),
+            ),
+            (
+                PushSubscriptionAttribute::HmacKey.to_string(),
+                AttributeValue::S("hmac".to_string()),
+            ),
+        ]);
+
+        let key: PushSubscriptionKey = serde_dynamo::from_item(item).unwrap();
+
+        assert_eq!(key.topic, "topic");
+        assert_eq!(key.hmac_key, "hmac");
+    }
+}
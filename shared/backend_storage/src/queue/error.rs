@@ -1,4 +1,5 @@
-use aws_sdk_sqs::error::SdkError;
+use aws_sdk_sqs::error::{BuildError, SdkError};
+use aws_sdk_sqs::operation::change_message_visibility::ChangeMessageVisibilityError;
 use aws_sdk_sqs::operation::delete_message::DeleteMessageError;
 use aws_sdk_sqs::operation::receive_message::ReceiveMessageError;
 use aws_sdk_sqs::operation::send_message::SendMessageError;
@@ -22,7 +23,35 @@ pub enum QueueError {
     #[error("Failed to delete message from SQS")]
     DeleteMessage(#[from] SdkError<DeleteMessageError>),
 
+    /// Error changing the visibility of a message in SQS
+    #[error("Failed to change message visibility in SQS")]
+    ChangeMessageVisibility(#[from] SdkError<ChangeMessageVisibilityError>),
+
     /// Error serializing message to JSON
     #[error("Failed to serialize message: {0}")]
     SerializationError(#[from] serde_json::Error),
+
+    /// Message delay is outside SQS limits
+    #[error("delay must be between 0 and 900 seconds, got {0}")]
+    DelayOutOfRange(i32),
+
+    /// Error building a message attribute
+    #[error("Failed to build message attribute: {0}")]
+    MessageAttribute(#[from] BuildError),
+}
+
+/// Error types for invalid queue configurations, see `QueueConfig::new`
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum QueueConfigError {
+    /// Maximum number of messages per receive is outside SQS limits
+    #[error("max messages must be between 1 and 10, got {0}")]
+    MaxMessagesOutOfRange(i32),
+
+    /// Visibility timeout is outside SQS limits
+    #[error("visibility timeout must be between 0 and 43200 seconds, got {0}")]
+    VisibilityTimeoutOutOfRange(i32),
+
+    /// Long polling wait time is outside SQS limits
+    #[error("wait time must be between 0 and 20 seconds, got {0}")]
+    WaitTimeOutOfRange(i32),
 }
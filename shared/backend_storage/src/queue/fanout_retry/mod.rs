@@ -0,0 +1,9 @@
+//! Fan-out retry queue operations
+//!
+//! This module holds XMTP messages whose fan-out to subscribers failed via AWS SQS FIFO queue,
+//! so they can be fanned out later instead of being lost.
+
+use crate::queue::{sqs_queue::SqsQueue, types::FanOutRetry};
+
+/// Fan-out retry queue for messages that couldn't be fanned out to subscribers
+pub type FanOutRetryQueue = SqsQueue<FanOutRetry>;
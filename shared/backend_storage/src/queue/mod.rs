@@ -5,16 +5,24 @@
 
 /// Error types for queue operations
 pub mod error;
+/// Fan-out retry queue functionality
+pub mod fanout_retry;
 /// Notification queue functionality
 pub mod notification;
 /// Generic SQS queue implementation
 pub mod sqs_queue;
 /// Subscription request queue functionality
 pub mod subscription_request;
+/// Trace context propagation through message attributes
+pub mod trace;
 /// Common types for queue operations
 pub mod types;
 
-pub use error::{QueueError, QueueResult};
+pub use error::{QueueConfigError, QueueError, QueueResult};
+pub use fanout_retry::FanOutRetryQueue;
 pub use notification::NotificationQueue;
 pub use subscription_request::SubscriptionRequestQueue;
-pub use types::{Notification, QueueConfig, QueueMessage, SubscriptionRequest, TopicMember};
+pub use types::{
+    FanOutRetry, Notification, PeekedMessage, QueueConfig, QueueMessage, SubscriptionRequest,
+    TopicMember,
+};
@@ -2,7 +2,25 @@
 //!
 //! This module handles notification delivery to subscribers via AWS SQS FIFO queue.
 
-use crate::queue::{sqs_queue::SqsQueue, types::Notification};
+use crate::queue::{error::QueueResult, sqs_queue::SqsQueue, types::Notification};
 
 /// Notification queue for delivering notifications to subscribers
 pub type NotificationQueue = SqsQueue<Notification>;
+
+impl NotificationQueue {
+    /// Enqueues a notification to be delivered once `delay_seconds` have passed, e.g. to
+    /// notify again after a cooldown
+    ///
+    /// See `SqsQueue::send_message_delayed` for how FIFO queues delay it.
+    ///
+    /// # Errors
+    ///
+    /// Returns `QueueError` if the delay is invalid for the queue or the send operation fails
+    pub async fn enqueue_delayed(
+        &self,
+        notification: &Notification,
+        delay_seconds: i32,
+    ) -> QueueResult<String> {
+        self.send_message_delayed(notification, delay_seconds).await
+    }
+}
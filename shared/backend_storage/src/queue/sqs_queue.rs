@@ -4,12 +4,20 @@
 //! with any message type that implements the required traits.
 
 use crate::queue::{
-    error::QueueResult,
-    types::{MessageGroupId, QueueConfig, QueueMessage},
+    error::{QueueError, QueueResult},
+    trace::{current_traceparent, TRACEPARENT_ATTRIBUTE},
+    types::{MessageGroupId, PeekedMessage, QueueConfig, QueueMessage},
+};
+use aws_sdk_sqs::{
+    types::{Message, MessageAttributeValue, MessageSystemAttributeName},
+    Client as SqsClient,
 };
-use aws_sdk_sqs::Client as SqsClient;
 use serde::{de::DeserializeOwned, Serialize};
 use std::sync::Arc;
+use uuid::Uuid;
+
+/// Message attribute holding when a delayed FIFO message is due, in milliseconds since epoch
+const DELIVER_AFTER_ATTRIBUTE: &str = "DeliverAfterMs";
 
 /// Generic SQS queue for handling any message type
 pub struct SqsQueue<T> {
@@ -51,18 +59,89 @@ where
     ///
     /// Returns `QueueError` if the send operation fails
     pub async fn send_message(&self, message: &T) -> QueueResult<String> {
+        self.send(message, None).await
+    }
+
+    /// Sends a message to the queue, delivered only once `delay_seconds` have passed
+    ///
+    /// Use this to process a message later, e.g. to retry it, without a separate scheduler.
+    ///
+    /// FIFO queues don't support per-message delays, so there the message is sent right away
+    /// with the time it is due. `poll_messages` hides messages that aren't due yet again until
+    /// then, and once due sends them again as a new message, so the receives spent waiting don't
+    /// count towards the redrive policy's `maxReceiveCount`. Delayed messages get their own
+    /// message group so they don't hold back the messages sent after them, and a unique
+    /// deduplication ID, as they usually repeat a message sent shortly before.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The message to send
+    /// * `delay_seconds` - Seconds before the message becomes visible, between 0 and 900
+    ///
+    /// # Returns
+    ///
+    /// The message ID if successful or an empty string
+    ///
+    /// # Errors
+    ///
+    /// Returns `QueueError::DelayOutOfRange` if the delay is outside SQS limits, or `QueueError`
+    /// if the send operation fails
+    pub async fn send_message_delayed(
+        &self,
+        message: &T,
+        delay_seconds: i32,
+    ) -> QueueResult<String> {
+        if !QueueConfig::DELAY_SECONDS_RANGE.contains(&delay_seconds) {
+            return Err(QueueError::DelayOutOfRange(delay_seconds));
+        }
+        if delay_seconds == 0 {
+            return self.send(message, None).await;
+        }
+
+        self.send(message, Some(delay_seconds)).await
+    }
+
+    async fn send(&self, message: &T, delay_seconds: Option<i32>) -> QueueResult<String> {
         // Serialize the message
         let body = serde_json::to_string(message)?;
 
-        // Send to SQS
-        let result = self
+        let request = self
             .sqs_client
             .send_message()
             .queue_url(&self.config.queue_url)
-            .message_body(body)
-            .message_group_id(message.message_group_id())
-            .send()
-            .await?;
+            .message_body(body);
+
+        // Send to SQS, only FIFO queues group messages
+        let request = match (self.config.is_fifo(), delay_seconds) {
+            (false, delay_seconds) => request.set_delay_seconds(delay_seconds),
+            (true, None) => request.message_group_id(message.message_group_id()),
+            (true, Some(delay_seconds)) => {
+                let deliver_after_ms =
+                    chrono::Utc::now().timestamp_millis() + i64::from(delay_seconds) * 1000;
+                request
+                    .message_group_id(format!("{}#delayed", message.message_group_id()))
+                    .message_deduplication_id(Uuid::new_v4().to_string())
+                    .message_attributes(
+                        DELIVER_AFTER_ATTRIBUTE,
+                        MessageAttributeValue::builder()
+                            .data_type("Number")
+                            .string_value(deliver_after_ms.to_string())
+                            .build()?,
+                    )
+            }
+        };
+        // Let the consumer continue the sender's trace
+        let request = match current_traceparent() {
+            Some(traceparent) => request.message_attributes(
+                TRACEPARENT_ATTRIBUTE,
+                MessageAttributeValue::builder()
+                    .data_type("String")
+                    .string_value(traceparent)
+                    .build()?,
+            ),
+            None => request,
+        };
+        let result = request.send().await?;
 
         Ok(result
             .message_id()
@@ -72,6 +151,9 @@ where
 
     /// Polls messages from the queue
     ///
+    /// Delayed FIFO messages are left out: those that aren't due yet are hidden again until they
+    /// are, the others are sent again as new messages, returned by a later poll.
+    ///
     /// # Returns
     ///
     /// A vector of messages with metadata
@@ -88,29 +170,105 @@ where
             .max_number_of_messages(self.config.default_max_messages)
             .visibility_timeout(self.config.default_visibility_timeout)
             .wait_time_seconds(self.config.default_wait_time_seconds)
+            .message_attribute_names(DELIVER_AFTER_ATTRIBUTE)
+            .message_attribute_names(TRACEPARENT_ATTRIBUTE)
             .send()
             .await?;
 
         // Parse messages
+        let mut messages = Vec::with_capacity(result.messages().len());
+        for msg in result.messages() {
+            let (Some(body), Some(receipt_handle), Some(message_id)) =
+                (msg.body(), msg.receipt_handle(), msg.message_id())
+            else {
+                continue;
+            };
+
+            if let Some(deliver_after_ms) = deliver_after_ms(msg) {
+                if let Some(remaining_secs) = remaining_delay_secs(deliver_after_ms) {
+                    // Otherwise the message reappears once the visibility timeout expires
+                    if let Err(e) = self.requeue_message(receipt_handle, remaining_secs).await {
+                        tracing::warn!("Failed to hide delayed message {message_id}: {e:?}");
+                    }
+                } else if let Err(e) = self.release_delayed(msg, body, receipt_handle).await {
+                    tracing::warn!("Failed to release delayed message {message_id}: {e:?}");
+                }
+                continue;
+            }
+
+            match serde_json::from_str::<T>(body) {
+                Ok(parsed) => messages.push(QueueMessage {
+                    body: parsed,
+                    receipt_handle: receipt_handle.to_string(),
+                    message_id: message_id.to_string(),
+                    trace_parent: message_attribute(msg, TRACEPARENT_ATTRIBUTE).map(str::to_string),
+                }),
+                Err(e) => {
+                    tracing::error!("Failed to deserialize message: {}", e);
+                }
+            }
+        }
+
+        Ok(messages)
+    }
+
+    /// Reads up to `max_messages` (at most 10) messages without consuming them
+    ///
+    /// Messages are received with a zero visibility timeout, so they stay visible to other
+    /// consumers and are never deleted. Receiving still increments their receive count.
+    /// Messages whose body can't be deserialized are returned with their raw body only.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_messages` - Maximum number of messages to return, clamped to 1..=10
+    ///
+    /// # Errors
+    ///
+    /// Returns `QueueError` if the receive operation fails
+    pub async fn peek_messages(&self, max_messages: i32) -> QueueResult<Vec<PeekedMessage<T>>> {
+        let result = self
+            .sqs_client
+            .receive_message()
+            .queue_url(&self.config.queue_url)
+            .max_number_of_messages(max_messages.clamp(1, 10))
+            .visibility_timeout(0)
+            .wait_time_seconds(0)
+            .message_system_attribute_names(MessageSystemAttributeName::ApproximateReceiveCount)
+            .message_system_attribute_names(MessageSystemAttributeName::SentTimestamp)
+            .send()
+            .await?;
+
         let messages = result
             .messages()
             .iter()
             .filter_map(|msg| {
                 let body = msg.body()?;
-                let receipt_handle = msg.receipt_handle()?.to_string();
                 let message_id = msg.message_id()?.to_string();
+                let attribute = |name: &MessageSystemAttributeName| {
+                    msg.attributes()
+                        .and_then(|attributes| attributes.get(name))
+                        .map(String::as_str)
+                };
+                let receive_count = attribute(&MessageSystemAttributeName::ApproximateReceiveCount)
+                    .and_then(|count| count.parse().ok())
+                    .unwrap_or_default();
+                let sent_timestamp_ms = attribute(&MessageSystemAttributeName::SentTimestamp)
+                    .and_then(|timestamp| timestamp.parse().ok());
 
-                match serde_json::from_str::<T>(body) {
-                    Ok(parsed) => Some(QueueMessage {
-                        body: parsed,
-                        receipt_handle,
-                        message_id,
-                    }),
-                    Err(e) => {
-                        tracing::error!("Failed to deserialize message: {}", e);
-                        None
-                    }
-                }
+                // Undecodable messages are still returned, they are often why a message is peeked
+                let parsed = serde_json::from_str::<T>(body)
+                    .inspect_err(|e| {
+                        tracing::warn!("Failed to deserialize peeked message {message_id}: {e}");
+                    })
+                    .ok();
+
+                Some(PeekedMessage {
+                    body: parsed,
+                    raw_body: body.to_string(),
+                    message_id,
+                    receive_count,
+                    sent_timestamp_ms,
+                })
             })
             .collect();
 
@@ -136,4 +294,92 @@ where
 
         Ok(())
     }
+
+    /// Sends a due delayed FIFO message again as a new message of its own message group, and
+    /// deletes the delayed one
+    ///
+    /// The new message starts with a receive count of zero, so the receives of the delayed one
+    /// don't count towards the redrive policy's `maxReceiveCount`.
+    async fn release_delayed(
+        &self,
+        msg: &Message,
+        body: &str,
+        receipt_handle: &str,
+    ) -> QueueResult<()> {
+        let message: T = serde_json::from_str(body)?;
+        let traceparent = message_attribute(msg, TRACEPARENT_ATTRIBUTE)
+            .map(|traceparent| {
+                MessageAttributeValue::builder()
+                    .data_type("String")
+                    .string_value(traceparent)
+                    .build()
+            })
+            .transpose()?;
+        self.sqs_client
+            .send_message()
+            .queue_url(&self.config.queue_url)
+            .message_body(body)
+            .message_group_id(message.message_group_id())
+            .message_deduplication_id(Uuid::new_v4().to_string())
+            .set_message_attributes(
+                traceparent.map(|value| [(TRACEPARENT_ATTRIBUTE.to_string(), value)].into()),
+            )
+            .send()
+            .await?;
+
+        self.ack_message(receipt_handle).await
+    }
+
+    /// Returns a received message to the queue, making it visible again after `delay_secs`
+    ///
+    /// Use this instead of waiting out the visibility timeout when a message can't be processed
+    /// yet but shouldn't be dropped either.
+    ///
+    /// # Arguments
+    ///
+    /// * `receipt_handle` - The receipt handle from the received message
+    /// * `delay_secs` - Seconds before the message becomes visible again, between 0 and 43200
+    ///
+    /// # Errors
+    ///
+    /// Returns `QueueError` if the visibility change fails
+    pub async fn requeue_message(&self, receipt_handle: &str, delay_secs: i32) -> QueueResult<()> {
+        self.sqs_client
+            .change_message_visibility()
+            .queue_url(&self.config.queue_url)
+            .receipt_handle(receipt_handle)
+            .visibility_timeout(delay_secs)
+            .send()
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// When a delayed FIFO message is due, in milliseconds since epoch, `None` if it isn't delayed
+fn deliver_after_ms(msg: &Message) -> Option<i64> {
+    message_attribute(msg, DELIVER_AFTER_ATTRIBUTE)?
+        .parse()
+        .ok()
+}
+
+/// String value of the message attribute `name` of a received message
+fn message_attribute<'a>(msg: &'a Message, name: &str) -> Option<&'a str> {
+    msg.message_attributes()?.get(name)?.string_value()
+}
+
+/// Seconds until a delayed FIFO message due at `deliver_after_ms` is due, rounded up, or `None`
+/// if it is due
+fn remaining_delay_secs(deliver_after_ms: i64) -> Option<i32> {
+    let remaining_ms = deliver_after_ms - chrono::Utc::now().timestamp_millis();
+    let remaining_secs = u64::try_from(remaining_ms).ok()?.div_ceil(1000);
+    if remaining_secs == 0 {
+        return None;
+    }
+
+    Some(
+        i32::try_from(remaining_secs)
+            .unwrap_or(i32::MAX)
+            .min(*QueueConfig::DELAY_SECONDS_RANGE.end()),
+    )
 }
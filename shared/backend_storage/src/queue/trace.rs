@@ -0,0 +1,96 @@
+//! W3C trace context propagation through SQS message attributes
+//!
+//! Messages sent from within a traced span carry its context in the `traceparent` message
+//! attribute, so consumers can continue the trace from `QueueMessage::trace_parent`. Message
+//! attributes aren't part of the body, so content-based deduplication is unaffected.
+
+use opentelemetry::trace::{SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Message attribute holding the W3C `traceparent` of the sender's span
+pub const TRACEPARENT_ATTRIBUTE: &str = "traceparent";
+
+/// `traceparent` of the current span, `None` when it isn't part of a trace
+pub(crate) fn current_traceparent() -> Option<String> {
+    let context = tracing::Span::current().context();
+    let span = context.span();
+    let span_context = span.span_context();
+
+    span_context.is_valid().then(|| {
+        format!(
+            "00-{}-{}-{:02x}",
+            span_context.trace_id(),
+            span_context.span_id(),
+            span_context.trace_flags().to_u8()
+        )
+    })
+}
+
+/// Remote parent context described by a version `00` `traceparent`, `None` if it is invalid
+#[must_use]
+pub fn parent_context(traceparent: &str) -> Option<opentelemetry::Context> {
+    let mut parts = traceparent.trim().split('-');
+    let (Some("00"), Some(trace_id), Some(span_id), Some(flags), None) = (
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+    ) else {
+        return None;
+    };
+    if trace_id.len() != 32 || span_id.len() != 16 || flags.len() != 2 {
+        return None;
+    }
+
+    let span_context = SpanContext::new(
+        TraceId::from_hex(trace_id).ok()?,
+        SpanId::from_hex(span_id).ok()?,
+        TraceFlags::new(u8::from_str_radix(flags, 16).ok()?),
+        true,
+        TraceState::default(),
+    );
+    span_context
+        .is_valid()
+        .then(|| opentelemetry::Context::new().with_remote_span_context(span_context))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parent_context_is_read_from_traceparent() {
+        let context =
+            parent_context("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01").unwrap();
+        let span = context.span();
+        let span_context = span.span_context();
+
+        assert_eq!(
+            span_context.trace_id().to_string(),
+            "4bf92f3577b34da6a3ce929d0e0e4736"
+        );
+        assert_eq!(span_context.span_id().to_string(), "00f067aa0ba902b7");
+        assert!(span_context.is_sampled());
+        assert!(span_context.is_remote());
+    }
+
+    #[test]
+    fn test_invalid_traceparent_is_ignored() {
+        for traceparent in [
+            "",
+            "01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+            "00-not-hex-01",
+        ] {
+            assert!(parent_context(traceparent).is_none(), "{traceparent}");
+        }
+    }
+
+    #[test]
+    fn test_untraced_span_has_no_traceparent() {
+        assert_eq!(current_traceparent(), None);
+    }
+}
@@ -1,5 +1,9 @@
+use std::ops::RangeInclusive;
+
 use serde::{Deserialize, Serialize};
 
+use super::error::QueueConfigError;
+
 /// Subscription request message types
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(tag = "type", rename_all = "snake_case")]
@@ -37,6 +41,25 @@ pub struct Notification {
     pub subscribed_encrypted_push_ids: Vec<String>,
     /// Encrypted Message Base64 encoded
     pub encrypted_message_base64: String,
+    /// Number of times these recipients were re-enqueued because the enclave lost its state
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub requeue_attempt: u32,
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+const fn is_zero(value: &u32) -> bool {
+    *value == 0
+}
+
+/// XMTP message whose fan-out to subscribers failed and must be retried
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FanOutRetry {
+    /// XMTP content topic of the message
+    pub content_topic: String,
+    /// Timestamp of the message (in nanoseconds)
+    pub timestamp_ns: u64,
+    /// Message bytes Base64 encoded
+    pub message_base64: String,
 }
 
 /// Notification recipient
@@ -57,6 +80,23 @@ pub struct QueueMessage<T> {
     pub receipt_handle: String,
     /// Message ID
     pub message_id: String,
+    /// W3C `traceparent` of the sender's span, see `queue::trace::parent_context`
+    pub trace_parent: Option<String>,
+}
+
+/// A message read without consuming it, with its delivery metadata
+#[derive(Debug, Clone)]
+pub struct PeekedMessage<T> {
+    /// The message body, `None` if it couldn't be deserialized
+    pub body: Option<T>,
+    /// The raw message body as sent to the queue
+    pub raw_body: String,
+    /// Message ID
+    pub message_id: String,
+    /// Approximate number of times the message was received, including the peek
+    pub receive_count: u32,
+    /// When the message was sent to the queue (Unix timestamp in milliseconds)
+    pub sent_timestamp_ms: Option<i64>,
 }
 
 /// Configuration for queue operations
@@ -72,6 +112,58 @@ pub struct QueueConfig {
     pub default_wait_time_seconds: i32,
 }
 
+impl QueueConfig {
+    /// Messages SQS returns per receive
+    pub const MAX_MESSAGES_RANGE: RangeInclusive<i32> = 1..=10;
+    /// Visibility timeouts SQS accepts (in seconds, up to 12 hours)
+    pub const VISIBILITY_TIMEOUT_RANGE: RangeInclusive<i32> = 0..=43_200;
+    /// Long polling wait times SQS accepts (in seconds)
+    pub const WAIT_TIME_SECONDS_RANGE: RangeInclusive<i32> = 0..=20;
+    /// Message delays SQS accepts (in seconds, up to 15 minutes)
+    pub const DELAY_SECONDS_RANGE: RangeInclusive<i32> = 0..=900;
+
+    /// Creates a queue configuration, checking the values against SQS limits
+    ///
+    /// # Errors
+    ///
+    /// Returns a `QueueConfigError` for the first value outside of SQS limits
+    pub fn new(
+        queue_url: String,
+        default_max_messages: i32,
+        default_visibility_timeout: i32,
+        default_wait_time_seconds: i32,
+    ) -> Result<Self, QueueConfigError> {
+        if !Self::MAX_MESSAGES_RANGE.contains(&default_max_messages) {
+            return Err(QueueConfigError::MaxMessagesOutOfRange(
+                default_max_messages,
+            ));
+        }
+        if !Self::VISIBILITY_TIMEOUT_RANGE.contains(&default_visibility_timeout) {
+            return Err(QueueConfigError::VisibilityTimeoutOutOfRange(
+                default_visibility_timeout,
+            ));
+        }
+        if !Self::WAIT_TIME_SECONDS_RANGE.contains(&default_wait_time_seconds) {
+            return Err(QueueConfigError::WaitTimeOutOfRange(
+                default_wait_time_seconds,
+            ));
+        }
+
+        Ok(Self {
+            queue_url,
+            default_max_messages,
+            default_visibility_timeout,
+            default_wait_time_seconds,
+        })
+    }
+
+    /// Whether the queue is a FIFO queue, whose URL ends with `.fifo`
+    #[must_use]
+    pub fn is_fifo(&self) -> bool {
+        self.queue_url.ends_with(".fifo")
+    }
+}
+
 /// Trait for extracting message group ID for FIFO queues
 pub trait MessageGroupId {
     /// Returns the message group ID for FIFO queue ordering
@@ -91,3 +183,87 @@ impl MessageGroupId for Notification {
         self.topic.clone()
     }
 }
+
+impl MessageGroupId for FanOutRetry {
+    fn message_group_id(&self) -> String {
+        self.content_topic.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUEUE_URL: &str = "http://localhost:4566/000000000000/queue.fifo";
+
+    #[test]
+    fn test_notification_without_requeue_attempt_deserializes() {
+        let notification: Notification = serde_json::from_value(serde_json::json!({
+            "topic": "topic",
+            "subscribed_encrypted_push_ids": ["push-id"],
+            "encrypted_message_base64": "bWVzc2FnZQ==",
+        }))
+        .unwrap();
+
+        assert_eq!(notification.requeue_attempt, 0);
+        // First attempts serialize as before, keeping content-based deduplication stable
+        assert!(serde_json::to_value(&notification)
+            .unwrap()
+            .get("requeue_attempt")
+            .is_none());
+    }
+
+    #[test]
+    fn test_valid_config_is_accepted() {
+        let config = QueueConfig::new(QUEUE_URL.to_string(), 10, 43_200, 0).unwrap();
+
+        assert_eq!(config.default_max_messages, 10);
+        assert_eq!(config.default_visibility_timeout, 43_200);
+        assert_eq!(config.default_wait_time_seconds, 0);
+    }
+
+    #[test]
+    fn test_max_messages_out_of_range_is_rejected() {
+        for max_messages in [0, 11] {
+            assert_eq!(
+                QueueConfig::new(QUEUE_URL.to_string(), max_messages, 60, 20).unwrap_err(),
+                QueueConfigError::MaxMessagesOutOfRange(max_messages)
+            );
+        }
+    }
+
+    #[test]
+    fn test_visibility_timeout_out_of_range_is_rejected() {
+        for visibility_timeout in [-1, 43_201] {
+            assert_eq!(
+                QueueConfig::new(QUEUE_URL.to_string(), 10, visibility_timeout, 20).unwrap_err(),
+                QueueConfigError::VisibilityTimeoutOutOfRange(visibility_timeout)
+            );
+        }
+    }
+
+    #[test]
+    fn test_wait_time_out_of_range_is_rejected() {
+        for wait_time in [-1, 21] {
+            assert_eq!(
+                QueueConfig::new(QUEUE_URL.to_string(), 10, 60, wait_time).unwrap_err(),
+                QueueConfigError::WaitTimeOutOfRange(wait_time)
+            );
+        }
+    }
+
+    #[test]
+    fn test_fifo_queues_are_detected_from_url() {
+        let fifo = QueueConfig::new(QUEUE_URL.to_string(), 10, 60, 20).unwrap();
+        let standard = QueueConfig::new(
+            "http://localhost:4566/000000000000/queue".to_string(),
+            10,
+            60,
+            20,
+        )
+        .unwrap();
+
+        assert!(fifo.is_fifo());
+        assert!(!standard.is_fifo());
+    }
+}
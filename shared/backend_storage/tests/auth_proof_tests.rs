@@ -10,6 +10,7 @@ use aws_sdk_dynamodb::Client as DynamoDbClient;
 use backend_storage::auth_proof::{
     AuthProofAttribute, AuthProofInsertRequest, AuthProofStorage, AuthProofStorageError,
 };
+use backend_storage::ids::{EncryptedPushId, Nullifier};
 use chrono::Utc;
 use uuid::Uuid;
 
@@ -110,6 +111,25 @@ async fn setup_test() -> TestContext {
     }
 }
 
+/// Fixed clock, 2024-12-01T14:30:00Z
+fn afternoon_clock() -> i64 {
+    1_733_063_400
+}
+
+/// Fixed clock, 2024-12-05T09:00:00Z
+fn morning_clock() -> i64 {
+    1_733_389_200
+}
+
+/// Asserts `ttl` lies in the 6-8 months window after `now`
+fn assert_ttl_window(ttl: i64, now: i64) {
+    const MONTH_SECONDS: i64 = 30 * 24 * 60 * 60;
+    assert!(
+        (now + 6 * MONTH_SECONDS..=now + 8 * MONTH_SECONDS).contains(&ttl),
+        "TTL {ttl} outside the window after {now}"
+    );
+}
+
 /// Creates a test auth proof insert request with unique nullifier
 fn create_test_auth_proof_request() -> AuthProofInsertRequest {
     AuthProofInsertRequest {
@@ -134,7 +154,7 @@ async fn test_get_by_nullifier() {
     // Get by nullifier - should exist
     let retrieved = context
         .storage
-        .get_by_nullifier(&auth_proof_request.nullifier)
+        .get_by_nullifier(&Nullifier::from(auth_proof_request.nullifier.as_str()))
         .await
         .expect("Failed to get by nullifier");
 
@@ -154,13 +174,59 @@ async fn test_get_by_nullifier() {
     // Get non-existent nullifier - should return None
     let non_existent = context
         .storage
-        .get_by_nullifier("non-existent-nullifier")
+        .get_by_nullifier(&Nullifier::from("non-existent-nullifier"))
         .await
         .expect("Failed to get non-existent");
 
     assert!(non_existent.is_none());
 }
 
+#[tokio::test]
+async fn test_batch_get_by_nullifiers() {
+    let context = setup_test().await;
+
+    // More than one batch get request
+    let mut inserted = Vec::new();
+    for _ in 0..120 {
+        let auth_proof = context
+            .storage
+            .insert(create_test_auth_proof_request())
+            .await
+            .expect("Failed to insert auth proof");
+        inserted.push(auth_proof);
+    }
+
+    let mut nullifiers: Vec<Nullifier> = inserted
+        .iter()
+        .map(|auth_proof| Nullifier::from(auth_proof.nullifier.as_str()))
+        .collect();
+    // Duplicate and missing nullifiers
+    nullifiers.push(nullifiers[0].clone());
+    nullifiers.push(Nullifier::from("non-existent-nullifier"));
+
+    let mut retrieved = context
+        .storage
+        .batch_get_by_nullifiers(&nullifiers)
+        .await
+        .expect("Failed to batch get by nullifiers");
+
+    retrieved.sort_by(|a, b| a.nullifier.cmp(&b.nullifier));
+    inserted.sort_by(|a, b| a.nullifier.cmp(&b.nullifier));
+    assert_eq!(retrieved.len(), inserted.len());
+    for (retrieved, inserted) in retrieved.iter().zip(&inserted) {
+        assert_eq!(retrieved.nullifier, inserted.nullifier);
+        assert_eq!(retrieved.encrypted_push_id, inserted.encrypted_push_id);
+        assert_eq!(retrieved.ttl, inserted.ttl);
+    }
+
+    let none = context
+        .storage
+        .batch_get_by_nullifiers(&[])
+        .await
+        .expect("Failed to batch get no nullifiers");
+    assert!(none.is_empty());
+}
+
 #[tokio::test]
 async fn test_insert_duplicate_prevention() {
     let context = setup_test().await;
@@ -208,7 +274,7 @@ async fn test_update_encrypted_push_id() {
     // Get initial state
     let initial = context
         .storage
-        .get_by_nullifier(&auth_proof_request.nullifier)
+        .get_by_nullifier(&Nullifier::from(auth_proof_request.nullifier.as_str()))
         .await
         .expect("Failed to get initial state")
         .expect("Auth proof should exist");
@@ -219,14 +285,17 @@ async fn test_update_encrypted_push_id() {
     let new_encrypted_push_id = format!("new-encrypted-{}", Uuid::new_v4());
     context
         .storage
-        .update_encrypted_push_id(&auth_proof_request.nullifier, &new_encrypted_push_id)
+        .update_encrypted_push_id(
+            &Nullifier::from(auth_proof_request.nullifier.as_str()),
+            &EncryptedPushId::from(new_encrypted_push_id.as_str()),
+        )
         .await
         .expect("Failed to update encrypted push id");
 
     // Retrieve and verify changes
     let updated = context
         .storage
-        .get_by_nullifier(&auth_proof_request.nullifier)
+        .get_by_nullifier(&Nullifier::from(auth_proof_request.nullifier.as_str()))
         .await
         .expect("Failed to get updated auth proof")
         .expect("Auth proof should exist");
@@ -259,7 +328,7 @@ async fn test_ping_auth_proof() {
     // Get initial state
     let initial = context
         .storage
-        .get_by_nullifier(&auth_proof_request.nullifier)
+        .get_by_nullifier(&Nullifier::from(auth_proof_request.nullifier.as_str()))
         .await
         .expect("Failed to get initial state")
         .expect("Auth proof should exist");
@@ -273,14 +342,14 @@ async fn test_ping_auth_proof() {
     // Ping the auth proof to refresh TTL
     context
         .storage
-        .ping_auth_proof(&auth_proof_request.nullifier)
+        .ping_auth_proof(&Nullifier::from(auth_proof_request.nullifier.as_str()))
         .await
         .expect("Failed to ping auth proof");
 
     // Retrieve and verify changes
     let pinged = context
         .storage
-        .get_by_nullifier(&auth_proof_request.nullifier)
+        .get_by_nullifier(&Nullifier::from(auth_proof_request.nullifier.as_str()))
         .await
         .expect("Failed to get pinged auth proof")
         .expect("Auth proof should exist");
@@ -300,6 +369,39 @@ async fn test_ping_auth_proof() {
     assert_eq!(pinged.nullifier, auth_proof_request.nullifier);
 }
 
+#[tokio::test]
+async fn test_delete_by_nullifier() {
+    let context = setup_test().await;
+
+    let auth_proof_request = create_test_auth_proof_request();
+    let nullifier = Nullifier::from(auth_proof_request.nullifier.as_str());
+    context
+        .storage
+        .insert(auth_proof_request)
+        .await
+        .expect("Failed to insert auth proof");
+
+    context
+        .storage
+        .delete_by_nullifier(&nullifier)
+        .await
+        .expect("Failed to delete auth proof");
+
+    let deleted = context
+        .storage
+        .get_by_nullifier(&nullifier)
+        .await
+        .expect("Failed to get deleted auth proof");
+    assert!(deleted.is_none());
+
+    // Deleting again is a no-op
+    context
+        .storage
+        .delete_by_nullifier(&nullifier)
+        .await
+        .expect("Deleting a missing auth proof should succeed");
+}
+
 #[tokio::test]
 async fn test_get_or_insert_creates_new() {
     let context = setup_test().await;
@@ -310,7 +412,7 @@ async fn test_get_or_insert_creates_new() {
     // Verify it doesn't exist yet
     let not_exists = context
         .storage
-        .get_by_nullifier(&nullifier)
+        .get_by_nullifier(&Nullifier::from(nullifier.as_str()))
         .await
         .expect("Failed to check existence");
     assert!(not_exists.is_none(), "Should not exist initially");
@@ -452,10 +554,131 @@ async fn test_get_or_insert_atomic_concurrent() {
     // Verify only one entry exists in the database
     let final_check = context
         .storage
-        .get_by_nullifier(&nullifier)
+        .get_by_nullifier(&Nullifier::from(nullifier.as_str()))
         .await
         .expect("Failed to get final state")
         .expect("Should exist");
 
     assert_eq!(final_check.encrypted_push_id, first.encrypted_push_id);
 }
+
+#[tokio::test]
+async fn test_insert_with_fixed_clock() {
+    let mut context = setup_test().await;
+    context.storage = context.storage.clone().with_clock(afternoon_clock);
+
+    let auth_proof = context
+        .storage
+        .insert(create_test_auth_proof_request())
+        .await
+        .expect("Failed to insert auth proof");
+
+    // Past noon, rounded up to 2024-12-02T00:00:00Z
+    assert_eq!(auth_proof.push_id_rotated_at, 1_733_097_600);
+    assert_ttl_window(auth_proof.ttl, afternoon_clock());
+}
+
+#[tokio::test]
+async fn test_update_encrypted_push_id_with_fixed_clock() {
+    let mut context = setup_test().await;
+    context.storage = context.storage.clone().with_clock(afternoon_clock);
+
+    let auth_proof_request = create_test_auth_proof_request();
+    let nullifier = Nullifier::from(auth_proof_request.nullifier.as_str());
+    context
+        .storage
+        .insert(auth_proof_request)
+        .await
+        .expect("Failed to insert auth proof");
+
+    // A few days later
+    context.storage = context.storage.clone().with_clock(morning_clock);
+    context
+        .storage
+        .update_encrypted_push_id(&nullifier, &EncryptedPushId::from("rotated-push-id"))
+        .await
+        .expect("Failed to update encrypted push id");
+
+    let updated = context
+        .storage
+        .get_by_nullifier(&nullifier)
+        .await
+        .expect("Failed to get by nullifier")
+        .expect("Auth proof should exist");
+
+    // Before noon, rounded down to 2024-12-05T00:00:00Z
+    assert_eq!(updated.push_id_rotated_at, 1_733_356_800);
+    assert_ttl_window(updated.ttl, morning_clock());
+}
+
+#[tokio::test]
+async fn test_update_encrypted_push_id_with_cooldown() {
+    const DAY_SECONDS: i64 = 24 * 60 * 60;
+
+    let mut context = setup_test().await;
+    context.storage = context.storage.clone().with_clock(afternoon_clock);
+
+    let auth_proof_request = create_test_auth_proof_request();
+    let nullifier = Nullifier::from(auth_proof_request.nullifier.as_str());
+    let inserted = context
+        .storage
+        .insert(auth_proof_request)
+        .await
+        .expect("Failed to insert auth proof");
+
+    // Rotated on 2024-12-02T00:00:00Z, a week ago is still within the cooldown
+    context.storage = context.storage.clone().with_clock(morning_clock);
+    let result = context
+        .storage
+        .update_encrypted_push_id_with_cooldown(
+            &nullifier,
+            &EncryptedPushId::from("rejected-push-id"),
+            7 * DAY_SECONDS,
+        )
+        .await;
+    assert!(matches!(
+        result,
+        Err(AuthProofStorageError::RotationCooldownActive)
+    ));
+
+    let unchanged = context
+        .storage
+        .get_by_nullifier(&nullifier)
+        .await
+        .expect("Failed to get by nullifier")
+        .expect("Auth proof should exist");
+    assert_eq!(unchanged.encrypted_push_id, inserted.encrypted_push_id);
+    assert_eq!(unchanged.push_id_rotated_at, inserted.push_id_rotated_at);
+
+    // Three days ago is past the cooldown
+    context
+        .storage
+        .update_encrypted_push_id_with_cooldown(
+            &nullifier,
+            &EncryptedPushId::from("rotated-push-id"),
+            3 * DAY_SECONDS,
+        )
+        .await
+        .expect("Rotation past the cooldown should succeed");
+
+    let rotated = context
+        .storage
+        .get_by_nullifier(&nullifier)
+        .await
+        .expect("Failed to get by nullifier")
+        .expect("Auth proof should exist");
+    assert_eq!(rotated.encrypted_push_id, "rotated-push-id");
+    assert_eq!(rotated.push_id_rotated_at, 1_733_356_800);
+
+    // Auth proofs that don't exist yet have no cooldown
+    let missing = create_test_auth_proof_request();
+    context
+        .storage
+        .update_encrypted_push_id_with_cooldown(
+            &Nullifier::from(missing.nullifier.as_str()),
+            &EncryptedPushId::from(missing.encrypted_push_id.as_str()),
+            7 * DAY_SECONDS,
+        )
+        .await
+        .expect("Rotation of a new auth proof should succeed");
+}
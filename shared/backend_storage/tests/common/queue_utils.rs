@@ -33,8 +33,21 @@ pub struct QueueTestContext {
 impl QueueTestContext {
     /// Creates a new test context with a unique FIFO queue
     pub async fn new(test_name: &str) -> Self {
-        // Create unique queue name
-        let queue_name = format!("{}-{}.fifo", test_name, Uuid::new_v4());
+        Self::create(test_name, true).await
+    }
+
+    /// Creates a new test context with a unique standard (non-FIFO) queue
+    pub async fn new_standard(test_name: &str) -> Self {
+        Self::create(test_name, false).await
+    }
+
+    async fn create(test_name: &str, fifo: bool) -> Self {
+        // Create unique queue name, FIFO queue names must end with `.fifo`
+        let queue_name = if fifo {
+            format!("{}-{}.fifo", test_name, Uuid::new_v4())
+        } else {
+            format!("{}-{}", test_name, Uuid::new_v4())
+        };
 
         // Setup LocalStack client with hardcoded credentials for CI
         let credentials = Credentials::from_keys(
@@ -52,15 +65,17 @@ impl QueueTestContext {
 
         let sqs_client = Arc::new(SqsClient::new(&config));
 
-        // Create FIFO queue with content-based deduplication
-        let result = sqs_client
-            .create_queue()
-            .queue_name(&queue_name)
-            .attributes(aws_sdk_sqs::types::QueueAttributeName::FifoQueue, "true")
-            .attributes(
-                aws_sdk_sqs::types::QueueAttributeName::ContentBasedDeduplication,
-                "true",
-            )
+        // Create FIFO queues with content-based deduplication
+        let mut create_queue = sqs_client.create_queue().queue_name(&queue_name);
+        if fifo {
+            create_queue = create_queue
+                .attributes(aws_sdk_sqs::types::QueueAttributeName::FifoQueue, "true")
+                .attributes(
+                    aws_sdk_sqs::types::QueueAttributeName::ContentBasedDeduplication,
+                    "true",
+                );
+        }
+        let result = create_queue
             .send()
             .await
             .expect("Failed to create test queue");
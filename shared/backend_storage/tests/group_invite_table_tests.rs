@@ -9,7 +9,7 @@ use aws_sdk_dynamodb::types::{
 };
 use aws_sdk_dynamodb::Client as DynamoDbClient;
 use backend_storage::group_invite::{
-    GroupInviteAttribute, GroupInviteCreateRequest, GroupInviteStorage,
+    GroupInviteAttribute, GroupInviteCreateRequest, GroupInviteStorage, GroupInviteStorageError,
 };
 use tokio::time::sleep;
 use uuid::Uuid;
@@ -248,6 +248,41 @@ async fn test_get_one_non_existing_invite() {
     assert!(result.is_none());
 }
 
+#[tokio::test]
+async fn test_get_one_active() {
+    let ctx = setup_test().await;
+    let topic = format!("topic-{}", Uuid::new_v4());
+    let now = chrono::Utc::now().timestamp();
+
+    let create = |expires_at| GroupInviteCreateRequest {
+        expires_at,
+        ..create_test_invite_request(&topic)
+    };
+    let never_expiring = ctx.storage.create(create(None)).await.unwrap();
+    let expiring_later = ctx.storage.create(create(Some(now + 3600))).await.unwrap();
+    let expired = ctx.storage.create(create(Some(now))).await.unwrap();
+
+    let result = ctx
+        .storage
+        .get_one_active(&never_expiring.id)
+        .await
+        .unwrap();
+    assert_eq!(result.map(|invite| invite.id), Some(never_expiring.id));
+
+    let result = ctx
+        .storage
+        .get_one_active(&expiring_later.id)
+        .await
+        .unwrap();
+    assert_eq!(result.map(|invite| invite.id), Some(expiring_later.id));
+
+    let result = ctx.storage.get_one_active(&expired.id).await.unwrap();
+    assert!(result.is_none());
+    // Still returned when expired invites are included
+    let result = ctx.storage.get_one(&expired.id).await.unwrap();
+    assert_eq!(result.map(|invite| invite.id), Some(expired.id));
+}
+
 #[tokio::test]
 async fn test_delete_existing_invite() {
     let ctx = setup_test().await;
@@ -363,3 +398,86 @@ async fn test_get_latest_by_topic() {
         created_invite_user_a_topic_a_latest.created_at
     );
 }
+
+#[tokio::test]
+async fn test_consume_use_until_exhausted() {
+    let ctx = setup_test().await;
+    let topic = format!("topic-{}", Uuid::new_v4());
+    let invite = ctx
+        .storage
+        .create(GroupInviteCreateRequest {
+            max_uses: Some(2),
+            ..create_test_invite_request(&topic)
+        })
+        .await
+        .expect("Failed to create group invite");
+    assert_eq!(invite.remaining_uses, Some(2));
+
+    // Each use decrements the remaining uses
+    let consumed = ctx
+        .storage
+        .consume_use(&invite.id)
+        .await
+        .expect("Failed to consume first use");
+    assert_eq!(consumed.remaining_uses, Some(1));
+
+    let consumed = ctx
+        .storage
+        .consume_use(&invite.id)
+        .await
+        .expect("Failed to consume second use");
+    assert_eq!(consumed.remaining_uses, Some(0));
+
+    // No use left
+    let result = ctx.storage.consume_use(&invite.id).await;
+    assert!(matches!(
+        result,
+        Err(GroupInviteStorageError::InviteExhausted)
+    ));
+
+    // The counter doesn't go below zero
+    let stored = ctx
+        .storage
+        .get_one(&invite.id)
+        .await
+        .expect("Failed to get group invite")
+        .expect("Invite not found");
+    assert_eq!(stored.remaining_uses, Some(0));
+}
+
+#[tokio::test]
+async fn test_consume_use_of_unlimited_invite() {
+    let ctx = setup_test().await;
+    let topic = format!("topic-{}", Uuid::new_v4());
+    let invite = ctx
+        .storage
+        .create(GroupInviteCreateRequest {
+            max_uses: None,
+            ..create_test_invite_request(&topic)
+        })
+        .await
+        .expect("Failed to create group invite");
+
+    for _ in 0..3 {
+        let consumed = ctx
+            .storage
+            .consume_use(&invite.id)
+            .await
+            .expect("Failed to consume use of unlimited invite");
+        assert_eq!(consumed.id, invite.id);
+        assert_eq!(consumed.max_uses, None);
+        assert_eq!(consumed.remaining_uses, None);
+    }
+}
+
+#[tokio::test]
+async fn test_consume_use_of_non_existing_invite() {
+    let ctx = setup_test().await;
+
+    let result = ctx.storage.consume_use(&Uuid::new_v4().to_string()).await;
+
+    assert!(matches!(
+        result,
+        Err(GroupInviteStorageError::InviteNotFound)
+    ));
+}
@@ -1,3 +1,4 @@
+use std::collections::HashSet;
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -9,7 +10,8 @@ use aws_sdk_dynamodb::types::{
 };
 use aws_sdk_dynamodb::Client as DynamoDbClient;
 use backend_storage::group_join_request::{
-    CreateGroupJoinRequest, GroupJoinRequestAttribute, GroupJoinRequestStorage, JoinRequestStatus,
+    CreateGroupJoinRequest, GroupJoinRequestAttribute, GroupJoinRequestStorage,
+    GroupJoinRequestStorageError, JoinRequestStatus,
 };
 use tokio::time::sleep;
 use uuid::Uuid;
@@ -18,6 +20,7 @@ use uuid::Uuid;
 const LOCALSTACK_ENDPOINT: &str = "http://localhost:4566";
 const TEST_REGION: &str = "us-east-1";
 const TEST_GROUP_INVITE_INDEX_NAME: &str = "group-invite-index";
+const TEST_NOTIFICATION_SENT_INDEX_NAME: &str = "status-notification-sent-index";
 
 /// Test context that automatically cleans up the table on drop
 struct TestContext {
@@ -90,6 +93,20 @@ async fn setup_test() -> TestContext {
                 .build()
                 .expect("Failed to build attribute definition"),
         )
+        .attribute_definitions(
+            AttributeDefinition::builder()
+                .attribute_name(GroupJoinRequestAttribute::Status.to_string())
+                .attribute_type(ScalarAttributeType::S)
+                .build()
+                .expect("Failed to build attribute definition"),
+        )
+        .attribute_definitions(
+            AttributeDefinition::builder()
+                .attribute_name(GroupJoinRequestAttribute::NotificationSentAt.to_string())
+                .attribute_type(ScalarAttributeType::N)
+                .build()
+                .expect("Failed to build attribute definition"),
+        )
         // Global Secondary Index for group invite queries
         .global_secondary_indexes(
             GlobalSecondaryIndex::builder()
@@ -109,6 +126,32 @@ async fn setup_test() -> TestContext {
                 .build()
                 .expect("Failed to build GSI"),
         )
+        // Global Secondary Index for notification escalation queries
+        .global_secondary_indexes(
+            GlobalSecondaryIndex::builder()
+                .index_name(TEST_NOTIFICATION_SENT_INDEX_NAME)
+                .key_schema(
+                    KeySchemaElement::builder()
+                        .attribute_name(GroupJoinRequestAttribute::Status.to_string())
+                        .key_type(KeyType::Hash)
+                        .build()
+                        .expect("Failed to build GSI key schema"),
+                )
+                .key_schema(
+                    KeySchemaElement::builder()
+                        .attribute_name(GroupJoinRequestAttribute::NotificationSentAt.to_string())
+                        .key_type(KeyType::Range)
+                        .build()
+                        .expect("Failed to build GSI key schema"),
+                )
+                .projection(
+                    Projection::builder()
+                        .projection_type(ProjectionType::All)
+                        .build(),
+                )
+                .build()
+                .expect("Failed to build GSI"),
+        )
         .send()
         .await
         .expect("Failed to create test table");
@@ -120,6 +163,7 @@ async fn setup_test() -> TestContext {
         dynamodb_client.clone(),
         table_name.clone(),
         TEST_GROUP_INVITE_INDEX_NAME.to_string(),
+        TEST_NOTIFICATION_SENT_INDEX_NAME.to_string(),
     );
 
     TestContext {
@@ -134,6 +178,7 @@ fn create_test_join_request(group_invite_id: &str) -> CreateGroupJoinRequest {
     CreateGroupJoinRequest {
         group_invite_id: group_invite_id.to_string(),
         encrypted_inbox_id: format!("encrypted_inbox_{}", Uuid::new_v4()),
+        idempotency_key: Uuid::new_v4().to_string(),
         status: JoinRequestStatus::Pending,
         notification_sent_at: Some(1_234_567_890),
     }
@@ -144,6 +189,7 @@ fn create_test_join_request_minimal(group_invite_id: &str) -> CreateGroupJoinReq
     CreateGroupJoinRequest {
         group_invite_id: group_invite_id.to_string(),
         encrypted_inbox_id: format!("encrypted_inbox_{}", Uuid::new_v4()),
+        idempotency_key: Uuid::new_v4().to_string(),
         status: JoinRequestStatus::Pending,
         notification_sent_at: None,
     }
@@ -173,6 +219,225 @@ async fn test_create_join_request() {
     );
 }
 
+#[tokio::test]
+async fn test_create_join_request_is_idempotent() {
+    let ctx = setup_test().await;
+    let group_invite_id = format!("invite-{}", Uuid::new_v4());
+    let request = create_test_join_request(&group_invite_id);
+
+    let first = ctx
+        .storage
+        .create(request.clone())
+        .await
+        .expect("Failed to create join request");
+    // Retries re-encrypt the inbox ID, only the idempotency key is stable
+    let retried = ctx
+        .storage
+        .create(CreateGroupJoinRequest {
+            encrypted_inbox_id: format!("encrypted_inbox_{}", Uuid::new_v4()),
+            ..request
+        })
+        .await
+        .expect("Failed to retry join request");
+
+    assert_eq!(retried.id, first.id);
+    assert_eq!(retried.created_at, first.created_at);
+
+    // Wait for GSI to be updated
+    sleep(Duration::from_millis(200)).await;
+
+    let stored = ctx
+        .storage
+        .get_by_group_invite_id(&group_invite_id)
+        .await
+        .expect("Failed to get by group invite id");
+    assert_eq!(stored.len(), 1);
+}
+
+#[tokio::test]
+async fn test_create_with_key_of_resolved_request_creates_new_request() {
+    let ctx = setup_test().await;
+    let group_invite_id = format!("invite-{}", Uuid::new_v4());
+    let request = create_test_join_request_minimal(&group_invite_id);
+
+    let first = ctx
+        .storage
+        .create(request.clone())
+        .await
+        .expect("Failed to create join request");
+    for status in [
+        JoinRequestStatus::NotificationSent,
+        JoinRequestStatus::Rejected,
+    ] {
+        ctx.storage
+            .update_status(&first.id, &status)
+            .await
+            .expect("Failed to update status")
+            .expect("Join request should exist");
+    }
+
+    let second = ctx
+        .storage
+        .create(request.clone())
+        .await
+        .expect("Failed to create join request");
+
+    assert_ne!(second.id, first.id);
+    assert_eq!(second.status, JoinRequestStatus::Pending);
+    // Retries of the new request return it rather than storing another one
+    let retried = ctx
+        .storage
+        .create(request)
+        .await
+        .expect("Failed to create join request");
+    assert_eq!(retried.id, second.id);
+    assert_eq!(
+        ctx.storage
+            .get_by_group_invite_id(&group_invite_id)
+            .await
+            .expect("Failed to get join requests")
+            .len(),
+        2
+    );
+    let first = ctx
+        .storage
+        .get_one(&first.id)
+        .await
+        .expect("Failed to get join request")
+        .expect("Join request should exist");
+    assert_eq!(first.status, JoinRequestStatus::Rejected);
+}
+
+#[tokio::test]
+async fn test_notification_sent_transition_keeps_first_timestamp() {
+    let ctx = setup_test().await;
+    let group_invite_id = format!("invite-{}", Uuid::new_v4());
+    let created = ctx
+        .storage
+        .create(create_test_join_request(&group_invite_id))
+        .await
+        .expect("Failed to create join request");
+    let first_notified_at = created.notification_sent_at;
+
+    for _ in 0..2 {
+        let updated = ctx
+            .storage
+            .update_status(&created.id, &JoinRequestStatus::NotificationSent)
+            .await
+            .expect("Failed to update status")
+            .expect("Join request should exist");
+        assert_eq!(updated.status, JoinRequestStatus::NotificationSent);
+        assert_eq!(updated.notification_sent_at, first_notified_at);
+    }
+
+    // Re-notifying explicitly moves the timestamp
+    let restamped = ctx
+        .storage
+        .restamp_notification_sent_at(&created.id)
+        .await
+        .expect("Failed to restamp")
+        .expect("Join request should exist");
+    assert!(restamped.notification_sent_at > first_notified_at);
+
+    // Unknown requests are not created by updates
+    let missing = ctx
+        .storage
+        .update_status("missing", &JoinRequestStatus::NotificationSent)
+        .await
+        .expect("Failed to update status");
+    assert!(missing.is_none());
+}
+
+#[tokio::test]
+async fn test_notification_sent_transition_stamps_unset_timestamp() {
+    let ctx = setup_test().await;
+    let group_invite_id = format!("invite-{}", Uuid::new_v4());
+    let created = ctx
+        .storage
+        .create(create_test_join_request_minimal(&group_invite_id))
+        .await
+        .expect("Failed to create join request");
+
+    let updated = ctx
+        .storage
+        .update_status(&created.id, &JoinRequestStatus::NotificationSent)
+        .await
+        .expect("Failed to update status")
+        .expect("Join request should exist");
+
+    assert!(updated.notification_sent_at.is_some());
+}
+
+#[tokio::test]
+async fn test_status_transitions_only_move_forward() {
+    let ctx = setup_test().await;
+    let group_invite_id = format!("invite-{}", Uuid::new_v4());
+    let created = ctx
+        .storage
+        .create(create_test_join_request_minimal(&group_invite_id))
+        .await
+        .expect("Failed to create join request");
+
+    // Pending requests can't be decided before the inviter is notified
+    let result = ctx
+        .storage
+        .update_status(&created.id, &JoinRequestStatus::Accepted)
+        .await;
+    assert!(matches!(
+        result,
+        Err(GroupJoinRequestStorageError::InvalidStatusTransition {
+            from: JoinRequestStatus::Pending,
+            to: JoinRequestStatus::Accepted,
+        })
+    ));
+
+    for status in [
+        JoinRequestStatus::NotificationSent,
+        JoinRequestStatus::Rejected,
+    ] {
+        let updated = ctx
+            .storage
+            .update_status(&created.id, &status)
+            .await
+            .expect("Failed to update status")
+            .expect("Join request should exist");
+        assert_eq!(updated.status, status);
+    }
+
+    // Decided requests are final
+    for status in [
+        JoinRequestStatus::Pending,
+        JoinRequestStatus::NotificationSent,
+        JoinRequestStatus::Accepted,
+    ] {
+        let result = ctx.storage.update_status(&created.id, &status).await;
+        assert!(matches!(
+            result,
+            Err(GroupJoinRequestStorageError::InvalidStatusTransition {
+                from: JoinRequestStatus::Rejected,
+                ..
+            })
+        ));
+    }
+
+    let stored = ctx
+        .storage
+        .get_one(&created.id)
+        .await
+        .expect("Failed to get join request")
+        .expect("Join request should exist");
+    assert_eq!(stored.status, JoinRequestStatus::Rejected);
+    assert!(stored.notification_sent_at.is_some());
+
+    // Unknown requests are still reported as missing
+    let missing = ctx
+        .storage
+        .update_status("missing", &JoinRequestStatus::Pending)
+        .await
+        .expect("Failed to update status");
+    assert!(missing.is_none());
+}
+
 #[tokio::test]
 async fn test_create_join_request_without_optional_fields() {
     let ctx = setup_test().await;
@@ -282,6 +547,112 @@ async fn test_get_by_group_invite_id() {
     }
 }
 
+#[tokio::test]
+async fn test_get_by_group_invite_id_spans_multiple_pages() {
+    let ctx = setup_test().await;
+    let group_invite_id = format!("invite-{}", Uuid::new_v4());
+
+    let mut created_ids = HashSet::new();
+    for _ in 0..5 {
+        let created = ctx
+            .storage
+            .create(create_test_join_request(&group_invite_id))
+            .await
+            .expect("Failed to create join request");
+        created_ids.insert(created.id);
+    }
+
+    // Wait for GSI to be updated
+    sleep(Duration::from_millis(200)).await;
+
+    // Page through the requests two at a time
+    let mut paged_ids = HashSet::new();
+    let mut pages = 0;
+    let mut cursor = None;
+    loop {
+        let page = ctx
+            .storage
+            .get_page_by_group_invite_id(&group_invite_id, Some(2), cursor)
+            .await
+            .expect("Failed to get page by group invite id");
+        assert!(page.requests.len() <= 2);
+        paged_ids.extend(page.requests.into_iter().map(|request| request.id));
+        pages += 1;
+
+        cursor = page.next_cursor;
+        if cursor.is_none() {
+            break;
+        }
+    }
+
+    assert!(pages >= 3, "Expected at least 3 pages, got {pages}");
+    assert_eq!(paged_ids, created_ids);
+
+    // The non-paginated query returns every request
+    let results = ctx
+        .storage
+        .get_by_group_invite_id(&group_invite_id)
+        .await
+        .expect("Failed to get by group invite id");
+    let all_ids = results
+        .into_iter()
+        .map(|request| request.id)
+        .collect::<HashSet<_>>();
+    assert_eq!(all_ids, created_ids);
+}
+
+#[tokio::test]
+async fn test_get_notified_before() {
+    let ctx = setup_test().await;
+    let group_invite_id = format!("invite-{}", Uuid::new_v4());
+    let cutoff = 1_700_000_000;
+
+    let mut expected_ids = HashSet::new();
+    for (status, notification_sent_at) in [
+        (JoinRequestStatus::NotificationSent, Some(cutoff - 3600)),
+        (JoinRequestStatus::NotificationSent, Some(cutoff - 1)),
+        // Notified at or after the cutoff
+        (JoinRequestStatus::NotificationSent, Some(cutoff)),
+        (JoinRequestStatus::NotificationSent, Some(cutoff + 3600)),
+        // Different status
+        (JoinRequestStatus::Accepted, Some(cutoff - 3600)),
+        // Never notified
+        (JoinRequestStatus::NotificationSent, None),
+    ] {
+        let should_match = status == JoinRequestStatus::NotificationSent
+            && notification_sent_at.is_some_and(|sent_at| sent_at < cutoff);
+        let created = ctx
+            .storage
+            .create(CreateGroupJoinRequest {
+                group_invite_id: group_invite_id.clone(),
+                encrypted_inbox_id: format!("encrypted_inbox_{}", Uuid::new_v4()),
+                idempotency_key: Uuid::new_v4().to_string(),
+                status,
+                notification_sent_at,
+            })
+            .await
+            .expect("Failed to create join request");
+        if should_match {
+            expected_ids.insert(created.id);
+        }
+    }
+
+    // Wait for GSI to be updated
+    sleep(Duration::from_millis(200)).await;
+
+    let results = ctx
+        .storage
+        .get_notified_before(&JoinRequestStatus::NotificationSent, cutoff)
+        .await
+        .expect("Failed to get notified join requests");
+
+    let result_ids = results
+        .into_iter()
+        .map(|request| request.id)
+        .collect::<HashSet<_>>();
+    assert_eq!(result_ids, expected_ids);
+}
+
 #[tokio::test]
 async fn test_delete_by_group_invite_id_single() {
     let ctx = setup_test().await;
@@ -405,7 +776,7 @@ async fn test_batch_delete_over_25_items() {
     let ctx = setup_test().await;
     let group_invite_id = format!("invite-{}", Uuid::new_v4());
 
-    // Create 30 join requests (more than DynamoDB's batch limit of 25)
+    // Create 30 join requests (more than DynamoDB's batch limit of 25, deleted in two pages)
     let mut created_ids = Vec::new();
     for _ in 0..30 {
         let request = create_test_join_request(&group_invite_id);
@@ -2,8 +2,10 @@
 
 mod common;
 
+use std::time::Duration;
+
 use crate::common::{assert_queue_message, QueueTestContext};
-use backend_storage::queue::{Notification, NotificationQueue, QueueConfig};
+use backend_storage::queue::{Notification, NotificationQueue, QueueConfig, QueueError};
 use pretty_assertions::assert_eq;
 
 #[tokio::test]
@@ -27,6 +29,7 @@ async fn test_send_consume_ack_happy_path() {
             "encrypted_push_id_2".to_string(),
         ],
         encrypted_message_base64: "eyJ0aXRsZSI6IkJyZWFraW5nIE5ld3MiLCJjb250ZW50IjoiSW1wb3J0YW50IHVwZGF0ZSIsInRpbWVzdGFtcCI6IjIwMjQtMDEtMDFUMTI6MDA6MDBaIn0=".to_string(),
+        requeue_attempt: 0,
     };
 
     // Send message
@@ -82,18 +85,21 @@ async fn test_fifo_topic_based_grouping() {
         topic: "news".to_string(),
         subscribed_encrypted_push_ids: vec!["enc_push_news_1".to_string()],
         encrypted_message_base64: "encoded_news_1_base64".to_string(),
+        requeue_attempt: 0,
     };
 
     let alert1 = Notification {
         topic: "alerts".to_string(),
         subscribed_encrypted_push_ids: vec!["enc_push_alert_1".to_string()],
         encrypted_message_base64: "encoded_alert_1_base64".to_string(),
+        requeue_attempt: 0,
     };
 
     let news2 = Notification {
         topic: "news".to_string(),
         subscribed_encrypted_push_ids: vec!["enc_push_news_2".to_string()],
         encrypted_message_base64: "encoded_news_2_base64".to_string(),
+        requeue_attempt: 0,
     };
 
     // Send messages
@@ -145,12 +151,14 @@ async fn test_fifo_topic_based_grouping() {
         topic: "news".to_string(),
         subscribed_encrypted_push_ids: vec!["enc_push_news_3".to_string()],
         encrypted_message_base64: "encoded_news_3_base64".to_string(),
+        requeue_attempt: 0,
     };
 
     let news4 = Notification {
         topic: "news".to_string(),
         subscribed_encrypted_push_ids: vec!["enc_push_news_4".to_string()],
         encrypted_message_base64: "encoded_news_4_base64".to_string(),
+        requeue_attempt: 0,
     };
 
     queue
@@ -178,3 +186,300 @@ async fn test_fifo_topic_based_grouping() {
         "encoded_news_4_base64"
     );
 }
+
+#[tokio::test]
+async fn test_peek_does_not_consume_messages() {
+    let ctx = QueueTestContext::new("notification-peek").await;
+
+    let config = QueueConfig {
+        queue_url: ctx.queue_url.clone(),
+        default_max_messages: 10,
+        default_visibility_timeout: 60,
+        default_wait_time_seconds: 0,
+    };
+    let queue = NotificationQueue::new(ctx.sqs_client.clone(), config);
+
+    let notification = Notification {
+        topic: "dead_letters".to_string(),
+        subscribed_encrypted_push_ids: vec!["encrypted_push_id_1".to_string()],
+        encrypted_message_base64: "ZGVhZCBsZXR0ZXI=".to_string(),
+        requeue_attempt: 0,
+    };
+    let message_id = queue
+        .send_message(&notification)
+        .await
+        .expect("Failed to send notification");
+
+    // Peeking twice returns the same message, as it is neither hidden nor deleted
+    for expected_receive_count in 1..=2 {
+        let peeked = queue
+            .peek_messages(10)
+            .await
+            .expect("Failed to peek messages");
+        assert_eq!(peeked.len(), 1);
+        assert_eq!(peeked[0].message_id, message_id);
+        assert_eq!(peeked[0].body.as_ref(), Some(&notification));
+        assert_eq!(peeked[0].receive_count, expected_receive_count);
+        assert!(peeked[0].sent_timestamp_ms.is_some());
+    }
+
+    // The message is still available to consumers
+    let messages = queue
+        .poll_messages()
+        .await
+        .expect("Failed to poll messages");
+    assert_eq!(messages.len(), 1);
+    assert_queue_message(&messages[0], &notification);
+}
+
+#[tokio::test]
+async fn test_peek_returns_undecodable_messages_raw() {
+    let ctx = QueueTestContext::new("notification-peek-raw").await;
+
+    let config =
+        QueueConfig::new(ctx.queue_url.clone(), 10, 60, 0).expect("Invalid queue configuration");
+    let queue = NotificationQueue::new(ctx.sqs_client.clone(), config);
+
+    ctx.sqs_client
+        .send_message()
+        .queue_url(&ctx.queue_url)
+        .message_body("not a notification")
+        .message_group_id("dead_letters")
+        .send()
+        .await
+        .expect("Failed to send raw message");
+
+    let peeked = queue
+        .peek_messages(10)
+        .await
+        .expect("Failed to peek messages");
+    assert_eq!(peeked.len(), 1);
+    assert!(peeked[0].body.is_none());
+    assert_eq!(peeked[0].raw_body, "not a notification");
+}
+
+#[tokio::test]
+async fn test_requeue_makes_message_visible_again() {
+    let ctx = QueueTestContext::new("notification-requeue").await;
+
+    let config = QueueConfig {
+        queue_url: ctx.queue_url.clone(),
+        default_max_messages: 10,
+        default_visibility_timeout: 60,
+        default_wait_time_seconds: 0,
+    };
+    let queue = NotificationQueue::new(ctx.sqs_client.clone(), config);
+
+    let notification = Notification {
+        topic: "requeued".to_string(),
+        subscribed_encrypted_push_ids: vec!["encrypted_push_id_1".to_string()],
+        encrypted_message_base64: "cmVxdWV1ZWQ=".to_string(),
+        requeue_attempt: 0,
+    };
+    queue
+        .send_message(&notification)
+        .await
+        .expect("Failed to send notification");
+
+    let messages = queue
+        .poll_messages()
+        .await
+        .expect("Failed to poll messages");
+    assert_eq!(messages.len(), 1);
+
+    // Without the requeue the message would stay hidden for the 60s visibility timeout
+    queue
+        .requeue_message(&messages[0].receipt_handle, 0)
+        .await
+        .expect("Failed to requeue message");
+
+    let messages = queue
+        .poll_messages()
+        .await
+        .expect("Failed to poll messages");
+    assert_eq!(messages.len(), 1);
+    assert_queue_message(&messages[0], &notification);
+}
+
+#[tokio::test]
+async fn test_enqueue_delayed_hides_message_until_delay_passes() {
+    let ctx = QueueTestContext::new_standard("notification-delayed").await;
+
+    let config =
+        QueueConfig::new(ctx.queue_url.clone(), 10, 60, 0).expect("Invalid queue configuration");
+    let queue = NotificationQueue::new(ctx.sqs_client.clone(), config);
+
+    let notification = Notification {
+        topic: "delayed".to_string(),
+        subscribed_encrypted_push_ids: vec!["encrypted_push_id_1".to_string()],
+        encrypted_message_base64: "ZGVsYXllZA==".to_string(),
+        requeue_attempt: 0,
+    };
+    queue
+        .enqueue_delayed(&notification, 2)
+        .await
+        .expect("Failed to enqueue delayed notification");
+
+    let messages = queue
+        .poll_messages()
+        .await
+        .expect("Failed to poll messages");
+    assert!(messages.is_empty(), "Delayed message should not be visible");
+
+    tokio::time::sleep(Duration::from_secs(3)).await;
+
+    let messages = queue
+        .poll_messages()
+        .await
+        .expect("Failed to poll messages");
+    assert_eq!(messages.len(), 1);
+    assert_queue_message(&messages[0], &notification);
+}
+
+#[tokio::test]
+async fn test_enqueue_delayed_on_fifo_queue_hides_message_until_due() {
+    let ctx = QueueTestContext::new("notification-delayed-fifo").await;
+
+    let config =
+        QueueConfig::new(ctx.queue_url.clone(), 10, 60, 0).expect("Invalid queue configuration");
+    let queue = NotificationQueue::new(ctx.sqs_client.clone(), config);
+
+    let delayed = Notification {
+        topic: "delayed".to_string(),
+        subscribed_encrypted_push_ids: vec!["encrypted_push_id_1".to_string()],
+        encrypted_message_base64: "ZGVsYXllZA==".to_string(),
+        requeue_attempt: 0,
+    };
+    let immediate = Notification {
+        topic: "delayed".to_string(),
+        subscribed_encrypted_push_ids: vec!["encrypted_push_id_2".to_string()],
+        encrypted_message_base64: "aW1tZWRpYXRl".to_string(),
+        requeue_attempt: 0,
+    };
+    queue
+        .enqueue_delayed(&delayed, 2)
+        .await
+        .expect("Failed to enqueue delayed notification");
+    queue
+        .send_message(&immediate)
+        .await
+        .expect("Failed to send notification");
+
+    // The delayed notification doesn't hold back later ones of the same topic
+    let messages = queue
+        .poll_messages()
+        .await
+        .expect("Failed to poll messages");
+    assert_eq!(messages.len(), 1);
+    assert_queue_message(&messages[0], &immediate);
+    queue
+        .ack_message(&messages[0].receipt_handle)
+        .await
+        .expect("Failed to ack message");
+
+    tokio::time::sleep(Duration::from_secs(3)).await;
+
+    // Once due, the delayed notification is sent again, so its earlier receives don't count
+    let messages = queue
+        .poll_messages()
+        .await
+        .expect("Failed to poll messages");
+    assert!(messages.is_empty());
+    let peeked = queue
+        .peek_messages(10)
+        .await
+        .expect("Failed to peek messages");
+    assert_eq!(peeked.len(), 1);
+    assert_eq!(peeked[0].body.as_ref(), Some(&delayed));
+    assert_eq!(peeked[0].receive_count, 1, "Only the peek should count");
+
+    let messages = queue
+        .poll_messages()
+        .await
+        .expect("Failed to poll messages");
+    assert_eq!(messages.len(), 1);
+    assert_queue_message(&messages[0], &delayed);
+}
+
+#[tokio::test]
+async fn test_enqueue_delayed_repeating_a_message_is_not_deduplicated() {
+    let ctx = QueueTestContext::new("notification-delayed-repeat").await;
+
+    let config =
+        QueueConfig::new(ctx.queue_url.clone(), 10, 60, 0).expect("Invalid queue configuration");
+    let queue = NotificationQueue::new(ctx.sqs_client.clone(), config);
+
+    let notification = Notification {
+        topic: "repeated".to_string(),
+        subscribed_encrypted_push_ids: vec!["encrypted_push_id_1".to_string()],
+        encrypted_message_base64: "cmVwZWF0ZWQ=".to_string(),
+        requeue_attempt: 0,
+    };
+    queue
+        .send_message(&notification)
+        .await
+        .expect("Failed to send notification");
+    let messages = queue
+        .poll_messages()
+        .await
+        .expect("Failed to poll messages");
+    assert_eq!(messages.len(), 1);
+    queue
+        .ack_message(&messages[0].receipt_handle)
+        .await
+        .expect("Failed to ack message");
+
+    // Retrying the same notification within the deduplication interval still delivers it
+    queue
+        .enqueue_delayed(&notification, 1)
+        .await
+        .expect("Failed to enqueue delayed notification");
+    tokio::time::sleep(Duration::from_secs(2)).await;
+
+    let mut delivered = Vec::new();
+    for _ in 0..2 {
+        delivered.extend(
+            queue
+                .poll_messages()
+                .await
+                .expect("Failed to poll messages"),
+        );
+    }
+    assert_eq!(delivered.len(), 1);
+    assert_queue_message(&delivered[0], &notification);
+}
+
+#[tokio::test]
+async fn test_enqueue_delayed_rejects_invalid_delays() {
+    let ctx = QueueTestContext::new("notification-delayed-invalid").await;
+
+    let config =
+        QueueConfig::new(ctx.queue_url.clone(), 10, 60, 0).expect("Invalid queue configuration");
+    let queue = NotificationQueue::new(ctx.sqs_client.clone(), config);
+
+    let notification = Notification {
+        topic: "delayed".to_string(),
+        subscribed_encrypted_push_ids: vec!["encrypted_push_id_1".to_string()],
+        encrypted_message_base64: "ZGVsYXllZA==".to_string(),
+        requeue_attempt: 0,
+    };
+
+    for delay_seconds in [-1, 901] {
+        assert!(matches!(
+            queue.enqueue_delayed(&notification, delay_seconds).await,
+            Err(QueueError::DelayOutOfRange(delay)) if delay == delay_seconds
+        ));
+    }
+
+    // Nothing was sent, but a zero delay is a regular send
+    queue
+        .enqueue_delayed(&notification, 0)
+        .await
+        .expect("Failed to enqueue notification without delay");
+    let messages = queue
+        .poll_messages()
+        .await
+        .expect("Failed to poll messages");
+    assert_eq!(messages.len(), 1);
+    assert_queue_message(&messages[0], &notification);
+}
@@ -5,18 +5,26 @@ use std::time::Duration;
 use aws_config::{BehaviorVersion, Region};
 use aws_credential_types::Credentials;
 use aws_sdk_dynamodb::types::{
-    AttributeDefinition, KeySchemaElement, KeyType, ScalarAttributeType,
+    AttributeDefinition, GlobalSecondaryIndex, KeySchemaElement, KeyType, Projection,
+    ProjectionType, ScalarAttributeType,
 };
 use aws_sdk_dynamodb::Client as DynamoDbClient;
+use backend_storage::ids::{EncryptedPushId, TopicId};
 use backend_storage::push_subscription::{
-    PushSubscription, PushSubscriptionAttribute, PushSubscriptionStorage,
+    subscribe_invalidations, InvalidSubscriptionKey, PushSubscription, PushSubscriptionAttribute,
+    PushSubscriptionStorage, PushSubscriptionStorageError, SubscriptionInvalidationPublisher,
+    SubscriptionKey, SubscriptionWriteOutcome, DEFAULT_TTL_OFFSET_SECS, HMAC_KEY_HEX_LENGTH,
 };
 use chrono::Utc;
+use futures::StreamExt;
+use redis::aio::ConnectionManager;
 use uuid::Uuid;
 
 /// Test configuration for LocalStack
 const LOCALSTACK_ENDPOINT: &str = "http://localhost:4566";
 const TEST_REGION: &str = "us-east-1";
+const REDIS_URL: &str = "redis://localhost:6379";
+const PUSH_ID_INDEX_NAME: &str = "encrypted-push-id-index";
 
 /// Test context that automatically cleans up the table on drop
 struct TestContext {
@@ -79,6 +87,13 @@ async fn setup_test() -> TestContext {
                 .build()
                 .unwrap(),
         )
+        .attribute_definitions(
+            AttributeDefinition::builder()
+                .attribute_name(PushSubscriptionAttribute::EncryptedPushId.to_string())
+                .attribute_type(ScalarAttributeType::S)
+                .build()
+                .unwrap(),
+        )
         .key_schema(
             KeySchemaElement::builder()
                 .attribute_name(PushSubscriptionAttribute::Topic.to_string())
@@ -93,6 +108,24 @@ async fn setup_test() -> TestContext {
                 .build()
                 .unwrap(),
         )
+        .global_secondary_indexes(
+            GlobalSecondaryIndex::builder()
+                .index_name(PUSH_ID_INDEX_NAME)
+                .key_schema(
+                    KeySchemaElement::builder()
+                        .attribute_name(PushSubscriptionAttribute::EncryptedPushId.to_string())
+                        .key_type(KeyType::Hash)
+                        .build()
+                        .unwrap(),
+                )
+                .projection(
+                    Projection::builder()
+                        .projection_type(ProjectionType::KeysOnly)
+                        .build(),
+                )
+                .build()
+                .unwrap(),
+        )
         .billing_mode(aws_sdk_dynamodb::types::BillingMode::PayPerRequest)
         .send()
         .await
@@ -116,7 +149,11 @@ async fn setup_test() -> TestContext {
     // Wait a bit for table to be ready
     tokio::time::sleep(Duration::from_millis(100)).await;
 
-    let storage = PushSubscriptionStorage::new(dynamodb_client.clone(), table_name.clone());
+    let storage = PushSubscriptionStorage::new(
+        dynamodb_client.clone(),
+        table_name.clone(),
+        PUSH_ID_INDEX_NAME.to_string(),
+    );
 
     TestContext {
         storage,
@@ -125,14 +162,26 @@ async fn setup_test() -> TestContext {
     }
 }
 
+/// Creates a unique, well-formed HMAC key
+fn test_hmac_key() -> String {
+    let hex = format!(
+        "{}{}{}",
+        Uuid::new_v4().simple(),
+        Uuid::new_v4().simple(),
+        Uuid::new_v4().simple()
+    );
+    hex[..HMAC_KEY_HEX_LENGTH].to_string()
+}
+
 /// Creates a test subscription with unique HMAC key
 fn create_test_subscription(topic: &str) -> PushSubscription {
     PushSubscription {
         topic: topic.to_string(),
-        hmac_key: format!("test-hmac-{}", Uuid::new_v4()),
+        hmac_key: test_hmac_key(),
         ttl: (Utc::now() + chrono::Duration::hours(24)).timestamp(),
         encrypted_push_id: format!("encrypted-{}", Uuid::new_v4()),
         deletion_request: None,
+        installation_id: None,
     }
 }
 
@@ -148,10 +197,11 @@ fn create_test_subscription_with_deletion(
 
     PushSubscription {
         topic: topic.to_string(),
-        hmac_key: format!("test-hmac-{}", Uuid::new_v4()),
+        hmac_key: test_hmac_key(),
         ttl: (Utc::now() + chrono::Duration::hours(24)).timestamp(),
         encrypted_push_id: format!("encrypted-{}", Uuid::new_v4()),
         deletion_request: Some(deletion_set),
+        installation_id: None,
     }
 }
 
@@ -172,7 +222,7 @@ async fn test_basic_insert_and_get_operations() {
     // Get by topic and hmac
     let retrieved = context
         .storage
-        .get_one(&subscription.topic, &subscription.hmac_key)
+        .get_one(&subscription.key())
         .await
         .expect("Failed to get by topic and hmac");
 
@@ -186,7 +236,7 @@ async fn test_basic_insert_and_get_operations() {
     // Get by topic
     let subscriptions = context
         .storage
-        .get_all_by_topic(&subscription.topic)
+        .get_all_by_topic(&TopicId::from(subscription.topic.as_str()))
         .await
         .expect("Failed to get by topic");
     assert_eq!(subscriptions.len(), 1);
@@ -244,14 +294,14 @@ async fn test_insert_duplicate_prevention() {
     // Should have one subscription for original topic and one for different topic
     let original_subscriptions = context
         .storage
-        .get_all_by_topic(&subscription.topic)
+        .get_all_by_topic(&TopicId::from(subscription.topic.as_str()))
         .await
         .expect("Failed to get all by original topic");
     assert_eq!(original_subscriptions.len(), 1);
 
     let different_subscriptions = context
         .storage
-        .get_all_by_topic(&different_topic_subscription.topic)
+        .get_all_by_topic(&TopicId::from(different_topic_subscription.topic.as_str()))
         .await
         .expect("Failed to get all by different topic");
     assert_eq!(different_subscriptions.len(), 1);
@@ -294,7 +344,7 @@ async fn test_get_all_by_topic_multiple_subscriptions() {
     // Query by shared topic
     let retrieved = context
         .storage
-        .get_all_by_topic(topic)
+        .get_all_by_topic(&TopicId::from(topic))
         .await
         .expect("Failed to query by topic");
 
@@ -315,7 +365,7 @@ async fn test_get_all_by_topic_multiple_subscriptions() {
     // Query by other topic
     let other_retrieved = context
         .storage
-        .get_all_by_topic(other_topic)
+        .get_all_by_topic(&TopicId::from(other_topic))
         .await
         .expect("Failed to query by other topic");
 
@@ -325,13 +375,108 @@ async fn test_get_all_by_topic_multiple_subscriptions() {
     // Query non-existent topic
     let empty = context
         .storage
-        .get_all_by_topic("non-existent")
+        .get_all_by_topic(&TopicId::from("non-existent"))
         .await
         .expect("Failed to query non-existent topic");
 
     assert_eq!(empty.len(), 0);
 }
 
+#[tokio::test]
+async fn test_stream_by_topic_yields_all_pages() {
+    let context = setup_test().await;
+
+    let topic = "streamed-topic";
+    let mut inserted = HashSet::new();
+    for _ in 0..5 {
+        let sub = create_test_subscription(topic);
+        context
+            .storage
+            .insert(&sub)
+            .await
+            .expect("Failed to insert");
+        inserted.insert(sub.hmac_key);
+    }
+
+    // Two subscriptions per page, so the stream has to fetch at least three pages
+    let streamed = context
+        .storage
+        .stream_by_topic(&TopicId::from(topic), Some(2))
+        .map(|subscription| subscription.expect("Failed to stream by topic").hmac_key)
+        .collect::<HashSet<_>>()
+        .await;
+
+    assert_eq!(streamed, inserted);
+}
+
+#[tokio::test]
+async fn test_count_by_topic() {
+    let context = setup_test().await;
+    let topic = "counted-topic";
+
+    let expired = PushSubscription {
+        ttl: (Utc::now() - chrono::Duration::days(2)).timestamp(),
+        ..create_test_subscription(topic)
+    };
+    let subscriptions: Vec<_> = (0..5)
+        .map(|_| create_test_subscription(topic))
+        .chain([expired, create_test_subscription("other-topic")])
+        .collect();
+    for subscription in &subscriptions {
+        context
+            .storage
+            .insert(subscription)
+            .await
+            .expect("Failed to insert subscription");
+    }
+
+    // The expired subscription and the other topic aren't counted
+    let count = context
+        .storage
+        .count_by_topic(&TopicId::from(topic))
+        .await
+        .expect("Failed to count subscriptions");
+    assert_eq!(count, 5);
+
+    for empty_topic in ["missing-topic", ""] {
+        let count = context
+            .storage
+            .count_by_topic(&TopicId::from(empty_topic))
+            .await
+            .expect("Failed to count subscriptions");
+        assert_eq!(count, 0);
+    }
+}
+
+#[tokio::test]
+async fn test_get_all_by_topic_skips_expired_subscriptions() {
+    let context = setup_test().await;
+    let topic = "expiring-topic";
+
+    let live = create_test_subscription(topic);
+    // Past the maximum TTL offset, so it is still expired once stored
+    let expired = PushSubscription {
+        ttl: (Utc::now() - chrono::Duration::days(2)).timestamp(),
+        ..create_test_subscription(topic)
+    };
+    for subscription in [&live, &expired] {
+        context
+            .storage
+            .insert(subscription)
+            .await
+            .expect("Failed to insert subscription");
+    }
+
+    let retrieved = context
+        .storage
+        .get_all_by_topic(&TopicId::from(topic))
+        .await
+        .expect("Failed to query by topic");
+
+    assert_eq!(retrieved.len(), 1);
+    assert_eq!(retrieved[0].hmac_key, live.hmac_key);
+}
+
 #[tokio::test]
 async fn test_get_one_not_found() {
     let context = setup_test().await;
@@ -339,7 +484,7 @@ async fn test_get_one_not_found() {
     // Try to get non-existent subscription
     let result = context
         .storage
-        .get_one("non-existent-topic", "non-existent-hmac")
+        .get_one(&SubscriptionKey::new("non-existent-topic", test_hmac_key()))
         .await
         .expect("Failed to query non-existent subscription");
 
@@ -366,7 +511,7 @@ async fn test_deletion_request_serialization() {
     // Retrieve and verify deletion request is preserved
     let retrieved = context
         .storage
-        .get_one(&subscription.topic, &subscription.hmac_key)
+        .get_one(&subscription.key())
         .await
         .expect("Failed to get subscription")
         .expect("Subscription should exist");
@@ -396,7 +541,7 @@ async fn test_subscription_without_deletion_request() {
     // Retrieve and verify deletion request is None
     let retrieved = context
         .storage
-        .get_one(&subscription.topic, &subscription.hmac_key)
+        .get_one(&subscription.key())
         .await
         .expect("Failed to get subscription")
         .expect("Subscription should exist");
@@ -420,7 +565,7 @@ async fn test_delete_subscription() {
     // Verify it exists
     let retrieved = context
         .storage
-        .get_one(&subscription.topic, &subscription.hmac_key)
+        .get_one(&subscription.key())
         .await
         .expect("Failed to get subscription");
     assert!(retrieved.is_some());
@@ -428,14 +573,14 @@ async fn test_delete_subscription() {
     // Delete the subscription
     context
         .storage
-        .delete(&subscription.topic, &subscription.hmac_key)
+        .delete(&subscription.key())
         .await
         .expect("Failed to delete subscription");
 
     // Verify it no longer exists
     let retrieved_after_delete = context
         .storage
-        .get_one(&subscription.topic, &subscription.hmac_key)
+        .get_one(&subscription.key())
         .await
         .expect("Failed to get subscription after delete");
     assert!(retrieved_after_delete.is_none());
@@ -443,7 +588,7 @@ async fn test_delete_subscription() {
     // Verify topic query returns empty
     let subscriptions = context
         .storage
-        .get_all_by_topic(&subscription.topic)
+        .get_all_by_topic(&TopicId::from(subscription.topic.as_str()))
         .await
         .expect("Failed to get all by topic after delete");
     assert_eq!(subscriptions.len(), 0);
@@ -456,7 +601,7 @@ async fn test_delete_nonexistent_subscription() {
     // Delete non-existent subscription should not fail
     context
         .storage
-        .delete("non-existent-topic", "non-existent-hmac")
+        .delete(&SubscriptionKey::new("non-existent-topic", test_hmac_key()))
         .await
         .expect("Delete of non-existent subscription should not fail");
 }
@@ -477,7 +622,7 @@ async fn test_append_delete_request_functionality() {
     // Verify initial state has no deletion request
     let retrieved = context
         .storage
-        .get_one(&subscription.topic, &subscription.hmac_key)
+        .get_one(&subscription.key())
         .await
         .expect("Failed to get subscription")
         .expect("Subscription should exist");
@@ -488,9 +633,8 @@ async fn test_append_delete_request_functionality() {
     context
         .storage
         .append_delete_request(
-            &subscription.topic,
-            &subscription.hmac_key,
-            first_request_id,
+            &subscription.key(),
+            &EncryptedPushId::from(first_request_id),
         )
         .await
         .expect("Failed to append first delete request");
@@ -498,7 +642,7 @@ async fn test_append_delete_request_functionality() {
     // Verify deletion request set was created with one entry
     let retrieved = context
         .storage
-        .get_one(&subscription.topic, &subscription.hmac_key)
+        .get_one(&subscription.key())
         .await
         .expect("Failed to get subscription")
         .expect("Subscription should exist");
@@ -512,9 +656,8 @@ async fn test_append_delete_request_functionality() {
     context
         .storage
         .append_delete_request(
-            &subscription.topic,
-            &subscription.hmac_key,
-            second_request_id,
+            &subscription.key(),
+            &EncryptedPushId::from(second_request_id),
         )
         .await
         .expect("Failed to append second delete request");
@@ -522,7 +665,7 @@ async fn test_append_delete_request_functionality() {
     // Verify deletion request set now has two entries
     let retrieved = context
         .storage
-        .get_one(&subscription.topic, &subscription.hmac_key)
+        .get_one(&subscription.key())
         .await
         .expect("Failed to get subscription")
         .expect("Subscription should exist");
@@ -536,9 +679,8 @@ async fn test_append_delete_request_functionality() {
     context
         .storage
         .append_delete_request(
-            &subscription.topic,
-            &subscription.hmac_key,
-            first_request_id,
+            &subscription.key(),
+            &EncryptedPushId::from(first_request_id),
         )
         .await
         .expect("Failed to append duplicate delete request");
@@ -546,7 +688,7 @@ async fn test_append_delete_request_functionality() {
     // Verify deletion request set still has only two unique entries
     let retrieved = context
         .storage
-        .get_one(&subscription.topic, &subscription.hmac_key)
+        .get_one(&subscription.key())
         .await
         .expect("Failed to get subscription")
         .expect("Subscription should exist");
@@ -560,3 +702,440 @@ async fn test_append_delete_request_functionality() {
     assert!(deletion_requests.contains(first_request_id));
     assert!(deletion_requests.contains(second_request_id));
 }
+
+#[tokio::test]
+async fn test_clear_deletion_requests() {
+    let context = setup_test().await;
+
+    let subscription =
+        create_test_subscription_with_deletion("test-topic", vec!["delete-request-1".to_string()]);
+    context
+        .storage
+        .insert(&subscription)
+        .await
+        .expect("Failed to insert subscription");
+
+    // Requests are appended to the set the subscription was stored with
+    context
+        .storage
+        .append_delete_request(
+            &subscription.key(),
+            &EncryptedPushId::from("delete-request-2"),
+        )
+        .await
+        .expect("Failed to append delete request");
+    let retrieved = context
+        .storage
+        .get_one(&subscription.key())
+        .await
+        .expect("Failed to get subscription")
+        .expect("Subscription should exist");
+    assert_eq!(
+        retrieved.deletion_request.map(|requests| requests.len()),
+        Some(2)
+    );
+
+    context
+        .storage
+        .clear_deletion_requests(&subscription.key())
+        .await
+        .expect("Failed to clear deletion requests");
+
+    let retrieved = context
+        .storage
+        .get_one(&subscription.key())
+        .await
+        .expect("Failed to get subscription")
+        .expect("Subscription should exist");
+    assert!(retrieved.deletion_request.is_none());
+    assert_eq!(retrieved.encrypted_push_id, subscription.encrypted_push_id);
+
+    // Clearing again is a no-op
+    context
+        .storage
+        .clear_deletion_requests(&subscription.key())
+        .await
+        .expect("Failed to clear empty deletion requests");
+
+    // Missing subscriptions aren't created
+    let missing = create_test_subscription("test-topic");
+    let result = context
+        .storage
+        .clear_deletion_requests(&missing.key())
+        .await;
+    assert!(matches!(
+        result,
+        Err(PushSubscriptionStorageError::NotFound)
+    ));
+}
+
+#[tokio::test]
+async fn test_insert_many_reports_outcomes() {
+    let context = setup_test().await;
+
+    let existing = create_test_subscription("insert-many-topic");
+    context
+        .storage
+        .insert(&existing)
+        .await
+        .expect("Failed to insert subscription");
+    let new = create_test_subscription("insert-many-topic");
+
+    let outcomes = context
+        .storage
+        .insert_many(&[existing.clone(), new.clone()])
+        .await;
+
+    assert!(matches!(
+        outcomes.as_slice(),
+        [
+            Ok(SubscriptionWriteOutcome::Refreshed),
+            Ok(SubscriptionWriteOutcome::Created)
+        ]
+    ));
+    for subscription in [&existing, &new] {
+        assert!(context
+            .storage
+            .get_one(&subscription.key())
+            .await
+            .expect("Failed to get subscription")
+            .is_some());
+    }
+}
+
+#[tokio::test]
+async fn test_batch_insert_writes_every_subscription() {
+    let context = setup_test().await;
+
+    // More than one batch write request, with a duplicate of the first subscription
+    let subscriptions: Vec<_> = (0..30)
+        .map(|_| create_test_subscription("batch-insert-topic"))
+        .collect();
+    let mut updated_first = subscriptions[0].clone();
+    updated_first.encrypted_push_id = format!("encrypted-{}", Uuid::new_v4());
+    let mut batch = subscriptions.clone();
+    batch.push(updated_first.clone());
+
+    let summary = context
+        .storage
+        .batch_insert(&batch)
+        .await
+        .expect("Failed to batch insert subscriptions");
+
+    assert_eq!(summary.written, 30);
+    assert!(summary.unprocessed.is_empty());
+
+    let stored = context
+        .storage
+        .get_all_by_topic(&TopicId::from("batch-insert-topic"))
+        .await
+        .expect("Failed to get subscriptions");
+    assert_eq!(stored.len(), 30);
+
+    // The last subscription with a key wins, and TTLs get their random offset
+    let first = context
+        .storage
+        .get_one(&updated_first.key())
+        .await
+        .expect("Failed to get subscription")
+        .expect("Subscription should exist");
+    assert_eq!(first.encrypted_push_id, updated_first.encrypted_push_id);
+    assert!(DEFAULT_TTL_OFFSET_SECS.contains(&(first.ttl - updated_first.ttl)));
+}
+
+#[tokio::test]
+async fn test_get_topics_for_push_id() {
+    let context = setup_test().await;
+    let encrypted_push_id = format!("encrypted-{}", Uuid::new_v4());
+
+    // Subscriptions for the same push ID across several topics
+    let mut expected = Vec::new();
+    for topic in ["topic-a", "topic-b", "topic-c"] {
+        let subscription = PushSubscription {
+            encrypted_push_id: encrypted_push_id.clone(),
+            ..create_test_subscription(topic)
+        };
+        context
+            .storage
+            .insert(&subscription)
+            .await
+            .expect("Failed to insert subscription");
+        expected.push((subscription.topic, subscription.hmac_key));
+    }
+
+    // Subscription of another push ID on a shared topic
+    context
+        .storage
+        .insert(&create_test_subscription("topic-a"))
+        .await
+        .expect("Failed to insert subscription");
+
+    let mut topics = context
+        .storage
+        .get_topics_for_push_id(&EncryptedPushId::from(encrypted_push_id.as_str()))
+        .await
+        .expect("Failed to get topics for push ID");
+    topics.sort();
+    expected.sort();
+    assert_eq!(topics, expected);
+
+    let unknown = context
+        .storage
+        .get_topics_for_push_id(&EncryptedPushId::from("encrypted-unknown"))
+        .await
+        .expect("Failed to get topics for unknown push ID");
+    assert!(unknown.is_empty());
+}
+
+#[tokio::test]
+async fn test_delete_all_by_push_id() {
+    let context = setup_test().await;
+    let encrypted_push_id = EncryptedPushId::from(format!("encrypted-{}", Uuid::new_v4()));
+
+    for topic in ["topic-a", "topic-b"] {
+        let subscription = PushSubscription {
+            encrypted_push_id: encrypted_push_id.to_string(),
+            ..create_test_subscription(topic)
+        };
+        context
+            .storage
+            .insert(&subscription)
+            .await
+            .expect("Failed to insert subscription");
+    }
+    // Subscription of another push ID on a shared topic
+    let other = create_test_subscription("topic-a");
+    context
+        .storage
+        .insert(&other)
+        .await
+        .expect("Failed to insert subscription");
+
+    let deleted = context
+        .storage
+        .delete_all_by_push_id(&encrypted_push_id)
+        .await
+        .expect("Failed to delete subscriptions of push ID");
+    assert_eq!(deleted, 2);
+
+    let remaining = context
+        .storage
+        .get_topics_for_push_id(&encrypted_push_id)
+        .await
+        .expect("Failed to get topics for push ID");
+    assert!(remaining.is_empty());
+    assert!(context
+        .storage
+        .get_one(&other.key())
+        .await
+        .expect("Failed to get subscription")
+        .is_some());
+}
+
+#[tokio::test]
+async fn test_insert_publishes_topic_invalidation() {
+    let context = setup_test().await;
+    let channel = format!("test-invalidation-{}", Uuid::new_v4());
+
+    let redis_client = redis::Client::open(REDIS_URL).expect("Invalid Redis URL");
+    let mut invalidations = Box::pin(
+        subscribe_invalidations(&redis_client, &channel)
+            .await
+            .expect("Failed to subscribe to invalidations"),
+    );
+
+    let connection_manager = ConnectionManager::new(redis_client)
+        .await
+        .expect("Failed to connect to Redis");
+    let storage = PushSubscriptionStorage::new(
+        context.dynamodb_client.clone(),
+        context.table_name.clone(),
+        PUSH_ID_INDEX_NAME.to_string(),
+    )
+    .with_invalidation_publisher(SubscriptionInvalidationPublisher::new(
+        connection_manager,
+        channel,
+    ));
+
+    let subscription = create_test_subscription("test-topic-invalidation");
+    storage
+        .insert(&subscription)
+        .await
+        .expect("Failed to insert subscription");
+
+    let invalidated_topic = tokio::time::timeout(Duration::from_secs(5), invalidations.next())
+        .await
+        .expect("Timed out waiting for invalidation")
+        .expect("Invalidation stream ended");
+    assert_eq!(invalidated_topic, subscription.topic);
+}
+
+#[tokio::test]
+async fn test_failed_batch_insert_invalidates_written_topics() {
+    let context = setup_test().await;
+    let channel = format!("test-invalidation-{}", Uuid::new_v4());
+
+    let redis_client = redis::Client::open(REDIS_URL).expect("Invalid Redis URL");
+    let mut invalidations = Box::pin(
+        subscribe_invalidations(&redis_client, &channel)
+            .await
+            .expect("Failed to subscribe to invalidations"),
+    );
+
+    let connection_manager = ConnectionManager::new(redis_client)
+        .await
+        .expect("Failed to connect to Redis");
+    let storage = PushSubscriptionStorage::new(
+        context.dynamodb_client.clone(),
+        context.table_name.clone(),
+        PUSH_ID_INDEX_NAME.to_string(),
+    )
+    .with_invalidation_publisher(SubscriptionInvalidationPublisher::new(
+        connection_manager,
+        channel,
+    ));
+
+    // The first request of 25 is written, the second is rejected for its oversized item
+    let mut batch: Vec<_> = (0..25)
+        .map(|_| create_test_subscription("batch-insert-written-topic"))
+        .collect();
+    batch.push(create_test_subscription_with_deletion(
+        "batch-insert-failed-topic",
+        vec!["x".repeat(500_000)],
+    ));
+
+    let result = storage.batch_insert(&batch).await;
+    assert!(result.is_err());
+
+    let mut invalidated_topics = HashSet::new();
+    for _ in 0..2 {
+        let topic = tokio::time::timeout(Duration::from_secs(5), invalidations.next())
+            .await
+            .expect("Timed out waiting for invalidation")
+            .expect("Invalidation stream ended");
+        invalidated_topics.insert(topic);
+    }
+    assert!(invalidated_topics.contains("batch-insert-written-topic"));
+}
+
+#[tokio::test]
+async fn test_malformed_keys_are_rejected_before_dynamodb() {
+    let context = setup_test().await;
+    let encrypted_push_id = EncryptedPushId::from("encrypted-push-id");
+
+    let malformed_keys = [
+        (
+            SubscriptionKey::new("", test_hmac_key()),
+            InvalidSubscriptionKey::EmptyTopic,
+        ),
+        (
+            SubscriptionKey::new("malformed-topic", "not-hex"),
+            InvalidSubscriptionKey::MalformedHmacKey,
+        ),
+    ];
+
+    for (key, expected) in malformed_keys {
+        let results = [
+            context.storage.get_one(&key).await.map(|_| ()),
+            context.storage.delete(&key).await,
+            context
+                .storage
+                .append_delete_request(&key, &encrypted_push_id)
+                .await,
+            context
+                .storage
+                .batch_get(std::slice::from_ref(&key))
+                .await
+                .map(|_| ()),
+            context
+                .storage
+                .batch_delete_many(std::slice::from_ref(&key))
+                .await,
+        ];
+
+        for result in results {
+            assert!(
+                matches!(result, Err(PushSubscriptionStorageError::InvalidKey(e)) if e == expected),
+                "{key:?}"
+            );
+        }
+    }
+
+    // The rejected tombstone didn't create a phantom subscription
+    let subscriptions = context
+        .storage
+        .get_all_by_topic(&TopicId::from("malformed-topic"))
+        .await
+        .expect("Failed to get all by topic");
+    assert!(subscriptions.is_empty());
+}
+
+#[tokio::test]
+async fn test_refresh_ttl() {
+    let context = setup_test().await;
+
+    // Expired, but not deleted by the TTL process yet
+    let subscription = PushSubscription {
+        ttl: (Utc::now() - chrono::Duration::days(2)).timestamp(),
+        ..create_test_subscription("test-topic-refresh-ttl")
+    };
+    context
+        .storage
+        .insert(&subscription)
+        .await
+        .expect("Failed to insert subscription");
+    let topic = TopicId::from(subscription.topic.as_str());
+    assert!(context
+        .storage
+        .get_all_by_topic(&topic)
+        .await
+        .expect("Failed to get all by topic")
+        .is_empty());
+
+    let ttl = (Utc::now() + chrono::Duration::days(30)).timestamp();
+    context
+        .storage
+        .refresh_ttl(&subscription.key(), ttl)
+        .await
+        .expect("Failed to refresh TTL");
+
+    // The refreshed TTL gets the same random offset as inserted ones
+    let refreshed = context
+        .storage
+        .get_one(&subscription.key())
+        .await
+        .expect("Failed to get subscription")
+        .expect("Subscription should exist");
+    assert!(DEFAULT_TTL_OFFSET_SECS.contains(&(refreshed.ttl - ttl)));
+    assert_eq!(refreshed.encrypted_push_id, subscription.encrypted_push_id);
+    assert_eq!(
+        context
+            .storage
+            .get_all_by_topic(&topic)
+            .await
+            .expect("Failed to get all by topic")
+            .len(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn test_refresh_ttl_of_missing_subscription() {
+    let context = setup_test().await;
+    let key = SubscriptionKey::new("test-topic-refresh-missing", test_hmac_key());
+    let ttl = (Utc::now() + chrono::Duration::days(30)).timestamp();
+
+    let result = context.storage.refresh_ttl(&key, ttl).await;
+
+    assert!(matches!(
+        result,
+        Err(PushSubscriptionStorageError::NotFound)
+    ));
+    // The update didn't create a partial subscription
+    assert!(context
+        .storage
+        .get_one(&key)
+        .await
+        .expect("Failed to get subscription")
+        .is_none());
+}
@@ -0,0 +1,195 @@
+//! Retry and timeout settings of the AWS SDK clients shared by the services
+//!
+//! The variables are prefixed with `AWS_CLIENT_` rather than reusing the SDK's own
+//! `AWS_MAX_ATTEMPTS` and `AWS_RETRY_MODE`, which the SDK reads itself with different defaults
+//! and accepted values.
+
+use std::{env, time::Duration};
+
+use aws_config::{retry::RetryConfig, timeout::TimeoutConfig, BehaviorVersion, SdkConfig};
+
+/// Default number of attempts of an AWS request, including the first one
+pub const DEFAULT_AWS_MAX_ATTEMPTS: u32 = 3;
+/// Default timeout of an AWS operation, including its retries
+pub const DEFAULT_AWS_OPERATION_TIMEOUT_MS: u64 = 30_000;
+/// Default timeout of a single attempt of an AWS operation, above the 20 seconds SQS long polls
+/// can wait
+pub const DEFAULT_AWS_OPERATION_ATTEMPT_TIMEOUT_MS: u64 = 25_000;
+/// Default timeout to connect to an AWS service
+pub const DEFAULT_AWS_CONNECT_TIMEOUT_MS: u64 = 3_000;
+
+/// Initial delay before retrying an AWS request
+const INITIAL_BACKOFF: Duration = Duration::from_millis(50);
+
+/// Retry settings of the AWS SDK clients
+///
+/// Uses the standard retry mode unless `AWS_CLIENT_RETRY_MODE` is `adaptive`, which also rate
+/// limits requests client-side once throttled. `AWS_CLIENT_MAX_ATTEMPTS` overrides the number of
+/// attempts.
+///
+/// # Panics
+///
+/// Panics if `AWS_CLIENT_RETRY_MODE` is neither `standard` nor `adaptive`
+#[must_use]
+pub fn retry_config() -> RetryConfig {
+    let retry_config = match env::var("AWS_CLIENT_RETRY_MODE").as_deref().map(str::trim) {
+        Err(_) | Ok("standard") => RetryConfig::standard(),
+        Ok("adaptive") => RetryConfig::adaptive(),
+        Ok(mode) => panic!("Invalid AWS_CLIENT_RETRY_MODE: {mode}"),
+    };
+
+    retry_config
+        .with_max_attempts(
+            env::var("AWS_CLIENT_MAX_ATTEMPTS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_AWS_MAX_ATTEMPTS),
+        )
+        .with_initial_backoff(INITIAL_BACKOFF)
+}
+
+/// Timeout settings of the AWS SDK clients
+///
+/// `AWS_OPERATION_TIMEOUT_MS` bounds a whole operation including its retries,
+/// `AWS_OPERATION_ATTEMPT_TIMEOUT_MS` a single attempt and `AWS_CONNECT_TIMEOUT_MS` the
+/// connection to the service.
+#[must_use]
+pub fn timeout_config() -> TimeoutConfig {
+    TimeoutConfig::builder()
+        .operation_timeout(duration_ms_from_env(
+            "AWS_OPERATION_TIMEOUT_MS",
+            DEFAULT_AWS_OPERATION_TIMEOUT_MS,
+        ))
+        .operation_attempt_timeout(duration_ms_from_env(
+            "AWS_OPERATION_ATTEMPT_TIMEOUT_MS",
+            DEFAULT_AWS_OPERATION_ATTEMPT_TIMEOUT_MS,
+        ))
+        .connect_timeout(duration_ms_from_env(
+            "AWS_CONNECT_TIMEOUT_MS",
+            DEFAULT_AWS_CONNECT_TIMEOUT_MS,
+        ))
+        .build()
+}
+
+/// Loads the AWS configuration shared by every SDK client of a service, see `tune_config`
+pub async fn load_config(endpoint_url: Option<&str>) -> SdkConfig {
+    tune_config(
+        &aws_config::load_defaults(BehaviorVersion::latest()).await,
+        endpoint_url,
+    )
+}
+
+/// Applies the retry and timeout settings to `aws_config`, and overrides its endpoint with
+/// `endpoint_url` if set
+#[must_use]
+pub fn tune_config(aws_config: &SdkConfig, endpoint_url: Option<&str>) -> SdkConfig {
+    let mut config_builder = aws_config
+        .to_builder()
+        .retry_config(retry_config())
+        .timeout_config(timeout_config());
+
+    if let Some(endpoint_url) = endpoint_url {
+        config_builder = config_builder.endpoint_url(endpoint_url);
+    }
+
+    config_builder.build()
+}
+
+/// Reads a duration in milliseconds from the `name` environment variable, `default_ms` if unset
+/// or invalid
+#[must_use]
+pub fn duration_ms_from_env(name: &str, default_ms: u64) -> Duration {
+    Duration::from_millis(
+        env::var(name)
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(default_ms),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use aws_config::{retry::RetryMode, Region};
+    use serial_test::serial;
+
+    use super::*;
+
+    #[test]
+    #[serial]
+    fn test_defaults() {
+        let retry_config = retry_config();
+        assert_eq!(retry_config.mode(), RetryMode::Standard);
+        assert_eq!(retry_config.max_attempts(), DEFAULT_AWS_MAX_ATTEMPTS);
+
+        let timeout_config = timeout_config();
+        assert_eq!(
+            timeout_config.operation_timeout(),
+            Some(Duration::from_millis(DEFAULT_AWS_OPERATION_TIMEOUT_MS))
+        );
+        assert_eq!(
+            timeout_config.connect_timeout(),
+            Some(Duration::from_millis(DEFAULT_AWS_CONNECT_TIMEOUT_MS))
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn test_tune_config() {
+        let region = Region::new("eu-central-1");
+        let aws_config = SdkConfig::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(region.clone())
+            .build();
+
+        let tuned = tune_config(&aws_config, Some("http://localhost:4566"));
+
+        assert_eq!(tuned.region(), Some(&region));
+        assert_eq!(tuned.endpoint_url(), Some("http://localhost:4566"));
+        assert_eq!(tuned.retry_config(), Some(&retry_config()));
+        assert_eq!(tuned.timeout_config(), Some(&timeout_config()));
+        assert_eq!(tune_config(&aws_config, None).endpoint_url(), None);
+    }
+
+    #[test]
+    #[serial]
+    fn test_overrides() {
+        env::set_var("AWS_CLIENT_RETRY_MODE", "adaptive");
+        env::set_var("AWS_CLIENT_MAX_ATTEMPTS", "5");
+        env::set_var("AWS_OPERATION_TIMEOUT_MS", "10000");
+
+        let retry_config = retry_config();
+        assert_eq!(retry_config.mode(), RetryMode::Adaptive);
+        assert_eq!(retry_config.max_attempts(), 5);
+        assert_eq!(
+            timeout_config().operation_timeout(),
+            Some(Duration::from_secs(10))
+        );
+
+        // Cleanup
+        env::remove_var("AWS_CLIENT_RETRY_MODE");
+        env::remove_var("AWS_CLIENT_MAX_ATTEMPTS");
+        env::remove_var("AWS_OPERATION_TIMEOUT_MS");
+    }
+
+    #[test]
+    #[serial]
+    fn test_sdk_variables_are_ignored() {
+        env::set_var("AWS_MAX_ATTEMPTS", "7");
+
+        assert_eq!(retry_config().max_attempts(), DEFAULT_AWS_MAX_ATTEMPTS);
+
+        // Cleanup
+        env::remove_var("AWS_MAX_ATTEMPTS");
+    }
+
+    #[test]
+    #[serial]
+    fn test_invalid_retry_mode() {
+        env::set_var("AWS_CLIENT_RETRY_MODE", "fast");
+        std::panic::catch_unwind(retry_config)
+            .expect_err("Expected panic for an invalid retry mode");
+
+        // Cleanup
+        env::remove_var("AWS_CLIENT_RETRY_MODE");
+    }
+}
@@ -0,0 +1,83 @@
+//! `DogStatsD` metrics recorder setup shared by the workers
+//!
+//! Each worker keeps its own `Metric` enum of known metric names next to its call to `init`.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use metrics::Label;
+use metrics_exporter_dogstatsd::DogStatsDBuilder;
+use tracing::{info, warn};
+
+/// Version of the build, tagged on every metric and logged at startup
+///
+/// The crates share the workspace version, so this is also the version of the binary.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Commit hash of the build (if available)
+pub const GIT_REV: Option<&str> = option_env!("GIT_REV");
+
+static INITIALIZED: AtomicBool = AtomicBool::new(false);
+
+/// Installs the global `DogStatsD` metrics recorder
+///
+/// Metrics are optional: if the recorder can't be installed a single warning is logged and every
+/// metric call is a no-op. Returns whether metrics are reported, see `is_initialized`.
+pub fn init(global_prefix: &str, remote_address: String) -> bool {
+    let result = DogStatsDBuilder::default()
+        .set_global_prefix(global_prefix)
+        .with_global_labels(default_labels())
+        .with_remote_address(remote_address)
+        .and_then(DogStatsDBuilder::install);
+
+    match result {
+        Ok(()) => {
+            INITIALIZED.store(true, Ordering::Release);
+            info!("✅ Initialized DogStatsD metrics recorder");
+            true
+        }
+        Err(e) => {
+            warn!("Metrics are disabled, failed to install DogStatsD recorder: {e}");
+            false
+        }
+    }
+}
+
+/// Labels added to every metric, so metric changes can be correlated with deploys
+#[must_use]
+pub fn default_labels() -> Vec<Label> {
+    let mut labels = vec![Label::new("version", VERSION)];
+    if let Some(git_rev) = GIT_REV {
+        labels.push(Label::new("git_rev", git_rev));
+    }
+    labels
+}
+
+/// Whether the `DogStatsD` recorder is installed and metrics are reported
+#[must_use]
+pub fn is_initialized() -> bool {
+    INITIALIZED.load(Ordering::Acquire)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_initialized_reflects_init_result() {
+        assert!(!is_initialized());
+
+        assert!(!init("test", "not an address".to_string()));
+        assert!(!is_initialized());
+
+        assert!(init("test", "127.0.0.1:8125".to_string()));
+        assert!(is_initialized());
+    }
+
+    #[test]
+    fn test_default_labels_include_version() {
+        let labels = default_labels();
+
+        assert!(labels
+            .iter()
+            .any(|label| label.key() == "version" && label.value() == VERSION));
+    }
+}
@@ -1,3 +1,9 @@
+pub mod aws;
+#[cfg(feature = "telemetry")]
+pub mod dogstatsd;
+pub mod logging;
+pub mod validate_config;
+
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use strum::Display;
@@ -0,0 +1,161 @@
+//! Log format selection shared by the binaries
+//!
+//! `LOG_FORMAT=json|pretty` selects the format of the logs. When it is unset, the format follows
+//! the environment: pretty in development, JSON for the log pipeline everywhere else.
+
+use std::str::FromStr;
+
+/// Environment variable selecting the log format
+pub const LOG_FORMAT_ENV: &str = "LOG_FORMAT";
+
+/// Environment variable naming the deployment stage, read by binaries without an `Environment`
+const APP_ENV: &str = "APP_ENV";
+
+/// Format of the emitted logs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Structured logs for the log pipeline
+    Json,
+    /// Human-readable logs
+    Pretty,
+}
+
+/// `LOG_FORMAT` holds an unknown format
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Invalid LOG_FORMAT '{0}', expected json or pretty")]
+pub struct InvalidLogFormat(pub String);
+
+impl FromStr for LogFormat {
+    type Err = InvalidLogFormat;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "json" => Ok(Self::Json),
+            "pretty" => Ok(Self::Pretty),
+            _ => Err(InvalidLogFormat(value.to_string())),
+        }
+    }
+}
+
+impl LogFormat {
+    /// Format used when `LOG_FORMAT` is unset: pretty in development, JSON otherwise
+    #[must_use]
+    pub const fn default_for(development: bool) -> Self {
+        if development {
+            Self::Pretty
+        } else {
+            Self::Json
+        }
+    }
+
+    /// Default format of the binaries without an `Environment`, from `APP_ENV`
+    ///
+    /// Unlike the services, an unset `APP_ENV` means JSON, as the enclave images don't set it.
+    #[must_use]
+    pub fn default_from_app_env() -> Self {
+        Self::default_for(
+            std::env::var(APP_ENV).is_ok_and(|env| env.trim().eq_ignore_ascii_case("development")),
+        )
+    }
+
+    /// Resolves the format from the value of `LOG_FORMAT`, falling back to `default`
+    ///
+    /// # Errors
+    ///
+    /// If the value is neither `json` nor `pretty`
+    pub fn from_env_value(value: Option<&str>, default: Self) -> Result<Self, InvalidLogFormat> {
+        value.map_or(Ok(default), str::parse)
+    }
+
+    /// Reads the format from `LOG_FORMAT`, falling back to `default`
+    ///
+    /// # Errors
+    ///
+    /// If `LOG_FORMAT` is neither `json` nor `pretty`
+    pub fn from_env(default: Self) -> Result<Self, InvalidLogFormat> {
+        Self::from_env_value(std::env::var(LOG_FORMAT_ENV).ok().as_deref(), default)
+    }
+}
+
+/// Logging and tracing of a service, to shut down before exiting
+#[cfg(feature = "telemetry")]
+#[must_use = "dropping the telemetry stops writing logs"]
+pub struct Telemetry {
+    /// Flushes the Datadog tracer and log writer, only installed for JSON logs
+    shutdown: Option<Box<dyn FnOnce()>>,
+}
+
+#[cfg(feature = "telemetry")]
+impl Telemetry {
+    /// Flushes the pending spans and logs
+    pub fn shutdown(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            shutdown();
+        }
+    }
+}
+
+/// Installs the global tracing subscriber in `format`
+///
+/// JSON logs go through Datadog tracing, which also exports the spans. Pretty logs are meant for
+/// local development and don't export spans.
+///
+/// # Errors
+///
+/// If a global subscriber is already installed or Datadog tracing fails to start
+#[cfg(feature = "telemetry")]
+pub fn init(format: LogFormat) -> anyhow::Result<Telemetry> {
+    use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+
+    match format {
+        LogFormat::Json => {
+            let (guard, tracer_shutdown) = datadog_tracing::init()?;
+            Ok(Telemetry {
+                shutdown: Some(Box::new(move || {
+                    tracer_shutdown.shutdown();
+                    drop(guard);
+                })),
+            })
+        }
+        LogFormat::Pretty => {
+            tracing_subscriber::registry()
+                .with(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
+                .with(tracing_subscriber::fmt::layer().pretty())
+                .try_init()?;
+            Ok(Telemetry { shutdown: None })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_selects_format_from_env_value() {
+        let default = LogFormat::Pretty;
+        assert_eq!(
+            LogFormat::from_env_value(Some("json"), default),
+            Ok(LogFormat::Json)
+        );
+        assert_eq!(
+            LogFormat::from_env_value(Some("JSON"), default),
+            Ok(LogFormat::Json)
+        );
+        assert_eq!(
+            LogFormat::from_env_value(Some("pretty"), LogFormat::Json),
+            Ok(LogFormat::Pretty)
+        );
+        assert_eq!(LogFormat::from_env_value(None, default), Ok(default));
+        assert_eq!(
+            LogFormat::from_env_value(Some("xml"), default),
+            Err(InvalidLogFormat("xml".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_defaults_to_pretty_in_development_only() {
+        assert_eq!(LogFormat::default_for(true), LogFormat::Pretty);
+        assert_eq!(LogFormat::default_for(false), LogFormat::Json);
+    }
+}
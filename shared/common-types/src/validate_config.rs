@@ -0,0 +1,132 @@
+//! Startup configuration validation shared by the services
+//!
+//! Started with `--validate-config`, a binary loads its configuration, builds its clients and
+//! runs a `Check` per dependency with `run_checks`, then exits without serving traffic.
+
+use std::{future::Future, time::Duration};
+
+use futures::future::{join_all, BoxFuture, FutureExt};
+use tokio::time::timeout;
+use tracing::{error, info};
+
+/// Command line flag that runs the startup validations and exits
+pub const VALIDATE_CONFIG_FLAG: &str = "--validate-config";
+
+const CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Whether the binary was started with `--validate-config`
+#[must_use]
+pub fn is_requested() -> bool {
+    std::env::args()
+        .skip(1)
+        .any(|arg| arg == VALIDATE_CONFIG_FLAG)
+}
+
+/// A named dependency check
+pub struct Check<'a> {
+    name: &'static str,
+    future: BoxFuture<'a, anyhow::Result<()>>,
+}
+
+impl<'a> Check<'a> {
+    /// Creates a check that passes if `future` resolves to `Ok`
+    pub fn new(
+        name: &'static str,
+        future: impl Future<Output = anyhow::Result<()>> + Send + 'a,
+    ) -> Self {
+        Self {
+            name,
+            future: future.boxed(),
+        }
+    }
+}
+
+/// Outcome of a single check
+#[derive(Debug)]
+pub struct CheckOutcome {
+    /// Name of the check
+    pub name: &'static str,
+    /// Why the check failed, if it did
+    pub error: Option<String>,
+}
+
+/// Outcome of all startup validations
+#[derive(Debug)]
+pub struct ValidationReport {
+    /// Outcome of each check, in the order they were given
+    pub outcomes: Vec<CheckOutcome>,
+}
+
+impl ValidationReport {
+    /// Whether every check passed
+    #[must_use]
+    pub fn is_ok(&self) -> bool {
+        self.outcomes.iter().all(|outcome| outcome.error.is_none())
+    }
+
+    /// Logs the outcome of each check
+    pub fn log(&self) {
+        for outcome in &self.outcomes {
+            match &outcome.error {
+                None => info!("✅ {}", outcome.name),
+                Some(e) => error!("❌ {}: {e}", outcome.name),
+            }
+        }
+    }
+}
+
+/// Runs `checks` concurrently, failing any that take longer than 10 seconds
+pub async fn run_checks(checks: Vec<Check<'_>>) -> ValidationReport {
+    let outcomes = join_all(checks.into_iter().map(|check| async move {
+        let error = match timeout(CHECK_TIMEOUT, check.future).await {
+            Ok(Ok(())) => None,
+            Ok(Err(e)) => Some(format!("{e:#}")),
+            Err(_) => Some(format!("timed out after {CHECK_TIMEOUT:?}")),
+        };
+
+        CheckOutcome {
+            name: check.name,
+            error,
+        }
+    }))
+    .await;
+
+    ValidationReport { outcomes }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_run_checks_reports_each_outcome() {
+        let report = run_checks(vec![
+            Check::new("healthy", async { Ok(()) }),
+            Check::new("unreachable", async {
+                Err(anyhow::anyhow!("connection refused"))
+            }),
+        ])
+        .await;
+
+        assert!(!report.is_ok());
+        assert_eq!(report.outcomes.len(), 2);
+        assert_eq!(report.outcomes[0].name, "healthy");
+        assert!(report.outcomes[0].error.is_none());
+        assert_eq!(report.outcomes[1].name, "unreachable");
+        assert_eq!(
+            report.outcomes[1].error.as_deref(),
+            Some("connection refused")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_checks_passes_when_all_checks_pass() {
+        let report = run_checks(vec![
+            Check::new("first", async { Ok(()) }),
+            Check::new("second", async { Ok(()) }),
+        ])
+        .await;
+
+        assert!(report.is_ok());
+    }
+}
@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+
 use pontifex::Request;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
@@ -27,14 +29,29 @@ pub enum EnclaveError {
     DecryptSecretKeyFailed(String),
     #[error("Missing state field: {0}")]
     MissingStateField(String),
+    #[error("Invalid state snapshot: {0}")]
+    InvalidStateSnapshot(String),
+    #[error("Initialize request does not carry the nonce of the outstanding initialize challenge")]
+    InitializeChallengeMismatch,
+    #[error("Invalid Braze API region: {0:?}")]
+    InvalidBrazeRegion(String),
+    #[error("Notification has {count} recipients, the maximum is {max}")]
+    TooManyRecipients { count: usize, max: usize },
+    #[error("Invalid notification message payload: {0}")]
+    InvalidMessagePayload(String),
 }
 
+/// Default maximum number of recipients accepted in a single notification request,
+/// matching the Braze `/messages/send` recipient limit
+pub const DEFAULT_MAX_NOTIFICATION_RECIPIENTS: u32 = 50;
+
 /// Braze API configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EnclaveInitializeRequest {
     /// Braze API key
     pub braze_api_key: String,
-    /// Braze REST API region (e.g., https://rest.{braze_api_region}.braze.com)
+    /// Braze REST API region, e.g. `iad-05` for https://rest.iad-05.braze.com
+    /// or `fra-01` for https://rest.fra-01.braze.eu
     pub braze_api_region: String,
     /// Enclave HTTP proxy port
     pub braze_http_proxy_port: u32,
@@ -49,6 +66,14 @@ pub struct EnclaveInitializeRequest {
     /// This port is used to proxy pontifex requests to other enclaves in the same cluster.
     /// It's used to request a secret key from other live enclaves.
     pub enclave_cluster_proxy_port: u32,
+    /// Nonce from the attestation document returned by `EnclaveInitializeChallengeRequest`.
+    ///
+    /// The enclave only accepts an initialize request carrying the nonce of the challenge it
+    /// most recently issued. This guards against stale or replayed requests, it doesn't
+    /// authenticate the caller: anyone able to reach the enclave can request a challenge.
+    pub attestation_nonce: Vec<u8>,
+    /// Maximum number of recipients accepted in a single `EnclaveNotificationRequest`
+    pub max_notification_recipients: u32,
 }
 
 impl Request for EnclaveInitializeRequest {
@@ -56,6 +81,15 @@ impl Request for EnclaveInitializeRequest {
     type Response = Result<(), EnclaveError>;
 }
 
+/// Requests a single-use nonce that must precede `EnclaveInitializeRequest`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnclaveInitializeChallengeRequest;
+
+impl Request for EnclaveInitializeChallengeRequest {
+    const ROUTE_ID: &'static str = "/v1/initialize-challenge";
+    type Response = Result<EnclaveAttestationDocResponse, EnclaveError>;
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EnclaveHealthCheckRequest;
 
@@ -97,11 +131,38 @@ pub struct EnclaveNotificationRequest {
     pub subscribed_encrypted_push_ids: Vec<String>,
     /// Encrypted Message Base64 encoded
     pub encrypted_message_base64: String,
+    /// Trace of the caller, continued by the enclave
+    #[serde(default)]
+    pub trace_context: Option<TraceContext>,
+}
+
+/// W3C trace context propagated across the pontifex boundary
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TraceContext {
+    /// Hex encoded 128-bit trace id
+    pub trace_id: String,
+    /// Hex encoded 64-bit id of the caller's span
+    pub parent_span_id: String,
 }
 
 impl Request for EnclaveNotificationRequest {
     const ROUTE_ID: &'static str = "/v1/notification";
-    type Response = Result<(), EnclaveError>;
+    type Response = Result<EnclaveNotificationResponse, EnclaveError>;
+}
+
+/// Per-recipient outcome of an `EnclaveNotificationRequest`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EnclaveNotificationResponse {
+    /// Encrypted Push IDs the enclave can't decrypt, e.g. sealed with a since-rotated key
+    ///
+    /// They were skipped rather than failing the request: the failure is permanent, so retrying
+    /// can't deliver to them.
+    pub undecryptable_push_ids: Vec<String>,
+    /// Encrypted Push IDs that aren't valid hex, so they were never sealed by a key at all
+    ///
+    /// They were skipped as well, but point at a validation bug rather than a rotated key.
+    #[serde(default)]
+    pub invalid_push_ids: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -114,3 +175,51 @@ impl Request for EnclaveSecretKeyRequest {
     const ROUTE_ID: &'static str = "/v1/secret-key";
     type Response = Result<Vec<u8>, EnclaveError>;
 }
+
+/// Audit record of the enclave releasing its secret key to another enclave
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SecretKeyReleaseAuditEntry {
+    /// Hex encoded PCR0 from the requesting enclave's attestation document
+    pub requester_pcr0: String,
+    /// Module ID of the requesting enclave
+    pub requester_module_id: String,
+    /// When the key was released (unix timestamp in milliseconds)
+    pub released_at_millis: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnclaveSecretKeyAuditRequest;
+
+impl Request for EnclaveSecretKeyAuditRequest {
+    const ROUTE_ID: &'static str = "/v1/secret-key-audit";
+    type Response = Result<Vec<SecretKeyReleaseAuditEntry>, EnclaveError>;
+}
+
+/// Counter of push IDs the enclave failed to decrypt
+pub const ENCLAVE_METRIC_DECRYPT_FAILURES: &str = "decrypt_failures";
+/// Counter of failed Braze requests
+pub const ENCLAVE_METRIC_BRAZE_FAILURES: &str = "braze_failures";
+/// Timing of Braze requests
+pub const ENCLAVE_METRIC_BRAZE_LATENCY: &str = "braze_latency";
+
+/// Metrics accumulated by the enclave since the previous `EnclaveMetricsRequest`
+///
+/// The enclave can't reach the metrics agent, so the worker pulls these and forwards them.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EnclaveMetricsSnapshot {
+    /// Counter increments by metric name
+    pub counters: BTreeMap<String, u64>,
+    /// Timing samples in milliseconds by metric name
+    pub timings_ms: BTreeMap<String, Vec<f64>>,
+    /// Number of samples dropped because the enclave's metric store was full
+    pub dropped_samples: u64,
+}
+
+/// Pulls and resets the metrics accumulated by the enclave
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnclaveMetricsRequest;
+
+impl Request for EnclaveMetricsRequest {
+    const ROUTE_ID: &'static str = "/v1/metrics";
+    type Response = Result<EnclaveMetricsSnapshot, EnclaveError>;
+}